@@ -1,8 +1,26 @@
-use super::IOArgs;
+use super::{IOArgs, InputArgs, OutputArgs};
 use clap::Subcommand;
+use fts_solver::export::ExportFormat;
+use std::{num::NonZeroUsize, path::PathBuf};
 
+mod bench;
+mod depth;
+mod diff;
 mod export;
+mod generate;
 mod solve;
+mod stream;
+mod table;
+mod tabular;
+mod verify;
+mod watch;
+
+pub use bench::BenchRecord;
+pub use depth::ProductDepth;
+pub use diff::OutcomeDiff;
+pub use tabular::OutputFormat;
+pub use verify::OutcomeVerification;
+pub use watch::watch;
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -14,6 +32,38 @@ pub enum Commands {
         /// Request a specific QP solver
         #[arg(short, long, default_value = "clarabel")]
         lib: solve::SolverLib,
+
+        /// The format of the solution: a JSON or MessagePack document, or a directory of Parquet
+        /// or Arrow IPC tables of the products and portfolios (which requires --output or
+        /// --output-dir). If omitted, will infer based on filename, defaulting to JSON.
+        #[arg(long, value_enum)]
+        output_format: Option<OutputFormat>,
+
+        /// Solve every auction in the input (a directory, or a glob such as "auctions/*.json"),
+        /// writing each solution to the file (or, for tables, the directory) of the same name in this directory
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+
+        /// The number of auctions to solve at once when solving into an output directory
+        #[arg(short, long, default_value = "1")]
+        jobs: NonZeroUsize,
+
+        /// Keep running, solving again whenever the input changes
+        #[arg(short, long)]
+        watch: bool,
+    },
+
+    /// Solve a stream of newline-delimited JSON auctions, writing one outcome per line
+    Stream {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Request a specific QP solver
+        #[arg(short, long, default_value = "clarabel")]
+        lib: solve::SolverLib,
     },
 
     /// Construct the flow trading quadratic program and export to a standard format
@@ -22,7 +72,112 @@ pub enum Commands {
         io: IOArgs,
 
         /// The file format to use (if omitted, will infer based on filename)
+        #[arg(short, long, value_parser = export::format())]
+        format: Option<ExportFormat>,
+    },
+
+    /// Solve auctions with each solver, comparing their performance and solutions
+    Bench {
+        /// The auction files (JSON, or CSV as for solve)
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// The QP solvers to compare (the first to succeed is the reference for divergences)
+        #[arg(short, long = "lib", value_enum, default_values_t = [solve::SolverLib::Clarabel, solve::SolverLib::Osqp])]
+        libs: Vec<solve::SolverLib>,
+
+        /// Report the results as JSON rather than as a table
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Compare two outcome files, exiting with an error if they differ
+    Diff {
+        /// The outcome JSON file to compare against
+        before: PathBuf,
+
+        /// The outcome JSON file to compare
+        after: PathBuf,
+
+        /// The largest difference in a price or trade which is not reported
+        #[arg(short, long, default_value_t = 1e-6)]
+        tolerance: f64,
+
+        /// Report the differences as JSON rather than as a table
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Check an outcome file against its auction, exiting with an error if it fails any check
+    Verify {
+        /// The auction file (JSON, or CSV as for solve)
+        auction: PathBuf,
+
+        /// The outcome JSON file to check
+        outcome: PathBuf,
+
+        /// The largest violation of a check which is not reported, relative to the magnitude
+        /// of the values concerned (or absolute, for values smaller than one)
+        #[arg(short, long, default_value_t = 1e-6)]
+        tolerance: f64,
+
+        /// Request a specific QP solver for the reference solution
+        #[arg(short, long, default_value = "clarabel")]
+        lib: solve::SolverLib,
+
+        /// Report the violations as JSON rather than as a table
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Report the aggregate supply and demand curves of each product, and where it clears
+    Depth {
+        #[command(flatten)]
+        io: IOArgs,
+
+        /// Request a specific QP solver for the clearing points
+        #[arg(short, long, default_value = "clarabel")]
+        lib: solve::SolverLib,
+
+        /// Report the curves as JSON rather than as a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check an auction for problems without solving it, exiting with an error if it is invalid
+    Validate {
+        #[command(flatten)]
+        io: IOArgs,
+    },
+
+    /// Generate a random auction, e.g. as a synthetic workload for benchmarks
+    Generate {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// The number of bidders, each with a single demand curve and portfolio
+        #[arg(short, long, default_value_t = 10)]
+        bidders: usize,
+
+        /// The number of products
+        #[arg(short, long, default_value_t = 5)]
+        products: usize,
+
+        /// The probability that a portfolio trades any given product
+        #[arg(short, long, default_value_t = 0.5, value_parser = generate::density)]
+        density: f64,
+
+        /// The seed of the generator (if omitted, a random seed is used)
         #[arg(short, long)]
-        format: Option<export::ExportFormat>,
+        seed: Option<u64>,
     },
 }
@@ -0,0 +1,125 @@
+use super::{
+    solve::SolverLib,
+    table::{optional, write_table},
+};
+use fts_solver::{
+    PortfolioOutcome, ProductOutcome,
+    io::{Auction, Outcome},
+};
+use serde::Serialize;
+use std::{io::Write, time::Instant};
+
+// The performance of a single solver on a single auction, and how far its
+// solution strays from that of the reference solver
+#[derive(Serialize)]
+pub struct BenchRecord {
+    pub input: String,
+    pub backend: String,
+    pub status: String,
+    pub wall_time: f64,
+    pub iterations: Option<f64>,
+    pub objective: Option<f64>,
+    pub max_price_divergence: Option<f64>,
+    pub max_trade_divergence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BenchRecord {
+    // Solve the auction with each solver in turn. The first solver to succeed
+    // is the reference against which the solutions of the others are compared.
+    pub async fn compare(input: &str, auction: Auction, libs: &[SolverLib]) -> Vec<Self> {
+        let mut reference: Option<Outcome<PortfolioOutcome, ProductOutcome>> = None;
+        let mut records = Vec::with_capacity(libs.len());
+
+        for lib in libs {
+            let auction = auction.clone();
+            let start = Instant::now();
+            let (solution, telemetry) = lib.solve_with_telemetry(auction).await;
+            let wall_time = start.elapsed().as_secs_f64();
+
+            let mut record = Self {
+                input: input.to_owned(),
+                backend: telemetry.backend,
+                status: telemetry.status,
+                wall_time,
+                iterations: telemetry.stats.get("iterations").copied(),
+                objective: telemetry.stats.get("obj_val").copied(),
+                max_price_divergence: None,
+                max_trade_divergence: None,
+                error: None,
+            };
+
+            match solution {
+                Ok(outcome) => {
+                    if let Some(reference) = &reference {
+                        record.max_price_divergence = Some(max_divergence(
+                            reference.products.iter().map(|(id, reference)| {
+                                (reference.price, outcome.products.get(id).map(|o| o.price))
+                            }),
+                        ));
+                        record.max_trade_divergence = Some(max_divergence(
+                            reference.portfolios.iter().map(|(id, reference)| {
+                                (reference.rate, outcome.portfolios.get(id).map(|o| o.rate))
+                            }),
+                        ));
+                    } else {
+                        reference = Some(outcome);
+                    }
+                }
+                Err(error) => record.error = Some(error.to_string()),
+            }
+
+            records.push(record);
+        }
+
+        records
+    }
+
+    // Write the records as a plain-text table, one row per solve
+    pub fn write_table<W: Write>(records: &[Self], buffer: &mut W) -> anyhow::Result<()> {
+        let header = [
+            "input",
+            "backend",
+            "status",
+            "wall (ms)",
+            "iterations",
+            "objective",
+            "max Δprice",
+            "max Δtrade",
+        ];
+        let rows = records
+            .iter()
+            .map(|record| {
+                [
+                    record.input.clone(),
+                    record.backend.clone(),
+                    record.status.clone(),
+                    format!("{:.3}", record.wall_time * 1000.0),
+                    optional(record.iterations, |value| format!("{value}")),
+                    optional(record.objective, |value| format!("{value:.6}")),
+                    optional(record.max_price_divergence, |value| format!("{value:.3e}")),
+                    optional(record.max_trade_divergence, |value| format!("{value:.3e}")),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        write_table(header, &rows, buffer)?;
+
+        for record in records {
+            if let Some(error) = &record.error {
+                writeln!(buffer, "{} ({}): {error}", record.input, record.backend)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The largest absolute difference between the reference values and their
+// counterparts, which is infinite if any counterpart is missing
+fn max_divergence(pairs: impl Iterator<Item = (f64, Option<f64>)>) -> f64 {
+    pairs
+        .map(|(reference, value)| value.map_or(f64::INFINITY, |value| (reference - value).abs()))
+        .fold(0.0, f64::max)
+}
@@ -0,0 +1,191 @@
+use super::table::{optional, write_table};
+use fts_core::models::Point;
+use fts_solver::{
+    PortfolioOutcome, ProductOutcome,
+    io::{Auction, Outcome, ProductId},
+};
+use serde::Serialize;
+use std::{collections::HashMap, io::Write};
+
+// The aggregate rates bought and sold of a product at a price
+#[derive(Serialize)]
+pub struct DepthPoint {
+    pub price: f64,
+    pub demand: f64,
+    pub supply: f64,
+}
+
+// Where a product cleared, according to the solver
+#[derive(Serialize)]
+pub struct Clearing {
+    pub price: f64,
+    pub rate: f64,
+}
+
+// The aggregate supply and demand curves of a single product, evaluated at
+// every price where one of the underlying curves bends
+#[derive(Serialize)]
+pub struct ProductDepth {
+    pub product: String,
+    pub points: Vec<DepthPoint>,
+    pub clearing: Option<Clearing>,
+}
+
+impl ProductDepth {
+    // Aggregate the demand curves of the auction by product, in order of each
+    // product's first appearance.
+    //
+    // A portfolio trading `b` of a product, weighted by `w` in a demand curve,
+    // trades `b / w` of the product per unit of the curve's rate, at `w / b`
+    // of the curve's price per unit of the product. Projecting every curve
+    // this way is exact for a portfolio of one product with a curve of its
+    // own; otherwise, each product sees the curve as if the portfolio traded
+    // only that product.
+    pub fn compute(
+        auction: &Auction,
+        outcome: Option<&Outcome<PortfolioOutcome, ProductOutcome>>,
+    ) -> Vec<Self> {
+        let mut products: Vec<(&ProductId, Vec<Vec<Point>>)> = Vec::new();
+        let mut index = HashMap::new();
+
+        for portfolio in auction.portfolios.values() {
+            for (product_id, &basis) in portfolio.basis().iter() {
+                let position = *index.entry(product_id).or_insert_with(|| {
+                    products.push((product_id, Vec::new()));
+                    products.len() - 1
+                });
+                for (demand_id, &weight) in portfolio.demand().iter() {
+                    let Some(curve) = auction.demand_curves.get(demand_id) else {
+                        continue;
+                    };
+                    if basis == 0.0 || weight == 0.0 {
+                        continue;
+                    }
+                    let points = project(curve.clone().points(), basis / weight);
+                    if !points.is_empty() {
+                        products[position].1.push(points);
+                    }
+                }
+            }
+        }
+
+        products
+            .into_iter()
+            .map(|(product_id, curves)| {
+                let mut prices = curves
+                    .iter()
+                    .flatten()
+                    .map(|point| point.price)
+                    .filter(|price| price.is_finite())
+                    .collect::<Vec<_>>();
+                prices.sort_by(f64::total_cmp);
+                prices.dedup();
+
+                let points = prices
+                    .into_iter()
+                    .map(|price| DepthPoint {
+                        price,
+                        demand: curves
+                            .iter()
+                            .map(|points| upper(points, price).max(0.0))
+                            .sum(),
+                        supply: curves
+                            .iter()
+                            .map(|points| (-lower(points, price)).max(0.0))
+                            .sum(),
+                    })
+                    .collect();
+
+                Self {
+                    product: product_id.to_string(),
+                    points,
+                    clearing: outcome
+                        .and_then(|outcome| outcome.products.get(product_id))
+                        .map(|outcome| Clearing {
+                            price: outcome.price,
+                            rate: outcome.rate,
+                        }),
+                }
+            })
+            .collect()
+    }
+
+    // Write the curves as a plain-text table, followed by each clearing point
+    pub fn write_table<W: Write>(depths: &[Self], buffer: &mut W) -> anyhow::Result<()> {
+        let rows = depths
+            .iter()
+            .flat_map(|depth| {
+                depth.points.iter().map(|point| {
+                    [
+                        depth.product.clone(),
+                        format!("{:.6}", point.price),
+                        format!("{:.6}", point.demand),
+                        format!("{:.6}", point.supply),
+                    ]
+                })
+            })
+            .collect::<Vec<_>>();
+
+        write_table(["product", "price", "demand", "supply"], &rows, buffer)?;
+
+        for depth in depths {
+            let clearing = depth.clearing.as_ref();
+            writeln!(
+                buffer,
+                "{} clears at price {} and rate {}",
+                depth.product,
+                optional(clearing.map(|c| c.price), |value| format!("{value:.6}")),
+                optional(clearing.map(|c| c.rate), |value| format!("{value:.6}")),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// Scale a curve's rates by `ratio` and its prices by its inverse, keeping the
+// points in order of rate
+fn project(points: Vec<Point>, ratio: f64) -> Vec<Point> {
+    let mut projected = points
+        .into_iter()
+        .map(|point| Point {
+            rate: point.rate * ratio,
+            price: point.price / ratio,
+        })
+        .collect::<Vec<_>>();
+    if ratio < 0.0 {
+        projected.reverse();
+    }
+    projected
+}
+
+// The largest rate the curve would buy at the price (or its smallest rate, if
+// even that is too expensive)
+fn upper(points: &[Point], price: f64) -> f64 {
+    if points[0].price < price {
+        return points[0].rate;
+    }
+    for pair in points.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if b.price < price {
+            return a.rate + (a.price - price) / (a.price - b.price) * (b.rate - a.rate);
+        }
+    }
+    points[points.len() - 1].rate
+}
+
+// The smallest rate the curve would sell down to at the price (or its largest
+// rate, if even that is too cheap)
+fn lower(points: &[Point], price: f64) -> f64 {
+    let last = &points[points.len() - 1];
+    if last.price > price {
+        return last.rate;
+    }
+    for pair in points.windows(2).rev() {
+        let (a, b) = (&pair[0], &pair[1]);
+        if a.price > price {
+            return a.rate + (a.price - price) / (a.price - b.price) * (b.rate - a.rate);
+        }
+    }
+    points[0].rate
+}
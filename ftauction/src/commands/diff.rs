@@ -0,0 +1,153 @@
+use super::table::{optional, write_table};
+use fts_solver::{PortfolioOutcome, ProductOutcome, io::Outcome};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+    io::Write,
+};
+
+// A value which differs between two outcomes by more than the tolerance, or
+// which is only present in one of them
+#[derive(Serialize)]
+pub struct Delta {
+    pub id: String,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    pub delta: Option<f64>,
+}
+
+// The product prices and portfolio trades which differ between two outcomes
+#[derive(Serialize)]
+pub struct OutcomeDiff {
+    pub tolerance: f64,
+    pub products: Vec<Delta>,
+    pub portfolios: Vec<Delta>,
+}
+
+impl OutcomeDiff {
+    pub fn compare(
+        before: &Outcome<PortfolioOutcome, ProductOutcome>,
+        after: &Outcome<PortfolioOutcome, ProductOutcome>,
+        tolerance: f64,
+    ) -> Self {
+        Self {
+            tolerance,
+            products: deltas(
+                before
+                    .products
+                    .iter()
+                    .map(|(id, outcome)| (id, outcome.price)),
+                after
+                    .products
+                    .iter()
+                    .map(|(id, outcome)| (id, outcome.price)),
+                tolerance,
+            ),
+            portfolios: deltas(
+                before
+                    .portfolios
+                    .iter()
+                    .map(|(id, outcome)| (id, outcome.rate)),
+                after
+                    .portfolios
+                    .iter()
+                    .map(|(id, outcome)| (id, outcome.rate)),
+                tolerance,
+            ),
+        }
+    }
+
+    // The number of differences found
+    pub fn len(&self) -> usize {
+        self.products.len() + self.portfolios.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Write the differences as a plain-text table, followed by a summary
+    pub fn write_table<W: Write>(&self, buffer: &mut W) -> anyhow::Result<()> {
+        let rows = self
+            .products
+            .iter()
+            .map(|delta| ("product price", delta))
+            .chain(
+                self.portfolios
+                    .iter()
+                    .map(|delta| ("portfolio trade", delta)),
+            )
+            .map(|(kind, delta)| {
+                [
+                    kind.to_owned(),
+                    delta.id.clone(),
+                    optional(delta.before, |value| format!("{value:.6}")),
+                    optional(delta.after, |value| format!("{value:.6}")),
+                    optional(delta.delta, |value| format!("{value:+.3e}")),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        if !rows.is_empty() {
+            write_table(["kind", "id", "before", "after", "delta"], &rows, buffer)?;
+        }
+        writeln!(
+            buffer,
+            "{} product price(s) and {} portfolio trade(s) differ by more than {}",
+            self.products.len(),
+            self.portfolios.len(),
+            self.tolerance
+        )?;
+
+        Ok(())
+    }
+}
+
+// Compare the values of each id, in the order of `before` and then of any ids
+// only found in `after`
+fn deltas<'a, K: Eq + Hash + Display + 'a>(
+    before: impl Iterator<Item = (&'a K, f64)>,
+    after: impl Iterator<Item = (&'a K, f64)>,
+    tolerance: f64,
+) -> Vec<Delta> {
+    let before = before.collect::<Vec<_>>();
+    let after = after.collect::<Vec<_>>();
+    let lookup = after.iter().copied().collect::<HashMap<_, _>>();
+    let seen = before.iter().map(|(id, _)| *id).collect::<HashSet<_>>();
+
+    let mut deltas = Vec::new();
+    for (id, value) in before {
+        match lookup.get(id) {
+            // A NaN is never within tolerance
+            Some(&other) if (other - value).is_nan() || (other - value).abs() > tolerance => deltas
+                .push(Delta {
+                    id: id.to_string(),
+                    before: Some(value),
+                    after: Some(other),
+                    delta: Some(other - value),
+                }),
+            Some(_) => {}
+            None => deltas.push(Delta {
+                id: id.to_string(),
+                before: Some(value),
+                after: None,
+                delta: None,
+            }),
+        }
+    }
+    deltas.extend(
+        after
+            .into_iter()
+            .filter(|(id, _)| !seen.contains(id))
+            .map(|(id, value)| Delta {
+                id: id.to_string(),
+                before: None,
+                after: Some(value),
+                delta: None,
+            }),
+    );
+
+    deltas
+}
@@ -0,0 +1,10 @@
+// The density of a generated auction is a probability, so clap should reject
+// anything else before the generator sees it
+pub fn density(s: &str) -> Result<f64, String> {
+    let density: f64 = s.parse().map_err(|err| format!("{err}"))?;
+    if (0.0..=1.0).contains(&density) {
+        Ok(density)
+    } else {
+        Err(format!("{density} is not within [0, 1]"))
+    }
+}
@@ -1,10 +1,19 @@
+use super::tabular::OutputFormat;
+use crate::{CliError, IOArgs, InputFormat, read_auction};
+use anyhow::Context as _;
 use clap::ValueEnum;
+use fts_core::models::SolverTelemetry;
 use fts_solver::{
-    PortfolioOutcome, ProductOutcome,
+    PortfolioOutcome, ProductOutcome, SolveError,
     clarabel::ClarabelSolver,
     io::{Auction, Outcome},
     osqp::OsqpSolver,
 };
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+use tokio::task::JoinSet;
 
 // This explicitly articulates the available solvers for the `solve` subcommand
 #[derive(Clone, Copy, ValueEnum)]
@@ -22,4 +31,129 @@ impl SolverLib {
             SolverLib::Osqp => auction.solve(OsqpSolver::default()).await,
         }
     }
+
+    pub async fn solve_with_telemetry(
+        &self,
+        auction: Auction,
+    ) -> (
+        Result<Outcome<PortfolioOutcome, ProductOutcome>, SolveError>,
+        SolverTelemetry,
+    ) {
+        match self {
+            SolverLib::Clarabel => {
+                auction
+                    .solve_with_telemetry(ClarabelSolver::default())
+                    .await
+            }
+            SolverLib::Osqp => auction.solve_with_telemetry(OsqpSolver::default()).await,
+        }
+    }
+
+    // Solve the input: a single auction, or in batch mode (with an output
+    // directory) every auction it names
+    pub async fn solve_input(
+        self,
+        io: &IOArgs,
+        output_format: Option<OutputFormat>,
+        output_dir: Option<&Path>,
+        jobs: NonZeroUsize,
+    ) -> anyhow::Result<()> {
+        if let Some(output_dir) = output_dir {
+            let inputs = io.inputs()?;
+            let total = inputs.len();
+            let output_format = output_format.unwrap_or(OutputFormat::Json);
+            let failures = self
+                .solve_all(inputs, io.input_format(), output_dir, output_format, jobs)
+                .await?;
+            if failures > 0 {
+                return Err(CliError::Unsolved(failures, total))?;
+            }
+        } else {
+            let auction = io.read_auction()?;
+            let (solution, _) = self.solve_with_telemetry(auction).await;
+            let output_format = output_format.unwrap_or_else(|| OutputFormat::infer(io.output()));
+            output_format.write(&solution?, io.output())?;
+        }
+
+        Ok(())
+    }
+
+    // Solve each auction independently, writing its solution to the file (or
+    // directory of tables) of the same name in `output_dir`, with up to `jobs` solves at once. A
+    // failure is reported without interrupting the others, and the number of
+    // failures is returned.
+    pub async fn solve_all(
+        self,
+        inputs: Vec<PathBuf>,
+        format: Option<InputFormat>,
+        output_dir: &Path,
+        output_format: OutputFormat,
+        jobs: NonZeroUsize,
+    ) -> anyhow::Result<usize> {
+        std::fs::create_dir_all(output_dir)?;
+
+        let mut outputs = Vec::with_capacity(inputs.len());
+        for input in &inputs {
+            let name = input
+                .file_name()
+                .with_context(|| format!("{} is not a file", input.display()))?;
+            let output = output_format.output_path(output_dir, name.as_ref());
+            if outputs.contains(&output) {
+                anyhow::bail!(
+                    "more than one input would be written to {}",
+                    output.display()
+                );
+            }
+            outputs.push(output);
+        }
+
+        let mut tasks = JoinSet::new();
+        let mut failures = 0;
+        for (input, output) in inputs.into_iter().zip(outputs) {
+            if tasks.len() >= jobs.get() {
+                failures += Self::report(tasks.join_next().await);
+            }
+            tasks.spawn(async move {
+                let result = self
+                    .solve_file(&input, format, &output, output_format)
+                    .await;
+                (input, result)
+            });
+        }
+        while let Some(joined) = tasks.join_next().await {
+            failures += Self::report(Some(joined));
+        }
+
+        Ok(failures)
+    }
+
+    async fn solve_file(
+        self,
+        input: &Path,
+        format: Option<InputFormat>,
+        output: &Path,
+        output_format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let auction = read_auction(input, format)?;
+        let (solution, _) = self.solve_with_telemetry(auction).await;
+        output_format.write_path(&solution?, output)?;
+        Ok(())
+    }
+
+    // Report the failure of a solve, if it failed, counting it
+    fn report(
+        joined: Option<Result<(PathBuf, anyhow::Result<()>), tokio::task::JoinError>>,
+    ) -> usize {
+        match joined {
+            Some(Ok((_, Ok(())))) | None => 0,
+            Some(Ok((input, Err(error)))) => {
+                eprintln!("{}: {error}", input.display());
+                1
+            }
+            Some(Err(error)) => {
+                eprintln!("{error}");
+                1
+            }
+        }
+    }
 }
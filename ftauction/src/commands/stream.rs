@@ -0,0 +1,55 @@
+use super::solve::SolverLib;
+use serde::Serialize;
+use std::io::{BufRead, Write};
+
+// In place of the outcome of an auction which could not be solved, so that
+// the outcomes still line up with the auctions
+#[derive(Serialize)]
+struct Failure {
+    error: String,
+}
+
+impl SolverLib {
+    // Solve each line of the input as an auction, writing its outcome (or the
+    // reason it has none) as a line of the output as soon as it is solved.
+    // Blank lines are skipped. Returns the number of failures and of auctions.
+    pub async fn solve_stream<R: BufRead, W: Write>(
+        self,
+        input: R,
+        output: &mut W,
+    ) -> anyhow::Result<(usize, usize)> {
+        let mut failures = 0;
+        let mut total = 0;
+
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            total += 1;
+
+            let solution = match serde_json::from_str(&line) {
+                Ok(auction) => self
+                    .solve_with_telemetry(auction)
+                    .await
+                    .0
+                    .map_err(anyhow::Error::from),
+                Err(error) => Err(error.into()),
+            };
+            match solution {
+                Ok(outcome) => serde_json::to_writer(&mut *output, &outcome)?,
+                Err(error) => {
+                    failures += 1;
+                    let failure = Failure {
+                        error: error.to_string(),
+                    };
+                    serde_json::to_writer(&mut *output, &failure)?;
+                }
+            }
+            writeln!(output)?;
+            output.flush()?;
+        }
+
+        Ok((failures, total))
+    }
+}
@@ -0,0 +1,33 @@
+use std::io::Write;
+
+// Write rows of cells as a plain-text table, each column as wide as its widest cell
+pub fn write_table<W: Write, const N: usize>(
+    header: [&str; N],
+    rows: &[[String; N]],
+    buffer: &mut W,
+) -> anyhow::Result<()> {
+    let mut widths = header.map(|column| column.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.map(str::to_owned);
+    for row in std::iter::once(&header).chain(rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(buffer, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+// Format an optional number, with a dash in place of a missing one
+pub fn optional(value: Option<f64>, format: impl Fn(f64) -> String) -> String {
+    value.map_or_else(|| "-".to_owned(), format)
+}
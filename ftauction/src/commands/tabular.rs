@@ -0,0 +1,167 @@
+use crate::{CliError, MSGPACK, OutputArgs};
+use anyhow::Context as _;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use clap::ValueEnum;
+use fts_solver::{PortfolioOutcome, ProductOutcome, io::Outcome};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// A solution is either a single JSON (or MessagePack) document, or a directory
+// holding a table of products and a table of portfolios, for analysis in
+// pandas, duckdb, etc.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Msgpack,
+    Parquet,
+    Arrow,
+}
+
+impl OutputFormat {
+    // Only a document's format can be inferred from the output's extension
+    pub fn infer(output: &OutputArgs) -> Self {
+        if output.extension() == Some(MSGPACK) {
+            Self::Msgpack
+        } else {
+            Self::Json
+        }
+    }
+
+    // The path of the solution of the auction named `name`, in batch mode
+    pub fn output_path(self, output_dir: &Path, name: &Path) -> PathBuf {
+        match self {
+            Self::Json => output_dir.join(name).with_extension("json"),
+            Self::Msgpack => output_dir.join(name).with_extension(MSGPACK),
+            Self::Parquet | Self::Arrow => output_dir.join(name).with_extension(""),
+        }
+    }
+
+    pub fn write(
+        self,
+        outcome: &Outcome<PortfolioOutcome, ProductOutcome>,
+        output: &OutputArgs,
+    ) -> anyhow::Result<()> {
+        match (self, output.path()) {
+            (_, Some(path)) => self.write_path(outcome, path),
+            (Self::Json | Self::Msgpack, None) => self.write_document(outcome, output.write()?),
+            (Self::Parquet | Self::Arrow, None) => Err(CliError::TabularStdout)?,
+        }
+    }
+
+    pub fn write_path(
+        self,
+        outcome: &Outcome<PortfolioOutcome, ProductOutcome>,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let extension = match self {
+            Self::Json | Self::Msgpack => {
+                return write_atomic(path, |file| {
+                    self.write_document(outcome, BufWriter::new(file))
+                });
+            }
+            Self::Parquet => "parquet",
+            Self::Arrow => "arrow",
+        };
+
+        let products = table(
+            "product_id",
+            outcome
+                .products
+                .iter()
+                .map(|(id, outcome)| (id.to_string(), outcome.price, outcome.rate)),
+        )?;
+        let portfolios = table(
+            "portfolio_id",
+            outcome
+                .portfolios
+                .iter()
+                .map(|(id, outcome)| (id.to_string(), outcome.price, outcome.rate)),
+        )?;
+
+        std::fs::create_dir_all(path)?;
+        for (name, batch) in [("products", products), ("portfolios", portfolios)] {
+            write_atomic(&path.join(name).with_extension(extension), |file| {
+                self.write_batch(&batch, file)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn write_document<W: Write>(
+        self,
+        outcome: &Outcome<PortfolioOutcome, ProductOutcome>,
+        mut output: W,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Json => serde_json::to_writer_pretty(&mut output, outcome)?,
+            Self::Msgpack => rmp_serde::encode::write_named(&mut output, outcome)?,
+            Self::Parquet | Self::Arrow => unreachable!("tabular solutions are not documents"),
+        }
+        Ok(output.flush()?)
+    }
+
+    fn write_batch<W: Write + Send>(self, batch: &RecordBatch, file: W) -> anyhow::Result<()> {
+        match self {
+            Self::Json | Self::Msgpack => unreachable!("document solutions are not tabular"),
+            Self::Parquet => {
+                // Snappy is the default of pandas and duckdb alike
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))?;
+                writer.write(batch)?;
+                writer.close()?;
+            }
+            Self::Arrow => {
+                let mut writer = arrow_ipc::writer::FileWriter::try_new(file, &batch.schema())?;
+                writer.write(batch)?;
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Write to a temporary file beside `path`, renaming it into place once it is
+// complete, so that nothing reading the solution ever sees half of it
+fn write_atomic(path: &Path, write: impl FnOnce(File) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file", path.display()))?;
+    let temporary = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+
+    let result = File::create(&temporary)
+        .map_err(anyhow::Error::from)
+        .and_then(write)
+        .and_then(|()| Ok(std::fs::rename(&temporary, path)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+// A table of the price and rate of each product or portfolio, keyed by `id`
+fn table(id: &str, rows: impl Iterator<Item = (String, f64, f64)>) -> anyhow::Result<RecordBatch> {
+    let (ids, (prices, rates)): (Vec<_>, (Vec<_>, Vec<_>)) =
+        rows.map(|(id, price, rate)| (id, (price, rate))).unzip();
+
+    let schema = Schema::new(vec![
+        Field::new(id, DataType::Utf8, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("rate", DataType::Float64, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(ids)),
+        Arc::new(Float64Array::from(prices)),
+        Arc::new(Float64Array::from(rates)),
+    ];
+
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
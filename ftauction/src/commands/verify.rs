@@ -0,0 +1,272 @@
+use super::table::{optional, write_table};
+use fts_core::models::Point;
+use fts_solver::{
+    PortfolioOutcome, ProductOutcome,
+    io::{Auction, DemandId, Outcome, ProductId},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
+
+// A check of an outcome against its auction which failed
+#[derive(Serialize)]
+pub struct Violation {
+    pub check: &'static str,
+    pub id: String,
+    pub expected: Option<f64>,
+    pub actual: Option<f64>,
+}
+
+// The checks an outcome fails against its auction, along with the surplus it
+// realizes and that of the reference solution
+#[derive(Serialize)]
+pub struct OutcomeVerification {
+    pub tolerance: f64,
+    pub surplus: f64,
+    pub reference: f64,
+    pub violations: Vec<Violation>,
+}
+
+impl OutcomeVerification {
+    // Check the outcome against the auction and a reference solution of it.
+    //
+    // The rate of each demand curve, the sum of the rates of its portfolios
+    // weighted by the curve, must lie within its domain. The trades of each
+    // product must net to zero, and its rate must be the volume traded (half
+    // the total of the absolute trades). Each portfolio's price must be the
+    // prices of its products weighted by its basis. Lastly, the surplus of
+    // the outcome (the integral of each demand curve from zero to its rate)
+    // must match that of the reference, since both maximize it.
+    //
+    // Differences are compared to the tolerance relative to the magnitude of
+    // the values concerned, or absolutely for values smaller than one.
+    pub fn verify(
+        auction: &Auction,
+        outcome: &Outcome<PortfolioOutcome, ProductOutcome>,
+        reference: &Outcome<PortfolioOutcome, ProductOutcome>,
+        tolerance: f64,
+    ) -> Self {
+        let mut violations = Vec::new();
+
+        for portfolio_id in outcome.portfolios.keys() {
+            if !auction.portfolios.contains_key(portfolio_id) {
+                violations.push(Violation {
+                    check: "unknown portfolio",
+                    id: portfolio_id.to_string(),
+                    expected: None,
+                    actual: None,
+                });
+            }
+        }
+
+        let mut rates: HashMap<&DemandId, f64> = HashMap::new();
+        // In order of product, so that violations are always listed alike
+        let mut trades: BTreeMap<&ProductId, (f64, f64)> = BTreeMap::new();
+        for (portfolio_id, portfolio) in auction.portfolios.iter() {
+            let Some(portfolio_outcome) = outcome.portfolios.get(portfolio_id) else {
+                violations.push(Violation {
+                    check: "missing portfolio",
+                    id: portfolio_id.to_string(),
+                    expected: None,
+                    actual: None,
+                });
+                continue;
+            };
+            let rate = portfolio_outcome.rate;
+
+            for (demand_id, &weight) in portfolio.demand().iter() {
+                *rates.entry(demand_id).or_default() += weight * rate;
+            }
+
+            let mut price = 0.0;
+            for (product_id, &weight) in portfolio.basis().iter() {
+                let (net, volume) = trades.entry(product_id).or_default();
+                *net += weight * rate;
+                *volume += (weight * rate).abs();
+                if let Some(product_outcome) = outcome.products.get(product_id) {
+                    price += weight * product_outcome.price;
+                }
+            }
+            // The price of a portfolio without products is left undefined
+            if !portfolio.basis().is_empty() && !close(price, portfolio_outcome.price, tolerance) {
+                violations.push(Violation {
+                    check: "portfolio price",
+                    id: portfolio_id.to_string(),
+                    expected: Some(price),
+                    actual: Some(portfolio_outcome.price),
+                });
+            }
+        }
+
+        for (demand_id, curve) in auction.demand_curves.iter() {
+            let rate = rates.get(demand_id).copied().unwrap_or_default();
+            let (min, max) = curve.domain();
+            let bound = if rate < min {
+                Some(min)
+            } else if rate > max {
+                Some(max)
+            } else {
+                None
+            };
+            if let Some(bound) = bound.filter(|&bound| !close(bound, rate, tolerance)) {
+                violations.push(Violation {
+                    check: "demand domain",
+                    id: demand_id.to_string(),
+                    expected: Some(bound),
+                    actual: Some(rate),
+                });
+            }
+        }
+
+        for (product_id, &(net, volume)) in trades.iter() {
+            if net.is_nan() || net.abs() > tolerance * volume.max(1.0) {
+                violations.push(Violation {
+                    check: "product balance",
+                    id: product_id.to_string(),
+                    expected: Some(0.0),
+                    actual: Some(net),
+                });
+            }
+            match outcome.products.get(*product_id) {
+                Some(product_outcome) => {
+                    if !close(volume * 0.5, product_outcome.rate, tolerance) {
+                        violations.push(Violation {
+                            check: "product volume",
+                            id: product_id.to_string(),
+                            expected: Some(volume * 0.5),
+                            actual: Some(product_outcome.rate),
+                        });
+                    }
+                }
+                None => violations.push(Violation {
+                    check: "missing product",
+                    id: product_id.to_string(),
+                    expected: None,
+                    actual: None,
+                }),
+            }
+        }
+        for product_id in outcome.products.keys() {
+            if !trades.contains_key(product_id) {
+                violations.push(Violation {
+                    check: "unknown product",
+                    id: product_id.to_string(),
+                    expected: None,
+                    actual: None,
+                });
+            }
+        }
+
+        let (surplus, reference) = (surplus(auction, outcome), surplus(auction, reference));
+        if !close(reference, surplus, tolerance) {
+            violations.push(Violation {
+                check: "surplus",
+                id: "-".to_owned(),
+                expected: Some(reference),
+                actual: Some(surplus),
+            });
+        }
+
+        Self {
+            tolerance,
+            surplus,
+            reference,
+            violations,
+        }
+    }
+
+    // The number of checks failed
+    pub fn len(&self) -> usize {
+        self.violations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Write the violations as a plain-text table, followed by a summary
+    pub fn write_table<W: Write>(&self, buffer: &mut W) -> anyhow::Result<()> {
+        let rows = self
+            .violations
+            .iter()
+            .map(|violation| {
+                [
+                    violation.check.to_owned(),
+                    violation.id.clone(),
+                    optional(violation.expected, |value| format!("{value:.6}")),
+                    optional(violation.actual, |value| format!("{value:.6}")),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        if !rows.is_empty() {
+            write_table(["check", "id", "expected", "actual"], &rows, buffer)?;
+        }
+        writeln!(
+            buffer,
+            "surplus {:.6} (reference {:.6}); {} check(s) fail by more than {}",
+            self.surplus,
+            self.reference,
+            self.len(),
+            self.tolerance
+        )?;
+
+        Ok(())
+    }
+}
+
+// Whether two values agree to within the tolerance, relative to the larger of
+// their magnitudes (a NaN never agrees)
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+}
+
+// The surplus the outcome realizes: the integral of each demand curve from
+// zero to its rate
+fn surplus(auction: &Auction, outcome: &Outcome<PortfolioOutcome, ProductOutcome>) -> f64 {
+    let mut rates: HashMap<&DemandId, f64> = HashMap::new();
+    for (portfolio_id, portfolio) in auction.portfolios.iter() {
+        let rate = outcome
+            .portfolios
+            .get(portfolio_id)
+            .map_or(0.0, |outcome| outcome.rate);
+        for (demand_id, &weight) in portfolio.demand().iter() {
+            *rates.entry(demand_id).or_default() += weight * rate;
+        }
+    }
+
+    auction
+        .demand_curves
+        .iter()
+        .map(|(demand_id, curve)| {
+            let rate = rates.get(demand_id).copied().unwrap_or_default();
+            integrate(&curve.clone().points(), rate)
+        })
+        .sum()
+}
+
+// The integral of the price of a piecewise linear curve from zero to `rate`,
+// which is negative when selling. The curve is clamped to its domain.
+fn integrate(points: &[Point], rate: f64) -> f64 {
+    let (lo, hi) = (rate.min(0.0), rate.max(0.0));
+    let mut area = 0.0;
+    for pair in points.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let (u, v) = (a.rate.max(lo), b.rate.min(hi));
+        if u >= v {
+            continue;
+        }
+        // Constant segments may extend without bound
+        let at = |x: f64| {
+            if a.price == b.price {
+                a.price
+            } else {
+                a.price + (x - a.rate) / (b.rate - a.rate) * (b.price - a.price)
+            }
+        };
+        area += 0.5 * (at(u) + at(v)) * (v - u);
+    }
+    if rate < 0.0 { -area } else { area }
+}
@@ -0,0 +1,72 @@
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+// Editors and generators often touch a file several times in quick succession,
+// so changes are coalesced until there is a pause of this length
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Run `solve` once, and again whenever `path` (or anything within it, if it is
+// a directory) changes, until interrupted. Changes to the paths in `ignore`
+// (i.e. our own outputs) are not changes to the input, and failures are
+// reported without ending the watch.
+pub async fn watch(
+    path: &Path,
+    ignore: &[PathBuf],
+    mut solve: impl AsyncFnMut() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let path = std::path::absolute(path)?;
+    let ignore = ignore
+        .iter()
+        .map(std::path::absolute)
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    // A file is watched through its directory, as editors commonly save by
+    // replacing the file rather than writing to it
+    let (root, mode) = if path.is_dir() {
+        (path.clone(), RecursiveMode::Recursive)
+    } else {
+        let parent = path.parent().unwrap_or(Path::new("/")).to_owned();
+        (parent, RecursiveMode::NonRecursive)
+    };
+    let relevant = move |changed: &Path| {
+        (changed == path || (changed.starts_with(&path) && path.is_dir()))
+            && !ignore.iter().any(|ignored| changed.starts_with(ignored))
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            // Reading the input is not changing it
+            Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+            Ok(event) => {
+                if event.paths.iter().any(|changed| relevant(changed)) {
+                    let _ = tx.send(());
+                }
+            }
+            Err(error) => eprintln!("Unable to watch for changes: {error}"),
+        }
+    })?;
+    watcher.watch(&root, mode)?;
+
+    loop {
+        if let Err(error) = solve().await {
+            eprintln!("Error: {error}");
+        }
+        eprintln!("Watching for changes (press Ctrl-C to stop)");
+
+        if rx.recv().await.is_none() {
+            return Ok(());
+        }
+        loop {
+            match tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return Ok(()),
+                Err(_) => break,
+            }
+        }
+    }
+}
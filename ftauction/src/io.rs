@@ -1,8 +1,11 @@
-use clap::Args;
+use anyhow::Context as _;
+use clap::{Args, ValueEnum};
+use fts_solver::io::{Auction, AuctionDto};
+use serde::Serialize;
 use std::{
     fs::File,
-    io::{BufReader, BufWriter, Read, Write, stdin, stdout},
-    path::PathBuf,
+    io::{BufRead, BufReader, BufWriter, Read, Write, stdin, stdout},
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -10,13 +13,16 @@ use std::{
 // This struct standardizes their implementation.
 #[derive(Args)]
 pub struct IOArgs {
-    /// The auction JSON file ("-" implies stdin)
+    /// The auction file, or directory or zip of CSV tables ("-" implies stdin)
     #[arg(value_parser = clap::value_parser!(PathOrStd))]
     input: PathOrStd,
 
-    /// The output file ("-" implies stdout)
-    #[arg(short, long, default_value = "-", value_parser = clap::value_parser!(PathOrStd))]
-    output: PathOrStd,
+    /// The format of the input (if omitted, will infer based on filename)
+    #[arg(long, value_enum)]
+    input_format: Option<InputFormat>,
+
+    #[command(flatten)]
+    output: OutputArgs,
 }
 
 impl IOArgs {
@@ -27,6 +33,245 @@ impl IOArgs {
         }
     }
 
+    pub fn write(&self) -> anyhow::Result<Box<dyn Write>> {
+        self.output.write()
+    }
+
+    pub fn output(&self) -> &OutputArgs {
+        &self.output
+    }
+
+    pub fn input_format(&self) -> Option<InputFormat> {
+        self.input_format
+    }
+
+    // Read the auction, without validating its demand curves
+    pub fn read_auction_dto(&self) -> anyhow::Result<AuctionDto> {
+        match &self.input {
+            PathOrStd::Path(path) => read_auction_dto(path, self.input_format),
+            PathOrStd::Std => self
+                .input_format
+                .unwrap_or(InputFormat::Json)
+                .read_dto(self.read()?),
+        }
+    }
+
+    // Read the auction, failing if any demand curve is invalid
+    pub fn read_auction(&self) -> anyhow::Result<Auction> {
+        match &self.input {
+            PathOrStd::Path(path) => read_auction(path, self.input_format),
+            PathOrStd::Std => self
+                .input_format
+                .unwrap_or(InputFormat::Json)
+                .read(self.read()?),
+        }
+    }
+
+    // In batch mode, the input names many files: those of a directory (in
+    // order of their name), or those matching a glob pattern
+    pub fn inputs(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let PathOrStd::Path(path) = &self.input else {
+            anyhow::bail!("Batch mode cannot read auctions from stdin");
+        };
+
+        let mut inputs = if path.is_dir() {
+            std::fs::read_dir(path)?
+                .map(|entry| Ok(entry?.path()))
+                .filter(|path| {
+                    path.as_ref().map_or(true, |path: &PathBuf| {
+                        path.is_file()
+                            && path.extension().is_some_and(|ext| {
+                                ext == "json" || ext == "csv" || ext == ZIP || ext == MSGPACK
+                            })
+                    })
+                })
+                .collect::<std::io::Result<Vec<_>>>()?
+        } else {
+            let pattern = path
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("{} is not a valid pattern", path.display()))?;
+            glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?
+        };
+        inputs.sort();
+
+        if inputs.is_empty() {
+            anyhow::bail!("No auctions found in {}", path.display());
+        }
+        Ok(inputs)
+    }
+
+    pub fn extension(&self) -> Option<&str> {
+        self.output.extension()
+    }
+
+    // The path to watch for changes to the input: the input itself or, for a
+    // glob pattern, the directory before its first wildcard
+    pub fn watch_path(&self) -> anyhow::Result<PathBuf> {
+        let PathOrStd::Path(path) = &self.input else {
+            anyhow::bail!("Cannot watch stdin for changes");
+        };
+        if path.exists() {
+            return Ok(path.clone());
+        }
+
+        let root = path
+            .components()
+            .take_while(|component| {
+                !component
+                    .as_os_str()
+                    .to_string_lossy()
+                    .contains(['*', '?', '['])
+            })
+            .collect::<PathBuf>();
+        if root.as_os_str().is_empty() {
+            Ok(PathBuf::from("."))
+        } else {
+            Ok(root)
+        }
+    }
+}
+
+// Auctions are JSON by default, but may also be CSV: either a single file with
+// a `kind` column, or a directory or zip of the tables below (see the README). For
+// large volumes of small auctions, MessagePack is far cheaper to (de)serialize.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum InputFormat {
+    Json,
+    Csv,
+    Msgpack,
+}
+
+// The extension of MessagePack files, whether auctions or outcomes
+pub const MSGPACK: &str = "msgpack";
+
+// The extension of zip archives of CSV tables
+const ZIP: &str = "zip";
+
+const CSV_TABLES: [&str; 3] = ["demands.csv", "portfolios.csv", "weights.csv"];
+
+impl InputFormat {
+    // A directory or zip can only hold CSV tables, and otherwise the extension
+    // decides
+    pub fn infer(path: &Path) -> Self {
+        if path.is_dir() {
+            return Self::Csv;
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv" | ZIP) => Self::Csv,
+            Some(MSGPACK) => Self::Msgpack,
+            _ => Self::Json,
+        }
+    }
+
+    fn read_dto<R: Read>(self, reader: R) -> anyhow::Result<AuctionDto> {
+        match self {
+            Self::Json => Ok(serde_json::from_reader(reader)?),
+            Self::Csv => Ok(AuctionDto::from_csv(reader)?),
+            Self::Msgpack => Ok(rmp_serde::from_read(reader)?),
+        }
+    }
+
+    fn read<R: Read>(self, reader: R) -> anyhow::Result<Auction> {
+        match self {
+            Self::Json => Ok(serde_json::from_reader(reader)?),
+            Self::Csv => Ok(Auction::try_from(self.read_dto(reader)?)?),
+            Self::Msgpack => Ok(rmp_serde::from_read(reader)?),
+        }
+    }
+}
+
+// Read the auction at the path, without validating its demand curves
+pub fn read_auction_dto(path: &Path, format: Option<InputFormat>) -> anyhow::Result<AuctionDto> {
+    let format = format.unwrap_or_else(|| InputFormat::infer(path));
+    if path.is_dir() {
+        if !matches!(format, InputFormat::Csv) {
+            anyhow::bail!("{} is a directory, not a file", path.display());
+        }
+        let [demands, portfolios, weights] = CSV_TABLES.map(|name| {
+            let table = path.join(name);
+            File::open(&table)
+                .map(BufReader::new)
+                .with_context(|| format!("Unable to open {}", table.display()))
+        });
+        Ok(AuctionDto::from_csv_tables(
+            demands?,
+            portfolios?,
+            weights?,
+        )?)
+    } else if is_zip(path) {
+        if !matches!(format, InputFormat::Csv) {
+            anyhow::bail!("{} is a zip of CSV tables", path.display());
+        }
+        read_csv_zip(path)
+    } else {
+        format.read_dto(BufReader::new(File::open(path)?))
+    }
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == ZIP)
+}
+
+// Read the CSV tables of a zip, which may be at its root or in a single
+// directory (as when a directory is zipped)
+fn read_csv_zip(path: &Path) -> anyhow::Result<AuctionDto> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+    let [demands, portfolios, weights] = CSV_TABLES.map(|name| {
+        let member = archive
+            .file_names()
+            .filter(|member| *member == name || member.ends_with(&format!("/{name}")))
+            .min_by_key(|member| member.len())
+            .map(str::to_owned)
+            .with_context(|| format!("{} has no {name}", path.display()))?;
+        let mut table = Vec::new();
+        archive.by_name(&member)?.read_to_end(&mut table)?;
+        anyhow::Ok(table)
+    });
+    Ok(AuctionDto::from_csv_tables(
+        demands?.as_slice(),
+        portfolios?.as_slice(),
+        weights?.as_slice(),
+    )?)
+}
+
+// Read the auction at the path, failing if any demand curve is invalid
+pub fn read_auction(path: &Path, format: Option<InputFormat>) -> anyhow::Result<Auction> {
+    let format = format.unwrap_or_else(|| InputFormat::infer(path));
+    if path.is_dir() || is_zip(path) {
+        Ok(Auction::try_from(read_auction_dto(path, Some(format))?)?)
+    } else {
+        format.read(BufReader::new(File::open(path)?))
+    }
+}
+
+// Subcommands which stream auctions read them as they arrive, so only have a
+// (JSON) input rather than the full IOArgs.
+#[derive(Args)]
+pub struct InputArgs {
+    /// The newline-delimited auction JSON file ("-" implies stdin)
+    #[arg(default_value = "-", value_parser = clap::value_parser!(PathOrStd))]
+    input: PathOrStd,
+}
+
+impl InputArgs {
+    pub fn read(&self) -> anyhow::Result<Box<dyn BufRead>> {
+        match &self.input {
+            PathOrStd::Path(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+            PathOrStd::Std => Ok(Box::new(stdin().lock())),
+        }
+    }
+}
+
+// Subcommands which produce an auction, rather than read one, only have an output.
+#[derive(Args)]
+pub struct OutputArgs {
+    /// The output file ("-" implies stdout)
+    #[arg(short, long, default_value = "-", value_parser = clap::value_parser!(PathOrStd))]
+    output: PathOrStd,
+}
+
+impl OutputArgs {
     pub fn write(&self) -> anyhow::Result<Box<dyn Write>> {
         match &self.output {
             PathOrStd::Path(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
@@ -34,12 +279,29 @@ impl IOArgs {
         }
     }
 
-    pub fn extension(&self) -> Option<&str> {
+    // Serialize the value as MessagePack if the output is a .msgpack file, and
+    // as JSON otherwise
+    pub fn serialize<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        let mut output = self.write()?;
+        if self.extension() == Some(MSGPACK) {
+            rmp_serde::encode::write_named(&mut output, value)?;
+        } else {
+            serde_json::to_writer_pretty(&mut output, value)?;
+        }
+        Ok(output.flush()?)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
         match &self.output {
-            PathOrStd::Path(path) => path.extension(),
+            PathOrStd::Path(path) => Some(path),
             PathOrStd::Std => None,
         }
-        .and_then(|ext| ext.to_str())
+    }
+
+    pub fn extension(&self) -> Option<&str> {
+        self.path()
+            .and_then(|path| path.extension())
+            .and_then(|ext| ext.to_str())
     }
 }
 
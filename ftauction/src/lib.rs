@@ -1,5 +1,11 @@
 use clap::Parser;
-use fts_solver::io::Auction;
+use fts_core::models::Severity;
+use fts_solver::{
+    PortfolioOutcome, ProductOutcome,
+    export::ExportFormat,
+    io::{AuctionGenerator, Outcome},
+};
+use std::{fs::File, io::BufReader, path::Path};
 
 mod io;
 pub use io::*;
@@ -18,27 +24,163 @@ pub struct BaseArgs {
 impl BaseArgs {
     pub async fn evaluate(self) -> anyhow::Result<()> {
         match self.command {
-            Commands::Solve { io, lib } => {
-                let input = io.read()?;
-                let auction = serde_json::from_reader::<_, Auction>(input)?;
-                let results = lib.solve(auction).await;
-                let output = io.write()?;
-                serde_json::to_writer_pretty(output, &results)?;
+            Commands::Solve {
+                io,
+                lib,
+                output_format,
+                output_dir,
+                jobs,
+                watch,
+            } => {
+                let solve = async || {
+                    lib.solve_input(&io, output_format, output_dir.as_deref(), jobs)
+                        .await
+                };
+                if watch {
+                    let ignore = output_dir
+                        .iter()
+                        .cloned()
+                        .chain(io.output().path().map(Path::to_owned))
+                        .collect::<Vec<_>>();
+                    commands::watch(&io.watch_path()?, &ignore, solve).await?;
+                } else {
+                    solve().await?;
+                }
+            }
+            Commands::Stream { input, output, lib } => {
+                let mut output = output.write()?;
+                let (failures, total) = lib.solve_stream(input.read()?, &mut output).await?;
+                if failures > 0 {
+                    return Err(CliError::Unsolved(failures, total))?;
+                }
             }
             Commands::Export { io, format } => {
-                let input = io.read()?;
-                let auction = serde_json::from_reader::<_, Auction>(input)?;
+                let auction = io.read_auction()?;
+
+                let format = format
+                    .or_else(|| io.extension().and_then(ExportFormat::from_extension))
+                    .ok_or(CliError::ExportInference)?;
+
+                let mut output = io.write()?;
+                auction.export(format, &mut output)?;
+            }
+            Commands::Bench {
+                inputs,
+                libs,
+                json,
+                output,
+            } => {
+                let mut records = Vec::new();
+                for path in inputs {
+                    let auction = read_auction(&path, None)?;
+                    records.extend(
+                        BenchRecord::compare(&path.display().to_string(), auction, &libs).await,
+                    );
+                }
 
-                let format = if let Some(format) = format {
-                    format
-                } else if let Some(ext) = io.extension() {
-                    ext.parse()?
+                let mut output = output.write()?;
+                if json {
+                    serde_json::to_writer_pretty(output, &records)?;
                 } else {
-                    return Err(CliError::ExportInference)?;
-                };
+                    BenchRecord::write_table(&records, &mut output)?;
+                }
+            }
+            Commands::Diff {
+                before,
+                after,
+                tolerance,
+                json,
+                output,
+            } => {
+                let diff = OutcomeDiff::compare(
+                    &read_outcome(&before)?,
+                    &read_outcome(&after)?,
+                    tolerance,
+                );
+
+                let mut output = output.write()?;
+                if json {
+                    serde_json::to_writer_pretty(output, &diff)?;
+                } else {
+                    diff.write_table(&mut output)?;
+                }
+
+                if !diff.is_empty() {
+                    return Err(CliError::Different(diff.len()))?;
+                }
+            }
+            Commands::Verify {
+                auction,
+                outcome,
+                tolerance,
+                lib,
+                json,
+                output,
+            } => {
+                let auction = read_auction(&auction, None)?;
+                let outcome = read_outcome(&outcome)?;
+                let (reference, _) = lib.solve_with_telemetry(auction.clone()).await;
+                let reference = reference
+                    .map_err(|error| anyhow::anyhow!("Unable to solve the auction: {error}"))?;
+                let verification =
+                    OutcomeVerification::verify(&auction, &outcome, &reference, tolerance);
+
+                let mut output = output.write()?;
+                if json {
+                    serde_json::to_writer_pretty(output, &verification)?;
+                } else {
+                    verification.write_table(&mut output)?;
+                }
+
+                if !verification.is_empty() {
+                    return Err(CliError::Unverified(verification.len()))?;
+                }
+            }
+            Commands::Depth { io, lib, json } => {
+                let auction = io.read_auction()?;
+                let (solution, _) = lib.solve_with_telemetry(auction.clone()).await;
+                let outcome = solution
+                    .inspect_err(|error| eprintln!("Unable to solve the auction: {error}"))
+                    .ok();
+                let depths = ProductDepth::compute(&auction, outcome.as_ref());
 
                 let mut output = io.write()?;
-                format.export(auction, &mut output)?;
+                if json {
+                    serde_json::to_writer_pretty(output, &depths)?;
+                } else {
+                    ProductDepth::write_table(&depths, &mut output)?;
+                }
+            }
+            Commands::Validate { io } => {
+                let auction = io.read_auction_dto()?;
+                let validation = auction.validate();
+
+                let output = io.write()?;
+                serde_json::to_writer_pretty(output, &validation)?;
+
+                if !validation.valid {
+                    let errors = validation
+                        .diagnostics
+                        .iter()
+                        .filter(|diagnostic| diagnostic.diagnostic.severity == Severity::Error)
+                        .count();
+                    return Err(CliError::Invalid(errors))?;
+                }
+            }
+            Commands::Generate {
+                output,
+                bidders,
+                products,
+                density,
+                seed,
+            } => {
+                let generator = AuctionGenerator {
+                    bidders,
+                    products,
+                    density,
+                };
+                let auction = generator.generate(seed.unwrap_or_else(rand::random));
+                output.serialize(&auction)?;
             }
         }
 
@@ -46,8 +188,28 @@ impl BaseArgs {
     }
 }
 
+// Read an outcome, as MessagePack if the file says so or else as JSON
+fn read_outcome(path: &Path) -> anyhow::Result<Outcome<PortfolioOutcome, ProductOutcome>> {
+    let input = BufReader::new(File::open(path)?);
+    if path.extension().is_some_and(|ext| ext == MSGPACK) {
+        Ok(rmp_serde::from_read(input)?)
+    } else {
+        Ok(serde_json::from_reader(input)?)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CliError {
-    #[error("Unable to infer export format, please specify a valid format")]
+    #[error("Unable to infer export format, please specify a valid format with --format")]
     ExportInference,
+    #[error("Tables are written to a directory, please specify one with --output")]
+    TabularStdout,
+    #[error("The auction is invalid, with {0} error(s)")]
+    Invalid(usize),
+    #[error("The outcomes differ, in {0} price(s) or trade(s)")]
+    Different(usize),
+    #[error("The outcome fails {0} check(s)")]
+    Unverified(usize),
+    #[error("{0} of {1} auction(s) could not be solved")]
+    Unsolved(usize, usize),
 }
@@ -0,0 +1,212 @@
+//! Backing up and restoring the database.
+//!
+//! Copying the database file is only safe while nothing writes to it, and
+//! even then misses whatever remains in its write-ahead log. A backup is
+//! instead taken with `VACUUM INTO`, which writes a consistent snapshot of
+//! the database as of a single transaction, so the server need not be
+//! stopped. Restoring a backup replaces the database with it, and so must
+//! happen while the server is stopped. Both verify the integrity of the file
+//! they write before reporting success.
+
+use fts_sqlite::{Db, config::SqliteConfig};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A backup to take of the database.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// The file to write the backup to, which must not already exist
+    pub output: PathBuf,
+}
+
+/// A backup with which to replace the database.
+#[derive(Debug, Clone)]
+pub struct Restore {
+    /// The backup to restore
+    pub input: PathBuf,
+    /// Whether to replace a database which already exists
+    pub force: bool,
+}
+
+/// The verified copy of a database written by a backup or restore.
+#[derive(Debug, Serialize)]
+pub struct BackupRecord {
+    /// The file written
+    pub path: PathBuf,
+    /// The size of the file, in bytes
+    pub bytes: u64,
+    /// The schema version of the copy, if it was ever migrated
+    pub version: Option<i64>,
+}
+
+impl Backup {
+    /// Back the database up, then verify the backup.
+    pub async fn run(&self, database: &SqliteConfig) -> anyhow::Result<BackupRecord> {
+        let db = Db::connect(database).await?;
+        db.backup(&self.output).await?;
+        db.reader.close().await;
+        db.writer.close().await;
+        verify(database, &self.output).await
+    }
+}
+
+impl Restore {
+    /// Verify the backup, replace the database with it, then verify the
+    /// database.
+    pub async fn run(&self, database: &SqliteConfig) -> anyhow::Result<BackupRecord> {
+        let Some(path) = &database.database_path else {
+            anyhow::bail!("an in-memory database cannot be restored");
+        };
+        if path.exists() && !self.force {
+            anyhow::bail!(
+                "{} already exists; stop the server and pass --force to replace it",
+                path.display()
+            );
+        }
+        verify(database, &self.input).await?;
+
+        // The backup is copied alongside the database, then moved over it, so
+        // the database is never left half-written. Its write-ahead log belongs
+        // to the database being replaced, so is discarded along with it.
+        let mut staged = path.clone().into_os_string();
+        staged.push(".restoring");
+        let staged = PathBuf::from(staged);
+        std::fs::copy(&self.input, &staged)?;
+        remove_with_log(path)?;
+        std::fs::rename(&staged, path)?;
+
+        verify(database, path).await
+    }
+}
+
+// Check the integrity of the copy of the database at `path`, reading it as
+// the database itself would be read (e.g. with the same encryption key)
+async fn verify(database: &SqliteConfig, path: &Path) -> anyhow::Result<BackupRecord> {
+    let config = SqliteConfig {
+        database_path: Some(path.to_owned()),
+        create_if_missing: false,
+        replica_paths: Vec::new(),
+        ..database.clone()
+    };
+    let db = Db::connect(&config).await?;
+    let checked = async {
+        let problems = db.integrity_check().await?;
+        let status = db.migration_status().await?;
+        Ok::<_, anyhow::Error>((problems, status))
+    }
+    .await;
+    db.reader.close().await;
+    db.writer.close().await;
+
+    let (problems, status) = checked?;
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "{} failed its integrity check: {}",
+            path.display(),
+            problems.join("; ")
+        );
+    }
+    Ok(BackupRecord {
+        path: path.to_owned(),
+        bytes: std::fs::metadata(path)?.len(),
+        version: status.version,
+    })
+}
+
+/// Remove a database file along with its write-ahead log and shared memory
+/// index, any of which may be missing.
+pub(crate) fn remove_with_log(path: &Path) -> std::io::Result<()> {
+    for suffix in ["-wal", "-shm", ""] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        match std::fs::remove_file(file) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impls::{ProductData, ProductKind};
+    use fts_core::ports::ProductRepository;
+    use fts_sqlite::types::DateTime;
+    use time::{Duration, OffsetDateTime};
+
+    #[tokio::test]
+    async fn restored_backups_match_the_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = SqliteConfig {
+            database_path: Some(dir.path().join("market.db")),
+            ..Default::default()
+        };
+        let from = OffsetDateTime::now_utc();
+        let now = DateTime::from(from);
+        let db = Db::open(&database, now).await.unwrap();
+        let product = |day: i64| {
+            let from = from + Duration::days(day);
+            let data = ProductData::new(from, from + Duration::days(1), ProductKind::Forward);
+            (data.id(), data)
+        };
+        let (backed_up, data) = product(0);
+        db.create_product(backed_up, data, now).await.unwrap();
+
+        // The backup is taken while the database is open
+        let output = dir.path().join("backup.db");
+        let record = Backup {
+            output: output.clone(),
+        }
+        .run(&database)
+        .await
+        .unwrap();
+        assert_eq!(record.path, output);
+        assert!(record.bytes > 0);
+
+        // so misses whatever is written afterwards
+        let (later, data) = product(1);
+        db.create_product(later, data, now).await.unwrap();
+        db.reader.close().await;
+        db.writer.close().await;
+
+        // An existing database is only replaced if forced
+        let restore = Restore {
+            input: output,
+            force: false,
+        };
+        assert!(restore.run(&database).await.is_err());
+        let restored = Restore {
+            force: true,
+            ..restore
+        }
+        .run(&database)
+        .await
+        .unwrap();
+        assert_eq!(restored.version, record.version);
+
+        let db = Db::open(&database, now).await.unwrap();
+        let get = async |product_id| {
+            ProductRepository::<ProductData>::get_product(&db, product_id, now)
+                .await
+                .unwrap()
+        };
+        assert!(get(backed_up).await.is_some());
+        assert!(get(later).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn corrupt_backups_are_not_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("backup.db");
+        std::fs::write(&input, b"not a database").unwrap();
+        let database = SqliteConfig {
+            database_path: Some(dir.path().join("market.db")),
+            ..Default::default()
+        };
+
+        let restore = Restore { input, force: true };
+        assert!(restore.run(&database).await.is_err());
+        assert!(!dir.path().join("market.db").exists());
+    }
+}
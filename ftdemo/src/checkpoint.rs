@@ -0,0 +1,51 @@
+//! Periodic checkpoints of the database's write-ahead log.
+//!
+//! By default, SQLite checkpoints its write-ahead log (WAL) on its own, once
+//! it has grown by `wal_autocheckpoint` pages. Deployments replicating the
+//! database may instead disable automatic checkpoints and have this module's
+//! maintenance task checkpoint the WAL on a fixed cadence.
+
+use fts_sqlite::checkpoint::CheckpointMode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{Instrument as _, Level, span};
+
+/// Configuration for periodically checkpointing the database's WAL.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// How often to checkpoint the WAL (if omitted, only automatic checkpoints are made)
+    #[serde(default, with = "humantime_serde::option")]
+    pub every: Option<Duration>,
+    /// How aggressively to checkpoint the WAL
+    #[serde(default)]
+    pub mode: CheckpointMode,
+}
+
+impl Checkpoint {
+    /// Execute a function every `every`, passing the mode of checkpoint to make.
+    ///
+    /// If no interval is configured, this never executes the function and
+    /// never returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(E)` if the function returns an error
+    pub async fn run<T, E>(
+        &self,
+        f: impl AsyncFn(CheckpointMode) -> Result<T, E>,
+    ) -> Result<(), E> {
+        let Some(every) = self.every else {
+            return std::future::pending().await;
+        };
+
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let span = span!(Level::INFO, "checkpointing");
+            f(self.mode).instrument(span).await?;
+        }
+    }
+}
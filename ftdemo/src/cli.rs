@@ -3,6 +3,7 @@
 //! This module defines the command-line arguments accepted by the application
 //! and provides parsing functionality using the clap crate.
 
+use crate::ReplaySolver;
 use clap::{Parser, Subcommand};
 use std::{
     fs::File,
@@ -10,6 +11,7 @@ use std::{
     path::PathBuf,
     str::FromStr,
 };
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
 
 /// Command-line arguments for the flow trading application.
 #[derive(Parser)]
@@ -20,7 +22,7 @@ pub struct Cli {
     pub command: Commands,
 }
 
-/// The action to take. Currently, run a server or print the OpenAPI schema
+/// The action to take. Currently, run a server, export its records or bids, migrate, check, seed, replay, prune or settle its database, simulate bidders against it, mint tokens for it, or print the OpenAPI schema
 #[derive(Subcommand)]
 pub enum Commands {
     /// Run an API server with the specified config and JWT secret
@@ -34,6 +36,315 @@ pub enum Commands {
         secret: String,
     },
 
+    /// Export the batch outcomes, trades, and settlements to Parquet files
+    Export {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The directory to write the Parquet files into
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// An RFC3339 timestamp to export from (if omitted, exports from the start)
+        #[arg(long, value_parser = parse_rfc3339)]
+        from: Option<OffsetDateTime>,
+
+        /// An RFC3339 timestamp to export until (if omitted, exports until now)
+        #[arg(long, value_parser = parse_rfc3339)]
+        until: Option<OffsetDateTime>,
+
+        /// The market to export (if omitted, exports the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Export the demands and portfolios active at a time as an auction for `ftauction solve`
+    ExportAuction {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The location to write the auction
+        #[arg(short, long, default_value = "-")]
+        output: PathOrStd,
+
+        /// An RFC3339 timestamp at which to gather the bids (if omitted, now)
+        #[arg(long, value_parser = parse_rfc3339)]
+        as_of: Option<OffsetDateTime>,
+
+        /// The market to export (if omitted, exports the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Create the products, demands and portfolios of an auction (e.g. from `export-auction`), then print a summary of them
+    ImportAuction {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The location to read the auction from
+        #[arg(default_value = "-")]
+        input: PathOrStd,
+
+        /// An RFC3339 timestamp to start the first of the daily products standing in for
+        /// products whose ids do not define them (if omitted, the start of tomorrow)
+        #[arg(long, value_parser = parse_rfc3339)]
+        from: Option<OffsetDateTime>,
+
+        /// The market to import into (if omitted, imports into the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Apply the pending schema migrations of the database, then print their status
+    Migrate {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// Only print the status of the migrations, without applying any
+        #[arg(long, conflicts_with = "dry_run")]
+        status: bool,
+
+        /// Apply the migrations to a temporary copy of the database, then print
+        /// the status the database would have, leaving it untouched
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Where to back the database up before migrating it (if omitted, next
+        /// to the database, e.g. `market.db.v12.bak` for schema version 12)
+        #[arg(long, conflicts_with = "no_backup")]
+        backup: Option<PathBuf>,
+
+        /// Migrate the database without backing it up first
+        #[arg(long)]
+        no_backup: bool,
+
+        /// The market whose database to migrate (if omitted, the database of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Back the database up to a new file (even while the server runs), then print the verified backup
+    Backup {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The file to write the backup to, which must not already exist
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// The market whose database to back up (if omitted, the database of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Replace the database with a backup (while the server is stopped), then print the verified database
+    Restore {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The backup to restore
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Replace the database if it already exists
+        #[arg(long)]
+        force: bool,
+
+        /// The market whose database to restore (if omitted, the database of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Check the records the database derives from others, then print the divergent ones
+    Check {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// Derive the divergent records anew
+        #[arg(long)]
+        repair: bool,
+
+        /// The market to check (if omitted, checks the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Check the configuration, the JWT secret and every database, then print what was found and what to do about it
+    Doctor {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The HMAC secret for verification of JWT claims (if omitted, it is not checked).
+        #[arg(short, long, env = "APP_SECRET")]
+        secret: Option<String>,
+    },
+
+    /// Solve the recorded batch auctions again, then print how their outcomes differ
+    Replay {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// An RFC3339 timestamp to replay from (if omitted, replays from the first batch)
+        #[arg(long, value_parser = parse_rfc3339)]
+        from: Option<OffsetDateTime>,
+
+        /// An RFC3339 timestamp to replay until (if omitted, replays until the last batch)
+        #[arg(long, value_parser = parse_rfc3339)]
+        until: Option<OffsetDateTime>,
+
+        /// The solver to replay the batches with
+        #[arg(long, value_enum, default_value_t = ReplaySolver::Clarabel)]
+        solver: ReplaySolver,
+
+        /// The largest difference in a price or rate considered to be the same
+        #[arg(long, default_value_t = 1e-6)]
+        tolerance: f64,
+
+        /// The market to replay (if omitted, replays the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Populate the database with a demonstration market, then print a summary of it
+    Seed {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// The seed of the random generator (the same seed generates the same market)
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// The number of bidders, alternately buyers and sellers
+        #[arg(long, default_value_t = 6)]
+        bidders: usize,
+
+        /// An RFC3339 timestamp to start the first daily product (if omitted, the start of tomorrow)
+        #[arg(long, value_parser = parse_rfc3339)]
+        from: Option<OffsetDateTime>,
+
+        /// The number of consecutive daily products
+        #[arg(long, default_value_t = 7)]
+        days: usize,
+
+        /// The number of batch auctions to run once the market is populated
+        #[arg(long, default_value_t = 0)]
+        batches: usize,
+
+        /// The market to seed (if omitted, seeds the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Prune the history superseded before the retention horizon (by default, from a copy of the database), then print what was pruned
+    Prune {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// How long to retain superseded history, e.g. "30days" (if omitted, the horizon of the retention configuration)
+        #[arg(long, value_parser = humantime_serde::re::humantime::parse_duration)]
+        horizon: Option<std::time::Duration>,
+
+        /// Prune the database itself, rather than reporting what would be pruned from it
+        #[arg(long)]
+        apply: bool,
+
+        /// The market to prune (if omitted, prunes the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Settle the trade activity since the last settlement, then print the positions and payments of each bidder
+    Settle {
+        /// Path to configuration file.
+        #[arg(short, long, env = "APP_CONFIG")]
+        config: Option<PathBuf>,
+
+        /// An RFC3339 timestamp to settle the activity before (if omitted, now)
+        #[arg(long, value_parser = parse_rfc3339)]
+        as_of: Option<OffsetDateTime>,
+
+        /// The length, in seconds, of the unit of time in which trade rates are expressed
+        #[arg(long, default_value_t = 3600.0)]
+        time_unit: f64,
+
+        /// The number of decimal places to which positions are rounded
+        #[arg(long, default_value_t = 6)]
+        position_decimals: u32,
+
+        /// The number of decimal places to which payments are rounded
+        #[arg(long, default_value_t = 2)]
+        payment_decimals: u32,
+
+        /// The market to settle (if omitted, settles the market of the database configuration)
+        #[arg(long)]
+        market: Option<String>,
+    },
+
+    /// Play synthetic bidders against a running server, then print a summary of the load
+    Simulate {
+        /// The base URL of the server
+        #[arg(long, default_value = "http://localhost:8080")]
+        url: String,
+
+        /// The HMAC secret for signing the tokens of the bidders.
+        #[arg(short, long, env = "APP_SECRET")]
+        secret: String,
+
+        /// The number of bidders, alternately buyers and sellers
+        #[arg(long, default_value_t = 10)]
+        bidders: usize,
+
+        /// The number of curve updates per second, across every bidder
+        #[arg(long, default_value_t = 10.0)]
+        rate: f64,
+
+        /// How long to update curves for, e.g. "5m" (if omitted, until interrupted)
+        #[arg(long, value_parser = humantime_serde::re::humantime::parse_duration)]
+        duration: Option<std::time::Duration>,
+
+        /// The seed of the random generator (the same seed generates the same bidders and curves)
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// The number of consecutive daily products to create, if the server has none
+        #[arg(long, default_value_t = 7)]
+        days: usize,
+    },
+
+    /// Mint a JWT signed with the JWT secret, then print it
+    Token {
+        /// The HMAC secret for signing JWT claims.
+        #[arg(short, long, env = "APP_SECRET")]
+        secret: String,
+
+        /// The UUID of the bidder the token acts for (if omitted, a new one; with `--admin`, none)
+        #[arg(long)]
+        subject: Option<uuid::Uuid>,
+
+        /// Grant every scope
+        #[arg(long)]
+        admin: bool,
+
+        /// A space-delimited list of scopes to grant (if omitted, `trade`)
+        #[arg(long)]
+        scope: Option<String>,
+
+        /// How long the token remains valid, e.g. "1h" or "30days"
+        #[arg(long, default_value = "1day", value_parser = humantime_serde::re::humantime::parse_duration)]
+        expires: std::time::Duration,
+    },
+
     /// Output the OpenAPI schema for the API
     Schema {
         /// The location to write the OpenAPI schema
@@ -52,6 +363,10 @@ impl Cli {
     }
 }
 
+fn parse_rfc3339(s: &str) -> Result<OffsetDateTime, time::error::Parse> {
+    OffsetDateTime::parse(s, &Rfc3339)
+}
+
 #[derive(Clone)]
 pub enum PathOrStd {
     Path(PathBuf),
@@ -4,9 +4,14 @@
 //! with a clear precedence order. Configuration can come from default values,
 //! configuration files, and environment variables.
 
-use crate::schedule::Scheduler;
+use crate::{
+    checkpoint::Checkpoint, metrics::Metrics, outbox::Outbox, retention::Retention,
+    schedule::Scheduler,
+};
+use fts_sqlite::{Db, config::SqliteConfig};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use time::OffsetDateTime;
 
 /// The main application configuration that composes all component configs
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -22,6 +27,87 @@ pub struct AppConfig {
     /// Batch auction scheduling configuration
     #[serde(default)]
     pub schedule: Scheduler,
+
+    /// History retention configuration
+    #[serde(default)]
+    pub retention: Retention,
+
+    /// WAL checkpoint configuration
+    #[serde(default)]
+    pub checkpoint: Checkpoint,
+
+    /// Event relay configuration
+    #[serde(default)]
+    pub outbox: Outbox,
+
+    /// Metrics exposure configuration
+    #[serde(default)]
+    pub metrics: Metrics,
+
+    /// Further markets hosted by the same process as the market of the
+    /// database configuration, each served under `/markets/<id>`
+    #[serde(default)]
+    pub markets: Vec<Market>,
+}
+
+/// A further market hosted by the process.
+///
+/// A market is given either by its id alone, hosting it in the database of
+/// the database configuration, or as a table, which may further give it a
+/// database and schedule of its own.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "MarketEntry")]
+pub struct Market {
+    /// The id of the market, under which it is served and stored
+    pub id: String,
+
+    /// The database hosting the market (if omitted, the database of the
+    /// database configuration). Its `market_id` is ignored, the market being
+    /// stored under its id instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<SqliteConfig>,
+
+    /// The schedule of the market's batch auctions (if omitted, the schedule
+    /// of the schedule configuration)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Scheduler>,
+}
+
+// The forms a market may be given in, which only exist while the
+// configuration is read
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+enum MarketEntry {
+    Id(String),
+    Table {
+        id: String,
+        #[serde(default)]
+        database: Option<SqliteConfig>,
+        #[serde(default)]
+        schedule: Option<Scheduler>,
+    },
+}
+
+impl From<MarketEntry> for Market {
+    fn from(entry: MarketEntry) -> Self {
+        match entry {
+            MarketEntry::Id(id) => Self {
+                id,
+                database: None,
+                schedule: None,
+            },
+            MarketEntry::Table {
+                id,
+                database,
+                schedule,
+            } => Self {
+                id,
+                database,
+                schedule,
+            },
+        }
+    }
 }
 
 impl AppConfig {
@@ -44,6 +130,29 @@ impl AppConfig {
     ///
     /// # Set scheduling interval
     /// export APP_SCHEDULE__EVERY="1h"
+    ///
+    /// # Further run auctions on the half hour during business hours
+    /// export APP_SCHEDULE__CRON__BUSINESS="0,30 9-16 * * mon-fri"
+    ///
+    /// # Prune history older than 30 days
+    /// export APP_RETENTION__HORIZON="30days"
+    ///
+    /// # Checkpoint the WAL every minute
+    /// export APP_CHECKPOINT__EVERY="1m"
+    ///
+    /// # Maintain the database daily, once it has been quiet for five minutes
+    /// export APP_DATABASE__MAINTENANCE__EVERY="1day"
+    /// export APP_DATABASE__MAINTENANCE__QUIET="5m"
+    ///
+    /// # Publish market events to a webhook
+    /// export APP_OUTBOX__WEBHOOK="https://example.com/events"
+    ///
+    /// # Serve metrics on a port of their own
+    /// export APP_METRICS__ENABLED=true
+    /// export APP_METRICS__BIND_ADDRESS="127.0.0.1:9090"
+    ///
+    /// # Host two further markets, separated by commas
+    /// export APP_MARKETS="east,west"
     /// ```
     pub fn load(file: Option<PathBuf>) -> anyhow::Result<Self> {
         let mut config = config::Config::builder();
@@ -69,10 +178,103 @@ impl AppConfig {
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
                 .separator("__")
+                .list_separator(",")
+                .with_list_parse_key("markets")
                 .try_parsing(true),
         );
 
         let built_config = config.build()?;
         built_config.try_deserialize().map_err(Into::into)
     }
+
+    /// The configuration of the database hosting a market (if omitted, the
+    /// market of the database configuration).
+    pub fn database(&self, market: Option<&str>) -> &SqliteConfig {
+        market
+            .and_then(|id| self.markets.iter().find(|market| market.id == id))
+            .and_then(|market| market.database.as_ref())
+            .unwrap_or(&self.database)
+    }
+
+    /// The schedule of a market's batch auctions (if omitted, the market of
+    /// the database configuration).
+    pub fn schedule(&self, market: Option<&str>) -> &Scheduler {
+        market
+            .and_then(|id| self.markets.iter().find(|market| market.id == id))
+            .and_then(|market| market.schedule.as_ref())
+            .unwrap_or(&self.schedule)
+    }
+
+    /// Open the database hosting a market, scoped to the market (if omitted,
+    /// the market of the database configuration).
+    pub async fn open(&self, market: Option<&str>, now: OffsetDateTime) -> anyhow::Result<Db> {
+        let db = Db::open(self.database(market), now.into()).await?;
+        Ok(match market {
+            Some(market) => db.market(market, now.into()).await?,
+            None => db,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cron_expressions_may_configure_their_auctions() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        std::fs::write(
+            &file,
+            "[schedule.cron]\nopen = \"0 9 * * mon-fri\"\nclose = { cron = \"30 16 * * mon-fri\", batch = { solver = \"clarabel\" } }\n",
+        )
+        .unwrap();
+        let config = AppConfig::load(Some(file)).unwrap();
+
+        let cron = &config.schedule(None).cron;
+        assert_eq!(cron["open"].batch, None);
+        assert_eq!(cron["close"].cron, "30 16 * * mon-fri".parse().unwrap());
+        assert_eq!(cron["close"].batch, Some(crate::BatchConfig::default()));
+    }
+
+    #[tokio::test]
+    async fn markets_may_have_databases_of_their_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        let pilot = dir.path().join("pilot.db");
+        std::fs::write(
+            &file,
+            format!(
+                "markets = [\"east\", {{ id = \"pilot\", database = {{ database_path = {pilot:?} }}, schedule = {{ every = \"5m\" }} }}]\n"
+            ),
+        )
+        .unwrap();
+        let config = AppConfig::load(Some(file)).unwrap();
+
+        let ids = config
+            .markets
+            .iter()
+            .map(|market| market.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["east", "pilot"]);
+        assert!(config.database(Some("east")).database_path.is_none());
+        assert_eq!(
+            config.database(Some("pilot")).database_path.as_ref(),
+            Some(&pilot)
+        );
+        assert_eq!(config.schedule(Some("east")).every, None);
+        assert_eq!(
+            config.schedule(Some("pilot")).every,
+            Some(Duration::from_secs(300))
+        );
+
+        // The market is stored under its id in its own database
+        let db = config
+            .open(Some("pilot"), OffsetDateTime::now_utc())
+            .await
+            .unwrap();
+        assert_eq!(db.market_id, "pilot");
+        assert!(pilot.exists());
+    }
 }
@@ -0,0 +1,282 @@
+//! Cron expressions for scheduling batch auctions.
+//!
+//! A market which clears on the half hour during business hours is awkward to
+//! describe as an interval, but natural as a cron expression. This module
+//! implements the five fields of the classic crontab (minute, hour, day of the
+//! month, month, and day of the week), evaluated in UTC.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use time::{Date, Duration, Month, OffsetDateTime, Time, UtcOffset};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// The furthest ahead to search for a matching time, beyond which an
+/// expression is taken never to match (e.g. the 31st of February)
+const HORIZON: Duration = Duration::days(366 * 8);
+
+/// A cron expression, such as `0,30 9-16 * * mon-fri` for every half hour
+/// from 9:00 to 16:30 on weekdays.
+///
+/// Each field is `*`, a value, a range `a-b`, or a comma-separated list of
+/// these, any of which may be stepped, as in `*/15` or `9-17/2`. Months and
+/// days of the week may be given by their first three letters, and Sunday is
+/// either 0 or 7. As in the crontab, a time matches if its day matches either
+/// the day of the month or the day of the week, unless one of them is `*`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// The earliest time at or after `after` which the expression matches,
+    /// at the start of a minute (in UTC), if any.
+    pub fn next(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let after = after.to_offset(UtcOffset::UTC);
+        let limit = after + HORIZON;
+
+        // Round up to the start of the next minute
+        let mut t = after.replace_time(Time::from_hms(after.hour(), after.minute(), 0).ok()?);
+        if t < after {
+            t += Duration::MINUTE;
+        }
+
+        while t < limit {
+            if !contains(self.months, u8::from(t.month()) as u32) {
+                let (year, month) = match t.month() {
+                    Month::December => (t.year() + 1, Month::January),
+                    month => (t.year(), month.next()),
+                };
+                let date = Date::from_calendar_date(year, month, 1).ok()?;
+                t = date.midnight().assume_utc();
+            } else if !self.matches_day(t.date()) {
+                t = t.date().next_day()?.midnight().assume_utc();
+            } else if !contains(self.hours, t.hour() as u32) {
+                t = t.replace_time(Time::from_hms(t.hour(), 0, 0).ok()?) + Duration::HOUR;
+            } else if !contains(self.minutes, t.minute() as u32) {
+                t += Duration::MINUTE;
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: Date) -> bool {
+        let day = contains(self.days, date.day() as u32);
+        let weekday = contains(
+            self.weekdays,
+            date.weekday().number_days_from_sunday() as u32,
+        );
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse one field into a set of values within `min..=max`, returning whether
+/// it was `*` (unstepped) alongside
+fn field(source: &str, min: u32, max: u32, names: &[&str]) -> Result<(u64, bool), String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            // Named months count from 1, and named weekdays from 0
+            Some(index) => index as u32 + min,
+            None => s
+                .parse()
+                .map_err(|_| format!("invalid value `{s}` in `{source}`"))?,
+        };
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!("`{s}` in `{source}` is not within {min}-{max}"))
+        }
+    };
+
+    let mut set = 0;
+    for item in source.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step `{step}` in `{source}`"))?;
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // A stepped value runs to the end of the field, as in `5/15`
+                None if step.is_some() => (value(range)?, max),
+                None => {
+                    let value = value(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if start > end {
+            return Err(format!("the range `{range}` in `{source}` is empty"));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok((set, source == "*"))
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "`{s}` does not have the five fields minute, hour, day, month, and weekday"
+            ));
+        };
+
+        let (minutes, _) = field(minutes, 0, 59, &[])?;
+        let (hours, _) = field(hours, 0, 23, &[])?;
+        let (days, any_day) = field(days, 1, 31, &[])?;
+        let (months, _) = field(months, 1, 12, &MONTHS)?;
+        let (mut weekdays, any_weekday) = field(weekdays, 0, 7, &WEEKDAYS)?;
+        // Sunday is both 0 and 7
+        if contains(weekdays, 7) {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            source: s.to_owned(),
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day,
+            any_weekday,
+        })
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(value: Cron) -> Self {
+        value.source
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn cron(s: &str) -> Cron {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_business_hours() {
+        let cron = cron("0,30 9-16 * * mon-fri");
+        // Friday afternoon
+        assert_eq!(
+            cron.next(datetime!(2025-01-03 16:10 UTC)),
+            Some(datetime!(2025-01-03 16:30 UTC))
+        );
+        assert_eq!(
+            cron.next(datetime!(2025-01-03 16:30 UTC)),
+            Some(datetime!(2025-01-03 16:30 UTC))
+        );
+        // ...then Monday morning
+        assert_eq!(
+            cron.next(datetime!(2025-01-03 16:30:00.001 UTC)),
+            Some(datetime!(2025-01-06 9:00 UTC))
+        );
+    }
+
+    #[test]
+    fn test_steps_and_ranges() {
+        assert_eq!(
+            cron("*/15 * * * *").next(datetime!(2025-01-01 0:01 UTC)),
+            Some(datetime!(2025-01-01 0:15 UTC))
+        );
+        assert_eq!(
+            cron("5/20 10-12/2 * * *").next(datetime!(2025-01-01 10:46 UTC)),
+            Some(datetime!(2025-01-01 12:05 UTC))
+        );
+        assert_eq!(
+            cron("0 0 1 jan *").next(datetime!(2025-03-01 0:00 UTC)),
+            Some(datetime!(2026-01-01 0:00 UTC))
+        );
+    }
+
+    #[test]
+    fn test_day_or_weekday() {
+        // The 13th, or any Friday
+        let cron = cron("0 0 13 * 5");
+        assert_eq!(
+            cron.next(datetime!(2025-01-04 0:00 UTC)),
+            Some(datetime!(2025-01-10 0:00 UTC))
+        );
+        assert_eq!(
+            cron.next(datetime!(2025-01-11 0:00 UTC)),
+            Some(datetime!(2025-01-13 0:00 UTC))
+        );
+        // Sunday is both 0 and 7
+        assert_eq!(
+            self::cron("0 0 * * 7").next(datetime!(2025-01-01 0:00 UTC)),
+            Some(datetime!(2025-01-05 0:00 UTC))
+        );
+    }
+
+    #[test]
+    fn test_impossible_and_invalid_expressions() {
+        assert_eq!(
+            cron("0 0 30 feb *").next(datetime!(2025-01-01 0:00 UTC)),
+            None
+        );
+        for invalid in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "0 0 * foo *",
+        ] {
+            assert!(invalid.parse::<Cron>().is_err(), "{invalid}");
+        }
+    }
+}
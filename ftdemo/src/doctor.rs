@@ -0,0 +1,453 @@
+//! Diagnosing a deployment.
+//!
+//! Support requests usually come down to one of a handful of causes: a
+//! configuration which does not load, a secret too short to be safe, a
+//! database which is corrupt or was migrated by another version, or derived
+//! records which have diverged from those they derive from. Each surfaces as
+//! a different failure, at a different time. This module checks them all at
+//! once, reporting every finding along with what to do about it.
+
+use crate::AppConfig;
+use fts_core::ports::HealthRepository as _;
+use fts_sqlite::{Db, config::SqliteConfig};
+use serde::Serialize;
+use std::{collections::HashSet, path::PathBuf};
+use time::OffsetDateTime;
+
+/// The fewest bytes of a secret which an HS256 key should have, i.e. the
+/// size of the hash
+const MIN_SECRET_BYTES: usize = 32;
+
+/// What to diagnose.
+#[derive(Debug, Clone, Default)]
+pub struct Doctor {
+    /// The configuration file (if omitted, the defaults and environment)
+    pub config: Option<PathBuf>,
+    /// The HMAC secret of the JWTs (if omitted, it is not checked)
+    pub secret: Option<String>,
+}
+
+/// How a check fared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Nothing to do
+    Ok,
+    /// Likely a mistake, though the server runs regardless
+    Warning,
+    /// The server fails, or misbehaves, until this is fixed
+    Error,
+}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// The kind of check, e.g. `integrity`
+    pub check: &'static str,
+    /// What was checked, e.g. the path of a database
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// How the check fared
+    pub status: Status,
+    /// What was found and, unless it is ok, what to do about it
+    pub message: String,
+}
+
+/// Every finding of a diagnosis.
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    /// Whether no check found an error
+    pub healthy: bool,
+    /// The findings, in the order they were checked
+    pub findings: Vec<Finding>,
+}
+
+impl DoctorReport {
+    /// The number of findings with the status.
+    pub fn count(&self, status: Status) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.status == status)
+            .count()
+    }
+}
+
+impl Doctor {
+    /// Check the configuration, the secret and every configured database.
+    ///
+    /// Nothing is changed, save that a market without a batch yet is given
+    /// one as the server would, and a database which does not exist is not
+    /// created. In-memory databases start afresh, so are not checked.
+    pub async fn run(&self, now: OffsetDateTime) -> DoctorReport {
+        let mut findings = Findings::default();
+
+        let config = match AppConfig::load(self.config.clone()) {
+            Ok(config) => {
+                let source = match &self.config {
+                    Some(path) => format!("loaded {}", path.display()),
+                    None => "no file given, so the defaults and APP_ environment variables apply"
+                        .to_owned(),
+                };
+                findings.found("config", None, Status::Ok, source);
+                Some(config)
+            }
+            Err(error) => {
+                findings.found(
+                    "config",
+                    None,
+                    Status::Error,
+                    format!(
+                        "unable to load the configuration: {error:#}; fix the file or the APP_ environment variables it names"
+                    ),
+                );
+                None
+            }
+        };
+
+        match &self.secret {
+            None => findings.found(
+                "secret",
+                None,
+                Status::Warning,
+                "no secret given, so it was not checked; pass --secret or set APP_SECRET"
+                    .to_owned(),
+            ),
+            Some(secret) if secret.len() < MIN_SECRET_BYTES => findings.found(
+                "secret",
+                None,
+                Status::Error,
+                format!(
+                    "the secret is {} bytes, fewer than the {MIN_SECRET_BYTES} an HS256 key needs to resist guessing; generate one with `openssl rand -base64 32` and sign new tokens with it",
+                    secret.len()
+                ),
+            ),
+            Some(secret) => findings.found(
+                "secret",
+                None,
+                Status::Ok,
+                format!("the secret is {} bytes", secret.len()),
+            ),
+        }
+
+        let Some(config) = config else {
+            return findings.report();
+        };
+
+        let mut ids = HashSet::from([config.database.market_id.as_str()]);
+        for market in config.markets.iter() {
+            if !ids.insert(market.id.as_str()) {
+                findings.found(
+                    "config",
+                    Some(format!("market {:?}", market.id)),
+                    Status::Error,
+                    "the market is configured more than once; give each market a distinct id"
+                        .to_owned(),
+                );
+            }
+        }
+        if config.metrics.enabled && config.metrics.bind_address == Some(config.server.bind_address)
+        {
+            findings.found(
+                "config",
+                Some("metrics".to_owned()),
+                Status::Error,
+                "metrics.bind_address is the address of the API itself; omit it to serve the metrics alongside the API, or choose another port".to_owned(),
+            );
+        }
+
+        // Each database, along with the markets it hosts
+        let mut databases: Vec<(&SqliteConfig, Vec<Option<&str>>)> =
+            vec![(&config.database, vec![None])];
+        for market in config.markets.iter() {
+            match &market.database {
+                Some(database) => databases.push((database, vec![Some(market.id.as_str())])),
+                None => databases[0].1.push(Some(market.id.as_str())),
+            }
+        }
+
+        for (database, markets) in databases {
+            check_database(database, &markets, now, &mut findings).await;
+        }
+
+        findings.report()
+    }
+}
+
+// The findings so far
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn found(
+        &mut self,
+        check: &'static str,
+        subject: Option<String>,
+        status: Status,
+        message: String,
+    ) {
+        self.0.push(Finding {
+            check,
+            subject,
+            status,
+            message,
+        });
+    }
+
+    fn report(self) -> DoctorReport {
+        DoctorReport {
+            healthy: self.0.iter().all(|finding| finding.status != Status::Error),
+            findings: self.0,
+        }
+    }
+}
+
+// Check the integrity, migrations and derived records of a database
+async fn check_database(
+    database: &SqliteConfig,
+    markets: &[Option<&str>],
+    now: OffsetDateTime,
+    findings: &mut Findings,
+) {
+    let Some(path) = database.database_path.as_ref() else {
+        findings.found(
+            "database",
+            Some(":memory:".to_owned()),
+            Status::Warning,
+            "the database is in memory, so every market it hosts is lost when the server stops; set database_path".to_owned(),
+        );
+        return;
+    };
+    let subject = Some(path.display().to_string());
+
+    if !path.exists() {
+        if database.create_if_missing {
+            findings.found(
+                "database",
+                subject,
+                Status::Warning,
+                "the database does not exist yet, and is created when the server starts; check database_path if it should".to_owned(),
+            );
+        } else {
+            findings.found(
+                "database",
+                subject,
+                Status::Error,
+                "the database does not exist, and create_if_missing is off; fix database_path, or restore a backup with `ftdemo restore`".to_owned(),
+            );
+        }
+        return;
+    }
+    for replica in database.replica_paths.iter() {
+        if !replica.exists() {
+            findings.found(
+                "database",
+                Some(replica.display().to_string()),
+                Status::Error,
+                "the replica does not exist; check that replication is running, or remove it from replica_paths".to_owned(),
+            );
+        }
+    }
+
+    let db = match Db::connect(&SqliteConfig {
+        create_if_missing: false,
+        replica_paths: Vec::new(),
+        ..database.clone()
+    })
+    .await
+    {
+        Ok(db) => db,
+        Err(error) => {
+            findings.found(
+                "database",
+                subject,
+                Status::Error,
+                format!(
+                    "unable to open the database: {error}; check its permissions, and its encryption_key if it is encrypted"
+                ),
+            );
+            return;
+        }
+    };
+
+    match db.integrity_check().await {
+        Ok(problems) if problems.is_empty() => findings.found(
+            "integrity",
+            subject.clone(),
+            Status::Ok,
+            "the database is intact".to_owned(),
+        ),
+        Ok(problems) => findings.found(
+            "integrity",
+            subject.clone(),
+            Status::Error,
+            format!(
+                "the database is corrupt, with {} problem(s), the first being {:?}; restore the latest backup with `ftdemo restore`",
+                problems.len(),
+                problems[0]
+            ),
+        ),
+        Err(error) => findings.found(
+            "integrity",
+            subject.clone(),
+            Status::Error,
+            format!("unable to check the integrity of the database: {error}"),
+        ),
+    }
+
+    let migrated = match (db.verify_migrations().await, db.check_health().await) {
+        (Ok(problems), _) if !problems.is_empty() => {
+            for problem in problems {
+                findings.found(
+                    "migrations",
+                    subject.clone(),
+                    Status::Error,
+                    format!(
+                        "{problem}, so the database cannot be migrated; run the version of ftdemo which migrated it, or restore a backup with `ftdemo restore`"
+                    ),
+                );
+            }
+            false
+        }
+        (Ok(_), Ok(status)) if status.pending > 0 => {
+            let message = if database.auto_migrate {
+                "which the server applies as it starts; to apply them beforehand, run `ftdemo migrate`"
+            } else {
+                "and auto_migrate is off, so the server refuses to start; run `ftdemo migrate`"
+            };
+            findings.found(
+                "migrations",
+                subject.clone(),
+                Status::Warning,
+                format!("{} migration(s) are pending, {message}", status.pending),
+            );
+            false
+        }
+        (Ok(_), Ok(status)) => {
+            findings.found(
+                "migrations",
+                subject.clone(),
+                Status::Ok,
+                format!(
+                    "the schema is up to date, at version {}",
+                    status.version.unwrap_or_default()
+                ),
+            );
+            true
+        }
+        (Err(error), _) | (_, Err(error)) => {
+            findings.found(
+                "migrations",
+                subject.clone(),
+                Status::Error,
+                format!("unable to check the migrations of the database: {error}"),
+            );
+            false
+        }
+    };
+
+    // The derived records only exist once the schema is up to date
+    if migrated {
+        for market in markets {
+            let id = market.unwrap_or(db.market_id.as_str());
+            let checked = async {
+                let scoped = db.market(id, now.into()).await?;
+                scoped.check_consistency(false).await
+            };
+            let (status, message) = match checked.await {
+                Ok(record) if record.is_consistent() => (
+                    Status::Ok,
+                    "the product tree and portfolio groups agree with their products and portfolios"
+                        .to_owned(),
+                ),
+                Ok(record) => (
+                    Status::Error,
+                    format!(
+                        "{} product(s) and {} portfolio(s) diverge from their derived records; run `ftdemo check --repair{}`",
+                        record.products.len(),
+                        record.portfolios.len(),
+                        market.map(|id| format!(" --market {id}")).unwrap_or_default()
+                    ),
+                ),
+                Err(error) => (
+                    Status::Error,
+                    format!("unable to check the derived records of the market: {error}"),
+                ),
+            };
+            findings.found(
+                "consistency",
+                Some(format!("market {id:?}")),
+                status,
+                message,
+            );
+        }
+    }
+
+    db.reader.close().await;
+    db.writer.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn problems_are_found_with_their_remedies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let database = dir.path().join("market.db");
+        std::fs::write(
+            &path,
+            format!(
+                "markets = [\"east\", \"east\"]\n[database]\ndatabase_path = {:?}\n",
+                database.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let now = OffsetDateTime::now_utc();
+        let doctor = Doctor {
+            config: Some(path),
+            secret: Some("short".to_owned()),
+        };
+
+        // The database does not exist yet, which is only a warning
+        let report = doctor.run(now).await;
+        let status = |report: &DoctorReport, check: &str| {
+            report
+                .findings
+                .iter()
+                .filter(|finding| finding.check == check)
+                .map(|finding| finding.status)
+                .collect::<Vec<_>>()
+        };
+        assert!(!report.healthy);
+        assert_eq!(status(&report, "secret"), [Status::Error]);
+        assert_eq!(status(&report, "config"), [Status::Ok, Status::Error]);
+        assert_eq!(status(&report, "database"), [Status::Warning]);
+
+        // Once it does, and the secret is long enough, the database is checked
+        let config = AppConfig::load(doctor.config.clone()).unwrap();
+        Db::open(&config.database, now.into()).await.unwrap();
+        let report = Doctor {
+            secret: Some("x".repeat(MIN_SECRET_BYTES)),
+            ..doctor.clone()
+        }
+        .run(now)
+        .await;
+        assert_eq!(status(&report, "secret"), [Status::Ok]);
+        assert_eq!(status(&report, "integrity"), [Status::Ok]);
+        assert_eq!(status(&report, "migrations"), [Status::Ok]);
+        assert_eq!(status(&report, "consistency"), [Status::Ok; 3]);
+        assert_eq!(report.count(Status::Error), 1);
+
+        let report = Doctor {
+            config: Some(dir.path().join("missing.toml")),
+            secret: None,
+        }
+        .run(now)
+        .await;
+        assert_eq!(status(&report, "config"), [Status::Error]);
+        assert_eq!(status(&report, "secret"), [Status::Warning]);
+        assert_eq!(report.findings.len(), 2);
+    }
+}
@@ -4,7 +4,11 @@
 //! integrating all components of the flow trading system with JWT-based
 //! authorization.
 
-use fts_core::ports::Application;
+use crate::Schedule;
+use fts_core::{
+    models::{BatchSchedule, ScheduleUpdate},
+    ports::Application,
+};
 use fts_solver::clarabel::ClarabelSolver;
 use fts_sqlite::{
     Db,
@@ -77,6 +81,72 @@ pub struct ProductData {
     kind: ProductKind,
 }
 
+impl ProductData {
+    /// Define a product of the given kind, delivered over `from` until `thru`.
+    pub fn new(from: time::OffsetDateTime, thru: time::OffsetDateTime, kind: ProductKind) -> Self {
+        Self { from, thru, kind }
+    }
+
+    /// The id of the product, which is determined by its definition.
+    pub fn id(&self) -> ProductId {
+        let duration = (((self.thru - self.from).whole_seconds() as u64) & 0xffff_ffff) << 24; // first 8 zero, middle 32 useful, last 24 zero
+        let kind = (<ProductKind as Into<u32>>::into(self.kind) as u64) & 0x00ff_ffff;
+        v8_id(0xb000_0000_0000_0000, self.from, duration | kind).into()
+    }
+
+    /// Recover the definition of a product from its id, which encodes it, or
+    /// `None` if the id is not that of a product.
+    pub fn from_id(product_id: ProductId) -> Option<Self> {
+        let (hi, lo) = product_id.0.as_u64_pair();
+        if lo & 0xf000_0000_0000_0000 != 0xb000_0000_0000_0000 {
+            return None;
+        }
+        let timestamp = (hi & 0xffff_ffff_ffff_0000) | ((hi & 0x0fff) << 4) | ((lo >> 56) & 0x000f);
+        let from = time::OffsetDateTime::from_unix_timestamp(timestamp as i64).ok()?;
+        let duration = time::Duration::seconds(((lo >> 24) & 0xffff_ffff) as i64);
+        let kind = ProductKind::try_from((lo & 0x00ff_ffff) as u32).ok()?;
+        let product = Self::new(from, from + duration, kind);
+        (product.id() == product_id).then_some(product)
+    }
+}
+
+impl DemandData {
+    /// Name a demand.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl PortfolioData {
+    /// Name a portfolio.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+/// Generate the id of a demand created at `now`, from 64 random bits.
+pub fn demand_id(now: time::OffsetDateTime, random: u64) -> DemandId {
+    v8_id(0x9000_0000_0000_0000, now, random >> 8).into() // 56 random bits
+}
+
+/// Generate the id of a portfolio created at `now`, from 64 random bits.
+pub fn portfolio_id(now: time::OffsetDateTime, random: u64) -> PortfolioId {
+    v8_id(0xa000_0000_0000_0000, now, random >> 8).into() // 56 random bits
+}
+
+/// Timestamp, partitioned into (48, 12, 4) bits and splatted into a V8 pattern
+/// with the id's tag and 56 bits of payload
+fn v8_id(tag: u64, timestamp: time::OffsetDateTime, payload: u64) -> Uuid {
+    let now = timestamp.unix_timestamp() as u64;
+    let now48 = 0xffff_ffff_ffff_0000 & now;
+    let now12 = (0xfff0 & now) >> 4;
+    let now04 = (0x000f & now) << 56;
+
+    let hi = 0x0000_0000_0000_8000 | now48 | now12;
+    let lo = tag | now04 | payload;
+    Uuid::from_u64_pair(hi, lo)
+}
+
 /// Main application implementation combining all system components.
 ///
 /// This struct implements the Application trait and provides the integration point
@@ -88,6 +158,8 @@ pub struct DemoApp {
     pub db: Db,
     /// HMAC key for JWT token verification
     pub key: HS256Key,
+    /// The schedule on which batch auctions are executed
+    pub schedule: Schedule,
 }
 
 impl DemoApp {
@@ -96,6 +168,32 @@ impl DemoApp {
         let token = context.0.token();
         self.key.verify_token::<CustomJWTClaims>(token, None).ok()
     }
+
+    /// Check whether the JWT grants the scope.
+    fn has_scope(&self, context: &Authorization<Bearer>, scope: Scope) -> bool {
+        self.claims(context)
+            .map(|claims| claims.custom.has_scope(scope))
+            .unwrap_or(false)
+    }
+
+    /// Describe the current state of the batch auction schedule.
+    fn batch_schedule(&self) -> BatchSchedule<DateTime> {
+        BatchSchedule {
+            every: self.schedule.every().map(|every| every.as_secs_f64()),
+            next: self.schedule.next().map(Into::into),
+            paused: self.schedule.paused(),
+        }
+    }
+
+    /// Extract the bidder id from the sub: claim, provided the JWT grants the scope.
+    fn bidder(&self, context: &Authorization<Bearer>, scope: Scope) -> Option<BidderId> {
+        let claims = self.claims(context)?;
+        if claims.custom.has_scope(scope) {
+            claims.subject?.parse().ok()
+        } else {
+            None
+        }
+    }
 }
 
 impl Application for DemoApp {
@@ -120,90 +218,38 @@ impl Application for DemoApp {
 
     fn generate_demand_id(&self, _data: &DemandData) -> (DemandId, DateTime) {
         let now = time::OffsetDateTime::now_utc();
-
-        let id = {
-            let rng56 = rand::rng().next_u64() >> 8; // 56 random bits
-
-            // Current timestamp, partitioned into (48, 12, 4) bits and splatted into a V8 pattern with id tag
-            let now = now.unix_timestamp() as u64;
-            let now48 = 0xffff_ffff_ffff_0000 & now;
-            let now12 = (0xfff0 & now) >> 4;
-            let now04 = (0x000f & now) << 56;
-
-            let hi = 0x0000_0000_0000_8000 | now48 | now12;
-            let lo = 0x9000_0000_0000_0000 | now04 | rng56;
-
-            Uuid::from_u64_pair(hi, lo)
-        };
-
-        (id.into(), now.into())
+        (demand_id(now, rand::rng().next_u64()), now.into())
     }
 
     fn generate_portfolio_id(&self, _data: &PortfolioData) -> (PortfolioId, DateTime) {
         let now = time::OffsetDateTime::now_utc();
-
-        let id = {
-            let rng56 = rand::rng().next_u64() >> 8; // 56 random bits
-
-            // Current timestamp, partitioned into (48, 12, 4) bits and splatted into a V8 pattern with id tag
-            let now = now.unix_timestamp() as u64;
-            let now48 = 0xffff_ffff_ffff_0000 & now;
-            let now12 = (0xfff0 & now) >> 4;
-            let now04 = (0x000f & now) << 56;
-
-            let hi = 0x0000_0000_0000_8000 | now48 | now12;
-            let lo = 0xa000_0000_0000_0000 | now04 | rng56;
-            Uuid::from_u64_pair(hi, lo)
-        };
-
-        (id.into(), now.into())
+        (portfolio_id(now, rand::rng().next_u64()), now.into())
     }
 
     fn generate_product_id(&self, data: &ProductData) -> (ProductId, DateTime) {
-        // Starting time, partitioned into (48, 12, 4) bits and splatted into a V8 pattern with id tag
-        let now = data.from.unix_timestamp() as u64;
-        let now48 = 0xffff_ffff_ffff_0000 & now;
-        let now12 = (0xfff0 & now) >> 4;
-        let now04 = (0x000f & now) << 56;
-
-        let duration = (((data.thru - data.from).whole_seconds() as u64) & 0xffff_ffff) << 24; // first 8 zero, middle 32 useful, last 24 zero
-        let kind = (<ProductKind as Into<u32>>::into(data.kind) as u64) & 0x00ff_ffff;
-
-        let hi = 0x0000_0000_0000_8000 | now48 | now12;
-        let lo = 0xb000_0000_0000_0000 | now04 | duration | kind;
-        (Uuid::from_u64_pair(hi, lo).into(), self.now())
+        (data.id(), self.now())
     }
 
     async fn can_create_bid(&self, context: &Self::Context) -> Option<BidderId> {
         // The demo app takes the standard sub: claim to be the bidder id
-        Some(self.claims(context)?.subject?.parse().ok()?)
+        self.bidder(context, Scope::Trade)
     }
 
     async fn can_query_bid(&self, context: &Self::Context) -> Vec<BidderId> {
         // The demo app takes the standard sub: claim to be the single allowed bidder id
-        self.claims(context)
-            .and_then(|claims| claims.subject)
-            .and_then(|sub| sub.parse().ok())
+        self.bidder(context, Scope::ReadOnly)
             .map(|bidder_id| vec![bidder_id])
             .unwrap_or_default()
     }
 
     async fn can_update_bid(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
         // The demo app compares the sub: claim against the specified bidder_id
-        self.claims(context)
-            .and_then(|claims| claims.subject)
-            .and_then(|sub| sub.parse::<BidderId>().ok())
-            .map(|claim_bidder| claim_bidder == bidder_id)
-            .unwrap_or(false)
+        self.bidder(context, Scope::Trade) == Some(bidder_id)
     }
 
     async fn can_read_bid(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
         // The demo app compares the sub: claim against the specified bidder_id
-        self.claims(context)
-            .and_then(|claims| claims.subject)
-            .and_then(|sub| sub.parse::<BidderId>().ok())
-            .map(|claim_bidder| claim_bidder == bidder_id)
-            .unwrap_or(false)
+        self.bidder(context, Scope::ReadOnly) == Some(bidder_id)
     }
 
     async fn can_view_products(&self, context: &Self::Context) -> bool {
@@ -215,17 +261,62 @@ impl Application for DemoApp {
     }
 
     async fn can_manage_products(&self, context: &Self::Context) -> bool {
-        // managing products requires an `admin: true` custom claim
-        self.claims(context)
-            .and_then(|claims| Some(claims.custom.admin))
-            .unwrap_or(false)
+        self.has_scope(context, Scope::ProductsManage)
     }
 
     async fn can_run_batch(&self, context: &Self::Context) -> bool {
-        // running a batch requires an `admin: true` custom claim
+        self.has_scope(context, Scope::BatchRun)
+    }
+
+    async fn can_preview_batch(&self, context: &Self::Context) -> bool {
+        self.has_scope(context, Scope::BatchPreview)
+    }
+
+    async fn can_manage_schedule(&self, context: &Self::Context) -> bool {
+        self.has_scope(context, Scope::BatchSchedule)
+    }
+
+    async fn can_view_health(&self, context: &Self::Context) -> bool {
+        // The detailed health of the system is reserved to administrators
         self.claims(context)
-            .and_then(|claims| Some(claims.custom.admin))
-            .unwrap_or(false)
+            .is_some_and(|claims| claims.custom.admin)
+    }
+
+    async fn can_run_settlement(&self, context: &Self::Context) -> bool {
+        self.has_scope(context, Scope::SettlementRun)
+    }
+
+    async fn can_purge_bid(&self, context: &Self::Context) -> bool {
+        self.has_scope(context, Scope::BidsPurge)
+    }
+
+    async fn can_audit(&self, context: &Self::Context) -> bool {
+        self.has_scope(context, Scope::Audit)
+    }
+
+    async fn can_read_settlement(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
+        // A bidder may read their own settlements, while the dedicated scope
+        // grants access to the settlements of every bidder
+        self.has_scope(context, Scope::SettlementRead)
+            || self.bidder(context, Scope::ReadOnly) == Some(bidder_id)
+    }
+
+    async fn schedule(&self) -> Option<BatchSchedule<DateTime>> {
+        Some(self.batch_schedule())
+    }
+
+    async fn update_schedule(
+        &self,
+        update: ScheduleUpdate<DateTime>,
+    ) -> Option<BatchSchedule<DateTime>> {
+        self.schedule.update(
+            update
+                .every
+                .and_then(|every| std::time::Duration::try_from_secs_f64(every).ok()),
+            update.next.map(Into::into),
+            update.paused,
+        );
+        Some(self.batch_schedule())
     }
 }
 
@@ -245,9 +336,88 @@ fn time_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
 /// Contains application-specific claims beyond standard JWT claims.
 #[derive(Serialize, Deserialize)]
 pub struct CustomJWTClaims {
-    /// Indicates whether the token holder has admin privileges.
+    /// Indicates whether the token holder has admin privileges, which grants every scope.
     #[serde(default)]
     pub admin: bool,
+
+    /// A space-delimited list of scopes (e.g. `"trade settlement:read"`), in the
+    /// manner of RFC 8693. If absent, the token is granted the `trade` scope.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl CustomJWTClaims {
+    /// Check whether the claims grant the scope. Unrecognized scopes are ignored.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        if self.admin {
+            return true;
+        }
+        match &self.scope {
+            None => Scope::Trade.implies(scope),
+            Some(scopes) => scopes
+                .split_whitespace()
+                .filter_map(|granted| granted.parse::<Scope>().ok())
+                .any(|granted| granted.implies(scope)),
+        }
+    }
+}
+
+/// The permissions that may be granted to a JWT through its `scope` claim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// `read-only`: read the bids and settlements of the token's bidder
+    ReadOnly,
+    /// `trade`: additionally create and update the bids of the token's bidder
+    Trade,
+    /// `products:manage`: create and partition products
+    ProductsManage,
+    /// `batch:run`: execute batch auctions, as well as preview them
+    BatchRun,
+    /// `batch:preview`: solve batch auctions without recording their outcomes
+    BatchPreview,
+    /// `batch:schedule`: view and change the schedule of batch auctions
+    BatchSchedule,
+    /// `settlement:read`: read the settlements of any bidder
+    SettlementRead,
+    /// `settlement:run`: settle trade activity, as well as read the settlements of any bidder
+    SettlementRun,
+    /// `bids:purge`: permanently erase the demands and portfolios of any bidder
+    BidsPurge,
+    /// `audit`: read demands, portfolios, and products as they were at another time
+    Audit,
+}
+
+impl Scope {
+    /// Check whether granting this scope also grants `other`.
+    pub fn implies(self, other: Scope) -> bool {
+        self == other
+            || matches!(
+                (self, other),
+                (Scope::Trade, Scope::ReadOnly)
+                    | (Scope::BatchRun, Scope::BatchPreview)
+                    | (Scope::SettlementRun, Scope::SettlementRead)
+            )
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read-only" => Ok(Scope::ReadOnly),
+            "trade" => Ok(Scope::Trade),
+            "products:manage" => Ok(Scope::ProductsManage),
+            "batch:run" => Ok(Scope::BatchRun),
+            "batch:preview" => Ok(Scope::BatchPreview),
+            "batch:schedule" => Ok(Scope::BatchSchedule),
+            "settlement:read" => Ok(Scope::SettlementRead),
+            "settlement:run" => Ok(Scope::SettlementRun),
+            "bids:purge" => Ok(Scope::BidsPurge),
+            "audit" => Ok(Scope::Audit),
+            other => Err(format!("unknown scope: {other}")),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -330,6 +500,7 @@ mod uuid_v8_tests {
         DemoApp {
             db: database,
             key: jwt_simple::prelude::HS256Key::generate(),
+            schedule: crate::Schedule::new(Default::default()),
         }
     }
 
@@ -450,4 +621,154 @@ mod uuid_v8_tests {
             "Only lowest byte should differ (got 0x{diff:x})"
         );
     }
+
+    // A product's definition is recovered from its id, and only from a product's id.
+    #[test]
+    fn test_product_definition_roundtrip() {
+        let base = time::OffsetDateTime::from_unix_timestamp(1_640_995_217).unwrap();
+        let option = ProductData::new(base, base + time::Duration::hours(5), ProductKind::Option);
+
+        let recovered = ProductData::from_id(option.id()).expect("a product id");
+        assert_eq!(recovered.from, option.from);
+        assert_eq!(recovered.thru, option.thru);
+        assert_eq!(recovered.id(), option.id());
+
+        let demand_id = demand_id(base, 42);
+        assert!(ProductData::from_id(demand_id.0.into()).is_none());
+    }
+}
+
+#[cfg(test)]
+mod scope_tests {
+    use super::*;
+    use jwt_simple::prelude::{Claims, Duration};
+
+    async fn create_test_app() -> DemoApp {
+        let now = time::OffsetDateTime::now_utc();
+        let database =
+            fts_sqlite::Db::open(&fts_sqlite::config::SqliteConfig::default(), now.into())
+                .await
+                .unwrap();
+        DemoApp {
+            db: database,
+            key: HS256Key::generate(),
+            schedule: crate::Schedule::new(Default::default()),
+        }
+    }
+
+    /// Sign a token for the bidder with the given custom claims
+    fn token(app: &DemoApp, bidder_id: BidderId, custom: CustomJWTClaims) -> Authorization<Bearer> {
+        let claims = Claims::with_custom_claims(custom, Duration::from_hours(1))
+            .with_subject(bidder_id.to_string());
+        Authorization::bearer(&app.key.authenticate(claims).unwrap()).unwrap()
+    }
+
+    fn scoped(scope: Option<&str>) -> CustomJWTClaims {
+        CustomJWTClaims {
+            admin: false,
+            scope: scope.map(str::to_owned),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_scope_is_trade() {
+        let app = create_test_app().await;
+        let bidder_id: BidderId = Uuid::from_u128(1).into();
+        let auth = token(&app, bidder_id, scoped(None));
+
+        assert_eq!(app.can_create_bid(&auth).await, Some(bidder_id));
+        assert!(app.can_update_bid(&auth, bidder_id).await);
+        assert!(app.can_read_bid(&auth, bidder_id).await);
+        assert!(app.can_read_settlement(&auth, bidder_id).await);
+        assert!(app.can_view_products(&auth).await);
+        assert!(!app.can_manage_products(&auth).await);
+        assert!(!app.can_run_batch(&auth).await);
+        assert!(!app.can_run_settlement(&auth).await);
+        assert!(!app.can_purge_bid(&auth).await);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_scope() {
+        let app = create_test_app().await;
+        let bidder_id: BidderId = Uuid::from_u128(1).into();
+        let other_id: BidderId = Uuid::from_u128(2).into();
+        let auth = token(&app, bidder_id, scoped(Some("read-only")));
+
+        assert_eq!(app.can_create_bid(&auth).await, None);
+        assert!(!app.can_update_bid(&auth, bidder_id).await);
+        assert!(app.can_read_bid(&auth, bidder_id).await);
+        assert!(!app.can_read_bid(&auth, other_id).await);
+        assert_eq!(app.can_query_bid(&auth).await, vec![bidder_id]);
+        assert!(app.can_read_settlement(&auth, bidder_id).await);
+        assert!(!app.can_read_settlement(&auth, other_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_operational_scopes() {
+        let app = create_test_app().await;
+        let bidder_id: BidderId = Uuid::from_u128(1).into();
+        let other_id: BidderId = Uuid::from_u128(2).into();
+        let auth = token(
+            &app,
+            bidder_id,
+            scoped(Some("batch:run settlement:read unknown:scope")),
+        );
+
+        assert!(app.can_run_batch(&auth).await);
+        assert!(app.can_preview_batch(&auth).await);
+        assert!(!app.can_manage_schedule(&auth).await);
+        assert!(!app.can_view_health(&auth).await);
+        assert!(app.can_read_settlement(&auth, other_id).await);
+        assert!(!app.can_run_settlement(&auth).await);
+        assert!(!app.can_manage_products(&auth).await);
+        assert_eq!(app.can_create_bid(&auth).await, None);
+        assert!(!app.can_read_bid(&auth, bidder_id).await);
+
+        let auth = token(
+            &app,
+            bidder_id,
+            scoped(Some("products:manage settlement:run")),
+        );
+        assert!(app.can_manage_products(&auth).await);
+        assert!(app.can_run_settlement(&auth).await);
+        assert!(app.can_read_settlement(&auth, other_id).await);
+        assert!(!app.can_run_batch(&auth).await);
+        assert!(!app.can_preview_batch(&auth).await);
+
+        let auth = token(&app, bidder_id, scoped(Some("batch:preview")));
+        assert!(app.can_preview_batch(&auth).await);
+        assert!(!app.can_run_batch(&auth).await);
+
+        let auth = token(&app, bidder_id, scoped(Some("batch:schedule")));
+        assert!(app.can_manage_schedule(&auth).await);
+        assert!(!app.can_run_batch(&auth).await);
+
+        let auth = token(&app, bidder_id, scoped(Some("read-only audit")));
+        assert!(app.can_audit(&auth).await);
+        assert!(app.can_read_bid(&auth, bidder_id).await);
+        assert!(!app.can_run_settlement(&auth).await);
+    }
+
+    #[tokio::test]
+    async fn test_admin_grants_every_scope() {
+        let app = create_test_app().await;
+        let bidder_id: BidderId = Uuid::from_u128(1).into();
+        let auth = token(
+            &app,
+            bidder_id,
+            CustomJWTClaims {
+                admin: true,
+                scope: Some("read-only".to_owned()),
+            },
+        );
+
+        assert_eq!(app.can_create_bid(&auth).await, Some(bidder_id));
+        assert!(app.can_manage_products(&auth).await);
+        assert!(app.can_run_batch(&auth).await);
+        assert!(app.can_manage_schedule(&auth).await);
+        assert!(app.can_view_health(&auth).await);
+        assert!(app.can_run_settlement(&auth).await);
+        assert!(app.can_purge_bid(&auth).await);
+        assert!(app.can_audit(&auth).await);
+    }
 }
@@ -0,0 +1,317 @@
+//! Loading an auction into a market.
+//!
+//! Exporting an auction writes the demands and portfolios active in a market
+//! as a document for `ftauction`. This module is its inverse, creating the
+//! products, demands and portfolios of such a document (or of any other
+//! auction, e.g. one generated by `ftauction generate`) in a market, so that
+//! a realistic market may be stood up from recorded data.
+
+use crate::impls::{DemandData, PortfolioData, ProductData, ProductKind, demand_id, portfolio_id};
+use fts_core::{
+    models::{Actor, Weights},
+    ports::{DemandRepository, PortfolioRepository, ProductRepository},
+};
+use fts_solver::io::Auction;
+use fts_sqlite::{
+    Db,
+    types::{BidderId, DemandId, PortfolioId, ProductId},
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use time::{Duration, OffsetDateTime};
+
+/// How to import an auction.
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The start of the first of the daily products standing in for the
+    /// products whose ids do not define them
+    pub from: OffsetDateTime,
+}
+
+/// A summary of an imported auction.
+#[derive(Debug, Serialize)]
+pub struct ImportRecord {
+    /// The number of products created, excluding those which already existed
+    pub products: usize,
+    /// The bidders, e.g. to sign tokens for
+    pub bidders: Vec<BidderId>,
+    /// The number of demands created
+    pub demands: usize,
+    /// The number of portfolios created
+    pub portfolios: usize,
+}
+
+impl Import {
+    /// Create the products, demands and portfolios of the auction in the
+    /// market of `db` as of `now`, all within a single transaction.
+    ///
+    /// An auction knows nothing of bidders, so the demands and portfolios are
+    /// divided among bidders as they are connected: a portfolio belongs to the
+    /// bidder of the demands it weights, and so those demands to one bidder.
+    /// Demands and portfolios keep their ids if they are uuids, and are
+    /// otherwise given new ones, named after their ids in the auction. A
+    /// product whose id is that of a product (as in an exported auction) is
+    /// created as that id defines it, unless it already exists, whereas any
+    /// other product stands in for a daily forward product, consecutively
+    /// from `from` in the order the portfolios trade them.
+    pub async fn run(
+        &self,
+        db: &Db,
+        auction: Auction,
+        now: OffsetDateTime,
+    ) -> anyhow::Result<ImportRecord> {
+        // The products, in the order the portfolios first trade them
+        let mut names = Vec::new();
+        let mut seen = HashSet::new();
+        for portfolio in auction.portfolios.values() {
+            for (product, _) in portfolio.basis().iter() {
+                let name = product.to_string();
+                if seen.insert(name.clone()) {
+                    names.push(name);
+                }
+            }
+        }
+
+        // Those defined by their ids first, so that no other takes their place
+        let mut products = HashMap::new();
+        for name in names.iter() {
+            if let Some(product) = name.parse().ok().and_then(ProductData::from_id) {
+                products.insert(name.as_str(), (product.id(), product));
+            }
+        }
+        let mut taken = products
+            .values()
+            .map(|(product_id, _)| *product_id)
+            .collect::<HashSet<ProductId>>();
+        let mut day = 0;
+        for name in names.iter() {
+            if products.contains_key(name.as_str()) {
+                continue;
+            }
+            let product = loop {
+                let from = self.from + Duration::days(day);
+                day += 1;
+                let product =
+                    ProductData::new(from, from + Duration::days(1), ProductKind::Forward);
+                if taken.insert(product.id()) {
+                    break product;
+                }
+            };
+            products.insert(name.as_str(), (product.id(), product));
+        }
+
+        // Every group of demands connected by portfolios belongs to a bidder
+        let demands = auction.demand_curves.keys().collect::<Vec<_>>();
+        let index = demands
+            .iter()
+            .enumerate()
+            .map(|(i, demand)| (demand.to_string(), i))
+            .collect::<HashMap<_, _>>();
+        let mut parents = (0..demands.len()).collect::<Vec<_>>();
+        for (portfolio, weights) in auction.portfolios.iter() {
+            let mut members = Vec::new();
+            for (demand, _) in weights.demand().iter() {
+                let Some(&i) = index.get(&demand.to_string()) else {
+                    anyhow::bail!(
+                        "portfolio {portfolio} weights demand {demand}, which the auction does not define"
+                    );
+                };
+                members.push(i);
+            }
+            for pair in members.windows(2) {
+                let (a, b) = (root(&mut parents, pair[0]), root(&mut parents, pair[1]));
+                parents[b] = a;
+            }
+        }
+        let mut bidders = Vec::new();
+        let mut roots = HashMap::new();
+        let mut new_bidder = || {
+            let bidder_id = BidderId(uuid::Builder::from_random_bytes(rand::random()).into_uuid());
+            bidders.push(bidder_id);
+            bidder_id
+        };
+        let demand_bidders = (0..demands.len())
+            .map(|i| {
+                *roots
+                    .entry(root(&mut parents, i))
+                    .or_insert_with(&mut new_bidder)
+            })
+            .collect::<Vec<_>>();
+
+        let mut demand_ids = HashMap::new();
+        let mut new_demands = Vec::with_capacity(demands.len());
+        for (i, (demand, curve)) in auction.demand_curves.iter().enumerate() {
+            let name = demand.to_string();
+            let demand_id = name
+                .parse::<DemandId>()
+                .unwrap_or_else(|_| demand_id(now, rand::random()));
+            demand_ids.insert(name.clone(), demand_id);
+            new_demands.push((demand_id, demand_bidders[i], name, curve.clone()));
+        }
+
+        let mut new_portfolios = Vec::with_capacity(auction.portfolios.len());
+        for (portfolio, weights) in auction.portfolios.iter() {
+            let name = portfolio.to_string();
+            let portfolio_id = name
+                .parse::<PortfolioId>()
+                .unwrap_or_else(|_| portfolio_id(now, rand::random()));
+            let demand = weights
+                .demand()
+                .iter()
+                .map(|(demand, &weight)| (demand_ids[&demand.to_string()], weight))
+                .collect::<Weights<_>>();
+            let basis = weights
+                .basis()
+                .iter()
+                .map(|(product, &weight)| (products[product.to_string().as_str()].0, weight))
+                .collect();
+            let bidder_id = match weights.demand().iter().next() {
+                Some((demand, _)) => demand_bidders[index[&demand.to_string()]],
+                None => new_bidder(),
+            };
+            new_portfolios.push((portfolio_id, bidder_id, name, demand, basis));
+        }
+
+        let products = names
+            .iter()
+            .filter_map(|name| products.remove(name.as_str()))
+            .collect::<Vec<_>>();
+        let record = ImportRecord {
+            products: 0,
+            bidders,
+            demands: new_demands.len(),
+            portfolios: new_portfolios.len(),
+        };
+
+        let as_of = now.into();
+        let created = db
+            .transaction(move |db| {
+                Box::pin(async move {
+                    let mut missing = Vec::new();
+                    for (product_id, product) in products {
+                        let existing =
+                            ProductRepository::<ProductData>::get_product(db, product_id, as_of)
+                                .await?;
+                        if existing.is_none() {
+                            missing.push((product_id, product));
+                        }
+                    }
+                    let created = missing.len();
+                    if created > 0 {
+                        db.create_products(missing, as_of).await?;
+                    }
+
+                    for (demand_id, bidder_id, name, curve) in new_demands {
+                        db.create_demand(
+                            demand_id,
+                            bidder_id,
+                            DemandData::new(name),
+                            curve,
+                            Actor::Operator,
+                            as_of,
+                        )
+                        .await?;
+                    }
+                    for (portfolio_id, bidder_id, name, demand, basis) in new_portfolios {
+                        db.create_portfolio(
+                            portfolio_id,
+                            bidder_id,
+                            PortfolioData::new(name),
+                            demand,
+                            basis,
+                            Actor::Operator,
+                            as_of,
+                        )
+                        .await?;
+                    }
+
+                    Ok::<_, anyhow::Error>(created)
+                })
+            })
+            .await?;
+
+        Ok(ImportRecord {
+            products: created,
+            ..record
+        })
+    }
+}
+
+// The representative of the group of `i`, halving the path to it as it goes
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fts_sqlite::config::SqliteConfig;
+
+    #[tokio::test]
+    async fn imports_are_exported_alike() {
+        let now = OffsetDateTime::now_utc();
+        let auction: Auction = serde_json::from_value(serde_json::json!({
+            "demand_curves": {
+                "buyer": [{ "rate": 0.0, "price": 10.0 }, { "rate": 5.0, "price": 8.0 }],
+                "seller": [{ "rate": -5.0, "price": 9.0 }, { "rate": 0.0, "price": 7.0 }],
+            },
+            "portfolios": {
+                "buy": { "demand": "buyer", "basis": "day-1" },
+                "sell": { "demand": "seller", "basis": "day-1" },
+                "roll": { "demand": "seller", "basis": { "day-1": 1.0, "day-2": -1.0 } },
+            },
+        }))
+        .unwrap();
+        let import = Import {
+            from: now.replace_time(time::Time::MIDNIGHT) + Duration::days(1),
+        };
+
+        let db = Db::open(&SqliteConfig::default(), now.into())
+            .await
+            .unwrap();
+        let record = import.run(&db, auction, now).await.unwrap();
+        assert_eq!(record.products, 2);
+        // The seller's portfolios share its demand, so the seller is one bidder
+        assert_eq!(record.bidders.len(), 2);
+        assert_eq!(record.demands, 2);
+        assert_eq!(record.portfolios, 3);
+
+        // The exported auction, whose ids are those of the market, imports
+        // into another database as it stood in the first, products included
+        let exported = serde_json::to_value(db.gather_auction(now.into()).await.unwrap()).unwrap();
+        let other = Db::open(&SqliteConfig::default(), now.into())
+            .await
+            .unwrap();
+        let record = import
+            .run(
+                &other,
+                serde_json::from_value(exported.clone()).unwrap(),
+                now,
+            )
+            .await
+            .unwrap();
+        assert_eq!(record.products, 2);
+        let again = serde_json::to_value(other.gather_auction(now.into()).await.unwrap()).unwrap();
+        assert_eq!(again, exported);
+
+        // A product which already exists is left as it is
+        let basis = exported["portfolios"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|portfolio| portfolio["basis"].clone())
+            .next()
+            .unwrap();
+        let auction = serde_json::from_value(serde_json::json!({
+            "demand_curves": {},
+            "portfolios": { "idle": { "demand": {}, "basis": basis } },
+        }))
+        .unwrap();
+        let record = import.run(&other, auction, now).await.unwrap();
+        assert_eq!(record.products, 0);
+    }
+}
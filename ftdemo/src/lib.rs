@@ -3,11 +3,58 @@
 
 pub mod impls;
 
+mod cron;
+pub use cron::Cron;
+
 mod schedule;
-pub use schedule::Scheduler;
+pub use schedule::{BatchConfig, BatchSolver, BatchState, CronSchedule, Schedule, Scheduler};
+
+mod retention;
+pub use retention::Retention;
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+
+mod outbox;
+pub use outbox::{Outbox, Publisher, relay};
+
+mod metrics;
+pub use metrics::{Metrics, Recorder};
+
+mod seed;
+pub use seed::{Seed, SeedRecord};
+
+mod replay;
+pub use replay::{
+    OutcomeDifference, Replay, ReplayInputs, ReplayOutcome, ReplayRecord, ReplaySolver,
+};
+
+mod token;
+pub use token::Token;
+
+mod import;
+pub use import::{Import, ImportRecord};
+
+mod simulate;
+pub use simulate::{Simulate, SimulateRecord};
+
+mod migrate;
+pub use migrate::Migrate;
+
+mod backup;
+pub use backup::{Backup, BackupRecord, Restore};
+
+mod doctor;
+pub use doctor::{Doctor, DoctorReport, Finding, Status};
+
+mod prune;
+pub use prune::{Prune, PruneReport};
 
 mod cli;
 pub use cli::{Cli, Commands};
 
 mod config;
-pub use config::AppConfig;
+pub use config::{AppConfig, Market};
+
+mod reload;
+pub use reload::Reloader;
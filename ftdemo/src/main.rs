@@ -1,11 +1,20 @@
-use ftdemo::{AppConfig, Cli, Commands, impls::DemoApp};
-use fts_axum::{schema, start_server};
-use fts_core::ports::BatchRepository as _;
-use fts_solver::clarabel::ClarabelSolver;
-use fts_sqlite::Db;
+use axum::Router;
+use ftdemo::{
+    AppConfig, Backup, BatchConfig, BatchState, Cli, Commands, Doctor, Import, Migrate, Outbox,
+    Prune, Recorder, Reloader, Replay, Restore, Retention, Seed, Simulate, Token, impls::DemoApp,
+    relay,
+};
+use fts_axum::{reloadable_router, schema, serve};
+use fts_core::{
+    models::{BatchError, SettlementConfig},
+    ports::{HealthRepository as _, RetentionRepository as _, SettlementRepository as _},
+};
+use fts_sqlite::{Db, checkpoint::CheckpointRecord, types::BidderId};
 use jwt_simple::prelude::HS256Key;
+use std::io::Write as _;
 use time::OffsetDateTime;
-use tokio::select;
+use tokio::task::JoinSet;
+use tracing::{Level, event};
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
 #[tokio::main]
@@ -27,50 +36,530 @@ async fn main() -> anyhow::Result<()> {
             let schema = schema::<DemoApp>();
             serde_json::to_writer_pretty(output.write()?, &schema)?;
         }
+        Commands::Export {
+            config,
+            output,
+            from,
+            until,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let until = until.unwrap_or(now);
+            let record = db
+                .export_parquet(&output, from.map(Into::into), until.into())
+                .await?;
+            event!(
+                Level::INFO,
+                product_outcomes = record.product_outcomes,
+                portfolio_outcomes = record.portfolio_outcomes,
+                trades = record.trades,
+                settlement_positions = record.settlement_positions,
+                settlement_payments = record.settlement_payments,
+                files = record.files.len(),
+            );
+        }
+        Commands::ExportAuction {
+            config,
+            output,
+            as_of,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let auction = db.gather_auction(as_of.unwrap_or(now).into()).await?;
+            let mut output = output.write()?;
+            serde_json::to_writer_pretty(&mut output, &auction)?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+        Commands::ImportAuction {
+            config,
+            input,
+            from,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let auction = serde_json::from_reader(input.read()?)?;
+            let from = from.unwrap_or_else(|| {
+                now.replace_time(time::Time::MIDNIGHT) + time::Duration::days(1)
+            });
+            let record = Import { from }.run(&db, auction, now).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+        }
+        Commands::Migrate {
+            config,
+            status,
+            dry_run,
+            backup,
+            no_backup,
+            market,
+        } => {
+            let config = AppConfig::load(config)?;
+            let database = config.database(market.as_deref());
+            let status = if status {
+                Db::connect(database).await?.migration_status().await?
+            } else {
+                let migrate = Migrate {
+                    dry_run,
+                    backup,
+                    no_backup,
+                };
+                migrate.run(database).await?
+            };
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &status)?;
+            println!();
+        }
+        Commands::Backup {
+            config,
+            output,
+            market,
+        } => {
+            let config = AppConfig::load(config)?;
+            let database = config.database(market.as_deref());
+            let record = Backup { output }.run(database).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+        }
+        Commands::Restore {
+            config,
+            input,
+            force,
+            market,
+        } => {
+            let config = AppConfig::load(config)?;
+            let database = config.database(market.as_deref());
+            let record = Restore { input, force }.run(database).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+        }
+        Commands::Check {
+            config,
+            repair,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let record = db.check_consistency(repair).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+            if !(record.is_consistent() || record.repaired) {
+                anyhow::bail!("the database is inconsistent");
+            }
+        }
+        Commands::Doctor { config, secret } => {
+            let report = Doctor { config, secret }
+                .run(OffsetDateTime::now_utc())
+                .await;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+            println!();
+            if !report.healthy {
+                anyhow::bail!(
+                    "the doctor found {} error(s)",
+                    report.count(ftdemo::Status::Error)
+                );
+            }
+        }
+        Commands::Replay {
+            config,
+            from,
+            until,
+            solver,
+            tolerance,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let replay = Replay {
+                from,
+                until,
+                solver,
+                tolerance,
+            };
+            let records = replay.run(&db).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &records)?;
+            println!();
+
+            let divergent = records
+                .iter()
+                .filter(|record| record.is_divergent())
+                .count();
+            if divergent > 0 {
+                anyhow::bail!("{divergent} of {} replayed batches diverge", records.len());
+            }
+        }
+        Commands::Seed {
+            config,
+            seed,
+            bidders,
+            from,
+            days,
+            batches,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            let from = from.unwrap_or_else(|| {
+                now.replace_time(time::Time::MIDNIGHT) + time::Duration::days(1)
+            });
+            let seed = Seed {
+                seed,
+                bidders,
+                from,
+                days,
+                batches,
+            };
+            let record = seed.run(&db, now).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+        }
+        Commands::Prune {
+            config,
+            horizon,
+            apply,
+            market,
+        } => {
+            let config = AppConfig::load(config)?;
+            let Some(horizon) = horizon.or(config.retention.horizon) else {
+                anyhow::bail!("no --horizon given, and no retention horizon is configured");
+            };
+            let now = OffsetDateTime::now_utc();
+            let db = config.open(market.as_deref(), now).await?;
+
+            let prune = Prune { horizon, apply };
+            let report = prune
+                .run(&db, config.database(market.as_deref()), now)
+                .await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+            println!();
+        }
+        Commands::Settle {
+            config,
+            as_of,
+            time_unit,
+            position_decimals,
+            payment_decimals,
+            market,
+        } => {
+            let now = OffsetDateTime::now_utc();
+            let db = AppConfig::load(config)?
+                .open(market.as_deref(), now)
+                .await?;
+
+            // As through the API, activity which is yet to occur cannot be
+            // settled
+            let as_of = as_of.unwrap_or(now);
+            if as_of > now {
+                anyhow::bail!("cannot settle future activity");
+            }
+            let settlement = SettlementConfig {
+                as_of: as_of.into(),
+                time_unit,
+                position_decimals,
+                payment_decimals,
+            };
+            match db.settle_activity(settlement).await? {
+                Ok(record) => {
+                    serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+                    println!();
+                }
+                Err(conflict) => anyhow::bail!("unable to settle: {conflict}"),
+            }
+        }
+        Commands::Simulate {
+            url,
+            secret,
+            bidders,
+            rate,
+            duration,
+            seed,
+            days,
+        } => {
+            let key = HS256Key::from_bytes(secret.as_bytes());
+            let simulate = Simulate {
+                url,
+                bidders,
+                rate,
+                duration,
+                seed,
+                days,
+            };
+            // Interrupting the simulation still prints its summary
+            let interrupted = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            let record = simulate.run(&key, interrupted).await?;
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &record)?;
+            println!();
+        }
+        Commands::Token {
+            secret,
+            subject,
+            admin,
+            scope,
+            expires,
+        } => {
+            let key = HS256Key::from_bytes(secret.as_bytes());
+
+            // A bidder's token is only useful with a bidder, so one is made up
+            // (and reported aside from the token) if none is given
+            let new_bidder = (subject.is_none() && !admin)
+                .then(|| BidderId(uuid::Builder::from_random_bytes(rand::random()).into_uuid()));
+            let token = Token {
+                subject: subject.map(Into::into).or(new_bidder),
+                admin,
+                scope,
+                valid_for: expires,
+            };
+            let signed = token.sign(&key)?;
+            if let Some(bidder_id) = new_bidder {
+                eprintln!("Minted a token for the new bidder {bidder_id}");
+            }
+            println!("{signed}");
+        }
         Commands::Serve { config, secret } => {
             let key = HS256Key::from_bytes(secret.as_bytes());
 
-            // Create config with proper layering of CLI args
+            // Create config with proper layering of CLI args, which is
+            // reloaded on SIGHUP or whenever the file changes
+            let mut reloader = Reloader::new(config.clone(), AppConfig::load(config)?);
             let AppConfig {
                 server,
                 database,
-                schedule,
-            } = AppConfig::load(config)?;
-
-            // Open database with config
-            let db = Db::open(&database, OffsetDateTime::now_utc().into()).await?;
-            let db2 = db.clone();
-            let app = DemoApp { db, key };
-
-            // We always run the server task.
-            let server_task = tokio::spawn(async move { start_server(server, app).await });
-
-            // However, we may or may not also run a scheduled batch task
-            if schedule.every.is_some() {
-                let solver_task = tokio::spawn(async move {
-                    let f = async move |now: OffsetDateTime| {
-                        let batch = db2
-                            .run_batch(now.into(), ClarabelSolver::default(), ())
-                            .await;
-                        match batch {
-                            Ok(Ok(expires)) => Ok(expires),
-                            Ok(Err(e)) => Err(anyhow::Error::new(e)),
-                            Err(e) => Err(anyhow::Error::new(e)),
-                        }
-                    };
-                    schedule.schedule(f).await
-                });
+                retention,
+                checkpoint,
+                outbox,
+                metrics,
+                markets,
+                ..
+            } = reloader.config().clone();
+
+            // Open database with config, recording every explicit checkpoint
+            let now = OffsetDateTime::now_utc();
+            let db = Db::open(&database, now.into())
+                .await?
+                .with_checkpoint_hook(log_checkpoint);
 
-                select! {
-                    r = server_task => r??,
-                    r = solver_task => r??,
+            let mut tasks = JoinSet::new();
+            let recorder = Recorder::default();
+
+            // The market of the database configuration is served at the root,
+            // and every further market under its own prefix. Each market has
+            // its own schedule of batch auctions, prunes its own history and
+            // relays its own events.
+            let mut service = host_market(
+                &mut tasks,
+                db.clone(),
+                None,
+                &key,
+                &mut reloader,
+                &retention,
+                &outbox,
+                &recorder,
+            )?;
+            let mut databases = vec![db.clone()];
+            for market in markets {
+                let host = match &market.database {
+                    Some(database) => {
+                        let own = Db::open(database, now.into())
+                            .await?
+                            .with_checkpoint_hook(log_checkpoint);
+                        databases.push(own.clone());
+                        own
+                    }
+                    None => db.clone(),
+                };
+                let host = host.market(market.id.as_str(), now.into()).await?;
+                let router = host_market(
+                    &mut tasks,
+                    host,
+                    Some(&market.id),
+                    &key,
+                    &mut reloader,
+                    &retention,
+                    &outbox,
+                    &recorder,
+                )?;
+                service = service.nest(&format!("/markets/{}", market.id), router);
+            }
+
+            // Metrics are served alongside the API unless given an address
+            // of their own, e.g. one which is not publicly reachable
+            if metrics.enabled {
+                match metrics.bind_address {
+                    Some(bind_address) => {
+                        let router = recorder.router();
+                        tasks.spawn(async move { Ok(serve(bind_address, router).await?) });
+                    }
+                    None => service = service.merge(recorder.router()),
                 }
-            } else {
-                // Otherwise, we just run the server task to completion
-                server_task.await??;
+            }
+
+            // Each database is shared by the markets it hosts, so is
+            // maintained and its WAL checkpointed once
+            for db in databases {
+                let db2 = db.clone();
+                tasks.spawn(async move { Ok(db2.run_maintenance().await?) });
+
+                let checkpoint = checkpoint.clone();
+                tasks.spawn(async move {
+                    let f = async move |mode| db.checkpoint(mode).await;
+                    Ok(checkpoint.run(f).await?)
+                });
+            }
+
+            tasks.spawn(reloader.run());
+            tasks.spawn(async move { Ok(serve(server.bind_address, service).await?) });
+
+            // Every task runs until the process is stopped, so the first to
+            // finish can only have failed
+            if let Some(result) = tasks.join_next().await {
+                result??;
             }
         }
     }
 
     Ok(())
 }
+
+/// Spawn the scheduled batch auctions, history retention and event relay of a
+/// market, returning the router which serves it.
+#[allow(clippy::too_many_arguments)]
+fn host_market(
+    tasks: &mut JoinSet<anyhow::Result<()>>,
+    db: Db,
+    market: Option<&str>,
+    key: &HS256Key,
+    reloader: &mut Reloader,
+    retention: &Retention,
+    outbox: &Outbox,
+    recorder: &Recorder,
+) -> anyhow::Result<Router> {
+    // The schedule may be changed at runtime through the API (or by reloading
+    // the configuration), so the scheduled batch task runs even if no
+    // interval is configured
+    let schedule = reloader.schedule(market);
+    let app = DemoApp {
+        db: db.clone(),
+        key: key.clone(),
+        schedule: schedule.clone(),
+    };
+    let service = reloadable_router(app, reloader.server());
+
+    let market_id = db.market_id.clone();
+    let db2 = db.clone();
+    let recorder = recorder.clone();
+    let market_id2 = market_id.clone();
+    tasks.spawn(async move {
+        // The outcomes of each scheduled batch warm-start the next
+        let state = BatchState::default();
+        let f = async move |now: OffsetDateTime, config: BatchConfig| {
+            let start = OffsetDateTime::now_utc();
+            let batch = config.run(&db2, now, &state).await;
+            match batch {
+                Ok(Ok(expires)) => {
+                    let lag = (start - now).try_into().unwrap_or_default();
+                    let duration = (OffsetDateTime::now_utc() - start)
+                        .try_into()
+                        .unwrap_or_default();
+                    recorder.record_batch(&market_id2, lag, duration);
+                    Ok(expires)
+                }
+                Ok(Err(BatchError::InProgress)) => {
+                    // The auction already underway accounts for this one
+                    event!(Level::WARN, "skipping scheduled auction: batch in progress");
+                    Ok(None)
+                }
+                Ok(Err(BatchError::Solver(e))) => Err(anyhow::Error::new(e)),
+                Err(e) => Err(anyhow::Error::new(e)),
+            }
+        };
+        schedule.run(f).await
+    });
+
+    let notifier = outbox.notifier(&market_id)?;
+    let outbox = outbox.clone();
+    let db2 = db.clone();
+    let market_id2 = market_id.clone();
+    tasks.spawn(async move {
+        let f = async move |limit: usize| {
+            let published = relay(&db2, &notifier, limit).await?;
+            if published > 0 {
+                event!(Level::DEBUG, market_id = market_id2, published);
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        outbox.run(f).await;
+        Ok(())
+    });
+
+    let compaction = retention.clone();
+    let db2 = db.clone();
+    let market_id2 = market_id.clone();
+    tasks.spawn(async move {
+        let f = async move || {
+            let record = db2.compact_history().await?;
+            event!(
+                Level::INFO,
+                market_id = market_id2,
+                demand_groups = record.demand_groups,
+                product_groups = record.product_groups,
+            );
+            Ok::<_, anyhow::Error>(())
+        };
+        compaction.compact(f).await
+    });
+
+    let retention = retention.clone();
+    tasks.spawn(async move {
+        let f = async move |before: OffsetDateTime| {
+            let record = db.prune_history(before.into()).await?;
+            event!(
+                Level::INFO,
+                market_id,
+                curves = record.curves,
+                demand_groups = record.demand_groups,
+                product_groups = record.product_groups,
+                portfolio_outcomes = record.portfolio_outcomes,
+                product_outcomes = record.product_outcomes,
+                batch_inputs = record.batch_inputs,
+                archived = record.archived,
+            );
+            Ok::<_, anyhow::Error>(())
+        };
+        retention.run(f).await
+    });
+
+    Ok(service)
+}
+
+/// Log every explicit checkpoint of a database.
+fn log_checkpoint(record: &CheckpointRecord) {
+    event!(
+        Level::INFO,
+        mode = ?record.mode,
+        busy = record.busy,
+        wal_frames = record.wal_frames,
+        checkpointed_frames = record.checkpointed_frames,
+    );
+}
@@ -0,0 +1,134 @@
+//! Exposing metrics of the server to be scraped.
+//!
+//! The process records the batch auctions it runs on schedule: how many have
+//! run, how long the last took, and how late it started relative to its
+//! scheduled time. When enabled, these are served in the OpenMetrics text
+//! format at `/metrics`, either alongside the API or, so that scraping may be
+//! kept apart from the public API, on a listener of its own.
+
+use axum::{Router, http::header, response::IntoResponse, routing::get};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, text::encode},
+    metrics::{counter::Counter, family::Family, gauge::Gauge},
+    registry::{Registry, Unit},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, atomic::AtomicU64},
+    time::Duration,
+};
+
+/// The media type of the OpenMetrics text format
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Configuration for exposing metrics.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    /// Whether to serve the metrics
+    #[serde(default)]
+    pub enabled: bool,
+    /// The address to serve the metrics on (if omitted, they are served by
+    /// the API server, alongside the API)
+    #[serde(default)]
+    pub bind_address: Option<SocketAddr>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct MarketLabels {
+    market: String,
+}
+
+/// The metrics recorded by the process, shared by every market it hosts.
+#[derive(Clone)]
+pub struct Recorder {
+    registry: Arc<Registry>,
+    batches: Family<MarketLabels, Counter>,
+    last_batch_duration: Family<MarketLabels, Gauge<f64, AtomicU64>>,
+    scheduler_lag: Family<MarketLabels, Gauge<f64, AtomicU64>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        let mut registry = Registry::with_prefix("ftdemo");
+        let batches = Family::<MarketLabels, Counter>::default();
+        registry.register("batches", "Batch auctions run on schedule", batches.clone());
+        let last_batch_duration = Family::<MarketLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register_with_unit(
+            "last_batch_duration",
+            "Time taken by the last scheduled batch auction",
+            Unit::Seconds,
+            last_batch_duration.clone(),
+        );
+        let scheduler_lag = Family::<MarketLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register_with_unit(
+            "scheduler_lag",
+            "Delay between the scheduled time of the last batch auction and its start",
+            Unit::Seconds,
+            scheduler_lag.clone(),
+        );
+
+        Self {
+            registry: Arc::new(registry),
+            batches,
+            last_batch_duration,
+            scheduler_lag,
+        }
+    }
+}
+
+impl Recorder {
+    /// Record a scheduled batch auction of the market, which started `lag`
+    /// after its scheduled time and took `duration`.
+    pub fn record_batch(&self, market_id: &str, lag: Duration, duration: Duration) {
+        let labels = MarketLabels {
+            market: market_id.to_owned(),
+        };
+        self.batches.get_or_create(&labels).inc();
+        self.last_batch_duration
+            .get_or_create(&labels)
+            .set(duration.as_secs_f64());
+        self.scheduler_lag
+            .get_or_create(&labels)
+            .set(lag.as_secs_f64());
+    }
+
+    /// The metrics, in the OpenMetrics text format.
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+        // Writing to a string cannot fail
+        let _ = encode(&mut text, &self.registry);
+        text
+    }
+
+    /// A router serving the metrics at `/metrics`.
+    pub fn router(&self) -> Router {
+        let recorder = self.clone();
+        Router::new().route(
+            "/metrics",
+            get(async move || {
+                ([(header::CONTENT_TYPE, CONTENT_TYPE)], recorder.encode()).into_response()
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_are_recorded_per_market() {
+        let recorder = Recorder::default();
+        recorder.record_batch("", Duration::from_millis(5), Duration::from_millis(250));
+        recorder.record_batch("", Duration::from_millis(10), Duration::from_millis(500));
+        recorder.record_batch("east", Duration::ZERO, Duration::from_secs(1));
+
+        let text = recorder.encode();
+        assert!(text.contains("ftdemo_batches_total{market=\"\"} 2"));
+        assert!(text.contains("ftdemo_batches_total{market=\"east\"} 1"));
+        assert!(text.contains("ftdemo_last_batch_duration_seconds{market=\"\"} 0.5"));
+        assert!(text.contains("ftdemo_scheduler_lag_seconds{market=\"\"} 0.01"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Applying schema migrations as a step of their own.
+//!
+//! Unless `auto_migrate` is disabled, the server migrates the database as it
+//! starts, which may come as a surprise in a point release. Migrating the
+//! database explicitly instead allows an operator to first see what would
+//! change, to rehearse the migrations on a copy of the database, and to keep a
+//! backup of the database as it was.
+
+use crate::backup::remove_with_log;
+use fts_core::models::MigrationStatus;
+use fts_sqlite::{Db, config::SqliteConfig};
+use std::path::{Path, PathBuf};
+use tracing::{Level, event};
+
+/// How to migrate a database.
+#[derive(Debug, Clone, Default)]
+pub struct Migrate {
+    /// Apply the migrations to a temporary copy of the database, leaving the
+    /// database itself untouched
+    pub dry_run: bool,
+    /// Where to back the database up before migrating it (if omitted, next to
+    /// the database, named by its schema version)
+    pub backup: Option<PathBuf>,
+    /// Whether to migrate the database without backing it up
+    pub no_backup: bool,
+}
+
+impl Migrate {
+    /// Apply the pending migrations (if any) to the database, returning the
+    /// resulting status of its migrations.
+    pub async fn run(&self, database: &SqliteConfig) -> anyhow::Result<MigrationStatus> {
+        let db = Db::connect(database).await?;
+        let status = db.migration_status().await?;
+        if status.pending == 0 {
+            return Ok(status);
+        }
+
+        if self.dry_run {
+            let copy = std::env::temp_dir().join(format!(
+                "ftdemo-migrate-{}.db",
+                uuid::Builder::from_random_bytes(rand::random()).into_uuid()
+            ));
+            db.backup(&copy).await?;
+            let rehearsal = rehearse(database, &copy).await;
+            let _ = remove_with_log(&copy);
+            return rehearsal;
+        }
+
+        let backup = match &database.database_path {
+            Some(path) if !self.no_backup => Some(
+                self.backup
+                    .clone()
+                    .unwrap_or_else(|| backup_path(path, status.version.unwrap_or_default())),
+            ),
+            // An in-memory database does not outlive the process anyway
+            _ => None,
+        };
+
+        if let Some(backup) = backup {
+            db.backup(&backup).await?;
+            event!(Level::INFO, backup = %backup.display(), "backed up the database");
+        }
+        let status = db.migrate().await?;
+        event!(
+            Level::INFO,
+            version = status.version,
+            "migrated the database"
+        );
+        Ok(status)
+    }
+}
+
+// Migrate the copy of the database, as the database itself would be
+async fn rehearse(database: &SqliteConfig, copy: &Path) -> anyhow::Result<MigrationStatus> {
+    let config = SqliteConfig {
+        database_path: Some(copy.to_owned()),
+        create_if_missing: false,
+        replica_paths: Vec::new(),
+        ..database.clone()
+    };
+    let db = Db::connect(&config).await?;
+    let status = db.migrate().await;
+    db.reader.close().await;
+    db.writer.close().await;
+    Ok(status?)
+}
+
+/// The default backup of the database at `path`, e.g. `market.db.v12.bak`
+fn backup_path(path: &Path, version: i64) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn dry_runs_leave_the_database_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = SqliteConfig {
+            database_path: Some(dir.path().join("market.db")),
+            ..Default::default()
+        };
+
+        let rehearsed = Migrate {
+            dry_run: true,
+            ..Default::default()
+        }
+        .run(&database)
+        .await
+        .unwrap();
+        assert_eq!(rehearsed.pending, 0);
+        let db = Db::connect(&database).await.unwrap();
+        assert_eq!(db.migration_status().await.unwrap().applied, 0);
+        assert!(!dir.path().join("market.db.v0.bak").exists());
+
+        // Migrating for real backs the database up first
+        let migrated = Migrate::default().run(&database).await.unwrap();
+        assert_eq!(migrated.applied, rehearsed.applied);
+        assert!(dir.path().join("market.db.v0.bak").exists());
+
+        // after which there is nothing left to do
+        let again = Migrate::default().run(&database).await.unwrap();
+        assert_eq!(again.version, migrated.version);
+    }
+}
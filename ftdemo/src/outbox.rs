@@ -0,0 +1,173 @@
+//! Reliable delivery of market events.
+//!
+//! Every change to a market records an event in the database's outbox, in the
+//! same transaction as the change itself. This module provides a maintenance
+//! task which relays the pending events of a market to a notifier, and marks
+//! them delivered once published. Events are published to a webhook, or if
+//! none is configured, to the log.
+
+use fts_core::{
+    models::OutboxEvent,
+    ports::{Notifier, OutboxRepository},
+};
+use fts_sqlite::Db;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{Instrument as _, Level, event, span};
+
+/// Configuration for relaying the events of the outbox.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Outbox {
+    /// URL to which pending events are POSTed (if omitted, events are logged instead)
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// How long to wait for the webhook to accept the events
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// How often to relay the pending events
+    #[serde(default = "default_every", with = "humantime_serde")]
+    pub every: Duration,
+    /// The most events to publish at once
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_every() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            timeout: default_timeout(),
+            every: default_every(),
+            limit: default_limit(),
+        }
+    }
+}
+
+impl Outbox {
+    /// Create the notifier to which the events of a market are published.
+    pub fn notifier(&self, market_id: &str) -> anyhow::Result<Publisher> {
+        Ok(match self.webhook.as_ref() {
+            Some(url) => Publisher::Webhook {
+                client: reqwest::Client::builder().timeout(self.timeout).build()?,
+                url: url.clone(),
+                market_id: market_id.to_owned(),
+            },
+            None => Publisher::Log {
+                market_id: market_id.to_owned(),
+            },
+        })
+    }
+
+    /// Execute a function every `every`, passing the most events to publish at once.
+    ///
+    /// The events remain pending until they are published, so a failure
+    /// (e.g. of the webhook) is logged and retried on the next tick rather
+    /// than returned. This never returns.
+    pub async fn run<T, E: std::fmt::Display>(&self, f: impl AsyncFn(usize) -> Result<T, E>) {
+        let mut interval = tokio::time::interval(self.every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let span = span!(Level::DEBUG, "relaying events");
+            if let Err(error) = f(self.limit).instrument(span).await {
+                event!(Level::WARN, %error, "failed to relay events");
+            }
+        }
+    }
+}
+
+/// Publish the pending events of the repository, `limit` at a time, until none
+/// remain, marking each page delivered once published.
+///
+/// # Returns
+///
+/// The number of events published.
+pub async fn relay<R, N>(db: &R, notifier: &N, limit: usize) -> anyhow::Result<u64>
+where
+    R: OutboxRepository<DateTime = fts_sqlite::types::DateTime>,
+    R::Error: Send + Sync + 'static,
+    N: Notifier<R>,
+    N::Error: Send + Sync + 'static,
+{
+    let mut published = 0;
+    loop {
+        let events = db.pending_events(limit).await?;
+        let Some(last) = events.last() else {
+            return Ok(published);
+        };
+
+        notifier.publish(&events).await?;
+        db.mark_delivered(last.sequence, OffsetDateTime::now_utc().into())
+            .await?;
+        published += events.len() as u64;
+    }
+}
+
+/// Publishes the events of a market to a webhook or the log.
+pub enum Publisher {
+    /// POST the events to a URL, as a JSON object of the market and its events
+    Webhook {
+        /// The client with which to POST
+        client: reqwest::Client,
+        /// The URL of the webhook
+        url: String,
+        /// The market whose events are published
+        market_id: String,
+    },
+    /// Log the events
+    Log {
+        /// The market whose events are published
+        market_id: String,
+    },
+}
+
+impl Notifier<Db> for Publisher {
+    type Error = reqwest::Error;
+
+    async fn publish(&self, events: &[OutboxEvent<Db>]) -> Result<(), Self::Error> {
+        match self {
+            Self::Webhook {
+                client,
+                url,
+                market_id,
+            } => {
+                client
+                    .post(url)
+                    .json(&serde_json::json!({
+                        "market_id": market_id,
+                        "events": events,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Self::Log { market_id } => {
+                for outbox_event in events {
+                    event!(
+                        Level::DEBUG,
+                        market_id,
+                        sequence = outbox_event.sequence,
+                        as_of = %outbox_event.as_of,
+                        event = %serde_json::to_string(&outbox_event.event).unwrap_or_default(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Pruning the history of a market on demand.
+//!
+//! The server prunes history on its own once a retention horizon is
+//! configured, which is a hard step to take on faith with a production
+//! database. Pruning from the command line instead reports what the
+//! retention policy would remove, by default by pruning a temporary copy of
+//! the database, so that it may first be tried by hand (e.g. in a maintenance
+//! window) before it is left to the server.
+
+use crate::backup::remove_with_log;
+use fts_core::{models::PruneRecord, ports::RetentionRepository as _};
+use fts_sqlite::{Db, config::SqliteConfig, types::DateTime};
+use serde::Serialize;
+use std::{path::Path, time::Duration};
+use time::OffsetDateTime;
+
+/// How to prune the history of a market.
+#[derive(Debug, Clone)]
+pub struct Prune {
+    /// How long to retain superseded history
+    pub horizon: Duration,
+    /// Whether to prune the database itself, rather than a temporary copy
+    pub apply: bool,
+}
+
+/// What was (or, in a dry run, would be) pruned.
+#[derive(Debug, Serialize)]
+pub struct PruneReport {
+    /// Whether a temporary copy of the database was pruned, leaving the
+    /// database itself untouched
+    pub dry_run: bool,
+    /// The rows pruned from each table
+    #[serde(flatten)]
+    pub record: PruneRecord<DateTime>,
+    /// The number of rows pruned, across every table
+    pub rows: u64,
+    /// The number of bytes of the database no longer holding data, which are
+    /// reused by later writes, or returned to the filesystem by a vacuum
+    pub bytes: u64,
+}
+
+impl Prune {
+    /// Prune the history of the market of `db`, hosted by the database
+    /// configured by `database`, which was superseded before `now` less the
+    /// horizon.
+    pub async fn run(
+        &self,
+        db: &Db,
+        database: &SqliteConfig,
+        now: OffsetDateTime,
+    ) -> anyhow::Result<PruneReport> {
+        let before = now - self.horizon;
+        if self.apply {
+            return measure(db, before, false).await;
+        }
+
+        let copy = std::env::temp_dir().join(format!(
+            "ftdemo-prune-{}.db",
+            uuid::Builder::from_random_bytes(rand::random()).into_uuid()
+        ));
+        db.backup(&copy).await?;
+        let rehearsal = rehearse(db, database, &copy, before).await;
+        let _ = remove_with_log(&copy);
+
+        // The copy has no archive, but its history would be archived
+        let mut report = rehearsal?;
+        report.record.archived = database.archive_path.is_some();
+        Ok(report)
+    }
+}
+
+// Prune the copy of the database, as the database itself would be, though
+// without archiving or exporting the history pruned
+async fn rehearse(
+    db: &Db,
+    database: &SqliteConfig,
+    copy: &Path,
+    before: OffsetDateTime,
+) -> anyhow::Result<PruneReport> {
+    let config = SqliteConfig {
+        database_path: Some(copy.to_owned()),
+        create_if_missing: false,
+        replica_paths: Vec::new(),
+        archive_path: None,
+        export_path: None,
+        ..database.clone()
+    };
+    let connected = Db::connect(&config).await?;
+    let report = async {
+        let market = connected
+            .market(db.market_id.as_str(), OffsetDateTime::now_utc().into())
+            .await?;
+        measure(&market, before, true).await
+    }
+    .await;
+    connected.reader.close().await;
+    connected.writer.close().await;
+    report
+}
+
+// Prune the history, measuring the bytes it held
+async fn measure(db: &Db, before: OffsetDateTime, dry_run: bool) -> anyhow::Result<PruneReport> {
+    let used = db.used_bytes().await?;
+    let record = db.prune_history(before.into()).await?;
+    let bytes = used.saturating_sub(db.used_bytes().await?);
+    let rows = record.curves
+        + record.demand_groups
+        + record.product_groups
+        + record.portfolio_outcomes
+        + record.product_outcomes
+        + record.batch_inputs;
+    Ok(PruneReport {
+        dry_run,
+        record,
+        rows,
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Seed;
+
+    #[tokio::test]
+    async fn dry_runs_leave_the_history_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = SqliteConfig {
+            database_path: Some(dir.path().join("market.db")),
+            ..Default::default()
+        };
+        let now = OffsetDateTime::now_utc();
+        let db = Db::open(&database, now.into()).await.unwrap();
+        Seed {
+            seed: 1,
+            bidders: 4,
+            from: now.replace_time(time::Time::MIDNIGHT) + time::Duration::days(1),
+            days: 3,
+            batches: 3,
+        }
+        .run(&db, now)
+        .await
+        .unwrap();
+
+        // The batches superseded one another's outcomes a minute apart, so
+        // all but the last are pruned with a horizon of under a minute
+        let prune = Prune {
+            horizon: Duration::from_secs(1),
+            apply: false,
+        };
+        let later = now + time::Duration::seconds(2);
+        let rehearsed = prune.run(&db, &database, later).await.unwrap();
+        assert!(rehearsed.dry_run);
+        assert!(rehearsed.rows > 0);
+
+        // so the same is pruned for real, once
+        let applied = Prune {
+            apply: true,
+            ..prune.clone()
+        }
+        .run(&db, &database, later)
+        .await
+        .unwrap();
+        assert!(!applied.dry_run);
+        assert_eq!(applied.rows, rehearsed.rows);
+        let again = prune.run(&db, &database, later).await.unwrap();
+        assert_eq!(again.rows, 0);
+    }
+}
@@ -0,0 +1,278 @@
+//! Reloading the configuration of a running server.
+//!
+//! Restarting the server to change, say, the interval between auctions would
+//! drop every open connection (including the event streams of its clients)
+//! and interrupt any auction being solved. Instead, the configuration is
+//! loaded anew whenever the process receives SIGHUP or the configuration file
+//! changes, and applied in place: the server settings to subsequent requests,
+//! and the schedules to the auctions of their markets. The remaining settings
+//! (the database, the markets hosted, retention, checkpointing, the event
+//! relay, metrics, and the address, API versions and compression of the
+//! server) are only read at startup, so changes to them are reported as
+//! requiring a restart.
+
+use crate::{AppConfig, Market, Schedule};
+use fts_axum::config::AxumConfig;
+use fts_sqlite::config::SqliteConfig;
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{mpsc, watch};
+use tracing::{Level, event};
+
+/// How long to wait for a burst of changes to the file to settle, as editors
+/// commonly save a file in several steps
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// The configuration of a running server, and the components to which a
+/// reloaded configuration is applied.
+pub struct Reloader {
+    file: Option<PathBuf>,
+    current: AppConfig,
+    server: watch::Sender<Arc<AxumConfig>>,
+    schedules: Vec<(Option<String>, Schedule)>,
+}
+
+impl Reloader {
+    /// Manage the configuration loaded from `file` (or from the environment
+    /// alone, if omitted).
+    pub fn new(file: Option<PathBuf>, config: AppConfig) -> Self {
+        let server = watch::Sender::new(Arc::new(config.server.clone()));
+        Self {
+            file,
+            current: config,
+            server,
+            schedules: Vec::new(),
+        }
+    }
+
+    /// The current configuration.
+    pub fn config(&self) -> &AppConfig {
+        &self.current
+    }
+
+    /// A receiver of the server configuration, for a
+    /// [reloadable router](fts_axum::reloadable_router).
+    pub fn server(&self) -> watch::Receiver<Arc<AxumConfig>> {
+        self.server.subscribe()
+    }
+
+    /// Create the schedule of a market (if omitted, the market of the
+    /// database configuration) from the current configuration, which is
+    /// reconfigured whenever the market's schedule is changed by a reload.
+    pub fn schedule(&mut self, market: Option<&str>) -> Schedule {
+        let schedule = Schedule::new(self.current.schedule(market).clone());
+        self.schedules
+            .push((market.map(str::to_owned), schedule.clone()));
+        schedule
+    }
+
+    /// Load the configuration anew and apply it, keeping the current one if
+    /// the new one is invalid.
+    ///
+    /// Only the sections which changed are applied, so that a reload does not
+    /// undo the changes made to the schedule through the API unless the
+    /// schedule itself was changed.
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        let config = AppConfig::load(self.file.clone())?;
+        let old = &self.current;
+
+        let server = changed(&old.server, &config.server);
+        if server {
+            self.server.send_replace(Arc::new(config.server.clone()));
+        }
+        let mut schedule = false;
+        for (market, handle) in &self.schedules {
+            let scheduler = config.schedule(market.as_deref());
+            if changed(old.schedule(market.as_deref()), scheduler) {
+                handle.reconfigure(scheduler.clone());
+                schedule = true;
+            }
+        }
+
+        let restart = [
+            (
+                "server.bind_address",
+                changed(&old.server.bind_address, &config.server.bind_address),
+            ),
+            (
+                "server.versions",
+                changed(&old.server.versions, &config.server.versions),
+            ),
+            (
+                "server.compression",
+                changed(&old.server.compression, &config.server.compression),
+            ),
+            ("database", changed(&old.database, &config.database)),
+            ("retention", changed(&old.retention, &config.retention)),
+            ("checkpoint", changed(&old.checkpoint, &config.checkpoint)),
+            ("outbox", changed(&old.outbox, &config.outbox)),
+            ("metrics", changed(&old.metrics, &config.metrics)),
+            (
+                "markets",
+                changed(&hosted(&old.markets), &hosted(&config.markets)),
+            ),
+        ];
+        for (setting, _) in restart.into_iter().filter(|(_, changed)| *changed) {
+            event!(Level::WARN, setting, "reloaded setting requires a restart");
+        }
+        event!(Level::INFO, server, schedule, "reloaded configuration");
+
+        self.current = config;
+        Ok(())
+    }
+
+    /// Reload the configuration whenever the process receives SIGHUP (on Unix)
+    /// or the configuration file changes, until the process is stopped.
+    ///
+    /// A configuration which fails to load is reported, and the current one
+    /// kept, so a mistake in editing the file does not stop the server.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hangup = signal(SignalKind::hangup())?;
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    if tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // The watcher stops once dropped, so is held until the server stops
+        let _watcher = match &self.file {
+            Some(file) => match watch_file(file, tx.clone()) {
+                Ok(watcher) => Some(watcher),
+                Err(error) => {
+                    event!(Level::ERROR, %error, "unable to watch the configuration file");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Since a sender is held here, the channel never closes
+        while rx.recv().await.is_some() {
+            while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+            if let Err(error) = self.reload() {
+                event!(Level::ERROR, %error, "unable to reload the configuration");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether a section of the configuration changed, as its serialized form does
+fn changed<T: Serialize>(old: &T, new: &T) -> bool {
+    serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+}
+
+// The markets hosted, and their databases, aside from their schedules
+fn hosted(markets: &[Market]) -> Vec<(&str, Option<&SqliteConfig>)> {
+    markets
+        .iter()
+        .map(|market| (market.id.as_str(), market.database.as_ref()))
+        .collect()
+}
+
+// A file is watched through its directory, as editors commonly save by
+// replacing the file rather than writing to it
+fn watch_file(
+    file: &Path,
+    tx: mpsc::UnboundedSender<()>,
+) -> anyhow::Result<notify::RecommendedWatcher> {
+    let file = std::path::absolute(file)?;
+    let parent = file.parent().unwrap_or(Path::new("/")).to_owned();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            // Reading the file is not changing it
+            Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+            Ok(event) => {
+                if event.paths.contains(&file) {
+                    let _ = tx.send(());
+                }
+            }
+            Err(error) => event!(Level::ERROR, %error, "unable to watch the configuration file"),
+        }
+    })?;
+    watcher.watch(&parent, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reloads_apply_changed_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        std::fs::write(&file, "[server]\npage_limit = 10\n").unwrap();
+
+        let config = AppConfig::load(Some(file.clone())).unwrap();
+        let mut reloader = Reloader::new(Some(file.clone()), config);
+        let server = reloader.server();
+        let schedule = reloader.schedule(None);
+        schedule.update(Some(Duration::from_secs(60)), None, None);
+
+        // The schedule changed through the API survives a reload which
+        // leaves the configured schedule alone
+        std::fs::write(&file, "[server]\npage_limit = 20\nauto_solve = true\n").unwrap();
+        reloader.reload().unwrap();
+        assert_eq!(server.borrow().page_limit, 20);
+        assert!(server.borrow().auto_solve);
+        assert_eq!(schedule.every(), Some(Duration::from_secs(60)));
+
+        std::fs::write(
+            &file,
+            "[server]\npage_limit = 20\n[schedule]\nevery = \"5m\"\n",
+        )
+        .unwrap();
+        reloader.reload().unwrap();
+        assert!(!server.borrow().auto_solve);
+        assert_eq!(schedule.every(), Some(Duration::from_secs(300)));
+
+        // An invalid configuration leaves the current one in place
+        std::fs::write(&file, "[server]\npage_limit = \"many\"\n").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.config().server.page_limit, 20);
+        assert_eq!(server.borrow().page_limit, 20);
+    }
+
+    #[tokio::test]
+    async fn markets_reload_their_own_schedules() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        let markets = |every: &str| {
+            format!(
+                "markets = [\"east\", {{ id = \"pilot\", schedule = {{ every = \"{every}\" }} }}]\n"
+            )
+        };
+        std::fs::write(&file, markets("5m")).unwrap();
+
+        let config = AppConfig::load(Some(file.clone())).unwrap();
+        let mut reloader = Reloader::new(Some(file.clone()), config);
+        let root = reloader.schedule(None);
+        let east = reloader.schedule(Some("east"));
+        let pilot = reloader.schedule(Some("pilot"));
+        assert_eq!(pilot.every(), Some(Duration::from_secs(300)));
+
+        // Only the market whose schedule changed is reconfigured
+        east.update(Some(Duration::from_secs(60)), None, None);
+        std::fs::write(&file, markets("10m")).unwrap();
+        reloader.reload().unwrap();
+        assert_eq!(root.every(), None);
+        assert_eq!(east.every(), Some(Duration::from_secs(60)));
+        assert_eq!(pilot.every(), Some(Duration::from_secs(600)));
+    }
+}
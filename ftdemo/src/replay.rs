@@ -0,0 +1,302 @@
+//! Replaying historical batch auctions.
+//!
+//! Before a solver is upgraded or reconfigured, it is worth knowing whether it
+//! would have cleared the market as the current one did. This module solves
+//! each recorded batch auction again, from the inputs recorded for it or, if
+//! none were, from its bids as the temporal tables say they stood, and
+//! compares the outcomes with those stored at the time.
+
+use clap::ValueEnum;
+use fts_core::{
+    models::{Basis, DateTimeRangeQuery, DemandCurve, Map, Weights},
+    ports::{BatchRepository, Solver as _},
+};
+use fts_solver::{PortfolioOutcome, ProductOutcome, clarabel::ClarabelSolver};
+use fts_sqlite::{
+    Db,
+    types::{DateTime, DemandId, PortfolioId, ProductId},
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+/// The number of batches retrieved from the history at a time.
+const PAGE: usize = 100;
+
+/// The solvers with which a batch may be replayed.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReplaySolver {
+    /// The interior-point solver with which the server runs its batches
+    Clarabel,
+    /// The ADMM solver
+    #[cfg(feature = "osqp")]
+    Osqp,
+}
+
+type Outcomes = (
+    Map<PortfolioId, PortfolioOutcome>,
+    Map<ProductId, ProductOutcome>,
+);
+
+impl ReplaySolver {
+    async fn solve(
+        self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+    ) -> Result<Outcomes, fts_solver::SolveError> {
+        match self {
+            Self::Clarabel => {
+                ClarabelSolver::default()
+                    .solve(demand_curves, portfolios, ())
+                    .await
+            }
+            #[cfg(feature = "osqp")]
+            Self::Osqp => {
+                fts_solver::osqp::OsqpSolver::default()
+                    .solve(demand_curves, portfolios, None)
+                    .await
+            }
+        }
+    }
+}
+
+/// Which batch auctions to replay, and how.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// Replay the batches run from this time (if omitted, from the first)
+    pub from: Option<OffsetDateTime>,
+    /// Replay the batches run before this time (if omitted, until the last)
+    pub until: Option<OffsetDateTime>,
+    /// The solver to replay the batches with
+    pub solver: ReplaySolver,
+    /// The largest difference in a price or rate considered to be the same
+    pub tolerance: f64,
+}
+
+/// Where the inputs of a replayed batch came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayInputs {
+    /// The inputs recorded by the batch itself
+    Recorded,
+    /// The bids active at the time of the batch, gathered anew
+    Reconstructed,
+}
+
+/// The price and rate of a portfolio or product.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ReplayOutcome {
+    /// The price of the portfolio or product
+    pub price: f64,
+    /// The rate of trade of the portfolio or product
+    pub rate: f64,
+}
+
+impl From<PortfolioOutcome> for ReplayOutcome {
+    fn from(outcome: PortfolioOutcome) -> Self {
+        Self {
+            price: outcome.price,
+            rate: outcome.rate,
+        }
+    }
+}
+
+impl From<ProductOutcome> for ReplayOutcome {
+    fn from(outcome: ProductOutcome) -> Self {
+        Self {
+            price: outcome.price,
+            rate: outcome.rate,
+        }
+    }
+}
+
+/// The outcomes of a portfolio or product which differ between the stored
+/// batch and its replay.
+#[derive(Debug, Serialize)]
+pub struct OutcomeDifference<Id> {
+    /// The portfolio or product
+    pub id: Id,
+    /// The outcome stored by the batch, if any
+    pub stored: Option<ReplayOutcome>,
+    /// The outcome of the replay, if any
+    pub replayed: Option<ReplayOutcome>,
+}
+
+/// The comparison of a batch auction with its replay.
+#[derive(Debug, Serialize)]
+pub struct ReplayRecord {
+    /// The time the batch was run
+    pub as_of: DateTime,
+    /// Where the inputs of the replay came from
+    pub inputs: ReplayInputs,
+    /// The error of the solver when the batch was run, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stored_error: Option<String>,
+    /// The error of the solver when the batch was replayed, if it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replayed_error: Option<String>,
+    /// The portfolios whose outcomes differ beyond the tolerance
+    pub portfolios: Vec<OutcomeDifference<PortfolioId>>,
+    /// The products whose outcomes differ beyond the tolerance
+    pub products: Vec<OutcomeDifference<ProductId>>,
+}
+
+impl ReplayRecord {
+    /// Whether the replay failed where the batch succeeded (or vice versa), or
+    /// cleared any portfolio or product differently.
+    pub fn is_divergent(&self) -> bool {
+        self.stored_error.is_some() != self.replayed_error.is_some()
+            || !self.portfolios.is_empty()
+            || !self.products.is_empty()
+    }
+}
+
+impl Replay {
+    /// Replay every batch in the range, oldest first.
+    pub async fn run(&self, db: &Db) -> anyhow::Result<Vec<ReplayRecord>> {
+        let mut batches = Vec::new();
+        let mut query = Some(DateTimeRangeQuery {
+            before: self.until.map(Into::into),
+            after: self.from.map(Into::into),
+        });
+        while let Some(page) = query {
+            let response =
+                BatchRepository::<ClarabelSolver<_, _, _>>::get_batch_runs(db, page, PAGE).await?;
+            batches.extend(
+                response
+                    .results
+                    .into_iter()
+                    .map(|record| (record.valid_from, record.value.error)),
+            );
+            query = response.more;
+        }
+        batches.reverse();
+
+        let mut records = Vec::with_capacity(batches.len());
+        for (as_of, stored_error) in batches {
+            records.push(self.replay(db, as_of, stored_error).await?);
+        }
+        Ok(records)
+    }
+
+    async fn replay(
+        &self,
+        db: &Db,
+        as_of: DateTime,
+        stored_error: Option<String>,
+    ) -> anyhow::Result<ReplayRecord> {
+        let recorded =
+            BatchRepository::<ClarabelSolver<_, _, _>>::get_batch_inputs(db, as_of).await?;
+        let (inputs, auction) = match recorded {
+            Some(auction) => (ReplayInputs::Recorded, auction),
+            None => (ReplayInputs::Reconstructed, db.gather_auction(as_of).await?),
+        };
+
+        let (stored_portfolios, stored_products) = db
+            .get_batch_outcomes::<PortfolioOutcome, ProductOutcome>(as_of)
+            .await?;
+
+        let (demand_curves, portfolios) = auction.into_parts();
+        let (replayed_error, (replayed_portfolios, replayed_products)) =
+            match self.solver.solve(demand_curves, portfolios).await {
+                Ok(outcomes) => (None, outcomes),
+                Err(error) => (Some(error.to_string()), Default::default()),
+            };
+
+        Ok(ReplayRecord {
+            as_of,
+            inputs,
+            stored_error,
+            replayed_error,
+            portfolios: self.compare(stored_portfolios, replayed_portfolios),
+            products: self.compare(stored_products, replayed_products),
+        })
+    }
+
+    // The outcomes present in either map which differ beyond the tolerance
+    fn compare<Id: Copy + std::hash::Hash + Eq, T: Into<ReplayOutcome>>(
+        &self,
+        stored: Map<Id, T>,
+        replayed: Map<Id, T>,
+    ) -> Vec<OutcomeDifference<Id>> {
+        let stored = convert(stored);
+        let replayed = convert(replayed);
+        let ids = stored
+            .keys()
+            .chain(replayed.keys().filter(|id| !stored.contains_key(*id)))
+            .copied()
+            .collect::<Vec<_>>();
+
+        ids.into_iter()
+            .filter_map(|id| {
+                let stored = stored.get(&id).copied();
+                let replayed = replayed.get(&id).copied();
+                let same = match (stored, replayed) {
+                    (Some(a), Some(b)) => self.same(a.price, b.price) && self.same(a.rate, b.rate),
+                    _ => false,
+                };
+                (!same).then_some(OutcomeDifference {
+                    id,
+                    stored,
+                    replayed,
+                })
+            })
+            .collect()
+    }
+
+    // A solver reports an undetermined price as NaN, which is the same as
+    // another, if not equal to it
+    fn same(&self, a: f64, b: f64) -> bool {
+        (a.is_nan() && b.is_nan()) || (a - b).abs() <= self.tolerance
+    }
+}
+
+fn convert<Id: std::hash::Hash + Eq, T: Into<ReplayOutcome>>(
+    outcomes: Map<Id, T>,
+) -> Map<Id, ReplayOutcome> {
+    outcomes
+        .into_iter()
+        .map(|(id, outcome)| (id, outcome.into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Seed;
+
+    #[tokio::test]
+    async fn replays_match_their_batches() {
+        let now = OffsetDateTime::now_utc();
+        let db = Db::open(&fts_sqlite::config::SqliteConfig::default(), now.into())
+            .await
+            .unwrap();
+        Seed {
+            seed: 3,
+            bidders: 6,
+            from: now.replace_time(time::Time::MIDNIGHT) + time::Duration::days(1),
+            days: 4,
+            batches: 3,
+        }
+        .run(&db, now)
+        .await
+        .unwrap();
+
+        let replay = Replay {
+            from: None,
+            until: None,
+            solver: ReplaySolver::Clarabel,
+            tolerance: 1e-6,
+        };
+        let records = replay.run(&db).await.unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records.windows(2).all(|pair| pair[0].as_of < pair[1].as_of));
+        assert!(records.iter().all(|record| !record.is_divergent()));
+
+        // Only the batches within the range are replayed
+        let replay = Replay {
+            from: Some(now - time::Duration::minutes(1)),
+            ..replay
+        };
+        assert_eq!(replay.run(&db).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! Periodic pruning of the repository's history.
+//!
+//! The history of demands, portfolios, and batch outcomes grows with every
+//! change and every auction. This module provides maintenance tasks which
+//! periodically prune the history older than a configured horizon, and
+//! compact the redundant history of portfolios which are resubmitted unchanged.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{Instrument as _, Level, span};
+
+/// Configuration for periodically pruning the repository's history.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Retention {
+    /// How long to retain superseded history (if omitted, history is retained indefinitely)
+    #[serde(default, with = "humantime_serde::option")]
+    pub horizon: Option<Duration>,
+    /// How often to prune the history
+    #[serde(default = "default_every", with = "humantime_serde")]
+    pub every: Duration,
+    /// How often to compact the history (if omitted, history is not compacted)
+    #[serde(default, with = "humantime_serde::option")]
+    pub compact_every: Option<Duration>,
+}
+
+fn default_every() -> Duration {
+    Duration::from_secs(3600)
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            horizon: None,
+            every: default_every(),
+            compact_every: None,
+        }
+    }
+}
+
+impl Retention {
+    /// Execute a function every `every`, passing the time before which
+    /// history should be pruned.
+    ///
+    /// If no horizon is configured, this never executes the function and
+    /// never returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(E)` if the function returns an error
+    pub async fn run<T, E>(
+        &self,
+        f: impl AsyncFn(OffsetDateTime) -> Result<T, E>,
+    ) -> Result<(), E> {
+        let Some(horizon) = self.horizon else {
+            return std::future::pending().await;
+        };
+
+        let mut interval = tokio::time::interval(self.every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let before = OffsetDateTime::now_utc() - horizon;
+
+            let span = span!(Level::INFO, "pruning history");
+            f(before).instrument(span).await?;
+        }
+    }
+
+    /// Execute a function every `compact_every`, to compact the history.
+    ///
+    /// If no interval is configured, this never executes the function and
+    /// never returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(E)` if the function returns an error
+    pub async fn compact<T, E>(&self, f: impl AsyncFn() -> Result<T, E>) -> Result<(), E> {
+        let Some(every) = self.compact_every else {
+            return std::future::pending().await;
+        };
+
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let span = span!(Level::INFO, "compacting history");
+            f().instrument(span).await?;
+        }
+    }
+}
@@ -1,43 +1,363 @@
 //! Scheduler for running periodic batch auctions.
 //!
-//! This module provides functionality to schedule and execute batch auctions at regular intervals.
-//! The scheduler can be configured with a start time and execution frequency, and will automatically
-//! align execution times with the configured schedule.
+//! This module provides functionality to schedule and execute batch auctions at regular intervals,
+//! or at the times given by named [cron expressions](Cron). The scheduler can be configured with a
+//! start time and execution frequency, and will automatically align execution times with the
+//! configured schedule. Once running, the schedule can be inspected
+//! and changed (e.g. through the REST API) via its [`Schedule`] handle. Each
+//! auction is executed as its [batch configuration](BatchConfig) says, which a
+//! named cron expression may override for the auctions it schedules.
 
+use crate::cron::Cron;
+use fts_core::{
+    models::{BatchError, Map},
+    ports::{BatchRepository as _, Repository},
+};
+use fts_solver::{SolveError, clarabel::ClarabelSolver};
+use fts_sqlite::{
+    Db,
+    types::{DateTime, PortfolioId},
+};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::sync::watch;
 use tracing::{Instrument as _, Level, event, span};
 
 /// Configuration for scheduling periodic batch auctions.
 ///
 /// The scheduler allows configuring when to start executing auctions and how frequently
 /// to run them. It handles clock alignment to ensure auctions run at predictable intervals.
+/// Auctions are further executed at the times of any named cron expressions, e.g.
+/// `business = "0,30 9-16 * * mon-fri"`, so that the next auction is the earliest due by
+/// the interval or any of the expressions. An expression may instead be given as a table
+/// with a batch configuration of its own, e.g. `close = { cron = "30 16 * * mon-fri", batch =
+/// { solver = "osqp" } }`.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Scheduler {
     /// An RFC3339 timestamp to start the auction schedule from (if omitted or empty, defaults to now)
-    #[serde(with = "time::serde::rfc3339::option")]
+    #[serde(default, with = "time::serde::rfc3339::option")]
     pub from: Option<time::OffsetDateTime>,
     /// How often to execute an auction
-    #[serde(with = "humantime_serde::option")]
+    #[serde(default, with = "humantime_serde::option")]
     pub every: Option<Duration>,
+    /// Cron expressions (in UTC) at whose times to further execute auctions, by name
+    #[serde(default)]
+    pub cron: BTreeMap<String, CronSchedule>,
+    /// How to execute the auctions, unless a cron expression due at the time says otherwise
+    #[serde(default)]
+    pub batch: BatchConfig,
 }
 
-impl Scheduler {
-    /// Schedule and execute a function at regular intervals.
+/// A named cron expression of a [`Scheduler`], along with how to execute the
+/// auctions it schedules (if omitted, as the scheduler's batch configuration says).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "CronEntry")]
+pub struct CronSchedule {
+    /// The times at which to execute auctions
+    pub cron: Cron,
+    /// How to execute the auctions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchConfig>,
+}
+
+// The forms a cron expression may be given in, which only exist while the
+// configuration is read
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CronEntry {
+    Cron(Cron),
+    Table {
+        cron: Cron,
+        #[serde(default)]
+        batch: Option<BatchConfig>,
+    },
+}
+
+impl From<CronEntry> for CronSchedule {
+    fn from(entry: CronEntry) -> Self {
+        match entry {
+            CronEntry::Cron(cron) => cron.into(),
+            CronEntry::Table { cron, batch } => Self { cron, batch },
+        }
+    }
+}
+
+impl From<Cron> for CronSchedule {
+    fn from(cron: Cron) -> Self {
+        Self { cron, batch: None }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse::<Cron>()?.into())
+    }
+}
+
+/// The solvers with which batch auctions may be executed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchSolver {
+    /// The interior-point solver
+    #[default]
+    Clarabel,
+    /// The ADMM solver
+    #[cfg(feature = "osqp")]
+    Osqp,
+}
+
+/// How to execute a batch auction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchConfig {
+    /// The solver with which to clear the auction
+    #[serde(default)]
+    pub solver: BatchSolver,
+
+    /// Whether to warm-start the solver from the trade rates of the batch
+    /// before, for the solvers able to make use of them
+    #[serde(default = "default_warm_start")]
+    pub warm_start: bool,
+}
+
+fn default_warm_start() -> bool {
+    true
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            solver: Default::default(),
+            warm_start: default_warm_start(),
+        }
+    }
+}
+
+/// The trade rates of the portfolios in the last batch auction a market
+/// executed, from which the next may be warm-started.
+#[derive(Clone, Debug, Default)]
+pub struct BatchState(Arc<Mutex<Option<Map<PortfolioId>>>>);
+
+impl BatchConfig {
+    /// Execute the batch auction of the market of `db` as of `now`, starting
+    /// from (and then updating) the rates in `state`.
+    pub async fn run(
+        &self,
+        db: &Db,
+        now: OffsetDateTime,
+        state: &BatchState,
+    ) -> Result<Result<Option<DateTime>, BatchError<SolveError>>, <Db as Repository>::Error> {
+        // A solver without a state leaves none for the next batch either
+        let batch = match self.solver {
+            BatchSolver::Clarabel => db
+                .run_batch_with_state(now.into(), ClarabelSolver::default(), ())
+                .await
+                .map(|batch| batch.map(|(expires, ())| (expires, None))),
+            #[cfg(feature = "osqp")]
+            BatchSolver::Osqp => {
+                let rates = if self.warm_start {
+                    state.0.lock().unwrap().clone()
+                } else {
+                    None
+                };
+                db.run_batch_with_state(now.into(), fts_solver::osqp::OsqpSolver::default(), rates)
+                    .await
+            }
+        };
+
+        Ok(batch?.map(|(expires, rates)| {
+            *state.0.lock().unwrap() = rates;
+            expires
+        }))
+    }
+}
+
+/// The state of a running schedule.
+#[derive(Clone, Debug)]
+struct State {
+    /// The time the schedule is aligned to
+    from: OffsetDateTime,
+    /// How often to execute an auction, if at all
+    every: Option<Duration>,
+    /// The cron expressions at whose times to further execute auctions
+    cron: BTreeMap<String, CronSchedule>,
+    /// How to execute the auctions, unless a cron expression says otherwise
+    batch: BatchConfig,
+    /// Whether the execution of auctions is suspended
+    paused: bool,
+    /// The time of the last auction executed
+    last: Option<OffsetDateTime>,
+}
+
+impl State {
+    /// The time of the next auction, i.e. the earliest time aligned with the
+    /// schedule which is no earlier than `now` and later than the last auction.
+    fn next(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        if self.paused {
+            return None;
+        }
+
+        let interval = self.every.map(|every| {
+            let mut next = self.from;
+            if next < now {
+                next += every * ((now - next) / every).ceil() as u32;
+            }
+            if let Some(last) = self.last {
+                while next <= last {
+                    next += every;
+                }
+            }
+            next
+        });
+
+        // A cron expression is due at most once per minute, so the first
+        // time after the last auction is the start of a later minute
+        let after = match self.last {
+            Some(last) => now.max(last + Duration::from_nanos(1)),
+            None => now,
+        };
+        let cron = self
+            .cron
+            .values()
+            .filter_map(|schedule| schedule.cron.next(after))
+            .min();
+
+        interval.into_iter().chain(cron).min()
+    }
+
+    /// The names of the cron expressions due at `time`.
+    fn due(&self, time: OffsetDateTime) -> Vec<&str> {
+        self.cron
+            .iter()
+            .filter(|(_, schedule)| schedule.cron.next(time) == Some(time))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// How to execute the auction at `time`: as the first of the cron
+    /// expressions due then (by name) with a batch configuration of its own
+    /// says, or else as the schedule says.
+    fn batch(&self, time: OffsetDateTime) -> &BatchConfig {
+        self.cron
+            .values()
+            .filter(|schedule| schedule.cron.next(time) == Some(time))
+            .find_map(|schedule| schedule.batch.as_ref())
+            .unwrap_or(&self.batch)
+    }
+}
+
+/// A handle to a batch auction schedule, which may be changed while it runs.
+///
+/// Handles are cheap to clone, and every clone controls the same schedule.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    state: Arc<watch::Sender<State>>,
+}
+
+impl Schedule {
+    /// Create a schedule from its configuration. Auctions are not executed
+    /// until the schedule is [run](Schedule::run).
+    pub fn new(config: Scheduler) -> Self {
+        let state = State {
+            from: config.from.unwrap_or_else(OffsetDateTime::now_utc),
+            every: config.every,
+            cron: config.cron,
+            batch: config.batch,
+            paused: false,
+            last: None,
+        };
+        Self {
+            state: Arc::new(watch::Sender::new(state)),
+        }
+    }
+
+    /// How often auctions are executed, if at all (aside from those due by a
+    /// cron expression).
+    pub fn every(&self) -> Option<Duration> {
+        self.state.borrow().every
+    }
+
+    /// Whether the execution of auctions is suspended.
+    pub fn paused(&self) -> bool {
+        self.state.borrow().paused
+    }
+
+    /// The time of the next auction, if any are scheduled.
+    pub fn next(&self) -> Option<OffsetDateTime> {
+        self.state.borrow().next(OffsetDateTime::now_utc())
+    }
+
+    /// Change the schedule, taking effect immediately. Each omitted argument
+    /// leaves the corresponding aspect of the schedule unchanged. Subsequent
+    /// auctions are executed every `every` from `next`, as well as whenever a
+    /// cron expression is due.
+    pub fn update(
+        &self,
+        every: Option<Duration>,
+        next: Option<OffsetDateTime>,
+        paused: Option<bool>,
+    ) {
+        self.state.send_modify(|state| {
+            if let Some(every) = every {
+                // Unless the next auction is also rescheduled, the schedule
+                // remains aligned with the upcoming auction
+                let upcoming = state.next(OffsetDateTime::now_utc());
+                if let Some(upcoming) = upcoming.filter(|_| next.is_none()) {
+                    state.from = upcoming;
+                }
+                state.every = Some(every);
+            }
+            if let Some(next) = next {
+                state.from = next;
+                // The rescheduled auction may precede the last one
+                state.last = None;
+            }
+            if let Some(paused) = paused {
+                state.paused = paused;
+            }
+        });
+    }
+
+    /// Replace the configuration of the schedule (e.g. once the configuration
+    /// file has been reloaded), taking effect immediately. Whether the schedule
+    /// is paused is unchanged, as is the time of the last auction, so that no
+    /// auction is executed twice. If `from` is omitted, the schedule remains
+    /// aligned as it was.
+    pub fn reconfigure(&self, config: Scheduler) {
+        self.state.send_modify(|state| {
+            if let Some(from) = config.from {
+                state.from = from;
+            }
+            state.every = config.every;
+            state.cron = config.cron;
+            state.batch = config.batch;
+        });
+    }
+
+    /// Execute a function according to the schedule.
     ///
-    /// This method will:
-    /// 1. Calculate the next execution time based on the configured schedule
-    /// 2. Wait until that time
-    /// 3. Execute the provided function repeatedly at the configured interval
+    /// This method will repeatedly:
+    /// 1. Calculate the next execution time based on the current schedule
+    /// 2. Wait until that time, or until the schedule is changed
+    /// 3. Execute the provided function for that time, and the configuration
+    ///    with which to execute its auction
+    ///
+    /// While the schedule is paused (or has neither an interval nor a cron
+    /// expression), nothing is executed until it is changed.
     ///
     /// # Arguments
     ///
-    /// * `f` - An async function that takes a timestamp and returns a Result
+    /// * `f` - An async function that takes a timestamp and a batch configuration, and returns a Result
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if scheduling is disabled (no interval configured)
     /// * `Err(E)` if the scheduled function returns an error
     ///
     /// # Examples
@@ -45,69 +365,241 @@ impl Scheduler {
     /// ```no_run
     /// use std::time::Duration;
     /// use time::OffsetDateTime;
-    /// use ftdemo::Scheduler;
+    /// use ftdemo::{BatchConfig, Schedule, Scheduler};
     ///
     /// # fn main() -> Result<(), String> {
-    /// let scheduler = Scheduler {
+    /// let schedule = Schedule::new(Scheduler {
     ///     from: Some(OffsetDateTime::now_utc()),
     ///     every: Some(Duration::from_secs(3600)), // Every hour
-    /// };
+    ///     cron: [("business".to_owned(), "0,30 9-16 * * mon-fri".parse()?)].into(),
+    ///     batch: Default::default(),
+    /// });
     ///
     /// # tokio_test::block_on(async {
-    /// scheduler.schedule(|timestamp| async move {
-    ///     println!("Running batch at {}", timestamp);
+    /// schedule.run(|timestamp, batch: BatchConfig| async move {
+    ///     println!("Running batch at {} with {:?}", timestamp, batch.solver);
     ///     Ok::<(), String>(())
     /// }).await?;
     /// # Ok(())
     /// # })
     /// # }
     /// ```
-    pub async fn schedule<T, E>(
+    pub async fn run<T, E>(
         &self,
-        f: impl AsyncFn(OffsetDateTime) -> Result<T, E>,
+        f: impl AsyncFn(OffsetDateTime, BatchConfig) -> Result<T, E>,
     ) -> Result<(), E> {
-        // extract the duration or return immediately
-        let Some(delta) = self.every else {
-            return Ok(());
-        };
+        let mut receiver = self.state.subscribe();
 
-        let now = time::OffsetDateTime::now_utc();
+        loop {
+            let next = receiver.borrow_and_update().next(OffsetDateTime::now_utc());
 
-        // adjust the anchor time to be >= now
-        let mut anchor = if let Some(mut from) = self.from {
-            if from < now {
-                let x = ((now - from) / delta).ceil() as u32;
-                from += delta * x;
-            }
-            from
-        } else {
-            now
-        };
+            let Some(next) = next else {
+                // Nothing is scheduled, so wait for the schedule to change.
+                // Since we hold a handle to the sender, this never fails.
+                let _ = receiver.changed().await;
+                continue;
+            };
 
-        // now we align the clocks as best we can
-        {
-            let sleepy: std::time::Duration = (anchor - now)
+            let sleepy: Duration = (next - OffsetDateTime::now_utc())
                 .try_into()
-                .expect("anchor too far in the future");
+                .unwrap_or_default();
 
-            tokio::time::sleep(sleepy).await;
-        };
-
-        // Finally, we can loop over a timer
-        let mut interval = tokio::time::interval(delta);
-
-        loop {
-            interval.tick().await;
+            tokio::select! {
+                _ = tokio::time::sleep(sleepy) => {}
+                _ = receiver.changed() => continue,
+            }
 
             let span = span!(Level::INFO, "running scheduled auction");
+            let (due, batch) = {
+                let state = self.state.borrow();
+                (state.due(next).join(","), state.batch(next).clone())
+            };
             async {
-                event!(Level::INFO, batch_time = anchor.format(&Rfc3339).unwrap(),);
-                f(anchor).await
+                event!(
+                    Level::INFO,
+                    batch_time = next.format(&Rfc3339).unwrap(),
+                    cron = due
+                );
+                f(next, batch).await
             }
             .instrument(span)
             .await?;
 
-            anchor += delta;
+            // Record the auction without waking the receiver
+            self.state.send_if_modified(|state| {
+                state.last = Some(next);
+                false
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn state(every: u64) -> State {
+        State {
+            from: datetime!(2025-01-01 0:00 UTC),
+            every: Some(Duration::from_secs(every)),
+            cron: BTreeMap::new(),
+            batch: BatchConfig::default(),
+            paused: false,
+            last: None,
+        }
+    }
+
+    #[test]
+    fn test_next_is_aligned_with_the_schedule() {
+        let state = state(60);
+        assert_eq!(
+            state.next(datetime!(2024-12-31 0:00 UTC)),
+            Some(datetime!(2025-01-01 0:00 UTC))
+        );
+        assert_eq!(
+            state.next(datetime!(2025-01-01 0:00:30 UTC)),
+            Some(datetime!(2025-01-01 0:01 UTC))
+        );
+        assert_eq!(
+            state.next(datetime!(2025-01-01 0:01 UTC)),
+            Some(datetime!(2025-01-01 0:01 UTC))
+        );
+    }
+
+    #[test]
+    fn test_next_follows_the_last_auction() {
+        let state = State {
+            last: Some(datetime!(2025-01-01 0:01 UTC)),
+            ..state(60)
+        };
+        assert_eq!(
+            state.next(datetime!(2025-01-01 0:01 UTC)),
+            Some(datetime!(2025-01-01 0:02 UTC))
+        );
+    }
+
+    #[test]
+    fn test_nothing_is_scheduled_while_paused() {
+        let state = State {
+            paused: true,
+            ..state(60)
+        };
+        assert_eq!(state.next(datetime!(2025-01-01 0:00 UTC)), None);
+    }
+
+    #[test]
+    fn test_next_is_the_earliest_of_interval_and_cron() {
+        let state = State {
+            cron: [
+                ("open".to_owned(), "0 9 * * *".parse().unwrap()),
+                ("close".to_owned(), "30 16 * * *".parse().unwrap()),
+            ]
+            .into(),
+            ..state(3600 * 24)
+        };
+        assert_eq!(
+            state.next(datetime!(2025-01-01 0:00:01 UTC)),
+            Some(datetime!(2025-01-01 9:00 UTC))
+        );
+        assert_eq!(
+            state.next(datetime!(2025-01-01 16:31 UTC)),
+            Some(datetime!(2025-01-02 0:00 UTC))
+        );
+        assert_eq!(state.due(datetime!(2025-01-01 16:30 UTC)), ["close"]);
+
+        // Without an interval, only the cron expressions are due, each once
+        let state = State {
+            every: None,
+            last: Some(datetime!(2025-01-01 9:00 UTC)),
+            ..state
+        };
+        assert_eq!(
+            state.next(datetime!(2025-01-01 9:00 UTC)),
+            Some(datetime!(2025-01-01 16:30 UTC))
+        );
+        let paused = State {
+            paused: true,
+            ..state
+        };
+        assert_eq!(paused.next(datetime!(2025-01-01 9:00 UTC)), None);
+    }
+
+    #[test]
+    fn test_a_cron_expression_may_configure_its_auctions() {
+        let state = State {
+            cron: [
+                ("open".to_owned(), "0 9 * * *".parse().unwrap()),
+                (
+                    "close".to_owned(),
+                    CronSchedule {
+                        cron: "30 16 * * *".parse().unwrap(),
+                        batch: Some(BatchConfig::default()),
+                    },
+                ),
+            ]
+            .into(),
+            ..state(3600 * 24)
+        };
+        let close = state.cron["close"].batch.as_ref().unwrap();
+        assert!(std::ptr::eq(
+            state.batch(datetime!(2025-01-01 16:30 UTC)),
+            close
+        ));
+        // Auctions due by the interval or a plain expression are executed as
+        // the schedule says
+        for time in [
+            datetime!(2025-01-01 0:00 UTC),
+            datetime!(2025-01-01 9:00 UTC),
+        ] {
+            assert!(std::ptr::eq(state.batch(time), &state.batch));
         }
     }
+
+    #[tokio::test]
+    async fn test_changing_the_interval_keeps_the_upcoming_auction() {
+        let next = OffsetDateTime::now_utc() + Duration::from_secs(3600);
+        let schedule = Schedule::new(Scheduler {
+            from: Some(next),
+            every: Some(Duration::from_secs(60)),
+            cron: BTreeMap::new(),
+            batch: BatchConfig::default(),
+        });
+        schedule.update(Some(Duration::from_secs(10)), None, None);
+        assert_eq!(schedule.next(), Some(next));
+        assert_eq!(schedule.every(), Some(Duration::from_secs(10)));
+
+        schedule.update(None, None, Some(true));
+        assert_eq!(schedule.next(), None);
+        schedule.update(None, None, Some(false));
+        assert_eq!(schedule.next(), Some(next));
+    }
+
+    #[tokio::test]
+    async fn test_reconfiguring_keeps_the_alignment_and_pause() {
+        let from = OffsetDateTime::now_utc() + Duration::from_secs(3600);
+        let schedule = Schedule::new(Scheduler {
+            from: Some(from),
+            every: Some(Duration::from_secs(60)),
+            cron: BTreeMap::new(),
+            batch: BatchConfig::default(),
+        });
+        schedule.update(None, None, Some(true));
+
+        schedule.reconfigure(Scheduler {
+            from: None,
+            every: Some(Duration::from_secs(600)),
+            cron: BTreeMap::new(),
+            batch: BatchConfig::default(),
+        });
+        assert_eq!(schedule.every(), Some(Duration::from_secs(600)));
+        assert_eq!(schedule.next(), None);
+        schedule.update(None, None, Some(false));
+        assert_eq!(schedule.next(), Some(from));
+
+        // Without an interval or cron expression, nothing is scheduled
+        schedule.reconfigure(Scheduler::default());
+        assert_eq!(schedule.every(), None);
+        assert_eq!(schedule.next(), None);
+    }
 }
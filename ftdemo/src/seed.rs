@@ -0,0 +1,279 @@
+//! Populating a market with a demonstration dataset.
+//!
+//! Demos and workshops need a market with something in it. This module seeds
+//! one with daily forward products, a handful of bidders buying and selling
+//! strips of them, and optionally a few solved batch auctions, all drawn from
+//! a seedable generator so that every run of a workshop starts the same way.
+
+use crate::impls::{DemandData, PortfolioData, ProductData, ProductKind, demand_id, portfolio_id};
+use fts_core::{
+    models::{Actor, Basis, BatchError, DemandCurve, Point, PwlCurve, Weights},
+    ports::{BatchRepository as _, DemandRepository, PortfolioRepository, ProductRepository},
+};
+use fts_solver::clarabel::ClarabelSolver;
+use fts_sqlite::{Db, types::BidderId};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+/// The time between the batch auctions run after seeding.
+const BATCH_INTERVAL: Duration = Duration::minutes(1);
+
+/// The shape of a demonstration market.
+#[derive(Debug, Clone)]
+pub struct Seed {
+    /// The seed of the generator; the same seed always generates the same
+    /// products, bidders, curves and portfolios
+    pub seed: u64,
+    /// The number of bidders, alternately buyers and sellers
+    pub bidders: usize,
+    /// The start of the first daily product
+    pub from: OffsetDateTime,
+    /// The number of consecutive daily products
+    pub days: usize,
+    /// The number of batch auctions to run once the market is seeded
+    pub batches: usize,
+}
+
+/// A summary of a seeded market.
+#[derive(Debug, Serialize)]
+pub struct SeedRecord {
+    /// The number of products created
+    pub products: usize,
+    /// The bidders, e.g. to sign tokens for
+    pub bidders: Vec<BidderId>,
+    /// The number of demands created
+    pub demands: usize,
+    /// The number of portfolios created
+    pub portfolios: usize,
+    /// The number of batch auctions run
+    pub batches: usize,
+}
+
+/// The curve of a demand, kept to nudge it between batches
+pub(crate) struct Bid {
+    buyer: bool,
+    quantity: f64,
+    value: f64,
+    spread: f64,
+}
+
+impl Bid {
+    /// The valuation of a bidder, about which its bids are drawn. Buyers
+    /// value the products somewhat more than sellers, so that the two trade.
+    pub(crate) fn base(rng: &mut impl Rng, buyer: bool) -> f64 {
+        if buyer {
+            rng.random_range(45.0..60.0)
+        } else {
+            rng.random_range(35.0..50.0)
+        }
+    }
+
+    /// Draw a bid about the valuation of a bidder.
+    pub(crate) fn new(rng: &mut impl Rng, buyer: bool, base: f64) -> Self {
+        Self {
+            buyer,
+            quantity: rng.random_range(5.0..20.0),
+            value: base + rng.random_range(-3.0..3.0),
+            spread: rng.random_range(1.0..5.0),
+        }
+    }
+
+    /// Revise the valuation of the bid a little.
+    pub(crate) fn nudge(&mut self, rng: &mut impl Rng) {
+        self.value += rng.random_range(-2.0..2.0);
+    }
+
+    /// A buyer buys up to `quantity` at prices falling from `value`, and a
+    /// seller sells up to `quantity` at prices rising from `value`.
+    pub(crate) fn curve(&self) -> anyhow::Result<DemandCurve> {
+        let points = if self.buyer {
+            vec![
+                Point {
+                    rate: 0.0,
+                    price: self.value,
+                },
+                Point {
+                    rate: self.quantity,
+                    price: self.value - self.spread,
+                },
+            ]
+        } else {
+            vec![
+                Point {
+                    rate: -self.quantity,
+                    price: self.value + self.spread,
+                },
+                Point {
+                    rate: 0.0,
+                    price: self.value,
+                },
+            ]
+        };
+        Ok(PwlCurve::new(points)?.into())
+    }
+}
+
+impl Seed {
+    /// Seed the market, finishing (after any batch auctions) at `now`.
+    ///
+    /// The records are created as of `now`, less the time taken by the batch
+    /// auctions, which follow one another a minute apart. The products must not
+    /// already exist.
+    pub async fn run(&self, db: &Db, now: OffsetDateTime) -> anyhow::Result<SeedRecord> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut as_of = now - BATCH_INTERVAL * self.batches as u32;
+
+        let products = (0..self.days)
+            .map(|day| {
+                let from = self.from + Duration::days(day as i64);
+                let data = ProductData::new(from, from + Duration::days(1), ProductKind::Forward);
+                (data.id(), data)
+            })
+            .collect::<Vec<_>>();
+        let product_ids = products.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        db.create_products(products, as_of.into()).await?;
+
+        let mut bidders = Vec::with_capacity(self.bidders);
+        let mut bids = Vec::new();
+        let mut portfolios = 0;
+
+        for i in 0..self.bidders {
+            let bidder_id = BidderId(uuid::Builder::from_random_bytes(rng.random()).into_uuid());
+            bidders.push(bidder_id);
+
+            let buyer = i % 2 == 0;
+            let base = Bid::base(&mut rng, buyer);
+
+            for k in 0..rng.random_range(1..=3) {
+                if product_ids.is_empty() {
+                    break;
+                }
+
+                // Each portfolio trades a strip of consecutive days
+                let length = rng.random_range(1..=product_ids.len().min(3));
+                let start = rng.random_range(0..=product_ids.len() - length);
+                let basis = product_ids[start..start + length]
+                    .iter()
+                    .map(|product_id| (*product_id, 1.0))
+                    .collect::<Basis<_>>();
+
+                let demand_id = demand_id(as_of, rng.next_u64());
+                let bid = Bid::new(&mut rng, buyer, base);
+                let name = format!("bidder {i} strip {k}");
+
+                db.create_demand(
+                    demand_id,
+                    bidder_id,
+                    DemandData::new(name.as_str()),
+                    bid.curve()?,
+                    Actor::Operator,
+                    as_of.into(),
+                )
+                .await?;
+                db.create_portfolio(
+                    portfolio_id(as_of, rng.next_u64()),
+                    bidder_id,
+                    PortfolioData::new(name),
+                    std::iter::once((demand_id, 1.0)).collect::<Weights<_>>(),
+                    basis,
+                    Actor::Operator,
+                    as_of.into(),
+                )
+                .await?;
+
+                bids.push((demand_id, bid));
+                portfolios += 1;
+            }
+        }
+
+        // Between batches, bidders revise their valuations a little, so that
+        // each batch clears somewhat differently
+        for batch in 0..self.batches {
+            as_of += BATCH_INTERVAL;
+            if batch > 0 {
+                for (demand_id, bid) in bids.iter_mut() {
+                    bid.nudge(&mut rng);
+                    DemandRepository::<DemandData>::update_demand(
+                        db,
+                        *demand_id,
+                        bid.curve()?,
+                        Actor::Operator,
+                        as_of.into(),
+                    )
+                    .await?;
+                }
+            }
+
+            match db
+                .run_batch(as_of.into(), ClarabelSolver::default(), ())
+                .await?
+            {
+                Ok(_) => {}
+                Err(BatchError::InProgress) => {
+                    anyhow::bail!("a batch auction is already in progress")
+                }
+                Err(BatchError::Solver(error)) => {
+                    anyhow::bail!("the batch auction failed: {error}")
+                }
+            }
+        }
+
+        Ok(SeedRecord {
+            products: product_ids.len(),
+            bidders,
+            demands: bids.len(),
+            portfolios,
+            batches: self.batches,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fts_core::ports::BatchRepository;
+    async fn seed(seed: u64, batches: usize) -> (Db, SeedRecord) {
+        let now = OffsetDateTime::now_utc();
+        let db = Db::open(&fts_sqlite::config::SqliteConfig::default(), now.into())
+            .await
+            .unwrap();
+        let record = Seed {
+            seed,
+            bidders: 6,
+            from: now.replace_time(time::Time::MIDNIGHT) + Duration::days(1),
+            days: 5,
+            batches,
+        }
+        .run(&db, now)
+        .await
+        .unwrap();
+        (db, record)
+    }
+
+    #[tokio::test]
+    async fn seeding_is_reproducible() {
+        let (_, a) = seed(42, 0).await;
+        let (_, b) = seed(42, 0).await;
+        let (_, c) = seed(43, 0).await;
+
+        assert_eq!(a.products, 5);
+        assert_eq!(a.bidders.len(), 6);
+        assert!(a.portfolios >= 6);
+        assert_eq!(a.bidders, b.bidders);
+        assert_eq!(a.portfolios, b.portfolios);
+        assert_ne!(a.bidders, c.bidders);
+    }
+
+    #[tokio::test]
+    async fn seeding_runs_batches() {
+        let (db, record) = seed(7, 2).await;
+        assert_eq!(record.batches, 2);
+
+        let latest = BatchRepository::<ClarabelSolver<_, _, _>>::get_latest_batch(&db)
+            .await
+            .unwrap();
+        assert!(latest.is_some());
+    }
+}
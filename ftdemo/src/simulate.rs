@@ -0,0 +1,370 @@
+//! Generating load against a running server.
+//!
+//! Seeding writes to the database directly, so exercises neither the HTTP
+//! API nor the batches the server solves as bids change. This module instead
+//! plays a number of synthetic bidders against a running server, each
+//! authenticating with a token of its own, creating demands and portfolios
+//! over strips of the products, and then revising its curves at random, so
+//! that the server may be watched under a steady stream of updates.
+
+use crate::{
+    Token,
+    impls::{DemandData, PortfolioData, ProductData, ProductKind},
+    seed::Bid,
+};
+use fts_client::{Client, Remote};
+use fts_core::models::{Basis, ProductQuery, Weights};
+use fts_sqlite::types::{BidderId, DateTime};
+use jwt_simple::prelude::HS256Key;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{Level, event};
+use uuid::Uuid;
+
+type SimClient = Client<Remote<DateTime, Uuid>>;
+
+/// How long the tokens of the synthetic bidders remain valid
+const TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The shape of the load to generate.
+#[derive(Debug, Clone)]
+pub struct Simulate {
+    /// The base URL of the server, e.g. `http://localhost:8080`
+    pub url: String,
+    /// The number of bidders, alternately buyers and sellers
+    pub bidders: usize,
+    /// The number of curve updates per second, across every bidder
+    pub rate: f64,
+    /// How long to update curves for (if omitted, until stopped)
+    pub duration: Option<Duration>,
+    /// The seed of the generator; the same seed always generates the same
+    /// bidders and curves, though not the same timing of their updates
+    pub seed: u64,
+    /// The number of consecutive daily products to create, starting
+    /// tomorrow, if the server has no products
+    pub days: usize,
+}
+
+/// A summary of the load generated.
+#[derive(Debug, Default, Serialize)]
+pub struct SimulateRecord {
+    /// The number of bidders
+    pub bidders: usize,
+    /// The number of products bid upon
+    pub products: usize,
+    /// The number of demands created
+    pub demands: usize,
+    /// The number of portfolios created
+    pub portfolios: usize,
+    /// The number of curve updates accepted
+    pub updates: usize,
+    /// The number of requests which failed
+    pub errors: usize,
+    /// The time spent updating curves, in seconds
+    pub elapsed: f64,
+    /// The mean latency of a request, in milliseconds
+    pub mean_latency: f64,
+    /// The greatest latency of a request, in milliseconds
+    pub max_latency: f64,
+}
+
+// The requests made by a bidder
+#[derive(Default)]
+struct Stats {
+    demands: usize,
+    portfolios: usize,
+    updates: usize,
+    errors: usize,
+    requests: usize,
+    latency: Duration,
+    max_latency: Duration,
+}
+
+impl Stats {
+    // Time a request
+    async fn time<T>(&mut self, request: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let response = request.await;
+        let latency = start.elapsed();
+        self.requests += 1;
+        self.latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+        response
+    }
+
+    fn merge(&mut self, other: Stats) {
+        self.demands += other.demands;
+        self.portfolios += other.portfolios;
+        self.updates += other.updates;
+        self.errors += other.errors;
+        self.requests += other.requests;
+        self.latency += other.latency;
+        self.max_latency = self.max_latency.max(other.max_latency);
+    }
+}
+
+impl Simulate {
+    /// Generate load against the server, whose tokens are signed with `key`,
+    /// until the duration elapses or `stop` completes, whichever is first.
+    ///
+    /// Failing to create the products, demands or portfolios stops the
+    /// simulation, whereas a failed update is reported and counted.
+    pub async fn run(
+        &self,
+        key: &HS256Key,
+        stop: impl Future<Output = ()>,
+    ) -> anyhow::Result<SimulateRecord> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut stats = Stats::default();
+
+        let admin = SimClient::new(self.url.as_str())?.with_token(
+            Token {
+                subject: None,
+                admin: true,
+                scope: None,
+                valid_for: TOKEN_LIFETIME,
+            }
+            .sign(key)?,
+        );
+        let product_ids = self.products(&admin, &mut stats).await?;
+
+        // Every bidder has a generator of its own, so that the curves each
+        // draws do not depend upon the order in which the bidders run
+        let mut bidders = Vec::with_capacity(self.bidders);
+        for i in 0..self.bidders {
+            let bidder_id = BidderId(uuid::Builder::from_random_bytes(rng.random()).into_uuid());
+            let client = SimClient::new(self.url.as_str())?.with_token(
+                Token {
+                    subject: Some(bidder_id),
+                    admin: false,
+                    scope: None,
+                    valid_for: TOKEN_LIFETIME,
+                }
+                .sign(key)?,
+            );
+            let bidder = Bidder {
+                name: format!("bidder {i}"),
+                client,
+                buyer: i % 2 == 0,
+                rng: ChaCha8Rng::seed_from_u64(rng.random()),
+            };
+            bidders.push(bidder);
+        }
+
+        let (stopping, stopped) = watch::channel(false);
+        let rate = self.rate / self.bidders.max(1) as f64;
+        let mut tasks = JoinSet::new();
+        for bidder in bidders {
+            let product_ids = product_ids.clone();
+            let stopped = stopped.clone();
+            tasks.spawn(bidder.run(product_ids, rate, stopped));
+        }
+
+        let start = Instant::now();
+        let deadline = async {
+            match self.duration {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = deadline => {}
+            _ = stop => {}
+            // A bidder only finishes early if it fails to create its bids
+            Some(result) = tasks.join_next() => stats.merge(result??),
+        }
+        let _ = stopping.send(true);
+        while let Some(result) = tasks.join_next().await {
+            stats.merge(result??);
+        }
+        let elapsed = start.elapsed();
+
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        Ok(SimulateRecord {
+            bidders: self.bidders,
+            products: product_ids.len(),
+            demands: stats.demands,
+            portfolios: stats.portfolios,
+            updates: stats.updates,
+            errors: stats.errors,
+            elapsed: elapsed.as_secs_f64(),
+            mean_latency: millis(stats.latency) / stats.requests.max(1) as f64,
+            max_latency: millis(stats.max_latency),
+        })
+    }
+
+    // The products to bid upon: those of the server if it has any, or else
+    // newly created daily products. Since the id of a product leads with the
+    // start of its delivery, the products are listed in order of delivery.
+    async fn products(&self, admin: &SimClient, stats: &mut Stats) -> anyhow::Result<Vec<Uuid>> {
+        let mut product_ids = Vec::new();
+        let mut query = Some(ProductQuery::default());
+        while let Some(page) = query {
+            let page = stats
+                .time(admin.query_products::<ProductData>(&page, None))
+                .await?;
+            product_ids.extend(page.results.into_iter().map(|product| product.id));
+            query = page.more;
+        }
+
+        if product_ids.is_empty() {
+            let tomorrow = OffsetDateTime::now_utc().replace_time(time::Time::MIDNIGHT)
+                + time::Duration::days(1);
+            let products = (0..self.days)
+                .map(|day| {
+                    let from = tomorrow + time::Duration::days(day as i64);
+                    ProductData::new(from, from + time::Duration::days(1), ProductKind::Forward)
+                })
+                .collect::<Vec<_>>();
+            let created = stats.time(admin.create_products(&products)).await?;
+            product_ids = created.into_iter().map(|product| product.id).collect();
+        }
+
+        if product_ids.is_empty() {
+            anyhow::bail!("there are no products to bid upon");
+        }
+        Ok(product_ids)
+    }
+}
+
+// A synthetic bidder
+struct Bidder {
+    name: String,
+    client: SimClient,
+    buyer: bool,
+    rng: ChaCha8Rng,
+}
+
+impl Bidder {
+    // Bid upon a few strips of the products, then update the curves at
+    // random, `rate` times a second on average, until stopped
+    async fn run(
+        mut self,
+        product_ids: Vec<Uuid>,
+        rate: f64,
+        mut stopped: watch::Receiver<bool>,
+    ) -> anyhow::Result<Stats> {
+        let mut stats = Stats::default();
+        let base = Bid::base(&mut self.rng, self.buyer);
+
+        let mut bids = Vec::new();
+        for k in 0..self.rng.random_range(1..=3) {
+            let length = self.rng.random_range(1..=product_ids.len().min(3));
+            let start = self.rng.random_range(0..=product_ids.len() - length);
+            let basis = product_ids[start..start + length]
+                .iter()
+                .map(|product_id| (*product_id, 1.0))
+                .collect::<Basis<_>>();
+            let bid = Bid::new(&mut self.rng, self.buyer, base);
+            let name = format!("{} strip {k}", self.name);
+
+            let demand = stats
+                .time(
+                    self.client
+                        .create_demand(&DemandData::new(name.as_str()), &bid.curve()?),
+                )
+                .await?;
+            stats.demands += 1;
+            stats
+                .time(self.client.create_portfolio(
+                    &PortfolioData::new(name),
+                    &std::iter::once((demand.id, 1.0)).collect::<Weights<_>>(),
+                    &basis,
+                ))
+                .await?;
+            stats.portfolios += 1;
+
+            bids.push((demand.id, bid));
+        }
+
+        if rate <= 0.0 {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+            return Ok(stats);
+        }
+
+        // Updates arrive as a Poisson process, i.e. a random time apart
+        loop {
+            let wait = -(1.0 - self.rng.random::<f64>()).ln() / rate;
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs_f64(wait)) => {}
+                _ = stopped.wait_for(|stopped| *stopped) => break,
+            }
+
+            let index = self.rng.random_range(0..bids.len());
+            let (demand_id, bid) = &mut bids[index];
+            bid.nudge(&mut self.rng);
+            let curve = bid.curve()?;
+            match stats
+                .time(self.client.update_demand::<DemandData>(demand_id, &curve))
+                .await
+            {
+                Ok(_) => stats.updates += 1,
+                Err(error) => {
+                    event!(Level::WARN, bidder = self.name, %demand_id, %error, "unable to update a curve");
+                    stats.errors += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Schedule, Scheduler, impls::DemoApp};
+    use fts_axum::config::AxumConfig;
+    use fts_sqlite::{Db, config::SqliteConfig};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn bidders_update_their_curves() {
+        let key = HS256Key::generate();
+        let now = OffsetDateTime::now_utc();
+        let app = DemoApp {
+            db: Db::open(&SqliteConfig::default(), now.into())
+                .await
+                .unwrap(),
+            key: key.clone(),
+            schedule: Schedule::new(Scheduler::default()),
+        };
+        let config = AxumConfig {
+            auto_solve: true,
+            solve_debounce: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, fts_axum::router(app, config)).into_future());
+
+        let simulate = Simulate {
+            url: format!("http://{address}"),
+            bidders: 4,
+            rate: 40.0,
+            duration: Some(Duration::from_secs(1)),
+            seed: 7,
+            days: 5,
+        };
+        let record = simulate.run(&key, std::future::pending()).await.unwrap();
+        assert_eq!(record.bidders, 4);
+        assert_eq!(record.products, 5);
+        assert!(record.demands >= 4);
+        assert_eq!(record.portfolios, record.demands);
+        assert!(record.updates > 0);
+        assert_eq!(record.errors, 0);
+
+        // A second run bids upon the products already created
+        let record = Simulate {
+            days: 10,
+            duration: Some(Duration::from_millis(100)),
+            ..simulate
+        }
+        .run(&key, std::future::pending())
+        .await
+        .unwrap();
+        assert_eq!(record.products, 5);
+    }
+}
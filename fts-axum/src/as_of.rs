@@ -0,0 +1,41 @@
+//! Point-in-time reads.
+//!
+//! The endpoints reading demands, portfolios, and products accept an `as_of`
+//! query parameter, returning the records exactly as they were at that
+//! instant rather than as they are now. As this reveals what has since been
+//! changed or deleted, an explicit `as_of` requires the `can_audit` permission
+//! in addition to that of the read itself.
+
+use crate::{ApiApplication, problem::Problem};
+use fts_core::ports::Repository;
+use schemars::JsonSchema;
+
+/// Query parameter selecting the instant at which to read.
+#[derive(serde::Deserialize, JsonSchema)]
+#[schemars(inline)]
+pub(crate) struct AsOfQuery<DateTime> {
+    /// Read the records as of this time (defaults to the current time), which
+    /// requires audit permission
+    as_of: Option<DateTime>,
+}
+
+impl<DateTime> AsOfQuery<DateTime> {
+    /// Whether the client chose the instant, rather than reading the present.
+    pub(crate) fn is_requested(&self) -> bool {
+        self.as_of.is_some()
+    }
+
+    /// The instant at which to read, provided the context may read at it.
+    pub(crate) async fn resolve<T>(self, app: &T, context: &T::Context) -> Result<DateTime, Problem>
+    where
+        T: ApiApplication,
+        T::Repository: Repository<DateTime = DateTime>,
+    {
+        match self.as_of {
+            None => Ok(app.now()),
+            Some(as_of) if app.can_audit(context).await => Ok(as_of),
+            Some(_) => Err(Problem::not_authorized()
+                .with_detail("reading as of another time requires audit permission")),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Authentication of requests.
+//!
+//! Every endpoint consults the application's permission hooks with a
+//! [`Context`](fts_core::ports::Application::Context) extracted from the
+//! request. By default this is the bearer token of the `Authorization` header,
+//! but an application may authenticate by any other means (e.g. client
+//! certificates, API-key headers, or cookies) by choosing a context type which
+//! implements [`AuthContext`].
+
+use crate::ApiApplication;
+use aide::{OperationInput, generate::GenContext, openapi::Operation};
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use axum_extra::{TypedHeader, typed_header::TypedHeaderRejection};
+use headers::{Authorization, authorization::Bearer};
+use std::convert::Infallible;
+
+/// A type which can be extracted from an incoming request to authenticate it.
+///
+/// This mirrors axum's [`FromRequestParts`], but, being local to this crate,
+/// may also be implemented for foreign types such as
+/// [`Authorization<Bearer>`].
+pub trait AuthContext<S>: Sized + Send + Sync + 'static {
+    /// The response sent when the request cannot be authenticated
+    type Rejection: IntoResponse;
+
+    /// Authenticate the request, producing the context to pass to the
+    /// application's permission hooks.
+    fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send;
+
+    /// Document the credentials expected by an operation, e.g. by adding the
+    /// header they are read from to its parameters.
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        let _ = (ctx, operation);
+    }
+}
+
+impl<S: Send + Sync> AuthContext<S> for Authorization<Bearer> {
+    type Rejection = TypedHeaderRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let TypedHeader(auth) =
+            <TypedHeader<Self> as FromRequestParts<S>>::from_request_parts(parts, state).await?;
+        Ok(auth)
+    }
+
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        TypedHeader::<Self>::operation_input(ctx, operation);
+    }
+}
+
+/// Extractor for the authentication context of the application `T`.
+pub(crate) struct Auth<T: ApiApplication>(pub T::Context);
+
+impl<T: ApiApplication> FromRequestParts<T> for Auth<T> {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &T) -> Result<Self, Self::Rejection> {
+        <T::Context as AuthContext<T>>::from_request_parts(parts, state)
+            .await
+            .map(Auth)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+/// Extracting an `Option<Auth<T>>` yields None for requests which cannot be
+/// authenticated, for endpoints which also serve anonymous requests.
+impl<T: ApiApplication> OptionalFromRequestParts<T> for Auth<T> {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &T,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(
+            <T::Context as AuthContext<T>>::from_request_parts(parts, state)
+                .await
+                .ok()
+                .map(Auth),
+        )
+    }
+}
+
+impl<T: ApiApplication> OperationInput for Auth<T> {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        <T::Context as AuthContext<T>>::operation_input(ctx, operation);
+    }
+}
@@ -0,0 +1,183 @@
+//! A debounced queue for automatically executing batch auctions.
+//!
+//! When `auto_solve` is enabled, every bid mutation should be followed by a
+//! batch auction. Rather than each request spawning its own solve, requests
+//! notify a single worker task. The worker waits for the debounce window to
+//! elapse, then executes one batch as of the latest notified timestamp; any
+//! notifications received in the meantime (including while a batch is being
+//! solved) are coalesced into the next batch. As such, there is at most one
+//! batch in flight at any time. The batch is traced under an `auto_solve` span
+//! recording the identifier of the request whose notification it executes.
+//! The progress of the worker is reported by the detailed health check.
+
+use crate::{ApiApplication, config::AxumConfig, request_id};
+use axum::http::HeaderValue;
+use fts_core::{
+    models::BatchError,
+    ports::{Application, BatchRepository as _, Repository, Solver},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+use tokio::sync::watch;
+use tracing::{Instrument as _, Level, event, info_span};
+
+type DateTime<T> = <<T as Application>::Repository as Repository>::DateTime;
+
+/// The state of the solver of `T`, carried from one batch to the next
+type SolverState<T> = <<T as Application>::Solver as Solver<
+    <<T as Application>::Repository as Repository>::DemandId,
+    <<T as Application>::Repository as Repository>::PortfolioId,
+    <<T as Application>::Repository as Repository>::ProductId,
+>>::State;
+
+/// The latest notified timestamp, along with the request that notified it
+type Notification<T> = Option<(DateTime<T>, Option<HeaderValue>)>;
+
+/// The progress of the worker, shared with every handle to the queue
+#[derive(Default)]
+struct Progress {
+    /// Whether a notification awaits execution
+    pending: AtomicBool,
+    /// Whether a batch is being executed
+    solving: AtomicBool,
+    /// The error of the most recent batch, if it failed
+    last_error: Mutex<Option<String>>,
+}
+
+/// The status of the automatic execution of batch auctions.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct AutoSolveStatus {
+    /// The number of batches awaiting or undergoing execution (at most 2)
+    depth: u32,
+    /// The error of the most recently executed batch, if it failed
+    last_error: Option<String>,
+}
+
+/// A handle for requesting the execution of a batch auction.
+pub(crate) struct BatchQueue<T: ApiApplication> {
+    sender: Option<Arc<watch::Sender<Notification<T>>>>,
+    progress: Arc<Progress>,
+}
+
+impl<T: ApiApplication> Clone for BatchQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            progress: self.progress.clone(),
+        }
+    }
+}
+
+impl<T: ApiApplication> BatchQueue<T> {
+    /// A queue which ignores all notifications.
+    pub(crate) fn disabled() -> Self {
+        Self {
+            sender: None,
+            progress: Default::default(),
+        }
+    }
+
+    /// Spawn the worker executing batches for `app`, waiting the debounce
+    /// window of the current configuration after a notification before solving.
+    ///
+    /// The worker stops once every handle to the queue has been dropped.
+    pub(crate) fn spawn(app: T, config: watch::Receiver<Arc<AxumConfig>>) -> Self {
+        let (sender, mut receiver) = watch::channel::<Notification<T>>(None);
+        let progress = Arc::new(Progress::default());
+        let worker = progress.clone();
+
+        tokio::spawn(async move {
+            // The solver state derived from the last batch executed here, with
+            // which the next is warm-started (if so configured)
+            let mut state = SolverState::<T>::default();
+            while receiver.changed().await.is_ok() {
+                let debounce = config.borrow().solve_debounce;
+                tokio::time::sleep(debounce).await;
+
+                let notification = receiver.borrow_and_update().clone();
+                worker.pending.store(false, Ordering::Relaxed);
+                let Some((as_of, request_id)) = notification else {
+                    continue;
+                };
+                worker.solving.store(true, Ordering::Relaxed);
+
+                let span = info_span!(
+                    "auto_solve",
+                    request_id = request_id
+                        .as_ref()
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default(),
+                );
+                if !config.borrow().warm_start {
+                    state = Default::default();
+                }
+                // A batch started elsewhere (by the scheduler, say) may not
+                // account for this notification, so wait for it to finish
+                let error = loop {
+                    match app
+                        .database()
+                        .run_batch_with_state(as_of.clone(), app.solver(), state.clone())
+                        .instrument(span.clone())
+                        .await
+                    {
+                        Ok(Ok((_, next_state))) => {
+                            state = next_state;
+                            break None;
+                        }
+                        Ok(Err(BatchError::InProgress)) => {}
+                        Ok(Err(err)) => break Some(err.to_string()),
+                        Err(err) => break Some(err.to_string()),
+                    }
+                    let debounce = config.borrow().solve_debounce;
+                    tokio::time::sleep(debounce).await;
+                };
+                let _enter = span.enter();
+
+                if let Some(err) = &error {
+                    event!(Level::ERROR, err = err.as_str());
+                }
+                *worker.last_error.lock().unwrap() = error;
+                worker.solving.store(false, Ordering::Relaxed);
+            }
+        });
+
+        Self {
+            sender: Some(Arc::new(sender)),
+            progress,
+        }
+    }
+
+    /// The status of the worker, or None if the queue is disabled.
+    pub(crate) fn status(&self) -> Option<AutoSolveStatus> {
+        self.sender.as_ref()?;
+        let progress = &self.progress;
+        Some(AutoSolveStatus {
+            depth: progress.pending.load(Ordering::Relaxed) as u32
+                + progress.solving.load(Ordering::Relaxed) as u32,
+            last_error: progress.last_error.lock().unwrap().clone(),
+        })
+    }
+
+    /// Request a batch auction as of (at least) `as_of`.
+    ///
+    /// Notifications older than the latest one are ignored, as the batch
+    /// executed for the latest timestamp accounts for them as well. The batch
+    /// is attributed to the request being handled when notified, if any.
+    pub(crate) fn notify(&self, as_of: DateTime<T>) {
+        if let Some(sender) = &self.sender {
+            sender.send_if_modified(|latest| {
+                if latest.as_ref().is_some_and(|(latest, _)| *latest >= as_of) {
+                    false
+                } else {
+                    *latest = Some((as_of, request_id::current()));
+                    self.progress.pending.store(true, Ordering::Relaxed);
+                    true
+                }
+            });
+        }
+    }
+}
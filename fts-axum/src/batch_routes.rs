@@ -1,23 +1,62 @@
 //! REST API endpoints for batch auction operations.
 //!
-//! This module provides endpoints for executing batch auctions. Batch auctions
-//! are the core mechanism through which flow trading clears the market by
-//! solving for optimal allocations and prices at regular intervals.
-
-use aide::axum::{ApiRouter, routing::post};
-use axum::{extract::State, http::StatusCode};
-use axum_extra::TypedHeader;
-use fts_core::ports::BatchRepository as _;
-use headers::{Authorization, authorization::Bearer};
+//! This module provides endpoints for executing batch auctions, for awaiting
+//! their completion, for reviewing how they were solved (or why they failed),
+//! and for solving self-contained auctions. Batch auctions are the core
+//! mechanism through which flow trading clears the market by solving for
+//! optimal allocations and prices at regular intervals.
+
+use aide::axum::{
+    ApiRouter,
+    routing::{get, post},
+};
+use axum::{
+    Extension, Json,
+    extract::{Query, State, rejection::JsonRejection},
+    http::StatusCode,
+    response::NoContent,
+};
+use fts_core::{
+    models::{
+        Auction, AuctionOutcome, BatchAttempt, BatchError, BatchMetadata, BatchPreview, BatchRun,
+        BatchSchedule, DateTimeRangeQuery, DateTimeRangeResponse, ScheduleUpdate,
+    },
+    ports::{Application, BatchRepository, Repository, Solver},
+};
+use std::{sync::Arc, time::Duration};
 use tracing::{Level, event};
 
-use crate::ApiApplication;
+use crate::{ApiApplication, auth::Auth, config::AxumConfig, limit::LimitQuery, problem::Problem};
 
 /// Creates a router with batch-related endpoints.
 pub fn router<T: ApiApplication>() -> ApiRouter<T> {
-    ApiRouter::new().api_route_with("/", post(batch_solve::<T>), |route| {
-        route.security_requirement("jwt").tag("admin")
-    })
+    ApiRouter::new()
+        .api_route_with("/", post(batch_solve::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/preview", post(batch_preview::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/adhoc", post(solve_auction::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/runs", get(batch_runs::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/attempts", get(batch_attempts::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/inputs", get(batch_inputs::<T>), |route| {
+            route.security_requirement("jwt").tag("admin")
+        })
+        .api_route_with("/next", get(next_batch::<T>), |route| {
+            route.security_requirement("jwt").tag("outcome")
+        })
+        .api_route_with(
+            "/schedule",
+            get(get_schedule::<T>).patch(update_schedule::<T>),
+            |route| route.security_requirement("jwt").tag("admin"),
+        )
 }
 
 /// Execute a batch auction at the current timestamp.
@@ -34,11 +73,12 @@ pub fn router<T: ApiApplication>() -> ApiRouter<T> {
 ///
 /// - `200 OK`: Batch executed successfully, returns the timestamp
 /// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `409 Conflict`: Another batch auction is already in progress
 /// - `500 Internal Server Error`: Solver or database operation failed
 async fn batch_solve<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-) -> Result<(StatusCode, String), (StatusCode, String)> {
+    Auth(auth): Auth<T>,
+) -> Result<(StatusCode, String), Problem> {
     let as_of = app.now();
     if app.can_run_batch(&auth).await {
         let db = app.database();
@@ -46,25 +86,393 @@ async fn batch_solve<T: ApiApplication>(
         let _expires = db
             .run_batch(as_of.clone(), app.solver(), Default::default())
             .await
-            .map_err(|err| {
-                event!(Level::ERROR, err = err.to_string());
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("failed to launch solver"),
-                )
-            })?
-            .map_err(|err| {
-                event!(Level::ERROR, err = err.to_string());
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("failed to solve batch"),
-                )
+            .map_err(Problem::internal)?
+            .map_err(|err| match err {
+                BatchError::InProgress => Problem::new(StatusCode::CONFLICT, "batch_in_progress")
+                    .with_detail(err.to_string()),
+                BatchError::Solver(err) => {
+                    event!(Level::ERROR, err = err.to_string());
+                    Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "solver_failed")
+                }
             })?;
 
         // assert _expires.is_none()?
 
         Ok((StatusCode::OK, format!("{}", as_of)))
     } else {
-        Err((StatusCode::UNAUTHORIZED, "not authorized".to_string()))
+        Err(Problem::not_authorized())
+    }
+}
+
+type Preview<T> = BatchPreview<
+    <T as Application>::Repository,
+    <<T as Application>::Solver as Solver<
+        <<T as Application>::Repository as Repository>::DemandId,
+        <<T as Application>::Repository as Repository>::PortfolioId,
+        <<T as Application>::Repository as Repository>::ProductId,
+    >>::PortfolioOutcome,
+    <<T as Application>::Solver as Solver<
+        <<T as Application>::Repository as Repository>::DemandId,
+        <<T as Application>::Repository as Repository>::PortfolioId,
+        <<T as Application>::Repository as Repository>::ProductId,
+    >>::ProductOutcome,
+>;
+
+/// Solve a batch auction at the current timestamp without recording it.
+///
+/// The active demands and portfolios are gathered and solved exactly as they
+/// would be by a batch auction, but the hypothetical outcomes are returned
+/// rather than persisted. This allows prices to be sanity-checked before a
+/// scheduled batch.
+///
+/// # Authorization
+///
+/// Requires `can_preview_batch` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The outcomes of every portfolio and product
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `500 Internal Server Error`: Solver or database operation failed
+async fn batch_preview<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+) -> Result<Json<Preview<T>>, Problem> {
+    if !app.can_preview_batch(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let preview = app
+        .database()
+        .preview_batch(app.now(), app.solver(), Default::default())
+        .await
+        .map_err(Problem::internal)?
+        .map_err(|err| {
+            event!(Level::ERROR, err = err.to_string());
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "solver_failed")
+        })?;
+
+    Ok(Json(preview))
+}
+
+/// Retrieve the records of how the batch auctions were solved.
+///
+/// Every batch records the solver backend and settings it was solved with,
+/// along with the status and statistics the solver reported, whether or not
+/// a solution was found. This allows the outcomes of any batch, e.g. an
+/// unexpected price, to be investigated after the fact.
+///
+/// # Authorization
+///
+/// Requires `can_run_batch` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated records, most recent first
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn batch_runs<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+) -> Result<Json<DateTimeRangeResponse<BatchRun, <T::Repository as Repository>::DateTime>>, Problem>
+{
+    if !app.can_run_batch(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    <T::Repository as BatchRepository<T::Solver>>::get_batch_runs(
+        app.database(),
+        query,
+        limit.page_size(&config),
+    )
+    .await
+    .map(Json)
+    .map_err(Problem::internal)
+}
+
+/// Retrieve the failed attempts to run a batch auction.
+///
+/// A batch fails if the solver cannot solve it or if its inputs cannot be
+/// gathered or its outcomes recorded, in which case the outcomes of the
+/// previous batch remain in effect. Each failure is recorded with its
+/// category (`database` or `solver`) and error, so that an outage of
+/// clearing is visible rather than silent.
+///
+/// # Authorization
+///
+/// Requires `can_run_batch` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated records, most recent first
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn batch_attempts<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+) -> Result<
+    Json<DateTimeRangeResponse<BatchAttempt, <T::Repository as Repository>::DateTime>>,
+    Problem,
+> {
+    if !app.can_run_batch(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    <T::Repository as BatchRepository<T::Solver>>::get_batch_attempts(
+        app.database(),
+        query,
+        limit.page_size(&config),
+    )
+    .await
+    .map(Json)
+    .map_err(Problem::internal)
+}
+
+/// The batch whose inputs to retrieve.
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+struct InputsQuery<T> {
+    /// The time of the batch
+    as_of: T,
+}
+
+/// Retrieve the inputs of a batch auction, exactly as they were assembled
+/// for the solver.
+///
+/// If the repository is configured to record them, the demand curves and
+/// portfolios of every batch are recorded as they were solved, whether or not
+/// a solution was found. The inputs are returned as a self-contained auction,
+/// which may be solved again through `/adhoc` to audit the outcomes of the
+/// batch or to reproduce a failure of the solver.
+///
+/// # Authorization
+///
+/// Requires `can_run_batch` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The demand curves and portfolios of the batch
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `404 Not Found`: No batch was run at the time, or its inputs were not recorded
+/// - `500 Internal Server Error`: Database query failed
+async fn batch_inputs<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Query(InputsQuery { as_of }): Query<InputsQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<Json<Auction<T::Repository>>, Problem> {
+    if !app.can_run_batch(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    <T::Repository as BatchRepository<T::Solver>>::get_batch_inputs(app.database(), as_of)
+        .await
+        .map_err(Problem::internal)?
+        .map(Json)
+        .ok_or_else(|| Problem::not_found("batch_inputs_not_found"))
+}
+
+type Outcome<T> = AuctionOutcome<
+    <T as Application>::Repository,
+    <<T as Application>::Solver as Solver<
+        <<T as Application>::Repository as Repository>::DemandId,
+        <<T as Application>::Repository as Repository>::PortfolioId,
+        <<T as Application>::Repository as Repository>::ProductId,
+    >>::PortfolioOutcome,
+    <<T as Application>::Solver as Solver<
+        <<T as Application>::Repository as Repository>::DemandId,
+        <<T as Application>::Repository as Repository>::PortfolioId,
+        <<T as Application>::Repository as Repository>::ProductId,
+    >>::ProductOutcome,
+>;
+
+/// Solve a self-contained auction with the configured solver.
+///
+/// The demand curves and portfolios are taken from the request body rather
+/// than the repository, and the outcomes are returned rather than persisted.
+/// This allows the production solver to be exercised on arbitrary inputs,
+/// e.g. for experimentation.
+///
+/// # Authorization
+///
+/// Requires `can_solve_auction` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The outcomes of every portfolio and product
+/// - `400 Bad Request`: Malformed JSON
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `422 Unprocessable Entity`: Invalid auction, e.g. a malformed demand curve
+/// - `500 Internal Server Error`: Solver failed
+async fn solve_auction<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    body: Result<Json<Auction<T::Repository>>, JsonRejection>,
+) -> Result<Json<Outcome<T>>, Problem> {
+    let Json(auction) = body?;
+    if !app.can_solve_auction(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let (demand_curves, portfolios) = auction.into_parts();
+    let (portfolios, products) = app
+        .solver()
+        .solve(demand_curves, portfolios, Default::default())
+        .await
+        .map_err(|err| {
+            event!(Level::ERROR, err = err.to_string());
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "solver_failed")
+        })?;
+
+    Ok(Json(AuctionOutcome {
+        portfolios,
+        products,
+    }))
+}
+
+/// The problem reported when the application does not schedule batch auctions
+fn schedule_not_found() -> Problem {
+    Problem::not_found("schedule_not_found")
+        .with_detail("batch auctions are not scheduled by this application")
+}
+
+/// Retrieve the schedule on which batch auctions are executed.
+///
+/// # Authorization
+///
+/// Requires `can_manage_schedule` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The current schedule, including the time of the next batch
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `404 Not Found`: The application does not schedule batch auctions
+async fn get_schedule<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+) -> Result<Json<BatchSchedule<<T::Repository as Repository>::DateTime>>, Problem> {
+    if !app.can_manage_schedule(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    app.schedule()
+        .await
+        .map(Json)
+        .ok_or_else(schedule_not_found)
+}
+
+/// Change the schedule on which batch auctions are executed.
+///
+/// Any of the interval, the time of the next batch, and whether the schedule
+/// is paused can be changed, taking effect immediately; omitted fields are
+/// left unchanged. Subsequent batches are scheduled at the interval from the
+/// time of the next batch.
+///
+/// # Authorization
+///
+/// Requires `can_manage_schedule` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The updated schedule
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `404 Not Found`: The application does not schedule batch auctions
+/// - `422 Unprocessable Entity`: The interval is not a positive number of seconds
+async fn update_schedule<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    body: Result<Json<ScheduleUpdate<<T::Repository as Repository>::DateTime>>, JsonRejection>,
+) -> Result<Json<BatchSchedule<<T::Repository as Repository>::DateTime>>, Problem> {
+    let Json(update) = body?;
+    if !app.can_manage_schedule(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    if update
+        .every
+        .is_some_and(|every| !(every.is_finite() && every > 0.0))
+    {
+        return Err(
+            Problem::new(StatusCode::UNPROCESSABLE_ENTITY, "schedule_invalid")
+                .with_detail("the interval must be a positive number of seconds"),
+        );
+    }
+
+    app.update_schedule(update)
+        .await
+        .map(Json)
+        .ok_or_else(schedule_not_found)
+}
+
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+pub(crate) struct NextQuery<T> {
+    /// Wait for a batch executed after this time (by default, the time of the request)
+    after: Option<T>,
+    /// The longest to wait, in seconds (by default, and at most, the server's
+    /// configured maximum)
+    timeout: Option<u64>,
+}
+
+/// Wait for the completion of a batch auction.
+///
+/// Responds as soon as the most recent batch was executed after `after`,
+/// e.g. the time of the last batch the client has seen. As there is no
+/// notification mechanism for completed batches, the repository is polled at
+/// the configured interval. This allows clients unable to maintain an event
+/// stream to follow the auctions without polling aggressively themselves.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The metadata of the batch
+/// - `204 No Content`: No batch completed before the timeout
+/// - `401 Unauthorized`: Missing view permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn next_batch<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<NextQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<Result<Json<BatchMetadata<T::Repository>>, NoContent>, Problem> {
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let after = query.after.unwrap_or_else(|| app.now());
+    let timeout = query
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(config.max_long_poll)
+        .min(config.max_long_poll);
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    let mut interval = tokio::time::interval(config.poll_interval);
+
+    loop {
+        // The first tick completes immediately, so the repository is checked
+        // at least once, even without a timeout
+        tokio::select! {
+            biased;
+            _ = interval.tick() => {}
+            _ = &mut deadline => return Ok(Err(NoContent)),
+        }
+
+        let latest =
+            <T::Repository as BatchRepository<T::Solver>>::get_latest_batch(app.database())
+                .await
+                .map_err(Problem::internal)?;
+
+        if let Some(batch) = latest.filter(|batch| batch.as_of > after) {
+            return Ok(Ok(Json(batch)));
+        }
     }
 }
@@ -0,0 +1,147 @@
+//! REST API endpoints for bidder-wide operations.
+//!
+//! This module provides endpoints which aggregate across all of a bidder's
+//! demands and portfolios, such as a summary of their trading or the outcomes
+//! of their portfolios in each batch.
+
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    cache,
+    config::AxumConfig,
+    limit::LimitQuery,
+    negotiate::{Accept, Negotiated},
+    problem::Problem,
+};
+use aide::axum::{ApiRouter, routing::get};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+};
+use axum_extra::TypedHeader;
+use fts_core::{
+    models::{BidderOutcome, BidderSummary, DateTimeRangeQuery, DateTimeRangeResponse},
+    ports::{BatchRepository as _, Repository, SettlementRepository as _},
+};
+use headers::CacheControl;
+use std::sync::Arc;
+
+/// Creates a router with bidder-related endpoints.
+pub fn router<T: ApiApplication>() -> ApiRouter<T> {
+    ApiRouter::new()
+        .api_route_with(
+            "/{bidder_id}/summary",
+            get(get_bidder_summary::<T>),
+            |route| {
+                route
+                    .security_requirement("jwt")
+                    .tag("bidder")
+                    .tag("settlement")
+            },
+        )
+        .api_route_with(
+            "/{bidder_id}/outcomes",
+            get(get_bidder_outcomes::<T>),
+            |route| {
+                route
+                    .security_requirement("jwt")
+                    .tag("bidder")
+                    .tag("outcome")
+            },
+        )
+}
+
+/// Path parameter for bidder-specific endpoints.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+struct Id<T> {
+    /// The unique identifier of the bidder
+    bidder_id: T,
+}
+
+/// Summarize the trading of a bidder.
+///
+/// Returns the bidder's net position in each product, the rates of trade of
+/// the batch outcomes currently in effect, and their settled and unsettled
+/// activity, as of the current time.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder (`can_read_settlement`).
+///
+/// # Returns
+///
+/// - `200 OK`: The bidder's summary
+/// - `401 Unauthorized`: Missing read permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_bidder_summary<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { bidder_id }): Path<Id<<T::Repository as Repository>::BidderId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+) -> Result<Json<BidderSummary<T::Repository>>, Problem> {
+    if !app.can_read_settlement(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let summary = app
+        .database()
+        .get_bidder_summary(bidder_id, app.now(), config.time_unit)
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(Json(summary))
+}
+
+/// Retrieve the batch auction outcomes of a bidder.
+///
+/// Returns the bidder's net trade and payment in each product, aggregated
+/// across all of their portfolios, for each batch in which they had an
+/// outcome, most recent first. As for the outcomes of a portfolio, a page
+/// whose interval has ended and whose outcomes have all been superseded may
+/// be cached indefinitely by the client.
+///
+/// # Authorization
+///
+/// Requires read permission for the bidder (`can_read_bid`).
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated outcome records
+/// - `401 Unauthorized`: Missing read permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_bidder_outcomes<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { bidder_id }): Path<Id<<T::Repository as Repository>::BidderId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
+) -> Result<
+    (
+        TypedHeader<CacheControl>,
+        Negotiated<
+            DateTimeRangeResponse<
+                BidderOutcome<<T::Repository as Repository>::ProductId>,
+                <T::Repository as Repository>::DateTime,
+            >,
+        >,
+    ),
+    Problem,
+> {
+    if !app.can_read_bid(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_bid_owner());
+    }
+
+    let before = query.before.clone();
+    let outcomes = app
+        .database()
+        .get_bidder_outcomes(bidder_id, query, limit.page_size(&config))
+        .await
+        .map_err(Problem::internal)?;
+
+    // Outcomes of a bidder are confidential, so are never cached publicly
+    let cache_control = cache::outcomes(before.as_ref(), &app.now(), &outcomes.results, false);
+    Ok((TypedHeader(cache_control), accept.respond(outcomes)))
+}
@@ -0,0 +1,118 @@
+//! HTTP caching of read-only responses.
+//!
+//! Every successful JSON response to a `GET` request is tagged with an `ETag`
+//! derived from its body, and conditional requests (`If-None-Match`) whose tag
+//! still matches are answered with `304 Not Modified` and no body. Endpoints
+//! additionally declare how long their responses may be reused: outcomes of
+//! past batches never change, and so may be cached indefinitely, while other
+//! records must be revalidated before reuse.
+
+use crate::problem::Problem;
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::{
+        Method, StatusCode,
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use fts_core::models::ValueRecord;
+use headers::{CacheControl, ETag, HeaderMapExt as _, IfNoneMatch};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    time::Duration,
+};
+
+/// How long immutable responses may be cached, i.e. a year (the conventional maximum)
+const IMMUTABLE_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Responses which may change and so must be revalidated before reuse.
+pub(crate) fn revalidate(public: bool) -> CacheControl {
+    scope(CacheControl::new().with_no_cache(), public)
+}
+
+/// Responses which will never change.
+pub(crate) fn immutable(public: bool) -> CacheControl {
+    scope(
+        CacheControl::new()
+            .with_max_age(IMMUTABLE_MAX_AGE)
+            .with_immutable(),
+        public,
+    )
+}
+
+/// The policy for a page of outcomes, which is immutable if its interval has
+/// ended (so that no new batches can be added to it) and every outcome in it
+/// has been superseded (so that no outcome's interval can change).
+pub(crate) fn outcomes<DateTime: PartialOrd, T>(
+    before: Option<&DateTime>,
+    now: &DateTime,
+    results: &[ValueRecord<DateTime, T>],
+    public: bool,
+) -> CacheControl {
+    if before.is_some_and(|before| before <= now)
+        && results.iter().all(|record| record.valid_until.is_some())
+    {
+        immutable(public)
+    } else {
+        revalidate(public)
+    }
+}
+
+/// Mark the response as storable by shared caches, or only by the client
+fn scope(cache_control: CacheControl, public: bool) -> CacheControl {
+    if public {
+        cache_control.with_public()
+    } else {
+        cache_control.with_private()
+    }
+}
+
+/// Middleware tagging JSON responses with an `ETag`, answering conditional
+/// requests with `304 Not Modified` when the tag matches.
+pub(crate) async fn etag(request: Request, next: Next) -> Response {
+    let conditional = matches!(*request.method(), Method::GET | Method::HEAD)
+        .then(|| request.headers().typed_get::<IfNoneMatch>());
+    let response = next.run(request).await;
+
+    let Some(if_none_match) = conditional else {
+        return response;
+    };
+    // Event streams never end, so we must not attempt to buffer them
+    if response.status() != StatusCode::OK || !is_json(&response) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => return Problem::internal(err).into_response(),
+    };
+
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    // The tag is weak, as the body may yet be compressed on its way out
+    let etag: ETag = format!("W/\"{:016x}\"", hasher.finish())
+        .parse()
+        .expect("hex digits form a valid entity tag");
+    parts.headers.typed_insert(etag.clone());
+
+    if if_none_match.is_some_and(|if_none_match| !if_none_match.precondition_passes(&etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_TYPE);
+        parts.headers.remove(CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
@@ -0,0 +1,30 @@
+//! Compression of response bodies.
+
+use crate::config::CompressionConfig;
+use axum::http::{Extensions, HeaderMap, StatusCode, Version, header::CONTENT_TYPE};
+use tower_http::compression::{CompressionLayer, Predicate, predicate::SizeAbove};
+
+/// Construct the layer compressing responses according to the configuration
+pub(crate) fn layer(config: &CompressionConfig) -> CompressionLayer<impl Predicate + use<>> {
+    let content_types = config.content_types.clone();
+    let predicate = SizeAbove::new(config.min_size).and(
+        move |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+            let Some(content_type) = headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+            else {
+                return false;
+            };
+            // Ignore any parameters, such as the charset
+            let essence = content_type.split(';').next().unwrap_or_default().trim();
+            content_types
+                .iter()
+                .any(|content_type| content_type.eq_ignore_ascii_case(essence))
+        },
+    );
+
+    CompressionLayer::new()
+        .gzip(config.gzip)
+        .br(config.brotli)
+        .compress_when(predicate)
+}
@@ -1,18 +1,20 @@
 //! Configuration types for the Axum HTTP server.
 //!
 //! This module provides configuration options for the REST API server,
-//! including network binding and pagination settings.
+//! including network binding, pagination settings, response compression, and
+//! the API versions to serve.
 
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
+use time::OffsetDateTime;
 
 /// Configuration for the Axum HTTP server.
 ///
 /// # Examples
 ///
 /// ```
-/// use fts_axum::config::AxumConfig;
-/// use std::net::SocketAddr;
+/// use fts_axum::config::{ApiVersion, AxumConfig, CompressionConfig, VersionConfig};
+/// use std::{net::SocketAddr, time::Duration};
 ///
 /// // Use default configuration
 /// let config = AxumConfig::default();
@@ -21,7 +23,16 @@ use std::net::SocketAddr;
 /// let config = AxumConfig {
 ///     bind_address: "127.0.0.1:3000".parse().unwrap(),
 ///     page_limit: 50,
+///     max_page_limit: 1000,
 ///     auto_solve: false,
+///     solve_debounce: Duration::from_millis(100),
+///     warm_start: true,
+///     time_unit: 3600.0,
+///     poll_interval: Duration::from_secs(1),
+///     max_long_poll: Duration::from_secs(60),
+///     public_cache: false,
+///     compression: CompressionConfig::default(),
+///     versions: vec![VersionConfig::new("/v1", ApiVersion::V1)],
 /// };
 /// ```
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -30,13 +41,170 @@ pub struct AxumConfig {
     #[serde(default = "default_bind_address")]
     pub bind_address: SocketAddr,
 
-    /// The page limit for paginated responses
+    /// The page limit for paginated responses, unless the client requests
+    /// another (using the `limit` query parameter)
     #[serde(default = "default_page_limit")]
     pub page_limit: usize,
 
+    /// The largest page limit a client may request
+    #[serde(default = "default_max_page_limit")]
+    pub max_page_limit: usize,
+
     /// A flag that, if true, will execute a full auction solve on every bid update
     #[serde(default)]
     pub auto_solve: bool,
+
+    /// When `auto_solve` is enabled, how long to wait after a bid update
+    /// before solving. Updates received in the meantime are coalesced into
+    /// a single auction.
+    #[serde(default = "default_solve_debounce", with = "humantime_serde")]
+    pub solve_debounce: Duration,
+
+    /// When `auto_solve` is enabled, whether each batch is warm-started from
+    /// the outcomes of the batch solved before it (for the solvers able to
+    /// make use of them), rather than solved afresh
+    #[serde(default = "default_true")]
+    pub warm_start: bool,
+
+    /// The length, in seconds, of the unit of time in which trade rates are
+    /// expressed. This is used when reporting unsettled activity and traded volume.
+    #[serde(default = "default_time_unit")]
+    pub time_unit: f64,
+
+    /// How often event streams and long-polling requests poll the repository
+    /// for new batch outcomes
+    #[serde(default = "default_poll_interval", with = "humantime_serde")]
+    pub poll_interval: Duration,
+
+    /// The longest a long-polling request may wait for a batch to complete.
+    /// This is also the wait if the client does not request a `timeout`.
+    #[serde(default = "default_max_long_poll", with = "humantime_serde")]
+    pub max_long_poll: Duration,
+
+    /// Whether responses concerning products (such as their outcomes) may be
+    /// stored by shared caches, e.g. a CDN. Such caches may serve a response
+    /// to requests lacking the permissions required to obtain it, so this
+    /// should only be enabled if products are not confidential. Responses
+    /// concerning bids are only ever cached privately.
+    #[serde(default)]
+    pub public_cache: bool,
+
+    /// How response bodies are compressed
+    #[serde(default)]
+    pub compression: CompressionConfig,
+
+    /// The API versions to serve, each under its own path prefix. By default,
+    /// only the current version is served, under `/v1`.
+    #[serde(default = "default_versions")]
+    pub versions: Vec<VersionConfig>,
+}
+
+/// Configuration for compressing response bodies.
+///
+/// A response is compressed using the best encoding the client accepts (per
+/// its `Accept-Encoding` header) among those enabled, provided its content
+/// type is listed and its body is large enough to benefit. Event streams are
+/// never compressed, as doing so would delay the delivery of events.
+///
+/// ```
+/// use fts_axum::config::CompressionConfig;
+///
+/// // Only compress large JSON responses, and only with gzip
+/// let config = CompressionConfig {
+///     brotli: false,
+///     min_size: 4096,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompressionConfig {
+    /// Whether to compress with gzip
+    #[serde(default = "default_true")]
+    pub gzip: bool,
+
+    /// Whether to compress with brotli
+    #[serde(default = "default_true")]
+    pub brotli: bool,
+
+    /// Responses whose bodies are smaller than this many bytes are not compressed
+    #[serde(default = "default_min_size")]
+    pub min_size: u16,
+
+    /// The content types to compress, e.g. `application/json`. Parameters
+    /// (such as the charset) are ignored when matching.
+    #[serde(default = "default_content_types")]
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            gzip: default_true(),
+            brotli: default_true(),
+            min_size: default_min_size(),
+            content_types: default_content_types(),
+        }
+    }
+}
+
+/// The revisions of the REST API this crate is able to serve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    /// The first (and current) revision of the API
+    V1,
+}
+
+/// Configuration for serving a revision of the API under a path prefix.
+///
+/// The same revision may be mounted under several prefixes, e.g. to keep
+/// serving an old prefix while clients migrate. Setting any of `deprecated`,
+/// `sunset`, or `successor` will attach the corresponding headers (RFC 9745,
+/// RFC 8594, and RFC 8288 respectively) to every response under the prefix.
+///
+/// ```
+/// use fts_axum::config::{ApiVersion, VersionConfig};
+/// use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+///
+/// let legacy = VersionConfig {
+///     deprecated: Some(OffsetDateTime::parse("2025-06-01T00:00:00Z", &Rfc3339).unwrap()),
+///     successor: Some("/v1".to_owned()),
+///     ..VersionConfig::new("", ApiVersion::V1)
+/// };
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VersionConfig {
+    /// The path prefix to mount the API under, e.g. `/v1`. An empty prefix
+    /// mounts the API at the root.
+    pub prefix: String,
+
+    /// The revision of the API to serve
+    pub api: ApiVersion,
+
+    /// If set, the time at which this prefix was deprecated
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub deprecated: Option<OffsetDateTime>,
+
+    /// If set, the time after which this prefix will stop being served
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub sunset: Option<OffsetDateTime>,
+
+    /// If set, the location of the version superseding this one
+    #[serde(default)]
+    pub successor: Option<String>,
+}
+
+impl VersionConfig {
+    /// Serve the revision `api` under `prefix`, without any deprecation notice.
+    pub fn new(prefix: impl Into<String>, api: ApiVersion) -> Self {
+        Self {
+            prefix: prefix.into(),
+            api,
+            deprecated: None,
+            sunset: None,
+            successor: None,
+        }
+    }
 }
 
 fn default_bind_address() -> SocketAddr {
@@ -47,12 +215,73 @@ fn default_page_limit() -> usize {
     100
 }
 
+fn default_max_page_limit() -> usize {
+    1000
+}
+
+fn default_solve_debounce() -> Duration {
+    Duration::from_millis(100)
+}
+
+fn default_time_unit() -> f64 {
+    3600.0
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_long_poll() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_min_size() -> u16 {
+    1024
+}
+
+fn default_content_types() -> Vec<String> {
+    vec![
+        "application/json".to_owned(),
+        "application/problem+json".to_owned(),
+        "text/csv".to_owned(),
+    ]
+}
+
+fn default_versions() -> Vec<VersionConfig> {
+    vec![VersionConfig::new("/v1", ApiVersion::V1)]
+}
+
+impl AxumConfig {
+    /// The page limit to use for a request, i.e. the `limit` requested by the
+    /// client (or `page_limit` if none was), bounded by `max_page_limit`.
+    /// Pages always have room for at least one result.
+    pub fn page_size(&self, limit: Option<usize>) -> usize {
+        limit
+            .unwrap_or(self.page_limit)
+            .min(self.max_page_limit)
+            .max(1)
+    }
+}
+
 impl Default for AxumConfig {
     fn default() -> Self {
         Self {
             bind_address: default_bind_address(),
             page_limit: default_page_limit(),
+            max_page_limit: default_max_page_limit(),
             auto_solve: Default::default(),
+            solve_debounce: default_solve_debounce(),
+            warm_start: default_true(),
+            time_unit: default_time_unit(),
+            poll_interval: default_poll_interval(),
+            max_long_poll: default_max_long_poll(),
+            public_cache: Default::default(),
+            compression: Default::default(),
+            versions: default_versions(),
         }
     }
 }
@@ -6,22 +6,32 @@
 
 use crate::{
     ApiApplication,
-    config::{self, AxumConfig},
+    as_of::AsOfQuery,
+    auth::Auth,
+    batch_queue::BatchQueue,
+    config,
+    fields::{FieldsQuery, Sparse},
+    limit::LimitQuery,
+    negotiate::{Accept, Negotiated, next_link},
+    problem::Problem,
+};
+use aide::axum::{
+    ApiRouter,
+    routing::{get, post},
 };
-use aide::axum::{ApiRouter, routing::get};
 use axum::{
     Extension, Json,
-    extract::{Path, Query, State},
+    extract::{Path, Query, RawQuery, State, rejection::JsonRejection},
     http::StatusCode,
 };
-use axum_extra::TypedHeader;
 use fts_core::{
-    models::{DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandRecord},
-    ports::{BatchRepository as _, DemandRepository as _, Repository},
+    models::{
+        CurveValidation, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandCurveDto,
+        DemandQuery, DemandRecord, Tombstone,
+    },
+    ports::{Application, DemandRepository, Repository},
 };
-use headers::{Authorization, authorization::Bearer};
 use std::sync::Arc;
-use tracing::{Level, event};
 
 /// Creates a router with demand-related endpoints.
 pub fn router<T: ApiApplication>() -> ApiRouter<T> {
@@ -31,6 +41,9 @@ pub fn router<T: ApiApplication>() -> ApiRouter<T> {
             get(query_demands::<T>).post(create_demand::<T>),
             |route| route.security_requirement("jwt").tag("demand"),
         )
+        .api_route_with("/validate", post(validate_demand::<T>), |route| {
+            route.security_requirement("jwt").tag("demand")
+        })
         .api_route_with(
             "/{demand_id}",
             get(get_demand::<T>)
@@ -48,6 +61,9 @@ pub fn router<T: ApiApplication>() -> ApiRouter<T> {
                     .tag("history")
             },
         )
+        .api_route_with("/{demand_id}/purge", post(purge_demand::<T>), |route| {
+            route.security_requirement("jwt").tag("demand").tag("admin")
+        })
 }
 
 /// Path parameter for demand-specific endpoints.
@@ -58,8 +74,33 @@ struct Id<T> {
     demand_id: T,
 }
 
+/// The parameters of `DemandQuery`, which the link to the next page replaces
+const DEMAND_QUERY: &[&str] = &["has_curve", "include_curve", "touched_since", "after"];
+
+/// Query parameters filtering and paginating the listing of demands.
+type ListFilter<T> = DemandQuery<
+    <<T as Application>::Repository as Repository>::DemandId,
+    <<T as Application>::Repository as Repository>::DateTime,
+>;
+
+/// Query parameters for listing demands.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+struct ListQuery<T> {
+    /// Only return demands belonging to a portfolio whose basis references this product
+    product_id: Option<T>,
+}
+
 /// Query all demands for bidders the requester is authorized to view.
 ///
+/// By default, only the demands with a curve are returned, along with the
+/// curve, a page at a time in order of their id. The next page, if any, is
+/// linked by the `Link` header, with `rel="next"`.
+///
+/// If `product_id` is specified, all the demands belonging to a portfolio
+/// whose basis references the product are instead returned at once, and the
+/// filters, pagination, and `include_curve` do not apply.
+///
 /// # Authorization
 ///
 /// Returns demands only for bidders that the context has query access to
@@ -67,24 +108,58 @@ struct Id<T> {
 ///
 /// # Returns
 ///
-/// - `200 OK`: List of demand IDs
+/// - `200 OK`: List of demands
+/// - `400 Bad Request`: Filters or pagination requested for a product
 /// - `401 Unauthorized`: No query permissions for any bidder
 /// - `500 Internal Server Error`: Database query failed
+#[allow(clippy::too_many_arguments)]
 async fn query_demands<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-) -> Result<Json<Vec<DemandRecord<T::Repository, T::DemandData>>>, StatusCode> {
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<config::AxumConfig>>,
+    Query(ListQuery { product_id }): Query<ListQuery<<T::Repository as Repository>::ProductId>>,
+    Query(mut query): Query<ListFilter<T>>,
+    Query(limit): Query<LimitQuery>,
+    Query(fields): Query<FieldsQuery>,
+    RawQuery(raw): RawQuery,
+) -> Result<Sparse<Vec<DemandRecord<T::Repository, T::DemandData>>>, Problem> {
     let db = app.database();
     let bidder_ids = app.can_query_bid(&auth).await;
 
     if bidder_ids.is_empty() {
-        Err(StatusCode::UNAUTHORIZED)
-    } else {
-        Ok(Json(db.query_demand(&bidder_ids).await.map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?))
+        return Err(Problem::not_authorized());
+    }
+
+    if let Some(product_id) = product_id {
+        if !query.has_curve
+            || !query.include_curve
+            || query.touched_since.is_some()
+            || query.after.is_some()
+            || limit.is_requested()
+        {
+            return Err(Problem::new(StatusCode::BAD_REQUEST, "query_invalid")
+                .with_detail("the listing options do not apply to the demands of a product"));
+        }
+        let demands = db
+            .query_demand_by_product(&bidder_ids, product_id)
+            .await
+            .map_err(Problem::internal)?;
+        return Ok(fields.apply(demands));
     }
+
+    // There is no need to read the curves if they are not to be returned
+    query.include_curve &= fields.includes("curve_data");
+    let page = db
+        .query_demand(&bidder_ids, query, limit.page_size(&config))
+        .await
+        .map_err(Problem::internal)?;
+    let next = page
+        .more
+        .map(|more| next_link(raw.as_deref(), DEMAND_QUERY, &more))
+        .transpose()
+        .map_err(Problem::internal)?;
+
+    Ok(fields.apply(page.results).with_next(next))
 }
 
 /// Create a new demand with optional initial curve data.
@@ -101,23 +176,64 @@ async fn query_demands<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database operation failed
 async fn create_demand<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-    Json(body): Json<CreateDemandDto<T::DemandData>>,
-) -> Result<(StatusCode, Json<DemandRecord<T::Repository, T::DemandData>>), StatusCode> {
+    Auth(auth): Auth<T>,
+    body: Result<Json<CreateDemandDto<T::DemandData>>, JsonRejection>,
+) -> Result<(StatusCode, Json<DemandRecord<T::Repository, T::DemandData>>), Problem> {
+    let Json(body) = body.map_err(Problem::curve_invalid)?;
     let db = app.database();
     let (demand_id, as_of) = app.generate_demand_id(&body.app_data);
     let bidder_id = app
         .can_create_bid(&auth)
         .await
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or_else(Problem::not_authorized)?;
+    let actor = app.actor(&auth, bidder_id.clone()).await;
 
-    db.create_demand(demand_id, bidder_id, body.app_data, body.curve_data, as_of)
-        .await
-        .map(|demand| (StatusCode::CREATED, Json(demand)))
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+    db.create_demand(
+        demand_id,
+        bidder_id,
+        body.app_data,
+        body.curve_data,
+        actor,
+        as_of,
+    )
+    .await
+    .map(|demand| (StatusCode::CREATED, Json(demand)))
+    .map_err(Problem::internal)
+}
+
+/// Validate a demand curve without creating anything.
+///
+/// Every problem with the curve is reported, rather than only the first,
+/// along with warnings of points that are accepted but have no effect and any
+/// diagnostics from the rules of the market. Each diagnostic carries a stable
+/// code and, where applicable, the index of the offending point.
+///
+/// # Authorization
+///
+/// Requires create permission (`can_create_bid`).
+///
+/// # Returns
+///
+/// - `200 OK`: The diagnostics of the curve, whether or not it is valid
+/// - `401 Unauthorized`: Missing create permissions
+/// - `422 Unprocessable Entity`: The body is not shaped like a curve
+async fn validate_demand<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    body: Result<Json<ValidateDemandDto>, JsonRejection>,
+) -> Result<Json<CurveValidation>, Problem> {
+    let Json(ValidateDemandDto(curve)) = body.map_err(Problem::curve_invalid)?;
+    if app.can_create_bid(&auth).await.is_none() {
+        return Err(Problem::not_authorized());
+    }
+
+    // The rules of the market only apply to structurally valid curves
+    let mut diagnostics = curve.diagnose();
+    if let Ok(curve) = DemandCurve::try_from(curve) {
+        diagnostics.extend(app.diagnose_curve(&auth, &curve).await);
+    }
+
+    Ok(Json(diagnostics.into_iter().collect()))
 }
 
 /// Retrieve a demand's current state.
@@ -127,7 +243,8 @@ async fn create_demand<T: ApiApplication>(
 ///
 /// # Authorization
 ///
-/// Requires read permission for the demand's bidder (`can_read_bid`).
+/// Requires read permission for the demand's bidder (`can_read_bid`), and
+/// `can_audit` permission to read the demand as of another time.
 ///
 /// # Returns
 ///
@@ -137,24 +254,23 @@ async fn create_demand<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database query failed
 async fn get_demand<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { demand_id }): Path<Id<<T::Repository as Repository>::DemandId>>,
-) -> Result<Json<DemandRecord<T::Repository, T::DemandData>>, StatusCode> {
-    let as_of = app.now();
+    Query(as_of): Query<AsOfQuery<<T::Repository as Repository>::DateTime>>,
+    Query(fields): Query<FieldsQuery>,
+) -> Result<Sparse<DemandRecord<T::Repository, T::DemandData>>, Problem> {
+    let as_of = as_of.resolve(&app, &auth).await?;
     let db = app.database();
     let demand = db
         .get_demand(demand_id, as_of)
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("demand_not_found"))?;
 
     if app.can_read_bid(&auth, demand.bidder_id.clone()).await {
-        Ok(Json(demand))
+        Ok(fields.apply(demand))
     } else {
-        Err(StatusCode::UNAUTHORIZED)
+        Err(Problem::not_bid_owner())
     }
 }
 
@@ -175,11 +291,12 @@ async fn get_demand<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database operation failed
 async fn update_demand<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { demand_id }): Path<Id<<T::Repository as Repository>::DemandId>>,
-    Extension(config): Extension<Arc<AxumConfig>>,
-    Json(body): Json<DemandCurve>,
-) -> Result<Json<DemandRecord<T::Repository, T::DemandData>>, StatusCode> {
+    Extension(queue): Extension<BatchQueue<T>>,
+    body: Result<Json<DemandCurve>, JsonRejection>,
+) -> Result<Json<DemandRecord<T::Repository, T::DemandData>>, Problem> {
+    let Json(body) = body.map_err(Problem::curve_invalid)?;
     let as_of = app.now();
     let db = app.database();
 
@@ -187,48 +304,21 @@ async fn update_demand<T: ApiApplication>(
     let bidder_id = db
         .get_demand_bidder_id(demand_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("demand_not_found"))?;
 
-    if !app.can_update_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+    if !app.can_update_bid(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_bid_owner());
     }
+    let actor = app.actor(&auth, bidder_id).await;
 
     let updated = db
-        .update_demand(demand_id, body, as_of.clone())
+        .update_demand(demand_id, body, actor, as_of.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or_else(|| {
-            event!(
-                Level::ERROR,
-                err = "failed to update demand after successful read"
-            );
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    if config.auto_solve {
-        tokio::spawn(async move {
-            let db = app.database();
-            let result = db.run_batch(as_of, app.solver(), Default::default()).await;
-
-            match result {
-                Err(err) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                Ok(Err(err)) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                _ => {}
-            };
-        });
-    };
+        .map_err(Problem::internal)?
+        .ok_or_else(|| Problem::internal("failed to update demand after successful read"))?;
+
+    queue.notify(as_of);
 
     Ok(Json(updated))
 }
@@ -250,10 +340,10 @@ async fn update_demand<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database operation failed
 async fn delete_demand<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { demand_id }): Path<Id<<T::Repository as Repository>::DemandId>>,
-    Extension(config): Extension<Arc<AxumConfig>>,
-) -> Result<Json<DemandRecord<T::Repository, T::DemandData>>, StatusCode> {
+    Extension(queue): Extension<BatchQueue<T>>,
+) -> Result<Json<DemandRecord<T::Repository, T::DemandData>>, Problem> {
     let as_of = app.now();
     let db = app.database();
 
@@ -261,47 +351,21 @@ async fn delete_demand<T: ApiApplication>(
     let bidder_id = db
         .get_demand_bidder_id(demand_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    if !app.can_update_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("demand_not_found"))?;
+
+    if !app.can_update_bid(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_bid_owner());
     }
+    let actor = app.actor(&auth, bidder_id).await;
 
     let deleted = db
-        .update_demand(demand_id, DemandCurve::None, as_of.clone())
+        .update_demand(demand_id, DemandCurve::None, actor, as_of.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or_else(|| {
-            event!(
-                Level::ERROR,
-                err = "failed to delete demand after successful read"
-            );
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    if config.auto_solve {
-        tokio::spawn(async move {
-            let db = app.database();
-            let result = db.run_batch(as_of, app.solver(), Default::default()).await;
-
-            match result {
-                Err(err) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                Ok(Err(err)) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                _ => {}
-            };
-        });
-    };
+        .map_err(Problem::internal)?
+        .ok_or_else(|| Problem::internal("failed to delete demand after successful read"))?;
+
+    queue.notify(as_of);
 
     Ok(Json(deleted))
 }
@@ -311,6 +375,9 @@ async fn delete_demand<T: ApiApplication>(
 /// Returns a paginated list of curve changes over time, including when
 /// the demand was created, updated, or deleted (curve set to None).
 ///
+/// A CSV request without a `limit` exports every change in the range as a
+/// single table, streamed as it is read rather than paginated.
+///
 /// # Authorization
 ///
 /// Requires read permission for the demand's bidder (`can_read_bid`).
@@ -323,13 +390,15 @@ async fn delete_demand<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database query failed
 async fn get_demand_curve_history<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { demand_id }): Path<Id<<T::Repository as Repository>::DemandId>>,
     Extension(config): Extension<Arc<config::AxumConfig>>,
     Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
 ) -> Result<
-    Json<DateTimeRangeResponse<DemandCurve, <T::Repository as Repository>::DateTime>>,
-    StatusCode,
+    Negotiated<DateTimeRangeResponse<DemandCurve, <T::Repository as Repository>::DateTime>>,
+    Problem,
 > {
     let db = app.database();
 
@@ -337,28 +406,75 @@ async fn get_demand_curve_history<T: ApiApplication>(
     let bidder_id = db
         .get_demand_bidder_id(demand_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("demand_not_found"))?;
 
     if !app.can_read_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(Problem::not_bid_owner());
+    }
+
+    if accept.is_csv() && !limit.is_requested() {
+        return Ok(accept.stream(move |sender| async move {
+            let history = app.database().stream_demand_curve_history(demand_id, query);
+            sender.forward(history).await
+        }));
     }
+
     let history = db
-        .get_demand_curve_history(demand_id, query, config.page_limit)
+        .get_demand_curve_history(demand_id, query, limit.page_size(&config))
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
+        .map_err(Problem::internal)?;
+
+    Ok(accept.respond(history))
+}
 
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+/// Permanently erase a demand's application data and curve history.
+///
+/// Intended for requests for erasure, where soft-deleting the demand would
+/// retain the bidder's data indefinitely. Only a tombstone remains, after
+/// which the demand is treated as though it does not exist.
+///
+/// # Authorization
+///
+/// Requires purge permission (`can_purge_bid`), which applies to the demands
+/// of every bidder.
+///
+/// # Returns
+///
+/// - `200 OK`: Demand purged successfully, returns the tombstone
+/// - `401 Unauthorized`: Missing purge permissions
+/// - `404 Not Found`: Demand does not exist (or was already purged)
+/// - `500 Internal Server Error`: Database operation failed
+async fn purge_demand<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { demand_id }): Path<Id<<T::Repository as Repository>::DemandId>>,
+    Extension(queue): Extension<BatchQueue<T>>,
+) -> Result<Json<Tombstone<T::Repository, <T::Repository as Repository>::DemandId>>, Problem> {
+    if !app.can_purge_bid(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = app.now();
+    let tombstone = <T::Repository as DemandRepository<T::DemandData>>::purge_demand(
+        app.database(),
+        demand_id,
+        as_of.clone(),
+    )
+    .await
+    .map_err(Problem::internal)?
+    .ok_or(Problem::not_found("demand_not_found"))?;
+
+    queue.notify(as_of);
 
-    Ok(Json(history))
+    Ok(Json(tombstone))
 }
 
+/// Request body for validating a demand curve, which need not be valid.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(transparent)]
+struct ValidateDemandDto(#[schemars(with = "DemandCurve")] DemandCurveDto);
+
 /// Request body for creating a new demand.
 #[derive(serde::Deserialize, schemars::JsonSchema)]
 #[schemars(inline)]
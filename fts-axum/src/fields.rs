@@ -0,0 +1,130 @@
+//! Sparse fieldsets for large responses.
+//!
+//! Records such as demands and portfolios can carry heavy fields (e.g. curve
+//! points or expanded bases) that a client may never display. Endpoints
+//! returning such records accept a `fields` query parameter, a comma-separated
+//! list of the top-level fields to include in each record. The `id` field is
+//! always included, and unknown fields are ignored.
+
+use crate::problem::Problem;
+use aide::{
+    OperationOutput,
+    generate::GenContext,
+    openapi::{Operation, Response},
+};
+use axum::{
+    Json,
+    http::{HeaderValue, header::LINK},
+    response::{IntoResponse, Response as AxumResponse},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Query parameter selecting the fields of the response.
+#[derive(Default, serde::Deserialize, JsonSchema)]
+#[schemars(inline)]
+pub(crate) struct FieldsQuery {
+    /// A comma-separated list of the top-level fields to include in each
+    /// record (all fields are included if omitted)
+    fields: Option<String>,
+}
+
+impl FieldsQuery {
+    /// Whether the response is to include the field.
+    pub(crate) fn includes(&self, field: &str) -> bool {
+        self.fields
+            .as_ref()
+            .is_none_or(|fields| fields.split(',').any(|requested| requested.trim() == field))
+    }
+
+    /// Wrap a response body, to be restricted to the requested fields.
+    pub(crate) fn apply<T>(self, value: T) -> Sparse<T> {
+        let fields = self.fields.map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(String::from)
+                .collect()
+        });
+        Sparse {
+            value,
+            fields,
+            next: None,
+        }
+    }
+}
+
+/// A JSON response body, optionally restricted to a subset of its fields.
+///
+/// For the purposes of the OpenAPI documentation, this is identical to `Json<T>`.
+pub(crate) struct Sparse<T> {
+    value: T,
+    fields: Option<Vec<String>>,
+    /// The `Link` header to the next page, if the response is a page of a
+    /// longer listing
+    next: Option<HeaderValue>,
+}
+
+impl<T> Sparse<T> {
+    /// Link the response to the next page of its listing.
+    pub(crate) fn with_next(mut self, next: Option<HeaderValue>) -> Self {
+        self.next = next;
+        self
+    }
+}
+
+impl<T: Serialize> IntoResponse for Sparse<T> {
+    fn into_response(self) -> AxumResponse {
+        let mut response = self.encode();
+        if let Some(next) = self.next {
+            response.headers_mut().append(LINK, next);
+        }
+        response
+    }
+}
+
+impl<T: Serialize> Sparse<T> {
+    fn encode(&self) -> AxumResponse {
+        let Some(fields) = &self.fields else {
+            return Json(&self.value).into_response();
+        };
+
+        match serde_json::to_value(&self.value) {
+            Ok(mut value) => {
+                match &mut value {
+                    serde_json::Value::Array(records) => {
+                        for record in records {
+                            retain(record, fields);
+                        }
+                    }
+                    record => retain(record, fields),
+                }
+                Json(value).into_response()
+            }
+            Err(err) => Problem::internal(err).into_response(),
+        }
+    }
+}
+
+/// Remove every field of the record not in `fields`, except for its id.
+fn retain(record: &mut serde_json::Value, fields: &[String]) {
+    if let serde_json::Value::Object(map) = record {
+        map.retain(|key, _| key == "id" || fields.iter().any(|field| field == key));
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Sparse<T> {
+    type Inner = T;
+
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<Response> {
+        Json::<T>::operation_response(ctx, operation)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, Response)> {
+        Json::<T>::inferred_responses(ctx, operation)
+    }
+}
@@ -0,0 +1,610 @@
+//! An optional GraphQL API for traversing the flow trading data.
+//!
+//! The REST API requires a request for every entity, which can be cumbersome
+//! when navigating the relationships between portfolios, demands, products,
+//! and their outcomes. This module exposes the same data as a read-only
+//! GraphQL schema, available at `/graphql` when the `graphql` feature is
+//! enabled.
+//!
+//! Authorization is enforced per field using the permission hooks of the
+//! [`Application`](fts_core::ports::Application): a field the caller is not
+//! permitted to view resolves to an error, while the rest of the query
+//! proceeds as usual. Identifiers, timestamps, and application data are
+//! exchanged using the `JSON` scalar, with the same representation as in the
+//! REST API.
+
+use crate::{ApiApplication, auth::Auth, config::AxumConfig};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Error, Json, Object, Result, Schema,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{Extension, extract::State};
+use fts_core::{
+    models::{
+        DateTimeRangeQuery, DemandQuery, DemandRecord, PortfolioQuery, PortfolioRecord,
+        PriceInterval, ProductRecord,
+    },
+    ports::{
+        BatchRepository, DemandRepository as _, PortfolioRepository as _, ProductRepository as _,
+        Repository, SettlementRepository as _,
+    },
+};
+use serde::Serialize;
+use std::{marker::PhantomData, sync::Arc};
+use tracing::{Level, event};
+
+type DateTime<T> = <<T as fts_core::ports::Application>::Repository as Repository>::DateTime;
+type BidderId<T> = <<T as fts_core::ports::Application>::Repository as Repository>::BidderId;
+type DemandId<T> = <<T as fts_core::ports::Application>::Repository as Repository>::DemandId;
+type PortfolioId<T> = <<T as fts_core::ports::Application>::Repository as Repository>::PortfolioId;
+type ProductId<T> = <<T as fts_core::ports::Application>::Repository as Repository>::ProductId;
+
+/// The GraphQL schema for an application.
+pub type ApiSchema<T> = Schema<QueryRoot<T>, EmptyMutation, EmptySubscription>;
+
+/// Construct the GraphQL schema for an application.
+pub fn schema<T: ApiApplication>() -> ApiSchema<T> {
+    Schema::build(
+        QueryRoot::<T>(PhantomData),
+        EmptyMutation,
+        EmptySubscription,
+    )
+    .finish()
+}
+
+/// Execute a GraphQL request on behalf of the authenticated client.
+pub(crate) async fn graphql_handler<T: ApiApplication>(
+    State(app): State<T>,
+    Extension(schema): Extension<ApiSchema<T>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Auth(auth): Auth<T>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let request = request.into_inner().data(app).data(auth).data(config);
+    schema.execute(request).await.into()
+}
+
+// Convenience accessors for the per-request data.
+
+fn app<'a, T: ApiApplication>(ctx: &Context<'a>) -> &'a T {
+    ctx.data_unchecked::<T>()
+}
+
+fn auth<'a, T: ApiApplication>(ctx: &Context<'a>) -> &'a T::Context {
+    ctx.data_unchecked::<T::Context>()
+}
+
+fn config<'a>(ctx: &Context<'a>) -> &'a AxumConfig {
+    ctx.data_unchecked::<Arc<AxumConfig>>()
+}
+
+fn unauthorized() -> Error {
+    Error::new("not authorized")
+}
+
+fn internal<E: std::error::Error>(err: E) -> Error {
+    event!(Level::ERROR, err = err.to_string());
+    Error::new("internal error")
+}
+
+fn json<V: Serialize>(value: &V) -> Result<Json<serde_json::Value>> {
+    Ok(Json(serde_json::to_value(value)?))
+}
+
+fn range<T: ApiApplication>(
+    before: Option<Json<DateTime<T>>>,
+    after: Option<Json<DateTime<T>>>,
+) -> DateTimeRangeQuery<DateTime<T>> {
+    DateTimeRangeQuery {
+        before: before.map(|x| x.0),
+        after: after.map(|x| x.0),
+    }
+}
+
+async fn require_products<T: ApiApplication>(ctx: &Context<'_>) -> Result<()> {
+    if app::<T>(ctx).can_view_products(auth::<T>(ctx)).await {
+        Ok(())
+    } else {
+        Err(unauthorized())
+    }
+}
+
+async fn require_bidder<T: ApiApplication>(
+    ctx: &Context<'_>,
+    bidder_id: &BidderId<T>,
+) -> Result<()> {
+    if app::<T>(ctx)
+        .can_read_bid(auth::<T>(ctx), bidder_id.clone())
+        .await
+    {
+        Ok(())
+    } else {
+        Err(unauthorized())
+    }
+}
+
+async fn require_settlement<T: ApiApplication>(
+    ctx: &Context<'_>,
+    bidder_id: &BidderId<T>,
+) -> Result<()> {
+    if app::<T>(ctx)
+        .can_read_settlement(auth::<T>(ctx), bidder_id.clone())
+        .await
+    {
+        Ok(())
+    } else {
+        Err(unauthorized())
+    }
+}
+
+async fn load_product<T: ApiApplication>(
+    ctx: &Context<'_>,
+    product_id: ProductId<T>,
+) -> Result<Option<ProductNode<T>>> {
+    require_products::<T>(ctx).await?;
+    let app = app::<T>(ctx);
+    let record = app
+        .database()
+        .get_product(product_id, app.now())
+        .await
+        .map_err(internal)?;
+    Ok(record.map(ProductNode))
+}
+
+async fn load_demand<T: ApiApplication>(
+    ctx: &Context<'_>,
+    demand_id: DemandId<T>,
+) -> Result<Option<DemandNode<T>>> {
+    let app = app::<T>(ctx);
+    let Some(record) = app
+        .database()
+        .get_demand(demand_id, app.now())
+        .await
+        .map_err(internal)?
+    else {
+        return Ok(None);
+    };
+    require_bidder::<T>(ctx, &record.bidder_id).await?;
+    Ok(Some(DemandNode(record)))
+}
+
+async fn load_portfolio<T: ApiApplication>(
+    ctx: &Context<'_>,
+    portfolio_id: PortfolioId<T>,
+    expand: bool,
+) -> Result<Option<PortfolioNode<T>>> {
+    let app = app::<T>(ctx);
+    let db = app.database();
+    let Some(record) = if expand {
+        db.get_portfolio_with_expanded_products(portfolio_id, app.now())
+            .await
+    } else {
+        db.get_portfolio(portfolio_id, app.now()).await
+    }
+    .map_err(internal)?
+    else {
+        return Ok(None);
+    };
+    require_bidder::<T>(ctx, &record.bidder_id).await?;
+    Ok(Some(PortfolioNode(record)))
+}
+
+/// Authorize reading the bids of each of `bidder_ids`, checking each bidder
+/// only once however many of the bids they own.
+async fn require_bidders<T: ApiApplication>(
+    ctx: &Context<'_>,
+    bidder_ids: impl IntoIterator<Item = &BidderId<T>>,
+) -> Result<()> {
+    let mut checked: Vec<BidderId<T>> = Vec::new();
+    for bidder_id in bidder_ids {
+        if !checked.contains(bidder_id) {
+            checked.push(bidder_id.clone());
+        }
+    }
+    for bidder_id in &checked {
+        require_bidder::<T>(ctx, bidder_id).await?;
+    }
+    Ok(())
+}
+
+/// Load several demands, looking up (and authorizing) their bidders all at
+/// once rather than per demand. Demands which do not exist are omitted.
+async fn load_demands<T: ApiApplication>(
+    ctx: &Context<'_>,
+    demand_ids: Vec<DemandId<T>>,
+) -> Result<Vec<DemandNode<T>>> {
+    let app = app::<T>(ctx);
+    let db = app.database();
+    let bidder_ids = db
+        .get_demand_bidder_ids(&demand_ids)
+        .await
+        .map_err(internal)?;
+    require_bidders::<T>(ctx, bidder_ids.values()).await?;
+
+    let mut demands = Vec::with_capacity(bidder_ids.len());
+    for demand_id in demand_ids {
+        if !bidder_ids.contains_key(&demand_id) {
+            continue;
+        }
+        if let Some(record) = db
+            .get_demand(demand_id, app.now())
+            .await
+            .map_err(internal)?
+        {
+            demands.push(DemandNode(record));
+        }
+    }
+    Ok(demands)
+}
+
+/// Load several portfolios, looking up (and authorizing) their bidders all at
+/// once rather than per portfolio. Portfolios which do not exist are omitted.
+async fn load_portfolios<T: ApiApplication>(
+    ctx: &Context<'_>,
+    portfolio_ids: Vec<PortfolioId<T>>,
+) -> Result<Vec<PortfolioNode<T>>> {
+    let app = app::<T>(ctx);
+    let db = app.database();
+    let bidder_ids = db
+        .get_portfolio_bidder_ids(&portfolio_ids)
+        .await
+        .map_err(internal)?;
+    require_bidders::<T>(ctx, bidder_ids.values()).await?;
+
+    let mut portfolios = Vec::with_capacity(bidder_ids.len());
+    for portfolio_id in portfolio_ids {
+        if !bidder_ids.contains_key(&portfolio_id) {
+            continue;
+        }
+        if let Some(record) = db
+            .get_portfolio(portfolio_id, app.now())
+            .await
+            .map_err(internal)?
+        {
+            portfolios.push(PortfolioNode(record));
+        }
+    }
+    Ok(portfolios)
+}
+
+/// The width of the buckets into which outcome history is aggregated.
+#[derive(async_graphql::Enum, Clone, Copy, PartialEq, Eq, Default)]
+#[graphql(name = "PriceInterval")]
+enum Interval {
+    /// Aggregate the outcomes of each (UTC) hour
+    #[default]
+    Hour,
+    /// Aggregate the outcomes of each (UTC) day
+    Day,
+}
+
+impl From<Interval> for PriceInterval {
+    fn from(value: Interval) -> Self {
+        match value {
+            Interval::Hour => PriceInterval::Hour,
+            Interval::Day => PriceInterval::Day,
+        }
+    }
+}
+
+/// The entry points of the GraphQL schema.
+pub struct QueryRoot<T>(PhantomData<T>);
+
+#[Object(name = "Query")]
+impl<T: ApiApplication> QueryRoot<T> {
+    /// Retrieve a product by id (requires product view permissions).
+    async fn product(
+        &self,
+        ctx: &Context<'_>,
+        product_id: Json<ProductId<T>>,
+    ) -> Result<Option<ProductNode<T>>> {
+        load_product::<T>(ctx, product_id.0).await
+    }
+
+    /// Retrieve a demand by id (requires read permissions for its bidder).
+    async fn demand(
+        &self,
+        ctx: &Context<'_>,
+        demand_id: Json<DemandId<T>>,
+    ) -> Result<Option<DemandNode<T>>> {
+        load_demand::<T>(ctx, demand_id.0).await
+    }
+
+    /// Retrieve a portfolio by id (requires read permissions for its bidder),
+    /// optionally expressing its basis in the contemporary product basis.
+    async fn portfolio(
+        &self,
+        ctx: &Context<'_>,
+        portfolio_id: Json<PortfolioId<T>>,
+        #[graphql(default)] expand: bool,
+    ) -> Result<Option<PortfolioNode<T>>> {
+        load_portfolio::<T>(ctx, portfolio_id.0, expand).await
+    }
+
+    /// Retrieve a page of the demands of a bidder, in order of their id,
+    /// beginning just after `after` if given. By default, only the demands
+    /// with a curve are included.
+    async fn demands(
+        &self,
+        ctx: &Context<'_>,
+        bidder_id: Json<BidderId<T>>,
+        #[graphql(default = true)] has_curve: bool,
+        touched_since: Option<Json<DateTime<T>>>,
+        after: Option<Json<DemandId<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Vec<DemandNode<T>>> {
+        require_bidder::<T>(ctx, &bidder_id.0).await?;
+        // The curves need only be read if they were asked for
+        let query = DemandQuery {
+            has_curve,
+            include_curve: ctx.look_ahead().field("curveData").exists(),
+            touched_since: touched_since.map(|json| json.0),
+            after: after.map(|json| json.0),
+        };
+        let page = app::<T>(ctx)
+            .database()
+            .query_demand(&[bidder_id.0], query, config(ctx).page_size(limit))
+            .await
+            .map_err(internal)?;
+        Ok(page.results.into_iter().map(DemandNode).collect())
+    }
+
+    /// Retrieve a page of the portfolios of a bidder, in order of their id,
+    /// beginning just after `after` if given. By default, only the portfolios
+    /// with non-empty demand or product groups are included.
+    #[allow(clippy::too_many_arguments)]
+    async fn portfolios(
+        &self,
+        ctx: &Context<'_>,
+        bidder_id: Json<BidderId<T>>,
+        #[graphql(default = true)] active_only: bool,
+        #[graphql(default)] has_curve: bool,
+        touched_since: Option<Json<DateTime<T>>>,
+        after: Option<Json<PortfolioId<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Vec<PortfolioNode<T>>> {
+        require_bidder::<T>(ctx, &bidder_id.0).await?;
+        let query = PortfolioQuery {
+            active_only,
+            has_curve,
+            touched_since: touched_since.map(|json| json.0),
+            after: after.map(|json| json.0),
+        };
+        let page = app::<T>(ctx)
+            .database()
+            .query_portfolio(&[bidder_id.0], query, config(ctx).page_size(limit))
+            .await
+            .map_err(internal)?;
+        Ok(page.results.into_iter().map(PortfolioNode).collect())
+    }
+
+    /// The settlement history of a bidder, as a paginated response.
+    async fn settlements(
+        &self,
+        ctx: &Context<'_>,
+        bidder_id: Json<BidderId<T>>,
+        before: Option<Json<DateTime<T>>>,
+        after: Option<Json<DateTime<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Json<serde_json::Value>> {
+        require_settlement::<T>(ctx, &bidder_id.0).await?;
+        let history = app::<T>(ctx)
+            .database()
+            .get_settlement_history(
+                bidder_id.0,
+                range::<T>(before, after),
+                config(ctx).page_size(limit),
+            )
+            .await
+            .map_err(internal)?;
+        json(&history)
+    }
+
+    /// The trade activity of a bidder since the most recent settlement.
+    async fn unsettled(
+        &self,
+        ctx: &Context<'_>,
+        bidder_id: Json<BidderId<T>>,
+        as_of: Option<Json<DateTime<T>>>,
+    ) -> Result<Json<serde_json::Value>> {
+        require_settlement::<T>(ctx, &bidder_id.0).await?;
+        let app = app::<T>(ctx);
+        let as_of = as_of.map(|x| x.0).unwrap_or_else(|| app.now());
+        let activity = app
+            .database()
+            .get_unsettled_activity(bidder_id.0, as_of, config(ctx).time_unit)
+            .await
+            .map_err(internal)?;
+        json(&activity)
+    }
+}
+
+/// A product in the product hierarchy.
+pub struct ProductNode<T: ApiApplication>(ProductRecord<T::Repository, T::ProductData>);
+
+#[Object(name = "Product")]
+impl<T: ApiApplication> ProductNode<T> {
+    /// The product id
+    async fn id(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.id)
+    }
+
+    /// The application data of the product
+    async fn app_data(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.app_data)
+    }
+
+    /// The weight of this product relative to its parent
+    async fn parent_weight(&self) -> f64 {
+        self.0.parent.1
+    }
+
+    /// The parent of this product, if it is not a root product
+    async fn parent(&self, ctx: &Context<'_>) -> Result<Option<ProductNode<T>>> {
+        if self.0.parent.0 == self.0.id {
+            Ok(None)
+        } else {
+            load_product::<T>(ctx, self.0.parent.0.clone()).await
+        }
+    }
+
+    /// The contemporary basis of this product, as a map of product ids to weights
+    async fn basis(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.basis)
+    }
+
+    /// The batch outcomes of this product, as a paginated response
+    async fn outcomes(
+        &self,
+        ctx: &Context<'_>,
+        before: Option<Json<DateTime<T>>>,
+        after: Option<Json<DateTime<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Json<serde_json::Value>> {
+        let outcomes = <T::Repository as BatchRepository<T::Solver>>::get_product_outcomes(
+            app::<T>(ctx).database(),
+            self.0.id.clone(),
+            range::<T>(before, after),
+            config(ctx).page_size(limit),
+        )
+        .await
+        .map_err(internal)?;
+        json(&outcomes)
+    }
+
+    /// The aggregated price history of this product, as a paginated response
+    async fn summary(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] interval: Interval,
+        before: Option<Json<DateTime<T>>>,
+        after: Option<Json<DateTime<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Json<serde_json::Value>> {
+        let app = app::<T>(ctx);
+        let summary = <T::Repository as BatchRepository<T::Solver>>::get_product_summary(
+            app.database(),
+            self.0.id.clone(),
+            interval.into(),
+            range::<T>(before, after),
+            app.now(),
+            config(ctx).time_unit,
+            config(ctx).page_size(limit),
+        )
+        .await
+        .map_err(internal)?;
+        json(&summary)
+    }
+}
+
+/// A bidder's demand curve.
+pub struct DemandNode<T: ApiApplication>(DemandRecord<T::Repository, T::DemandData>);
+
+#[Object(name = "Demand")]
+impl<T: ApiApplication> DemandNode<T> {
+    /// The demand id
+    async fn id(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.id)
+    }
+
+    /// The bidder id
+    async fn bidder_id(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.bidder_id)
+    }
+
+    /// The timestamp when the current curve became effective
+    async fn valid_from(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.valid_from)
+    }
+
+    /// The application data of the demand
+    async fn app_data(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.app_data)
+    }
+
+    /// The demand curve
+    async fn curve_data(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.curve_data)
+    }
+
+    /// The weights with which this demand is associated to each portfolio
+    async fn portfolio_weights(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.portfolios)
+    }
+
+    /// The portfolios this demand is associated to
+    async fn portfolios(&self, ctx: &Context<'_>) -> Result<Vec<PortfolioNode<T>>> {
+        load_portfolios::<T>(ctx, self.0.portfolios.keys().cloned().collect()).await
+    }
+}
+
+/// A bidder's portfolio.
+pub struct PortfolioNode<T: ApiApplication>(PortfolioRecord<T::Repository, T::PortfolioData>);
+
+#[Object(name = "Portfolio")]
+impl<T: ApiApplication> PortfolioNode<T> {
+    /// The portfolio id
+    async fn id(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.id)
+    }
+
+    /// The bidder id
+    async fn bidder_id(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.bidder_id)
+    }
+
+    /// The timestamp when the current groups became effective
+    async fn valid_from(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.valid_from)
+    }
+
+    /// The application data of the portfolio
+    async fn app_data(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.app_data)
+    }
+
+    /// The demand group, as a map of demand ids to weights
+    async fn demand_weights(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.demand)
+    }
+
+    /// The product basis, as a map of product ids to weights
+    async fn basis(&self) -> Result<Json<serde_json::Value>> {
+        json(&self.0.basis)
+    }
+
+    /// The demands in the demand group
+    async fn demands(&self, ctx: &Context<'_>) -> Result<Vec<DemandNode<T>>> {
+        load_demands::<T>(ctx, self.0.demand.keys().cloned().collect()).await
+    }
+
+    /// The products in the product basis (requires product view permissions)
+    async fn products(&self, ctx: &Context<'_>) -> Result<Vec<ProductNode<T>>> {
+        let mut products = Vec::with_capacity(self.0.basis.len());
+        for product_id in self.0.basis.keys() {
+            if let Some(product) = load_product::<T>(ctx, product_id.clone()).await? {
+                products.push(product);
+            }
+        }
+        Ok(products)
+    }
+
+    /// The batch outcomes of this portfolio, as a paginated response
+    async fn outcomes(
+        &self,
+        ctx: &Context<'_>,
+        before: Option<Json<DateTime<T>>>,
+        after: Option<Json<DateTime<T>>>,
+        limit: Option<usize>,
+    ) -> Result<Json<serde_json::Value>> {
+        let outcomes = <T::Repository as BatchRepository<T::Solver>>::get_portfolio_outcomes(
+            app::<T>(ctx).database(),
+            self.0.id.clone(),
+            range::<T>(before, after),
+            config(ctx).page_size(limit),
+        )
+        .await
+        .map_err(internal)?;
+        json(&outcomes)
+    }
+}
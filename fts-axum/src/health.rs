@@ -0,0 +1,156 @@
+//! The health check endpoint.
+//!
+//! By default, the health check merely reports that the server is up, and is
+//! served to anyone (e.g. load balancers). With `?verbose=true`, it also checks
+//! the components of the system on behalf of an operator: the connectivity of
+//! the database and the status of its migrations, the schedule of batch
+//! auctions, the automatic execution of batch auctions, and the most recent
+//! batch.
+
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use fts_core::{
+    models::{BatchMetadata, BatchSchedule, MigrationStatus},
+    ports::{BatchRepository as _, HealthRepository as _, Repository},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    batch_queue::{AutoSolveStatus, BatchQueue},
+    problem::Problem,
+};
+
+/// Query parameters for the health check endpoint
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct HealthQuery {
+    /// Whether to check the components of the system (requires authorization)
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Response for the health check endpoint
+#[derive(Serialize, JsonSchema)]
+#[serde(bound(serialize = "R::DateTime: Serialize"))]
+#[schemars(inline, rename = "HealthResponse", bound = "R::DateTime: JsonSchema")]
+pub(crate) struct HealthResponse<R: Repository> {
+    /// Either "ok", or "degraded" if any component is unhealthy
+    status: String,
+    /// The checks of each component, if a verbose health check was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<HealthChecks<R>>,
+}
+
+/// The checks of each component of the system
+#[derive(Serialize, JsonSchema)]
+#[serde(bound(serialize = "R::DateTime: Serialize"))]
+#[schemars(rename = "HealthChecks", bound = "R::DateTime: JsonSchema")]
+struct HealthChecks<R: Repository> {
+    /// The connectivity of the database
+    database: DatabaseHealth,
+    /// The schedule of batch auctions, if the application schedules them
+    schedule: Option<BatchSchedule<R::DateTime>>,
+    /// The automatic execution of batch auctions, if enabled
+    auto_solve: Option<AutoSolveStatus>,
+    /// The most recently executed batch, if any
+    last_batch: Option<BatchMetadata<R>>,
+}
+
+/// The connectivity of the database
+#[derive(Serialize, JsonSchema)]
+struct DatabaseHealth {
+    /// Whether the database could be reached
+    connected: bool,
+    /// The time taken to check the database, in milliseconds
+    latency_ms: f64,
+    /// The status of the schema migrations, if the database could be reached
+    migrations: Option<MigrationStatus>,
+    /// The error encountered reaching the database, if any
+    error: Option<String>,
+}
+
+impl DatabaseHealth {
+    fn is_healthy(&self) -> bool {
+        self.migrations
+            .as_ref()
+            .is_some_and(|migrations| migrations.pending == 0)
+    }
+}
+
+/// Check the health of the server.
+///
+/// # Authorization
+///
+/// None, unless `verbose` is set, which requires `can_view_health` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The server (and, if verbose, each of its components) is healthy
+/// - `401 Unauthorized`: Missing or insufficient permissions for a verbose check
+/// - `503 Service Unavailable`: A component is unhealthy, as detailed in the response
+pub(crate) async fn health_check<T: ApiApplication>(
+    State(app): State<T>,
+    Extension(queue): Extension<BatchQueue<T>>,
+    Query(query): Query<HealthQuery>,
+    auth: Option<Auth<T>>,
+) -> Result<(StatusCode, Json<HealthResponse<T::Repository>>), Problem> {
+    if !query.verbose {
+        let response = HealthResponse {
+            status: "ok".to_string(),
+            checks: None,
+        };
+        return Ok((StatusCode::OK, Json(response)));
+    }
+
+    let Some(Auth(auth)) = auth else {
+        return Err(Problem::not_authorized());
+    };
+    if !app.can_view_health(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let db = app.database();
+
+    let start = Instant::now();
+    let (migrations, error) = match db.check_health().await {
+        Ok(migrations) => (Some(migrations), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    let database = DatabaseHealth {
+        connected: error.is_none(),
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        migrations,
+        error,
+    };
+
+    let last_batch = if database.connected {
+        db.get_latest_batch().await.map_err(Problem::internal)?
+    } else {
+        None
+    };
+
+    let (status, code) = if database.is_healthy() {
+        ("ok", StatusCode::OK)
+    } else {
+        ("degraded", StatusCode::SERVICE_UNAVAILABLE)
+    };
+
+    let checks = HealthChecks {
+        database,
+        schedule: app.schedule().await,
+        auto_solve: queue.status(),
+        last_batch,
+    };
+
+    let response = HealthResponse {
+        status: status.to_string(),
+        checks: Some(checks),
+    };
+    Ok((code, Json(response)))
+}
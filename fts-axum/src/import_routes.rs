@@ -0,0 +1,95 @@
+//! REST API endpoint for importing a market in bulk.
+//!
+//! This module provides an administrative endpoint which imports products,
+//! demands, and portfolios from a single document, all or nothing.
+
+use crate::{ApiApplication, auth::Auth, batch_queue::BatchQueue, problem::Problem};
+use aide::axum::{ApiRouter, routing::post};
+use axum::{
+    Extension, Json,
+    extract::{Query, State, rejection::JsonRejection},
+    http::StatusCode,
+};
+use fts_core::{
+    models::{Actor, ImportDocument, ImportRecord},
+    ports::ImportRepository as _,
+};
+
+/// Creates a router with the import endpoint.
+pub fn router<T: ApiApplication>() -> ApiRouter<T> {
+    ApiRouter::new().api_route_with("/", post(import::<T>), |route| {
+        route.security_requirement("jwt").tag("admin")
+    })
+}
+
+/// Query parameters for an import.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+struct ImportQuery {
+    /// Validate and perform the import, but roll it back rather than commit it
+    #[serde(default)]
+    dry_run: bool,
+}
+
+type Document<T> = ImportDocument<
+    <T as fts_core::ports::Application>::Repository,
+    <T as fts_core::ports::Application>::DemandData,
+    <T as fts_core::ports::Application>::PortfolioData,
+    <T as fts_core::ports::Application>::ProductData,
+>;
+
+/// Import products, demands, and portfolios from a single document.
+///
+/// Every entity carries its own id (and, for bids, its bidder), so that an
+/// existing market can be migrated intact. The document is validated against
+/// itself and the existing entities: ids must be new, a product's parent must
+/// precede it or already exist, and a portfolio's demands and products must
+/// be imported alongside it or already exist. The import is then performed in
+/// a single transaction, attributing every bid to an operator.
+///
+/// # Authorization
+///
+/// Requires `can_import` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The document would be imported (for a dry run)
+/// - `201 Created`: The document was imported
+/// - `400 Bad Request`: Malformed JSON
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `422 Unprocessable Entity`: Invalid document, e.g. a duplicate id or an unknown reference
+/// - `500 Internal Server Error`: Database operation failed
+async fn import<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(queue): Extension<BatchQueue<T>>,
+    Query(query): Query<ImportQuery>,
+    body: Result<Json<Document<T>>, JsonRejection>,
+) -> Result<(StatusCode, Json<ImportRecord>), Problem> {
+    let Json(document) = body?;
+    if !app.can_import(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = app.now();
+    let record = app
+        .database()
+        .import(document, Actor::Operator, as_of.clone(), query.dry_run)
+        .await
+        .map_err(Problem::internal)?
+        .map_err(|issues| {
+            let detail = issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ");
+            Problem::new(StatusCode::UNPROCESSABLE_ENTITY, "import_invalid").with_detail(detail)
+        })?;
+
+    if record.dry_run {
+        Ok((StatusCode::OK, Json(record)))
+    } else {
+        queue.notify(as_of);
+        Ok((StatusCode::CREATED, Json(record)))
+    }
+}
@@ -6,21 +6,39 @@
 //! [fts_sqlite]: https://docs.rs/fts_sqlite/latest/fts_sqlite/index.html
 #![doc = include_str!("../README.md")]
 
+mod as_of;
+mod auth;
+mod batch_queue;
 mod batch_routes;
+mod bidder_routes;
+mod cache;
+mod compression;
 mod demand_routes;
+mod fields;
+mod health;
+mod import_routes;
+mod limit;
+mod negotiate;
 mod portfolio_routes;
+mod problem;
 mod product_routes;
+mod reload;
+mod request_id;
+mod settlement_routes;
+mod versioning;
+
+#[cfg(feature = "graphql")]
+pub mod graphql;
 
 use aide::{
     axum::{ApiRouter, routing::get},
     openapi::OpenApi,
 };
-use axum::{Extension, Json};
+use axum::Extension;
 use fts_core::ports::{Application, Repository, Solver};
-use headers::{Authorization, authorization::Bearer};
 use schemars::JsonSchema;
 use serde::{Serialize, de::DeserializeOwned};
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, net::SocketAddr, sync::Arc};
 
 mod openapi;
 use openapi::{api_docs, docs_routes};
@@ -28,56 +46,84 @@ use openapi::{api_docs, docs_routes};
 pub mod config;
 use config::AxumConfig;
 
-/// Response for the health check endpoint
-#[derive(Serialize, JsonSchema)]
-#[schemars(inline)]
-struct HealthResponse {
-    status: String,
-}
+pub use auth::AuthContext;
 
-/// Simple health check endpoint
-async fn health_check() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok".to_string(),
-    })
+/// Compose the health check, the configured API versions, and the documentation
+fn api_router<T: ApiApplication>(config: &AxumConfig) -> ApiRouter<T> {
+    let router = ApiRouter::new().api_route("/health", get(health::health_check::<T>));
+    versioning::mount(router, &config.versions).nest_api_service("/docs", docs_routes())
 }
 
-/// Extract the OpenAPI documentation for the server
+/// Extract the OpenAPI documentation for the server, using the default
+/// configuration of API versions
 pub fn schema<T: ApiApplication>() -> OpenApi {
     let mut api = OpenApi::default();
-    let _ = ApiRouter::new()
-        .api_route("/health", get(health_check))
-        .nest("/product", product_routes::router::<T>())
-        .nest("/demand", demand_routes::router::<T>())
-        .nest("/portfolio", portfolio_routes::router::<T>())
-        .nest("/batch", batch_routes::router::<T>())
-        .nest_api_service("/docs", docs_routes())
-        .finish_api_with(&mut api, api_docs);
+    let _ = api_router::<T>(&AxumConfig::default()).finish_api_with(&mut api, api_docs);
     api
 }
 
 /// Construct a full API router with the given state and config
 pub fn router<T: ApiApplication>(state: T, config: AxumConfig) -> axum::Router {
+    let (_, config) = tokio::sync::watch::channel(Arc::new(config));
+    reloadable_router(state, config)
+}
+
+/// Construct a full API router with the given state, whose configuration may
+/// be changed while it serves by sending a new one through the channel.
+///
+/// Each request is handled with the configuration current when it arrives, so
+/// the page limits, polling intervals, caching, time unit, and `auto_solve`
+/// settings all take effect without dropping any connection. The bind
+/// address, API versions, and compression are fixed when the router is built.
+pub fn reloadable_router<T: ApiApplication>(
+    state: T,
+    config: tokio::sync::watch::Receiver<Arc<AxumConfig>>,
+) -> axum::Router {
     let policy = tower_http::cors::CorsLayer::new()
         .allow_origin(tower_http::cors::Any)
         .allow_methods(tower_http::cors::Any)
         .allow_headers([
             axum::http::header::AUTHORIZATION,
             axum::http::header::CONTENT_TYPE,
+        ])
+        .expose_headers([
+            axum::http::header::LINK,
+            versioning::DEPRECATION,
+            versioning::SUNSET,
+            request_id::X_REQUEST_ID,
         ]);
 
+    let initial = config.borrow().clone();
     let mut api = OpenApi::default();
-    ApiRouter::new()
-        .api_route("/health", get(health_check))
-        .nest("/product", product_routes::router())
-        .nest("/demand", demand_routes::router())
-        .nest("/portfolio", portfolio_routes::router())
-        .nest("/batch", batch_routes::router())
-        .nest_api_service("/docs", docs_routes())
-        .finish_api_with(&mut api, api_docs)
+    let router = api_router(&initial).finish_api_with(&mut api, api_docs);
+
+    // The GraphQL endpoint is not part of the OpenAPI documentation, as it
+    // provides its own introspection.
+    #[cfg(feature = "graphql")]
+    let router = router
+        .route(
+            "/graphql",
+            axum::routing::post(graphql::graphql_handler::<T>),
+        )
+        .layer(Extension(graphql::schema::<T>()));
+
+    // The worker idles unless `auto_solve` is (or is later) enabled
+    let current = reload::Current {
+        queue: batch_queue::BatchQueue::spawn(state.clone(), config.clone()),
+        config,
+    };
+
+    let compression = compression::layer(&initial.compression);
+
+    router
+        .layer(axum::middleware::from_fn_with_state(
+            current,
+            reload::current::<T>,
+        ))
         .layer(Extension(Arc::new(api))) // Arc is very important here or you will face massive memory and performance issues
-        .layer(Extension(Arc::new(config)))
+        .layer(compression)
         .layer(policy)
+        .layer(axum::middleware::from_fn(request_id::trace))
         .with_state(state)
 }
 
@@ -86,7 +132,16 @@ pub async fn start_server<T: ApiApplication>(
     config: AxumConfig,
     app: T,
 ) -> Result<(), std::io::Error> {
-    let listener = tokio::net::TcpListener::bind(config.bind_address)
+    let bind_address = config.bind_address;
+
+    // Here, we could apply additional config like timeouts, CORS, etc.
+    serve(bind_address, router(app, config)).await
+}
+
+/// Serves an arbitrary router, such as one nesting the routers of several
+/// applications, on the given address
+pub async fn serve(bind_address: SocketAddr, service: axum::Router) -> Result<(), std::io::Error> {
+    let listener = tokio::net::TcpListener::bind(bind_address)
         .await
         .expect("Unable to bind to address");
 
@@ -95,8 +150,6 @@ pub async fn start_server<T: ApiApplication>(
         listener.local_addr().unwrap()
     );
 
-    // Here, we could apply additional config like timeouts, CORS, etc.
-    let service = router(app, config);
     axum::serve(listener, service).await
 }
 
@@ -110,7 +163,7 @@ pub trait ApiApplication:
     + Sync
     + 'static
     + Application<
-        Context = Authorization<Bearer>,
+        Context: AuthContext<Self>,
         DemandData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
         PortfolioData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
         ProductData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
@@ -119,18 +172,28 @@ pub trait ApiApplication:
                         + Sync
                         + 'static
                         + Repository<
-            DateTime: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
+            DateTime: Clone
+                          + PartialOrd
+                          + Display
+                          + Serialize
+                          + DeserializeOwned
+                          + JsonSchema
+                          + Send
+                          + Sync,
             BidderId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
             DemandId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
             PortfolioId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
             ProductId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
         >,
-        Solver: Solver<
+        Solver: Send
+                    + Sync
+                    + Solver<
             <Self::Repository as Repository>::DemandId,
             <Self::Repository as Repository>::PortfolioId,
             <Self::Repository as Repository>::ProductId,
             PortfolioOutcome: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
             ProductOutcome: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
+            State: Clone + Send + 'static,
         >,
     >
 {
@@ -139,7 +202,7 @@ pub trait ApiApplication:
 // this is the blanket implementation
 impl<T: Clone + Send + Sync + 'static> ApiApplication for T where
     T: Application<
-            Context = Authorization<Bearer>,
+            Context: AuthContext<T>,
             DemandData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
             PortfolioData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
             ProductData: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
@@ -148,7 +211,14 @@ impl<T: Clone + Send + Sync + 'static> ApiApplication for T where
                             + Sync
                             + 'static
                             + Repository<
-                DateTime: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
+                DateTime: Clone
+                              + PartialOrd
+                              + Display
+                              + Serialize
+                              + DeserializeOwned
+                              + JsonSchema
+                              + Send
+                              + Sync,
                 BidderId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
                 DemandId: Clone + Display + Serialize + DeserializeOwned + JsonSchema + Send + Sync,
                 PortfolioId: Clone
@@ -166,12 +236,15 @@ impl<T: Clone + Send + Sync + 'static> ApiApplication for T where
                                + Send
                                + Sync,
             >,
-            Solver: Solver<
+            Solver: Send
+                        + Sync
+                        + Solver<
                 <T::Repository as Repository>::DemandId,
                 <T::Repository as Repository>::PortfolioId,
                 <T::Repository as Repository>::ProductId,
                 PortfolioOutcome: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
                 ProductOutcome: Send + Sync + Serialize + DeserializeOwned + JsonSchema + 'static,
+                State: Clone + Send + 'static,
             >,
         >
 {
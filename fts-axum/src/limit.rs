@@ -0,0 +1,30 @@
+//! Client-controlled page sizes.
+//!
+//! Paginated endpoints accept a `limit` query parameter, allowing each client
+//! to choose a page size suited to its purpose (e.g. large pages for backfill
+//! jobs, small pages for interactive use) within the bound configured by
+//! `max_page_limit`.
+
+use crate::config::AxumConfig;
+use schemars::JsonSchema;
+
+/// Query parameter selecting the size of a page of results.
+#[derive(Default, serde::Deserialize, JsonSchema)]
+#[schemars(inline)]
+pub(crate) struct LimitQuery {
+    /// The maximum number of results to return (the server's default page
+    /// limit if omitted); larger values are reduced to the server's maximum
+    limit: Option<usize>,
+}
+
+impl LimitQuery {
+    /// The page limit to use for the request.
+    pub(crate) fn page_size(&self, config: &AxumConfig) -> usize {
+        config.page_size(self.limit)
+    }
+
+    /// Whether the client chose a page size, rather than leaving it to the server.
+    pub(crate) fn is_requested(&self) -> bool {
+        self.limit.is_some()
+    }
+}
@@ -0,0 +1,533 @@
+//! Content negotiation for tabular responses.
+//!
+//! Endpoints returning a history of values (such as outcomes) encode their
+//! response according to the request's `Accept` header: as JSON (the
+//! default), as MessagePack (`application/msgpack`) for clients wanting a
+//! compact binary encoding, or as CSV (`text/csv`) for export into
+//! spreadsheets and the like.
+//!
+//! A CSV response has a row per record, whose nested fields are flattened into
+//! columns with dotted names (e.g. `value.price`); arrays are written as JSON.
+//! As there is no room in the table for the pagination metadata, the next page
+//! is instead linked using the `Link` header, with `rel="next"`.
+//!
+//! Exports too large for a single page may instead be streamed as CSV, a row
+//! at a time. Since the columns cannot be known in advance, they are taken
+//! from the first row, and fields absent from it are omitted from the table.
+//!
+//! Documents (such as settlement statements) are instead rendered by the
+//! application in whichever media type it supports, the client's preferences
+//! being offered in turn, falling back to JSON.
+
+use crate::problem::Problem;
+use aide::{
+    OperationInput, OperationOutput,
+    generate::GenContext,
+    openapi::{MediaType, Operation, Response},
+};
+use axum::{
+    Json,
+    body::{Body as AxumBody, Bytes},
+    extract::FromRequestParts,
+    http::{
+        HeaderValue,
+        header::{ACCEPT, CONTENT_TYPE, LINK, VARY},
+        request::Parts,
+    },
+    response::{IntoResponse, Response as AxumResponse},
+};
+use fts_core::models::{DateTimeRangeQuery, DateTimeRangeResponse, ValueRecord};
+use futures_util::{Stream, StreamExt as _};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{collections::HashSet, convert::Infallible};
+use tokio::sync::mpsc;
+use tracing::{Level, event};
+
+const MSGPACK: &str = "application/msgpack";
+const CSV: &str = "text/csv";
+
+/// The number of rows of a streamed table buffered ahead of the client
+const ROW_BUFFER: usize = 256;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The encodings of a response body.
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    MessagePack,
+    Csv,
+}
+
+impl Format {
+    /// The format corresponding to a media range, if supported
+    fn from_media_range(media_range: &str) -> Option<Self> {
+        let media_range = media_range.to_ascii_lowercase();
+        match media_range.as_str() {
+            "*/*" | "application/*" | "application/json" => Some(Self::Json),
+            MSGPACK | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            "text/*" | CSV => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Extractor for the client's preferred encoding of the response.
+///
+/// The supported media range with the highest quality wins, with ties broken
+/// in favour of the first listed. If no supported media range is acceptable,
+/// the response is encoded as JSON regardless.
+pub(crate) struct Accept {
+    format: Format,
+    /// The query string of the request, from which links to further pages
+    /// are derived
+    query: Option<String>,
+}
+
+impl Accept {
+    /// Wrap a response body, to be encoded in the negotiated format.
+    pub(crate) fn respond<T>(self, body: T) -> Negotiated<T> {
+        Negotiated {
+            format: self.format,
+            query: self.query,
+            body: Body::Page(body),
+        }
+    }
+
+    /// Whether the client asked for a CSV table, which may be streamed.
+    pub(crate) fn is_csv(&self) -> bool {
+        matches!(self.format, Format::Csv)
+    }
+
+    /// Stream a CSV table in place of a response body, with the rows sent by
+    /// `task`, which is spawned so as to outlive the handler.
+    pub(crate) fn stream<T, F, Fut>(self, task: F) -> Negotiated<T>
+    where
+        F: FnOnce(RowSender) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(ROW_BUFFER);
+        tokio::spawn(task(RowSender(sender)));
+        Negotiated {
+            format: self.format,
+            query: self.query,
+            body: Body::Rows(receiver),
+        }
+    }
+
+    fn negotiate(accept: &str) -> Format {
+        let mut best: Option<(Format, f32)> = None;
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let Some(format) = Format::from_media_range(params.next().unwrap_or_default().trim())
+            else {
+                continue;
+            };
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((format, quality));
+            }
+        }
+        best.map_or(Format::Json, |(format, _)| format)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Accept {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(Self {
+            format: Self::negotiate(&accept),
+            query: parts.uri.query().map(String::from),
+        })
+    }
+}
+
+impl OperationInput for Accept {}
+
+/// Extractor for the media ranges acceptable to the client, most preferred
+/// first (ties keeping the order listed), excluding those with zero quality.
+pub(crate) struct MediaRanges(Vec<String>);
+
+impl MediaRanges {
+    fn parse(accept: &str) -> Vec<String> {
+        let mut ranges = Vec::new();
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let media_range = params.next().unwrap_or_default().trim();
+            if media_range.is_empty() {
+                continue;
+            }
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 {
+                ranges.push((media_range.to_ascii_lowercase(), quality));
+            }
+        }
+        ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        ranges
+            .into_iter()
+            .map(|(media_range, _)| media_range)
+            .collect()
+    }
+
+    /// Whether a media range is satisfied by JSON
+    fn is_json(media_range: &str) -> bool {
+        matches!(media_range, "*/*" | "application/*" | "application/json")
+    }
+
+    /// The media types in which to render a document, most preferred first,
+    /// being those preferred to JSON (ranges such as `text/*` are skipped).
+    pub(crate) fn preferred(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .map(String::as_str)
+            .take_while(|media_range| !Self::is_json(media_range))
+            .filter(|media_range| !media_range.ends_with("/*"))
+    }
+
+    /// Whether the client accepts JSON, as it does if it sends no `Accept`
+    /// header at all.
+    pub(crate) fn accepts_json(&self) -> bool {
+        self.0.is_empty() || self.0.iter().any(|media_range| Self::is_json(media_range))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for MediaRanges {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(Self(Self::parse(&accept)))
+    }
+}
+
+impl OperationInput for MediaRanges {}
+
+/// A document, either as JSON or as rendered by the application.
+///
+/// For the purposes of the OpenAPI documentation, this is `Json<T>`.
+pub(crate) enum Rendered<T> {
+    Json(T),
+    Document { media_type: String, body: Vec<u8> },
+}
+
+impl<T: Serialize> IntoResponse for Rendered<T> {
+    fn into_response(self) -> AxumResponse {
+        let mut response = match self {
+            Self::Json(document) => Json(document).into_response(),
+            Self::Document { media_type, body } => match HeaderValue::try_from(media_type) {
+                Ok(content_type) => ([(CONTENT_TYPE, content_type)], body).into_response(),
+                Err(err) => return Problem::internal(err).into_response(),
+            },
+        };
+        // Shared caches must not serve one rendering in place of another
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Rendered<T> {
+    type Inner = T;
+
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<Response> {
+        Json::<T>::operation_response(ctx, operation)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, Response)> {
+        Json::<T>::inferred_responses(ctx, operation)
+    }
+}
+
+/// A response body which can be laid out as a table.
+pub(crate) trait Tabular {
+    /// The type of a row of the table
+    type Row: Serialize;
+    /// The query for the next page of rows
+    type Next: Serialize;
+
+    /// The query parameters replaced by the query for the next page
+    const CURSOR: &[&str];
+
+    /// The rows of the table
+    fn rows(&self) -> &[Self::Row];
+
+    /// The query for the next page of rows, if there are more
+    fn next(&self) -> Option<&Self::Next>;
+}
+
+impl<T: Serialize, DateTime: Serialize> Tabular for DateTimeRangeResponse<T, DateTime> {
+    type Row = ValueRecord<DateTime, T>;
+    type Next = DateTimeRangeQuery<DateTime>;
+
+    const CURSOR: &[&str] = &["before", "after"];
+
+    fn rows(&self) -> &[Self::Row] {
+        &self.results
+    }
+
+    fn next(&self) -> Option<&Self::Next> {
+        self.more.as_ref()
+    }
+}
+
+/// Sends the rows of a streamed table to the response body.
+pub(crate) struct RowSender(mpsc::Sender<Result<serde_json::Value, BoxError>>);
+
+impl RowSender {
+    /// Forward the rows to the response body, until the first error (which
+    /// aborts the response) or until the client goes away.
+    pub(crate) async fn forward<R, E>(self, rows: impl Stream<Item = Result<R, E>>)
+    where
+        R: Serialize,
+        E: std::fmt::Display,
+    {
+        let mut rows = std::pin::pin!(rows);
+        loop {
+            // The repository's error need not be sendable, so is converted
+            // before awaiting the client
+            let Some(row) = rows.next().await else {
+                break;
+            };
+            let row = row
+                .map_err(|err| {
+                    event!(Level::ERROR, err = err.to_string());
+                    BoxError::from(err.to_string())
+                })
+                .and_then(|row| Ok(serde_json::to_value(row)?));
+            let failed = row.is_err();
+            if self.0.send(row).await.is_err() || failed {
+                break;
+            }
+        }
+    }
+}
+
+/// The content of a negotiated response.
+enum Body<T> {
+    /// A response body, in its entirety
+    Page(T),
+    /// The rows of a table, as they are received
+    Rows(mpsc::Receiver<Result<serde_json::Value, BoxError>>),
+}
+
+/// A response body, encoded in the format negotiated with the client.
+///
+/// For the purposes of the OpenAPI documentation, this is `Json<T>`, whose
+/// schema also describes the MessagePack encoding.
+pub(crate) struct Negotiated<T> {
+    format: Format,
+    query: Option<String>,
+    body: Body<T>,
+}
+
+impl<T: Serialize + Tabular> Negotiated<T> {
+    fn encode(self) -> Result<AxumResponse, Box<dyn std::error::Error>> {
+        let body = match self.body {
+            Body::Page(body) => body,
+            // Only tables are streamed
+            Body::Rows(rows) => {
+                return Ok(([csv_content_type()], stream_csv(rows)).into_response());
+            }
+        };
+        Ok(match self.format {
+            Format::Json => Json(body).into_response(),
+            Format::MessagePack => (
+                [(CONTENT_TYPE, HeaderValue::from_static(MSGPACK))],
+                rmp_serde::to_vec_named(&body)?,
+            )
+                .into_response(),
+            Format::Csv => {
+                let mut response = ([csv_content_type()], to_csv(body.rows())?).into_response();
+                if let Some(next) = body.next() {
+                    let link = next_link(self.query.as_deref(), T::CURSOR, next)?;
+                    response.headers_mut().append(LINK, link);
+                }
+                response
+            }
+        })
+    }
+}
+
+/// The `Content-Type` header of a CSV response
+fn csv_content_type() -> (axum::http::HeaderName, HeaderValue) {
+    (
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/csv; charset=utf-8"),
+    )
+}
+
+impl<T: Serialize + Tabular> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> AxumResponse {
+        match self.encode() {
+            Ok(mut response) => {
+                // Shared caches must not serve one encoding in place of another
+                response
+                    .headers_mut()
+                    .append(VARY, HeaderValue::from_static("accept"));
+                response
+            }
+            Err(err) => Problem::internal(err).into_response(),
+        }
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Negotiated<T> {
+    type Inner = T;
+
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<Response> {
+        let mut response = Json::<T>::operation_response(ctx, operation)?;
+        let schema = response
+            .content
+            .get("application/json")
+            .and_then(|media_type| media_type.schema.clone());
+        response.content.insert(
+            MSGPACK.into(),
+            MediaType {
+                schema,
+                ..Default::default()
+            },
+        );
+        response.content.insert(CSV.into(), MediaType::default());
+        Some(response)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, Response)> {
+        Self::operation_response(ctx, operation)
+            .map(|response| vec![(Some(200), response)])
+            .unwrap_or_default()
+    }
+}
+
+/// Link to the next page, by replacing the cursor of the request's query with
+/// that of the next page. As the reference consists only of a query string, it
+/// resolves against the path of the request.
+pub(crate) fn next_link(
+    query: Option<&str>,
+    cursor: &[&str],
+    next: &impl Serialize,
+) -> Result<HeaderValue, Box<dyn std::error::Error>> {
+    let mut pairs: Vec<(String, String)> = serde_urlencoded::from_str(query.unwrap_or_default())?;
+    pairs.retain(|(key, _)| !cursor.contains(&key.as_str()));
+    pairs.extend(serde_urlencoded::from_str::<Vec<(String, String)>>(
+        &serde_urlencoded::to_string(next)?,
+    )?);
+    let query = serde_urlencoded::to_string(pairs)?;
+    Ok(HeaderValue::try_from(format!("<?{query}>; rel=\"next\""))?)
+}
+
+/// Lay out the rows as a table, whose columns are every (flattened) field of
+/// every row, in the order they are first encountered.
+fn to_csv<R: Serialize>(rows: &[R]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut columns = Vec::new();
+    let mut seen = HashSet::new();
+    let mut cells = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut flat = serde_json::Map::new();
+        flatten(String::new(), serde_json::to_value(row)?, &mut flat);
+        for column in flat.keys() {
+            if seen.insert(column.clone()) {
+                columns.push(column.clone());
+            }
+        }
+        cells.push(flat);
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if !columns.is_empty() {
+        writer.write_record(&columns)?;
+    }
+    for flat in cells {
+        writer.write_record(columns.iter().map(|column| cell(flat.get(column))))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Lay out the rows as a table as they are received, whose columns are the
+/// (flattened) fields of the first row.
+fn stream_csv(rows: mpsc::Receiver<Result<serde_json::Value, BoxError>>) -> AxumBody {
+    let stream = futures_util::stream::unfold(
+        (rows, None::<Vec<String>>),
+        |(mut rows, mut columns)| async move {
+            let row = rows.recv().await?;
+            let chunk = row.and_then(|row| {
+                let mut flat = serde_json::Map::new();
+                flatten(String::new(), row, &mut flat);
+
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                let header = columns.is_none();
+                let columns = columns.get_or_insert_with(|| flat.keys().cloned().collect());
+                if header {
+                    writer.write_record(columns.iter())?;
+                }
+                writer.write_record(columns.iter().map(|column| cell(flat.get(column))))?;
+                Ok(Bytes::from(
+                    writer.into_inner().map_err(|err| err.into_error())?,
+                ))
+            });
+            Some((chunk, (rows, columns)))
+        },
+    );
+    AxumBody::from_stream(stream)
+}
+
+/// The text of a table's cell
+fn cell(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Flatten the nested objects of `value` into `flat`, joining keys with dots
+fn flatten(
+    prefix: String,
+    value: serde_json::Value,
+    flat: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(key, value, flat);
+            }
+        }
+        value => {
+            flat.insert(prefix, value);
+        }
+    }
+}
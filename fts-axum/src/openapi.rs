@@ -85,9 +85,19 @@ pub(crate) fn api_docs(api: TransformOpenApi) -> TransformOpenApi {
             description: Some("Outcomes associated to a batch auction".into()),
             ..Default::default()
         })
+        .tag(Tag {
+            name: "settlement".into(),
+            description: Some("Settlement of the trade activity produced by batch auctions".into()),
+            ..Default::default()
+        })
+        .tag(Tag {
+            name: "bidder".into(),
+            description: Some("Aggregate views across all of a bidder's bids".into()),
+            ..Default::default()
+        })
         .tag(Tag {
             name: "admin".into(),
-            description: Some("Operations requiring `admin: true` claim in JWT".into()),
+            description: Some("Operations requiring elevated permissions, such as the `admin: true` claim or a dedicated scope in the JWT".into()),
             ..Default::default()
         })
 }
@@ -4,11 +4,11 @@
 //! and associate them with tradeable products. Portfolios enable complex trading
 //! strategies by allowing weighted combinations of demands and products.
 
-use crate::ApiApplication;
+use crate::{ApiApplication, problem::Problem};
 use aide::{
     axum::{
         ApiRouter,
-        routing::{get, get_with},
+        routing::{get, get_with, post},
     },
     transform::TransformOperation,
 };
@@ -79,6 +79,16 @@ pub fn router<T: ApiApplication>() -> ApiRouter<T> {
                     .tag("outcome")
             },
         )
+        .api_route_with(
+            "/{portfolio_id}/purge",
+            post(purge_portfolio::<T>),
+            |route| {
+                route
+                    .security_requirement("jwt")
+                    .tag("portfolio")
+                    .tag("admin")
+            },
+        )
 }
 
 fn list_portfolios_docs(op: TransformOperation<'_>) -> TransformOperation<'_> {
@@ -86,14 +96,30 @@ fn list_portfolios_docs(op: TransformOperation<'_>) -> TransformOperation<'_> {
         .description(
             r#"
             Query all portfolios for bidders the requester is authorized to view.
-            Returns only portfolios with non-empty demand or product groups.
+            By default, returns only portfolios with non-empty demand or product
+            groups, a page at a time in order of their id; the next page, if any,
+            is linked by the `Link` header, with `rel="next"`. Setting `active_only`
+            to false includes the portfolios with empty groups, `has_curve` restricts
+            the portfolios to those with a demand with a curve, and `touched_since`
+            to those changed since the given time.
+
+            If `product_id` is specified, only portfolios whose basis references
+            the product are returned. If `expand` is true, the product groups are
+            expanded to include any child products created through partitioning
+            (and `product_id` is matched against the expanded groups). Either
+            returns all the matching portfolios at once, so the filters and
+            pagination do not apply. The `fields` parameter restricts each record
+            to the listed fields.
 
             Requires `can_query_bid` permission.
             "#,
         )
         //.response_with::<200, _, _>(|res| res.description("List of portfolio IDs"))
-        .response_with::<401, String, _>(|res| res.description("Unauthorized"))
-        .response_with::<500, String, _>(|res| res.description("Database query failed"))
+        .response_with::<400, Problem, _>(|res| {
+            res.description("Filters or pagination requested for a product or expansion")
+        })
+        .response_with::<401, Problem, _>(|res| res.description("Unauthorized"))
+        .response_with::<500, Problem, _>(|res| res.description("Database query failed"))
 }
 
 fn create_portfolio_docs(op: TransformOperation<'_>) -> TransformOperation<'_> {
@@ -107,6 +133,6 @@ fn create_portfolio_docs(op: TransformOperation<'_>) -> TransformOperation<'_> {
             "#,
         )
         // FIXME: The 201 is not automatically generated, but manually documenting it here is... not nice.
-        .response_with::<401, String, _>(|res| res.description("Missing create permissions"))
-        .response_with::<500, String, _>(|res| res.description("Database operation failed"))
+        .response_with::<401, Problem, _>(|res| res.description("Missing create permissions"))
+        .response_with::<500, Problem, _>(|res| res.description("Database operation failed"))
 }
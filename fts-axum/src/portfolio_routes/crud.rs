@@ -1,44 +1,53 @@
 use super::Id;
-use crate::{ApiApplication, config::AxumConfig};
+use crate::{
+    ApiApplication,
+    as_of::AsOfQuery,
+    auth::Auth,
+    batch_queue::BatchQueue,
+    fields::{FieldsQuery, Sparse},
+    problem::Problem,
+};
 
 use axum::{
     Extension, Json,
-    extract::{Path, Query, State},
+    extract::{Path, Query, State, rejection::JsonRejection},
     http::StatusCode,
 };
-use axum_extra::TypedHeader;
 use fts_core::{
-    models::{Basis, PortfolioRecord, Weights},
-    ports::{BatchRepository as _, PortfolioRepository as _, Repository},
+    models::{Basis, PortfolioRecord, Tombstone, Weights},
+    ports::{PortfolioRepository, Repository},
 };
-use headers::{Authorization, authorization::Bearer};
-use std::{hash::Hash, sync::Arc};
-use tracing::{Level, event};
+use std::hash::Hash;
 
 pub(crate) async fn create_portfolio<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-    Extension(config): Extension<Arc<AxumConfig>>,
-    Json(body): Json<
-        CreatePortfolioDto<
-            T::PortfolioData,
-            <T::Repository as Repository>::DemandId,
-            <T::Repository as Repository>::ProductId,
+    Auth(auth): Auth<T>,
+    Extension(queue): Extension<BatchQueue<T>>,
+    body: Result<
+        Json<
+            CreatePortfolioDto<
+                T::PortfolioData,
+                <T::Repository as Repository>::DemandId,
+                <T::Repository as Repository>::ProductId,
+            >,
         >,
+        JsonRejection,
     >,
 ) -> Result<
     (
         StatusCode,
         Json<PortfolioRecord<T::Repository, T::PortfolioData>>,
     ),
-    StatusCode,
+    Problem,
 > {
+    let Json(body) = body?;
     let db = app.database();
     let (portfolio_id, as_of) = app.generate_portfolio_id(&body.app_data);
     let bidder_id = app
         .can_create_bid(&auth)
         .await
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or_else(Problem::not_authorized)?;
+    let actor = app.actor(&auth, bidder_id.clone()).await;
 
     let created = db
         .create_portfolio(
@@ -47,30 +56,13 @@ pub(crate) async fn create_portfolio<T: ApiApplication>(
             body.app_data,
             body.demand,
             body.basis,
+            actor,
             as_of.clone(),
         )
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    if config.auto_solve {
-        tokio::spawn(async move {
-            let db = app.database();
-            let result = db.run_batch(as_of, app.solver(), Default::default()).await;
+        .map_err(Problem::internal)?;
 
-            match result {
-                Err(err) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                Ok(Err(err)) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                _ => {}
-            };
-        });
-    };
+    queue.notify(as_of);
 
     Ok((StatusCode::CREATED, Json(created)))
 }
@@ -83,7 +75,8 @@ pub(crate) async fn create_portfolio<T: ApiApplication>(
 ///
 /// # Authorization
 ///
-/// Requires read permission for the portfolio's bidder (`can_read_bid`).
+/// Requires read permission for the portfolio's bidder (`can_read_bid`), and
+/// `can_audit` permission to read the portfolio as of another time.
 ///
 /// # Returns
 ///
@@ -93,11 +86,13 @@ pub(crate) async fn create_portfolio<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn read_portfolio<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
     Query(params): Query<GetPortfolioQuery>,
-) -> Result<Json<PortfolioRecord<T::Repository, T::PortfolioData>>, StatusCode> {
-    let as_of = app.now();
+    Query(as_of): Query<AsOfQuery<<T::Repository as Repository>::DateTime>>,
+    Query(fields): Query<FieldsQuery>,
+) -> Result<Sparse<PortfolioRecord<T::Repository, T::PortfolioData>>, Problem> {
+    let as_of = as_of.resolve(&app, &auth).await?;
     let db = app.database();
     let portfolio = if params.expand {
         db.get_portfolio_with_expanded_products(portfolio_id, as_of)
@@ -105,16 +100,13 @@ pub(crate) async fn read_portfolio<T: ApiApplication>(
     } else {
         db.get_portfolio(portfolio_id, as_of).await
     }
-    .map_err(|err| {
-        event!(Level::ERROR, err = err.to_string());
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .map_err(Problem::internal)?
+    .ok_or(Problem::not_found("portfolio_not_found"))?;
 
     if !app.can_read_bid(&auth, portfolio.bidder_id.clone()).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(Problem::not_bid_owner());
     }
-    Ok(Json(portfolio))
+    Ok(fields.apply(portfolio))
 }
 
 /// Update a portfolio's demand and/or product associations.
@@ -134,74 +126,52 @@ pub(crate) async fn read_portfolio<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database operation failed
 pub(crate) async fn update_portfolio<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
-    Extension(config): Extension<Arc<AxumConfig>>,
-    Json(body): Json<
-        UpdatePortfolioDto<
-            <T::Repository as Repository>::DemandId,
-            <T::Repository as Repository>::ProductId,
+    Extension(queue): Extension<BatchQueue<T>>,
+    body: Result<
+        Json<
+            UpdatePortfolioDto<
+                <T::Repository as Repository>::DemandId,
+                <T::Repository as Repository>::ProductId,
+            >,
         >,
+        JsonRejection,
     >,
-) -> Result<Json<PortfolioRecord<T::Repository, T::PortfolioData>>, StatusCode> {
+) -> Result<Json<PortfolioRecord<T::Repository, T::PortfolioData>>, Problem> {
+    let Json(body) = body?;
     let as_of = app.now();
     let db = app.database();
     let bidder_id = db
         .get_portfolio_bidder_id(portfolio_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("portfolio_not_found"))?;
 
-    if !app.can_update_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+    if !app.can_update_bid(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_bid_owner());
     }
+    let actor = app.actor(&auth, bidder_id).await;
 
     let updated = match (body.demand, body.basis) {
         (Some(demand), Some(basis)) => {
-            db.update_portfolio(portfolio_id, demand, basis, as_of.clone())
+            db.update_portfolio(portfolio_id, demand, basis, actor, as_of.clone())
                 .await
         }
         (Some(demand), None) => {
-            db.update_portfolio_demand(portfolio_id, demand, as_of.clone())
+            db.update_portfolio_demand(portfolio_id, demand, actor, as_of.clone())
                 .await
         }
         (None, Some(basis)) => {
-            db.update_portfolio_basis(portfolio_id, basis, as_of.clone())
+            db.update_portfolio_basis(portfolio_id, basis, actor, as_of.clone())
                 .await
         }
         (None, None) => db.get_portfolio(portfolio_id, as_of.clone()).await,
     }
-    .map_err(|err| {
-        event!(Level::ERROR, err = err.to_string());
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or_else(|| {
-        event!(
-            Level::ERROR,
-            err = "failed to update portfolio after successful read"
-        );
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    .map_err(Problem::internal)?
+    .ok_or_else(|| Problem::internal("failed to update portfolio after successful read"))?;
 
-    if config.auto_solve {
-        tokio::spawn(async move {
-            let db = app.database();
-            let result = db.run_batch(as_of, app.solver(), Default::default()).await;
-
-            match result {
-                Err(err) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                Ok(Err(err)) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                _ => {}
-            };
-        });
-    };
+    queue.notify(as_of);
 
     Ok(Json(updated))
 }
@@ -223,65 +193,83 @@ pub(crate) async fn update_portfolio<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database operation failed
 pub(crate) async fn delete_portfolio<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
-    Extension(config): Extension<Arc<AxumConfig>>,
-) -> Result<Json<PortfolioRecord<T::Repository, T::PortfolioData>>, StatusCode> {
+    Extension(queue): Extension<BatchQueue<T>>,
+) -> Result<Json<PortfolioRecord<T::Repository, T::PortfolioData>>, Problem> {
     let as_of = app.now();
     let db = app.database();
     let bidder_id = db
         .get_portfolio_bidder_id(portfolio_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("portfolio_not_found"))?;
 
-    if !app.can_update_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+    if !app.can_update_bid(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_bid_owner());
     }
+    let actor = app.actor(&auth, bidder_id).await;
 
     let deleted = db
         .update_portfolio(
             portfolio_id,
             Default::default(),
             Default::default(),
+            actor,
             as_of.clone(),
         )
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or_else(|| {
-            event!(
-                Level::ERROR,
-                err = "failed to delete portfolio after successful read"
-            );
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    if config.auto_solve {
-        tokio::spawn(async move {
-            let db = app.database();
-            let result = db.run_batch(as_of, app.solver(), Default::default()).await;
+        .map_err(Problem::internal)?
+        .ok_or_else(|| Problem::internal("failed to delete portfolio after successful read"))?;
 
-            match result {
-                Err(err) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                Ok(Err(err)) => {
-                    event!(Level::ERROR, err = err.to_string());
-                }
-                _ => {}
-            };
-        });
-    };
+    queue.notify(as_of);
 
     Ok(Json(deleted))
 }
 
+/// Permanently erase a portfolio's application data and group histories.
+///
+/// Intended for requests for erasure, where soft-deleting the portfolio would
+/// retain the bidder's data indefinitely. Only a tombstone remains, after
+/// which the portfolio is treated as though it does not exist. The batch
+/// outcomes of the portfolio are retained.
+///
+/// # Authorization
+///
+/// Requires purge permission (`can_purge_bid`), which applies to the
+/// portfolios of every bidder.
+///
+/// # Returns
+///
+/// - `200 OK`: Portfolio purged successfully, returns the tombstone
+/// - `401 Unauthorized`: Missing purge permissions
+/// - `404 Not Found`: Portfolio does not exist (or was already purged)
+/// - `500 Internal Server Error`: Database operation failed
+pub(crate) async fn purge_portfolio<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
+    Extension(queue): Extension<BatchQueue<T>>,
+) -> Result<Json<Tombstone<T::Repository, <T::Repository as Repository>::PortfolioId>>, Problem> {
+    if !app.can_purge_bid(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = app.now();
+    let tombstone = <T::Repository as PortfolioRepository<T::PortfolioData>>::purge_portfolio(
+        app.database(),
+        portfolio_id,
+        as_of.clone(),
+    )
+    .await
+    .map_err(Problem::internal)?
+    .ok_or(Problem::not_found("portfolio_not_found"))?;
+
+    queue.notify(as_of);
+
+    Ok(Json(tombstone))
+}
+
 /// Request body for creating a new portfolio.
 #[derive(schemars::JsonSchema, serde::Deserialize)]
 #[schemars(inline)]
@@ -1,25 +1,31 @@
 use super::Id;
-use crate::{ApiApplication, config::AxumConfig};
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    config::AxumConfig,
+    limit::LimitQuery,
+    negotiate::{Accept, Negotiated},
+    problem::Problem,
+};
 
 use axum::{
-    Extension, Json,
+    Extension,
     extract::{Path, Query, State},
-    http::StatusCode,
 };
-use axum_extra::TypedHeader;
 use fts_core::{
     models::{Basis, DateTimeRangeQuery, DateTimeRangeResponse, Weights},
     ports::{PortfolioRepository as _, Repository},
 };
-use headers::{Authorization, authorization::Bearer};
 use std::sync::Arc;
-use tracing::{Level, event};
 
 /// Retrieve the historical changes to a portfolio's demand group.
 ///
 /// Returns a paginated list of demand group changes over time, showing
 /// how the portfolio's demand associations have evolved.
 ///
+/// A CSV request without a `limit` exports every change in the range as a
+/// single table, streamed as it is read rather than paginated.
+///
 /// # Authorization
 ///
 /// Requires read permission for the portfolio's bidder (`can_read_bid`).
@@ -32,18 +38,20 @@ use tracing::{Level, event};
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn get_portfolio_demand_history<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
     Extension(config): Extension<Arc<AxumConfig>>,
     Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
 ) -> Result<
-    Json<
+    Negotiated<
         DateTimeRangeResponse<
             Weights<<T::Repository as Repository>::DemandId>,
             <T::Repository as Repository>::DateTime,
         >,
     >,
-    StatusCode,
+    Problem,
 > {
     let db = app.database();
 
@@ -51,25 +59,28 @@ pub(crate) async fn get_portfolio_demand_history<T: ApiApplication>(
     let bidder_id = db
         .get_portfolio_bidder_id(portfolio_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("portfolio_not_found"))?;
 
     if !app.can_read_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(Problem::not_bid_owner());
+    }
+
+    if accept.is_csv() && !limit.is_requested() {
+        return Ok(accept.stream(move |sender| async move {
+            let history = app
+                .database()
+                .stream_portfolio_demand_history(portfolio_id, query);
+            sender.forward(history).await
+        }));
     }
 
     let history = db
-        .get_portfolio_demand_history(portfolio_id, query, config.page_limit)
+        .get_portfolio_demand_history(portfolio_id, query, limit.page_size(&config))
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(Problem::internal)?;
 
-    Ok(Json(history))
+    Ok(accept.respond(history))
 }
 
 /// Retrieve the historical changes to a portfolio's product group.
@@ -77,6 +88,9 @@ pub(crate) async fn get_portfolio_demand_history<T: ApiApplication>(
 /// Returns a paginated list of product group changes over time, showing
 /// how the portfolio's product associations have evolved.
 ///
+/// A CSV request without a `limit` exports every change in the range as a
+/// single table, streamed as it is read rather than paginated.
+///
 /// # Authorization
 ///
 /// Requires read permission for the portfolio's bidder (`can_read_bid`).
@@ -89,18 +103,20 @@ pub(crate) async fn get_portfolio_demand_history<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn get_portfolio_product_history<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
     Extension(config): Extension<Arc<AxumConfig>>,
     Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
 ) -> Result<
-    Json<
+    Negotiated<
         DateTimeRangeResponse<
             Basis<<T::Repository as Repository>::ProductId>,
             <T::Repository as Repository>::DateTime,
         >,
     >,
-    StatusCode,
+    Problem,
 > {
     let db = app.database();
 
@@ -108,23 +124,26 @@ pub(crate) async fn get_portfolio_product_history<T: ApiApplication>(
     let bidder_id = db
         .get_portfolio_bidder_id(portfolio_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("portfolio_not_found"))?;
 
     if !app.can_read_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(Problem::not_bid_owner());
+    }
+
+    if accept.is_csv() && !limit.is_requested() {
+        return Ok(accept.stream(move |sender| async move {
+            let history = app
+                .database()
+                .stream_portfolio_product_history(portfolio_id, query);
+            sender.forward(history).await
+        }));
     }
 
     let history = db
-        .get_portfolio_product_history(portfolio_id, query, config.page_limit)
+        .get_portfolio_product_history(portfolio_id, query, limit.page_size(&config))
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(Problem::internal)?;
 
-    Ok(Json(history))
+    Ok(accept.respond(history))
 }
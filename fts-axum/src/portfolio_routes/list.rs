@@ -1,26 +1,106 @@
-use axum::{Json, extract::State, http::StatusCode};
-use axum_extra::TypedHeader;
-use fts_core::{models::PortfolioRecord, ports::PortfolioRepository as _};
-use headers::{Authorization, authorization::Bearer};
-use tracing::{Level, event};
+use axum::{
+    Extension,
+    extract::{Query, RawQuery, State},
+    http::StatusCode,
+};
+use fts_core::{
+    models::{PortfolioQuery, PortfolioRecord},
+    ports::{Application, PortfolioRepository as _, Repository},
+};
+use std::sync::Arc;
 
-use crate::ApiApplication;
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    config::AxumConfig,
+    fields::{FieldsQuery, Sparse},
+    limit::LimitQuery,
+    negotiate::next_link,
+    problem::Problem,
+};
 
+/// The parameters of `PortfolioQuery`, which the link to the next page replaces
+const PORTFOLIO_QUERY: &[&str] = &["active_only", "has_curve", "touched_since", "after"];
+
+/// Query parameters filtering and paginating the listing of portfolios.
+type ListFilter<T> = PortfolioQuery<
+    <<T as Application>::Repository as Repository>::PortfolioId,
+    <<T as Application>::Repository as Repository>::DateTime,
+>;
+
+/// Query parameters for listing portfolios.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+pub(crate) struct ListQuery<T> {
+    /// Only return portfolios whose basis references this product
+    product_id: Option<T>,
+    /// Expand the product groups to include any child products created through partitioning
+    #[serde(default)]
+    expand: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn list_portfolios<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-) -> Result<Json<Vec<PortfolioRecord<T::Repository, T::PortfolioData>>>, StatusCode> {
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(ListQuery { product_id, expand }): Query<
+        ListQuery<<T::Repository as Repository>::ProductId>,
+    >,
+    Query(query): Query<ListFilter<T>>,
+    Query(limit): Query<LimitQuery>,
+    Query(fields): Query<FieldsQuery>,
+    RawQuery(raw): RawQuery,
+) -> Result<Sparse<Vec<PortfolioRecord<T::Repository, T::PortfolioData>>>, Problem> {
     let db = app.database();
     let bidder_ids = app.can_query_bid(&auth).await;
 
     if bidder_ids.is_empty() {
-        Err(StatusCode::UNAUTHORIZED)
-    } else {
-        Ok(Json(db.query_portfolio(&bidder_ids).await.map_err(
-            |err| {
-                event!(Level::ERROR, err = err.to_string());
-                StatusCode::INTERNAL_SERVER_ERROR
-            },
-        )?))
+        return Err(Problem::not_authorized());
+    }
+
+    if product_id.is_none() && !expand {
+        let page = db
+            .query_portfolio(&bidder_ids, query, limit.page_size(&config))
+            .await
+            .map_err(Problem::internal)?;
+        let next = page
+            .more
+            .map(|more| next_link(raw.as_deref(), PORTFOLIO_QUERY, &more))
+            .transpose()
+            .map_err(Problem::internal)?;
+        return Ok(fields.apply(page.results).with_next(next));
+    }
+
+    if !query.active_only
+        || query.has_curve
+        || query.touched_since.is_some()
+        || query.after.is_some()
+        || limit.is_requested()
+    {
+        return Err(Problem::new(StatusCode::BAD_REQUEST, "query_invalid").with_detail(
+            "filters and pagination do not apply to the portfolios of a product or when expanded",
+        ));
     }
+
+    // When expanded, the product filter applies to the expanded basis, so that
+    // filtering on a child product includes portfolios defined on its ancestors
+    let portfolios = match product_id {
+        Some(product_id) if expand => db
+            .query_portfolio_with_expanded_products(&bidder_ids, app.now())
+            .await
+            .map(|portfolios| {
+                portfolios
+                    .into_iter()
+                    .filter(|portfolio| portfolio.basis.contains_key(&product_id))
+                    .collect()
+            }),
+        Some(product_id) => db.query_portfolio_by_product(&bidder_ids, product_id).await,
+        None => {
+            db.query_portfolio_with_expanded_products(&bidder_ids, app.now())
+                .await
+        }
+    };
+
+    Ok(fields.apply(portfolios.map_err(Problem::internal)?))
 }
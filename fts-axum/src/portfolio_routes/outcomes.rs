@@ -1,24 +1,35 @@
 use super::Id;
-use crate::{ApiApplication, config::AxumConfig};
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    cache,
+    config::AxumConfig,
+    limit::LimitQuery,
+    negotiate::{Accept, Negotiated},
+    problem::Problem,
+};
 
 use axum::{
-    Extension, Json,
+    Extension,
     extract::{Path, Query, State},
-    http::StatusCode,
 };
 use axum_extra::TypedHeader;
 use fts_core::{
     models::{DateTimeRangeQuery, DateTimeRangeResponse},
     ports::{BatchRepository, PortfolioRepository as _, Repository, Solver},
 };
-use headers::{Authorization, authorization::Bearer};
+use headers::CacheControl;
 use std::sync::Arc;
-use tracing::{Level, event};
 
 /// Retrieve batch auction outcomes for a portfolio.
 ///
 /// Returns the historical allocations computed by the solver for this
-/// portfolio across multiple batch auctions.
+/// portfolio across multiple batch auctions. A page whose interval has ended
+/// and whose outcomes have all been superseded never changes, and so may be
+/// cached indefinitely by the client.
+///
+/// A CSV request without a `limit` exports every outcome in the range as a
+/// single table, streamed as it is read rather than paginated.
 ///
 /// # Authorization
 ///
@@ -32,22 +43,27 @@ use tracing::{Level, event};
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn get_portfolio_outcomes<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { portfolio_id }): Path<Id<<T::Repository as Repository>::PortfolioId>>,
     Extension(config): Extension<Arc<AxumConfig>>,
     Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
 ) -> Result<
-    Json<
-        DateTimeRangeResponse<
-            <T::Solver as Solver<
-                <T::Repository as Repository>::DemandId,
-                <T::Repository as Repository>::PortfolioId,
-                <T::Repository as Repository>::ProductId,
-            >>::PortfolioOutcome,
-            <T::Repository as Repository>::DateTime,
+    (
+        TypedHeader<CacheControl>,
+        Negotiated<
+            DateTimeRangeResponse<
+                <T::Solver as Solver<
+                    <T::Repository as Repository>::DemandId,
+                    <T::Repository as Repository>::PortfolioId,
+                    <T::Repository as Repository>::ProductId,
+                >>::PortfolioOutcome,
+                <T::Repository as Repository>::DateTime,
+            >,
         >,
-    >,
-    StatusCode,
+    ),
+    Problem,
 > {
     let db = app.database();
 
@@ -55,23 +71,30 @@ pub(crate) async fn get_portfolio_outcomes<T: ApiApplication>(
     let bidder_id = db
         .get_portfolio_bidder_id(portfolio_id.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .map_err(Problem::internal)?
+        .ok_or(Problem::not_found("portfolio_not_found"))?;
 
     if !app.can_read_bid(&auth, bidder_id).await {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(Problem::not_bid_owner());
+    }
+
+    if accept.is_csv() && !limit.is_requested() {
+        let outcomes = accept.stream(move |sender| async move {
+            let outcomes = app
+                .database()
+                .stream_portfolio_outcomes(portfolio_id, query);
+            sender.forward(outcomes).await
+        });
+        return Ok((TypedHeader(cache::revalidate(false)), outcomes));
     }
 
+    let before = query.before.clone();
     let outcomes = db
-        .get_portfolio_outcomes(portfolio_id, query, config.page_limit)
+        .get_portfolio_outcomes(portfolio_id, query, limit.page_size(&config))
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(Problem::internal)?;
 
-    Ok(Json(outcomes))
+    // Outcomes of a portfolio are confidential, so are never cached publicly
+    let cache_control = cache::outcomes(before.as_ref(), &app.now(), &outcomes.results, false);
+    Ok((TypedHeader(cache_control), accept.respond(outcomes)))
 }
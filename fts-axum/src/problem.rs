@@ -0,0 +1,163 @@
+//! Structured error responses, following RFC 7807 ("problem details").
+//!
+//! Every error produced by the API is reported with the appropriate status
+//! code and an `application/problem+json` body. In addition to the standard
+//! members, the body carries a machine-readable `code` (e.g.
+//! `demand_not_found` or `not_bid_owner`), allowing clients to distinguish
+//! errors that share a status code.
+
+use aide::{
+    OperationOutput,
+    generate::GenContext,
+    openapi::{Operation, Response},
+};
+use axum::{
+    Json,
+    extract::rejection::JsonRejection,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response as AxumResponse},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt::Display;
+use tracing::{Level, event};
+
+/// The media type of problem details
+pub(crate) const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The body of an error response.
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct ProblemDetails {
+    /// A URI identifying the problem type (always `about:blank`, as the
+    /// problem is identified by its `code`)
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// A short summary of the problem, i.e. the reason phrase of the status code
+    pub title: String,
+    /// The HTTP status code
+    pub status: u16,
+    /// A machine-readable code identifying the problem
+    pub code: String,
+    /// A human-readable explanation specific to this occurrence of the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// An error response of the API.
+#[derive(Debug)]
+pub(crate) struct Problem {
+    status: StatusCode,
+    code: &'static str,
+    detail: Option<String>,
+}
+
+impl Problem {
+    /// A problem with the given status and code.
+    pub(crate) fn new(status: StatusCode, code: &'static str) -> Self {
+        Self {
+            status,
+            code,
+            detail: None,
+        }
+    }
+
+    /// Attach an explanation to the problem.
+    pub(crate) fn with_detail(mut self, detail: impl Display) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// The requester lacks the permission for this operation.
+    pub(crate) fn not_authorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "not_authorized")
+    }
+
+    /// The requester may not access the bids of the bidder owning the resource.
+    pub(crate) fn not_bid_owner() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "not_bid_owner")
+    }
+
+    /// The resource identified by the path does not exist.
+    pub(crate) fn not_found(code: &'static str) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code)
+    }
+
+    /// An unexpected failure, such as an error from the repository or the solver.
+    ///
+    /// The error is logged, but not reported to the client.
+    pub(crate) fn internal(err: impl Display) -> Self {
+        event!(Level::ERROR, err = err.to_string());
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error")
+    }
+
+    /// The body of a request containing a demand curve could not be parsed.
+    ///
+    /// Bodies that are well-formed JSON but fail validation are reported as
+    /// invalid curves, while other rejections are reported as usual.
+    pub(crate) fn curve_invalid(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(err) => {
+                Self::new(err.status(), "curve_invalid").with_detail(err.body_text())
+            }
+            rejection => rejection.into(),
+        }
+    }
+}
+
+impl From<JsonRejection> for Problem {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match rejection {
+            JsonRejection::JsonDataError(_) => "invalid_body",
+            JsonRejection::JsonSyntaxError(_) => "malformed_body",
+            JsonRejection::MissingJsonContentType(_) => "unsupported_media_type",
+            _ => "unreadable_body",
+        };
+        Self::new(rejection.status(), code).with_detail(rejection.body_text())
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> AxumResponse {
+        let body = ProblemDetails {
+            kind: "about:blank".to_owned(),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_owned(),
+            status: self.status.as_u16(),
+            code: self.code.to_owned(),
+            detail: self.detail,
+        };
+        (
+            self.status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+impl OperationOutput for Problem {
+    type Inner = ProblemDetails;
+
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<Response> {
+        // Identical to the JSON response, save for the media type
+        let mut response = Json::<ProblemDetails>::operation_response(ctx, operation)?;
+        response.content = response
+            .content
+            .into_iter()
+            .map(|(_, media)| (PROBLEM_JSON.to_owned(), media))
+            .collect();
+        Some(response)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, Response)> {
+        Self::operation_response(ctx, operation)
+            .map(|res| vec![(None, res)])
+            .unwrap_or_default()
+    }
+}
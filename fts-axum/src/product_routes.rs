@@ -1,8 +1,9 @@
 //! REST API endpoints for product operations.
 //!
 //! This module provides operations for managing the product hierarchy, including
-//! creating root products and partitioning them into child products. Products
-//! represent the tradeable assets in the flow trading system.
+//! creating root products, partitioning them into child products, listing the
+//! product catalogue and searching for products by their application data. Products represent the tradeable
+//! assets in the flow trading system.
 
 use crate::ApiApplication;
 use aide::axum::{
@@ -16,6 +17,9 @@ use crud::*;
 mod outcomes;
 use outcomes::*;
 
+mod search;
+use search::*;
+
 /// Path parameter for product-specific endpoints.
 #[derive(serde::Deserialize, schemars::JsonSchema)]
 #[schemars(inline)]
@@ -27,13 +31,33 @@ struct Id<T> {
 /// Creates a router with product-related endpoints.
 pub fn router<T: ApiApplication>() -> ApiRouter<T> {
     ApiRouter::new()
-        // TODO: figure out approach to querying!
-        .api_route_with("/", post(create_product::<T>), |route| {
+        .api_route(
+            "/",
+            get_with(list_products::<T>, |route| {
+                route.security_requirement("jwt").tag("product")
+            })
+            .post_with(create_product::<T>, |route| {
+                route
+                    .security_requirement("jwt")
+                    .tag("product")
+                    .tag("admin")
+            }),
+        )
+        .api_route_with("/bulk", post(create_products::<T>), |route| {
             route
                 .security_requirement("jwt")
                 .tag("product")
                 .tag("admin")
         })
+        .api_route_with("/search", post(search_products::<T>), |route| {
+            route.security_requirement("jwt").tag("product")
+        })
+        .api_route_with("/statistics", get(get_market_statistics::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("product")
+                .tag("outcome")
+        })
         .api_route(
             "/{product_id}",
             get_with(read_product::<T>, |route| {
@@ -56,4 +80,20 @@ pub fn router<T: ApiApplication>() -> ApiRouter<T> {
                     .tag("outcome")
             },
         )
+        // aide cannot describe an event stream, so this endpoint is not part
+        // of the OpenAPI documentation
+        .route(
+            "/{product_id}/outcomes/stream",
+            axum::routing::get(stream_product_outcomes::<T>),
+        )
+        .api_route_with(
+            "/{product_id}/summary",
+            get(get_product_summary::<T>),
+            |route| {
+                route
+                    .security_requirement("jwt")
+                    .tag("product")
+                    .tag("outcome")
+            },
+        )
 }
@@ -1,18 +1,26 @@
 use super::Id;
-use crate::ApiApplication;
+use crate::{
+    ApiApplication,
+    as_of::AsOfQuery,
+    auth::Auth,
+    cache,
+    config::AxumConfig,
+    fields::{FieldsQuery, Sparse},
+    problem::Problem,
+};
 
 use axum::{
-    Json,
-    extract::{Path, State},
+    Extension, Json,
+    extract::{Path, Query, State, rejection::JsonRejection},
     http::StatusCode,
 };
 use axum_extra::TypedHeader;
 use fts_core::{
-    models::ProductRecord,
+    models::{ProductPartition, ProductRecord},
     ports::{ProductRepository as _, Repository},
 };
-use headers::{Authorization, authorization::Bearer};
-use tracing::{Level, event};
+use headers::CacheControl;
+use std::sync::Arc;
 
 /// Create a new root product.
 ///
@@ -34,15 +42,17 @@ use tracing::{Level, event};
 /// - `500 Internal Server Error`: Database operation failed
 pub(crate) async fn create_product<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
-    Json(product_data): Json<T::ProductData>,
+    Auth(auth): Auth<T>,
+    product_data: Result<Json<T::ProductData>, JsonRejection>,
 ) -> Result<
     (
         StatusCode,
         Json<ProductRecord<T::Repository, T::ProductData>>,
     ),
-    StatusCode,
+    Problem,
 > {
+    let Json(product_data) = product_data?;
+
     // Notice that can_create does not have a &self parameter, while can_read above does.
     // In this case, we expect D::can_create to check if the `auth` corresponds to an admin
     // user.
@@ -53,24 +63,76 @@ pub(crate) async fn create_product<T: ApiApplication>(
         let product_record = db
             .create_product(product_id, product_data, as_of)
             .await
-            .map_err(|err| {
-                event!(Level::ERROR, err = err.to_string());
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+            .map_err(Problem::internal)?;
 
         Ok((StatusCode::CREATED, Json(product_record)))
     } else {
-        Err(StatusCode::UNAUTHORIZED)
+        Err(Problem::not_authorized())
+    }
+}
+
+/// Create several new root products at once.
+///
+/// Equivalent to creating each product in turn, except that the products are
+/// created atomically: if any cannot be created, none are.
+///
+/// # Request Body
+///
+/// An array of application-specific product data. The product IDs will be
+/// generated based on this data.
+///
+/// # Authorization
+///
+/// Requires `can_manage_products` permission.
+///
+/// # Returns
+///
+/// - `201 Created`: Products created successfully, returns the product records in the same order as the request
+/// - `401 Unauthorized`: Missing management permissions
+/// - `500 Internal Server Error`: Database operation failed
+pub(crate) async fn create_products<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    products: Result<Json<Vec<T::ProductData>>, JsonRejection>,
+) -> Result<
+    (
+        StatusCode,
+        Json<Vec<ProductRecord<T::Repository, T::ProductData>>>,
+    ),
+    Problem,
+> {
+    let Json(products) = products?;
+
+    if !app.can_manage_products(&auth).await {
+        return Err(Problem::not_authorized());
     }
+
+    // As when partitioning, the products share a single creation time
+    let as_of = app.now();
+    let products = products
+        .into_iter()
+        .map(|product_data| (app.generate_product_id(&product_data).0, product_data))
+        .collect::<Vec<_>>();
+
+    let product_records = app
+        .database()
+        .create_products(products, as_of)
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok((StatusCode::CREATED, Json(product_records)))
 }
 
 /// Retrieve a product's data.
 ///
-/// Returns the application-specific data associated with the product.
+/// Returns the application-specific data associated with the product. As its
+/// basis changes when the product is partitioned, the response must be
+/// revalidated (using its `ETag`) before it is reused.
 ///
 /// # Authorization
 ///
-/// Requires `can_view_products` permission.
+/// Requires `can_view_products` permission, and `can_audit` permission to
+/// read the product as of another time.
 ///
 /// # Returns
 ///
@@ -80,25 +142,36 @@ pub(crate) async fn create_product<T: ApiApplication>(
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn read_product<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { product_id }): Path<Id<<T::Repository as Repository>::ProductId>>,
-) -> Result<Json<ProductRecord<T::Repository, T::ProductData>>, StatusCode> {
-    let as_of = app.now();
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(as_of): Query<AsOfQuery<<T::Repository as Repository>::DateTime>>,
+    Query(fields): Query<FieldsQuery>,
+) -> Result<
+    (
+        TypedHeader<CacheControl>,
+        Sparse<ProductRecord<T::Repository, T::ProductData>>,
+    ),
+    Problem,
+> {
+    // A shared cache must not serve an audited view to those who may not audit
+    let public = config.public_cache && !as_of.is_requested();
     let db = app.database();
 
     if app.can_view_products(&auth).await {
+        let as_of = as_of.resolve(&app, &auth).await?;
         let product_record = db
             .get_product(product_id, as_of)
             .await
-            .map_err(|err| {
-                event!(Level::ERROR, err = err.to_string());
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-            .ok_or(StatusCode::NOT_FOUND)?;
+            .map_err(Problem::internal)?
+            .ok_or(Problem::not_found("product_not_found"))?;
 
-        Ok(Json(product_record))
+        Ok((
+            TypedHeader(cache::revalidate(public)),
+            fields.apply(product_record),
+        ))
     } else {
-        Err(StatusCode::UNAUTHORIZED)
+        Err(Problem::not_authorized())
     }
 }
 
@@ -114,29 +187,33 @@ pub(crate) async fn read_product<T: ApiApplication>(
 ///
 /// # Returns
 ///
-/// - `201 Created`: List of created child product records, in the same order as the request
+/// - `201 Created`: The updated parent product, whose basis reflects the new
+///   partition, along with the created child product records, in the same
+///   order as the request
 /// - `401 Unauthorized`: Missing management permissions
 /// - `403 Forbidden`: The product has already been partitioned
 /// - `404 Not Found`: Parent product does not exist
 /// - `500 Internal Server Error`: Database operation failed
 pub(crate) async fn update_product<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { product_id }): Path<Id<<T::Repository as Repository>::ProductId>>,
-    Json(children): Json<Vec<PartitionItem<T::ProductData>>>,
+    children: Result<Json<Vec<PartitionItem<T::ProductData>>>, JsonRejection>,
 ) -> Result<
     (
         StatusCode,
-        Json<Vec<ProductRecord<T::Repository, T::ProductData>>>,
+        Json<ProductPartition<T::Repository, T::ProductData>>,
     ),
-    StatusCode,
+    Problem,
 > {
+    let Json(children) = children?;
     let as_of = app.now();
     let db = app.database();
 
     if app.can_manage_products(&auth).await {
         if children.is_empty() {
-            return Err(StatusCode::BAD_REQUEST);
+            return Err(Problem::new(StatusCode::BAD_REQUEST, "partition_empty")
+                .with_detail("a product must be partitioned into at least one child"));
         }
 
         // compile the specified data and the ids into the appropriate format for the partition function
@@ -152,21 +229,34 @@ pub(crate) async fn update_product<T: ApiApplication>(
 
         // do the partitioning
         let child_records = db
-            .partition_product(product_id.clone(), child_data, as_of)
+            .partition_product(product_id.clone(), child_data, as_of.clone())
             .await
-            .map_err(|err| {
-                event!(Level::ERROR, err = err.to_string());
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-            .ok_or(StatusCode::NOT_FOUND)?;
+            .map_err(Problem::internal)?
+            .ok_or(Problem::not_found("product_not_found"))?;
 
         if child_records.is_empty() {
-            Err(StatusCode::FORBIDDEN)
-        } else {
-            Ok((StatusCode::CREATED, Json(child_records)))
+            return Err(Problem::new(
+                StatusCode::FORBIDDEN,
+                "product_already_partitioned",
+            ));
         }
+
+        // re-read the parent, so that its basis reflects the new partition
+        let parent = db
+            .get_product(product_id, as_of)
+            .await
+            .map_err(Problem::internal)?
+            .ok_or(Problem::not_found("product_not_found"))?;
+
+        Ok((
+            StatusCode::CREATED,
+            Json(ProductPartition {
+                parent,
+                children: child_records,
+            }),
+        ))
     } else {
-        Err(StatusCode::UNAUTHORIZED)
+        Err(Problem::not_authorized())
     }
 }
 
@@ -1,24 +1,41 @@
 use super::Id;
-use crate::{ApiApplication, config::AxumConfig};
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    cache,
+    config::AxumConfig,
+    limit::LimitQuery,
+    negotiate::{Accept, Negotiated},
+    problem::Problem,
+};
 
 use axum::{
     Extension, Json,
     extract::{Path, Query, State},
-    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
 };
 use axum_extra::TypedHeader;
 use fts_core::{
-    models::{DateTimeRangeQuery, DateTimeRangeResponse},
-    ports::{BatchRepository as _, ProductRepository as _, Repository, Solver},
+    models::{
+        DateTimeRangeQuery, DateTimeRangeResponse, MarketStatistics, PriceInterval, PriceSummary,
+        ValueRecord,
+    },
+    ports::{Application, BatchRepository, ProductRepository as _, Repository, Solver},
 };
-use headers::{Authorization, authorization::Bearer};
-use std::sync::Arc;
+use futures_util::Stream;
+use headers::CacheControl;
+use std::{collections::VecDeque, sync::Arc};
 use tracing::{Level, event};
 
 /// Retrieve batch auction outcomes for a product.
 ///
 /// Returns the historical clearing prices computed by the solver for this
-/// product across multiple batch auctions.
+/// product across multiple batch auctions. A page whose interval has ended
+/// and whose outcomes have all been superseded never changes, and so may be
+/// cached indefinitely.
+///
+/// A CSV request without a `limit` exports every outcome in the range as a
+/// single table, streamed as it is read rather than paginated.
 ///
 /// # Authorization
 ///
@@ -31,56 +48,318 @@ use tracing::{Level, event};
 /// - `500 Internal Server Error`: Database query failed
 pub(crate) async fn get_product_outcomes<T: ApiApplication>(
     State(app): State<T>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Auth(auth): Auth<T>,
     Path(Id { product_id }): Path<Id<<T::Repository as Repository>::ProductId>>,
     Extension(config): Extension<Arc<AxumConfig>>,
     Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
 ) -> Result<
-    Json<
-        DateTimeRangeResponse<
-            <T::Solver as Solver<
-                <T::Repository as Repository>::DemandId,
-                <T::Repository as Repository>::PortfolioId,
-                <T::Repository as Repository>::ProductId,
-            >>::ProductOutcome,
-            <T::Repository as Repository>::DateTime,
+    (
+        TypedHeader<CacheControl>,
+        Negotiated<
+            DateTimeRangeResponse<
+                <T::Solver as Solver<
+                    <T::Repository as Repository>::DemandId,
+                    <T::Repository as Repository>::PortfolioId,
+                    <T::Repository as Repository>::ProductId,
+                >>::ProductOutcome,
+                <T::Repository as Repository>::DateTime,
+            >,
         >,
-    >,
-    (StatusCode, String),
+    ),
+    Problem,
 > {
     let as_of = app.now();
     let db = app.database();
 
     if !app.can_view_products(&auth).await {
-        return Err((StatusCode::UNAUTHORIZED, "not authorized".to_string()));
+        return Err(Problem::not_authorized());
     }
 
     // First we get the existing data.
     let _product_data = db
-        .get_product(product_id.clone(), as_of)
+        .get_product(product_id.clone(), as_of.clone())
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("failed to get product {}", product_id),
-            )
-        })?
-        .ok_or((
-            StatusCode::NOT_FOUND,
-            format!("unknown product {}", product_id),
-        ))?;
+        .map_err(Problem::internal)?
+        .ok_or_else(|| {
+            Problem::not_found("product_not_found")
+                .with_detail(format!("unknown product {}", product_id))
+        })?;
+
+    if accept.is_csv() && !limit.is_requested() {
+        let outcomes = accept.stream(move |sender| async move {
+            let outcomes = app.database().stream_product_outcomes(product_id, query);
+            sender.forward(outcomes).await
+        });
+        return Ok((
+            TypedHeader(cache::revalidate(config.public_cache)),
+            outcomes,
+        ));
+    }
 
+    let before = query.before.clone();
     let outcomes = db
-        .get_product_outcomes(product_id.clone(), query, config.page_limit)
+        .get_product_outcomes(product_id.clone(), query, limit.page_size(&config))
         .await
-        .map_err(|err| {
-            event!(Level::ERROR, err = err.to_string());
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("failed to get product outcomes {}", product_id),
-            )
+        .map_err(Problem::internal)?;
+
+    let cache_control = cache::outcomes(
+        before.as_ref(),
+        &as_of,
+        &outcomes.results,
+        config.public_cache,
+    );
+    Ok((TypedHeader(cache_control), accept.respond(outcomes)))
+}
+
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+pub(crate) struct SummaryQuery<T> {
+    /// The width of each bucket (defaults to an hour)
+    #[serde(default)]
+    interval: PriceInterval,
+    /// The upper bound (exclusive) for the batch times
+    before: Option<T>,
+    /// The lower bound (inclusive) for the batch times
+    after: Option<T>,
+}
+
+/// Retrieve the aggregated price history for a product.
+///
+/// Summarizes the batch auction outcomes for this product into hourly or
+/// daily buckets, reporting the open, high, low, and close clearing prices,
+/// the time-weighted average price, and the traded volume of each bucket.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated summary records, one per bucket
+/// - `401 Unauthorized`: Missing view permissions
+/// - `404 Not Found`: Product does not exist
+/// - `500 Internal Server Error`: Database query failed
+pub(crate) async fn get_product_summary<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { product_id }): Path<Id<<T::Repository as Repository>::ProductId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<SummaryQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
+) -> Result<
+    Negotiated<DateTimeRangeResponse<PriceSummary, <T::Repository as Repository>::DateTime>>,
+    Problem,
+> {
+    let as_of = app.now();
+    let db = app.database();
+
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let _product_data = db
+        .get_product(product_id.clone(), as_of.clone())
+        .await
+        .map_err(Problem::internal)?
+        .ok_or_else(|| {
+            Problem::not_found("product_not_found")
+                .with_detail(format!("unknown product {}", product_id))
+        })?;
+
+    let summary = <T::Repository as BatchRepository<T::Solver>>::get_product_summary(
+        db,
+        product_id.clone(),
+        query.interval,
+        DateTimeRangeQuery {
+            before: query.before,
+            after: query.after,
+        },
+        as_of,
+        config.time_unit,
+        limit.page_size(&config),
+    )
+    .await
+    .map_err(Problem::internal)?;
+
+    Ok(accept.respond(summary))
+}
+
+/// Retrieve market-wide statistics for every product.
+///
+/// Reports, for each product which has been traded or is referenced by an
+/// active portfolio, the most recent clearing price, the volume traded within
+/// the window given by `after` and `before` (by default, all time), the open
+/// interest, and the number of active portfolios and demands. No individual
+/// bid is revealed.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The statistics of every product
+/// - `401 Unauthorized`: Missing view permissions
+/// - `500 Internal Server Error`: Database query failed
+pub(crate) async fn get_market_statistics<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<Json<MarketStatistics<T::Repository>>, Problem> {
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let statistics = <T::Repository as BatchRepository<T::Solver>>::get_market_statistics(
+        app.database(),
+        query,
+        app.now(),
+        config.time_unit,
+    )
+    .await
+    .map_err(Problem::internal)?;
+
+    Ok(Json(statistics))
+}
+
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+pub(crate) struct StreamQuery<T> {
+    /// The lower bound (inclusive) for the batch times
+    after: Option<T>,
+}
+
+/// Stream batch auction outcomes for a product as server-sent events.
+///
+/// Every outcome at or after `after` (or every future outcome, if omitted) is
+/// sent as an `outcome` event, whose id is the time of the batch and whose data
+/// is the JSON-encoded outcome record. There is no notification mechanism for
+/// completed batches, so the repository is polled at the configured interval.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: An event stream of outcome records
+/// - `401 Unauthorized`: Missing view permissions
+/// - `404 Not Found`: Product does not exist
+/// - `500 Internal Server Error`: Database query failed
+pub(crate) async fn stream_product_outcomes<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { product_id }): Path<Id<<T::Repository as Repository>::ProductId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<StreamQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, Problem> {
+    let as_of = app.now();
+
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let _product_data = app
+        .database()
+        .get_product(product_id.clone(), as_of.clone())
+        .await
+        .map_err(Problem::internal)?
+        .ok_or_else(|| {
+            Problem::not_found("product_not_found")
+                .with_detail(format!("unknown product {}", product_id))
         })?;
 
-    Ok(Json(outcomes))
+    // Absent an explicit lower bound, we only stream outcomes from now on
+    let after = query.after.unwrap_or(as_of);
+
+    let state = OutcomeStream::<T> {
+        app,
+        product_id,
+        after,
+        last_sent: None,
+        pending: VecDeque::new(),
+        interval: tokio::time::interval(config.poll_interval),
+        page_limit: config.page_limit,
+    };
+
+    let stream = futures_util::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(outcome) = state.pending.pop_front() {
+                let event = Event::default()
+                    .event("outcome")
+                    .id(outcome.valid_from.to_string())
+                    .json_data(&outcome);
+                state.last_sent = Some(outcome.valid_from);
+                return Some((event, state));
+            }
+
+            state.interval.tick().await;
+            if let Err(err) = state.poll().await {
+                event!(Level::ERROR, err = err.to_string());
+                return None;
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+type ProductOutcome<T> = <<T as Application>::Solver as Solver<
+    <<T as Application>::Repository as Repository>::DemandId,
+    <<T as Application>::Repository as Repository>::PortfolioId,
+    <<T as Application>::Repository as Repository>::ProductId,
+>>::ProductOutcome;
+
+/// The state of an outcome stream between polls of the repository.
+struct OutcomeStream<T: ApiApplication> {
+    app: T,
+    product_id: <T::Repository as Repository>::ProductId,
+    after: <T::Repository as Repository>::DateTime,
+    last_sent: Option<<T::Repository as Repository>::DateTime>,
+    pending: VecDeque<ValueRecord<<T::Repository as Repository>::DateTime, ProductOutcome<T>>>,
+    interval: tokio::time::Interval,
+    page_limit: usize,
+}
+
+impl<T: ApiApplication> OutcomeStream<T> {
+    /// Queue every outcome newer than the last one sent, in chronological order.
+    async fn poll(&mut self) -> Result<(), <T::Repository as Repository>::Error> {
+        let mut query = DateTimeRangeQuery {
+            before: None,
+            after: Some(self.last_sent.clone().unwrap_or(self.after.clone())),
+        };
+
+        loop {
+            let page = <T::Repository as BatchRepository<T::Solver>>::get_product_outcomes(
+                self.app.database(),
+                self.product_id.clone(),
+                query,
+                self.page_limit,
+            )
+            .await?;
+
+            // Pages are ordered from most to least recent
+            for outcome in page.results {
+                // The lower bound of the query is inclusive, so we skip the
+                // outcome we have already sent
+                if self
+                    .last_sent
+                    .as_ref()
+                    .is_some_and(|last_sent| outcome.valid_from <= *last_sent)
+                {
+                    continue;
+                }
+                self.pending.push_front(outcome);
+            }
+
+            match page.more {
+                Some(more) => query = more,
+                None => return Ok(()),
+            }
+        }
+    }
 }
@@ -0,0 +1,98 @@
+use crate::{
+    ApiApplication, as_of::AsOfQuery, auth::Auth, config::AxumConfig, limit::LimitQuery,
+    problem::Problem,
+};
+
+use axum::{
+    Extension, Json,
+    extract::{Query, State, rejection::JsonRejection},
+    http::StatusCode,
+};
+use fts_core::{
+    models::{ProductQuery, ProductQueryResponse, ProductSearch, ProductSearchResponse},
+    ports::{ProductRepository as _, Repository},
+};
+use std::sync::Arc;
+
+/// List the product catalogue.
+///
+/// Returns the products existing as of `as_of`, ordered by product id. Only
+/// the root products are listed unless `include_tree` is set, in which case
+/// the products created by partitioning are listed as well. Each record
+/// reflects the partition tree as of the same time.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission, and `can_audit` permission to
+/// list the products as of another time.
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated product records
+/// - `401 Unauthorized`: Missing view permissions
+/// - `500 Internal Server Error`: Database query failed
+pub(crate) async fn list_products<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<ProductQuery<<T::Repository as Repository>::ProductId>>,
+    Query(as_of): Query<AsOfQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+) -> Result<Json<ProductQueryResponse<T::Repository, T::ProductData>>, Problem> {
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = as_of.resolve(&app, &auth).await?;
+
+    let products = app
+        .database()
+        .query_products(query, as_of, limit.page_size(&config))
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(Json(products))
+}
+
+/// Search for products by their application data.
+///
+/// Returns the products whose application data satisfies every filter,
+/// ordered by product id. Each filter compares the field at a JSON path
+/// against a value, e.g. `{ "path": "$.kind", "op": "eq", "value": "energy" }`.
+///
+/// # Authorization
+///
+/// Requires `can_view_products` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated product records
+/// - `400 Bad Request`: A filter path is not a JSON path
+/// - `401 Unauthorized`: Missing view permissions
+/// - `500 Internal Server Error`: Database query failed
+pub(crate) async fn search_products<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(limit): Query<LimitQuery>,
+    query: Result<Json<ProductSearch<<T::Repository as Repository>::ProductId>>, JsonRejection>,
+) -> Result<Json<ProductSearchResponse<T::Repository, T::ProductData>>, Problem> {
+    let Json(query) = query?;
+
+    if !app.can_view_products(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    if let Some(filter) = query.filters.iter().find(|filter| !filter.has_valid_path()) {
+        return Err(Problem::new(StatusCode::BAD_REQUEST, "filter_invalid")
+            .with_detail(format!("{} is not a JSON path", filter.path)));
+    }
+
+    let products = app
+        .database()
+        .search_products(query, app.now(), limit.page_size(&config))
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(Json(products))
+}
@@ -0,0 +1,52 @@
+//! Reloading the configuration of a running server.
+//!
+//! Handlers read the configuration (page limits, polling intervals, and so
+//! on) from the extensions of each request, rather than capturing it when the
+//! router is built. This middleware inserts the configuration current at the
+//! time the request arrives, so that a new configuration takes effect for
+//! subsequent requests without restarting the server (and so without dropping
+//! any open connection). Requests already in flight keep the configuration
+//! they started with.
+
+use crate::{ApiApplication, batch_queue::BatchQueue, config::AxumConfig};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// The source of the current configuration, and the queue executing batches
+/// while `auto_solve` is enabled.
+pub(crate) struct Current<T: ApiApplication> {
+    pub(crate) config: watch::Receiver<Arc<AxumConfig>>,
+    pub(crate) queue: BatchQueue<T>,
+}
+
+impl<T: ApiApplication> Clone for Current<T> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+/// Middleware providing every request with the current configuration.
+pub(crate) async fn current<T: ApiApplication>(
+    State(current): State<Current<T>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let config = current.config.borrow().clone();
+    // While disabled, bid mutations notify a queue which ignores them
+    let queue = if config.auto_solve {
+        current.queue
+    } else {
+        BatchQueue::disabled()
+    };
+    request.extensions_mut().insert(queue);
+    request.extensions_mut().insert(config);
+    next.run(request).await
+}
@@ -0,0 +1,85 @@
+//! Request identifiers and access logging.
+//!
+//! Every request is assigned an identifier, taken from its `X-Request-Id`
+//! header if the client provided a usable one and generated otherwise. The
+//! identifier is echoed back in the response's `X-Request-Id` header and
+//! recorded on a span enclosing the handling of the request, so that every
+//! tracing event emitted on its behalf can be correlated. Once the response is
+//! ready, a single structured access log event is emitted under the
+//! `fts_axum::access` target.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+use tracing::{Instrument as _, Level, event, info_span};
+
+/// The header carrying the request identifier
+pub(crate) const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The longest client-provided identifier that is accepted
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    /// The identifier of the request currently being handled
+    static CURRENT: HeaderValue;
+}
+
+/// The identifier of the request currently being handled, if any.
+///
+/// This allows work spawned on behalf of a request (such as an automatic
+/// batch auction) to be attributed to it.
+pub(crate) fn current() -> Option<HeaderValue> {
+    CURRENT.try_with(HeaderValue::clone).ok()
+}
+
+/// Middleware assigning an identifier to, and logging, every request.
+pub(crate) async fn trace(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .filter(|value| is_valid(value))
+        .cloned()
+        .unwrap_or_else(generate);
+
+    let span = info_span!(
+        "request",
+        request_id = request_id.to_str().unwrap_or_default(),
+        method = %request.method(),
+        path = request.uri().path(),
+    );
+
+    let start = Instant::now();
+    let mut response = CURRENT
+        .scope(request_id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+
+    let _enter = span.enter();
+    event!(
+        target: "fts_axum::access",
+        Level::INFO,
+        status = response.status().as_u16(),
+        latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+        "request completed"
+    );
+
+    response.headers_mut().insert(X_REQUEST_ID, request_id);
+    response
+}
+
+/// Whether a client-provided identifier is safe to log and echo back
+fn is_valid(value: &HeaderValue) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_LENGTH
+        && value.as_bytes().iter().all(|byte| byte.is_ascii_graphic())
+}
+
+/// A fresh, random identifier
+fn generate() -> HeaderValue {
+    HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+        .expect("a UUID is a valid header value")
+}
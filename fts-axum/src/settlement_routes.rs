@@ -0,0 +1,534 @@
+//! REST API endpoints for settlement operations.
+//!
+//! This module provides endpoints for settling the trade activity produced by
+//! batch auctions. Bidders may inspect their unsettled activity and their
+//! settlement history, while administrators execute the settlements.
+//! Statements of the entries posted to each bidder may be retrieved as
+//! structured records, or as documents rendered by the application.
+
+use crate::{
+    ApiApplication,
+    auth::Auth,
+    config::AxumConfig,
+    limit::LimitQuery,
+    negotiate::{Accept, MediaRanges, Negotiated, Rendered},
+    problem::Problem,
+};
+use aide::axum::{
+    ApiRouter,
+    routing::{get, post},
+};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State, rejection::JsonRejection},
+    http::StatusCode,
+};
+use fts_core::{
+    models::{
+        Activity, DateTimeRangeQuery, DateTimeRangeResponse, SettlementConfig, SettlementConflict,
+        SettlementRecord, SettlementRevision, SettlementStatement, UnsettledActivityQuery,
+        UnsettledActivityResponse, ValueRecord,
+    },
+    ports::{Repository, SettlementRepository as _},
+};
+use std::sync::Arc;
+
+/// Creates a router with settlement-related endpoints.
+pub fn router<T: ApiApplication>() -> ApiRouter<T> {
+    ApiRouter::new()
+        .api_route_with("/", post(settle_activity::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("settlement")
+                .tag("admin")
+        })
+        .api_route_with("/reversal", post(reverse_settlement::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("settlement")
+                .tag("admin")
+        })
+        .api_route_with("/correction", post(correct_settlement::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("settlement")
+                .tag("admin")
+        })
+        .api_route_with("/revisions", get(get_settlement_revisions::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("settlement")
+                .tag("admin")
+        })
+        .api_route_with(
+            "/statements",
+            get(get_settlement_statements::<T>),
+            |route| route.security_requirement("jwt").tag("settlement"),
+        )
+        .api_route_with(
+            "/statements/{statement_id}",
+            get(get_settlement_statement::<T>),
+            |route| route.security_requirement("jwt").tag("settlement"),
+        )
+        .api_route_with("/{bidder_id}", get(get_settlement_history::<T>), |route| {
+            route
+                .security_requirement("jwt")
+                .tag("settlement")
+                .tag("history")
+        })
+        .api_route_with(
+            "/{bidder_id}/unsettled",
+            get(get_unsettled_activity::<T>).post(get_unsettled_positions::<T>),
+            |route| route.security_requirement("jwt").tag("settlement"),
+        )
+}
+
+/// Path parameter for bidder-specific endpoints.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+struct Id<T> {
+    /// The unique identifier of the bidder
+    bidder_id: T,
+}
+
+/// Path parameter for statement-specific endpoints.
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[schemars(inline)]
+struct StatementId {
+    /// The number identifying the statement
+    statement_id: u64,
+}
+
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+struct UnsettledQuery<T> {
+    /// Accrue activity up to this time (defaults to the current time)
+    as_of: Option<T>,
+}
+
+/// Settle all trade activity since the previous settlement.
+///
+/// Integrates the batch outcomes up to `as_of`, rounding the accrued positions
+/// and payments according to the provided configuration. Repeating a request
+/// returns the settlement it made, so that a request which timed out may be
+/// safely retried.
+///
+/// # Authorization
+///
+/// Requires `can_run_settlement` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The settled activity of every bidder
+/// - `400 Bad Request`: The settlement time is in the future
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `409 Conflict`: The settlement time precedes the previous settlement, or
+///   activity was already settled at that time with a different configuration,
+///   or that settlement has since been reversed
+/// - `500 Internal Server Error`: Database operation failed
+async fn settle_activity<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    settlement_config: Result<
+        Json<SettlementConfig<<T::Repository as Repository>::DateTime>>,
+        JsonRejection,
+    >,
+) -> Result<Json<SettlementRecord<T::Repository>>, Problem> {
+    let Json(settlement_config) = settlement_config?;
+
+    if !app.can_run_settlement(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    if settlement_config.as_of > app.now() {
+        return Err(
+            Problem::new(StatusCode::BAD_REQUEST, "settlement_in_future")
+                .with_detail("cannot settle future activity"),
+        );
+    }
+
+    app.database()
+        .settle_activity(settlement_config)
+        .await
+        .map_err(Problem::internal)?
+        .map(Json)
+        .map_err(conflict_problem)
+}
+
+/// The settlement to reverse, or whose revisions or statements to list.
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+struct RevisionQuery<T> {
+    /// The time up to which the settlement settled activity
+    as_of: T,
+}
+
+/// Map a conflicting settlement or revision to its problem
+fn conflict_problem<DateTime: std::fmt::Display>(
+    conflict: SettlementConflict<DateTime>,
+) -> Problem {
+    match conflict {
+        SettlementConflict::Missing { .. } => {
+            Problem::new(StatusCode::NOT_FOUND, "settlement_not_found").with_detail(conflict)
+        }
+        SettlementConflict::Reversed { .. } => {
+            Problem::new(StatusCode::CONFLICT, "settlement_reversed").with_detail(conflict)
+        }
+        SettlementConflict::Superseded { .. } => {
+            Problem::new(StatusCode::CONFLICT, "already_settled").with_detail(conflict)
+        }
+        SettlementConflict::Mismatched { .. } => {
+            Problem::new(StatusCode::CONFLICT, "settlement_mismatch").with_detail(conflict)
+        }
+    }
+}
+
+/// Reverse a settlement.
+///
+/// Posts entries offsetting every position and payment of the settlement as
+/// of `as_of`, leaving the original entries in place. A reversed settlement
+/// may subsequently be corrected.
+///
+/// # Authorization
+///
+/// Requires `can_run_settlement` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The reversing revision and its entries
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `404 Not Found`: No settlement was made as of that time
+/// - `409 Conflict`: The settlement is already reversed
+/// - `500 Internal Server Error`: Database operation failed
+async fn reverse_settlement<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    body: Result<Json<RevisionQuery<<T::Repository as Repository>::DateTime>>, JsonRejection>,
+) -> Result<Json<SettlementRevision<T::Repository>>, Problem> {
+    let Json(RevisionQuery { as_of }) = body?;
+
+    if !app.can_run_settlement(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    app.database()
+        .reverse_settlement(as_of, app.now())
+        .await
+        .map_err(Problem::internal)?
+        .map(Json)
+        .map_err(conflict_problem)
+}
+
+/// Correct a settlement.
+///
+/// Settles the interval of the settlement as of `as_of` again with the given
+/// configuration, reversing the settlement first unless it is already
+/// reversed. The activity originally accrued is re-expressed in the time unit
+/// of the correction. Repeating a request returns the correction it made.
+///
+/// # Authorization
+///
+/// Requires `can_run_settlement` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The correcting revision and its entries
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `404 Not Found`: No settlement was made as of that time
+/// - `500 Internal Server Error`: Database operation failed
+async fn correct_settlement<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    settlement_config: Result<
+        Json<SettlementConfig<<T::Repository as Repository>::DateTime>>,
+        JsonRejection,
+    >,
+) -> Result<Json<SettlementRevision<T::Repository>>, Problem> {
+    let Json(settlement_config) = settlement_config?;
+
+    if !app.can_run_settlement(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    app.database()
+        .correct_settlement(settlement_config, app.now())
+        .await
+        .map_err(Problem::internal)?
+        .map(Json)
+        .map_err(conflict_problem)
+}
+
+/// List the revisions of a settlement.
+///
+/// Returns every reversal and correction of the settlement as of `as_of`, in
+/// the order they were made, along with the entries each posted.
+///
+/// # Authorization
+///
+/// Requires `can_run_settlement` permission.
+///
+/// # Returns
+///
+/// - `200 OK`: The revisions of the settlement
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_settlement_revisions<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Query(RevisionQuery { as_of }): Query<RevisionQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<Json<Vec<SettlementRevision<T::Repository>>>, Problem> {
+    if !app.can_run_settlement(&auth).await {
+        return Err(Problem::not_authorized());
+    }
+
+    app.database()
+        .get_settlement_revisions(as_of)
+        .await
+        .map(Json)
+        .map_err(Problem::internal)
+}
+
+/// The bidder whose statements to list, if not every bidder.
+#[derive(schemars::JsonSchema, serde::Deserialize)]
+#[schemars(inline)]
+struct StatementQuery<T> {
+    /// Restrict the statements to those issued to this bidder
+    bidder_id: Option<T>,
+}
+
+/// List the statements issued for a settlement.
+///
+/// Returns the statements issued as of the settlement and each of its
+/// revisions, in the order they were issued, optionally restricted to those
+/// of a single bidder.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder (`can_read_settlement`)
+/// if `bidder_id` is given, or `can_run_settlement` permission otherwise.
+///
+/// # Returns
+///
+/// - `200 OK`: The statements
+/// - `401 Unauthorized`: Missing or insufficient permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_settlement_statements<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Query(RevisionQuery { as_of }): Query<RevisionQuery<<T::Repository as Repository>::DateTime>>,
+    Query(StatementQuery { bidder_id }): Query<
+        StatementQuery<<T::Repository as Repository>::BidderId>,
+    >,
+) -> Result<Json<Vec<SettlementStatement<T::Repository>>>, Problem> {
+    let authorized = match &bidder_id {
+        Some(bidder_id) => app.can_read_settlement(&auth, bidder_id.clone()).await,
+        None => app.can_run_settlement(&auth).await,
+    };
+    if !authorized {
+        return Err(Problem::not_authorized());
+    }
+
+    app.database()
+        .get_settlement_statements(as_of, bidder_id)
+        .await
+        .map(Json)
+        .map_err(Problem::internal)
+}
+
+/// Retrieve a settlement statement.
+///
+/// The statement is returned as JSON, unless the client prefers a media type
+/// (per its `Accept` header) in which the application renders statements,
+/// such as a PDF for a bidder's records.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder to whom the statement
+/// was issued (`can_read_settlement`).
+///
+/// # Returns
+///
+/// - `200 OK`: The statement, as JSON or as rendered
+/// - `401 Unauthorized`: Missing read permissions
+/// - `404 Not Found`: No statement has that id
+/// - `406 Not Acceptable`: The statement is not rendered in any acceptable media type
+/// - `500 Internal Server Error`: Database query or rendering failed
+async fn get_settlement_statement<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(StatementId { statement_id }): Path<StatementId>,
+    media_ranges: MediaRanges,
+) -> Result<Rendered<SettlementStatement<T::Repository>>, Problem> {
+    let statement = app
+        .database()
+        .get_settlement_statement(statement_id)
+        .await
+        .map_err(Problem::internal)?
+        .ok_or_else(|| Problem::not_found("statement_not_found"))?;
+
+    if !app
+        .can_read_settlement(&auth, statement.bidder_id.clone())
+        .await
+    {
+        return Err(Problem::not_authorized());
+    }
+
+    for media_type in media_ranges.preferred() {
+        if let Some(body) = app
+            .render_statement(&statement, media_type)
+            .await
+            .map_err(Problem::internal)?
+        {
+            return Ok(Rendered::Document {
+                media_type: media_type.to_owned(),
+                body,
+            });
+        }
+    }
+
+    if media_ranges.accepts_json() {
+        Ok(Rendered::Json(statement))
+    } else {
+        Err(
+            Problem::new(StatusCode::NOT_ACCEPTABLE, "statement_format_unsupported")
+                .with_detail("the statement is not available in any acceptable media type"),
+        )
+    }
+}
+
+/// Retrieve the settlement history of a bidder.
+///
+/// Returns the bidder's settled positions and payments, one record per
+/// settlement in which the bidder had activity.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder (`can_read_settlement`).
+///
+/// # Returns
+///
+/// - `200 OK`: Paginated settlement records
+/// - `401 Unauthorized`: Missing read permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_settlement_history<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { bidder_id }): Path<Id<<T::Repository as Repository>::BidderId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<DateTimeRangeQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    accept: Accept,
+) -> Result<
+    Negotiated<
+        DateTimeRangeResponse<
+            Activity<<T::Repository as Repository>::ProductId>,
+            <T::Repository as Repository>::DateTime,
+        >,
+    >,
+    Problem,
+> {
+    if !app.can_read_settlement(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let history = app
+        .database()
+        .get_settlement_history(bidder_id, query, limit.page_size(&config))
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(accept.respond(history))
+}
+
+/// Retrieve the trade activity of a bidder since the previous settlement.
+///
+/// The positions and payments are accrued up to `as_of` (or the current time)
+/// and are not rounded.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder (`can_read_settlement`).
+///
+/// # Returns
+///
+/// - `200 OK`: The unsettled activity
+/// - `401 Unauthorized`: Missing read permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_unsettled_activity<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { bidder_id }): Path<Id<<T::Repository as Repository>::BidderId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<UnsettledQuery<<T::Repository as Repository>::DateTime>>,
+) -> Result<
+    Json<
+        ValueRecord<
+            <T::Repository as Repository>::DateTime,
+            Activity<<T::Repository as Repository>::ProductId>,
+        >,
+    >,
+    Problem,
+> {
+    if !app.can_read_settlement(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = query.as_of.unwrap_or_else(|| app.now());
+    let activity = app
+        .database()
+        .get_unsettled_activity(bidder_id, as_of, config.time_unit)
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(Json(activity))
+}
+
+/// Retrieve a page of the trade activity of a bidder since the previous settlement.
+///
+/// This is the paginated counterpart of the `GET` endpoint, for bidders
+/// trading too many products to report at once. The positions are ordered by
+/// product id and may be restricted to the `products` of the request, while
+/// the payment always accounts for all of the bidder's activity.
+///
+/// # Authorization
+///
+/// Requires settlement read permission for the bidder (`can_read_settlement`).
+///
+/// # Returns
+///
+/// - `200 OK`: A page of the unsettled activity
+/// - `401 Unauthorized`: Missing read permissions
+/// - `500 Internal Server Error`: Database query failed
+async fn get_unsettled_positions<T: ApiApplication>(
+    State(app): State<T>,
+    Auth(auth): Auth<T>,
+    Path(Id { bidder_id }): Path<Id<<T::Repository as Repository>::BidderId>>,
+    Extension(config): Extension<Arc<AxumConfig>>,
+    Query(query): Query<UnsettledQuery<<T::Repository as Repository>::DateTime>>,
+    Query(limit): Query<LimitQuery>,
+    body: Result<
+        Json<UnsettledActivityQuery<<T::Repository as Repository>::ProductId>>,
+        JsonRejection,
+    >,
+) -> Result<Json<UnsettledActivityResponse<T::Repository>>, Problem> {
+    let Json(body) = body?;
+
+    if !app.can_read_settlement(&auth, bidder_id.clone()).await {
+        return Err(Problem::not_authorized());
+    }
+
+    let as_of = query.as_of.unwrap_or_else(|| app.now());
+    let activity = app
+        .database()
+        .get_unsettled_positions(
+            bidder_id,
+            as_of,
+            config.time_unit,
+            body,
+            limit.page_size(&config),
+        )
+        .await
+        .map_err(Problem::internal)?;
+
+    Ok(Json(activity))
+}
@@ -0,0 +1,104 @@
+//! Composition of the versioned API routers.
+//!
+//! Each revision of the API is a standalone router, which is mounted under
+//! every prefix the configuration assigns to it. Deprecated prefixes have the
+//! `Deprecation`, `Sunset`, and `Link` headers attached to their responses so
+//! that clients may discover the need to migrate.
+
+use crate::{
+    ApiApplication, batch_routes, bidder_routes, cache,
+    config::{ApiVersion, VersionConfig},
+    demand_routes, import_routes, portfolio_routes, product_routes, settlement_routes,
+};
+use aide::axum::ApiRouter;
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue, header::LINK},
+    response::Response,
+};
+
+/// The `Deprecation` header, as specified by RFC 9745
+pub(crate) const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+/// The `Sunset` header, as specified by RFC 8594
+pub(crate) const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// The routes comprising version 1 of the API
+fn v1<T: ApiApplication>() -> ApiRouter<T> {
+    ApiRouter::new()
+        .nest("/product", product_routes::router())
+        .nest("/demand", demand_routes::router())
+        .nest("/portfolio", portfolio_routes::router())
+        .nest("/batch", batch_routes::router())
+        .nest("/settlement", settlement_routes::router())
+        .nest("/bidder", bidder_routes::router())
+        .nest("/import", import_routes::router())
+        .layer(axum::middleware::from_fn(cache::etag))
+}
+
+/// Construct the router for a revision of the API
+fn routes<T: ApiApplication>(api: ApiVersion) -> ApiRouter<T> {
+    match api {
+        ApiVersion::V1 => v1(),
+    }
+}
+
+/// Mount each configured version onto the router
+pub(crate) fn mount<T: ApiApplication>(
+    mut router: ApiRouter<T>,
+    versions: &[VersionConfig],
+) -> ApiRouter<T> {
+    for version in versions {
+        let mut routes = routes::<T>(version.api);
+
+        let headers = deprecation_headers(version);
+        if !headers.is_empty() {
+            routes = routes.layer(axum::middleware::map_response(
+                move |mut response: Response| {
+                    let headers = headers.clone();
+                    async move {
+                        response.headers_mut().extend(headers);
+                        response
+                    }
+                },
+            ));
+        }
+
+        // Axum does not allow nesting at the root, so we merge instead
+        router = match version.prefix.trim_end_matches('/') {
+            "" => router.merge(routes),
+            prefix => router.nest(prefix, routes),
+        };
+    }
+    router
+}
+
+/// Compute the headers announcing the deprecation of a version, if any
+fn deprecation_headers(version: &VersionConfig) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if let Some(deprecated) = version.deprecated {
+        // RFC 9745 expresses the date as a structured field (a unix timestamp)
+        let value = format!("@{}", deprecated.unix_timestamp());
+        headers.insert(DEPRECATION, HeaderValue::try_from(value).unwrap());
+    }
+
+    if let Some(sunset) = version.sunset {
+        // RFC 8594 expresses the date as an HTTP-date
+        let value = httpdate::fmt_http_date(sunset.into());
+        headers.insert(SUNSET, HeaderValue::try_from(value).unwrap());
+    }
+
+    if let Some(successor) = &version.successor {
+        let value = format!("<{successor}>; rel=\"successor-version\"");
+        match HeaderValue::try_from(value) {
+            Ok(value) => {
+                headers.append(LINK, value);
+            }
+            Err(_) => {
+                tracing::warn!(successor, "ignoring invalid successor link");
+            }
+        }
+    }
+
+    headers
+}
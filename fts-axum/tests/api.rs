@@ -1,11 +1,14 @@
 use axum_test::{TestServer, TestServerConfig};
-use fts_axum::{config::AxumConfig, router};
+use fts_axum::{
+    config::{ApiVersion, AxumConfig, VersionConfig},
+    reloadable_router, router,
+};
 use fts_sqlite::{Db, config::SqliteConfig, types::DateTime};
 use hurl::runner::{self, VariableSet};
 use hurl::runner::{RunnerOptionsBuilder, Value};
 use hurl::util::logger::LoggerOptionsBuilder;
 use rstest::*;
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 mod app;
 use app::TestApp;
@@ -15,14 +18,258 @@ use app::TestApp;
 #[rstest]
 #[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
 async fn test_api(#[files("tests/api/**/*.hurl")] test: PathBuf) {
-    let app = {
-        let config = SqliteConfig::default();
-        let now = DateTime::from(time::OffsetDateTime::now_utc());
-        let db = Db::open(&config, now).await.unwrap();
-        TestApp(db)
+    run_hurl(test).await
+}
+
+#[cfg(feature = "graphql")]
+#[rstest]
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn test_graphql(#[files("tests/graphql/**/*.hurl")] test: PathBuf) {
+    run_hurl(test).await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn test_deprecated_version() {
+    let deprecated = time::macros::datetime!(2025-01-01 0:00 UTC);
+    let sunset = time::macros::datetime!(2030-01-01 0:00 UTC);
+
+    // Serve v1 under both /v0 (deprecated) and /v1
+    let config = AxumConfig {
+        versions: vec![
+            VersionConfig {
+                deprecated: Some(deprecated),
+                sunset: Some(sunset),
+                successor: Some("/v1".to_owned()),
+                ..VersionConfig::new("/v0", ApiVersion::V1)
+            },
+            VersionConfig::new("/v1", ApiVersion::V1),
+        ],
+        ..Default::default()
     };
+    let server = TestServer::new(router(test_app().await, config)).unwrap();
+    let path = format!("/product/{}", uuid::Uuid::new_v4());
+
+    let response = server
+        .get(&format!("/v0{path}"))
+        .authorization_bearer("can_view_products=true")
+        .await;
+    response.assert_status_not_found();
+    response.assert_header("deprecation", "@1735689600");
+    response.assert_header("sunset", "Tue, 01 Jan 2030 00:00:00 GMT");
+    response.assert_header("link", "</v1>; rel=\"successor-version\"");
+
+    let response = server
+        .get(&format!("/v1{path}"))
+        .authorization_bearer("can_view_products=true")
+        .await;
+    response.assert_status_not_found();
+    assert!(response.maybe_header("deprecation").is_none());
+
+    // Unconfigured versions are not served
+    server
+        .get(&format!("/v2{path}"))
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+    server.get("/health").await.assert_status_ok();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn test_auto_solve_is_debounced() {
+    let config = AxumConfig {
+        auto_solve: true,
+        solve_debounce: std::time::Duration::from_millis(500),
+        ..Default::default()
+    };
+    let server = TestServer::new(router(test_app().await, config)).unwrap();
+
+    let bidder_id = uuid::Uuid::new_v4();
+    let token = format!(
+        "bidder_id={bidder_id}&can_create_bid=true&can_update_bid=true&can_manage_products=true&can_view_products=true"
+    );
+    let product_id = uuid::Uuid::new_v4();
+    let demand_id = uuid::Uuid::new_v4();
+
+    server
+        .post("/v1/product")
+        .authorization_bearer(&token)
+        .json(&product_id)
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    server
+        .post("/v1/demand")
+        .authorization_bearer(&token)
+        .json(&serde_json::json!({
+            "app_data": demand_id,
+            "curve_data": { "min_rate": -10, "max_rate": 10, "price": 10.0 },
+        }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    server
+        .post("/v1/portfolio")
+        .authorization_bearer(&token)
+        .json(&serde_json::json!({
+            "app_data": uuid::Uuid::new_v4(),
+            "demand": { demand_id.to_string(): 1 },
+            "basis": { product_id.to_string(): 1 },
+        }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+
+    // A burst of updates is coalesced into (at most) a couple of auctions
+    for price in 0..10 {
+        server
+            .put(&format!("/v1/demand/{demand_id}"))
+            .authorization_bearer(&token)
+            .json(&serde_json::json!({ "min_rate": -10, "max_rate": 10, "price": price }))
+            .await
+            .assert_status_ok();
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    let outcomes = server
+        .get(&format!("/v1/product/{product_id}/outcomes"))
+        .authorization_bearer(&token)
+        .await
+        .json::<serde_json::Value>();
+    let batches = outcomes["results"].as_array().unwrap().len();
+    assert!((1..=2).contains(&batches), "executed {batches} batches");
+
+    // Once the burst has been solved, the queue is idle
+    let health = server
+        .get("/health")
+        .add_query_param("verbose", true)
+        .authorization_bearer("can_run_batch=true")
+        .await
+        .json::<serde_json::Value>();
+    assert_eq!(health["checks"]["auto_solve"]["depth"], 0);
+    assert_eq!(
+        health["checks"]["auto_solve"]["last_error"],
+        serde_json::Value::Null
+    );
+    assert_eq!(health["checks"]["last_batch"]["products"], 1);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn test_csv_pagination() {
+    // Requested page limits are bounded by the maximum
+    let config = AxumConfig {
+        max_page_limit: 1,
+        ..Default::default()
+    };
+    let server = TestServer::new(router(test_app().await, config)).unwrap();
+
+    let bidder_id = uuid::Uuid::new_v4();
+    let token =
+        format!("bidder_id={bidder_id}&can_create_bid=true&can_update_bid=true&can_read_bid=true");
+    let demand_id = uuid::Uuid::new_v4();
+
+    server
+        .post("/v1/demand")
+        .authorization_bearer(&token)
+        .json(&serde_json::json!({
+            "app_data": demand_id,
+            "curve_data": { "price": 10.0 },
+        }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    server
+        .put(&format!("/v1/demand/{demand_id}"))
+        .authorization_bearer(&token)
+        .json(&serde_json::json!({ "price": 5.0 }))
+        .await
+        .assert_status_ok();
+
+    // The table only has room for the records, so the next page is linked
+    let response = server
+        .get(&format!("/v1/demand/{demand_id}/curve-history?limit=5"))
+        .authorization_bearer(&token)
+        .add_header("accept", "text/csv")
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.text().lines().count(), 2);
+    let link = response.header("link");
+    let link = link.to_str().unwrap();
+    assert!(link.starts_with("<?limit=5&before=") && link.ends_with(">; rel=\"next\""));
+
+    // Following the link retrieves the rest of the history
+    let query = &link[1..link.find('>').unwrap()];
+    let response = server
+        .get(&format!("/v1/demand/{demand_id}/curve-history{query}"))
+        .authorization_bearer(&token)
+        .add_header("accept", "text/csv")
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.text().lines().count(), 2);
+    assert!(response.maybe_header("link").is_none());
+
+    // Without a limit, the whole history is streamed as a single table
+    let response = server
+        .get(&format!("/v1/demand/{demand_id}/curve-history"))
+        .authorization_bearer(&token)
+        .add_header("accept", "text/csv")
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.text().lines().count(), 3);
+    assert!(response.maybe_header("link").is_none());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+async fn test_reloaded_config_applies_to_later_requests() {
+    let (sender, receiver) = tokio::sync::watch::channel(Arc::new(AxumConfig::default()));
+    let server = TestServer::new(reloadable_router(test_app().await, receiver)).unwrap();
+
+    let token = "can_manage_products=true&can_view_products=true&can_run_batch=true";
+    let products = (0..3).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>();
+    server
+        .post("/v1/product/bulk")
+        .authorization_bearer(token)
+        .json(&products)
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+
+    let page = async || {
+        server
+            .post("/v1/product/search")
+            .authorization_bearer(token)
+            .json(&serde_json::json!({}))
+            .await
+            .json::<serde_json::Value>()["results"]
+            .as_array()
+            .unwrap()
+            .len()
+    };
+    let auto_solve = async || {
+        server
+            .get("/health")
+            .add_query_param("verbose", true)
+            .authorization_bearer(token)
+            .await
+            .json::<serde_json::Value>()["checks"]["auto_solve"]
+            .clone()
+    };
+    assert_eq!(page().await, 3);
+    assert_eq!(auto_solve().await, serde_json::Value::Null);
+
+    sender.send_replace(Arc::new(AxumConfig {
+        page_limit: 2,
+        auto_solve: true,
+        ..Default::default()
+    }));
+    assert_eq!(page().await, 2);
+    assert_eq!(auto_solve().await["depth"], 0);
+}
+
+async fn test_app() -> TestApp {
+    let config = SqliteConfig::default();
+    let now = DateTime::from(time::OffsetDateTime::now_utc());
+    let db = Db::open(&config, now).await.unwrap();
+    TestApp(db)
+}
 
-    let router = router(app, AxumConfig::default());
+async fn run_hurl(test: PathBuf) {
+    let router = router(test_app().await, AxumConfig::default());
 
     // Run the test server on a local TCP port
     let server = TestServer::new_with_config(
@@ -1,5 +1,8 @@
 use super::Permissions;
-use fts_core::ports::Application;
+use fts_core::{
+    models::{Actor, CurveDiagnostic, DemandCurve},
+    ports::Application,
+};
 use fts_solver::clarabel::ClarabelSolver;
 use fts_sqlite::{
     Db,
@@ -116,4 +119,60 @@ impl Application for TestApp {
             .map(|p| p.can_run_batch)
             .unwrap_or(false)
     }
+
+    async fn can_preview_batch(&self, context: &Self::Context) -> bool {
+        self.permissions(context)
+            .map(|p| p.can_run_batch || p.can_preview_batch)
+            .unwrap_or(false)
+    }
+
+    async fn can_run_settlement(&self, context: &Self::Context) -> bool {
+        self.permissions(context)
+            .map(|p| p.can_run_settlement)
+            .unwrap_or(false)
+    }
+
+    async fn can_purge_bid(&self, context: &Self::Context) -> bool {
+        self.permissions(context)
+            .map(|p| p.can_purge_bid)
+            .unwrap_or(false)
+    }
+
+    async fn can_read_settlement(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
+        // Settlement data is readable by the bidder, or by anyone with the
+        // dedicated permission
+        self.permissions(context)
+            .map(|p| {
+                p.can_read_settlement
+                    || (p.can_read_bid && p.bidder_id.iter().any(|id| id == &bidder_id))
+            })
+            .unwrap_or(false)
+    }
+
+    async fn actor(&self, context: &Self::Context, bidder_id: BidderId) -> Actor {
+        // The first bidder id of the token is its own; changes made on behalf
+        // of any other bidder are attributed to an operator
+        match self.permissions(context) {
+            Some(p) if p.bidder_id.first() != Some(&bidder_id) => Actor::Operator,
+            _ => Actor::Bidder,
+        }
+    }
+
+    async fn diagnose_curve(
+        &self,
+        _context: &Self::Context,
+        curve: &DemandCurve,
+    ) -> Vec<CurveDiagnostic> {
+        // A market rule for exercising the validation endpoint
+        curve
+            .clone()
+            .points()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, point)| point.price.abs() > 1000.0)
+            .map(|(index, _)| {
+                CurveDiagnostic::error("price_limit", "Prices are limited to ±1000").at(index)
+            })
+            .collect()
+    }
 }
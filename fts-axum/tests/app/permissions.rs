@@ -24,6 +24,14 @@ pub struct Permissions {
     pub can_manage_products: bool,
     #[serde(default)]
     pub can_run_batch: bool,
+    #[serde(default)]
+    pub can_preview_batch: bool,
+    #[serde(default)]
+    pub can_run_settlement: bool,
+    #[serde(default)]
+    pub can_read_settlement: bool,
+    #[serde(default)]
+    pub can_purge_bid: bool,
 }
 
 impl Display for Permissions {
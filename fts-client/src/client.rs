@@ -0,0 +1,1043 @@
+use crate::{Error, sse};
+use fts_core::{
+    models::{
+        Activity, Auction, AuctionOutcome, Basis, BatchAttempt, BatchMetadata, BatchPreview,
+        BatchRun, BatchSchedule, BidderOutcome, BidderSummary, CurveValidation, DateTimeRangeQuery,
+        DateTimeRangeResponse, DemandCurve, DemandCurveDto, DemandQuery, DemandQueryResponse,
+        DemandRecord, ImportDocument, ImportRecord, MarketStatistics, PortfolioQuery,
+        PortfolioQueryResponse, PortfolioRecord, PriceInterval, PriceSummary, ProductPartition,
+        ProductQuery, ProductQueryResponse, ProductRecord, ProductSearch, ProductSearchResponse,
+        ScheduleUpdate, SettlementConfig, SettlementRecord, SettlementRevision,
+        SettlementStatement, Tombstone, UnsettledActivityQuery, UnsettledActivityResponse,
+        ValueRecord, Weights,
+    },
+    ports::Repository,
+};
+use futures_util::{Stream, StreamExt as _, TryStreamExt as _};
+use reqwest::{
+    IntoUrl, Method, RequestBuilder, Url,
+    header::{ACCEPT, LINK},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{hash::Hash, marker::PhantomData, time::Duration};
+
+/// The version of the API this client targets
+const API_VERSION: &str = "v1";
+
+/// A typed client for the REST API.
+///
+/// The repository type `R` fixes the identifier and timestamp types of the
+/// server, while the application data and outcome types are chosen per call.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), fts_client::Error> {
+/// use fts_client::{Client, Remote};
+///
+/// let client: Client<Remote<String, String>> =
+///     Client::new("http://localhost:8080")?.with_token("...");
+/// let demands = client.list_demands::<serde_json::Value>().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client<R> {
+    http: reqwest::Client,
+    base_url: Url,
+    token: Option<String>,
+    page_limit: Option<usize>,
+    repository: PhantomData<fn() -> R>,
+}
+
+impl<R> Clone for Client<R> {
+    fn clone(&self) -> Self {
+        Self {
+            http: self.http.clone(),
+            base_url: self.base_url.clone(),
+            token: self.token.clone(),
+            page_limit: self.page_limit,
+            repository: PhantomData,
+        }
+    }
+}
+
+/// Request body item for partitioning a product.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionItem<D> {
+    /// Application-specific data for the child product
+    pub app_data: D,
+    /// Weight of this child relative to the parent
+    pub ratio: f64,
+}
+
+#[derive(Serialize)]
+struct CreateDemand<'a, D> {
+    app_data: &'a D,
+    curve_data: &'a DemandCurve,
+}
+
+#[derive(Serialize)]
+struct CreatePortfolio<'a, D, DemandId: Eq + Hash + Clone, ProductId: Eq + Hash + Clone> {
+    app_data: &'a D,
+    demand: &'a Weights<DemandId>,
+    basis: &'a Basis<ProductId>,
+}
+
+#[derive(Serialize)]
+struct UpdatePortfolio<'a, DemandId: Eq + Hash + Clone, ProductId: Eq + Hash + Clone> {
+    demand: Option<&'a Weights<DemandId>>,
+    basis: Option<&'a Basis<ProductId>>,
+}
+
+#[derive(Serialize)]
+struct ExpandQuery {
+    expand: bool,
+}
+
+#[derive(Serialize)]
+struct SummaryQuery<'a, DateTime> {
+    interval: PriceInterval,
+    #[serde(flatten)]
+    range: &'a DateTimeRangeQuery<DateTime>,
+}
+
+#[derive(Serialize)]
+struct AsOfQuery<'a, DateTime> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    as_of: Option<&'a DateTime>,
+}
+
+#[derive(Serialize)]
+struct StatementQuery<'a, DateTime, BidderId> {
+    as_of: &'a DateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bidder_id: Option<&'a BidderId>,
+}
+
+#[derive(Serialize)]
+struct AfterQuery<'a, DateTime> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<&'a DateTime>,
+}
+
+#[derive(Serialize)]
+struct NextQuery<'a, DateTime> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<&'a DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct DryRunQuery {
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct LimitQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ProductIdQuery<'a, ProductId> {
+    product_id: &'a ProductId,
+}
+
+impl<R> Client<R> {
+    /// Create a client for the server at `base_url` (e.g. `http://localhost:8080`).
+    pub fn new(base_url: impl IntoUrl) -> Result<Self, Error> {
+        let base_url = base_url.into_url()?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url,
+            token: None,
+            page_limit: None,
+            repository: PhantomData,
+        })
+    }
+
+    /// Use the bearer token for every request.
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        Self {
+            token: Some(token.into()),
+            ..self
+        }
+    }
+
+    /// Request pages of (at most) `limit` results from paginated endpoints,
+    /// rather than the server's default. The server may reduce the limit to
+    /// its configured maximum.
+    pub fn with_page_limit(self, limit: usize) -> Self {
+        Self {
+            page_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Use a preconfigured HTTP client, e.g. to set timeouts or proxies.
+    pub fn with_http_client(self, http: reqwest::Client) -> Self {
+        Self { http, ..self }
+    }
+
+    /// Append the (percent-encoded) segments to the base url
+    fn url<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("base url is a valid base")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    /// Build a request to the path, formed from the versioned API prefix and
+    /// the provided segments
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let url = self.url(std::iter::once(API_VERSION).chain(segments.iter().copied()));
+        let request = self.http.request(method, url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Build a request to a paginated endpoint, requesting the page limit (if any)
+    fn paginated(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        self.request(method, segments).query(&LimitQuery {
+            limit: self.page_limit,
+        })
+    }
+
+    /// Send the request, ensuring a successful status
+    async fn send(request: RequestBuilder) -> Result<reqwest::Response, Error> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let message = response.text().await.unwrap_or_default();
+            // Errors are reported as problem details, carrying a machine-readable code
+            let code = serde_json::from_str::<serde_json::Value>(&message)
+                .ok()
+                .and_then(|body| body.get("code")?.as_str().map(String::from));
+            Err(Error::Status {
+                status,
+                code,
+                message,
+            })
+        }
+    }
+
+    /// Send the request and decode the JSON response
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, Error> {
+        let bytes = Self::send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Send the request, decoding a page of a listing from the JSON response
+    /// along with whether it links to a next page
+    async fn page<T: DeserializeOwned>(request: RequestBuilder) -> Result<(T, bool), Error> {
+        let response = Self::send(request).await?;
+        let next = response
+            .headers()
+            .get_all(LINK)
+            .iter()
+            .filter_map(|link| link.to_str().ok())
+            .any(|link| link.ends_with("rel=\"next\""));
+        let bytes = response.bytes().await?;
+        Ok((serde_json::from_slice(&bytes)?, next))
+    }
+
+    /// Check that the server is running.
+    pub async fn health(&self) -> Result<(), Error> {
+        Self::send(self.http.get(self.url(["health"]))).await?;
+        Ok(())
+    }
+}
+
+/// Format an identifier or timestamp as a path segment, using its serde
+/// representation (as the server does).
+fn segment<V: Serialize>(value: &V) -> Result<String, Error> {
+    Ok(match serde_json::to_value(value)? {
+        serde_json::Value::String(value) => value,
+        other => other.to_string(),
+    })
+}
+
+impl<R> Client<R>
+where
+    R: Repository<
+            DateTime: Serialize + DeserializeOwned,
+            BidderId: Serialize + DeserializeOwned,
+            DemandId: Clone + Serialize + DeserializeOwned,
+            PortfolioId: Clone + Serialize + DeserializeOwned,
+            ProductId: Clone + Serialize + DeserializeOwned,
+        >,
+{
+    // Products
+
+    /// Create a new root product.
+    pub async fn create_product<D: Serialize + DeserializeOwned>(
+        &self,
+        app_data: &D,
+    ) -> Result<ProductRecord<R, D>, Error> {
+        Self::json(self.request(Method::POST, &["product"]).json(app_data)).await
+    }
+
+    /// Create several new root products atomically, returning them in order.
+    pub async fn create_products<D: Serialize + DeserializeOwned>(
+        &self,
+        app_data: &[D],
+    ) -> Result<Vec<ProductRecord<R, D>>, Error> {
+        Self::json(
+            self.request(Method::POST, &["product", "bulk"])
+                .json(app_data),
+        )
+        .await
+    }
+
+    /// List the products existing as of a time (defaults to the current
+    /// time), a page at a time.
+    pub async fn query_products<D: DeserializeOwned>(
+        &self,
+        query: &ProductQuery<R::ProductId>,
+        as_of: Option<&R::DateTime>,
+    ) -> Result<ProductQueryResponse<R, D>, Error> {
+        Self::json(
+            self.paginated(Method::GET, &["product"])
+                .query(query)
+                .query(&AsOfQuery { as_of }),
+        )
+        .await
+    }
+
+    /// Search for products whose application data satisfies every filter of
+    /// the query, a page at a time.
+    pub async fn search_products<D: DeserializeOwned>(
+        &self,
+        query: &ProductSearch<R::ProductId>,
+    ) -> Result<ProductSearchResponse<R, D>, Error> {
+        Self::json(
+            self.paginated(Method::POST, &["product", "search"])
+                .json(query),
+        )
+        .await
+    }
+
+    /// Retrieve a product.
+    pub async fn get_product<D: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+    ) -> Result<ProductRecord<R, D>, Error> {
+        let product_id = segment(product_id)?;
+        Self::json(self.request(Method::GET, &["product", &product_id])).await
+    }
+
+    /// Retrieve a product as it was at `as_of`, which requires audit permission.
+    pub async fn get_product_as_of<D: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+        as_of: &R::DateTime,
+    ) -> Result<ProductRecord<R, D>, Error> {
+        let product_id = segment(product_id)?;
+        Self::json(
+            self.request(Method::GET, &["product", &product_id])
+                .query(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// Partition a product into children, returning the updated product
+    /// alongside the new children.
+    pub async fn partition_product<D: Serialize + DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+        children: &[PartitionItem<D>],
+    ) -> Result<ProductPartition<R, D>, Error> {
+        let product_id = segment(product_id)?;
+        Self::json(
+            self.request(Method::POST, &["product", &product_id])
+                .json(children),
+        )
+        .await
+    }
+
+    /// Retrieve a page of batch outcomes for a product, most recent first.
+    pub async fn product_outcomes<O: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<O, R::DateTime>, Error> {
+        let product_id = segment(product_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["product", &product_id, "outcomes"])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve a page of the aggregated price history for a product.
+    pub async fn product_summary(
+        &self,
+        product_id: &R::ProductId,
+        interval: PriceInterval,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<PriceSummary, R::DateTime>, Error> {
+        let product_id = segment(product_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["product", &product_id, "summary"])
+                .query(&SummaryQuery {
+                    interval,
+                    range: &query,
+                }),
+        )
+        .await
+    }
+
+    /// Retrieve market-wide statistics for every product, accumulating trade
+    /// volume over the window of `query`.
+    pub async fn market_statistics(
+        &self,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<MarketStatistics<R>, Error> {
+        Self::json(
+            self.request(Method::GET, &["product", "statistics"])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Subscribe to the batch outcomes of a product, in chronological order.
+    ///
+    /// Outcomes at or after `after` are sent first; if omitted, only outcomes
+    /// of future batches are sent. The stream ends if the connection is lost,
+    /// after which the subscription can be resumed from the last outcome.
+    pub async fn subscribe_product_outcomes<O: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+        after: Option<&R::DateTime>,
+    ) -> Result<impl Stream<Item = Result<ValueRecord<R::DateTime, O>, Error>> + use<O, R>, Error>
+    {
+        let product_id = segment(product_id)?;
+        let response = Self::send(
+            self.request(Method::GET, &["product", &product_id, "outcomes", "stream"])
+                .query(&AfterQuery { after }),
+        )
+        .await?;
+
+        Ok(sse::events(response)
+            .try_filter(|event| std::future::ready(event.event.as_deref() == Some("outcome")))
+            .map(|event| event?.json()))
+    }
+
+    // Demands
+
+    /// List the demands with a curve of the bidders the token may query,
+    /// following the pages of the listing.
+    pub async fn list_demands<D: DeserializeOwned>(
+        &self,
+    ) -> Result<Vec<DemandRecord<R, D>>, Error> {
+        let mut query = DemandQuery::default();
+        let mut demands = Vec::new();
+        loop {
+            let page = self.query_demands(query).await?;
+            demands.extend(page.results);
+            match page.more {
+                Some(more) => query = more,
+                None => return Ok(demands),
+            }
+        }
+    }
+
+    /// List a page of the demands of the bidders the token may query which
+    /// match the query, in order of their id.
+    pub async fn query_demands<D: DeserializeOwned>(
+        &self,
+        query: DemandQuery<R::DemandId, R::DateTime>,
+    ) -> Result<DemandQueryResponse<R, D>, Error> {
+        let (results, next): (Vec<DemandRecord<R, D>>, _) =
+            Self::page(self.paginated(Method::GET, &["demand"]).query(&query)).await?;
+        let more = results.last().filter(|_| next).map(|last| DemandQuery {
+            after: Some(last.id.clone()),
+            ..query
+        });
+        Ok(DemandQueryResponse { results, more })
+    }
+
+    /// List the demands of the bidders the token may query, restricted to
+    /// those belonging to a portfolio whose basis references the product.
+    pub async fn list_demands_by_product<D: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+    ) -> Result<Vec<DemandRecord<R, D>>, Error> {
+        Self::json(
+            self.request(Method::GET, &["demand"])
+                .query(&ProductIdQuery { product_id }),
+        )
+        .await
+    }
+
+    /// Create a new demand.
+    pub async fn create_demand<D: Serialize + DeserializeOwned>(
+        &self,
+        app_data: &D,
+        curve_data: &DemandCurve,
+    ) -> Result<DemandRecord<R, D>, Error> {
+        Self::json(self.request(Method::POST, &["demand"]).json(&CreateDemand {
+            app_data,
+            curve_data,
+        }))
+        .await
+    }
+
+    /// Validate a demand curve without creating anything, reporting every
+    /// problem with it. The curve need not be valid.
+    pub async fn validate_demand(&self, curve: &DemandCurveDto) -> Result<CurveValidation, Error> {
+        Self::json(
+            self.request(Method::POST, &["demand", "validate"])
+                .json(curve),
+        )
+        .await
+    }
+
+    /// Retrieve a demand.
+    pub async fn get_demand<D: DeserializeOwned>(
+        &self,
+        demand_id: &R::DemandId,
+    ) -> Result<DemandRecord<R, D>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(self.request(Method::GET, &["demand", &demand_id])).await
+    }
+
+    /// Retrieve a demand as it was at `as_of`, which requires audit permission.
+    pub async fn get_demand_as_of<D: DeserializeOwned>(
+        &self,
+        demand_id: &R::DemandId,
+        as_of: &R::DateTime,
+    ) -> Result<DemandRecord<R, D>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(
+            self.request(Method::GET, &["demand", &demand_id])
+                .query(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// Replace the curve of a demand.
+    pub async fn update_demand<D: DeserializeOwned>(
+        &self,
+        demand_id: &R::DemandId,
+        curve_data: &DemandCurve,
+    ) -> Result<DemandRecord<R, D>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(
+            self.request(Method::PUT, &["demand", &demand_id])
+                .json(curve_data),
+        )
+        .await
+    }
+
+    /// Delete a demand, i.e. remove its curve.
+    pub async fn delete_demand<D: DeserializeOwned>(
+        &self,
+        demand_id: &R::DemandId,
+    ) -> Result<DemandRecord<R, D>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(self.request(Method::DELETE, &["demand", &demand_id])).await
+    }
+
+    /// Permanently erase a demand and its history, leaving only a tombstone.
+    pub async fn purge_demand(
+        &self,
+        demand_id: &R::DemandId,
+    ) -> Result<Tombstone<R, R::DemandId>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(self.request(Method::POST, &["demand", &demand_id, "purge"])).await
+    }
+
+    /// Retrieve a page of the curve history of a demand, most recent first.
+    pub async fn demand_curve_history(
+        &self,
+        demand_id: &R::DemandId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<DemandCurve, R::DateTime>, Error> {
+        let demand_id = segment(demand_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["demand", &demand_id, "curve-history"])
+                .query(&query),
+        )
+        .await
+    }
+
+    // Portfolios
+
+    /// List the portfolios with non-empty groups of the bidders the token may
+    /// query, optionally expressing their bases in terms of the contemporary
+    /// product partitions. Unless expanded, the pages of the listing are
+    /// followed.
+    pub async fn list_portfolios<D: DeserializeOwned>(
+        &self,
+        expand: bool,
+    ) -> Result<Vec<PortfolioRecord<R, D>>, Error> {
+        if expand {
+            return Self::json(
+                self.request(Method::GET, &["portfolio"])
+                    .query(&ExpandQuery { expand }),
+            )
+            .await;
+        }
+
+        let mut query = PortfolioQuery::default();
+        let mut portfolios = Vec::new();
+        loop {
+            let page = self.query_portfolios(query).await?;
+            portfolios.extend(page.results);
+            match page.more {
+                Some(more) => query = more,
+                None => return Ok(portfolios),
+            }
+        }
+    }
+
+    /// List a page of the portfolios of the bidders the token may query
+    /// which match the query, in order of their id.
+    pub async fn query_portfolios<D: DeserializeOwned>(
+        &self,
+        query: PortfolioQuery<R::PortfolioId, R::DateTime>,
+    ) -> Result<PortfolioQueryResponse<R, D>, Error> {
+        let (results, next): (Vec<PortfolioRecord<R, D>>, _) =
+            Self::page(self.paginated(Method::GET, &["portfolio"]).query(&query)).await?;
+        let more = results.last().filter(|_| next).map(|last| PortfolioQuery {
+            after: Some(last.id.clone()),
+            ..query
+        });
+        Ok(PortfolioQueryResponse { results, more })
+    }
+
+    /// List the portfolios of the bidders the token may query, restricted to
+    /// those whose basis references the product.
+    ///
+    /// If `expand` is true, the bases are expressed in terms of the
+    /// contemporary product partitions, and the product is matched against
+    /// the expanded bases.
+    pub async fn list_portfolios_by_product<D: DeserializeOwned>(
+        &self,
+        product_id: &R::ProductId,
+        expand: bool,
+    ) -> Result<Vec<PortfolioRecord<R, D>>, Error> {
+        Self::json(
+            self.request(Method::GET, &["portfolio"])
+                .query(&ProductIdQuery { product_id })
+                .query(&ExpandQuery { expand }),
+        )
+        .await
+    }
+
+    /// Create a new portfolio.
+    pub async fn create_portfolio<D: Serialize + DeserializeOwned>(
+        &self,
+        app_data: &D,
+        demand: &Weights<R::DemandId>,
+        basis: &Basis<R::ProductId>,
+    ) -> Result<PortfolioRecord<R, D>, Error> {
+        Self::json(
+            self.request(Method::POST, &["portfolio"])
+                .json(&CreatePortfolio {
+                    app_data,
+                    demand,
+                    basis,
+                }),
+        )
+        .await
+    }
+
+    /// Retrieve a portfolio, optionally expressing its basis in terms of the
+    /// contemporary product partitions.
+    pub async fn get_portfolio<D: DeserializeOwned>(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        expand: bool,
+    ) -> Result<PortfolioRecord<R, D>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.request(Method::GET, &["portfolio", &portfolio_id])
+                .query(&ExpandQuery { expand }),
+        )
+        .await
+    }
+
+    /// Retrieve a portfolio as it was at `as_of`, which requires audit permission.
+    pub async fn get_portfolio_as_of<D: DeserializeOwned>(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        expand: bool,
+        as_of: &R::DateTime,
+    ) -> Result<PortfolioRecord<R, D>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.request(Method::GET, &["portfolio", &portfolio_id])
+                .query(&ExpandQuery { expand })
+                .query(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// Replace the demand weights and/or product basis of a portfolio.
+    pub async fn update_portfolio<D: DeserializeOwned>(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        demand: Option<&Weights<R::DemandId>>,
+        basis: Option<&Basis<R::ProductId>>,
+    ) -> Result<PortfolioRecord<R, D>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.request(Method::PATCH, &["portfolio", &portfolio_id])
+                .json(&UpdatePortfolio { demand, basis }),
+        )
+        .await
+    }
+
+    /// Delete a portfolio, i.e. remove its demand weights and product basis.
+    pub async fn delete_portfolio<D: DeserializeOwned>(
+        &self,
+        portfolio_id: &R::PortfolioId,
+    ) -> Result<PortfolioRecord<R, D>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(self.request(Method::DELETE, &["portfolio", &portfolio_id])).await
+    }
+
+    /// Permanently erase a portfolio and its history, leaving only a tombstone.
+    pub async fn purge_portfolio(
+        &self,
+        portfolio_id: &R::PortfolioId,
+    ) -> Result<Tombstone<R, R::PortfolioId>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(self.request(Method::POST, &["portfolio", &portfolio_id, "purge"])).await
+    }
+
+    /// Retrieve a page of the demand history of a portfolio, most recent first.
+    pub async fn portfolio_demand_history(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<Weights<R::DemandId>, R::DateTime>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["portfolio", &portfolio_id, "demand-history"])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve a page of the product history of a portfolio, most recent first.
+    pub async fn portfolio_product_history(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<Basis<R::ProductId>, R::DateTime>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.paginated(
+                Method::GET,
+                &["portfolio", &portfolio_id, "product-history"],
+            )
+            .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve a page of batch outcomes for a portfolio, most recent first.
+    pub async fn portfolio_outcomes<O: DeserializeOwned>(
+        &self,
+        portfolio_id: &R::PortfolioId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<O, R::DateTime>, Error> {
+        let portfolio_id = segment(portfolio_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["portfolio", &portfolio_id, "outcomes"])
+                .query(&query),
+        )
+        .await
+    }
+
+    // Batches
+
+    /// Execute a batch auction, returning the time of the batch.
+    pub async fn run_batch(&self) -> Result<R::DateTime, Error> {
+        let text = Self::send(self.request(Method::POST, &["batch"]))
+            .await?
+            .text()
+            .await?;
+        Ok(serde_json::from_value(serde_json::Value::String(text))?)
+    }
+
+    /// Solve a batch auction without recording it, returning the hypothetical outcomes.
+    pub async fn preview_batch<PO: DeserializeOwned, PR: DeserializeOwned>(
+        &self,
+    ) -> Result<BatchPreview<R, PO, PR>, Error> {
+        Self::json(self.request(Method::POST, &["batch", "preview"])).await
+    }
+
+    /// Solve a self-contained auction with the server's solver, without
+    /// reading from or recording to its repository.
+    pub async fn solve_auction<PO: DeserializeOwned, PR: DeserializeOwned>(
+        &self,
+        auction: &Auction<R>,
+    ) -> Result<AuctionOutcome<R, PO, PR>, Error> {
+        Self::json(
+            self.request(Method::POST, &["batch", "adhoc"])
+                .json(auction),
+        )
+        .await
+    }
+
+    /// Wait for a batch auction executed after `after` (or the time of the
+    /// request) to complete, returning None if none does within `timeout`
+    /// (or the server's maximum wait).
+    pub async fn next_batch(
+        &self,
+        after: Option<&R::DateTime>,
+        timeout: Option<Duration>,
+    ) -> Result<Option<BatchMetadata<R>>, Error> {
+        let response = Self::send(self.request(Method::GET, &["batch", "next"]).query(
+            &NextQuery {
+                after,
+                timeout: timeout.map(|timeout| timeout.as_secs()),
+            },
+        ))
+        .await?;
+        if response.status() == reqwest::StatusCode::NO_CONTENT {
+            Ok(None)
+        } else {
+            Ok(Some(serde_json::from_slice(&response.bytes().await?)?))
+        }
+    }
+
+    /// Retrieve a page of the records of how batch auctions were solved, most
+    /// recent first.
+    pub async fn batch_runs(
+        &self,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<BatchRun, R::DateTime>, Error> {
+        Self::json(
+            self.paginated(Method::GET, &["batch", "runs"])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve a page of the failed attempts to run a batch auction, most
+    /// recent first.
+    pub async fn batch_attempts(
+        &self,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<BatchAttempt, R::DateTime>, Error> {
+        Self::json(
+            self.paginated(Method::GET, &["batch", "attempts"])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve the inputs of the batch auction executed at `as_of`, exactly
+    /// as they were assembled for the solver, which may be solved again with
+    /// [`solve_auction`](Self::solve_auction).
+    pub async fn batch_inputs(&self, as_of: &R::DateTime) -> Result<Auction<R>, Error> {
+        Self::json(
+            self.request(Method::GET, &["batch", "inputs"])
+                .query(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// Retrieve the schedule on which the server executes batch auctions.
+    pub async fn schedule(&self) -> Result<BatchSchedule<R::DateTime>, Error> {
+        Self::json(self.request(Method::GET, &["batch", "schedule"])).await
+    }
+
+    /// Change the schedule on which the server executes batch auctions,
+    /// returning the updated schedule.
+    pub async fn update_schedule(
+        &self,
+        update: &ScheduleUpdate<R::DateTime>,
+    ) -> Result<BatchSchedule<R::DateTime>, Error> {
+        Self::json(
+            self.request(Method::PATCH, &["batch", "schedule"])
+                .json(update),
+        )
+        .await
+    }
+
+    // Settlements
+
+    /// Settle the trade activity of every bidder up to `config.as_of`.
+    pub async fn settle(
+        &self,
+        config: &SettlementConfig<R::DateTime>,
+    ) -> Result<SettlementRecord<R>, Error> {
+        Self::json(self.request(Method::POST, &["settlement"]).json(config)).await
+    }
+
+    /// Reverse the settlement made up to `as_of`.
+    pub async fn reverse_settlement(
+        &self,
+        as_of: &R::DateTime,
+    ) -> Result<SettlementRevision<R>, Error> {
+        Self::json(
+            self.request(Method::POST, &["settlement", "reversal"])
+                .json(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// Correct the settlement made up to `config.as_of`, settling its interval
+    /// again with the given configuration.
+    pub async fn correct_settlement(
+        &self,
+        config: &SettlementConfig<R::DateTime>,
+    ) -> Result<SettlementRevision<R>, Error> {
+        Self::json(
+            self.request(Method::POST, &["settlement", "correction"])
+                .json(config),
+        )
+        .await
+    }
+
+    /// Retrieve every revision of the settlement made up to `as_of`, in order.
+    pub async fn settlement_revisions(
+        &self,
+        as_of: &R::DateTime,
+    ) -> Result<Vec<SettlementRevision<R>>, Error> {
+        Self::json(
+            self.request(Method::GET, &["settlement", "revisions"])
+                .query(&AsOfQuery { as_of: Some(as_of) }),
+        )
+        .await
+    }
+
+    /// List the statements issued for the settlement as of `as_of` and its
+    /// revisions, optionally only those issued to a bidder.
+    pub async fn settlement_statements(
+        &self,
+        as_of: &R::DateTime,
+        bidder_id: Option<&R::BidderId>,
+    ) -> Result<Vec<SettlementStatement<R>>, Error> {
+        Self::json(
+            self.request(Method::GET, &["settlement", "statements"])
+                .query(&StatementQuery { as_of, bidder_id }),
+        )
+        .await
+    }
+
+    /// Retrieve a settlement statement.
+    pub async fn settlement_statement(
+        &self,
+        statement_id: u64,
+    ) -> Result<SettlementStatement<R>, Error> {
+        Self::json(self.request(
+            Method::GET,
+            &["settlement", "statements", &statement_id.to_string()],
+        ))
+        .await
+    }
+
+    /// Retrieve a settlement statement as rendered by the server's application
+    /// in the given media type (e.g. `application/pdf`).
+    pub async fn render_settlement_statement(
+        &self,
+        statement_id: u64,
+        media_type: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let request = self
+            .request(
+                Method::GET,
+                &["settlement", "statements", &statement_id.to_string()],
+            )
+            .header(ACCEPT, media_type);
+        Ok(Self::send(request).await?.bytes().await?.to_vec())
+    }
+
+    /// Retrieve a page of the settlement history of a bidder, most recent first.
+    pub async fn settlement_history(
+        &self,
+        bidder_id: &R::BidderId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<Activity<R::ProductId>, R::DateTime>, Error> {
+        let bidder_id = segment(bidder_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["settlement", &bidder_id])
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Retrieve the trade activity of a bidder since its last settlement,
+    /// accrued up to `as_of` (or the current time).
+    pub async fn unsettled_activity(
+        &self,
+        bidder_id: &R::BidderId,
+        as_of: Option<&R::DateTime>,
+    ) -> Result<ValueRecord<R::DateTime, Activity<R::ProductId>>, Error> {
+        let bidder_id = segment(bidder_id)?;
+        Self::json(
+            self.request(Method::GET, &["settlement", &bidder_id, "unsettled"])
+                .query(&AsOfQuery { as_of }),
+        )
+        .await
+    }
+
+    /// Retrieve a page of the trade activity of a bidder since its last
+    /// settlement, accrued up to `as_of` (or the current time).
+    pub async fn unsettled_positions(
+        &self,
+        bidder_id: &R::BidderId,
+        as_of: Option<&R::DateTime>,
+        query: &UnsettledActivityQuery<R::ProductId>,
+    ) -> Result<UnsettledActivityResponse<R>, Error> {
+        let bidder_id = segment(bidder_id)?;
+        Self::json(
+            self.paginated(Method::POST, &["settlement", &bidder_id, "unsettled"])
+                .query(&AsOfQuery { as_of })
+                .json(query),
+        )
+        .await
+    }
+
+    // Bidders
+
+    /// Summarize the trading of a bidder as of the current time.
+    pub async fn bidder_summary(&self, bidder_id: &R::BidderId) -> Result<BidderSummary<R>, Error> {
+        let bidder_id = segment(bidder_id)?;
+        Self::json(self.request(Method::GET, &["bidder", &bidder_id, "summary"])).await
+    }
+
+    /// Retrieve a page of a bidder's batch outcomes, aggregated across all of
+    /// their portfolios, most recent first.
+    pub async fn bidder_outcomes(
+        &self,
+        bidder_id: &R::BidderId,
+        query: DateTimeRangeQuery<R::DateTime>,
+    ) -> Result<DateTimeRangeResponse<BidderOutcome<R::ProductId>, R::DateTime>, Error> {
+        let bidder_id = segment(bidder_id)?;
+        Self::json(
+            self.paginated(Method::GET, &["bidder", &bidder_id, "outcomes"])
+                .query(&query),
+        )
+        .await
+    }
+
+    // Imports
+
+    /// Import products, demands, and portfolios from a single document, all
+    /// or nothing. A dry run validates the document without importing it.
+    pub async fn import<DemandData: Serialize, PortfolioData: Serialize, ProductData: Serialize>(
+        &self,
+        document: &ImportDocument<R, DemandData, PortfolioData, ProductData>,
+        dry_run: bool,
+    ) -> Result<ImportRecord, Error> {
+        Self::json(
+            self.request(Method::POST, &["import"])
+                .query(&DryRunQuery { dry_run })
+                .json(document),
+        )
+        .await
+    }
+}
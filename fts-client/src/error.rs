@@ -0,0 +1,46 @@
+/// The errors that may occur when interacting with the server.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request could not be sent, or the response could not be read
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    /// The server responded with an unsuccessful status code
+    #[error("server responded with {status}: {message}")]
+    Status {
+        /// The status code of the response
+        status: reqwest::StatusCode,
+        /// The machine-readable error code, if the body was a problem report
+        code: Option<String>,
+        /// The body of the response, if any
+        message: String,
+    },
+
+    /// The response (or an event) could not be decoded
+    #[error("failed to decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+
+    /// The event stream was malformed
+    #[error("invalid event stream: {0}")]
+    EventStream(String),
+}
+
+impl Error {
+    /// The status code returned by the server, if the request reached it.
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::Request(err) => err.status(),
+            _ => None,
+        }
+    }
+
+    /// The machine-readable error code reported by the server (e.g.
+    /// `demand_not_found`), if any.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Status { code, .. } => code.as_deref(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+#![warn(missing_docs)]
+//! A typed async client for the REST API of `fts-axum`.
+//!
+//! The [`Client`] provides a method for every endpoint of the API, returning
+//! the same models (from `fts_core`) that the server produces. As these models
+//! are generic over the identifier and timestamp types of the server's
+//! [`Repository`], so is the client. Any type implementing [`Repository`] with
+//! the appropriate types can be used, such as the [`Remote`] marker.
+//!
+//! Paginated endpoints return a single page, but [`paginate`] can be used to
+//! stream every record across pages. Outcomes of batch auctions can be
+//! subscribed to as they are computed, via server-sent events (see [`sse`]).
+
+use fts_core::ports::Repository;
+use std::{hash::Hash, marker::PhantomData};
+
+mod client;
+pub use client::{Client, PartitionItem};
+
+mod error;
+pub use error::Error;
+
+mod pagination;
+pub use pagination::paginate;
+
+pub mod sse;
+
+/// A marker type describing a server whose timestamps have type `DateTime`
+/// and whose identifiers all have type `Id` (e.g. a UUID).
+///
+/// The client does not interact with any database, but the `fts_core` models
+/// are parameterized by a [`Repository`], so this type implements it.
+pub struct Remote<DateTime, Id>(PhantomData<fn() -> (DateTime, Id)>);
+
+impl<DateTime, Id: Eq + Hash> Repository for Remote<DateTime, Id> {
+    type Error = Error;
+    type DateTime = DateTime;
+    type BidderId = Id;
+    type DemandId = Id;
+    type PortfolioId = Id;
+    type ProductId = Id;
+}
@@ -0,0 +1,45 @@
+use crate::Error;
+use fts_core::models::{DateTimeRangeQuery, DateTimeRangeResponse, ValueRecord};
+use futures_util::{Stream, TryStreamExt as _, stream};
+
+/// Stream every record of a paginated endpoint, starting from `query`.
+///
+/// The `fetch` closure is called with the query for each page, most recent
+/// first, until the server indicates there are no more records.
+///
+/// ```no_run
+/// # async fn example(client: fts_client::Client<fts_client::Remote<String, String>>) {
+/// use fts_core::models::DateTimeRangeQuery;
+/// use futures_util::TryStreamExt as _;
+///
+/// let product_id = "my-product".to_string();
+/// let query = DateTimeRangeQuery {
+///     before: None,
+///     after: None,
+/// };
+/// let outcomes: Vec<_> = fts_client::paginate(query, |query| {
+///     client.product_outcomes::<serde_json::Value>(&product_id, query)
+/// })
+/// .try_collect()
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+pub fn paginate<V, DateTime, F, Fut>(
+    query: DateTimeRangeQuery<DateTime>,
+    fetch: F,
+) -> impl Stream<Item = Result<ValueRecord<DateTime, V>, Error>>
+where
+    F: FnMut(DateTimeRangeQuery<DateTime>) -> Fut,
+    Fut: Future<Output = Result<DateTimeRangeResponse<V, DateTime>, Error>>,
+{
+    stream::try_unfold((Some(query), fetch), |(query, mut fetch)| async move {
+        let Some(query) = query else {
+            return Ok::<_, Error>(None);
+        };
+        let page = fetch(query).await?;
+        let records = stream::iter(page.results.into_iter().map(Ok));
+        Ok(Some((records, (page.more, fetch))))
+    })
+    .try_flatten()
+}
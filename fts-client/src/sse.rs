@@ -0,0 +1,139 @@
+//! A minimal decoder for server-sent events.
+//!
+//! Only the parts of the [specification](https://html.spec.whatwg.org/multipage/server-sent-events.html)
+//! relevant to consuming the API are implemented: the `event`, `data`, and
+//! `id` fields are collected, while comments (used by the server as
+//! keep-alives) and the `retry` field are ignored.
+
+use crate::Error;
+use bytes::{Buf as _, BytesMut};
+use futures_util::{Stream, StreamExt as _, TryStreamExt as _, stream};
+
+/// A single server-sent event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    /// The type of the event, if specified
+    pub event: Option<String>,
+    /// The id of the event, if specified
+    pub id: Option<String>,
+    /// The data of the event, with multiple `data` fields joined by newlines
+    pub data: String,
+}
+
+impl Event {
+    /// Decode the data of the event as JSON.
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.data)?)
+    }
+}
+
+/// Decode the body of an `text/event-stream` response into events.
+///
+/// The stream ends when the server closes the connection.
+pub fn events(response: reqwest::Response) -> impl Stream<Item = Result<Event, Error>> {
+    decode(response.bytes_stream().map_err(Error::from))
+}
+
+/// Decode a stream of bytes into events.
+pub(crate) fn decode<S>(bytes: S) -> impl Stream<Item = Result<Event, Error>>
+where
+    S: Stream<Item = Result<bytes::Bytes, Error>> + Unpin,
+{
+    let state = (bytes, BytesMut::new(), Event::default());
+    stream::try_unfold(state, |(mut bytes, mut buffer, mut event)| async move {
+        loop {
+            // Process every complete line in the buffer
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line = buffer.split_to(end);
+                buffer.advance(1);
+                let line = std::str::from_utf8(&line)
+                    .map_err(|err| Error::EventStream(err.to_string()))?
+                    .trim_end_matches('\r');
+
+                // A blank line dispatches the event, unless it is empty
+                if line.is_empty() {
+                    if event.data.is_empty() && event.event.is_none() {
+                        continue;
+                    }
+                    let mut dispatched = std::mem::take(&mut event);
+                    if dispatched.data.ends_with('\n') {
+                        dispatched.data.pop();
+                    }
+                    return Ok(Some((dispatched, (bytes, buffer, event))));
+                }
+
+                let (field, value) = match line.split_once(':') {
+                    Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                    None => (line, ""),
+                };
+                match field {
+                    "event" => event.event = Some(value.to_owned()),
+                    "id" => event.id = Some(value.to_owned()),
+                    "data" => {
+                        event.data.push_str(value);
+                        event.data.push('\n');
+                    }
+                    // Comments, `retry`, and unknown fields are ignored
+                    _ => {}
+                }
+            }
+
+            match bytes.next().await {
+                Some(chunk) => buffer.extend_from_slice(&chunk?),
+                None => return Ok(None),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn collect(chunks: &[&'static str]) -> Vec<Event> {
+        let bytes = stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes()))),
+        );
+        decode(bytes).try_collect().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_decode_events() {
+        let events = collect(&[
+            ": keep-alive\n\n",
+            "event: outcome\nid: 1\ndata: {\"a\":\n",
+            "data: 1}\n\nda",
+            "ta: second\r\n\r\n",
+        ])
+        .await;
+
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    event: Some("outcome".to_owned()),
+                    id: Some("1".to_owned()),
+                    data: "{\"a\":\n1}".to_owned(),
+                },
+                Event {
+                    event: None,
+                    id: None,
+                    data: "second".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            events[0].json::<serde_json::Value>().unwrap(),
+            serde_json::json!({ "a": 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_event_is_dropped() {
+        let events = collect(&["data: complete\n\n", "data: incomplete\n"]).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "complete");
+    }
+}
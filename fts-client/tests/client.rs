@@ -0,0 +1,591 @@
+mod common;
+
+use common::TestApp;
+use fts_axum::config::AxumConfig;
+use fts_client::{Client, PartitionItem, Remote, paginate};
+use fts_core::models::{
+    Auction, AuctionPortfolio, Basis, DateTimeRangeQuery, DemandCurve, DemandQuery, ImportDemand,
+    ImportDocument, ImportPortfolio, ImportProduct, Map, PortfolioQuery, PriceInterval,
+    ProductQuery, ProductSearch, SettlementConfig, UnsettledActivityQuery, Weights,
+};
+use fts_solver::{PortfolioOutcome, ProductOutcome};
+use fts_sqlite::{Db, config::SqliteConfig, types::DateTime};
+use futures_util::{StreamExt as _, TryStreamExt as _};
+use std::time::Duration;
+use uuid::Uuid;
+
+type TestClient = Client<Remote<DateTime, Uuid>>;
+
+fn curve(points: serde_json::Value) -> DemandCurve {
+    serde_json::from_value(points).unwrap()
+}
+
+fn everything() -> DateTimeRangeQuery<DateTime> {
+    DateTimeRangeQuery {
+        before: None,
+        after: None,
+    }
+}
+
+/// Serve the API on a random local port, returning its address
+async fn start(app: TestApp) -> anyhow::Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    let config = AxumConfig {
+        page_limit: 2,
+        poll_interval: Duration::from_millis(50),
+        ..Default::default()
+    };
+    tokio::spawn(axum::serve(listener, fts_axum::router(app, config)).into_future());
+    Ok(format!("http://{address}"))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_client_session() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+    let address = start(TestApp(db)).await?;
+
+    let buyer = Uuid::new_v4();
+    let seller = Uuid::new_v4();
+    let buyer_client = TestClient::new(address.as_str())?.with_token(buyer.to_string());
+    let seller_client = TestClient::new(address.as_str())?.with_token(seller.to_string());
+
+    buyer_client.health().await?;
+
+    // Requests without a token are rejected
+    let err = TestClient::new(address.as_str())?
+        .list_demands::<()>()
+        .await
+        .err()
+        .expect("request should be rejected");
+    assert_eq!(err.status(), Some(reqwest::StatusCode::BAD_REQUEST));
+
+    let product = buyer_client.create_product(&()).await?;
+
+    let products = buyer_client.create_products(&[(), ()]).await?;
+    assert_eq!(products.len(), 2);
+    assert_ne!(products[0].id, products[1].id);
+
+    // Searching pages through the products
+    let search = ProductSearch {
+        filters: Vec::new(),
+        after: None,
+    };
+    let page = buyer_client.search_products::<()>(&search).await?;
+    assert_eq!(page.results.len(), 2);
+    let page = buyer_client
+        .search_products::<()>(&page.more.expect("more products"))
+        .await?;
+    assert_eq!(page.results.len(), 1);
+    assert!(page.more.is_none());
+
+    // Partitioning reports the parent's updated expansion
+    let partition = buyer_client
+        .partition_product(
+            &products[0].id,
+            &[
+                PartitionItem {
+                    app_data: (),
+                    ratio: 1.0,
+                },
+                PartitionItem {
+                    app_data: (),
+                    ratio: 0.5,
+                },
+            ],
+        )
+        .await?;
+    assert_eq!(partition.parent.id, products[0].id);
+    assert_eq!(partition.children.len(), 2);
+    let child = &partition.children[1];
+    assert_eq!(child.parent, (products[0].id, 0.5));
+    assert_eq!(partition.parent.basis.get(&child.id), Some(&0.5));
+
+    // The catalogue lists the root products unless the tree is requested
+    let roots = buyer_client
+        .query_products::<()>(&ProductQuery::default(), None)
+        .await?;
+    assert_eq!(roots.results.len(), 2);
+    let roots = buyer_client
+        .query_products::<()>(&roots.more.expect("more products"), None)
+        .await?;
+    assert_eq!(roots.results.len(), 1);
+    assert!(roots.more.is_none());
+    let mut tree = Vec::new();
+    let mut query = Some(ProductQuery {
+        include_tree: true,
+        after: None,
+    });
+    while let Some(next) = query {
+        let page = buyer_client.query_products::<()>(&next, None).await?;
+        tree.extend(page.results.into_iter().map(|product| product.id));
+        query = page.more;
+    }
+    assert_eq!(tree.len(), 5);
+    assert!(tree.contains(&child.id));
+
+    // Invalid curves can be diagnosed before they are submitted
+    let validation = buyer_client
+        .validate_demand(&serde_json::from_value(serde_json::json!([
+            { "rate": 1, "price": 10 },
+            { "rate": 10, "price": 20 }
+        ]))?)
+        .await?;
+    assert!(!validation.valid);
+    let codes: Vec<_> = validation
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.code.as_str())
+        .collect();
+    assert_eq!(codes, ["zero_trade", "non_monotone"]);
+
+    let mut portfolios = Vec::new();
+    for (client, points) in [
+        (
+            &buyer_client,
+            serde_json::json!([{ "rate": 0, "price": 10 }, { "rate": 10, "price": 0 }]),
+        ),
+        (
+            &seller_client,
+            serde_json::json!([{ "rate": -10, "price": 10 }, { "rate": 0, "price": 0 }]),
+        ),
+    ] {
+        let demand = client.create_demand(&(), &curve(points)).await?;
+        let portfolio = client
+            .create_portfolio(
+                &(),
+                &Weights::from_iter([(demand.id, 1.0)]),
+                &Basis::from_iter([(product.id, 1.0)]),
+            )
+            .await?;
+        assert_eq!(portfolio.demand.get(&demand.id), Some(&1.0));
+        portfolios.push(portfolio);
+    }
+    let (buyer_portfolio, seller_portfolio) = (&portfolios[0], &portfolios[1]);
+
+    // Bidders cannot read each other's bids
+    let err = seller_client
+        .get_portfolio::<()>(&buyer_portfolio.id, false)
+        .await
+        .err()
+        .expect("request should be rejected");
+    assert_eq!(err.status(), Some(reqwest::StatusCode::UNAUTHORIZED));
+    assert_eq!(err.code(), Some("not_bid_owner"));
+
+    assert_eq!(buyer_client.list_demands::<()>().await?.len(), 1);
+    assert_eq!(seller_client.list_portfolios::<()>(false).await?.len(), 1);
+    assert_eq!(
+        buyer_client
+            .list_portfolios_by_product::<()>(&product.id, true)
+            .await?
+            .len(),
+        1
+    );
+    assert_eq!(
+        buyer_client
+            .list_demands_by_product::<()>(&Uuid::new_v4())
+            .await?
+            .len(),
+        0
+    );
+
+    // Previewing a batch solves it without recording any outcomes
+    let preview = buyer_client
+        .preview_batch::<PortfolioOutcome, ProductOutcome>()
+        .await?;
+    assert!((preview.products[&product.id].price - 5.0).abs() < 1e-6);
+    assert!(
+        buyer_client
+            .product_outcomes::<ProductOutcome>(&product.id, everything())
+            .await?
+            .results
+            .is_empty()
+    );
+
+    // Self-contained auctions are solved without reference to the bids
+    let (bid, ask, product_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let auction = Auction {
+        demand_curves: Map::from_iter([
+            (
+                bid,
+                curve(serde_json::json!([{ "rate": 0, "price": 8 }, { "rate": 4, "price": 0 }])),
+            ),
+            (
+                ask,
+                curve(serde_json::json!([{ "rate": -4, "price": 8 }, { "rate": 0, "price": 0 }])),
+            ),
+        ]),
+        portfolios: Map::from_iter([bid, ask].map(|demand_id| {
+            (
+                demand_id,
+                AuctionPortfolio {
+                    demand: Weights::from_iter([(demand_id, 1.0)]),
+                    basis: Basis::from_iter([(product_id, 1.0)]),
+                },
+            )
+        })),
+    };
+    let outcome = buyer_client
+        .solve_auction::<PortfolioOutcome, ProductOutcome>(&auction)
+        .await?;
+    assert!((outcome.products[&product_id].price - 4.0).abs() < 1e-6);
+    assert!((outcome.portfolios[&bid].rate - 2.0).abs() < 1e-6);
+
+    // The test application does not schedule batch auctions
+    let err = buyer_client
+        .schedule()
+        .await
+        .err()
+        .expect("batches are not scheduled");
+    assert_eq!(err.code(), Some("schedule_not_found"));
+
+    // Subscribe before any batch has been executed
+    let mut subscription = Box::pin(
+        buyer_client
+            .subscribe_product_outcomes::<ProductOutcome>(&product.id, None)
+            .await?,
+    );
+
+    // Every batch is streamed as it is executed
+    let mut batches = Vec::new();
+    for _ in 0..3 {
+        let batch = buyer_client.run_batch().await?;
+        let outcome = tokio::time::timeout(Duration::from_secs(5), subscription.next())
+            .await?
+            .expect("stream ended")?;
+        assert_eq!(outcome.valid_from, batch);
+        assert!((outcome.value.price - 5.0).abs() < 1e-6);
+        assert!((outcome.value.rate - 5.0).abs() < 1e-6);
+        batches.push(batch);
+    }
+
+    // Long-polling responds immediately, as a newer batch has completed
+    let next = buyer_client
+        .next_batch(Some(&batches[0]), Some(Duration::ZERO))
+        .await?
+        .expect("batch completed");
+    assert_eq!(next.as_of, batches[2]);
+    assert_eq!((next.portfolios, next.products), (2, 1));
+    assert!(
+        buyer_client
+            .next_batch(Some(&batches[2]), Some(Duration::ZERO))
+            .await?
+            .is_none()
+    );
+
+    // Each batch records how it was solved
+    let runs = buyer_client.batch_runs(everything()).await?;
+    assert_eq!(runs.results.len(), 2);
+    assert_eq!(runs.results[0].valid_from, batches[2]);
+    assert_eq!(runs.results[0].value.solver.backend, "clarabel");
+    assert_eq!(runs.results[0].value.solver.status, "Solved");
+    assert!(runs.results[0].value.error.is_none());
+    assert!(
+        buyer_client
+            .batch_attempts(everything())
+            .await?
+            .results
+            .is_empty()
+    );
+
+    // A single page is bounded by the page limit, while `paginate` retrieves everything
+    let page = buyer_client
+        .product_outcomes::<ProductOutcome>(&product.id, everything())
+        .await?;
+    assert_eq!(page.results.len(), 2);
+    assert!(page.more.is_some());
+
+    // Unless the client chooses another page limit
+    let page = buyer_client
+        .clone()
+        .with_page_limit(3)
+        .product_outcomes::<ProductOutcome>(&product.id, everything())
+        .await?;
+    assert_eq!(page.results.len(), 3);
+    assert!(page.more.is_none());
+
+    let err = paginate(everything(), |query| {
+        buyer_client.portfolio_outcomes::<PortfolioOutcome>(&seller_portfolio.id, query)
+    })
+    .try_collect::<Vec<_>>()
+    .await
+    .err()
+    .expect("request should be rejected");
+    assert_eq!(err.status(), Some(reqwest::StatusCode::UNAUTHORIZED));
+
+    let outcomes: Vec<_> = paginate(everything(), |query| {
+        seller_client.portfolio_outcomes::<PortfolioOutcome>(&seller_portfolio.id, query)
+    })
+    .try_collect()
+    .await?;
+    let times: Vec<_> = outcomes.iter().rev().map(|o| o.valid_from).collect();
+    assert_eq!(times, batches);
+    assert!(outcomes.iter().all(|o| (o.value.rate + 5.0).abs() < 1e-6));
+
+    // A CSV export without a limit streams every outcome as a single table
+    let csv = reqwest::Client::new()
+        .get(format!("{address}/v1/product/{}/outcomes", product.id))
+        .bearer_auth(buyer)
+        .header(reqwest::header::ACCEPT, "text/csv")
+        .send()
+        .await?
+        .error_for_status()?;
+    assert!(!csv.headers().contains_key(reqwest::header::LINK));
+    let csv = csv.text().await?;
+    assert_eq!(csv.lines().count(), 1 + batches.len());
+    assert!(csv.lines().next().unwrap().contains("value.price"));
+
+    let summary = buyer_client
+        .product_summary(&product.id, PriceInterval::Day, everything())
+        .await?;
+    assert_eq!(summary.results.len(), 1);
+    assert_eq!(summary.results[0].value.batches, 3);
+
+    let statistics = buyer_client.market_statistics(everything()).await?;
+    let product_statistics = &statistics.products[&product.id];
+    assert!((product_statistics.price.unwrap() - 5.0).abs() < 1e-6);
+    assert_eq!(product_statistics.portfolios, 2);
+    assert!(product_statistics.volume > 0.0);
+
+    // Updates are reflected in the history
+    let updated = buyer_client
+        .update_demand::<()>(
+            buyer_portfolio.demand.keys().next().unwrap(),
+            &curve(serde_json::json!({ "price": 7 })),
+        )
+        .await?;
+    let history = buyer_client
+        .demand_curve_history(&updated.id, everything())
+        .await?;
+    assert_eq!(history.results.len(), 2);
+
+    // The demand may still be viewed as it was before the update
+    let original = buyer_client
+        .get_demand_as_of::<()>(&updated.id, &history.results[1].valid_from)
+        .await?;
+    assert_eq!(original.valid_until, Some(updated.valid_from));
+    assert_eq!(original.valid_from, history.results[1].valid_from);
+
+    let updated = buyer_client
+        .update_portfolio::<()>(
+            &buyer_portfolio.id,
+            None,
+            Some(&Basis::from_iter([(product.id, 2.0)])),
+        )
+        .await?;
+    assert_eq!(updated.basis.get(&product.id), Some(&2.0));
+    let history = buyer_client
+        .portfolio_product_history(&buyer_portfolio.id, everything())
+        .await?;
+    assert_eq!(history.results.len(), 2);
+
+    // Settle everything up to the last batch
+    let unsettled = buyer_client
+        .unsettled_activity(&buyer, Some(batches.last().unwrap()))
+        .await?;
+    assert!(unsettled.value.positions.get(&product.id).is_some());
+
+    let page = buyer_client
+        .unsettled_positions(
+            &buyer,
+            Some(batches.last().unwrap()),
+            &UnsettledActivityQuery {
+                products: vec![product.id],
+                after: None,
+            },
+        )
+        .await?;
+    assert!(page.more.is_none());
+    assert_eq!(
+        page.activity.value.positions.get(&product.id),
+        unsettled.value.positions.get(&product.id)
+    );
+    assert_eq!(page.activity.value.payment, unsettled.value.payment);
+
+    let settlement = buyer_client
+        .settle(&SettlementConfig {
+            as_of: *batches.last().unwrap(),
+            time_unit: 1.0,
+            position_decimals: 6,
+            payment_decimals: 6,
+        })
+        .await?;
+    assert!(settlement.activity.contains_key(&buyer));
+    assert!(settlement.activity.contains_key(&seller));
+
+    let history = seller_client
+        .settlement_history(&seller, everything())
+        .await?;
+    assert_eq!(history.results.len(), 1);
+
+    let correction = buyer_client
+        .correct_settlement(&SettlementConfig {
+            as_of: *batches.last().unwrap(),
+            time_unit: 1.0,
+            position_decimals: 3,
+            payment_decimals: 3,
+        })
+        .await?;
+    assert_eq!(correction.revision, 2);
+    let revisions = buyer_client
+        .settlement_revisions(batches.last().unwrap())
+        .await?;
+    assert_eq!(revisions.len(), 2);
+    assert!(revisions[0].config.is_none());
+
+    let statements = seller_client
+        .settlement_statements(batches.last().unwrap(), Some(&seller))
+        .await?;
+    assert_eq!(statements.len(), 3);
+    let statement = seller_client.settlement_statement(statements[2].id).await?;
+    assert_eq!(statement.revision, 2);
+    assert_eq!(statement.activity, correction.activity[&seller]);
+    let unrendered = seller_client
+        .render_settlement_statement(statement.id, "application/pdf")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        unrendered.status(),
+        Some(reqwest::StatusCode::NOT_ACCEPTABLE)
+    );
+
+    let summary = seller_client.bidder_summary(&seller).await?;
+    assert!((summary.rates[&product.id] + 5.0).abs() < 1e-6);
+    assert!(summary.settled.positions[&product.id] < 0.0);
+    assert!(summary.positions[&product.id] <= summary.settled.positions[&product.id]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_client_listing() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+    let address = start(TestApp(db)).await?;
+
+    let bidder = Uuid::new_v4();
+    let client = TestClient::new(address.as_str())?
+        .with_token(bidder.to_string())
+        .with_page_limit(1);
+
+    // Two demands with a curve in a portfolio each, and a demand without a
+    // curve in a portfolio with empty groups
+    let product = client.create_product(&()).await?;
+    for points in [
+        serde_json::json!([{ "rate": 0, "price": 10 }, { "rate": 10, "price": 0 }]),
+        serde_json::json!([{ "rate": 0, "price": 5 }, { "rate": 5, "price": 0 }]),
+    ] {
+        let demand = client.create_demand(&(), &curve(points)).await?;
+        client
+            .create_portfolio(
+                &(),
+                &Weights::from_iter([(demand.id, 1.0)]),
+                &Basis::from_iter([(product.id, 1.0)]),
+            )
+            .await?;
+    }
+    client.create_demand(&(), &DemandCurve::None).await?;
+    client
+        .create_portfolio(&(), &Weights::default(), &Basis::default())
+        .await?;
+
+    // Listing follows the pages, which by default omit the inactive bids
+    assert_eq!(client.list_demands::<()>().await?.len(), 2);
+    assert_eq!(client.list_portfolios::<()>(false).await?.len(), 2);
+
+    // A page at a time, in order of id
+    let query = DemandQuery {
+        has_curve: false,
+        ..Default::default()
+    };
+    let mut ids = Vec::new();
+    let mut next = Some(query);
+    while let Some(query) = next {
+        let page = client.query_demands::<()>(query).await?;
+        assert_eq!(page.results.len(), 1);
+        ids.extend(page.results.iter().map(|record| record.id));
+        next = page.more;
+    }
+    assert_eq!(ids.len(), 3);
+    assert!(ids.is_sorted());
+
+    // The curves may be left out of the listing
+    let page = client
+        .query_demands::<()>(DemandQuery {
+            include_curve: false,
+            ..Default::default()
+        })
+        .await?;
+    assert!(matches!(page.results[0].curve_data, DemandCurve::None));
+
+    let page = client
+        .query_portfolios::<()>(PortfolioQuery {
+            active_only: false,
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(page.results.len(), 1);
+    assert!(page.more.is_some());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_client_import() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+    let address = start(TestApp(db)).await?;
+
+    let bidder = Uuid::new_v4();
+    let client = TestClient::new(address.as_str())?.with_token(bidder.to_string());
+
+    let (product_id, demand_id, portfolio_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let document = ImportDocument {
+        products: vec![ImportProduct {
+            id: product_id,
+            app_data: (),
+            parent: None,
+        }],
+        demands: vec![ImportDemand {
+            id: demand_id,
+            bidder_id: bidder,
+            app_data: (),
+            curve_data: curve(
+                serde_json::json!([{ "rate": 0, "price": 10 }, { "rate": 10, "price": 0 }]),
+            ),
+        }],
+        portfolios: vec![ImportPortfolio {
+            id: portfolio_id,
+            bidder_id: bidder,
+            app_data: (),
+            demand: Weights::from_iter([(demand_id, 1.0)]),
+            basis: Basis::from_iter([(product_id, 1.0)]),
+        }],
+    };
+
+    // A dry run imports nothing
+    let record = client.import(&document, true).await?;
+    assert_eq!(record.portfolios, 1);
+    assert!(record.dry_run);
+    assert!(client.list_portfolios::<()>(false).await?.is_empty());
+
+    let record = client.import(&document, false).await?;
+    assert!(!record.dry_run);
+    let portfolio = client.get_portfolio::<()>(&portfolio_id, false).await?;
+    assert_eq!(portfolio.basis.get(&product_id), Some(&1.0));
+
+    // The same document cannot be imported twice
+    let err = client
+        .import(&document, false)
+        .await
+        .err()
+        .expect("import should be rejected");
+    assert_eq!(
+        err.status(),
+        Some(reqwest::StatusCode::UNPROCESSABLE_ENTITY)
+    );
+    assert_eq!(err.code(), Some("import_invalid"));
+
+    Ok(())
+}
@@ -6,6 +6,15 @@
 //! following the principles of the hexagonal architecture to separate domain entities
 //! from their persistence and processing implementations.
 
+mod actor;
+pub use actor::*;
+
+mod auction;
+pub use auction::*;
+
+mod batch;
+pub use batch::*;
+
 mod curve;
 pub use curve::*;
 
@@ -24,5 +33,32 @@ pub use map::*;
 mod datetime;
 pub use datetime::*;
 
+mod filter;
+pub use filter::*;
+
 mod group;
 pub use group::*;
+
+mod health;
+pub use health::*;
+
+mod import;
+pub use import::*;
+
+mod outbox;
+pub use outbox::*;
+
+mod retention;
+pub use retention::*;
+
+mod schedule;
+pub use schedule::*;
+
+mod settlement;
+pub use settlement::*;
+
+mod summary;
+pub use summary::*;
+
+mod tombstone;
+pub use tombstone::*;
@@ -0,0 +1,54 @@
+/// The party responsible for a change to a demand or portfolio.
+///
+/// Changes are attributed when they are made, allowing the history of a bid to
+/// distinguish the bidder's own actions from those taken on their behalf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "Actor")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Actor {
+    /// The bidder owning the demand or portfolio
+    #[default]
+    Bidder,
+    /// An operator of the market, acting on the bidder's behalf
+    Operator,
+    /// The system itself, e.g. as part of an automated process
+    System,
+}
+
+impl Actor {
+    /// The canonical, lowercase name of the actor.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Actor::Bidder => "bidder",
+            Actor::Operator => "operator",
+            Actor::System => "system",
+        }
+    }
+}
+
+impl std::fmt::Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Actor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bidder" => Ok(Actor::Bidder),
+            "operator" => Ok(Actor::Operator),
+            "system" => Ok(Actor::System),
+            other => Err(format!("unknown actor: {other}")),
+        }
+    }
+}
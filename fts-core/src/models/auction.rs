@@ -0,0 +1,162 @@
+use crate::{
+    models::{Basis, DemandCurve, Map, Weights},
+    ports::Repository,
+};
+
+/// A self-contained batch auction, comprising every demand curve and portfolio
+/// to solve.
+///
+/// Unlike a batch auction, whose bids are gathered from a repository, an
+/// auction is provided in full, which allows the solver to be exercised on
+/// arbitrary inputs. The document mirrors the format read by `ftauction solve`,
+/// though the identifiers must be of the repository's types.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "Auction",
+        bound = "
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize + Clone
+        ",
+        deserialize = "
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct Auction<T: Repository> {
+    /// The demand curves, keyed by their id
+    pub demand_curves: Map<T::DemandId, DemandCurve>,
+
+    /// The portfolios, keyed by their id
+    pub portfolios: Map<T::PortfolioId, AuctionPortfolio<T>>,
+}
+
+/// The portfolios of an auction, as a [`Solver`](crate::ports::Solver) takes them
+type SolverPortfolios<T> = Map<
+    <T as Repository>::PortfolioId,
+    (
+        Weights<<T as Repository>::DemandId>,
+        Basis<<T as Repository>::ProductId>,
+    ),
+>;
+
+impl<T: Repository> Auction<T> {
+    /// Assemble an auction from the demand curves and portfolios as a
+    /// [`Solver`](crate::ports::Solver) takes them.
+    pub fn from_parts(
+        demand_curves: Map<T::DemandId, DemandCurve>,
+        portfolios: SolverPortfolios<T>,
+    ) -> Self {
+        Self {
+            demand_curves,
+            portfolios: portfolios
+                .into_iter()
+                .map(|(portfolio_id, (demand, basis))| {
+                    (portfolio_id, AuctionPortfolio { demand, basis })
+                })
+                .collect(),
+        }
+    }
+
+    /// Split the auction into its demand curves and portfolios, as a
+    /// [`Solver`](crate::ports::Solver) takes them.
+    pub fn into_parts(self) -> (Map<T::DemandId, DemandCurve>, SolverPortfolios<T>) {
+        let portfolios = self
+            .portfolios
+            .into_iter()
+            .map(|(portfolio_id, AuctionPortfolio { demand, basis })| {
+                (portfolio_id, (demand, basis))
+            })
+            .collect();
+        (self.demand_curves, portfolios)
+    }
+}
+
+/// A portfolio within a self-contained [`Auction`].
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "AuctionPortfolio",
+        bound = "
+            T::DemandId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DemandId: serde::Serialize + Clone,
+            T::ProductId: serde::Serialize + Clone
+        ",
+        deserialize = "
+            T::DemandId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct AuctionPortfolio<T: Repository> {
+    /// The weights of the demand curves associated to the portfolio
+    pub demand: Weights<T::DemandId>,
+
+    /// The weights of the products the portfolio trades
+    pub basis: Basis<T::ProductId>,
+}
+
+/// The outcomes of a self-contained [`Auction`].
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "AuctionOutcome",
+        bound = "
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            PortfolioOutcome: schemars::JsonSchema,
+            ProductOutcome: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize,
+            PortfolioOutcome: serde::Serialize,
+            ProductOutcome: serde::Serialize
+        ",
+        deserialize = "
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            PortfolioOutcome: serde::Deserialize<'de>,
+            ProductOutcome: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct AuctionOutcome<T: Repository, PortfolioOutcome, ProductOutcome> {
+    /// The outcome of each portfolio in the auction
+    pub portfolios: Map<T::PortfolioId, PortfolioOutcome>,
+
+    /// The outcome of each product in the auction
+    pub products: Map<T::ProductId, ProductOutcome>,
+}
@@ -0,0 +1,240 @@
+use crate::{models::Map, ports::Repository};
+use std::hash::Hash;
+
+/// The hypothetical outcomes of a batch auction which was not executed.
+///
+/// A preview assembles and solves the active demands and portfolios exactly
+/// as a batch auction would, but nothing is recorded. This allows operators
+/// to sanity-check the prospective prices and trades before a scheduled batch.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "BatchPreview",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            PortfolioOutcome: schemars::JsonSchema,
+            ProductOutcome: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize,
+            PortfolioOutcome: serde::Serialize,
+            ProductOutcome: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            PortfolioOutcome: serde::Deserialize<'de>,
+            ProductOutcome: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct BatchPreview<T: Repository, PortfolioOutcome, ProductOutcome> {
+    /// The time as of which the demands and portfolios were gathered
+    pub as_of: T::DateTime,
+
+    /// The time, if any, at which the first of the gathered bids expires
+    pub expires: Option<T::DateTime>,
+
+    /// The outcome of each portfolio in the batch
+    pub portfolios: Map<T::PortfolioId, PortfolioOutcome>,
+
+    /// The outcome of each product in the batch
+    pub products: Map<T::ProductId, ProductOutcome>,
+}
+
+/// A description of a batch auction which was executed.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "BatchMetadata",
+        bound = "
+            T::DateTime: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct BatchMetadata<T: Repository> {
+    /// The time as of which the batch was solved
+    pub as_of: T::DateTime,
+
+    /// The number of portfolios with an outcome in the batch
+    pub portfolios: u32,
+
+    /// The number of products with an outcome in the batch
+    pub products: u32,
+}
+
+/// A description of how a solver solved (or failed to solve) a batch auction.
+///
+/// The contents are particular to each solver, but should be sufficient to
+/// reproduce the solve and to judge the quality of its solution.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolverTelemetry {
+    /// The name of the solver backend (e.g. `clarabel`)
+    pub backend: String,
+
+    /// The settings with which the solver was configured, by name
+    pub settings: Map<String, String>,
+
+    /// The status the solver reported on completion
+    pub status: String,
+
+    /// Statistics of the solve, such as its iterations and time, by name
+    pub stats: Map<String, f64>,
+}
+
+/// A batch auction which was run, whether or not it was solved.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchRun {
+    /// How the batch was solved
+    pub solver: SolverTelemetry,
+
+    /// The error of the solver, if it failed to solve the batch
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub error: Option<String>,
+}
+
+/// The stage at which a batch auction failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BatchFailure")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum BatchFailure {
+    /// The repository could not gather the inputs or record the outcomes
+    Database,
+    /// The solver did not produce a solution
+    Solver,
+}
+
+impl BatchFailure {
+    /// The canonical, lowercase name of the failure.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchFailure::Database => "database",
+            BatchFailure::Solver => "solver",
+        }
+    }
+}
+
+impl std::fmt::Display for BatchFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for BatchFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "database" => Ok(BatchFailure::Database),
+            "solver" => Ok(BatchFailure::Solver),
+            other => Err(format!("unknown batch failure: {other}")),
+        }
+    }
+}
+
+/// An attempt to run a batch auction which failed, leaving the outcomes of
+/// the previous batch in effect.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchAttempt {
+    /// The stage at which the batch failed
+    pub category: BatchFailure,
+
+    /// The error which caused the failure
+    pub message: String,
+}
+
+/// The reason a batch auction did not run to completion, other than a failure
+/// of the repository itself.
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError<E> {
+    /// Another batch auction is executing against the same market; nothing
+    /// was gathered, solved or recorded
+    #[error("a batch auction is already in progress")]
+    InProgress,
+    /// The solver did not produce a solution
+    #[error(transparent)]
+    Solver(E),
+}
+
+/// The outcome of a batch auction for a single bidder, aggregated across all
+/// of their portfolios.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BidderOutcome")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BidderOutcome<ProductId: Eq + Hash> {
+    /// The net trade in each product traded by the bidder's portfolios
+    pub trades: Map<ProductId, BidderTrade>,
+
+    /// The net rate of payment, i.e. the sum of the payments of every trade
+    /// (negative if the bidder is owed)
+    pub payment: f64,
+}
+
+impl<ProductId: Eq + Hash> Default for BidderOutcome<ProductId> {
+    fn default() -> Self {
+        Self {
+            trades: Map::default(),
+            payment: 0.0,
+        }
+    }
+}
+
+/// The net trade of a bidder in a single product, as determined by a batch
+/// auction.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BidderTrade {
+    /// The net rate of trade (negative for sell, positive for buy)
+    pub rate: f64,
+
+    /// The rate of payment for the trade at the product's clearing price
+    /// (negative if the bidder is owed)
+    pub payment: f64,
+}
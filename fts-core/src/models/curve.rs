@@ -5,9 +5,11 @@
 //! - [`ConstantCurve`]: Fixed price curves for simple trading strategies
 
 mod constant;
+mod diagnostic;
 mod pwl;
 
 pub use constant::*;
+pub use diagnostic::*;
 pub use pwl::*;
 
 // `schemars` does not support serde's try_from/into (https://github.com/GREsau/schemars/issues/210).
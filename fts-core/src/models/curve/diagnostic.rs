@@ -0,0 +1,295 @@
+use crate::models::{ConstantCurveDto, DemandCurveDto, Point, PwlCurveDto};
+use std::cmp::Ordering;
+
+/// The severity of a curve diagnostic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Severity {
+    /// The curve would be rejected
+    Error,
+    /// The curve would be accepted, but likely does not express what was intended
+    Warning,
+}
+
+/// A single finding from validating a demand curve.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CurveDiagnostic {
+    /// Whether the finding prevents the curve from being accepted
+    pub severity: Severity,
+    /// A stable, machine-readable identifier of the finding, e.g. `non_monotone`
+    pub code: String,
+    /// A human-readable description of the finding
+    pub message: String,
+    /// The index of the offending point of a piecewise-linear curve, if any
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub point: Option<usize>,
+}
+
+impl CurveDiagnostic {
+    /// An error, which prevents the curve from being accepted
+    pub fn error(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: code.into(),
+            message: message.into(),
+            point: None,
+        }
+    }
+
+    /// A warning, which does not prevent the curve from being accepted
+    pub fn warning(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: code.into(),
+            message: message.into(),
+            point: None,
+        }
+    }
+
+    /// Attribute the finding to the point at `index`
+    pub fn at(self, index: usize) -> Self {
+        Self {
+            point: Some(index),
+            ..self
+        }
+    }
+}
+
+/// The outcome of validating a demand curve without submitting it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CurveValidation {
+    /// Whether the curve would be accepted, i.e. there are no errors
+    pub valid: bool,
+    /// Every finding, in the order of the points they concern
+    pub diagnostics: Vec<CurveDiagnostic>,
+}
+
+impl FromIterator<CurveDiagnostic> for CurveValidation {
+    fn from_iter<I: IntoIterator<Item = CurveDiagnostic>>(iter: I) -> Self {
+        let diagnostics: Vec<CurveDiagnostic> = iter.into_iter().collect();
+        Self {
+            valid: diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.severity != Severity::Error),
+            diagnostics,
+        }
+    }
+}
+
+impl DemandCurveDto {
+    /// Diagnose every problem with the curve, rather than only the first.
+    ///
+    /// This performs the same checks as the conversion into a [`DemandCurve`](crate::models::DemandCurve),
+    /// additionally warning of points the solver would discard when
+    /// disaggregating the curve into its simple segments.
+    pub fn diagnose(&self) -> Vec<CurveDiagnostic> {
+        match self {
+            DemandCurveDto::None => Vec::new(),
+            DemandCurveDto::Pwl(curve) => curve.diagnose(),
+            DemandCurveDto::Constant(curve) => curve.diagnose(),
+        }
+    }
+}
+
+impl PwlCurveDto {
+    /// Diagnose every problem with the points of the curve.
+    pub fn diagnose(&self) -> Vec<CurveDiagnostic> {
+        let points = &self.0;
+        if points.is_empty() {
+            return vec![CurveDiagnostic::error("empty", "No points provided")];
+        }
+
+        let mut diagnostics = Vec::new();
+        for (index, point) in points.iter().enumerate() {
+            if point.rate.is_nan() || point.price.is_nan() {
+                diagnostics.push(CurveDiagnostic::error("nan", "NaN value encountered").at(index));
+            } else if point.rate.is_infinite() || point.price.is_infinite() {
+                diagnostics.push(
+                    CurveDiagnostic::error("infinite", "Rates and prices cannot be infinite")
+                        .at(index),
+                );
+            }
+        }
+        // Monotonicity and collinearity are meaningless without numbers
+        if !diagnostics.is_empty() {
+            return diagnostics;
+        }
+
+        for (index, pair) in points.windows(2).enumerate() {
+            if pair[0].partial_cmp(&pair[1]).is_none_or(Ordering::is_gt) {
+                diagnostics.push(
+                    CurveDiagnostic::error(
+                        "non_monotone",
+                        "Points are not ordered by ascending rate, descending price",
+                    )
+                    .at(index + 1),
+                );
+            } else if pair[0] == pair[1] {
+                diagnostics.push(
+                    CurveDiagnostic::warning("duplicate_point", "Point repeats its predecessor")
+                        .at(index + 1),
+                );
+            }
+        }
+
+        let (min, max) = (points[0].rate, points[points.len() - 1].rate);
+        if !(min <= 0.0 && 0.0 <= max) {
+            diagnostics.push(CurveDiagnostic::error(
+                "zero_trade",
+                "Domain excludes rate=0",
+            ));
+        } else if min == max {
+            diagnostics.push(CurveDiagnostic::warning(
+                "no_trade",
+                "Domain only includes rate=0, so the curve can never trade",
+            ));
+        }
+
+        // The solver merges interior points lying on the line through their
+        // neighbours, so these do not change the curve
+        if diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Severity::Error)
+        {
+            for (index, triple) in points.windows(3).enumerate() {
+                if triple[0] != triple[1]
+                    && triple[1] != triple[2]
+                    && is_collinear(&triple[1], &triple[0], &triple[2])
+                {
+                    diagnostics.push(
+                        CurveDiagnostic::warning(
+                            "collinear_point",
+                            "Point lies on the line through its neighbours and is redundant",
+                        )
+                        .at(index + 1),
+                    );
+                }
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.point);
+        diagnostics
+    }
+}
+
+impl ConstantCurveDto {
+    /// Diagnose every problem with the curve.
+    pub fn diagnose(&self) -> Vec<CurveDiagnostic> {
+        let min_rate = self.min_rate.unwrap_or(f64::NEG_INFINITY);
+        let max_rate = self.max_rate.unwrap_or(f64::INFINITY);
+
+        let mut diagnostics = Vec::new();
+        if min_rate.is_nan() || max_rate.is_nan() || self.price.is_nan() {
+            diagnostics.push(CurveDiagnostic::error("nan", "NaN value encountered"));
+            return diagnostics;
+        }
+        if self.price.is_infinite() {
+            diagnostics.push(CurveDiagnostic::error(
+                "infinite_price",
+                "Price cannot be infinite",
+            ));
+        }
+        if !(min_rate <= 0.0 && 0.0 <= max_rate) {
+            diagnostics.push(CurveDiagnostic::error(
+                "zero_trade",
+                "Domain excludes rate=0",
+            ));
+        } else if min_rate == max_rate {
+            diagnostics.push(CurveDiagnostic::warning(
+                "no_trade",
+                "Domain only includes rate=0, so the curve can never trade",
+            ));
+        }
+        diagnostics
+    }
+}
+
+/// Whether `pt` lies on the line through `lhs` and `rhs`
+fn is_collinear(pt: &Point, lhs: &Point, rhs: &Point) -> bool {
+    (rhs.rate - lhs.rate) * (pt.price - lhs.price) == (pt.rate - lhs.rate) * (rhs.price - lhs.price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pwl(points: &[(f64, f64)]) -> DemandCurveDto {
+        DemandCurveDto::Pwl(PwlCurveDto(
+            points
+                .iter()
+                .map(|&(rate, price)| Point { rate, price })
+                .collect(),
+        ))
+    }
+
+    fn codes(diagnostics: &[CurveDiagnostic]) -> Vec<(&str, Option<usize>)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code.as_str(), diagnostic.point))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_curve() {
+        let diagnostics = pwl(&[(0.0, 10.0), (5.0, 5.0), (10.0, 4.0)]).diagnose();
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let diagnostics = pwl(&[(1.0, 10.0), (5.0, 15.0), (3.0, 4.0)]).diagnose();
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("zero_trade", None),
+                ("non_monotone", Some(1)),
+                ("non_monotone", Some(2)),
+            ]
+        );
+        assert!(!CurveValidation::from_iter(diagnostics).valid);
+    }
+
+    #[test]
+    fn test_reports_nonfinite_points() {
+        let diagnostics = pwl(&[(0.0, f64::NAN), (5.0, 5.0), (f64::INFINITY, 4.0)]).diagnose();
+        assert_eq!(
+            codes(&diagnostics),
+            vec![("nan", Some(0)), ("infinite", Some(2))]
+        );
+    }
+
+    #[test]
+    fn test_warnings_are_valid() {
+        let diagnostics = pwl(&[(0.0, 10.0), (0.0, 10.0), (5.0, 5.0), (10.0, 0.0)]).diagnose();
+        assert_eq!(
+            codes(&diagnostics),
+            vec![("duplicate_point", Some(1)), ("collinear_point", Some(2))]
+        );
+        assert!(CurveValidation::from_iter(diagnostics).valid);
+    }
+
+    #[test]
+    fn test_constant_curve() {
+        let curve = DemandCurveDto::Constant(ConstantCurveDto {
+            min_rate: Some(1.0),
+            max_rate: None,
+            price: f64::INFINITY,
+        });
+        assert_eq!(
+            codes(&curve.diagnose()),
+            vec![("infinite_price", None), ("zero_trade", None)]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::models::Actor;
+
 /// A timestamped record of some value.
 ///
 /// The interval for which the entity has this value is provided alongside
@@ -15,6 +17,13 @@ pub struct ValueRecord<DateTime, Value> {
     pub valid_until: Option<DateTime>,
     /// The component value
     pub value: Value,
+    /// The party responsible for the change, for records in the history of a
+    /// demand or portfolio (and None otherwise)
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub actor: Option<Actor>,
 }
 
 /// A query type for dealing with datetime ranges
@@ -1,5 +1,5 @@
 use crate::{
-    models::{DemandCurve, Sum},
+    models::{AppDataFilter, DemandCurve, Sum},
     ports::Repository,
 };
 
@@ -25,14 +25,23 @@ use crate::{
 )]
 #[cfg_attr(
     feature = "serde",
-    derive(serde::Serialize),
-    serde(bound(serialize = "
-        T::DateTime: serde::Serialize,
-        T::BidderId: serde::Serialize,
-        T::DemandId: serde::Serialize + Clone,
-        T::PortfolioId: serde::Serialize + Clone,
-        AppData: serde::Serialize
-    "))
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
 )]
 pub struct DemandRecord<T: Repository, AppData> {
     /// Unique identifier for this demand instance.
@@ -67,3 +76,158 @@ pub struct DemandRecord<T: Repository, AppData> {
     /// the demand is not yet associated with any portfolios.
     pub portfolios: Sum<T::PortfolioId>,
 }
+
+/// A query for the demands of some bidders, a page at a time.
+///
+/// Results are ordered by demand id, and `after` is the exclusive lower
+/// bound on the ids of the next page. By default, only the demands with a
+/// curve are included, along with their curve data.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "DemandQuery")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DemandQuery<DemandId, DateTime> {
+    /// Only include the demands with a curve (true if omitted)
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub has_curve: bool,
+
+    /// Include the current curve data of each demand (true if omitted);
+    /// otherwise, the curve data of each record is left empty
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub include_curve: bool,
+
+    /// Only include the demands changed at or after this time
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub touched_since: Option<DateTime>,
+
+    /// The lower bound (exclusive) for the demand ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<DemandId>,
+}
+
+impl<DemandId, DateTime> Default for DemandQuery<DemandId, DateTime> {
+    fn default() -> Self {
+        Self {
+            has_curve: true,
+            include_curve: true,
+            touched_since: None,
+            after: None,
+        }
+    }
+}
+
+/// The default of the flags which apply unless disabled
+#[cfg(feature = "serde")]
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+/// A page of the response to a demand query.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "DemandQueryResponse",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct DemandQueryResponse<T: Repository, AppData> {
+    /// The demands matching the query
+    pub results: Vec<DemandRecord<T, AppData>>,
+
+    /// The query for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<DemandQuery<T::DemandId, T::DateTime>>,
+}
+
+/// A search for the demands whose application data satisfies some filters.
+///
+/// Results are ordered by demand id, and `after` is the exclusive lower
+/// bound on the ids of the next page.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "DemandSearch")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DemandSearch<DemandId> {
+    /// The filters every demand must satisfy
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filters: Vec<AppDataFilter>,
+
+    /// The lower bound (exclusive) for the demand ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<DemandId>,
+}
+
+/// The paginated response to a demand search.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "DemandSearchResponse",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct DemandSearchResponse<T: Repository, AppData> {
+    /// The demands matching the query
+    pub results: Vec<DemandRecord<T, AppData>>,
+
+    /// The search for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<DemandSearch<T::DemandId>>,
+}
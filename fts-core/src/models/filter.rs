@@ -0,0 +1,71 @@
+/// A condition on a single field of a record's application data.
+///
+/// The field is addressed by a JSON path, e.g. `$.kind` or `$.window.start`,
+/// and compared against a scalar value. Several filters are combined by
+/// conjunction, so that a delivery window `[start, end)` overlapping the range
+/// `[from, until)` can be expressed as `$.start < until` and `$.end > from`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppDataFilter {
+    /// A JSON path into the application data, beginning with `$`
+    pub path: String,
+    /// How the field is compared against the value
+    pub op: Comparison,
+    /// The value to compare the field against
+    pub value: FilterValue,
+}
+
+impl AppDataFilter {
+    /// Whether the path is plausibly a JSON path, i.e. begins with `$`.
+    ///
+    /// Implementations may still reject a path that passes this check.
+    pub fn has_valid_path(&self) -> bool {
+        self.path.starts_with('$')
+    }
+}
+
+/// A comparison between a field of the application data and a value.
+///
+/// Fields which are absent never satisfy a comparison.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Comparison {
+    /// The field equals the value
+    Eq,
+    /// The field does not equal the value
+    Ne,
+    /// The field is less than the value
+    Lt,
+    /// The field is at most the value
+    Le,
+    /// The field is greater than the value
+    Gt,
+    /// The field is at least the value
+    Ge,
+}
+
+/// A scalar value to compare a field of the application data against.
+///
+/// Strings compare lexicographically, so timestamps should be stored in a
+/// consistent, sortable format (such as RFC 3339 in UTC) to be compared.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(untagged)
+)]
+pub enum FilterValue {
+    /// A boolean
+    Bool(bool),
+    /// A number
+    Number(f64),
+    /// A string
+    String(String),
+}
@@ -0,0 +1,80 @@
+/// The status of the schema migrations of a repository's storage.
+///
+/// A repository whose storage has pending migrations was opened against an
+/// older schema than the one it expects, which is typically a deployment error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "MigrationStatus")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MigrationStatus {
+    /// The number of migrations applied to the storage
+    pub applied: u32,
+
+    /// The number of migrations known to the repository but not yet applied
+    pub pending: u32,
+
+    /// The version of the schema, i.e. of the latest migration applied to the
+    /// storage, or None if no migration was ever applied
+    pub version: Option<i64>,
+
+    /// Every migration known to the repository or applied to the storage, in
+    /// order of version
+    pub migrations: Vec<SchemaMigration>,
+}
+
+/// A schema migration, and whether it was applied to the storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "SchemaMigration")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SchemaMigration {
+    /// The version of the migration
+    pub version: i64,
+
+    /// A description of the migration
+    pub description: String,
+
+    /// Whether the migration was applied to the storage
+    pub applied: bool,
+}
+
+/// The result of checking the tables a repository derives from others.
+///
+/// The product tree, which holds the ratio of each product to every product
+/// composing it as of any time, is derived from the products' parents, and
+/// the current demand and product groups of each portfolio from the portfolio
+/// itself. Both are maintained incrementally as the records they derive from
+/// change, so a defect in that maintenance (such as a misfired trigger) leaves
+/// them divergent, which this record reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ConsistencyRecord")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsistencyRecord<ProductId, PortfolioId> {
+    /// The products whose paths in the product tree diverge from those
+    /// derived from the products' parents, ordered by id
+    pub products: Vec<ProductId>,
+
+    /// The portfolios whose current groups diverge from the portfolio,
+    /// ordered by id
+    pub portfolios: Vec<PortfolioId>,
+
+    /// Whether the divergent records were derived anew
+    pub repaired: bool,
+}
+
+impl<ProductId, PortfolioId> ConsistencyRecord<ProductId, PortfolioId> {
+    /// Whether every derived record agrees with the records it derives from
+    pub fn is_consistent(&self) -> bool {
+        self.products.is_empty() && self.portfolios.is_empty()
+    }
+}
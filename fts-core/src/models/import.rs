@@ -0,0 +1,449 @@
+use crate::{
+    models::{Basis, DemandCurve, Weights},
+    ports::Repository,
+};
+use std::{collections::HashSet, fmt::Display};
+
+/// A document of products, demands, and portfolios to import at once.
+///
+/// Unlike the records created through the individual operations, every
+/// entity in the document carries its own id (and bidder), allowing an
+/// existing market to be migrated with its identifiers intact. The document
+/// is imported in its entirety or not at all.
+///
+/// Entities may reference one another: a product may name a parent defined
+/// earlier in the document, and a portfolio may reference any demand or
+/// product in the document. References to entities which already exist in
+/// the repository are also permitted.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ImportDocument",
+        bound = "
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            DemandData: schemars::JsonSchema,
+            PortfolioData: schemars::JsonSchema,
+            ProductData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize + Clone,
+            DemandData: serde::Serialize,
+            PortfolioData: serde::Serialize,
+            ProductData: serde::Serialize
+        ",
+        deserialize = "
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            DemandData: serde::Deserialize<'de>,
+            PortfolioData: serde::Deserialize<'de>,
+            ProductData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ImportDocument<T: Repository, DemandData, PortfolioData, ProductData> {
+    /// The products to create, with parents preceding their children
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub products: Vec<ImportProduct<T, ProductData>>,
+
+    /// The demands to create
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub demands: Vec<ImportDemand<T, DemandData>>,
+
+    /// The portfolios to create
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub portfolios: Vec<ImportPortfolio<T, PortfolioData>>,
+}
+
+/// A product within an [`ImportDocument`].
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ImportProduct",
+        bound = "
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::ProductId: serde::Serialize,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ImportProduct<T: Repository, AppData> {
+    /// The id of the product
+    pub id: T::ProductId,
+
+    /// Application-specific data attached to the product
+    pub app_data: AppData,
+
+    /// The parent the product partitions, along with its ratio, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub parent: Option<(T::ProductId, f64)>,
+}
+
+/// A demand within an [`ImportDocument`].
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ImportDemand",
+        bound = "
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ImportDemand<T: Repository, AppData> {
+    /// The id of the demand
+    pub id: T::DemandId,
+
+    /// The bidder owning the demand
+    pub bidder_id: T::BidderId,
+
+    /// Application-specific data attached to the demand
+    pub app_data: AppData,
+
+    /// The demand curve
+    pub curve_data: DemandCurve,
+}
+
+/// A portfolio within an [`ImportDocument`].
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ImportPortfolio",
+        bound = "
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize + Clone,
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ImportPortfolio<T: Repository, AppData> {
+    /// The id of the portfolio
+    pub id: T::PortfolioId,
+
+    /// The bidder owning the portfolio
+    pub bidder_id: T::BidderId,
+
+    /// Application-specific data attached to the portfolio
+    pub app_data: AppData,
+
+    /// The weights of the demand curves associated to the portfolio
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub demand: Weights<T::DemandId>,
+
+    /// The weights of the products the portfolio trades
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub basis: Basis<T::ProductId>,
+}
+
+/// A reason an [`ImportDocument`] cannot be imported.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ImportIssue",
+        bound = "
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        tag = "code",
+        rename_all = "snake_case",
+        bound(
+            serialize = "
+                T::DemandId: serde::Serialize,
+                T::PortfolioId: serde::Serialize,
+                T::ProductId: serde::Serialize
+            ",
+            deserialize = "
+                T::DemandId: serde::Deserialize<'de>,
+                T::PortfolioId: serde::Deserialize<'de>,
+                T::ProductId: serde::Deserialize<'de>
+            "
+        )
+    )
+)]
+pub enum ImportIssue<T: Repository> {
+    /// The product is defined more than once, or already exists
+    DuplicateProduct {
+        /// The id of the product
+        product_id: T::ProductId,
+    },
+    /// The demand is defined more than once, or already exists
+    DuplicateDemand {
+        /// The id of the demand
+        demand_id: T::DemandId,
+    },
+    /// The portfolio is defined more than once, or already exists
+    DuplicatePortfolio {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+    },
+    /// The parent of a product neither precedes it nor exists
+    UnknownParent {
+        /// The id of the product
+        product_id: T::ProductId,
+        /// The id of its parent
+        parent_id: T::ProductId,
+    },
+    /// A portfolio references a demand which is neither imported nor exists
+    UnknownDemand {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+        /// The id of the demand
+        demand_id: T::DemandId,
+    },
+    /// A portfolio references a product which is neither imported nor exists
+    UnknownProduct {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+        /// The id of the product
+        product_id: T::ProductId,
+    },
+}
+
+impl<T: Repository> Display for ImportIssue<T>
+where
+    T::DemandId: Display,
+    T::PortfolioId: Display,
+    T::ProductId: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateProduct { product_id } => {
+                write!(f, "product {product_id} is duplicated or already exists")
+            }
+            Self::DuplicateDemand { demand_id } => {
+                write!(f, "demand {demand_id} is duplicated or already exists")
+            }
+            Self::DuplicatePortfolio { portfolio_id } => {
+                write!(
+                    f,
+                    "portfolio {portfolio_id} is duplicated or already exists"
+                )
+            }
+            Self::UnknownParent {
+                product_id,
+                parent_id,
+            } => write!(f, "product {product_id} has unknown parent {parent_id}"),
+            Self::UnknownDemand {
+                portfolio_id,
+                demand_id,
+            } => write!(
+                f,
+                "portfolio {portfolio_id} references unknown demand {demand_id}"
+            ),
+            Self::UnknownProduct {
+                portfolio_id,
+                product_id,
+            } => write!(
+                f,
+                "portfolio {portfolio_id} references unknown product {product_id}"
+            ),
+        }
+    }
+}
+
+/// The result of importing an [`ImportDocument`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ImportRecord")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportRecord {
+    /// The number of imported products
+    pub products: u64,
+
+    /// The number of imported demands
+    pub demands: u64,
+
+    /// The number of imported portfolios
+    pub portfolios: u64,
+
+    /// Whether the import was rolled back rather than committed
+    pub dry_run: bool,
+}
+
+impl<T: Repository, DemandData, PortfolioData, ProductData>
+    ImportDocument<T, DemandData, PortfolioData, ProductData>
+where
+    T::DemandId: Clone,
+    T::PortfolioId: Clone,
+    T::ProductId: Clone,
+{
+    /// Every product id the document defines or references.
+    pub fn product_ids(&self) -> impl Iterator<Item = &T::ProductId> {
+        let defined = self.products.iter().flat_map(|product| {
+            std::iter::once(&product.id).chain(product.parent.as_ref().map(|(id, _)| id))
+        });
+        let referenced = self
+            .portfolios
+            .iter()
+            .flat_map(|portfolio| portfolio.basis.keys());
+        defined.chain(referenced)
+    }
+
+    /// Every demand id the document defines or references.
+    pub fn demand_ids(&self) -> impl Iterator<Item = &T::DemandId> {
+        let defined = self.demands.iter().map(|demand| &demand.id);
+        let referenced = self
+            .portfolios
+            .iter()
+            .flat_map(|portfolio| portfolio.demand.keys());
+        defined.chain(referenced)
+    }
+
+    /// Every portfolio id the document defines.
+    pub fn portfolio_ids(&self) -> impl Iterator<Item = &T::PortfolioId> {
+        self.portfolios.iter().map(|portfolio| &portfolio.id)
+    }
+
+    /// Check the references of the document against itself and the ids which
+    /// already exist in the repository.
+    ///
+    /// The existing ids need only include those returned by `product_ids`,
+    /// `demand_ids`, and `portfolio_ids`.
+    ///
+    /// # Returns
+    ///
+    /// Every issue preventing the import, which is empty if there are none.
+    pub fn validate(
+        &self,
+        products: &HashSet<T::ProductId>,
+        demands: &HashSet<T::DemandId>,
+        portfolios: &HashSet<T::PortfolioId>,
+    ) -> Vec<ImportIssue<T>> {
+        let mut issues = Vec::new();
+
+        // A parent must precede its children, so we check as we go
+        let mut imported_products = HashSet::new();
+        for product in self.products.iter() {
+            if let Some((parent_id, _)) = product.parent.as_ref()
+                && !imported_products.contains(parent_id)
+                && !products.contains(parent_id)
+            {
+                issues.push(ImportIssue::UnknownParent {
+                    product_id: product.id.clone(),
+                    parent_id: parent_id.clone(),
+                });
+            }
+            if products.contains(&product.id) || !imported_products.insert(&product.id) {
+                issues.push(ImportIssue::DuplicateProduct {
+                    product_id: product.id.clone(),
+                });
+            }
+        }
+
+        let mut imported_demands = HashSet::new();
+        for demand in self.demands.iter() {
+            if demands.contains(&demand.id) || !imported_demands.insert(&demand.id) {
+                issues.push(ImportIssue::DuplicateDemand {
+                    demand_id: demand.id.clone(),
+                });
+            }
+        }
+
+        let mut imported_portfolios = HashSet::new();
+        for portfolio in self.portfolios.iter() {
+            if portfolios.contains(&portfolio.id) || !imported_portfolios.insert(&portfolio.id) {
+                issues.push(ImportIssue::DuplicatePortfolio {
+                    portfolio_id: portfolio.id.clone(),
+                });
+            }
+            for demand_id in portfolio.demand.keys() {
+                if !imported_demands.contains(demand_id) && !demands.contains(demand_id) {
+                    issues.push(ImportIssue::UnknownDemand {
+                        portfolio_id: portfolio.id.clone(),
+                        demand_id: demand_id.clone(),
+                    });
+                }
+            }
+            for product_id in portfolio.basis.keys() {
+                if !imported_products.contains(product_id) && !products.contains(product_id) {
+                    issues.push(ImportIssue::UnknownProduct {
+                        portfolio_id: portfolio.id.clone(),
+                        product_id: product_id.clone(),
+                    });
+                }
+            }
+        }
+
+        issues
+    }
+}
@@ -0,0 +1,161 @@
+use crate::ports::Repository;
+
+/// A change to the market, as recorded in the outbox of a repository.
+///
+/// An event is recorded in the same transaction as the change it describes,
+/// so that it is neither lost nor published for a change which was rolled
+/// back. Events only identify what changed; the current state of the entity
+/// is then available from the repository.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "MarketEvent",
+        bound = "
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        tag = "type",
+        rename_all = "snake_case",
+        bound(
+            serialize = "
+                T::BidderId: serde::Serialize,
+                T::DemandId: serde::Serialize,
+                T::PortfolioId: serde::Serialize,
+                T::ProductId: serde::Serialize
+            ",
+            deserialize = "
+                T::BidderId: serde::Deserialize<'de>,
+                T::DemandId: serde::Deserialize<'de>,
+                T::PortfolioId: serde::Deserialize<'de>,
+                T::ProductId: serde::Deserialize<'de>
+            "
+        )
+    )
+)]
+pub enum MarketEvent<T: Repository> {
+    /// A demand was created
+    DemandCreated {
+        /// The id of the demand
+        demand_id: T::DemandId,
+        /// The bidder who owns the demand
+        bidder_id: T::BidderId,
+    },
+
+    /// The curve of a demand was updated
+    DemandUpdated {
+        /// The id of the demand
+        demand_id: T::DemandId,
+        /// The bidder who owns the demand
+        bidder_id: T::BidderId,
+    },
+
+    /// A demand was purged
+    DemandPurged {
+        /// The id of the demand
+        demand_id: T::DemandId,
+        /// The bidder who owned the demand
+        bidder_id: T::BidderId,
+    },
+
+    /// A portfolio was created
+    PortfolioCreated {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+        /// The bidder who owns the portfolio
+        bidder_id: T::BidderId,
+    },
+
+    /// The demand or product groups of a portfolio were updated
+    PortfolioUpdated {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+        /// The bidder who owns the portfolio
+        bidder_id: T::BidderId,
+    },
+
+    /// A portfolio was purged
+    PortfolioPurged {
+        /// The id of the portfolio
+        portfolio_id: T::PortfolioId,
+        /// The bidder who owned the portfolio
+        bidder_id: T::BidderId,
+    },
+
+    /// A product was created, either directly or by partitioning its parent
+    ProductCreated {
+        /// The id of the product
+        product_id: T::ProductId,
+    },
+
+    /// The ratio of a product to its parent was updated, or the product was
+    /// moved under a different parent
+    ProductMoved {
+        /// The id of the product
+        product_id: T::ProductId,
+    },
+
+    /// A batch auction was executed, superseding the previous outcomes
+    BatchExecuted,
+
+    /// The trade activity of the market was settled
+    Settled,
+
+    /// A settlement was reversed or corrected
+    SettlementRevised,
+}
+
+/// An event of the outbox, along with its position and time.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "OutboxEvent",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::DemandId: serde::Serialize,
+            T::PortfolioId: serde::Serialize,
+            T::ProductId: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct OutboxEvent<T: Repository> {
+    /// The position of the event in the outbox, which increases with every event
+    pub sequence: u64,
+
+    /// The time at which the change took effect
+    pub as_of: T::DateTime,
+
+    /// What changed
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub event: MarketEvent<T>,
+}
@@ -1,5 +1,5 @@
 use crate::{
-    models::{Basis, Weights},
+    models::{AppDataFilter, Basis, Weights},
     ports::Repository,
 };
 
@@ -33,15 +33,25 @@ use crate::{
 )]
 #[cfg_attr(
     feature = "serde",
-    derive(serde::Serialize),
-    serde(bound(serialize = "
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
             T::DateTime: serde::Serialize,
             T::BidderId: serde::Serialize,
             T::PortfolioId: serde::Serialize + Clone,
             T::DemandId: serde::Serialize + Clone,
             T::ProductId: serde::Serialize + Clone,
             AppData: serde::Serialize
-        "))
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
 )]
 pub struct PortfolioRecord<T: Repository, AppData> {
     /// Unique identifier for this portfolio instance.
@@ -69,3 +79,157 @@ pub struct PortfolioRecord<T: Repository, AppData> {
     /// Map of products this portfolio can trade and their weights.
     pub basis: Basis<T::ProductId>,
 }
+
+/// A query for the portfolios of some bidders, a page at a time.
+///
+/// Results are ordered by portfolio id, and `after` is the exclusive lower
+/// bound on the ids of the next page. By default, only the portfolios with a
+/// non-empty demand or product group are included.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "PortfolioQuery")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortfolioQuery<PortfolioId, DateTime> {
+    /// Only include the portfolios with a non-empty group (true if omitted)
+    #[cfg_attr(feature = "serde", serde(default = "super::demand::default_true"))]
+    pub active_only: bool,
+
+    /// Only include the portfolios whose demand group has a demand with a curve
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub has_curve: bool,
+
+    /// Only include the portfolios changed at or after this time
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub touched_since: Option<DateTime>,
+
+    /// The lower bound (exclusive) for the portfolio ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<PortfolioId>,
+}
+
+impl<PortfolioId, DateTime> Default for PortfolioQuery<PortfolioId, DateTime> {
+    fn default() -> Self {
+        Self {
+            active_only: true,
+            has_curve: false,
+            touched_since: None,
+            after: None,
+        }
+    }
+}
+
+/// A page of the response to a portfolio query.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "PortfolioQueryResponse",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::PortfolioId: serde::Serialize + Clone,
+            T::DemandId: serde::Serialize + Clone,
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct PortfolioQueryResponse<T: Repository, AppData> {
+    /// The portfolios matching the query
+    pub results: Vec<PortfolioRecord<T, AppData>>,
+
+    /// The query for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<PortfolioQuery<T::PortfolioId, T::DateTime>>,
+}
+
+/// A search for the portfolios whose application data satisfies some filters.
+///
+/// Results are ordered by portfolio id, and `after` is the exclusive lower
+/// bound on the ids of the next page.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "PortfolioSearch")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortfolioSearch<PortfolioId> {
+    /// The filters every portfolio must satisfy
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filters: Vec<AppDataFilter>,
+
+    /// The lower bound (exclusive) for the portfolio ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<PortfolioId>,
+}
+
+/// The paginated response to a portfolio search.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "PortfolioSearchResponse",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::PortfolioId: schemars::JsonSchema,
+            T::DemandId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::PortfolioId: serde::Serialize + Clone,
+            T::DemandId: serde::Serialize + Clone,
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::PortfolioId: serde::Deserialize<'de>,
+            T::DemandId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct PortfolioSearchResponse<T: Repository, AppData> {
+    /// The portfolios matching the query
+    pub results: Vec<PortfolioRecord<T, AppData>>,
+
+    /// The search for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<PortfolioSearch<T::PortfolioId>>,
+}
@@ -1,4 +1,8 @@
-use crate::{models::Basis, ports::Repository};
+use crate::{
+    models::{AppDataFilter, Basis},
+    ports::Repository,
+};
+use std::fmt::Display;
 
 /// Represents a demand entity in the flow trading system.
 ///
@@ -19,11 +23,17 @@ use crate::{models::Basis, ports::Repository};
 )]
 #[cfg_attr(
     feature = "serde",
-    derive(serde::Serialize),
-    serde(bound(serialize = "
-        T::ProductId: serde::Serialize + Clone,
-        AppData: serde::Serialize
-    "))
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
 )]
 pub struct ProductRecord<T: Repository, AppData> {
     /// Unique identifier for this product instance.
@@ -43,3 +53,202 @@ pub struct ProductRecord<T: Repository, AppData> {
     /// (with weight=1) or can be represented in terms of a finer basis.
     pub basis: Basis<T::ProductId>,
 }
+
+/// The result of partitioning a product into weighted children.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ProductPartition",
+        bound = "
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ProductPartition<T: Repository, AppData> {
+    /// The partitioned product, as of the partitioning. Its basis is the
+    /// updated expansion of the product in terms of its descendants.
+    pub parent: ProductRecord<T, AppData>,
+
+    /// The new child products, in the order they were requested. The `parent`
+    /// of each child carries its ratio relative to the partitioned product.
+    pub children: Vec<ProductRecord<T, AppData>>,
+}
+
+/// A reason the product tree cannot be changed as requested.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ProductTreeConflict")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "code", rename_all = "snake_case")
+)]
+pub enum ProductTreeConflict<ProductId> {
+    /// The product does not exist
+    Missing {
+        /// The id of the missing product
+        product_id: ProductId,
+    },
+    /// The product is a root product, and so has no ratio to its parent
+    Root {
+        /// The id of the root product
+        product_id: ProductId,
+    },
+    /// The new parent is the product itself or one of its descendants
+    Cycle {
+        /// The id of the would-be parent
+        parent_id: ProductId,
+    },
+}
+
+impl<ProductId: Display> Display for ProductTreeConflict<ProductId> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { product_id } => write!(f, "product {product_id} does not exist"),
+            Self::Root { product_id } => write!(f, "product {product_id} has no parent"),
+            Self::Cycle { parent_id } => {
+                write!(f, "product {parent_id} is a descendant of the product")
+            }
+        }
+    }
+}
+
+/// A search for the products whose application data satisfies some filters.
+///
+/// Results are ordered by product id, and `after` is the exclusive lower
+/// bound on the ids of the next page.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ProductSearch")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProductSearch<ProductId> {
+    /// The filters every product must satisfy
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filters: Vec<AppDataFilter>,
+
+    /// The lower bound (exclusive) for the product ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<ProductId>,
+}
+
+/// The paginated response to a product search.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ProductSearchResponse",
+        bound = "
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ProductSearchResponse<T: Repository, AppData> {
+    /// The products matching the query
+    pub results: Vec<ProductRecord<T, AppData>>,
+
+    /// The search for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<ProductSearch<T::ProductId>>,
+}
+
+/// A listing of the products existing at some time, a page at a time.
+///
+/// Results are ordered by product id, and `after` is the exclusive lower
+/// bound on the ids of the next page. By default, only the root products are
+/// listed.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ProductQuery")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProductQuery<ProductId> {
+    /// Also list the products created by partitioning, so that the listing
+    /// covers the whole of the partition tree (false if omitted)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub include_tree: bool,
+
+    /// The lower bound (exclusive) for the product ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<ProductId>,
+}
+
+impl<ProductId> Default for ProductQuery<ProductId> {
+    fn default() -> Self {
+        Self {
+            include_tree: false,
+            after: None,
+        }
+    }
+}
+
+/// A page of the response to a product listing.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "ProductQueryResponse",
+        bound = "
+            T::ProductId: schemars::JsonSchema,
+            AppData: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::ProductId: serde::Serialize + Clone,
+            AppData: serde::Serialize
+        ",
+        deserialize = "
+            T::ProductId: serde::Deserialize<'de>,
+            AppData: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct ProductQueryResponse<T: Repository, AppData> {
+    /// The products of the page
+    pub results: Vec<ProductRecord<T, AppData>>,
+
+    /// The query for the next page of results, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<ProductQuery<T::ProductId>>,
+}
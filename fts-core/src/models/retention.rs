@@ -0,0 +1,59 @@
+/// The result of pruning the history of a repository.
+///
+/// The history of demand curves and portfolio demand groups is pruned up to
+/// the requested horizon. Batch outcomes and portfolio product groups are
+/// also needed to accrue trade activity, so they are only pruned up to the
+/// most recent settlement (if it precedes the horizon), and not at all if
+/// nothing has been settled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "PruneRecord")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PruneRecord<DateTime> {
+    /// History superseded before this time was pruned
+    pub before: DateTime,
+
+    /// Batch outcomes and product groups superseded before this time were pruned, if any
+    pub settled_before: Option<DateTime>,
+
+    /// The number of pruned demand curve revisions
+    pub curves: u64,
+
+    /// The number of pruned entries of portfolio demand groups
+    pub demand_groups: u64,
+
+    /// The number of pruned entries of portfolio product groups
+    pub product_groups: u64,
+
+    /// The number of pruned portfolio outcomes
+    pub portfolio_outcomes: u64,
+
+    /// The number of pruned product outcomes
+    pub product_outcomes: u64,
+
+    /// The number of pruned records of the inputs of batches run before `before`
+    pub batch_inputs: u64,
+
+    /// Whether the pruned history was archived before its removal
+    pub archived: bool,
+}
+
+/// The result of compacting the history of a repository.
+///
+/// A portfolio update rewrites its entire demand and product groups, even if
+/// they are unchanged. Compaction merges each run of consecutive, identical
+/// versions of a group (by the same actor) into its first version, so the
+/// group's value as of any time is unaffected.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactionRecord {
+    /// The number of removed entries of portfolio demand groups
+    pub demand_groups: u64,
+
+    /// The number of removed entries of portfolio product groups
+    pub product_groups: u64,
+}
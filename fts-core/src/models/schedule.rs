@@ -0,0 +1,44 @@
+/// The schedule on which batch auctions are executed.
+///
+/// Batch auctions are executed every `every` seconds, the first of which at
+/// `next`. While the schedule is paused (or has no interval), no batch is
+/// executed and `next` is None.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "BatchSchedule")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchSchedule<DateTime> {
+    /// The interval between batch auctions, in seconds, if any
+    pub every: Option<f64>,
+
+    /// The time of the next scheduled batch auction, if any
+    pub next: Option<DateTime>,
+
+    /// Whether the execution of scheduled batch auctions is suspended
+    pub paused: bool,
+}
+
+/// A change to the schedule of batch auctions.
+///
+/// Each omitted field leaves the corresponding aspect of the schedule unchanged.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ScheduleUpdate")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScheduleUpdate<DateTime> {
+    /// The new interval between batch auctions, in seconds
+    pub every: Option<f64>,
+
+    /// The time of the next batch auction, from which subsequent batches are
+    /// scheduled at the interval
+    pub next: Option<DateTime>,
+
+    /// Whether to suspend (or resume) the execution of scheduled batch auctions
+    pub paused: Option<bool>,
+}
@@ -0,0 +1,393 @@
+use crate::{
+    models::{Map, ValueRecord},
+    ports::Repository,
+};
+use std::{fmt::Display, hash::Hash};
+
+/// Configuration controlling how trade activity is aggregated into a settlement.
+///
+/// Batch auctions produce *rates* of trade, which accrue into positions (and
+/// payments) for as long as the outcome remains in effect. A settlement
+/// integrates these rates over time and rounds the accumulated quantities to
+/// a fixed precision, carrying any rounding residual forward into subsequent
+/// settlements.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "SettlementConfig")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettlementConfig<DateTime> {
+    /// Settle all trade activity occurring before this time
+    pub as_of: DateTime,
+
+    /// The length, in seconds, of the unit of time in which trade rates are expressed
+    pub time_unit: f64,
+
+    /// The number of decimal places to which positions are rounded
+    pub position_decimals: u32,
+
+    /// The number of decimal places to which payments are rounded
+    pub payment_decimals: u32,
+}
+
+/// A reason trade activity cannot be settled as requested.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "SettlementConflict")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "code", rename_all = "snake_case")
+)]
+pub enum SettlementConflict<DateTime> {
+    /// The activity has already been settled up to a later time
+    Superseded {
+        /// The time up to which the activity was most recently settled
+        latest: DateTime,
+    },
+    /// The activity was settled up to the same time with a different configuration
+    Mismatched {
+        /// The configuration of the existing settlement
+        existing: SettlementConfig<DateTime>,
+    },
+    /// No settlement was made up to the time
+    Missing {
+        /// The time up to which the settlement would have settled activity
+        as_of: DateTime,
+    },
+    /// The settlement was reversed, and has yet to be corrected
+    Reversed {
+        /// The time up to which the reversed settlement settled activity
+        as_of: DateTime,
+    },
+}
+
+impl<DateTime: Display> Display for SettlementConflict<DateTime> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Superseded { latest } => {
+                write!(f, "activity has already been settled as of {latest}")
+            }
+            Self::Mismatched { existing } => write!(
+                f,
+                "activity was settled as of {} with a different configuration",
+                existing.as_of
+            ),
+            Self::Missing { as_of } => write!(f, "no settlement was made as of {as_of}"),
+            Self::Reversed { as_of } => {
+                write!(f, "the settlement as of {as_of} has been reversed")
+            }
+        }
+    }
+}
+
+/// The aggregated trade activity of a single bidder over an interval of time.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "Activity")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Activity<ProductId: Eq + Hash> {
+    /// The net quantity traded of each product (negative for sell, positive for buy)
+    pub positions: Map<ProductId>,
+
+    /// The net payment owed by the bidder (negative if the bidder is owed)
+    pub payment: f64,
+}
+
+impl<ProductId: Eq + Hash> Default for Activity<ProductId> {
+    fn default() -> Self {
+        Self {
+            positions: Map::default(),
+            payment: 0.0,
+        }
+    }
+}
+
+/// The result of settling all trade activity within an interval of time.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "SettlementRecord",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct SettlementRecord<T: Repository> {
+    /// The (inclusive) start of the settled interval
+    pub valid_from: T::DateTime,
+
+    /// The (exclusive) end of the settled interval, i.e. the `as_of` of the settlement
+    pub valid_until: T::DateTime,
+
+    /// The settled activity of each bidder with trade in the interval
+    pub activity: Map<T::BidderId, Activity<T::ProductId>>,
+}
+
+/// A revision of a settlement, which either reverses or corrects it.
+///
+/// Settlements are never altered once made. Instead, a reversal posts entries
+/// offsetting each of the settlement's positions and payments, and a correction
+/// posts the entries of settling the interval again with another configuration.
+/// The net settlement is the sum of the original entries and every revision.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "SettlementRevision",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct SettlementRevision<T: Repository> {
+    /// The time up to which the revised settlement settled activity
+    pub as_of: T::DateTime,
+
+    /// The number of the revision, counting from 1 for the first revision of
+    /// the settlement (the settlement as originally made being revision 0)
+    pub revision: u32,
+
+    /// The time at which the revision was made
+    pub revised_at: T::DateTime,
+
+    /// The configuration with which the settlement was corrected, or None if
+    /// the revision reverses it
+    pub config: Option<SettlementConfig<T::DateTime>>,
+
+    /// The entries posted by the revision for each bidder
+    pub activity: Map<T::BidderId, Activity<T::ProductId>>,
+}
+
+/// A finalized statement of the entries posted for a bidder by a settlement.
+///
+/// A statement is issued to each bidder with entries whenever a settlement is
+/// made or revised, in the same transaction, and never changes thereafter: a
+/// revision issues further statements rather than altering those already
+/// issued. Its `id` is unique within the repository and never reused, so that
+/// the statement may be referenced by other records, such as invoices.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "SettlementStatement",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct SettlementStatement<T: Repository> {
+    /// The number identifying the statement
+    pub id: u64,
+
+    /// The bidder to whom the statement is issued
+    pub bidder_id: T::BidderId,
+
+    /// The (inclusive) start of the settled interval
+    pub valid_from: T::DateTime,
+
+    /// The (exclusive) end of the settled interval, i.e. the `as_of` of the settlement
+    pub valid_until: T::DateTime,
+
+    /// The revision of the settlement which posted the entries (0 for the
+    /// settlement as originally made)
+    pub revision: u32,
+
+    /// The time at which the revision was made, or None for the original settlement
+    pub revised_at: Option<T::DateTime>,
+
+    /// The configuration with which the entries were settled, or None if
+    /// they reverse the settlement
+    pub config: Option<SettlementConfig<T::DateTime>>,
+
+    /// The entries posted for the bidder
+    pub activity: Activity<T::ProductId>,
+}
+
+/// An overview of a bidder's trading at a point in time.
+///
+/// This combines the bidder's settled and unsettled activity with the rates
+/// of trade of the batch outcomes currently in effect, sparing clients from
+/// assembling the same from the history of each of their portfolios.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "BidderSummary",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct BidderSummary<T: Repository> {
+    /// The time the summary was computed for
+    pub as_of: T::DateTime,
+
+    /// The net position in each product, i.e. the settled and unsettled positions combined
+    pub positions: Map<T::ProductId>,
+
+    /// The net rate of trade in each product implied by the batch outcomes in effect
+    pub rates: Map<T::ProductId>,
+
+    /// The cumulative activity across all settlements up to `as_of`
+    pub settled: Activity<T::ProductId>,
+
+    /// The (unrounded) activity since the most recent settlement
+    pub unsettled: Activity<T::ProductId>,
+
+    /// The start of the unsettled interval, i.e. the most recent settlement
+    /// (or the first batch, if there is none)
+    pub unsettled_from: T::DateTime,
+}
+
+/// A query for a page of a bidder's unsettled positions.
+///
+/// Positions are ordered by product id, and `after` is the exclusive lower
+/// bound on the ids of the next page.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "UnsettledActivityQuery")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "ProductId: serde::Deserialize<'de>"))
+)]
+pub struct UnsettledActivityQuery<ProductId> {
+    /// The products whose positions to report (every product, if empty)
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub products: Vec<ProductId>,
+
+    /// The lower bound (exclusive) for the product ids
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub after: Option<ProductId>,
+}
+
+impl<ProductId> Default for UnsettledActivityQuery<ProductId> {
+    fn default() -> Self {
+        Self {
+            products: Vec::new(),
+            after: None,
+        }
+    }
+}
+
+/// A page of a bidder's unsettled activity.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "UnsettledActivityResponse",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct UnsettledActivityResponse<T: Repository> {
+    /// The unsettled activity, whose positions are limited to the page. The
+    /// payment is that of all the bidder's activity, whichever products are
+    /// reported.
+    pub activity: ValueRecord<T::DateTime, Activity<T::ProductId>>,
+
+    /// The query for the next page of positions, if there are more.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub more: Option<UnsettledActivityQuery<T::ProductId>>,
+}
@@ -0,0 +1,132 @@
+use crate::{models::Map, ports::Repository};
+
+/// The width of the buckets into which outcome history is aggregated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "PriceInterval")
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum PriceInterval {
+    /// Aggregate the outcomes of each (UTC) hour
+    #[default]
+    Hour,
+    /// Aggregate the outcomes of each (UTC) day
+    Day,
+}
+
+/// An aggregate of the batch outcomes for a product within a bucket of time.
+///
+/// Each batch is attributed to the bucket in which it cleared, and its outcome
+/// is considered to be in effect until it is superseded by the next batch.
+/// Prices are `None` if none of the batches in the bucket produced a price.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "PriceSummary")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceSummary {
+    /// The clearing price of the first batch in the bucket
+    pub open: Option<f64>,
+
+    /// The highest clearing price in the bucket
+    pub high: Option<f64>,
+
+    /// The lowest clearing price in the bucket
+    pub low: Option<f64>,
+
+    /// The clearing price of the last batch in the bucket
+    pub close: Option<f64>,
+
+    /// The average clearing price, weighted by how long each outcome was in effect
+    pub twap: Option<f64>,
+
+    /// The quantity traded, i.e. the rate of trade integrated over time
+    pub volume: f64,
+
+    /// The number of batches in the bucket
+    pub batches: u32,
+}
+
+/// Aggregate figures for a single product, derived from the batch outcomes
+/// and the active bids without revealing any individual bid.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ProductStatistics")
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProductStatistics<DateTime> {
+    /// The clearing price of the most recent batch to produce a price
+    pub price: Option<f64>,
+
+    /// The time of the most recent batch to produce a price
+    pub cleared: Option<DateTime>,
+
+    /// The quantity traded within the window, i.e. the rate of trade integrated over time
+    pub volume: f64,
+
+    /// The sum of every bidder's net long position, settled and unsettled
+    pub open_interest: f64,
+
+    /// The number of active portfolios whose basis includes the product
+    pub portfolios: u32,
+
+    /// The number of active demands associated to those portfolios
+    pub demands: u32,
+}
+
+impl<DateTime> Default for ProductStatistics<DateTime> {
+    fn default() -> Self {
+        Self {
+            price: None,
+            cleared: None,
+            volume: 0.0,
+            open_interest: 0.0,
+            portfolios: 0,
+            demands: 0,
+        }
+    }
+}
+
+/// Market-wide statistics, reported per product.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "MarketStatistics",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::ProductId: schemars::JsonSchema,
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::ProductId: serde::Serialize,
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::ProductId: serde::Deserialize<'de>,
+        "
+    ))
+)]
+pub struct MarketStatistics<T: Repository> {
+    /// The time the statistics were computed for
+    pub as_of: T::DateTime,
+
+    /// The statistics of every product which has been traded or is referenced by an active portfolio
+    pub products: Map<T::ProductId, ProductStatistics<T::DateTime>>,
+}
@@ -0,0 +1,46 @@
+use crate::ports::Repository;
+
+/// What remains of a purged demand or portfolio.
+///
+/// Purging permanently erases the application data and history of a record,
+/// e.g. to honour a request for erasure. The tombstone retains only the
+/// identity of the record and its owner, so that references to it (such as
+/// from portfolio groups or batch outcomes) remain meaningful.
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(
+        rename = "Tombstone",
+        bound = "
+            T::DateTime: schemars::JsonSchema,
+            T::BidderId: schemars::JsonSchema,
+            Id: schemars::JsonSchema
+        "
+    )
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(
+        serialize = "
+            T::DateTime: serde::Serialize,
+            T::BidderId: serde::Serialize,
+            Id: serde::Serialize
+        ",
+        deserialize = "
+            T::DateTime: serde::Deserialize<'de>,
+            T::BidderId: serde::Deserialize<'de>,
+            Id: serde::Deserialize<'de>
+        "
+    ))
+)]
+pub struct Tombstone<T: Repository, Id> {
+    /// The id of the purged record
+    pub id: Id,
+
+    /// The bidder who owned the purged record
+    pub bidder_id: T::BidderId,
+
+    /// The time at which the record was purged
+    pub purged_at: T::DateTime,
+}
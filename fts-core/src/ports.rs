@@ -7,6 +7,9 @@
 //! This separation allows for easier testing and the ability to swap out infrastructure
 //! components without affecting the core business logic.
 
+use crate::models::{
+    Actor, BatchSchedule, CurveDiagnostic, DemandCurve, ScheduleUpdate, SettlementStatement,
+};
 use std::hash::Hash;
 
 mod product;
@@ -21,6 +24,24 @@ pub use portfolio::PortfolioRepository;
 mod batch;
 pub use batch::BatchRepository;
 
+mod settlement;
+pub use settlement::SettlementRepository;
+
+mod health;
+pub use health::HealthRepository;
+
+mod retention;
+pub use retention::RetentionRepository;
+
+mod import;
+pub use import::ImportRepository;
+
+mod outbox;
+pub use outbox::OutboxRepository;
+
+mod notifier;
+pub use notifier::Notifier;
+
 mod solver;
 pub use solver::Solver;
 
@@ -81,7 +102,11 @@ pub trait Application {
     type Repository: DemandRepository<Self::DemandData>
         + PortfolioRepository<Self::PortfolioData>
         + ProductRepository<Self::ProductData>
-        + BatchRepository<Self::Solver>;
+        + BatchRepository<Self::Solver>
+        + SettlementRepository
+        + HealthRepository
+        + RetentionRepository
+        + ImportRepository<Self::DemandData, Self::PortfolioData, Self::ProductData>;
 
     /// The solver to use for executing auctions
     type Solver: Solver<
@@ -168,6 +193,147 @@ pub trait Application {
     /// Check if the context can manage products (create, partition).
     fn can_manage_products(&self, context: &Self::Context) -> impl Future<Output = bool> + Send;
 
+    /// Check if the context can import products, demands, and portfolios in bulk.
+    ///
+    /// Like purging, an import applies to the bids of every bidder, so by
+    /// default, this is permitted to anyone who can purge bids.
+    fn can_import(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_purge_bid(context)
+    }
+
     /// Check if the context can execute batch auctions.
     fn can_run_batch(&self, context: &Self::Context) -> impl Future<Output = bool> + Send;
+
+    /// Check if the context can preview batch auctions without executing them.
+    ///
+    /// By default, this is permitted to anyone who can execute batch auctions.
+    fn can_preview_batch(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_run_batch(context)
+    }
+
+    /// Check if the context can solve self-contained auctions, which are
+    /// neither gathered from nor recorded to the repository.
+    ///
+    /// By default, this is permitted to anyone who can preview batch auctions.
+    fn can_solve_auction(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_preview_batch(context)
+    }
+
+    /// Check if the context can view and change the schedule of batch auctions.
+    ///
+    /// By default, this is permitted to anyone who can execute batch auctions.
+    fn can_manage_schedule(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_run_batch(context)
+    }
+
+    /// Check if the context can view the detailed health of the system, such
+    /// as the status of the storage and of the batch auction schedule.
+    ///
+    /// By default, this is permitted to anyone who can execute batch auctions.
+    fn can_view_health(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_run_batch(context)
+    }
+
+    /// Check if the context can settle trade activity.
+    fn can_run_settlement(&self, context: &Self::Context) -> impl Future<Output = bool> + Send;
+
+    /// Check if the context can view demands, portfolios, and products as they
+    /// were at another instant, such as an auditor reconstructing the market.
+    ///
+    /// By default, this is permitted to anyone who can settle trade activity.
+    fn can_audit(&self, context: &Self::Context) -> impl Future<Output = bool> + Send {
+        self.can_run_settlement(context)
+    }
+
+    /// Check if the context can permanently purge demands and portfolios.
+    ///
+    /// This is intended for operators handling requests for erasure, and
+    /// applies to the bids of every bidder.
+    fn can_purge_bid(&self, context: &Self::Context) -> impl Future<Output = bool> + Send;
+
+    /// Check if the context can read the settled and unsettled activity of a
+    /// specific bidder.
+    ///
+    /// By default, this defers to `can_read_bid`, but an application may
+    /// grant this separately, e.g. to an accounting role without bid access.
+    fn can_read_settlement(
+        &self,
+        context: &Self::Context,
+        bidder_id: <Self::Repository as Repository>::BidderId,
+    ) -> impl Future<Output = bool> + Send {
+        self.can_read_bid(context, bidder_id)
+    }
+
+    /// Identify the party acting upon the bids of `bidder_id`, so that the
+    /// change can be attributed in the history of the bid.
+    ///
+    /// By default, every change is attributed to the bidder, but an
+    /// application may distinguish e.g. operators acting on their behalf.
+    fn actor(
+        &self,
+        context: &Self::Context,
+        bidder_id: <Self::Repository as Repository>::BidderId,
+    ) -> impl Future<Output = Actor> + Send {
+        let _ = (context, bidder_id);
+        std::future::ready(Actor::Bidder)
+    }
+
+    /// Diagnose a (structurally valid) demand curve against the rules of the
+    /// market, e.g. limits on prices or rates, on behalf of the context.
+    ///
+    /// These diagnostics are reported alongside the structural ones when a
+    /// curve is validated. By default, there are no additional rules.
+    fn diagnose_curve(
+        &self,
+        context: &Self::Context,
+        curve: &DemandCurve,
+    ) -> impl Future<Output = Vec<CurveDiagnostic>> + Send {
+        let _ = (context, curve);
+        std::future::ready(Vec::new())
+    }
+
+    /// Render a settlement statement as a document of the given media type
+    /// (e.g. `application/pdf` or `text/csv`), for a back office or bidder
+    /// wanting the statement in a form other than its structured record.
+    ///
+    /// Returns None if the application does not render statements as the
+    /// media type. By default, statements are not rendered at all.
+    fn render_statement(
+        &self,
+        statement: &SettlementStatement<Self::Repository>,
+        media_type: &str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>>> + Send
+    {
+        let _ = (statement, media_type);
+        std::future::ready(Ok(None))
+    }
+
+    /// Get the schedule on which the application executes batch auctions.
+    ///
+    /// By default, the application does not schedule batch auctions, and
+    /// None is returned.
+    fn schedule(
+        &self,
+    ) -> impl Future<Output = Option<BatchSchedule<<Self::Repository as Repository>::DateTime>>> + Send
+    {
+        async { None }
+    }
+
+    /// Change the schedule on which the application executes batch auctions,
+    /// taking effect immediately.
+    ///
+    /// Returns the updated schedule, or None if the application does not
+    /// schedule batch auctions (as is the default).
+    fn update_schedule(
+        &self,
+        update: ScheduleUpdate<<Self::Repository as Repository>::DateTime>,
+    ) -> impl Future<Output = Option<BatchSchedule<<Self::Repository as Repository>::DateTime>>> + Send
+    {
+        let _ = update;
+        async { None }
+    }
 }
+
+/// A streamed record of the history of the repository `R`, or the error reading it
+type HistoryRecord<R, T> =
+    Result<crate::models::ValueRecord<<R as Repository>::DateTime, T>, <R as Repository>::Error>;
@@ -1,4 +1,9 @@
-use crate::models::{DateTimeRangeQuery, DateTimeRangeResponse};
+use crate::models::{
+    Auction, BatchAttempt, BatchError, BatchMetadata, BatchPreview, BatchRun, BidderOutcome,
+    DateTimeRangeQuery, DateTimeRangeResponse, MarketStatistics, PriceInterval, PriceSummary,
+    ValueRecord,
+};
+use futures_core::Stream;
 
 /// Repository interface for batch auction execution and outcome retrieval.
 ///
@@ -10,19 +15,111 @@ pub trait BatchRepository<T: super::Solver<Self::DemandId, Self::PortfolioId, Se
     /// Execute a batch auction for a specific timestamp.
     ///
     /// Gather all the portfolios and demand curves for the requested time
-    /// and solve the corresponding auction using `solver`.
+    /// and solve the corresponding auction using `solver`. How the auction
+    /// was solved is recorded, whether or not the solver succeeded, and a
+    /// failed attempt (whether of the solver or of the repository itself) is
+    /// recorded as such, as far as the repository is able.
+    ///
+    /// Batches execute serially: if another batch is already executing
+    /// against the market, this call returns immediately without gathering,
+    /// solving or recording anything.
     ///
     /// # Returns
     ///
     /// - Ok(Ok(Option<DateTime>)) if the batch completed successfully, return the (optional) expiration time of the batch (typically None)
-    /// - Ok(Err(solver_error)) if the solver failed
+    /// - Ok(Err(BatchError::InProgress)) if another batch is already executing
+    /// - Ok(Err(BatchError::Solver(solver_error))) if the solver failed
     /// - Err(repository_error) if there is some other error
     fn run_batch(
         &self,
         timestamp: Self::DateTime,
         solver: T,
         state: T::State,
-    ) -> impl Future<Output = Result<Result<Option<Self::DateTime>, T::Error>, Self::Error>> + Send;
+    ) -> impl Future<
+        Output = Result<Result<Option<Self::DateTime>, BatchError<T::Error>>, Self::Error>,
+    > + Send {
+        let batch = self.run_batch_with_state(timestamp, solver, state);
+        async move { Ok(batch.await?.map(|(expires, _)| expires)) }
+    }
+
+    /// Execute a batch auction as `run_batch` does, additionally returning
+    /// the state with which to solve the next batch, as derived by
+    /// `Solver::next_state` from the outcomes of this one.
+    ///
+    /// Callers which execute batches in sequence pass the returned state to
+    /// the next call to warm-start the solver.
+    fn run_batch_with_state(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> impl Future<Output = Result<BatchOutcome<Self, T>, Self::Error>> + Send;
+
+    /// Solve a batch auction for a specific timestamp without recording it.
+    ///
+    /// The portfolios and demand curves are gathered exactly as in `run_batch`,
+    /// but the outcomes are returned to the caller rather than persisted.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(preview)) if the solver completed successfully
+    /// - Ok(Err(solver_error)) if the solver failed
+    /// - Err(repository_error) if there is some other error
+    fn preview_batch(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> impl Future<Output = Result<PreviewOutcome<Self, T>, Self::Error>> + Send;
+
+    /// Describe the most recently executed batch auction.
+    ///
+    /// # Returns
+    ///
+    /// The metadata of the latest batch, or None if no batch has been executed.
+    fn get_latest_batch(
+        &self,
+    ) -> impl Future<Output = Result<Option<BatchMetadata<Self>>, Self::Error>> + Send;
+
+    /// Retrieve the history of batch auctions which were run, including those
+    /// the solver failed to solve, along with how each was solved.
+    ///
+    /// # Returns
+    ///
+    /// A paginated response containing one record per batch, most recent first,
+    /// each of which is valid from the time the batch was run.
+    fn get_batch_runs(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<Output = Result<DateTimeRangeResponse<BatchRun, Self::DateTime>, Self::Error>> + Send;
+
+    /// Retrieve the history of failed attempts to run a batch auction.
+    ///
+    /// # Returns
+    ///
+    /// A paginated response containing one record per failed batch, most
+    /// recent first, each of which is valid from the time of the batch.
+    fn get_batch_attempts(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<
+        Output = Result<DateTimeRangeResponse<BatchAttempt, Self::DateTime>, Self::Error>,
+    > + Send;
+
+    /// Retrieve the inputs of the batch auction run at `as_of`, exactly as
+    /// they were assembled for the solver, so that the batch may be solved
+    /// again, e.g. to audit its outcomes or to reproduce a failure.
+    ///
+    /// # Returns
+    ///
+    /// The demand curves and portfolios of the batch, or None if no batch was
+    /// run at that time or the repository was not recording its inputs.
+    fn get_batch_inputs(
+        &self,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Option<Auction<Self>>, Self::Error>> + Send;
 
     /// Retrieve historical batch outcomes for a portfolio.
     ///
@@ -38,6 +135,29 @@ pub trait BatchRepository<T: super::Solver<Self::DemandId, Self::PortfolioId, Se
         Output = Result<DateTimeRangeResponse<T::PortfolioOutcome, Self::DateTime>, Self::Error>,
     > + Send;
 
+    /// Retrieve historical batch outcomes for a bidder, aggregated across all
+    /// of their portfolios.
+    ///
+    /// The rate of each portfolio is distributed over the products of its
+    /// contemporary basis and netted per product, with each trade paid for at
+    /// the product's clearing price in the same batch.
+    ///
+    /// # Returns
+    ///
+    /// A paginated response containing one record per batch in which the
+    /// bidder had an outcome, most recent first.
+    fn get_bidder_outcomes(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<
+        Output = Result<
+            DateTimeRangeResponse<BidderOutcome<Self::ProductId>, Self::DateTime>,
+            Self::Error,
+        >,
+    > + Send;
+
     /// Retrieve historical batch outcomes for a product.
     ///
     /// # Returns
@@ -51,4 +171,106 @@ pub trait BatchRepository<T: super::Solver<Self::DemandId, Self::PortfolioId, Se
     ) -> impl Future<
         Output = Result<DateTimeRangeResponse<T::ProductOutcome, Self::DateTime>, Self::Error>,
     > + Send;
+
+    /// Stream every historical batch outcome for a portfolio within the range.
+    ///
+    /// This is the unpaginated counterpart of `get_portfolio_outcomes`, for
+    /// exporting histories too large to hold in memory. The outcomes are
+    /// yielded in the same order, most recent first.
+    fn stream_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = OutcomeRecord<Self, T::PortfolioOutcome>> + Send;
+
+    /// Stream every historical batch outcome for a product within the range.
+    ///
+    /// This is the unpaginated counterpart of `get_product_outcomes`, yielding
+    /// the outcomes most recent first.
+    fn stream_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = OutcomeRecord<Self, T::ProductOutcome>> + Send;
+
+    /// Retrieve the outcome history for a product, aggregated into buckets of time.
+    ///
+    /// Outcomes still in effect are considered to end at `as_of`, and trade
+    /// volume is measured in units of `time_unit` seconds.
+    ///
+    /// # Returns
+    ///
+    /// A paginated response containing one summary per bucket with at least one
+    /// batch, where the record's interval is the span of the bucket.
+    fn get_product_summary(
+        &self,
+        product_id: Self::ProductId,
+        interval: PriceInterval,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        limit: usize,
+    ) -> impl Future<
+        Output = Result<DateTimeRangeResponse<PriceSummary, Self::DateTime>, Self::Error>,
+    > + Send;
+
+    /// Compute market-wide statistics for every product as of the specified time.
+    ///
+    /// Trade volume is accumulated over the outcomes in effect within `query`
+    /// (with outcomes still in effect considered to end at `as_of`), measured
+    /// in units of `time_unit` seconds. Open interest accrues over all time.
+    fn get_market_statistics(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> impl Future<Output = Result<MarketStatistics<Self>, Self::Error>> + Send;
 }
+
+/// The preview of a batch auction for the repository `R` as solved by `T`,
+/// or the error of the solver
+type PreviewOutcome<R, T> = Result<
+    BatchPreview<
+        R,
+        <T as super::Solver<
+            <R as super::Repository>::DemandId,
+            <R as super::Repository>::PortfolioId,
+            <R as super::Repository>::ProductId,
+        >>::PortfolioOutcome,
+        <T as super::Solver<
+            <R as super::Repository>::DemandId,
+            <R as super::Repository>::PortfolioId,
+            <R as super::Repository>::ProductId,
+        >>::ProductOutcome,
+    >,
+    <T as super::Solver<
+        <R as super::Repository>::DemandId,
+        <R as super::Repository>::PortfolioId,
+        <R as super::Repository>::ProductId,
+    >>::Error,
+>;
+
+/// The (optional) expiration of a batch auction of the repository `R` as
+/// solved by `T`, along with the state with which to solve the next, or why
+/// the batch did not complete
+type BatchOutcome<R, T> = Result<
+    (
+        Option<<R as super::Repository>::DateTime>,
+        <T as super::Solver<
+            <R as super::Repository>::DemandId,
+            <R as super::Repository>::PortfolioId,
+            <R as super::Repository>::ProductId,
+        >>::State,
+    ),
+    BatchError<
+        <T as super::Solver<
+            <R as super::Repository>::DemandId,
+            <R as super::Repository>::PortfolioId,
+            <R as super::Repository>::ProductId,
+        >>::Error,
+    >,
+>;
+
+/// A streamed outcome of the repository `R`, or the error reading it
+type OutcomeRecord<R, T> =
+    Result<ValueRecord<<R as super::Repository>::DateTime, T>, <R as super::Repository>::Error>;
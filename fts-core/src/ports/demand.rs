@@ -1,4 +1,8 @@
-use crate::models::{DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandRecord};
+use crate::models::{
+    Actor, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandQuery,
+    DemandQueryResponse, DemandRecord, DemandSearch, DemandSearchResponse, Map, Tombstone,
+};
+use futures_core::Stream;
 
 /// Repository interface for demand curve submission and retrieval.
 ///
@@ -15,13 +19,24 @@ pub trait DemandRepository<DemandData>: super::Repository {
         demand_id: Self::DemandId,
     ) -> impl Future<Output = Result<Option<Self::BidderId>, Self::Error>> + Send;
 
+    /// Get the bidder ids associated to several demands at once.
+    ///
+    /// Demands which do not exist (or have been purged) are omitted from the result.
+    fn get_demand_bidder_ids(
+        &self,
+        demand_ids: &[Self::DemandId],
+    ) -> impl Future<Output = Result<Map<Self::DemandId, Self::BidderId>, Self::Error>> + Send;
+
     /// Create a new demand with an optional initial curve.
+    ///
+    /// The `actor` is recorded in the curve history, as with every change.
     fn create_demand(
         &self,
         demand_id: Self::DemandId,
         bidder_id: Self::BidderId,
         app_data: DemandData,
         curve_data: DemandCurve,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<DemandRecord<Self, DemandData>, Self::Error>> + Send;
 
@@ -39,6 +54,7 @@ pub trait DemandRepository<DemandData>: super::Repository {
         &self,
         demand_id: Self::DemandId,
         curve_data: DemandCurve,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<DemandRecord<Self, DemandData>>, Self::Error>> + Send;
 
@@ -51,16 +67,46 @@ pub trait DemandRepository<DemandData>: super::Repository {
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<DemandRecord<Self, DemandData>>, Self::Error>> + Send;
 
-    /// Query all the demand curves with non-null data associated to any of `bidder_ids`.
+    /// Query the demands associated to any of `bidder_ids` which satisfy the
+    /// filters of `query`.
     ///
     /// # Returns
     ///
-    /// A vector of "active" (as of the time of querying) demand records.
+    /// At most `limit` demand records (as of the time of querying), ordered
+    /// by id, along with the query for the next page if there are more.
     fn query_demand(
         &self,
         bidder_ids: &[Self::BidderId],
+        query: DemandQuery<Self::DemandId, Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<Output = Result<DemandQueryResponse<Self, DemandData>, Self::Error>> + Send;
+
+    /// Query all the demand curves with non-null data associated to any of `bidder_ids`,
+    /// restricted to those belonging to a portfolio whose basis references `product_id`.
+    ///
+    /// # Returns
+    ///
+    /// A vector of "active" (as of the time of querying) demand records.
+    fn query_demand_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
     ) -> impl Future<Output = Result<Vec<DemandRecord<Self, DemandData>>, Self::Error>> + Send;
 
+    /// Search the demand curves with non-null data associated to any of
+    /// `bidder_ids` for those whose application data satisfies every filter.
+    ///
+    /// # Returns
+    ///
+    /// At most `limit` "active" (as of the time of querying) demand records,
+    /// ordered by id, along with the search for the next page if there are more.
+    fn search_demands(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandSearch<Self::DemandId>,
+        limit: usize,
+    ) -> impl Future<Output = Result<DemandSearchResponse<Self, DemandData>, Self::Error>> + Send;
+
     /// Retrieve the history of curve changes for a demand.
     ///
     /// # Returns
@@ -75,4 +121,32 @@ pub trait DemandRepository<DemandData>: super::Repository {
     ) -> impl Future<
         Output = Result<DateTimeRangeResponse<DemandCurve, Self::DateTime>, Self::Error>,
     > + Send;
+
+    /// Stream every curve change for a demand within the range.
+    ///
+    /// This is the unpaginated counterpart of `get_demand_curve_history`, for
+    /// exporting histories too large to hold in memory. The records are
+    /// yielded in the same order, most recent first.
+    fn stream_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = super::HistoryRecord<Self, DemandCurve>> + Send;
+
+    /// Permanently erase a demand's application data and curve history.
+    ///
+    /// Unlike setting the curve to None, this does not preserve the demand's
+    /// history: only a tombstone remains, after which the demand is treated
+    /// as though it does not exist.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Some(tombstone)) if successful
+    /// - Ok(None) if no such demand exists (or it was already purged)
+    /// - Err otherwise
+    fn purge_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Option<Tombstone<Self, Self::DemandId>>, Self::Error>> + Send;
 }
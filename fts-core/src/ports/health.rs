@@ -0,0 +1,36 @@
+use crate::models::{ConsistencyRecord, MigrationStatus};
+
+/// Repository interface for monitoring the underlying storage.
+///
+/// This allows operators to verify that the storage is reachable and that its
+/// schema is up to date, e.g. from a health check, and that the records it
+/// derives from others agree with them.
+pub trait HealthRepository: super::Repository {
+    /// Check the connectivity of the storage.
+    ///
+    /// # Returns
+    ///
+    /// The status of the storage's schema migrations, or an error if the
+    /// storage could not be reached.
+    fn check_health(&self) -> impl Future<Output = Result<MigrationStatus, Self::Error>> + Send;
+
+    /// Check the records derived from others against a fresh derivation.
+    ///
+    /// This is meant to be run after a schema migration, or whenever the
+    /// incremental maintenance of the derived records is suspect. If `repair`
+    /// is true, the divergent records are replaced by the fresh derivation:
+    /// the paths of a divergent product are derived anew for all time, while
+    /// the current groups of a divergent portfolio are restarted as of its
+    /// latest update.
+    ///
+    /// # Returns
+    ///
+    /// The products and portfolios whose derived records diverged, or an error
+    /// if the check could not be made.
+    fn check_consistency(
+        &self,
+        repair: bool,
+    ) -> impl Future<
+        Output = Result<ConsistencyRecord<Self::ProductId, Self::PortfolioId>, Self::Error>,
+    > + Send;
+}
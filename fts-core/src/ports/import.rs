@@ -0,0 +1,33 @@
+use crate::models::{Actor, ImportDocument, ImportIssue, ImportRecord};
+
+/// Repository interface for importing products, demands, and portfolios in bulk.
+///
+/// Migrating an existing market entity-by-entity is slow, and a failure
+/// midway leaves the repository partially populated. This trait instead
+/// imports a complete document in a single transaction.
+pub trait ImportRepository<DemandData, PortfolioData, ProductData>: super::Repository {
+    /// Import every product, demand, and portfolio of `document` as of `as_of`.
+    ///
+    /// The references of the document are validated against itself and the
+    /// current contents of the repository, and nothing is imported if there
+    /// are any issues. Every demand and portfolio is attributed to `actor`.
+    /// If `dry_run` is set, the import is performed but rolled back, so that
+    /// a document may be checked without altering the repository.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(record)) if the document was (or, for a dry run, would be) imported
+    /// - Ok(Err(issues)) if the document failed validation
+    /// - Err(repository_error) if there is some other error
+    fn import(
+        &self,
+        document: ImportDocument<Self, DemandData, PortfolioData, ProductData>,
+        actor: Actor,
+        as_of: Self::DateTime,
+        dry_run: bool,
+    ) -> impl Future<Output = Result<ImportOutcome<Self>, Self::Error>> + Send;
+}
+
+/// The result of importing a document: either a record of the import, or
+/// the issues which prevented it.
+type ImportOutcome<T> = Result<ImportRecord, Vec<ImportIssue<T>>>;
@@ -0,0 +1,20 @@
+use crate::{models::OutboxEvent, ports::Repository};
+
+/// Interface for publishing market events to external systems, such as
+/// webhooks or a message broker.
+pub trait Notifier<T: Repository> {
+    /// The error type for failed publications
+    type Error: std::error::Error;
+
+    /// Publish a sequence of events, in order.
+    ///
+    /// The events are only marked delivered once this succeeds, so an
+    /// implementation should not return until the events are durably
+    /// accepted by the external system. Should publication fail, the same
+    /// events are published again later, so consumers should use the
+    /// `sequence` of each event to discard any duplicates.
+    fn publish(
+        &self,
+        events: &[OutboxEvent<T>],
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
@@ -0,0 +1,33 @@
+use crate::models::OutboxEvent;
+
+/// Repository interface for the reliable delivery of market events.
+///
+/// Every change to the market records an event in an outbox, in the same
+/// transaction as the change itself. A relay then publishes the pending
+/// events through a [`Notifier`](super::Notifier) and marks them delivered,
+/// so that no event is lost should the process die between a change and its
+/// publication. Delivery is therefore at least once: an event published just
+/// before the process dies is published again once it restarts.
+pub trait OutboxRepository: super::Repository {
+    /// Retrieve the oldest events yet to be delivered, in the order in which
+    /// they were recorded.
+    ///
+    /// # Returns
+    ///
+    /// Up to `limit` events, or an empty vector if every event was delivered.
+    fn pending_events(
+        &self,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<OutboxEvent<Self>>, Self::Error>> + Send;
+
+    /// Mark every event up to and including `sequence` as delivered at `as_of`.
+    ///
+    /// # Returns
+    ///
+    /// The number of events newly marked as delivered.
+    fn mark_delivered(
+        &self,
+        sequence: u64,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<u64, Self::Error>> + Send;
+}
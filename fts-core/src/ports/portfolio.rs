@@ -1,4 +1,9 @@
-use crate::models::{Basis, DateTimeRangeQuery, DateTimeRangeResponse, PortfolioRecord, Weights};
+use crate::models::{
+    Actor, Basis, DateTimeRangeQuery, DateTimeRangeResponse, Map, PortfolioQuery,
+    PortfolioQueryResponse, PortfolioRecord, PortfolioSearch, PortfolioSearchResponse, Tombstone,
+    Weights,
+};
+use futures_core::Stream;
 
 /// Repository interface for portfolio CRUD operations and history tracking.
 ///
@@ -15,7 +20,18 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         portfolio_id: Self::PortfolioId,
     ) -> impl Future<Output = Result<Option<Self::BidderId>, Self::Error>> + Send;
 
+    /// Get the bidder ids associated to several portfolios at once.
+    ///
+    /// Portfolios which do not exist (or have been purged) are omitted from the result.
+    fn get_portfolio_bidder_ids(
+        &self,
+        portfolio_ids: &[Self::PortfolioId],
+    ) -> impl Future<Output = Result<Map<Self::PortfolioId, Self::BidderId>, Self::Error>> + Send;
+
     /// Create a new portfolio with initial demand and product associations.
+    ///
+    /// The `actor` is recorded in the group histories, as with every change.
+    #[allow(clippy::too_many_arguments)]
     fn create_portfolio(
         &self,
         portfolio_id: Self::PortfolioId,
@@ -23,6 +39,7 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         app_data: PortfolioData,
         demand: Weights<Self::DemandId>,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<PortfolioRecord<Self, PortfolioData>, Self::Error>> + Send;
 
@@ -31,6 +48,7 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         &self,
         portfolio_id: Self::PortfolioId,
         demand: Weights<Self::DemandId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
 
@@ -39,6 +57,7 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         &self,
         portfolio_id: Self::PortfolioId,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
 
@@ -48,6 +67,7 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         portfolio_id: Self::PortfolioId,
         demand: Weights<Self::DemandId>,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
 
@@ -66,16 +86,59 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
 
-    /// Query all the portfolios with non-empty groups associated to `bidder_id`.
+    /// Query the portfolios associated to any of `bidder_ids` which satisfy
+    /// the filters of `query`.
     ///
     /// # Returns
     ///
-    /// A vector of "active" (as of the time of querying) portfolio records.
+    /// At most `limit` portfolio records (as of the time of querying),
+    /// ordered by id, along with the query for the next page if there are more.
     fn query_portfolio(
         &self,
         bidder_ids: &[Self::BidderId],
+        query: PortfolioQuery<Self::PortfolioId, Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<Output = Result<PortfolioQueryResponse<Self, PortfolioData>, Self::Error>> + Send;
+
+    /// Query all the portfolios with non-empty groups associated to `bidder_id`,
+    /// with the product groups expanded in the product basis as of `as_of`.
+    ///
+    /// # Returns
+    ///
+    /// A vector of "active" (as of the time of querying) portfolio records.
+    fn query_portfolio_with_expanded_products(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
+
+    /// Query all the portfolios with non-empty groups associated to `bidder_id`,
+    /// restricted to those whose basis references `product_id`.
+    ///
+    /// # Returns
+    ///
+    /// A vector of "active" (as of the time of querying) portfolio records.
+    fn query_portfolio_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
     ) -> impl Future<Output = Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error>> + Send;
 
+    /// Search the portfolios with non-empty groups associated to any of
+    /// `bidder_ids` for those whose application data satisfies every filter.
+    ///
+    /// # Returns
+    ///
+    /// At most `limit` "active" (as of the time of querying) portfolio
+    /// records, ordered by id, along with the search for the next page if
+    /// there are more.
+    fn search_portfolios(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioSearch<Self::PortfolioId>,
+        limit: usize,
+    ) -> impl Future<Output = Result<PortfolioSearchResponse<Self, PortfolioData>, Self::Error>> + Send;
+
     /// Retrieve the history of demand group changes for a portfolio.
     ///
     /// # Returns
@@ -93,6 +156,16 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
         >,
     > + Send;
 
+    /// Stream every demand group change for a portfolio within the range.
+    ///
+    /// This is the unpaginated counterpart of `get_portfolio_demand_history`,
+    /// yielding the records most recent first.
+    fn stream_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = super::HistoryRecord<Self, Weights<Self::DemandId>>> + Send;
+
     /// Retrieve the history of product group changes for a portfolio.
     ///
     /// # Returns
@@ -106,4 +179,33 @@ pub trait PortfolioRepository<PortfolioData>: super::Repository {
     ) -> impl Future<
         Output = Result<DateTimeRangeResponse<Basis<Self::ProductId>, Self::DateTime>, Self::Error>,
     > + Send;
+
+    /// Stream every product group change for a portfolio within the range.
+    ///
+    /// This is the unpaginated counterpart of `get_portfolio_product_history`,
+    /// yielding the records most recent first.
+    fn stream_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = super::HistoryRecord<Self, Basis<Self::ProductId>>> + Send;
+
+    /// Permanently erase a portfolio's application data and group histories.
+    ///
+    /// Unlike clearing the groups, this does not preserve the portfolio's
+    /// history: only a tombstone remains, after which the portfolio is treated
+    /// as though it does not exist. The batch outcomes of the portfolio are
+    /// retained, but its unsettled activity can no longer be attributed to
+    /// products, so activity should be settled before purging.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Some(tombstone)) if successful
+    /// - Ok(None) if no such portfolio exists (or it was already purged)
+    /// - Err otherwise
+    fn purge_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Option<Tombstone<Self, Self::PortfolioId>>, Self::Error>> + Send;
 }
@@ -1,4 +1,7 @@
-use crate::models::ProductRecord;
+use crate::models::{
+    ProductQuery, ProductQueryResponse, ProductRecord, ProductSearch, ProductSearchResponse,
+    ProductTreeConflict,
+};
 
 /// Repository interface for product hierarchy management.
 ///
@@ -12,6 +15,7 @@ use crate::models::ProductRecord;
 /// - Root products have no parent
 /// - Products can be partitioned into weighted children
 /// - Child weights represent the proportion of the parent product
+/// - Ratios can be corrected, and children moved to another parent, over time
 pub trait ProductRepository<ProductData>: super::Repository {
     /// Define a new root product with no parent.
     fn create_product(
@@ -21,6 +25,21 @@ pub trait ProductRepository<ProductData>: super::Repository {
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<ProductRecord<Self, ProductData>, Self::Error>> + Send;
 
+    /// Define several new root products at once.
+    ///
+    /// The products are created atomically: if any cannot be created, none are.
+    ///
+    /// # Returns
+    ///
+    /// The created records, in the same order as `products`.
+    fn create_products<T: Send + IntoIterator<Item = (Self::ProductId, ProductData)>>(
+        &self,
+        products: T,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Vec<ProductRecord<Self, ProductData>>, Self::Error>> + Send
+    where
+        T::IntoIter: Send;
+
     /// Partition an existing product into new weighted children.
     ///
     /// This operation creates child products that represent portions of the parent.
@@ -42,6 +61,47 @@ pub trait ProductRepository<ProductData>: super::Repository {
     where
         T::IntoIter: Send + ExactSizeIterator;
 
+    /// Update the ratio of a product to its parent.
+    ///
+    /// From `as_of` on, the ratio of every ancestor of the product to each of
+    /// the product's descendants is rescaled accordingly, while the previous
+    /// ratios remain in effect before it. Portfolios referencing an ancestor
+    /// are therefore expanded with the new ratio only from `as_of` on.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(record)) with the product as of `as_of`
+    /// - Ok(Err(conflict)) if the product does not exist or is a root product
+    /// - Err(repository_error) if there is some other error
+    fn update_product_ratio(
+        &self,
+        product_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<ProductTreeOutcome<Self, ProductData>, Self::Error>> + Send;
+
+    /// Move a product, along with its descendants, under a different parent.
+    ///
+    /// From `as_of` on, the product is a child of `parent_id` with the given
+    /// ratio. A parent which had no other children becomes its own basis
+    /// element again, while a new parent which had none ceases to be one, as
+    /// if it had been partitioned. The tree as it was before `as_of` is
+    /// unaffected. A root product may be moved under a parent, too.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(record)) with the product as of `as_of`
+    /// - Ok(Err(conflict)) if either product does not exist, or if the new
+    ///   parent is the product itself or one of its descendants
+    /// - Err(repository_error) if there is some other error
+    fn move_product(
+        &self,
+        product_id: Self::ProductId,
+        parent_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<ProductTreeOutcome<Self, ProductData>, Self::Error>> + Send;
+
     /// Get the data associated with a product at a specific time.
     ///
     /// # Returns
@@ -52,4 +112,38 @@ pub trait ProductRepository<ProductData>: super::Repository {
         product_id: Self::ProductId,
         as_of: Self::DateTime,
     ) -> impl Future<Output = Result<Option<ProductRecord<Self, ProductData>>, Self::Error>> + Send;
+
+    /// List the products existing at the specified time.
+    ///
+    /// Unless `query.include_tree` is set, only the root products (those
+    /// without a parent as of the time) are listed.
+    ///
+    /// # Returns
+    ///
+    /// At most `limit` products, ordered by id, along with the query for the
+    /// next page if there are more.
+    fn query_products(
+        &self,
+        query: ProductQuery<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> impl Future<Output = Result<ProductQueryResponse<Self, ProductData>, Self::Error>> + Send;
+
+    /// Search for the products whose application data satisfies every filter.
+    ///
+    /// # Returns
+    ///
+    /// At most `limit` products existing at the specified time, ordered by
+    /// id, along with the search for the next page if there are more.
+    fn search_products(
+        &self,
+        query: ProductSearch<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> impl Future<Output = Result<ProductSearchResponse<Self, ProductData>, Self::Error>> + Send;
 }
+
+/// The result of changing the product tree: either the updated record of the
+/// product, or the conflict which prevented the change.
+type ProductTreeOutcome<T, ProductData> =
+    Result<ProductRecord<T, ProductData>, ProductTreeConflict<<T as super::Repository>::ProductId>>;
@@ -0,0 +1,37 @@
+use crate::models::{CompactionRecord, PruneRecord};
+
+/// Repository interface for bounding the growth of the storage.
+///
+/// Every change to a demand or portfolio, and every batch auction, is kept as
+/// history, which eventually dominates the storage. This trait allows an
+/// operator to discard the history which is no longer of interest.
+pub trait RetentionRepository: super::Repository {
+    /// Permanently remove the history superseded before `before`.
+    ///
+    /// Only history which is no longer in effect is removed, so the current
+    /// state of every demand, portfolio, and product is unaffected, as is any
+    /// trade activity which has yet to be settled. If the repository is
+    /// configured with an archive, the history is copied there first.
+    ///
+    /// # Returns
+    ///
+    /// A record of what was pruned, or an error if nothing was pruned.
+    fn prune_history(
+        &self,
+        before: Self::DateTime,
+    ) -> impl Future<Output = Result<PruneRecord<Self::DateTime>, Self::Error>> + Send;
+
+    /// Merge the redundant versions of the portfolios' demand and product
+    /// groups.
+    ///
+    /// Every update of a portfolio records a new version of its groups, even
+    /// if they are unchanged. Each version identical to the one it
+    /// immediately follows is merged into it, so the value of every group as
+    /// of any time is unaffected, but its history lists only the changes.
+    ///
+    /// # Returns
+    ///
+    /// A record of what was removed, or an error if nothing was removed.
+    fn compact_history(&self)
+    -> impl Future<Output = Result<CompactionRecord, Self::Error>> + Send;
+}
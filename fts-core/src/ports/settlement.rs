@@ -0,0 +1,183 @@
+use crate::models::{
+    Activity, BidderSummary, DateTimeRangeQuery, DateTimeRangeResponse, SettlementConfig,
+    SettlementConflict, SettlementRecord, SettlementRevision, SettlementStatement,
+    UnsettledActivityQuery, UnsettledActivityResponse, ValueRecord,
+};
+
+/// Repository interface for settling the trade activity produced by batch auctions.
+///
+/// Batch outcomes are expressed as rates of trade, which accrue into positions
+/// and payments while the outcome is in effect. This trait provides methods to
+/// inspect the activity accrued since the most recent settlement, to settle it,
+/// and to review past settlements.
+pub trait SettlementRepository: super::Repository {
+    /// Aggregate a bidder's trade activity since the most recent settlement.
+    ///
+    /// The activity is accrued up to `as_of` and is neither rounded nor persisted.
+    ///
+    /// # Returns
+    ///
+    /// A record of the unrounded activity, whose interval spans the previous
+    /// settlement (or the first batch, if there is none) until `as_of`.
+    fn get_unsettled_activity(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> impl Future<
+        Output = Result<ValueRecord<Self::DateTime, Activity<Self::ProductId>>, Self::Error>,
+    > + Send;
+
+    /// Aggregate a page of a bidder's trade activity since the most recent settlement.
+    ///
+    /// This is the paginated counterpart of `get_unsettled_activity`, for
+    /// bidders trading too many products to report at once. The positions are
+    /// restricted to `query.products` (if any), while the payment accounts for
+    /// all the bidder's activity.
+    ///
+    /// # Returns
+    ///
+    /// The unrounded activity, with at most `limit` positions ordered by
+    /// product id, along with the query for the next page if there are more.
+    fn get_unsettled_positions(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        query: UnsettledActivityQuery<Self::ProductId>,
+        limit: usize,
+    ) -> impl Future<Output = Result<UnsettledActivityResponse<Self>, Self::Error>> + Send;
+
+    /// Settle all trade activity since the most recent settlement up to `config.as_of`.
+    ///
+    /// The activity is accrued and settled a page of bidders at a time, rather
+    /// than aggregating the activity of every bidder in a single query, all
+    /// within a single transaction: a settlement is either made in full or not
+    /// at all. Settling is idempotent on `config.as_of`, so that a settlement
+    /// whose outcome is unknown (e.g. after a timeout) may be safely retried.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(record)) with the settled activity of every bidder, which is the
+    ///   existing settlement if one was already made with the same configuration
+    /// - Ok(Err(conflict)) if the activity was already settled up to a later
+    ///   time, or up to `config.as_of` with a different configuration
+    /// - Err(repository_error) if there is some other error
+    fn settle_activity(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+    ) -> impl Future<Output = Result<SettlementOutcome<Self>, Self::Error>> + Send;
+
+    /// Reverse the settlement made up to `as_of`, as of `revised_at`.
+    ///
+    /// Entries offsetting each of the settlement's net positions and payments
+    /// are posted as a new revision, so that the activity of the interval is
+    /// no longer settled, yet the original entries remain on record. The
+    /// rounding residuals of later settlements are unaffected.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(revision)) with the offsetting entries
+    /// - Ok(Err(conflict)) if there is no such settlement, or it is already reversed
+    /// - Err(repository_error) if there is some other error
+    fn reverse_settlement(
+        &self,
+        as_of: Self::DateTime,
+        revised_at: Self::DateTime,
+    ) -> impl Future<Output = Result<RevisionOutcome<Self>, Self::Error>> + Send;
+
+    /// Correct the settlement made up to `config.as_of` by settling its
+    /// interval again with `config`, as of `revised_at`.
+    ///
+    /// Unless it was already reversed, the settlement is first reversed as by
+    /// `reverse_settlement`, and the activity it accrued (re-expressed in
+    /// units of `config.time_unit`) is then settled anew as a further revision.
+    /// This does not depend on the batch outcomes of the interval, which may
+    /// since have been pruned. Both revisions are made in a single transaction,
+    /// and repeating a correction returns the correction already made.
+    ///
+    /// # Returns
+    ///
+    /// - Ok(Ok(revision)) with the corrected entries
+    /// - Ok(Err(conflict)) if there is no such settlement
+    /// - Err(repository_error) if there is some other error
+    fn correct_settlement(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+        revised_at: Self::DateTime,
+    ) -> impl Future<Output = Result<RevisionOutcome<Self>, Self::Error>> + Send;
+
+    /// Retrieve every revision of the settlement made up to `as_of`.
+    ///
+    /// # Returns
+    ///
+    /// The reversals and corrections of the settlement in the order they were
+    /// made, which is empty if the settlement was never revised (or not made).
+    fn get_settlement_revisions(
+        &self,
+        as_of: Self::DateTime,
+    ) -> impl Future<Output = Result<Vec<SettlementRevision<Self>>, Self::Error>> + Send;
+
+    /// Retrieve the statement issued with the given id.
+    ///
+    /// # Returns
+    ///
+    /// The statement, or None if no statement was issued with that id.
+    fn get_settlement_statement(
+        &self,
+        statement_id: u64,
+    ) -> impl Future<Output = Result<Option<SettlementStatement<Self>>, Self::Error>> + Send;
+
+    /// Retrieve the statements issued for the settlement made up to `as_of`,
+    /// by the settlement and by each of its revisions, to every bidder (or
+    /// only to `bidder_id`, if given).
+    ///
+    /// # Returns
+    ///
+    /// The statements in the order they were issued, which is empty if no
+    /// such settlement was made.
+    fn get_settlement_statements(
+        &self,
+        as_of: Self::DateTime,
+        bidder_id: Option<Self::BidderId>,
+    ) -> impl Future<Output = Result<Vec<SettlementStatement<Self>>, Self::Error>> + Send;
+
+    /// Retrieve the settled activity of a bidder across past settlements.
+    ///
+    /// # Returns
+    ///
+    /// A paginated response containing the bidder's settled activity, one record per settlement,
+    /// net of any revisions of the settlement.
+    fn get_settlement_history(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> impl Future<
+        Output = Result<
+            DateTimeRangeResponse<Activity<Self::ProductId>, Self::DateTime>,
+            Self::Error,
+        >,
+    > + Send;
+
+    /// Summarize a bidder's trading as of the specified time.
+    ///
+    /// The unsettled activity is accrued exactly as by `get_unsettled_activity`,
+    /// measuring time in units of `time_unit` seconds.
+    fn get_bidder_summary(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> impl Future<Output = Result<BidderSummary<Self>, Self::Error>> + Send;
+}
+
+/// The result of settling trade activity: either a record of the settlement,
+/// or the conflict which prevented it.
+type SettlementOutcome<T> =
+    Result<SettlementRecord<T>, SettlementConflict<<T as super::Repository>::DateTime>>;
+
+/// The result of revising a settlement: either a record of the revision, or
+/// the conflict which prevented it.
+type RevisionOutcome<T> =
+    Result<SettlementRevision<T>, SettlementConflict<<T as super::Repository>::DateTime>>;
@@ -1,4 +1,4 @@
-use crate::models::{Basis, DemandCurve, Map, Weights};
+use crate::models::{Basis, DemandCurve, Map, SolverTelemetry, Weights};
 use std::hash::Hash;
 
 /// Interface for optimization solvers that compute market clearing solutions.
@@ -52,4 +52,43 @@ pub trait Solver<DemandId: Eq + Hash, PortfolioId: Eq + Hash, ProductId: Eq + Ha
             Self::Error,
         >,
     > + Send;
+
+    /// Produce a solution as `solve` does, along with telemetry describing
+    /// how it was produced (or why it could not be).
+    ///
+    /// The default implementation reports no telemetry; solvers should
+    /// override it to report their backend, settings, and statistics.
+    fn solve_with_telemetry(
+        &self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        state: Self::State,
+    ) -> impl Future<
+        Output = (
+            Result<
+                (
+                    Map<PortfolioId, Self::PortfolioOutcome>,
+                    Map<ProductId, Self::ProductOutcome>,
+                ),
+                Self::Error,
+            >,
+            SolverTelemetry,
+        ),
+    > + Send {
+        let solution = self.solve(demand_curves, portfolios, state);
+        async move { (solution.await, SolverTelemetry::default()) }
+    }
+
+    /// Derive the state with which to solve the next batch from the outcomes
+    /// of this one, e.g. to warm-start from the previous trade rates.
+    ///
+    /// The default implementation returns the initial state, so that each
+    /// batch is solved afresh.
+    fn next_state(
+        &self,
+        portfolio_outcomes: &Map<PortfolioId, Self::PortfolioOutcome>,
+    ) -> Self::State {
+        let _ = portfolio_outcomes;
+        Self::State::default()
+    }
 }
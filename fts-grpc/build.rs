@@ -0,0 +1,12 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+
+    // We compile the protobuf definitions with a pure-Rust implementation,
+    // which avoids requiring `protoc` to be installed to build the crate.
+    let fds = protox::compile(["fts/v1/flow_trading.proto"], ["proto"])?;
+    tonic_prost_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile_fds(fds)?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Configuration types for the gRPC server.
+//!
+//! This module provides configuration options for the gRPC server,
+//! including network binding, pagination, and streaming settings.
+
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+
+/// Configuration for the gRPC server.
+///
+/// # Examples
+///
+/// ```
+/// use fts_grpc::config::GrpcConfig;
+/// use std::time::Duration;
+///
+/// // Use default configuration
+/// let config = GrpcConfig::default();
+///
+/// // Custom configuration
+/// let config = GrpcConfig {
+///     bind_address: "127.0.0.1:50051".parse().unwrap(),
+///     page_limit: 50,
+///     poll_interval: Duration::from_millis(500),
+/// };
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GrpcConfig {
+    /// The address to bind the server to
+    #[serde(default = "default_bind_address")]
+    pub bind_address: SocketAddr,
+
+    /// The page limit for paginated responses
+    #[serde(default = "default_page_limit")]
+    pub page_limit: usize,
+
+    /// How often streaming calls check the repository for new outcomes
+    #[serde(default = "default_poll_interval", with = "humantime_serde")]
+    pub poll_interval: Duration,
+}
+
+fn default_bind_address() -> SocketAddr {
+    "0.0.0.0:50051".parse().unwrap()
+}
+
+fn default_page_limit() -> usize {
+    100
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: default_bind_address(),
+            page_limit: default_page_limit(),
+            poll_interval: default_poll_interval(),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Conversions between the protobuf messages and the core models.
+//!
+//! Identifiers and timestamps are generic in `fts_core`, so we rely on their
+//! serde representations: a value is exchanged as the string it serializes to,
+//! matching the representation used by the REST API.
+
+use crate::proto;
+use fts_core::models::{
+    ConstantCurveDto, DateTimeRangeResponse, DemandCurve, DemandCurveDto, DemandRecord, Point,
+    PortfolioRecord, PwlCurveDto, ValueRecord,
+};
+use fts_core::ports::Repository;
+use serde::{Serialize, de::DeserializeOwned};
+use std::hash::Hash;
+use tonic::Status;
+
+/// Parse an identifier or timestamp from its string representation.
+pub(crate) fn parse<V: DeserializeOwned>(value: &str) -> Result<V, Status> {
+    serde_json::from_value(serde_json::Value::String(value.to_owned()))
+        .map_err(|err| Status::invalid_argument(format!("invalid value {value}: {err}")))
+}
+
+/// Parse an optional identifier or timestamp from its string representation.
+pub(crate) fn parse_opt<V: DeserializeOwned>(value: Option<&str>) -> Result<Option<V>, Status> {
+    value.map(parse).transpose()
+}
+
+/// Format an identifier or timestamp as a string.
+pub(crate) fn format<V: Serialize>(value: &V) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(value)) => value,
+        Ok(other) => other.to_string(),
+        Err(err) => {
+            tracing::error!(err = err.to_string());
+            String::new()
+        }
+    }
+}
+
+/// Parse JSON-encoded application data.
+pub(crate) fn parse_json<V: DeserializeOwned>(value: &str) -> Result<V, Status> {
+    serde_json::from_str(value)
+        .map_err(|err| Status::invalid_argument(format!("invalid JSON: {err}")))
+}
+
+/// Encode a value as JSON.
+pub(crate) fn to_json<V: Serialize>(value: &V) -> Result<String, Status> {
+    serde_json::to_string(value).map_err(|err| Status::internal(err.to_string()))
+}
+
+/// Parse a map of weights keyed by identifier.
+pub(crate) fn parse_weights<K, C>(
+    weights: std::collections::HashMap<String, f64>,
+) -> Result<C, Status>
+where
+    K: DeserializeOwned + Eq + Hash,
+    C: FromIterator<(K, f64)>,
+{
+    weights
+        .into_iter()
+        .map(|(key, weight)| Ok((parse(&key)?, weight)))
+        .collect()
+}
+
+/// Format a map of weights keyed by identifier.
+pub(crate) fn format_weights<'a, K: Serialize + 'a>(
+    weights: impl IntoIterator<Item = (&'a K, &'a f64)>,
+) -> std::collections::HashMap<String, f64> {
+    weights
+        .into_iter()
+        .map(|(key, weight)| (format(key), *weight))
+        .collect()
+}
+
+/// Parse and validate a demand curve. An absent curve corresponds to "no demand".
+pub(crate) fn parse_curve(value: Option<proto::DemandCurve>) -> Result<DemandCurve, Status> {
+    let dto = match value.and_then(|curve| curve.curve) {
+        None => DemandCurveDto::None,
+        Some(proto::demand_curve::Curve::Pwl(curve)) => DemandCurveDto::Pwl(PwlCurveDto(
+            curve
+                .points
+                .into_iter()
+                .map(|point| Point {
+                    rate: point.rate,
+                    price: point.price,
+                })
+                .collect(),
+        )),
+        Some(proto::demand_curve::Curve::Constant(curve)) => {
+            DemandCurveDto::Constant(ConstantCurveDto {
+                min_rate: curve.min_rate,
+                max_rate: curve.max_rate,
+                price: curve.price,
+            })
+        }
+    };
+    DemandCurve::try_from(dto).map_err(|err| Status::invalid_argument(err.to_string()))
+}
+
+fn format_curve(curve: &DemandCurve) -> Option<proto::DemandCurve> {
+    let dto: DemandCurveDto = curve.clone().into();
+    let curve = match dto {
+        DemandCurveDto::None => return None,
+        DemandCurveDto::Pwl(curve) => proto::demand_curve::Curve::Pwl(proto::PwlCurve {
+            points: curve
+                .0
+                .into_iter()
+                .map(|point| proto::Point {
+                    rate: point.rate,
+                    price: point.price,
+                })
+                .collect(),
+        }),
+        DemandCurveDto::Constant(curve) => {
+            proto::demand_curve::Curve::Constant(proto::ConstantCurve {
+                min_rate: curve.min_rate,
+                max_rate: curve.max_rate,
+                price: curve.price,
+            })
+        }
+    };
+    Some(proto::DemandCurve { curve: Some(curve) })
+}
+
+pub(crate) fn demand_to_proto<T, AppData>(
+    record: &DemandRecord<T, AppData>,
+) -> Result<proto::Demand, Status>
+where
+    T: Repository<
+            DateTime: Serialize,
+            BidderId: Serialize,
+            DemandId: Serialize,
+            PortfolioId: Serialize,
+        >,
+    AppData: Serialize,
+{
+    Ok(proto::Demand {
+        id: format(&record.id),
+        bidder_id: format(&record.bidder_id),
+        valid_from: format(&record.valid_from),
+        valid_until: record.valid_until.as_ref().map(format),
+        app_data: to_json(&record.app_data)?,
+        curve: format_curve(&record.curve_data),
+        portfolios: format_weights(record.portfolios.iter()),
+    })
+}
+
+pub(crate) fn portfolio_to_proto<T, AppData>(
+    record: &PortfolioRecord<T, AppData>,
+) -> Result<proto::Portfolio, Status>
+where
+    T: Repository<
+            DateTime: Serialize,
+            BidderId: Serialize,
+            DemandId: Serialize,
+            PortfolioId: Serialize,
+            ProductId: Serialize,
+        >,
+    AppData: Serialize,
+{
+    Ok(proto::Portfolio {
+        id: format(&record.id),
+        bidder_id: format(&record.bidder_id),
+        valid_from: format(&record.valid_from),
+        valid_until: record.valid_until.as_ref().map(format),
+        app_data: to_json(&record.app_data)?,
+        demand: format_weights(record.demand.iter()),
+        basis: format_weights(record.basis.iter()),
+    })
+}
+
+pub(crate) fn outcome_to_proto<DateTime: Serialize, V: Serialize>(
+    record: &ValueRecord<DateTime, V>,
+) -> Result<proto::Outcome, Status> {
+    Ok(proto::Outcome {
+        valid_from: format(&record.valid_from),
+        valid_until: record.valid_until.as_ref().map(format),
+        value: to_json(&record.value)?,
+    })
+}
+
+pub(crate) fn outcomes_to_proto<DateTime: Serialize, V: Serialize>(
+    response: &DateTimeRangeResponse<V, DateTime>,
+) -> Result<proto::OutcomesResponse, Status> {
+    Ok(proto::OutcomesResponse {
+        outcomes: response
+            .results
+            .iter()
+            .map(outcome_to_proto)
+            .collect::<Result<_, _>>()?,
+        more: response
+            .more
+            .as_ref()
+            .and_then(|more| more.before.as_ref())
+            .map(format),
+    })
+}
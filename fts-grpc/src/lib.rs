@@ -0,0 +1,112 @@
+#![warn(missing_docs)]
+//! A gRPC server for the core flow trading operations.
+//!
+//! This crate mirrors the REST API of `fts-axum` for the core bidding flows,
+//! using protobuf contracts (see `proto/fts/v1/flow_trading.proto`) and
+//! server-side streaming for batch outcomes. As with the REST API, every call
+//! expects a bearer token in the `authorization` metadata, which is provided
+//! to the permission hooks of the [`Application`].
+
+use fts_core::ports::{Application, Repository, Solver};
+use headers::{Authorization, authorization::Bearer};
+use serde::{Serialize, de::DeserializeOwned};
+
+pub mod config;
+use config::GrpcConfig;
+
+mod convert;
+
+mod service;
+pub use service::FlowTradingService;
+
+/// The generated protobuf types, as well as the client and server stubs.
+#[allow(missing_docs)]
+pub mod proto {
+    tonic::include_proto!("fts.v1");
+}
+
+/// Construct the gRPC service for an application.
+pub fn service<T: GrpcApplication>(
+    app: T,
+    config: GrpcConfig,
+) -> proto::flow_trading_server::FlowTradingServer<FlowTradingService<T>> {
+    proto::flow_trading_server::FlowTradingServer::new(FlowTradingService::new(app, config))
+}
+
+/// Starts the gRPC server with the provided configuration
+pub async fn start_server<T: GrpcApplication>(
+    config: GrpcConfig,
+    app: T,
+) -> Result<(), tonic::transport::Error> {
+    let address = config.bind_address;
+    tracing::info!("Listening for gRPC requests on {}", address);
+
+    tonic::transport::Server::builder()
+        .add_service(service(app, config))
+        .serve(address)
+        .await
+}
+
+/// As with `fts_axum::ApiApplication`, this trait and its blanket
+/// implementation specify upfront the constraints tonic imposes on the
+/// application. Any application usable with the REST API is also usable here.
+pub trait GrpcApplication:
+    Clone
+    + Send
+    + Sync
+    + 'static
+    + Application<
+        Context = Authorization<Bearer>,
+        DemandData: Send + Sync + Serialize + DeserializeOwned + 'static,
+        PortfolioData: Send + Sync + Serialize + DeserializeOwned + 'static,
+        ProductData: Send + Sync + Serialize + DeserializeOwned + 'static,
+        Repository: Clone
+                        + Send
+                        + Sync
+                        + 'static
+                        + Repository<
+            DateTime: Clone + Serialize + DeserializeOwned + Send + Sync,
+            BidderId: Clone + Serialize + DeserializeOwned + Send + Sync,
+            DemandId: Clone + Serialize + DeserializeOwned + Send + Sync,
+            PortfolioId: Clone + Serialize + DeserializeOwned + Send + Sync,
+            ProductId: Clone + Serialize + DeserializeOwned + Send + Sync,
+        >,
+        Solver: Solver<
+            <Self::Repository as Repository>::DemandId,
+            <Self::Repository as Repository>::PortfolioId,
+            <Self::Repository as Repository>::ProductId,
+            PortfolioOutcome: Send + Sync + Serialize + 'static,
+            ProductOutcome: Send + Sync + Serialize + 'static,
+        >,
+    >
+{
+}
+
+// this is the blanket implementation
+impl<T: Clone + Send + Sync + 'static> GrpcApplication for T where
+    T: Application<
+            Context = Authorization<Bearer>,
+            DemandData: Send + Sync + Serialize + DeserializeOwned + 'static,
+            PortfolioData: Send + Sync + Serialize + DeserializeOwned + 'static,
+            ProductData: Send + Sync + Serialize + DeserializeOwned + 'static,
+            Repository: Clone
+                            + Send
+                            + Sync
+                            + 'static
+                            + Repository<
+                DateTime: Clone + Serialize + DeserializeOwned + Send + Sync,
+                BidderId: Clone + Serialize + DeserializeOwned + Send + Sync,
+                DemandId: Clone + Serialize + DeserializeOwned + Send + Sync,
+                PortfolioId: Clone + Serialize + DeserializeOwned + Send + Sync,
+                ProductId: Clone + Serialize + DeserializeOwned + Send + Sync,
+            >,
+            Solver: Solver<
+                <T::Repository as Repository>::DemandId,
+                <T::Repository as Repository>::PortfolioId,
+                <T::Repository as Repository>::ProductId,
+                PortfolioOutcome: Send + Sync + Serialize + 'static,
+                ProductOutcome: Send + Sync + Serialize + 'static,
+            >,
+        >
+{
+}
@@ -0,0 +1,417 @@
+//! The implementation of the `FlowTrading` gRPC service.
+
+use crate::{
+    GrpcApplication,
+    config::GrpcConfig,
+    convert::{
+        demand_to_proto, format, outcome_to_proto, outcomes_to_proto, parse, parse_curve,
+        parse_json, parse_opt, parse_weights, portfolio_to_proto,
+    },
+    proto::{self, flow_trading_server::FlowTrading},
+};
+use fts_core::{
+    models::DateTimeRangeQuery,
+    ports::{
+        BatchRepository, DemandRepository as _, PortfolioRepository as _, ProductRepository as _,
+        Repository,
+    },
+};
+use headers::{Authorization, authorization::Bearer};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{Level, event};
+
+/// The gRPC service, wrapping an application.
+pub struct FlowTradingService<T> {
+    app: T,
+    config: Arc<GrpcConfig>,
+}
+
+impl<T> FlowTradingService<T> {
+    /// Create a new service for the application.
+    pub fn new(app: T, config: GrpcConfig) -> Self {
+        Self {
+            app,
+            config: Arc::new(config),
+        }
+    }
+}
+
+/// Extract the bearer token from the request metadata.
+fn context<R>(request: &Request<R>) -> Result<Authorization<Bearer>, Status> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| Authorization::bearer(token).ok())
+        .ok_or_else(|| Status::unauthenticated("missing bearer token"))
+}
+
+fn internal<E: std::error::Error>(err: E) -> Status {
+    event!(Level::ERROR, err = err.to_string());
+    Status::internal("internal error")
+}
+
+fn unauthorized() -> Status {
+    Status::permission_denied("not authorized")
+}
+
+#[tonic::async_trait]
+impl<T: GrpcApplication> FlowTrading for FlowTradingService<T> {
+    async fn create_demand(
+        &self,
+        request: Request<proto::CreateDemandRequest>,
+    ) -> Result<Response<proto::Demand>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let app_data: T::DemandData = parse_json(&body.app_data)?;
+        let curve_data = parse_curve(body.curve)?;
+
+        let bidder_id = self
+            .app
+            .can_create_bid(&auth)
+            .await
+            .ok_or_else(unauthorized)?;
+        let actor = self.app.actor(&auth, bidder_id.clone()).await;
+        let (demand_id, as_of) = self.app.generate_demand_id(&app_data);
+
+        let demand = self
+            .app
+            .database()
+            .create_demand(demand_id, bidder_id, app_data, curve_data, actor, as_of)
+            .await
+            .map_err(internal)?;
+
+        Ok(Response::new(demand_to_proto(&demand)?))
+    }
+
+    async fn get_demand(
+        &self,
+        request: Request<proto::GetDemandRequest>,
+    ) -> Result<Response<proto::Demand>, Status> {
+        let auth = context(&request)?;
+        let demand_id = parse(&request.get_ref().demand_id)?;
+
+        let demand = self
+            .app
+            .database()
+            .get_demand(demand_id, self.app.now())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown demand"))?;
+
+        if !self.app.can_read_bid(&auth, demand.bidder_id.clone()).await {
+            return Err(unauthorized());
+        }
+
+        Ok(Response::new(demand_to_proto(&demand)?))
+    }
+
+    async fn update_demand(
+        &self,
+        request: Request<proto::UpdateDemandRequest>,
+    ) -> Result<Response<proto::Demand>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let demand_id: <T::Repository as Repository>::DemandId = parse(&body.demand_id)?;
+        let curve_data = parse_curve(body.curve)?;
+        let db = self.app.database();
+
+        let bidder_id = db
+            .get_demand_bidder_id(demand_id.clone())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown demand"))?;
+
+        if !self.app.can_update_bid(&auth, bidder_id.clone()).await {
+            return Err(unauthorized());
+        }
+        let actor = self.app.actor(&auth, bidder_id).await;
+
+        let demand = db
+            .update_demand(demand_id, curve_data, actor, self.app.now())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::internal("failed to update demand after successful read"))?;
+
+        Ok(Response::new(demand_to_proto(&demand)?))
+    }
+
+    async fn create_portfolio(
+        &self,
+        request: Request<proto::CreatePortfolioRequest>,
+    ) -> Result<Response<proto::Portfolio>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let app_data: T::PortfolioData = parse_json(&body.app_data)?;
+        let demand = parse_weights(body.demand)?;
+        let basis = parse_weights(body.basis)?;
+
+        let bidder_id = self
+            .app
+            .can_create_bid(&auth)
+            .await
+            .ok_or_else(unauthorized)?;
+        let actor = self.app.actor(&auth, bidder_id.clone()).await;
+        let (portfolio_id, as_of) = self.app.generate_portfolio_id(&app_data);
+
+        let portfolio = self
+            .app
+            .database()
+            .create_portfolio(
+                portfolio_id,
+                bidder_id,
+                app_data,
+                demand,
+                basis,
+                actor,
+                as_of,
+            )
+            .await
+            .map_err(internal)?;
+
+        Ok(Response::new(portfolio_to_proto(&portfolio)?))
+    }
+
+    async fn get_portfolio(
+        &self,
+        request: Request<proto::GetPortfolioRequest>,
+    ) -> Result<Response<proto::Portfolio>, Status> {
+        let auth = context(&request)?;
+        let portfolio_id = parse(&request.get_ref().portfolio_id)?;
+        let db = self.app.database();
+        let as_of = self.app.now();
+
+        let portfolio = if request.get_ref().expand {
+            db.get_portfolio_with_expanded_products(portfolio_id, as_of)
+                .await
+        } else {
+            db.get_portfolio(portfolio_id, as_of).await
+        }
+        .map_err(internal)?
+        .ok_or_else(|| Status::not_found("unknown portfolio"))?;
+
+        if !self
+            .app
+            .can_read_bid(&auth, portfolio.bidder_id.clone())
+            .await
+        {
+            return Err(unauthorized());
+        }
+
+        Ok(Response::new(portfolio_to_proto(&portfolio)?))
+    }
+
+    async fn update_portfolio(
+        &self,
+        request: Request<proto::UpdatePortfolioRequest>,
+    ) -> Result<Response<proto::Portfolio>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let portfolio_id: <T::Repository as Repository>::PortfolioId = parse(&body.portfolio_id)?;
+        let demand = body
+            .demand
+            .map(|demand| parse_weights(demand.weights))
+            .transpose()?;
+        let basis = body
+            .basis
+            .map(|basis| parse_weights(basis.weights))
+            .transpose()?;
+        let db = self.app.database();
+        let as_of = self.app.now();
+
+        let bidder_id = db
+            .get_portfolio_bidder_id(portfolio_id.clone())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown portfolio"))?;
+
+        if !self.app.can_update_bid(&auth, bidder_id.clone()).await {
+            return Err(unauthorized());
+        }
+        let actor = self.app.actor(&auth, bidder_id).await;
+
+        let updated = match (demand, basis) {
+            (Some(demand), Some(basis)) => {
+                db.update_portfolio(portfolio_id, demand, basis, actor, as_of)
+                    .await
+            }
+            (Some(demand), None) => {
+                db.update_portfolio_demand(portfolio_id, demand, actor, as_of)
+                    .await
+            }
+            (None, Some(basis)) => {
+                db.update_portfolio_basis(portfolio_id, basis, actor, as_of)
+                    .await
+            }
+            (None, None) => db.get_portfolio(portfolio_id, as_of).await,
+        }
+        .map_err(internal)?
+        .ok_or_else(|| Status::internal("failed to update portfolio after successful read"))?;
+
+        Ok(Response::new(portfolio_to_proto(&updated)?))
+    }
+
+    async fn get_portfolio_outcomes(
+        &self,
+        request: Request<proto::OutcomesRequest>,
+    ) -> Result<Response<proto::OutcomesResponse>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let portfolio_id: <T::Repository as Repository>::PortfolioId = parse(&body.id)?;
+        let query = DateTimeRangeQuery {
+            before: parse_opt(body.before.as_deref())?,
+            after: parse_opt(body.after.as_deref())?,
+        };
+        let db = self.app.database();
+
+        let bidder_id = db
+            .get_portfolio_bidder_id(portfolio_id.clone())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown portfolio"))?;
+
+        if !self.app.can_read_bid(&auth, bidder_id).await {
+            return Err(unauthorized());
+        }
+
+        let outcomes = <T::Repository as BatchRepository<T::Solver>>::get_portfolio_outcomes(
+            db,
+            portfolio_id,
+            query,
+            self.config.page_limit,
+        )
+        .await
+        .map_err(internal)?;
+
+        Ok(Response::new(outcomes_to_proto(&outcomes)?))
+    }
+
+    async fn get_product_outcomes(
+        &self,
+        request: Request<proto::OutcomesRequest>,
+    ) -> Result<Response<proto::OutcomesResponse>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let product_id: <T::Repository as Repository>::ProductId = parse(&body.id)?;
+        let query = DateTimeRangeQuery {
+            before: parse_opt(body.before.as_deref())?,
+            after: parse_opt(body.after.as_deref())?,
+        };
+        let db = self.app.database();
+
+        if !self.app.can_view_products(&auth).await {
+            return Err(unauthorized());
+        }
+
+        db.get_product(product_id.clone(), self.app.now())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown product"))?;
+
+        let outcomes = <T::Repository as BatchRepository<T::Solver>>::get_product_outcomes(
+            db,
+            product_id,
+            query,
+            self.config.page_limit,
+        )
+        .await
+        .map_err(internal)?;
+
+        Ok(Response::new(outcomes_to_proto(&outcomes)?))
+    }
+
+    type WatchProductOutcomesStream = ReceiverStream<Result<proto::Outcome, Status>>;
+
+    async fn watch_product_outcomes(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchProductOutcomesStream>, Status> {
+        let auth = context(&request)?;
+        let body = request.into_inner();
+        let product_id: <T::Repository as Repository>::ProductId = parse(&body.product_id)?;
+        let mut after: Option<<T::Repository as Repository>::DateTime> =
+            parse_opt(body.after.as_deref())?;
+
+        if !self.app.can_view_products(&auth).await {
+            return Err(unauthorized());
+        }
+
+        self.app
+            .database()
+            .get_product(product_id.clone(), self.app.now())
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("unknown product"))?;
+
+        let (tx, rx) = mpsc::channel(16);
+        let app = self.app.clone();
+        let config = self.config.clone();
+
+        // There is no notification mechanism for completed batches, so we poll
+        // the repository for outcomes newer than the last one we sent. The
+        // task ends once the client disconnects.
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.poll_interval);
+            loop {
+                interval.tick().await;
+
+                // Gather every outcome since the last one we sent, paging as
+                // necessary, then emit them in chronological order.
+                let mut outcomes = Vec::new();
+                let mut query = DateTimeRangeQuery {
+                    before: None,
+                    after: after.clone(),
+                };
+                loop {
+                    let page = <T::Repository as BatchRepository<T::Solver>>::get_product_outcomes(
+                        app.database(),
+                        product_id.clone(),
+                        query,
+                        config.page_limit,
+                    )
+                    .await
+                    .map_err(internal);
+
+                    match page {
+                        Ok(page) => {
+                            outcomes.extend(page.results);
+                            match page.more {
+                                Some(more) => query = more,
+                                None => break,
+                            }
+                        }
+                        Err(status) => {
+                            let _ = tx.send(Err(status)).await;
+                            return;
+                        }
+                    }
+                }
+
+                for outcome in outcomes.into_iter().rev() {
+                    // The lower bound of the query is inclusive, so we skip
+                    // the outcome we have already sent.
+                    if after
+                        .as_ref()
+                        .is_some_and(|after| format(after) == format(&outcome.valid_from))
+                    {
+                        continue;
+                    }
+                    after = Some(outcome.valid_from.clone());
+                    if tx.send(outcome_to_proto(&outcome)).await.is_err() {
+                        return;
+                    }
+                }
+
+                if tx.is_closed() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -0,0 +1,87 @@
+use fts_core::ports::Application;
+use fts_solver::clarabel::ClarabelSolver;
+use fts_sqlite::{
+    Db,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use headers::{Authorization, authorization::Bearer};
+
+// For simplicity, the bearer token is simply the bidder id, which grants full
+// access to that bidder's bids and read access to the products.
+#[derive(Clone)]
+pub struct TestApp(pub Db);
+
+impl TestApp {
+    fn bidder_id(&self, context: &Authorization<Bearer>) -> Option<BidderId> {
+        context.token().parse::<uuid::Uuid>().ok().map(BidderId)
+    }
+}
+
+impl Application for TestApp {
+    type Context = Authorization<Bearer>;
+    type DemandData = ();
+    type PortfolioData = ();
+    type ProductData = ();
+    type Repository = Db;
+    type Solver = ClarabelSolver<DemandId, PortfolioId, ProductId>;
+
+    fn database(&self) -> &Self::Repository {
+        &self.0
+    }
+
+    fn now(&self) -> DateTime {
+        time::OffsetDateTime::now_utc().into()
+    }
+
+    fn solver(&self) -> Self::Solver {
+        ClarabelSolver::default()
+    }
+
+    fn generate_demand_id(&self, _data: &Self::DemandData) -> (DemandId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_portfolio_id(&self, _data: &Self::PortfolioData) -> (PortfolioId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_product_id(&self, _data: &Self::ProductData) -> (ProductId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    async fn can_create_bid(&self, context: &Self::Context) -> Option<BidderId> {
+        self.bidder_id(context)
+    }
+
+    async fn can_query_bid(&self, context: &Self::Context) -> Vec<BidderId> {
+        self.bidder_id(context).into_iter().collect()
+    }
+
+    async fn can_read_bid(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
+        self.bidder_id(context) == Some(bidder_id)
+    }
+
+    async fn can_update_bid(&self, context: &Self::Context, bidder_id: BidderId) -> bool {
+        self.bidder_id(context) == Some(bidder_id)
+    }
+
+    async fn can_view_products(&self, context: &Self::Context) -> bool {
+        self.bidder_id(context).is_some()
+    }
+
+    async fn can_manage_products(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_batch(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_settlement(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_purge_bid(&self, _context: &Self::Context) -> bool {
+        false
+    }
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{BatchRepository as _, ProductRepository as _};
+use fts_grpc::{
+    config::GrpcConfig,
+    proto::{self, flow_trading_client::FlowTradingClient},
+    service,
+};
+use fts_sqlite::{Db, config::SqliteConfig, types::ProductId};
+use std::time::Duration;
+use tokio_stream::{StreamExt as _, wrappers::TcpListenerStream};
+use tonic::{Code, Request, transport::Channel};
+
+fn authorized<T>(bidder_id: &uuid::Uuid, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert(
+        "authorization",
+        format!("Bearer {bidder_id}").parse().unwrap(),
+    );
+    request
+}
+
+fn pwl(points: &[(f64, f64)]) -> Option<proto::DemandCurve> {
+    Some(proto::DemandCurve {
+        curve: Some(proto::demand_curve::Curve::Pwl(proto::PwlCurve {
+            points: points
+                .iter()
+                .map(|&(rate, price)| proto::Point { rate, price })
+                .collect(),
+        })),
+    })
+}
+
+async fn start(app: TestApp) -> anyhow::Result<FlowTradingClient<Channel>> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    let config = GrpcConfig {
+        poll_interval: Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service(app, config))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    Ok(FlowTradingClient::connect(format!("http://{address}")).await?)
+}
+
+#[tokio::test]
+async fn test_grpc_session() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+    let app = TestApp(db.clone());
+    let mut client = start(app).await?;
+
+    let buyer = uuid::Uuid::new_v4();
+    let seller = uuid::Uuid::new_v4();
+
+    let product_id = ProductId(uuid::Uuid::new_v4());
+    db.create_product(product_id, (), now.into()).await?;
+
+    // Calls without a bearer token are rejected
+    let status = client
+        .create_demand(proto::CreateDemandRequest {
+            app_data: "null".to_string(),
+            curve: pwl(&[(0.0, 10.0), (10.0, 0.0)]),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+
+    // Invalid curves are rejected
+    let status = client
+        .create_demand(authorized(
+            &buyer,
+            proto::CreateDemandRequest {
+                app_data: "null".to_string(),
+                curve: pwl(&[(10.0, 0.0), (0.0, 10.0)]),
+            },
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let mut portfolios = Vec::new();
+    for (bidder_id, curve) in [
+        (buyer, pwl(&[(0.0, 10.0), (10.0, 0.0)])),
+        (seller, pwl(&[(-10.0, 10.0), (0.0, 0.0)])),
+    ] {
+        let demand = client
+            .create_demand(authorized(
+                &bidder_id,
+                proto::CreateDemandRequest {
+                    app_data: "null".to_string(),
+                    curve: curve.clone(),
+                },
+            ))
+            .await?
+            .into_inner();
+        assert_eq!(demand.bidder_id, bidder_id.to_string());
+        assert_eq!(demand.curve, curve);
+
+        let portfolio = client
+            .create_portfolio(authorized(
+                &bidder_id,
+                proto::CreatePortfolioRequest {
+                    app_data: "null".to_string(),
+                    demand: [(demand.id.clone(), 1.0)].into(),
+                    basis: [(product_id.0.to_string(), 1.0)].into(),
+                },
+            ))
+            .await?
+            .into_inner();
+        assert_eq!(portfolio.demand[&demand.id], 1.0);
+        portfolios.push(portfolio);
+    }
+
+    // Bidders cannot read each other's portfolios
+    let status = client
+        .get_portfolio(authorized(
+            &seller,
+            proto::GetPortfolioRequest {
+                portfolio_id: portfolios[0].id.clone(),
+                expand: false,
+            },
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+
+    let mut stream = client
+        .watch_product_outcomes(authorized(
+            &buyer,
+            proto::WatchRequest {
+                product_id: product_id.0.to_string(),
+                after: None,
+            },
+        ))
+        .await?
+        .into_inner();
+
+    db.run_batch(
+        time::OffsetDateTime::now_utc().into(),
+        fts_solver::clarabel::ClarabelSolver::default(),
+        (),
+    )
+    .await??;
+
+    // The batch outcome is streamed to the client
+    let outcome = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await?
+        .unwrap()?;
+    let value: serde_json::Value = serde_json::from_str(&outcome.value)?;
+    assert!((value["rate"].as_f64().unwrap() - 5.0).abs() < 1e-3);
+
+    let outcomes = client
+        .get_portfolio_outcomes(authorized(
+            &buyer,
+            proto::OutcomesRequest {
+                id: portfolios[0].id.clone(),
+                before: None,
+                after: None,
+            },
+        ))
+        .await?
+        .into_inner();
+    assert_eq!(outcomes.outcomes.len(), 1);
+    assert_eq!(outcomes.outcomes[0].valid_from, outcome.valid_from);
+    assert!(outcomes.more.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! The errors of the in-memory repository.
+//!
+//! The database backends reject writes violating the constraints of their
+//! schemas, such as a duplicate primary key or a reference to a missing row.
+//! This repository enforces the same constraints, so that an application
+//! tested against it fails where it would against a database.
+
+/// An error of the in-memory repository.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A record with the same key already exists
+    #[error("{entity} {key} already exists")]
+    Duplicate {
+        /// The kind of record, e.g. `product`
+        entity: &'static str,
+        /// The key of the record
+        key: String,
+    },
+
+    /// A record references another which does not exist
+    #[error("{entity} {key} does not exist")]
+    Missing {
+        /// The kind of the referenced record, e.g. `demand`
+        entity: &'static str,
+        /// The key of the referenced record
+        key: String,
+    },
+
+    /// A filter's path into the application data could not be parsed
+    #[error("{0} is not a supported JSON path")]
+    Path(String),
+
+    /// Application data or outcomes could not be (de)serialized
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl Error {
+    pub(crate) fn duplicate(entity: &'static str, key: impl std::fmt::Display) -> Self {
+        Self::Duplicate {
+            entity,
+            key: key.to_string(),
+        }
+    }
+
+    pub(crate) fn missing(entity: &'static str, key: impl std::fmt::Display) -> Self {
+        Self::Missing {
+            entity,
+            key: key.to_string(),
+        }
+    }
+}
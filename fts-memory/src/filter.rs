@@ -0,0 +1,104 @@
+//! Filtering of records by their application data.
+//!
+//! Products, demands, and portfolios may be searched by JSON paths into their
+//! application data, each of which is compared against a value. Only the
+//! member (`.name` or `."name"`) and index (`[0]`) accessors of JSON paths are
+//! supported.
+
+use crate::Error;
+use fts_core::models::{AppDataFilter, Comparison, FilterValue};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A step of a JSON path
+enum Step {
+    Member(String),
+    Index(usize),
+}
+
+/// Parse a JSON path, e.g. `$.window.start` or `$.tags[0]`
+fn parse(path: &str) -> Result<Vec<Step>, Error> {
+    let invalid = || Error::Path(path.to_owned());
+
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix(".\"") {
+            // A quoted member, within which quotes and backslashes are escaped
+            let mut name = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next().ok_or_else(invalid)? {
+                    (end, '"') => break end,
+                    (_, '\\') => name.push(chars.next().ok_or_else(invalid)?.1),
+                    (_, c) => name.push(c),
+                }
+            };
+            steps.push(Step::Member(name));
+            rest = &quoted[end + 1..];
+        } else if let Some(member) = rest.strip_prefix('.') {
+            let end = member.find(['.', '[']).unwrap_or(member.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            steps.push(Step::Member(member[..end].to_owned()));
+            rest = &member[end..];
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let end = index.find(']').ok_or_else(invalid)?;
+            steps.push(Step::Index(index[..end].parse().map_err(|_| invalid())?));
+            rest = &index[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(steps)
+}
+
+/// Filters whose paths have been parsed, to be applied to many records
+pub(crate) struct Filters<'a>(Vec<(Vec<Step>, &'a AppDataFilter)>);
+
+impl<'a> Filters<'a> {
+    /// Parse the path of every filter, failing if any is unsupported
+    pub(crate) fn new(filters: &'a [AppDataFilter]) -> Result<Self, Error> {
+        filters
+            .iter()
+            .map(|filter| Ok((parse(&filter.path)?, filter)))
+            .collect::<Result<_, Error>>()
+            .map(Self)
+    }
+
+    /// Whether `app_data` satisfies every filter.
+    pub(crate) fn matches(&self, app_data: &Value) -> bool {
+        self.0.iter().all(|(steps, filter)| {
+            let mut field = Some(app_data);
+            for step in steps {
+                field = match step {
+                    Step::Member(name) => field.and_then(|value| value.get(name.as_str())),
+                    Step::Index(index) => field.and_then(|value| value.get(*index)),
+                };
+            }
+
+            // As null never compares true in SQL, absent fields (and fields of
+            // another type than the value) never satisfy a filter
+            let ordering = match (field, &filter.value) {
+                (Some(Value::Bool(field)), FilterValue::Bool(value)) => Some(field.cmp(value)),
+                (Some(Value::Number(field)), FilterValue::Number(value)) => {
+                    field.as_f64().and_then(|field| field.partial_cmp(value))
+                }
+                (Some(Value::String(field)), FilterValue::String(value)) => {
+                    Some(field.as_str().cmp(value))
+                }
+                _ => None,
+            };
+
+            ordering.is_some_and(|ordering| match filter.op {
+                Comparison::Eq => ordering == Ordering::Equal,
+                Comparison::Ne => ordering != Ordering::Equal,
+                Comparison::Lt => ordering == Ordering::Less,
+                Comparison::Le => ordering != Ordering::Greater,
+                Comparison::Gt => ordering == Ordering::Greater,
+                Comparison::Ge => ordering != Ordering::Less,
+            })
+        })
+    }
+}
@@ -0,0 +1,41 @@
+//! Repository trait implementations for the in-memory repository.
+//!
+//! This module contains the implementations of all repository traits defined in
+//! `fts-core`, each of which reads or writes the tables of `State` under a
+//! single lock.
+
+use crate::{
+    Db, Error,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::ports::Repository;
+
+mod batch;
+mod demand;
+mod health;
+mod import;
+mod outbox;
+mod portfolio;
+mod product;
+mod retention;
+mod settlement;
+
+impl Repository for Db {
+    type Error = Error;
+    type DateTime = DateTime;
+    type BidderId = BidderId;
+    type ProductId = ProductId;
+    type DemandId = DemandId;
+    type PortfolioId = PortfolioId;
+}
+
+/// Split off the extra result of a query for `limit + 1` results, returning
+/// whether there was one
+pub(crate) fn paginate<T>(rows: &mut Vec<T>, limit: usize) -> bool {
+    if rows.len() > limit {
+        rows.truncate(limit);
+        true
+    } else {
+        false
+    }
+}
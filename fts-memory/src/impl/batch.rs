@@ -0,0 +1,761 @@
+use super::paginate;
+use crate::{
+    Db, Error,
+    state::{OutcomeRow, State, coalesce_min, in_range, valid_at},
+    types::{DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{
+        Auction, BatchAttempt, BatchError, BatchFailure, BatchMetadata, BatchPreview, BatchRun,
+        BidderOutcome, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, Map, MarketEvent,
+        MarketStatistics, PriceInterval, PriceSummary, ProductStatistics, ValueRecord,
+    },
+    models::{Basis, Weights},
+    ports::{BatchRepository, Solver},
+};
+use futures_util::Stream;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The inputs to a batch auction, along with the time the first of them expires
+type BatchInputs = (
+    Map<DemandId, DemandCurve>,
+    Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+    Option<DateTime>,
+);
+
+/// Marks a batch auction as executing until dropped
+struct BatchGuard<'a>(&'a AtomicBool);
+
+impl<'a> BatchGuard<'a> {
+    /// Mark a batch as executing, unless one already is
+    fn acquire(running: &'a AtomicBool) -> Option<Self> {
+        running
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| Self(running))
+    }
+}
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// A numeric field of an outcome, as `(value ->> field)::double precision`
+fn field(value: &Value, field: &str) -> Option<f64> {
+    value.get(field).and_then(Value::as_f64)
+}
+
+/// The outcomes of an entity within a range, most recent first
+fn outcomes<Id: PartialEq, Outcome: serde::de::DeserializeOwned>(
+    rows: &[OutcomeRow<Id>],
+    id: Id,
+    query: &DateTimeRangeQuery<DateTime>,
+) -> Result<Vec<ValueRecord<DateTime, Outcome>>, Error> {
+    let mut rows = rows
+        .iter()
+        .filter(|row| row.id == id && in_range(row.valid_from, query.after, query.before))
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|row| std::cmp::Reverse(row.valid_from));
+    rows.into_iter()
+        .map(|row| {
+            Ok(ValueRecord {
+                valid_from: row.valid_from,
+                valid_until: row.valid_until,
+                value: serde_json::from_value(row.value.clone())?,
+                actor: None,
+            })
+        })
+        .collect()
+}
+
+/// Supersede the outcomes of the batch as of `previous` with those of the
+/// batch at `timestamp`
+fn replace_outcomes<Id: Copy + PartialEq + std::fmt::Display>(
+    rows: &mut Vec<OutcomeRow<Id>>,
+    outcomes: Vec<(Id, Value)>,
+    previous: DateTime,
+    timestamp: DateTime,
+) -> Result<(), Error> {
+    for row in rows.iter_mut().filter(|row| row.valid_from == previous) {
+        row.valid_until = Some(timestamp);
+    }
+    for (id, value) in outcomes {
+        // The outcomes are keyed by their start, as in the databases
+        if rows
+            .iter()
+            .any(|row| row.id == id && row.valid_from == timestamp)
+        {
+            return Err(Error::duplicate(
+                "outcome",
+                format!("{id} as of {timestamp}"),
+            ));
+        }
+        rows.push(OutcomeRow {
+            id,
+            value,
+            valid_from: timestamp,
+            valid_until: None,
+        });
+    }
+    Ok(())
+}
+
+/// The outcomes of a solve, serialized for storage
+type SerializedOutcomes = (Vec<(PortfolioId, Value)>, Vec<(ProductId, Value)>);
+
+fn serialize_outcomes<PortfolioOutcome: serde::Serialize, ProductOutcome: serde::Serialize>(
+    portfolios: Map<PortfolioId, PortfolioOutcome>,
+    products: Map<ProductId, ProductOutcome>,
+) -> Result<SerializedOutcomes, Error> {
+    Ok((
+        portfolios
+            .into_iter()
+            .map(|(id, outcome)| Ok((id, serde_json::to_value(outcome)?)))
+            .collect::<Result<_, Error>>()?,
+        products
+            .into_iter()
+            .map(|(id, outcome)| Ok((id, serde_json::to_value(outcome)?)))
+            .collect::<Result<_, Error>>()?,
+    ))
+}
+
+impl State {
+    /// Gather the demands and portfolios active at `timestamp`
+    fn gather_batch(&self, timestamp: DateTime) -> BatchInputs {
+        let mut expires = None;
+
+        // A demand is active if it has a curve and belongs to a portfolio
+        let mut demand_groups: BTreeMap<DemandId, Option<DateTime>> = BTreeMap::new();
+        for row in self
+            .portfolio_demand
+            .iter()
+            .filter(|row| valid_at(row.valid_from, row.valid_until, timestamp))
+        {
+            demand_groups
+                .entry(row.id)
+                .and_modify(|until| *until = coalesce_min(*until, row.valid_until))
+                .or_insert(row.valid_until);
+        }
+        let mut curves: BTreeMap<DemandId, (&DemandCurve, Option<DateTime>)> = BTreeMap::new();
+        for row in self.curve_data.iter().filter(|row| {
+            !matches!(row.value, DemandCurve::None)
+                && valid_at(row.valid_from, row.valid_until, timestamp)
+        }) {
+            curves.insert(row.demand_id, (&row.value, row.valid_until));
+        }
+        let demands = demand_groups
+            .into_iter()
+            .filter_map(|(demand_id, until)| {
+                let (curve, curve_until) = curves.get(&demand_id)?;
+                expires = coalesce_min(expires, coalesce_min(until, *curve_until));
+                Some((demand_id, (*curve).clone()))
+            })
+            .collect();
+
+        // A portfolio is active if it has both demands and products
+        let mut portfolio_demands: BTreeMap<PortfolioId, (Weights<DemandId>, Option<DateTime>)> =
+            BTreeMap::new();
+        for row in self
+            .portfolio_demand
+            .iter()
+            .filter(|row| valid_at(row.valid_from, row.valid_until, timestamp))
+        {
+            let (weights, until) = portfolio_demands
+                .entry(row.portfolio_id)
+                .or_insert_with(|| (Weights::default(), row.valid_until));
+            weights.insert(row.id, row.weight);
+            *until = coalesce_min(*until, row.valid_until);
+        }
+        let mut portfolio_bases: BTreeMap<PortfolioId, (Basis<ProductId>, Option<DateTime>)> =
+            BTreeMap::new();
+        for row in self
+            .basis_view()
+            .filter(|row| valid_at(row.valid_from, row.valid_until, timestamp))
+        {
+            let (basis, until) = portfolio_bases
+                .entry(row.portfolio_id)
+                .or_insert_with(|| (Basis::default(), row.valid_until));
+            basis.insert(row.product_id, row.weight);
+            *until = coalesce_min(*until, row.valid_until);
+        }
+        let portfolios = portfolio_demands
+            .into_iter()
+            .filter_map(|(portfolio_id, (demand, demand_until))| {
+                let (basis, basis_until) = portfolio_bases.remove(&portfolio_id)?;
+                expires = coalesce_min(expires, coalesce_min(demand_until, basis_until));
+                Some((portfolio_id, (demand, basis)))
+            })
+            .collect();
+
+        (demands, portfolios, expires)
+    }
+
+    /// Record the outcomes of the batch at `timestamp`, superseding those of
+    /// the previous batch
+    fn record_batch(
+        &mut self,
+        timestamp: DateTime,
+        (portfolios, products): SerializedOutcomes,
+    ) -> Result<(), Error> {
+        let previous = self.batch.as_of;
+        self.batch.as_of = timestamp;
+        self.batch.portfolios = portfolios.len() as u32;
+        self.batch.products = products.len() as u32;
+        replace_outcomes(
+            &mut self.portfolio_outcomes,
+            portfolios,
+            previous,
+            timestamp,
+        )?;
+        replace_outcomes(&mut self.product_outcomes, products, previous, timestamp)?;
+        self.record(timestamp, MarketEvent::BatchExecuted)
+    }
+}
+
+impl Db {
+    /// Execute a batch auction at `timestamp`, recording its outcomes and
+    /// how it was solved
+    async fn execute_batch<T>(
+        &self,
+        timestamp: DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<DateTime>, T::State), T::Error>, Error>
+    where
+        T: Solver<DemandId, PortfolioId, ProductId>,
+        T::PortfolioOutcome: serde::Serialize,
+        T::ProductOutcome: serde::Serialize,
+    {
+        // The lock is released while solving, as a database connection is
+        let (demands, portfolios, expires) = self.lock().gather_batch(timestamp);
+        let inputs = if self.record_batch_inputs {
+            Some(serde_json::to_string(&Auction::<Self>::from_parts(
+                demands.clone(),
+                portfolios.clone(),
+            ))?)
+        } else {
+            None
+        };
+
+        let (outcome, telemetry) = solver
+            .solve_with_telemetry(demands, portfolios, state)
+            .await;
+
+        let (result, outcomes, error) = match outcome {
+            Ok((portfolios, products)) => (
+                Ok((expires, solver.next_state(&portfolios))),
+                Some(serialize_outcomes(portfolios, products)?),
+                None,
+            ),
+            Err(error) => {
+                let message = error.to_string();
+                (Err(error), None, Some(message))
+            }
+        };
+
+        // However the solve ended, it is recorded alongside any outcomes
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            if let Some(outcomes) = outcomes {
+                state.record_batch(timestamp, outcomes)?;
+            }
+            state.batch_runs.insert(
+                timestamp,
+                BatchRun {
+                    solver: telemetry,
+                    error,
+                },
+            );
+            if let Some(inputs) = inputs {
+                state.batch_inputs.insert(timestamp, inputs);
+            }
+            Ok(())
+        })?;
+
+        Ok(result)
+    }
+}
+
+impl<T: Solver<DemandId, PortfolioId, ProductId>> BatchRepository<T> for Db
+where
+    T: Send,
+    T::Error: Send,
+    T::State: Send,
+    T::PortfolioOutcome: Send + serde::Serialize + serde::de::DeserializeOwned,
+    T::ProductOutcome: Send + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn run_batch_with_state(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<Self::DateTime>, T::State), BatchError<T::Error>>, Self::Error> {
+        let Some(_guard) = BatchGuard::acquire(&self.batch_running) else {
+            return Ok(Err(BatchError::InProgress));
+        };
+
+        let result = self.execute_batch(timestamp, solver, state).await;
+
+        let failure = match &result {
+            Ok(Ok(_)) => None,
+            Ok(Err(error)) => Some((BatchFailure::Solver, error.to_string())),
+            Err(error) => Some((BatchFailure::Database, error.to_string())),
+        };
+        if let Some((category, message)) = failure {
+            self.lock()
+                .batch_attempts
+                .insert(timestamp, BatchAttempt { category, message });
+        }
+
+        result.map(|outcome| outcome.map_err(BatchError::Solver))
+    }
+
+    async fn preview_batch(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<
+        Result<BatchPreview<Self, T::PortfolioOutcome, T::ProductOutcome>, T::Error>,
+        Self::Error,
+    > {
+        let (demands, portfolios, expires) = self.lock().gather_batch(timestamp);
+
+        Ok(solver
+            .solve(demands, portfolios, state)
+            .await
+            .map(|(portfolios, products)| BatchPreview {
+                as_of: timestamp,
+                expires,
+                portfolios,
+                products,
+            }))
+    }
+
+    async fn get_latest_batch(&self) -> Result<Option<BatchMetadata<Self>>, Self::Error> {
+        // As the batch table of the databases, the batch is initialized
+        // without any outcomes and overwritten by each batch auction
+        let state = self.lock();
+        Ok(Some(BatchMetadata {
+            as_of: state.batch.as_of,
+            portfolios: state.batch.portfolios,
+            products: state.batch.products,
+        }))
+    }
+
+    async fn get_batch_runs(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchRun, Self::DateTime>, Self::Error> {
+        let state = self.lock();
+        let mut rows = state
+            .batch_runs
+            .iter()
+            .rev()
+            .filter(|(as_of, _)| in_range(**as_of, query.after, query.before))
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The upper bound is exclusive, so the next page begins with the
+        // extra row, just below the oldest row of this page.
+        let more = paginate(&mut rows, limit).then(|| DateTimeRangeQuery {
+            before: rows.last().map(|(as_of, _)| **as_of),
+            after: query.after,
+        });
+
+        Ok(DateTimeRangeResponse {
+            results: rows
+                .into_iter()
+                .map(|(as_of, run)| ValueRecord {
+                    valid_from: *as_of,
+                    valid_until: None,
+                    value: run.clone(),
+                    actor: None,
+                })
+                .collect(),
+            more,
+        })
+    }
+
+    async fn get_batch_attempts(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchAttempt, Self::DateTime>, Self::Error> {
+        let state = self.lock();
+        let mut rows = state
+            .batch_attempts
+            .iter()
+            .rev()
+            .filter(|(as_of, _)| in_range(**as_of, query.after, query.before))
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The upper bound is exclusive, so the next page begins with the
+        // extra row, just below the oldest row of this page.
+        let more = paginate(&mut rows, limit).then(|| DateTimeRangeQuery {
+            before: rows.last().map(|(as_of, _)| **as_of),
+            after: query.after,
+        });
+
+        Ok(DateTimeRangeResponse {
+            results: rows
+                .into_iter()
+                .map(|(as_of, attempt)| ValueRecord {
+                    valid_from: *as_of,
+                    valid_until: None,
+                    value: attempt.clone(),
+                    actor: None,
+                })
+                .collect(),
+            more,
+        })
+    }
+
+    async fn get_batch_inputs(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Auction<Self>>, Self::Error> {
+        self.lock()
+            .batch_inputs
+            .get(&as_of)
+            .map(|inputs| serde_json::from_str(inputs))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Get the portfolio's outcomes
+    ///
+    /// This returns a list of outcomes, each corresponding to a specific point in time.
+    /// The records are ordered by `valid_from` in descending order.
+    async fn get_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<T::PortfolioOutcome, Self::DateTime>, Self::Error> {
+        let mut results = outcomes(&self.lock().portfolio_outcomes, portfolio_id, &query)?;
+
+        // The upper bound is exclusive, so the next page begins with the
+        // extra row, just below the oldest row of this page.
+        let more = paginate(&mut results, limit).then(|| DateTimeRangeQuery {
+            before: results.last().map(|row| row.valid_from),
+            after: query.after,
+        });
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    async fn get_bidder_outcomes(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BidderOutcome<Self::ProductId>, Self::DateTime>, Self::Error>
+    {
+        let state = self.lock();
+
+        // The outcomes are expressed in terms of the contemporary product
+        // basis of the batch that produced them
+        let mut batches: BTreeMap<DateTime, ValueRecord<DateTime, BidderOutcome<ProductId>>> =
+            BTreeMap::new();
+        for row in state.portfolio_outcomes.iter().filter(|row| {
+            state.portfolios[&row.id].bidder_id == bidder_id
+                && in_range(row.valid_from, query.after, query.before)
+        }) {
+            let record = batches
+                .entry(row.valid_from)
+                .or_insert_with(|| ValueRecord {
+                    valid_from: row.valid_from,
+                    valid_until: row.valid_until,
+                    value: BidderOutcome::default(),
+                    actor: None,
+                });
+            let rate = field(&row.value, "rate").unwrap_or(0.0);
+            for basis in state.basis_view().filter(|basis| {
+                basis.portfolio_id == row.id
+                    && valid_at(basis.valid_from, basis.valid_until, row.valid_from)
+            }) {
+                let price = state
+                    .product_outcomes
+                    .iter()
+                    .find(|outcome| {
+                        outcome.id == basis.product_id && outcome.valid_from == row.valid_from
+                    })
+                    .and_then(|outcome| field(&outcome.value, "price"))
+                    .unwrap_or(0.0);
+                let trade = record.value.trades.entry(basis.product_id).or_default();
+                trade.rate += rate * basis.weight;
+                trade.payment += rate * basis.weight * price;
+                record.value.payment += rate * basis.weight * price;
+            }
+        }
+
+        let mut results: Vec<_> = batches
+            .into_values()
+            .rev()
+            .map(|mut record| {
+                record.value.trades.sort_keys();
+                record
+            })
+            .collect();
+
+        // The upper bound is exclusive, so the next page begins with the
+        // extra batch, just below the oldest batch of this page.
+        let more = paginate(&mut results, limit).then(|| DateTimeRangeQuery {
+            before: results.last().map(|record| record.valid_from),
+            after: query.after,
+        });
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    /// Get the product's outcomes
+    ///
+    /// This returns a list of outcomes, each corresponding to a specific point in time.
+    /// The records are ordered by `valid_from` in descending order.
+    async fn get_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<T::ProductOutcome, Self::DateTime>, Self::Error> {
+        let mut results = outcomes(&self.lock().product_outcomes, product_id, &query)?;
+
+        // The upper bound is exclusive, so the next page begins with the
+        // extra row, just below the oldest row of this page.
+        let more = paginate(&mut results, limit).then(|| DateTimeRangeQuery {
+            before: results.last().map(|row| row.valid_from),
+            after: query.after,
+        });
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    fn stream_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::PortfolioOutcome>, Error>> + Send {
+        // The outcomes are read upfront, rather than holding the lock while
+        // the stream is consumed
+        let records = outcomes(&self.lock().portfolio_outcomes, portfolio_id, &query);
+        futures_util::stream::iter(match records {
+            Ok(records) => records.into_iter().map(Ok).collect(),
+            Err(error) => vec![Err(error)],
+        })
+    }
+
+    fn stream_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::ProductOutcome>, Error>> + Send {
+        // The outcomes are read upfront, rather than holding the lock while
+        // the stream is consumed
+        let records = outcomes(&self.lock().product_outcomes, product_id, &query);
+        futures_util::stream::iter(match records {
+            Ok(records) => records.into_iter().map(Ok).collect(),
+            Err(error) => vec![Err(error)],
+        })
+    }
+
+    async fn get_product_summary(
+        &self,
+        product_id: Self::ProductId,
+        interval: PriceInterval,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<PriceSummary, Self::DateTime>, Self::Error> {
+        let (day, span) = match interval {
+            PriceInterval::Hour => (false, time::Duration::HOUR),
+            PriceInterval::Day => (true, time::Duration::DAY),
+        };
+
+        // Bucket the outcomes by their start, in the order they cleared.
+        // Outcomes still in effect are considered to end at `as_of`.
+        let state = self.lock();
+        let mut rows = state
+            .product_outcomes
+            .iter()
+            .filter(|row| {
+                row.id == product_id && in_range(row.valid_from, query.after, query.before)
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|row| row.valid_from);
+
+        let mut buckets: BTreeMap<DateTime, Vec<(Option<f64>, f64, f64)>> = BTreeMap::new();
+        for row in rows {
+            let price = field(&row.value, "price");
+            let rate = field(&row.value, "rate").unwrap_or(0.0);
+            let duration = row
+                .valid_from
+                .seconds_until(row.valid_until.unwrap_or(as_of))
+                .max(0.0);
+            buckets
+                .entry(row.valid_from.truncate(day))
+                .or_default()
+                .push((price, rate, duration));
+        }
+
+        let mut results = buckets
+            .into_iter()
+            .rev()
+            .take(limit + 1)
+            .map(|(bucket, batches)| {
+                let prices = batches
+                    .iter()
+                    .filter_map(|(price, _, duration)| price.map(|price| (price, *duration)));
+                let priced = prices.clone().map(|(_, duration)| duration).sum::<f64>();
+                ValueRecord {
+                    valid_from: bucket,
+                    valid_until: Some(bucket.add(span)),
+                    value: PriceSummary {
+                        open: batches.first().and_then(|(price, _, _)| *price),
+                        high: prices.clone().map(|(price, _)| price).reduce(f64::max),
+                        low: prices.clone().map(|(price, _)| price).reduce(f64::min),
+                        close: batches.last().and_then(|(price, _, _)| *price),
+                        twap: (priced != 0.0).then(|| {
+                            prices
+                                .map(|(price, duration)| price * duration)
+                                .sum::<f64>()
+                                / priced
+                        }),
+                        volume: batches
+                            .iter()
+                            .map(|(_, rate, duration)| rate * duration)
+                            .sum::<f64>()
+                            / time_unit,
+                        batches: batches.len() as u32,
+                    },
+                    actor: None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Batches are bucketed by their start, so we page on the end of the
+        // extra bucket to ensure all of its batches are in the next page.
+        let more = if results.len() == limit + 1 {
+            let extra = results.pop().unwrap();
+            Some(DateTimeRangeQuery {
+                before: extra.valid_until,
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    async fn get_market_statistics(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<MarketStatistics<Self>, Self::Error> {
+        let until = query.before.map_or(as_of, |before| before.min(as_of));
+        let state = self.lock();
+
+        let mut products: Map<ProductId, ProductStatistics<DateTime>> = Map::default();
+
+        // The volume traded over [after, until), where outcomes still in
+        // effect are considered to end at `until`
+        let mut volumes: BTreeMap<ProductId, f64> = BTreeMap::new();
+        for row in state.product_outcomes.iter().filter(|row| {
+            row.valid_from < until
+                && (query.after.is_none()
+                    || row
+                        .valid_until
+                        .is_none_or(|valid_until| query.after.unwrap() < valid_until))
+        }) {
+            let start = query
+                .after
+                .map_or(row.valid_from, |after| row.valid_from.max(after));
+            let end = row.valid_until.unwrap_or(until).min(until);
+            let rate = field(&row.value, "rate").unwrap_or(0.0);
+            *volumes.entry(row.id).or_default() +=
+                rate * start.seconds_until(end).max(0.0) / time_unit;
+        }
+
+        // The most recent price as of `as_of`
+        let mut prices: BTreeMap<ProductId, (DateTime, f64)> = BTreeMap::new();
+        for row in state
+            .product_outcomes
+            .iter()
+            .filter(|row| row.valid_from <= as_of)
+        {
+            if let Some(price) = field(&row.value, "price") {
+                let latest = prices.entry(row.id).or_insert((row.valid_from, price));
+                if row.valid_from >= latest.0 {
+                    *latest = (row.valid_from, price);
+                }
+            }
+        }
+
+        let product_ids = volumes
+            .keys()
+            .chain(prices.keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+        for product_id in product_ids {
+            let entry = products.entry(product_id).or_default();
+            if let Some((cleared, price)) = prices.get(&product_id) {
+                entry.price = Some(*price);
+                entry.cleared = Some(*cleared);
+            }
+            entry.volume = volumes.get(&product_id).copied().unwrap_or(0.0);
+        }
+
+        // The active portfolios whose (expanded) basis includes each product,
+        // along with the active demands associated to them
+        let mut bids: BTreeMap<ProductId, (BTreeSet<PortfolioId>, BTreeSet<DemandId>)> =
+            BTreeMap::new();
+        for row in state
+            .basis_view()
+            .filter(|row| valid_at(row.valid_from, row.valid_until, as_of))
+        {
+            let mut demands = state
+                .portfolio_demand
+                .iter()
+                .filter(|group| {
+                    group.portfolio_id == row.portfolio_id
+                        && valid_at(group.valid_from, group.valid_until, as_of)
+                })
+                .peekable();
+            if demands.peek().is_none() {
+                continue;
+            }
+
+            let (portfolios, active) = bids.entry(row.product_id).or_default();
+            portfolios.insert(row.portfolio_id);
+            active.extend(demands.map(|group| group.id).filter(|demand_id| {
+                state.curve_data.iter().any(|curve| {
+                    curve.demand_id == *demand_id
+                        && !matches!(curve.value, DemandCurve::None)
+                        && valid_at(curve.valid_from, curve.valid_until, as_of)
+                })
+            }));
+        }
+        for (product_id, (portfolios, demands)) in bids {
+            let entry = products.entry(product_id).or_default();
+            entry.portfolios = portfolios.len() as u32;
+            entry.demands = demands.len() as u32;
+        }
+
+        for ((_, product_id), position) in state.net_positions(as_of, time_unit) {
+            if position > 0.0 {
+                products.entry(product_id).or_default().open_interest += position;
+            }
+        }
+
+        Ok(MarketStatistics { as_of, products })
+    }
+}
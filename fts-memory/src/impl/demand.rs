@@ -0,0 +1,372 @@
+use super::paginate;
+use crate::{
+    Db, Error,
+    filter::Filters,
+    state::{Demand, State, coalesce_min, in_range, valid_at},
+    types::{DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{
+        Actor, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandQuery,
+        DemandQueryResponse, DemandRecord, DemandSearch, DemandSearchResponse, Map, MarketEvent,
+        Sum, Tombstone, ValueRecord,
+    },
+    ports::DemandRepository,
+};
+use futures_util::Stream;
+
+impl State {
+    /// The record of a demand as of its latest change, as returned by writes
+    /// and queries of the current state
+    fn demand_record<DemandData: serde::de::DeserializeOwned>(
+        demand_id: DemandId,
+        demand: &Demand,
+    ) -> Result<DemandRecord<Db, DemandData>, Error> {
+        Ok(DemandRecord {
+            id: demand_id,
+            valid_from: demand.as_of,
+            valid_until: None,
+            bidder_id: demand.bidder_id,
+            app_data: serde_json::from_value(demand.app_data.clone())?,
+            curve_data: demand.curve_data.clone(),
+            portfolios: Sum::default(),
+        })
+    }
+
+    /// Whether the demand belongs to a portfolio whose basis currently
+    /// references the product
+    fn demand_references(&self, demand_id: DemandId, product_id: ProductId) -> bool {
+        self.portfolio_demand
+            .iter()
+            .filter(|row| row.id == demand_id && row.valid_until.is_none())
+            .any(|row| {
+                self.portfolio_product.iter().any(|other| {
+                    other.portfolio_id == row.portfolio_id
+                        && other.id == product_id
+                        && other.valid_until.is_none()
+                })
+            })
+    }
+
+    /// The (unpurged) demands of any of the bidders with a curve
+    fn active_demands_of<'a>(
+        &'a self,
+        bidder_ids: &'a [crate::types::BidderId],
+    ) -> impl Iterator<Item = (&'a DemandId, &'a Demand)> {
+        self.demands.iter().filter(|(_, demand)| {
+            bidder_ids.contains(&demand.bidder_id)
+                && !matches!(demand.curve_data, DemandCurve::None)
+        })
+    }
+}
+
+impl<DemandData: Send + serde::Serialize + serde::de::DeserializeOwned> DemandRepository<DemandData>
+    for Db
+{
+    async fn get_demand_bidder_id(
+        &self,
+        demand_id: Self::DemandId,
+    ) -> Result<Option<Self::BidderId>, Self::Error> {
+        let state = self.lock();
+        Ok(state
+            .demands
+            .get(&demand_id)
+            .filter(|demand| demand.purged_at.is_none())
+            .map(|demand| demand.bidder_id))
+    }
+
+    async fn get_demand_bidder_ids(
+        &self,
+        demand_ids: &[Self::DemandId],
+    ) -> Result<Map<Self::DemandId, Self::BidderId>, Self::Error> {
+        let state = self.lock();
+        Ok(demand_ids
+            .iter()
+            .filter_map(|demand_id| {
+                state
+                    .demands
+                    .get(demand_id)
+                    .filter(|demand| demand.purged_at.is_none())
+                    .map(|demand| (*demand_id, demand.bidder_id))
+            })
+            .collect())
+    }
+
+    async fn query_demand(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandQuery<Self::DemandId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<DemandQueryResponse<Self, DemandData>, Self::Error> {
+        let state = self.lock();
+
+        let mut rows = state
+            .demands
+            .iter()
+            .filter(|(demand_id, demand)| {
+                bidder_ids.contains(&demand.bidder_id)
+                    && demand.purged_at.is_none()
+                    && !(query.has_curve && matches!(demand.curve_data, DemandCurve::None))
+                    && query
+                        .touched_since
+                        .is_none_or(|touched_since| demand.as_of >= touched_since)
+                    && query.after.is_none_or(|after| **demand_id > after)
+            })
+            // +1 to check if there are more results
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut rows, limit).then(|| DemandQuery {
+            has_curve: query.has_curve,
+            include_curve: query.include_curve,
+            touched_since: query.touched_since,
+            after: rows.last().map(|(demand_id, _)| **demand_id),
+        });
+
+        Ok(DemandQueryResponse {
+            results: rows
+                .into_iter()
+                .map(|(&demand_id, demand)| {
+                    let mut record = State::demand_record(demand_id, demand)?;
+                    if !query.include_curve {
+                        record.curve_data = DemandCurve::None;
+                    }
+                    Ok(record)
+                })
+                .collect::<Result<_, Error>>()?,
+            more,
+        })
+    }
+
+    async fn query_demand_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
+    ) -> Result<Vec<DemandRecord<Self, DemandData>>, Self::Error> {
+        let state = self.lock();
+        state
+            .active_demands_of(bidder_ids)
+            .filter(|(demand_id, _)| state.demand_references(**demand_id, product_id))
+            .map(|(&demand_id, demand)| State::demand_record(demand_id, demand))
+            .collect()
+    }
+
+    async fn create_demand(
+        &self,
+        demand_id: Self::DemandId,
+        bidder_id: Self::BidderId,
+        app_data: DemandData,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<DemandRecord<Self, DemandData>, Self::Error> {
+        let app_data = serde_json::to_value(app_data)?;
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            state.insert_demand(demand_id, bidder_id, app_data, curve_data, actor, as_of)?;
+            State::demand_record(demand_id, &state.demands[&demand_id])
+        })
+    }
+
+    async fn update_demand(
+        &self,
+        demand_id: Self::DemandId,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            state
+                .update_demand(demand_id, curve_data, actor, as_of)?
+                .map(|demand| State::demand_record(demand_id, demand))
+                .transpose()
+        })
+    }
+
+    async fn get_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
+        let state = self.lock();
+        let Some(demand) = state
+            .demands
+            .get(&demand_id)
+            .filter(|demand| demand.purged_at.is_none())
+        else {
+            return Ok(None);
+        };
+
+        let curve = state.curve_data.iter().find(|row| {
+            row.demand_id == demand_id && valid_at(row.valid_from, row.valid_until, as_of)
+        });
+
+        let mut portfolios = Sum::<PortfolioId>::default();
+        let mut group_from: Option<DateTime> = None;
+        let mut group_until: Option<DateTime> = None;
+        for row in state
+            .portfolio_demand
+            .iter()
+            .filter(|row| row.id == demand_id && valid_at(row.valid_from, row.valid_until, as_of))
+        {
+            portfolios.insert(row.portfolio_id, row.weight);
+            group_from = group_from.max(Some(row.valid_from));
+            group_until = coalesce_min(group_until, row.valid_until);
+        }
+
+        // The record is valid for the intersection of the lifetimes of the
+        // curve and the portfolios
+        let valid_from = curve.map(|row| row.valid_from).max(group_from);
+        let valid_until = coalesce_min(curve.and_then(|row| row.valid_until), group_until);
+
+        Ok(Some(DemandRecord {
+            id: demand_id,
+            valid_from: valid_from.unwrap_or(demand.as_of),
+            valid_until,
+            bidder_id: demand.bidder_id,
+            app_data: serde_json::from_value(demand.app_data.clone())?,
+            curve_data: curve.map(|row| row.value.clone()).unwrap_or_default(),
+            portfolios,
+        }))
+    }
+
+    async fn search_demands(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandSearch<Self::DemandId>,
+        limit: usize,
+    ) -> Result<DemandSearchResponse<Self, DemandData>, Self::Error> {
+        let filters = Filters::new(&query.filters)?;
+        let state = self.lock();
+
+        let mut rows = state
+            .active_demands_of(bidder_ids)
+            .filter(|(demand_id, demand)| {
+                query.after.is_none_or(|after| **demand_id > after)
+                    && filters.matches(&demand.app_data)
+            })
+            // +1 to check if there are more results
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut rows, limit).then(|| DemandSearch {
+            after: rows.last().map(|(demand_id, _)| **demand_id),
+            filters: query.filters.clone(),
+        });
+
+        Ok(DemandSearchResponse {
+            results: rows
+                .into_iter()
+                .map(|(&demand_id, demand)| State::demand_record(demand_id, demand))
+                .collect::<Result<_, _>>()?,
+            more,
+        })
+    }
+
+    async fn get_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<DemandCurve, Self::DateTime>, Self::Error> {
+        Ok(curve_history(&self.lock(), demand_id, query, limit))
+    }
+
+    fn stream_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, DemandCurve>, Error>> + Send {
+        // The history is read upfront, rather than holding the lock while the
+        // stream is consumed
+        let history = curve_history(&self.lock(), demand_id, query, usize::MAX);
+        futures_util::stream::iter(history.results.into_iter().map(Ok))
+    }
+
+    async fn purge_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::DemandId>>, Self::Error> {
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            // Erase the data of the demand itself, leaving the row as a
+            // tombstone, along with the whole of its curve history and its
+            // curve in the recorded inputs of past batches
+            let Some(demand) = state
+                .demands
+                .get_mut(&demand_id)
+                .filter(|demand| demand.purged_at.is_none())
+            else {
+                return Ok(None);
+            };
+            demand.as_of = as_of;
+            demand.app_data = serde_json::Value::Null;
+            demand.curve_data = DemandCurve::None;
+            demand.purged_at = Some(as_of);
+            let bidder_id = demand.bidder_id;
+
+            state.curve_data.retain(|row| row.demand_id != demand_id);
+            state.redact_batch_inputs(|auction| {
+                auction.demand_curves.shift_remove(&demand_id).is_some()
+            })?;
+            state.record(
+                as_of,
+                MarketEvent::DemandPurged {
+                    demand_id,
+                    bidder_id,
+                },
+            )?;
+
+            Ok(Some(Tombstone {
+                id: demand_id,
+                bidder_id,
+                purged_at: as_of,
+            }))
+        })
+    }
+}
+
+/// The history of a demand's curve, most recent first
+fn curve_history(
+    state: &State,
+    demand_id: DemandId,
+    query: DateTimeRangeQuery<DateTime>,
+    limit: usize,
+) -> DateTimeRangeResponse<DemandCurve, DateTime> {
+    let mut rows = state
+        .curve_data
+        .iter()
+        .filter(|row| {
+            row.demand_id == demand_id
+                && in_range(row.valid_from, query.after, query.before)
+                && !matches!(row.value, DemandCurve::None)
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|row| std::cmp::Reverse(row.valid_from));
+
+    // The upper bound is exclusive, so the next page begins with the
+    // extra row, just below the oldest row of this page.
+    let more = paginate(&mut rows, limit).then(|| DateTimeRangeQuery {
+        before: rows.last().map(|row| row.valid_from),
+        after: query.after,
+    });
+
+    DateTimeRangeResponse {
+        results: rows
+            .into_iter()
+            .map(|row| ValueRecord {
+                valid_from: row.valid_from,
+                valid_until: row.valid_until,
+                value: row.value.clone(),
+                actor: Some(row.actor),
+            })
+            .collect(),
+        more,
+    }
+}
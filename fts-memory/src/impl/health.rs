@@ -0,0 +1,141 @@
+use crate::{
+    Db,
+    state::{GroupRow, ProductPath},
+    types::{PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{ConsistencyRecord, MigrationStatus},
+    ports::HealthRepository,
+};
+
+impl HealthRepository for Db {
+    async fn check_health(&self) -> Result<MigrationStatus, Self::Error> {
+        // The tables are created with the repository, so there is no schema
+        // to migrate
+        Ok(MigrationStatus {
+            applied: 0,
+            pending: 0,
+            version: None,
+            migrations: Vec::new(),
+        })
+    }
+
+    async fn check_consistency(
+        &self,
+        repair: bool,
+    ) -> Result<ConsistencyRecord<ProductId, PortfolioId>, Self::Error> {
+        let mut state = self.lock();
+
+        let derived = state.derive_product_tree();
+        let products: Vec<ProductId> = state
+            .products
+            .keys()
+            .copied()
+            .filter(|&product_id| {
+                !same_paths(
+                    paths_from(&state.product_tree, product_id),
+                    paths_from(&derived, product_id),
+                )
+            })
+            .collect();
+
+        let portfolios: Vec<PortfolioId> = state
+            .portfolios
+            .iter()
+            .filter(|&(&portfolio_id, portfolio)| {
+                current_group(&state.portfolio_demand, portfolio_id)
+                    != sorted(portfolio.demand.iter().map(|(&id, &weight)| (id, weight)))
+                    || current_group(&state.portfolio_product, portfolio_id)
+                        != sorted(portfolio.basis.iter().map(|(&id, &weight)| (id, weight)))
+            })
+            .map(|(&portfolio_id, _)| portfolio_id)
+            .collect();
+
+        let repaired = repair && !(products.is_empty() && portfolios.is_empty());
+        if repaired {
+            state
+                .product_tree
+                .retain(|path| !products.contains(&path.src_id));
+            state.product_tree.extend(
+                derived
+                    .into_iter()
+                    .filter(|path| products.contains(&path.src_id)),
+            );
+            for &portfolio_id in &portfolios {
+                state.restart_groups(portfolio_id);
+            }
+        }
+
+        Ok(ConsistencyRecord {
+            products,
+            portfolios,
+            repaired,
+        })
+    }
+}
+
+/// The paths from a product, ordered by their leaf and then by time
+fn paths_from(paths: &[ProductPath], product_id: ProductId) -> Vec<&ProductPath> {
+    let mut paths: Vec<&ProductPath> = paths
+        .iter()
+        .filter(|path| path.src_id == product_id)
+        .collect();
+    paths.sort_by_key(|path| (path.dst_id, path.valid_from));
+    paths
+}
+
+/// Whether two ordered lists of paths hold the same ratios at every time.
+///
+/// A path may be ended and begun anew with the same ratio (e.g. by an update
+/// which does not change it), so consecutive paths with the same ratio are
+/// merged before they are compared.
+fn same_paths(actual: Vec<&ProductPath>, derived: Vec<&ProductPath>) -> bool {
+    let merge = |paths: Vec<&ProductPath>| {
+        let mut merged: Vec<ProductPath> = Vec::new();
+        for path in paths {
+            match merged.last_mut() {
+                Some(last)
+                    if last.dst_id == path.dst_id
+                        && last.valid_until == Some(path.valid_from)
+                        && same_ratio(last.ratio, path.ratio) =>
+                {
+                    last.valid_until = path.valid_until;
+                }
+                _ => merged.push(path.clone()),
+            }
+        }
+        merged
+    };
+
+    let (actual, derived) = (merge(actual), merge(derived));
+    actual.len() == derived.len()
+        && actual.iter().zip(&derived).all(|(a, b)| {
+            a.dst_id == b.dst_id
+                && a.valid_from == b.valid_from
+                && a.valid_until == b.valid_until
+                && same_ratio(a.ratio, b.ratio)
+        })
+}
+
+/// Whether two ratios are equal, up to the rounding of their products
+fn same_ratio(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * a.abs().max(b.abs())
+}
+
+/// The current members of a portfolio's group, ordered by id
+fn current_group<Id: Copy + Ord>(
+    rows: &[GroupRow<Id>],
+    portfolio_id: PortfolioId,
+) -> Vec<(Id, f64)> {
+    sorted(
+        rows.iter()
+            .filter(|row| row.portfolio_id == portfolio_id && row.valid_until.is_none())
+            .map(|row| (row.id, row.weight)),
+    )
+}
+
+fn sorted<Id: Ord>(members: impl Iterator<Item = (Id, f64)>) -> Vec<(Id, f64)> {
+    let mut members: Vec<(Id, f64)> = members.collect();
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    members
+}
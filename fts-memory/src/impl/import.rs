@@ -0,0 +1,93 @@
+use crate::{Db, Error};
+use fts_core::{
+    models::{Actor, ImportDocument, ImportIssue, ImportRecord},
+    ports::ImportRepository,
+};
+use std::collections::HashSet;
+
+impl<DemandData, PortfolioData, ProductData>
+    ImportRepository<DemandData, PortfolioData, ProductData> for Db
+where
+    DemandData: Send + serde::Serialize + serde::de::DeserializeOwned,
+    PortfolioData: Send + serde::Serialize + serde::de::DeserializeOwned,
+    ProductData: Send + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn import(
+        &self,
+        document: ImportDocument<Self, DemandData, PortfolioData, ProductData>,
+        actor: Actor,
+        as_of: Self::DateTime,
+        dry_run: bool,
+    ) -> Result<Result<ImportRecord, Vec<ImportIssue<Self>>>, Self::Error> {
+        let mut state = self.lock();
+
+        let products: HashSet<_> = document
+            .product_ids()
+            .filter(|id| state.products.contains_key(id))
+            .copied()
+            .collect();
+        let demands: HashSet<_> = document
+            .demand_ids()
+            .filter(|id| state.demands.contains_key(id))
+            .copied()
+            .collect();
+        let portfolios: HashSet<_> = document
+            .portfolio_ids()
+            .filter(|id| state.portfolios.contains_key(id))
+            .copied()
+            .collect();
+
+        let issues = document.validate(&products, &demands, &portfolios);
+        if !issues.is_empty() {
+            return Ok(Err(issues));
+        }
+
+        let record = ImportRecord {
+            products: document.products.len() as u64,
+            demands: document.demands.len() as u64,
+            portfolios: document.portfolios.len() as u64,
+            dry_run,
+        };
+
+        // A dry run is applied in full, so that it fails where the import
+        // would, and then discarded
+        state.atomically(!dry_run, |state| {
+            // Parents must precede their children, to extend the product tree
+            for product in document.products {
+                state.insert_product(
+                    product.id,
+                    as_of,
+                    serde_json::to_value(product.app_data)?,
+                    product.parent,
+                )?;
+            }
+
+            for demand in document.demands {
+                state.insert_demand(
+                    demand.id,
+                    demand.bidder_id,
+                    serde_json::to_value(demand.app_data)?,
+                    demand.curve_data,
+                    actor,
+                    as_of,
+                )?;
+            }
+
+            for portfolio in document.portfolios {
+                state.insert_portfolio(
+                    portfolio.id,
+                    portfolio.bidder_id,
+                    serde_json::to_value(portfolio.app_data)?,
+                    portfolio.demand,
+                    portfolio.basis,
+                    actor,
+                    as_of,
+                )?;
+            }
+
+            Ok::<_, Error>(())
+        })?;
+
+        Ok(Ok(record))
+    }
+}
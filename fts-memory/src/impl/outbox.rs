@@ -0,0 +1,39 @@
+use crate::Db;
+use fts_core::{models::OutboxEvent, ports::OutboxRepository};
+
+impl OutboxRepository for Db {
+    async fn pending_events(&self, limit: usize) -> Result<Vec<OutboxEvent<Self>>, Self::Error> {
+        let state = self.lock();
+        state
+            .outbox
+            .iter()
+            .filter(|row| row.delivered_at.is_none())
+            .take(limit)
+            .map(|row| {
+                Ok(OutboxEvent {
+                    sequence: row.sequence,
+                    as_of: row.as_of,
+                    event: serde_json::from_value(row.event.clone())?,
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_delivered(
+        &self,
+        sequence: u64,
+        as_of: Self::DateTime,
+    ) -> Result<u64, Self::Error> {
+        let mut state = self.lock();
+        let mut delivered = 0;
+        for row in state
+            .outbox
+            .iter_mut()
+            .filter(|row| row.sequence <= sequence && row.delivered_at.is_none())
+        {
+            row.delivered_at = Some(as_of);
+            delivered += 1;
+        }
+        Ok(delivered)
+    }
+}
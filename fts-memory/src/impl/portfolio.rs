@@ -0,0 +1,583 @@
+use super::paginate;
+use crate::{
+    Db, Error,
+    filter::Filters,
+    state::{GroupRow, Portfolio, State, coalesce_min, in_range, valid_at},
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{
+        Actor, Basis, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, Map, MarketEvent,
+        PortfolioQuery, PortfolioQueryResponse, PortfolioRecord, PortfolioSearch,
+        PortfolioSearchResponse, Tombstone, ValueRecord, Weights,
+    },
+    ports::PortfolioRepository,
+};
+use futures_util::Stream;
+
+/// A portfolio's (effective) weight of a product, accounting for the
+/// partitioning of the products of its basis, as the `basis_view` of the
+/// database backends
+pub(crate) struct BasisRow {
+    pub portfolio_id: PortfolioId,
+    pub product_id: ProductId,
+    pub weight: f64,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+}
+
+/// The members of a group valid at a time, along with the (intersection of
+/// their) lifetimes
+struct Group<Id> {
+    members: Vec<(Id, f64)>,
+    valid_from: Option<DateTime>,
+    valid_until: Option<DateTime>,
+}
+
+impl<Id> Group<Id> {
+    fn collect(
+        rows: impl Iterator<Item = (Id, f64, DateTime, Option<DateTime>)>,
+        as_of: DateTime,
+    ) -> Self {
+        let mut group = Self {
+            members: Vec::new(),
+            valid_from: None,
+            valid_until: None,
+        };
+        for (id, weight, valid_from, valid_until) in rows {
+            if valid_at(valid_from, valid_until, as_of) {
+                group.members.push((id, weight));
+                group.valid_from = group.valid_from.max(Some(valid_from));
+                group.valid_until = coalesce_min(group.valid_until, valid_until);
+            }
+        }
+        group
+    }
+}
+
+impl State {
+    /// The effective product weights of every portfolio over time
+    pub(crate) fn basis_view(&self) -> impl Iterator<Item = BasisRow> + '_ {
+        self.portfolio_product.iter().flat_map(move |row| {
+            self.product_tree
+                .iter()
+                .filter(move |path| path.src_id == row.id)
+                .filter_map(move |path| {
+                    let valid_from = row.valid_from.max(path.valid_from);
+                    let valid_until = coalesce_min(row.valid_until, path.valid_until);
+                    valid_until
+                        .is_none_or(|valid_until| valid_from < valid_until)
+                        .then_some(BasisRow {
+                            portfolio_id: row.portfolio_id,
+                            product_id: path.dst_id,
+                            weight: path.ratio * row.weight,
+                            valid_from,
+                            valid_until,
+                        })
+                })
+        })
+    }
+
+    /// The record of a portfolio as of its latest change, as returned by
+    /// writes and queries of the current state
+    fn portfolio_record<PortfolioData: serde::de::DeserializeOwned>(
+        portfolio_id: PortfolioId,
+        portfolio: &Portfolio,
+    ) -> Result<PortfolioRecord<Db, PortfolioData>, Error> {
+        Ok(PortfolioRecord {
+            id: portfolio_id,
+            valid_from: portfolio.as_of,
+            valid_until: None,
+            bidder_id: portfolio.bidder_id,
+            app_data: serde_json::from_value(portfolio.app_data.clone())?,
+            demand: portfolio.demand.clone(),
+            basis: portfolio.basis.clone(),
+        })
+    }
+
+    /// The record of a portfolio as of `as_of`, with its basis either as
+    /// specified or expanded into the products it is partitioned into
+    fn portfolio_record_at<PortfolioData: serde::de::DeserializeOwned>(
+        &self,
+        portfolio_id: PortfolioId,
+        portfolio: &Portfolio,
+        as_of: DateTime,
+        expanded: bool,
+    ) -> Result<PortfolioRecord<Db, PortfolioData>, Error> {
+        let demand = Group::collect(
+            self.portfolio_demand
+                .iter()
+                .filter(|row| row.portfolio_id == portfolio_id)
+                .map(|row| (row.id, row.weight, row.valid_from, row.valid_until)),
+            as_of,
+        );
+        let basis = if expanded {
+            Group::collect(
+                self.basis_view()
+                    .filter(|row| row.portfolio_id == portfolio_id)
+                    .map(|row| (row.product_id, row.weight, row.valid_from, row.valid_until)),
+                as_of,
+            )
+        } else {
+            Group::collect(
+                self.portfolio_product
+                    .iter()
+                    .filter(|row| row.portfolio_id == portfolio_id)
+                    .map(|row| (row.id, row.weight, row.valid_from, row.valid_until)),
+                as_of,
+            )
+        };
+
+        // The record is valid for the intersection of the lifetimes of the
+        // demand and product groups
+        Ok(PortfolioRecord {
+            id: portfolio_id,
+            valid_from: demand
+                .valid_from
+                .max(basis.valid_from)
+                .unwrap_or(portfolio.as_of),
+            valid_until: coalesce_min(demand.valid_until, basis.valid_until),
+            bidder_id: portfolio.bidder_id,
+            app_data: serde_json::from_value(portfolio.app_data.clone())?,
+            demand: demand.members.into_iter().collect(),
+            basis: basis.members.into_iter().collect(),
+        })
+    }
+
+    /// The (unpurged) portfolios of any of the bidders with a demand or basis
+    fn active_portfolios_of<'a>(
+        &'a self,
+        bidder_ids: &'a [BidderId],
+    ) -> impl Iterator<Item = (&'a PortfolioId, &'a Portfolio)> {
+        self.portfolios.iter().filter(|(_, portfolio)| {
+            bidder_ids.contains(&portfolio.bidder_id)
+                && !(portfolio.demand.is_empty() && portfolio.basis.is_empty())
+        })
+    }
+
+    fn update_portfolio_record<PortfolioData: serde::de::DeserializeOwned>(
+        &mut self,
+        portfolio_id: PortfolioId,
+        demand: Option<Weights<crate::types::DemandId>>,
+        basis: Option<Basis<ProductId>>,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<Option<PortfolioRecord<Db, PortfolioData>>, Error> {
+        self.atomically(true, |state| {
+            state
+                .update_portfolio(portfolio_id, demand, basis, actor, as_of)?
+                .map(|portfolio| State::portfolio_record(portfolio_id, portfolio))
+                .transpose()
+        })
+    }
+}
+
+/// The history of a portfolio's group, grouped by the change which wrote it
+fn group_history<Id: Copy, Map: FromIterator<(Id, f64)>>(
+    rows: &[GroupRow<Id>],
+    portfolio_id: PortfolioId,
+    query: DateTimeRangeQuery<DateTime>,
+    limit: usize,
+) -> DateTimeRangeResponse<Map, DateTime> {
+    let mut changes: Vec<(DateTime, Vec<&GroupRow<Id>>)> = Vec::new();
+    for row in rows.iter().filter(|row| {
+        row.portfolio_id == portfolio_id && in_range(row.valid_from, query.after, query.before)
+    }) {
+        match changes
+            .iter_mut()
+            .find(|(valid_from, _)| *valid_from == row.valid_from)
+        {
+            Some((_, members)) => members.push(row),
+            None => changes.push((row.valid_from, vec![row])),
+        }
+    }
+    changes.sort_by_key(|change| std::cmp::Reverse(change.0));
+
+    // The upper bound is exclusive, so the next page begins with the
+    // extra row, just below the oldest row of this page.
+    let more = paginate(&mut changes, limit).then(|| DateTimeRangeQuery {
+        before: changes.last().map(|(valid_from, _)| *valid_from),
+        after: query.after,
+    });
+
+    DateTimeRangeResponse {
+        results: changes
+            .into_iter()
+            .map(|(valid_from, members)| ValueRecord {
+                valid_from,
+                // every row of a group is written (and closed) by the same change
+                valid_until: members
+                    .iter()
+                    .fold(None, |until, row| coalesce_min(until, row.valid_until)),
+                actor: members.first().map(|row| row.actor),
+                value: members.iter().map(|row| (row.id, row.weight)).collect(),
+            })
+            .collect(),
+        more,
+    }
+}
+
+impl<PortfolioData: Send + serde::Serialize + serde::de::DeserializeOwned>
+    PortfolioRepository<PortfolioData> for Db
+{
+    async fn get_portfolio_bidder_id(
+        &self,
+        portfolio_id: Self::PortfolioId,
+    ) -> Result<Option<Self::BidderId>, Self::Error> {
+        let state = self.lock();
+        Ok(state
+            .portfolios
+            .get(&portfolio_id)
+            .filter(|portfolio| portfolio.purged_at.is_none())
+            .map(|portfolio| portfolio.bidder_id))
+    }
+
+    async fn get_portfolio_bidder_ids(
+        &self,
+        portfolio_ids: &[Self::PortfolioId],
+    ) -> Result<Map<Self::PortfolioId, Self::BidderId>, Self::Error> {
+        let state = self.lock();
+        Ok(portfolio_ids
+            .iter()
+            .filter_map(|portfolio_id| {
+                state
+                    .portfolios
+                    .get(portfolio_id)
+                    .filter(|portfolio| portfolio.purged_at.is_none())
+                    .map(|portfolio| (*portfolio_id, portfolio.bidder_id))
+            })
+            .collect())
+    }
+
+    async fn query_portfolio(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioQuery<Self::PortfolioId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<PortfolioQueryResponse<Self, PortfolioData>, Self::Error> {
+        let state = self.lock();
+
+        let has_curve = |portfolio: &Portfolio| {
+            portfolio.demand.keys().any(|demand_id| {
+                state
+                    .demands
+                    .get(demand_id)
+                    .is_some_and(|demand| !matches!(demand.curve_data, DemandCurve::None))
+            })
+        };
+        let mut rows = state
+            .portfolios
+            .iter()
+            .filter(|(portfolio_id, portfolio)| {
+                bidder_ids.contains(&portfolio.bidder_id)
+                    && portfolio.purged_at.is_none()
+                    && !(query.active_only
+                        && portfolio.demand.is_empty()
+                        && portfolio.basis.is_empty())
+                    && (!query.has_curve || has_curve(portfolio))
+                    && query
+                        .touched_since
+                        .is_none_or(|touched_since| portfolio.as_of >= touched_since)
+                    && query.after.is_none_or(|after| **portfolio_id > after)
+            })
+            // +1 to check if there are more results
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut rows, limit).then(|| PortfolioQuery {
+            active_only: query.active_only,
+            has_curve: query.has_curve,
+            touched_since: query.touched_since,
+            after: rows.last().map(|(portfolio_id, _)| **portfolio_id),
+        });
+
+        Ok(PortfolioQueryResponse {
+            results: rows
+                .into_iter()
+                .map(|(&portfolio_id, portfolio)| State::portfolio_record(portfolio_id, portfolio))
+                .collect::<Result<_, _>>()?,
+            more,
+        })
+    }
+
+    async fn query_portfolio_with_expanded_products(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        as_of: Self::DateTime,
+    ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let state = self.lock();
+        state
+            .active_portfolios_of(bidder_ids)
+            .map(|(&portfolio_id, portfolio)| {
+                state.portfolio_record_at(portfolio_id, portfolio, as_of, true)
+            })
+            .collect()
+    }
+
+    async fn query_portfolio_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
+    ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let state = self.lock();
+        state
+            .portfolios
+            .iter()
+            .filter(|(portfolio_id, portfolio)| {
+                bidder_ids.contains(&portfolio.bidder_id)
+                    && state.portfolio_product.iter().any(|row| {
+                        row.portfolio_id == **portfolio_id
+                            && row.id == product_id
+                            && row.valid_until.is_none()
+                    })
+            })
+            .map(|(&portfolio_id, portfolio)| State::portfolio_record(portfolio_id, portfolio))
+            .collect()
+    }
+
+    async fn create_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        bidder_id: Self::BidderId,
+        app_data: PortfolioData,
+        demand: Weights<Self::DemandId>,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<PortfolioRecord<Self, PortfolioData>, Self::Error> {
+        let app_data = serde_json::to_value(app_data)?;
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            state.insert_portfolio(
+                portfolio_id,
+                bidder_id,
+                app_data,
+                demand,
+                basis,
+                actor,
+                as_of,
+            )?;
+            State::portfolio_record(portfolio_id, &state.portfolios[&portfolio_id])
+        })
+    }
+
+    async fn update_portfolio_demand(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        demand: Weights<Self::DemandId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        self.lock()
+            .update_portfolio_record(portfolio_id, Some(demand), None, actor, as_of)
+    }
+
+    async fn update_portfolio_basis(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        self.lock()
+            .update_portfolio_record(portfolio_id, None, Some(basis), actor, as_of)
+    }
+
+    async fn update_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        demand: Weights<Self::DemandId>,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        self.lock()
+            .update_portfolio_record(portfolio_id, Some(demand), Some(basis), actor, as_of)
+    }
+
+    async fn get_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let state = self.lock();
+        state
+            .portfolios
+            .get(&portfolio_id)
+            .filter(|portfolio| portfolio.purged_at.is_none())
+            .map(|portfolio| state.portfolio_record_at(portfolio_id, portfolio, as_of, false))
+            .transpose()
+    }
+
+    async fn get_portfolio_with_expanded_products(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let state = self.lock();
+        state
+            .portfolios
+            .get(&portfolio_id)
+            .filter(|portfolio| portfolio.purged_at.is_none())
+            .map(|portfolio| state.portfolio_record_at(portfolio_id, portfolio, as_of, true))
+            .transpose()
+    }
+
+    async fn search_portfolios(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioSearch<Self::PortfolioId>,
+        limit: usize,
+    ) -> Result<PortfolioSearchResponse<Self, PortfolioData>, Self::Error> {
+        let filters = Filters::new(&query.filters)?;
+        let state = self.lock();
+
+        let mut rows = state
+            .active_portfolios_of(bidder_ids)
+            .filter(|(portfolio_id, portfolio)| {
+                query.after.is_none_or(|after| **portfolio_id > after)
+                    && filters.matches(&portfolio.app_data)
+            })
+            // +1 to check if there are more results
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut rows, limit).then(|| PortfolioSearch {
+            after: rows.last().map(|(portfolio_id, _)| **portfolio_id),
+            filters: query.filters.clone(),
+        });
+
+        Ok(PortfolioSearchResponse {
+            results: rows
+                .into_iter()
+                .map(|(&portfolio_id, portfolio)| State::portfolio_record(portfolio_id, portfolio))
+                .collect::<Result<_, _>>()?,
+            more,
+        })
+    }
+
+    /// Get the history of this portfolio's demands
+    ///
+    /// This returns a list of records, each containing the state of the portfolio's demand group
+    /// at a specific point in time. The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`. This is important for a `more` pointer to work correctly,
+    /// so the demand is actually a map of `demand_id` to `weight` at that point in time.
+    async fn get_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Weights<Self::DemandId>, Self::DateTime>, Self::Error> {
+        let state = self.lock();
+        Ok(group_history(
+            &state.portfolio_demand,
+            portfolio_id,
+            query,
+            limit,
+        ))
+    }
+
+    fn stream_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Weights<DemandId>>, Error>> + Send {
+        // The history is read upfront, rather than holding the lock while the
+        // stream is consumed
+        let history = group_history(
+            &self.lock().portfolio_demand,
+            portfolio_id,
+            query,
+            usize::MAX,
+        );
+        futures_util::stream::iter(history.results.into_iter().map(Ok))
+    }
+
+    /// Get the history of this portfolio's products
+    ///
+    /// This returns a list of records, each containing the state of the portfolio's product group
+    /// at a specific point in time. The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`. This is important for a `more` pointer to work correctly,
+    /// so the basis is actually a map of `product_id` to `weight` at that point in time.
+    async fn get_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Basis<Self::ProductId>, Self::DateTime>, Self::Error> {
+        let state = self.lock();
+        Ok(group_history(
+            &state.portfolio_product,
+            portfolio_id,
+            query,
+            limit,
+        ))
+    }
+
+    fn stream_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Basis<ProductId>>, Error>> + Send {
+        let history = group_history(
+            &self.lock().portfolio_product,
+            portfolio_id,
+            query,
+            usize::MAX,
+        );
+        futures_util::stream::iter(history.results.into_iter().map(Ok))
+    }
+
+    async fn purge_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::PortfolioId>>, Self::Error> {
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            // Erase the data of the portfolio itself, leaving the row as a
+            // tombstone, along with the whole of its group histories and its
+            // groups in the recorded inputs of past batches
+            let Some(portfolio) = state
+                .portfolios
+                .get_mut(&portfolio_id)
+                .filter(|portfolio| portfolio.purged_at.is_none())
+            else {
+                return Ok(None);
+            };
+            portfolio.as_of = as_of;
+            portfolio.app_data = serde_json::Value::Null;
+            portfolio.demand = Weights::default();
+            portfolio.basis = Basis::default();
+            portfolio.purged_at = Some(as_of);
+            let bidder_id = portfolio.bidder_id;
+
+            state
+                .portfolio_demand
+                .retain(|row| row.portfolio_id != portfolio_id);
+            state
+                .portfolio_product
+                .retain(|row| row.portfolio_id != portfolio_id);
+            state.redact_batch_inputs(|auction| {
+                auction.portfolios.shift_remove(&portfolio_id).is_some()
+            })?;
+            state.record(
+                as_of,
+                MarketEvent::PortfolioPurged {
+                    portfolio_id,
+                    bidder_id,
+                },
+            )?;
+
+            Ok(Some(Tombstone {
+                id: portfolio_id,
+                bidder_id,
+                purged_at: as_of,
+            }))
+        })
+    }
+}
@@ -0,0 +1,299 @@
+use super::paginate;
+use crate::{
+    Db, Error,
+    filter::Filters,
+    state::{State, valid_at},
+    types::{DateTime, ProductId},
+};
+use fts_core::{
+    models::{
+        Basis, ProductQuery, ProductQueryResponse, ProductRecord, ProductSearch,
+        ProductSearchResponse, ProductTreeConflict,
+    },
+    ports::ProductRepository,
+};
+
+impl State {
+    /// The products a product is partitioned into as of `as_of`, which is
+    /// only itself if it is not partitioned
+    pub(crate) fn product_basis(&self, product_id: ProductId, as_of: DateTime) -> Basis<ProductId> {
+        self.product_tree
+            .iter()
+            .filter(|path| {
+                path.src_id == product_id && valid_at(path.valid_from, path.valid_until, as_of)
+            })
+            .map(|path| (path.dst_id, path.ratio))
+            .collect()
+    }
+
+    /// The record of a product as of `as_of`, given its basis at the time
+    fn product_record<ProductData: serde::de::DeserializeOwned>(
+        &self,
+        product_id: ProductId,
+        basis: Basis<ProductId>,
+        as_of: DateTime,
+    ) -> Result<ProductRecord<Db, ProductData>, Error> {
+        let product = &self.products[&product_id];
+        let parent = self
+            .product_parent
+            .iter()
+            .find(|row| {
+                row.product_id == product_id && valid_at(row.valid_from, row.valid_until, as_of)
+            })
+            .map_or((product_id, 1.0), |row| (row.parent_id, row.ratio));
+        Ok(ProductRecord {
+            id: product_id,
+            app_data: serde_json::from_value(product.app_data.clone())?,
+            parent,
+            basis,
+        })
+    }
+}
+
+impl<ProductData: Send + serde::Serialize + serde::de::DeserializeOwned>
+    ProductRepository<ProductData> for Db
+{
+    async fn create_product(
+        &self,
+        product_id: Self::ProductId,
+        app_data: ProductData,
+        as_of: Self::DateTime,
+    ) -> Result<ProductRecord<Self, ProductData>, Self::Error> {
+        let app_data = serde_json::to_value(app_data)?;
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            state.insert_product(product_id, as_of, app_data, None)?;
+            state.product_record(
+                product_id,
+                std::iter::once((product_id, 1.0)).collect(),
+                as_of,
+            )
+        })
+    }
+
+    async fn create_products<T: Send + IntoIterator<Item = (Self::ProductId, ProductData)>>(
+        &self,
+        products: T,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<ProductRecord<Self, ProductData>>, Self::Error>
+    where
+        T::IntoIter: Send,
+    {
+        let products = products
+            .into_iter()
+            .map(|(product_id, app_data)| Ok((product_id, serde_json::to_value(app_data)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut state = self.lock();
+        state.atomically(true, |state| {
+            products
+                .into_iter()
+                .map(|(product_id, app_data)| {
+                    state.insert_product(product_id, as_of, app_data, None)?;
+                    state.product_record(
+                        product_id,
+                        std::iter::once((product_id, 1.0)).collect(),
+                        as_of,
+                    )
+                })
+                .collect()
+        })
+    }
+
+    async fn partition_product<T: Send + IntoIterator<Item = (Self::ProductId, ProductData, f64)>>(
+        &self,
+        product_id: Self::ProductId,
+        children: T,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Vec<ProductRecord<Self, ProductData>>>, Self::Error>
+    where
+        T::IntoIter: Send + ExactSizeIterator,
+    {
+        let children = children
+            .into_iter()
+            .map(|(child_id, app_data, ratio)| {
+                Ok((child_id, serde_json::to_value(app_data)?, ratio))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut state = self.lock();
+        if !state.products.contains_key(&product_id) {
+            return Ok(None);
+        }
+        match state.product_basis(product_id, as_of).len() {
+            0 => return Ok(None),
+            1 => {}
+            _ => return Ok(Some(Vec::new())),
+        }
+
+        state
+            .atomically(true, |state| {
+                children
+                    .into_iter()
+                    .map(|(child_id, app_data, ratio)| {
+                        state.insert_product(
+                            child_id,
+                            as_of,
+                            app_data,
+                            Some((product_id, ratio)),
+                        )?;
+                        state.product_record(
+                            child_id,
+                            std::iter::once((child_id, 1.0)).collect(),
+                            as_of,
+                        )
+                    })
+                    .collect()
+            })
+            .map(Some)
+    }
+
+    async fn update_product_ratio(
+        &self,
+        product_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut state = self.lock();
+        let Some(product) = state.products.get(&product_id) else {
+            return Ok(Err(ProductTreeConflict::Missing { product_id }));
+        };
+        let Some((parent_id, _)) = product.parent else {
+            return Ok(Err(ProductTreeConflict::Root { product_id }));
+        };
+
+        state.atomically(true, |state| {
+            state.move_product(product_id, parent_id, ratio, as_of)?;
+            let basis = state.product_basis(product_id, as_of);
+            state.product_record(product_id, basis, as_of).map(Ok)
+        })
+    }
+
+    async fn move_product(
+        &self,
+        product_id: Self::ProductId,
+        parent_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut state = self.lock();
+        for id in [product_id, parent_id] {
+            if !state.products.contains_key(&id) {
+                return Ok(Err(ProductTreeConflict::Missing { product_id: id }));
+            }
+        }
+        if state
+            .lineage(parent_id)
+            .iter()
+            .any(|(id, _)| *id == product_id)
+        {
+            return Ok(Err(ProductTreeConflict::Cycle { parent_id }));
+        }
+
+        state.atomically(true, |state| {
+            state.move_product(product_id, parent_id, ratio, as_of)?;
+            let basis = state.product_basis(product_id, as_of);
+            state.product_record(product_id, basis, as_of).map(Ok)
+        })
+    }
+
+    async fn get_product(
+        &self,
+        product_id: Self::ProductId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<ProductRecord<Self, ProductData>>, Self::Error> {
+        let state = self.lock();
+        let basis = state.product_basis(product_id, as_of);
+        if basis.is_empty() {
+            Ok(None)
+        } else {
+            state.product_record(product_id, basis, as_of).map(Some)
+        }
+    }
+
+    async fn query_products(
+        &self,
+        query: ProductQuery<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductQueryResponse<Self, ProductData>, Self::Error> {
+        let state = self.lock();
+
+        let mut ids = Vec::new();
+        for &product_id in state.products.keys() {
+            if query.after.is_some_and(|after| product_id <= after) {
+                continue;
+            }
+            let basis = state.product_basis(product_id, as_of);
+            // A root product has no parent as of the time
+            let listed = query.include_tree
+                || !state.product_parent.iter().any(|row| {
+                    row.product_id == product_id && valid_at(row.valid_from, row.valid_until, as_of)
+                });
+            if !basis.is_empty() && listed {
+                ids.push((product_id, basis));
+                // +1 to check if there are more results
+                if ids.len() > limit {
+                    break;
+                }
+            }
+        }
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut ids, limit).then(|| ProductQuery {
+            include_tree: query.include_tree,
+            after: ids.last().map(|(product_id, _)| *product_id),
+        });
+
+        Ok(ProductQueryResponse {
+            results: ids
+                .into_iter()
+                .map(|(product_id, basis)| state.product_record(product_id, basis, as_of))
+                .collect::<Result<_, _>>()?,
+            more,
+        })
+    }
+
+    async fn search_products(
+        &self,
+        query: ProductSearch<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductSearchResponse<Self, ProductData>, Self::Error> {
+        let filters = Filters::new(&query.filters)?;
+        let state = self.lock();
+
+        let mut ids = Vec::new();
+        for (&product_id, product) in state.products.iter() {
+            if query.after.is_some_and(|after| product_id <= after) {
+                continue;
+            }
+            let basis = state.product_basis(product_id, as_of);
+            if !basis.is_empty() && filters.matches(&product.app_data) {
+                ids.push((product_id, basis));
+                // +1 to check if there are more results
+                if ids.len() > limit {
+                    break;
+                }
+            }
+        }
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = paginate(&mut ids, limit).then(|| ProductSearch {
+            after: ids.last().map(|(product_id, _)| *product_id),
+            filters: query.filters.clone(),
+        });
+
+        Ok(ProductSearchResponse {
+            results: ids
+                .into_iter()
+                .map(|(product_id, basis)| state.product_record(product_id, basis, as_of))
+                .collect::<Result<_, _>>()?,
+            more,
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    Db,
+    state::{GroupRow, OutcomeRow},
+    types::{DateTime, PortfolioId},
+};
+use fts_core::{
+    models::{CompactionRecord, PruneRecord},
+    ports::RetentionRepository,
+};
+use std::collections::BTreeMap;
+
+impl RetentionRepository for Db {
+    async fn prune_history(
+        &self,
+        before: Self::DateTime,
+    ) -> Result<PruneRecord<Self::DateTime>, Self::Error> {
+        let mut state = self.lock();
+
+        // The history needed to accrue unsettled activity is only pruned up
+        // to the latest settlement
+        let settled_before = state
+            .settlements
+            .keys()
+            .next_back()
+            .map(|latest| (*latest).min(before));
+
+        let curves = prune(&mut state.curve_data, |row| row.valid_until, Some(before));
+        let demand_groups = prune(
+            &mut state.portfolio_demand,
+            |row: &GroupRow<_>| row.valid_until,
+            Some(before),
+        );
+        let product_groups = prune(
+            &mut state.portfolio_product,
+            |row: &GroupRow<_>| row.valid_until,
+            settled_before,
+        );
+        let portfolio_outcomes = prune(
+            &mut state.portfolio_outcomes,
+            |row: &OutcomeRow<_>| row.valid_until,
+            settled_before,
+        );
+        let product_outcomes = prune(
+            &mut state.product_outcomes,
+            |row: &OutcomeRow<_>| row.valid_until,
+            settled_before,
+        );
+
+        // The recorded inputs of a batch are only of use to solve it again
+        let count = state.batch_inputs.len();
+        state.batch_inputs.retain(|as_of, _| *as_of >= before);
+        let batch_inputs = (count - state.batch_inputs.len()) as u64;
+
+        Ok(PruneRecord {
+            before,
+            settled_before,
+            curves,
+            demand_groups,
+            product_groups,
+            portfolio_outcomes,
+            product_outcomes,
+            batch_inputs,
+            archived: false,
+        })
+    }
+
+    async fn compact_history(&self) -> Result<CompactionRecord, Self::Error> {
+        let mut state = self.lock();
+        Ok(CompactionRecord {
+            demand_groups: compact(&mut state.portfolio_demand),
+            product_groups: compact(&mut state.portfolio_product),
+        })
+    }
+}
+
+/// Delete the rows superseded by `horizon` (if any), returning their number
+fn prune<Row>(
+    rows: &mut Vec<Row>,
+    valid_until: impl Fn(&Row) -> Option<DateTime>,
+    horizon: Option<DateTime>,
+) -> u64 {
+    let Some(horizon) = horizon else {
+        return 0;
+    };
+    let count = rows.len();
+    rows.retain(|row| valid_until(row).is_none_or(|valid_until| valid_until > horizon));
+    (count - rows.len()) as u64
+}
+
+/// Merge each version of a group identical to the version it immediately
+/// follows into that version, returning the number of removed rows
+fn compact<Id: Ord + Copy>(rows: &mut Vec<GroupRow<Id>>) -> u64 {
+    // The versions of each portfolio's group, in order
+    let mut versions = BTreeMap::<(PortfolioId, DateTime), Vec<usize>>::new();
+    for (index, row) in rows.iter().enumerate() {
+        versions
+            .entry((row.portfolio_id, row.valid_from))
+            .or_default()
+            .push(index);
+    }
+    for members in versions.values_mut() {
+        members.sort_by_key(|&index| rows[index].id);
+    }
+
+    let identical = |a: &[usize], b: &[usize]| {
+        a.len() == b.len()
+            && a.iter().zip(b).all(|(&a, &b)| {
+                let (a, b) = (&rows[a], &rows[b]);
+                a.id == b.id && a.weight == b.weight && a.actor == b.actor
+            })
+    };
+
+    // Each run of identical versions is kept as its first version, which
+    // lasts until the end of the run
+    let mut removed = vec![false; rows.len()];
+    let mut runs = Vec::<(&[usize], Option<DateTime>)>::new();
+    let mut previous: Option<PortfolioId> = None;
+    for ((portfolio_id, valid_from), members) in versions.iter() {
+        let valid_until = rows[members[0]].valid_until;
+        match runs.last_mut() {
+            Some((head, until))
+                if previous == Some(*portfolio_id)
+                    && *until == Some(*valid_from)
+                    && identical(head, members) =>
+            {
+                *until = valid_until;
+                for &index in members {
+                    removed[index] = true;
+                }
+            }
+            _ => runs.push((members, valid_until)),
+        }
+        previous = Some(*portfolio_id);
+    }
+
+    for (head, until) in runs {
+        for &index in head {
+            rows[index].valid_until = until;
+        }
+    }
+
+    let mut index = 0;
+    rows.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+    removed.iter().filter(|removed| **removed).count() as u64
+}
@@ -0,0 +1,809 @@
+use super::paginate;
+use crate::{
+    Db, Error,
+    state::{Entry, Revision, Settlement, State, Statement, in_range, valid_at},
+    types::{BidderId, DateTime, ProductId},
+};
+use fts_core::{
+    models::{
+        Activity, BidderSummary, DateTimeRangeQuery, DateTimeRangeResponse, Map, MarketEvent,
+        SettlementConfig, SettlementConflict, SettlementRecord, SettlementRevision,
+        SettlementStatement, UnsettledActivityQuery, UnsettledActivityResponse, ValueRecord,
+    },
+    ports::SettlementRepository,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The activity accrued by a bidder over some interval. If `product_id` is
+/// None, the row corresponds to the bidder's payment.
+struct AccruedRow {
+    bidder_id: BidderId,
+    product_id: Option<ProductId>,
+    accrued: f64,
+}
+
+/// The activity of a bidder summed over some entries
+#[derive(Default, Clone, Copy)]
+struct Total {
+    accrued: f64,
+    settled: f64,
+}
+
+/// A settlement as it stands after its latest revision, if any.
+struct PostedSettlement {
+    settled_from: DateTime,
+    /// The configuration with which the settlement was originally made
+    original: SettlementConfig<DateTime>,
+    /// The latest revision, or 0 if never revised
+    revision: u32,
+    revised_at: DateTime,
+    /// The configuration in effect, or None if the settlement is reversed
+    config: Option<SettlementConfig<DateTime>>,
+}
+
+/// Collect settled entries into the activity of each bidder
+fn settled_activity(
+    totals: BTreeMap<(BidderId, Option<ProductId>), Total>,
+) -> Map<BidderId, Activity<ProductId>> {
+    let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+    for ((bidder_id, product_id), total) in totals {
+        let entry = activity.entry(bidder_id).or_default();
+        match product_id {
+            Some(product_id) => {
+                entry.positions.insert(product_id, total.settled);
+            }
+            None => entry.payment = total.settled,
+        }
+    }
+    activity
+}
+
+/// The statement as issued under `id`
+fn issued_statement(id: u64, statement: &Statement) -> SettlementStatement<Db> {
+    SettlementStatement {
+        id,
+        bidder_id: statement.bidder_id,
+        valid_from: statement.settled_from,
+        valid_until: statement.as_of,
+        revision: statement.revision,
+        revised_at: statement.revised_at,
+        config: statement.config.clone(),
+        activity: statement.activity.clone(),
+    }
+}
+
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+/// The `rate` and `price` of a portfolio outcome
+fn rate_and_price(value: &serde_json::Value) -> (Option<f64>, f64) {
+    let field = |name| value.get(name).and_then(serde_json::Value::as_f64);
+    (field("rate"), field("price").unwrap_or(0.0))
+}
+
+impl State {
+    /// The time up to which activity was most recently settled
+    fn latest_settlement(&self) -> Option<DateTime> {
+        self.settlements.keys().next_back().copied()
+    }
+
+    /// The timestamp of the earliest batch outcome, if any
+    fn first_batch(&self) -> Option<DateTime> {
+        self.portfolio_outcomes
+            .iter()
+            .map(|row| row.valid_from)
+            .min()
+    }
+
+    /// Integrate the portfolio outcomes over [from, until), measuring time in
+    /// units of `time_unit` seconds, for the given bidders (or every bidder).
+    /// Positions are reported in the contemporary product basis of each batch,
+    /// restricted to the given products (if any) with ids following `after`,
+    /// and at most `limit` (if any) of them are reported in order of bidder and
+    /// product. The bidders' payments follow, and are never restricted.
+    #[allow(clippy::too_many_arguments)]
+    fn accrued_activity(
+        &self,
+        bidder_ids: Option<&[BidderId]>,
+        from: Option<DateTime>,
+        until: DateTime,
+        time_unit: f64,
+        product_ids: Option<&[ProductId]>,
+        after: Option<ProductId>,
+        limit: Option<usize>,
+    ) -> Vec<AccruedRow> {
+        let mut positions: BTreeMap<(BidderId, ProductId), f64> = BTreeMap::new();
+        let mut payments: BTreeMap<BidderId, f64> = BTreeMap::new();
+
+        for row in self.portfolio_outcomes.iter().filter(|row| {
+            row.valid_from < until
+                && (from.is_none()
+                    || row
+                        .valid_until
+                        .is_none_or(|valid_until| from.unwrap() < valid_until))
+        }) {
+            let bidder_id = self.portfolios[&row.id].bidder_id;
+            if bidder_ids.is_some_and(|bidder_ids| !bidder_ids.contains(&bidder_id)) {
+                continue;
+            }
+            let (Some(rate), price) = rate_and_price(&row.value) else {
+                continue;
+            };
+            if rate == 0.0 {
+                continue;
+            }
+
+            let start = from.map_or(row.valid_from, |from| row.valid_from.max(from));
+            let end = row.valid_until.unwrap_or(until).min(until);
+            let duration = start.seconds_until(end) / time_unit;
+
+            *payments.entry(bidder_id).or_default() += price * rate * duration;
+            for basis in self.basis_view().filter(|basis| {
+                basis.portfolio_id == row.id
+                    && valid_at(basis.valid_from, basis.valid_until, row.valid_from)
+                    && product_ids.is_none_or(|product_ids| product_ids.contains(&basis.product_id))
+                    && after.is_none_or(|after| basis.product_id > after)
+            }) {
+                *positions.entry((bidder_id, basis.product_id)).or_default() +=
+                    rate * basis.weight * duration;
+            }
+        }
+
+        positions
+            .into_iter()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|((bidder_id, product_id), accrued)| AccruedRow {
+                bidder_id,
+                product_id: Some(product_id),
+                accrued,
+            })
+            .chain(payments.into_iter().map(|(bidder_id, accrued)| AccruedRow {
+                bidder_id,
+                product_id: None,
+                accrued,
+            }))
+            .collect()
+    }
+
+    /// The net position of every bidder in every product, combining the
+    /// settled positions with the activity accrued since the most recent
+    /// settlement up to `as_of`.
+    pub(crate) fn net_positions(
+        &self,
+        as_of: DateTime,
+        time_unit: f64,
+    ) -> BTreeMap<(BidderId, ProductId), f64> {
+        let mut positions = BTreeMap::new();
+        for entry in self.entries.iter().filter(|entry| entry.as_of <= as_of) {
+            if let Some(product_id) = entry.product_id {
+                *positions.entry((entry.bidder_id, product_id)).or_default() += entry.settled;
+            }
+        }
+        let latest = self.latest_settlement();
+        for row in self.accrued_activity(None, latest, as_of, time_unit, None, None, None) {
+            // Rows without a product are payments
+            if let Some(product_id) = row.product_id {
+                *positions.entry((row.bidder_id, product_id)).or_default() += row.accrued;
+            }
+        }
+        positions
+    }
+
+    /// The activity of a bidder accrued since the most recent settlement up
+    /// to `as_of`, restricted to at most `limit` (if any) positions in
+    /// `product_ids` (if any) following `after`, along with the start of the
+    /// unsettled interval.
+    fn unsettled_activity(
+        &self,
+        bidder_id: BidderId,
+        as_of: DateTime,
+        time_unit: f64,
+        product_ids: &[ProductId],
+        after: Option<ProductId>,
+        limit: Option<usize>,
+    ) -> (DateTime, Vec<AccruedRow>) {
+        let latest = self.latest_settlement();
+        let rows = self.accrued_activity(
+            Some(&[bidder_id]),
+            latest,
+            as_of,
+            time_unit,
+            (!product_ids.is_empty()).then_some(product_ids),
+            after,
+            limit,
+        );
+        let valid_from = latest.or_else(|| self.first_batch()).unwrap_or(as_of);
+        (valid_from, rows)
+    }
+
+    /// The state of the settlement as of `as_of`, if it was made, net of any
+    /// revisions since.
+    fn posted_settlement(&self, as_of: DateTime) -> Option<PostedSettlement> {
+        let settlement = self.settlements.get(&as_of)?;
+        let latest = self
+            .revisions
+            .iter()
+            .filter(|revision| revision.as_of == as_of)
+            .max_by_key(|revision| revision.revision);
+
+        let original = settlement.config.clone();
+        Some(match latest {
+            Some(latest) => PostedSettlement {
+                settled_from: settlement.settled_from,
+                original,
+                revision: latest.revision,
+                revised_at: latest.revised_at,
+                config: latest.config.clone(),
+            },
+            None => PostedSettlement {
+                settled_from: settlement.settled_from,
+                revision: 0,
+                revised_at: as_of,
+                config: Some(original.clone()),
+                original,
+            },
+        })
+    }
+
+    /// The entries of the settlement as of `as_of` posted by the revisions
+    /// `from..=until`, summed per bidder and product.
+    fn settlement_entries(
+        &self,
+        as_of: DateTime,
+        from: u32,
+        until: u32,
+    ) -> BTreeMap<(BidderId, Option<ProductId>), Total> {
+        let mut totals: BTreeMap<_, Total> = BTreeMap::new();
+        for entry in self
+            .entries
+            .iter()
+            .filter(|entry| entry.as_of == as_of && (from..=until).contains(&entry.revision))
+        {
+            let total = totals
+                .entry((entry.bidder_id, entry.product_id))
+                .or_default();
+            total.accrued += entry.accrued;
+            total.settled += entry.settled;
+        }
+        totals
+    }
+
+    /// Settle the accrued activity as of the configuration's `as_of`, posting
+    /// the entries as `revision`.
+    fn post_entries(
+        &mut self,
+        config: &SettlementConfig<DateTime>,
+        revision: u32,
+        rows: Vec<AccruedRow>,
+    ) -> Map<BidderId, Activity<ProductId>> {
+        // Rounding each settlement independently would let the residuals drift,
+        // so we instead round the running totals and settle the difference
+        // against what has already been settled.
+        let mut totals: BTreeMap<(BidderId, Option<ProductId>), Total> = BTreeMap::new();
+        for entry in self.entries.iter() {
+            let total = totals
+                .entry((entry.bidder_id, entry.product_id))
+                .or_default();
+            total.accrued += entry.accrued;
+            total.settled += entry.settled;
+        }
+
+        let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+        for row in rows {
+            let total = totals
+                .get(&(row.bidder_id, row.product_id))
+                .copied()
+                .unwrap_or_default();
+            let decimals = match row.product_id {
+                Some(_) => config.position_decimals,
+                None => config.payment_decimals,
+            };
+            let amount = round(
+                round(total.accrued + row.accrued, decimals) - total.settled,
+                decimals,
+            );
+            self.entries.push(Entry {
+                as_of: config.as_of,
+                revision,
+                bidder_id: row.bidder_id,
+                product_id: row.product_id,
+                accrued: row.accrued,
+                settled: amount,
+            });
+
+            let entry = activity.entry(row.bidder_id).or_default();
+            match row.product_id {
+                Some(product_id) => {
+                    entry.positions.insert(product_id, amount);
+                }
+                None => entry.payment = amount,
+            }
+        }
+        activity
+    }
+
+    /// Record a revision of the settlement as of `as_of`
+    fn insert_revision(
+        &mut self,
+        as_of: DateTime,
+        revision: u32,
+        revised_at: DateTime,
+        config: Option<SettlementConfig<DateTime>>,
+    ) -> Result<(), Error> {
+        self.revisions.push(Revision {
+            as_of,
+            revision,
+            revised_at,
+            config,
+        });
+        self.record(as_of, MarketEvent::SettlementRevised)
+    }
+
+    /// Issue a statement to each bidder of the entries posted by a revision
+    /// of the settlement (or by the settlement itself, as revision 0).
+    fn issue_statements(&mut self, settled_from: DateTime, entries: &SettlementRevision<Db>) {
+        let revised_at = (entries.revision > 0).then_some(entries.revised_at);
+        for (bidder_id, activity) in entries.activity.iter() {
+            self.statements.push(Statement {
+                as_of: entries.as_of,
+                revision: entries.revision,
+                bidder_id: *bidder_id,
+                settled_from,
+                revised_at,
+                config: entries.config.clone(),
+                activity: activity.clone(),
+            });
+        }
+    }
+
+    /// Reverse the settlement as of `as_of`, posting entries offsetting every
+    /// entry posted so far as `revision`.
+    fn post_reversal(
+        &mut self,
+        as_of: DateTime,
+        revision: u32,
+        revised_at: DateTime,
+    ) -> Result<SettlementRevision<Db>, Error> {
+        self.insert_revision(as_of, revision, revised_at, None)?;
+
+        let offsets = self.settlement_entries(as_of, 0, revision - 1);
+        self.entries.extend(
+            offsets
+                .into_iter()
+                .map(|((bidder_id, product_id), total)| Entry {
+                    as_of,
+                    revision,
+                    bidder_id,
+                    product_id,
+                    accrued: -total.accrued,
+                    settled: -total.settled,
+                }),
+        );
+
+        Ok(SettlementRevision {
+            as_of,
+            revision,
+            revised_at,
+            config: None,
+            activity: settled_activity(self.settlement_entries(as_of, revision, revision)),
+        })
+    }
+}
+
+impl SettlementRepository for Db {
+    async fn get_unsettled_activity(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<ValueRecord<Self::DateTime, Activity<Self::ProductId>>, Self::Error> {
+        let (valid_from, rows) =
+            self.lock()
+                .unsettled_activity(bidder_id, as_of, time_unit, &[], None, None);
+
+        let mut activity = Activity::default();
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => {
+                    activity.positions.insert(product_id, row.accrued);
+                }
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        Ok(ValueRecord {
+            valid_from,
+            valid_until: Some(as_of),
+            value: activity,
+            actor: None,
+        })
+    }
+
+    async fn get_unsettled_positions(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        query: UnsettledActivityQuery<Self::ProductId>,
+        limit: usize,
+    ) -> Result<UnsettledActivityResponse<Self>, Self::Error> {
+        let (valid_from, rows) = self.lock().unsettled_activity(
+            bidder_id,
+            as_of,
+            time_unit,
+            &query.products,
+            query.after,
+            Some(limit + 1),
+        );
+
+        // The positions precede the payment, in order of product
+        let mut activity = Activity::default();
+        let mut positions = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => positions.push((product_id, row.accrued)),
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        let more = paginate(&mut positions, limit).then(|| UnsettledActivityQuery {
+            after: positions.last().map(|(product_id, _)| *product_id),
+            products: query.products.clone(),
+        });
+        activity.positions.extend(positions);
+
+        Ok(UnsettledActivityResponse {
+            activity: ValueRecord {
+                valid_from,
+                valid_until: Some(as_of),
+                value: activity,
+                actor: None,
+            },
+            more,
+        })
+    }
+
+    async fn settle_activity(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+    ) -> Result<Result<SettlementRecord<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut state = self.lock();
+
+        // A settlement which has already been made is returned as it stands,
+        // provided it is retried with the configuration now in effect
+        if let Some(posted) = state.posted_settlement(config.as_of) {
+            return Ok(match posted.config {
+                None => Err(SettlementConflict::Reversed {
+                    as_of: config.as_of,
+                }),
+                Some(existing) if existing != config => {
+                    Err(SettlementConflict::Mismatched { existing })
+                }
+                Some(_) => Ok(SettlementRecord {
+                    valid_from: posted.settled_from,
+                    valid_until: config.as_of,
+                    activity: settled_activity(state.settlement_entries(
+                        config.as_of,
+                        0,
+                        posted.revision,
+                    )),
+                }),
+            });
+        }
+
+        let latest = state.latest_settlement();
+        if let Some(latest) = latest.filter(|latest| *latest > config.as_of) {
+            return Ok(Err(SettlementConflict::Superseded { latest }));
+        }
+
+        let settled_from = latest
+            .or_else(|| state.first_batch())
+            .unwrap_or(config.as_of);
+
+        state.atomically(true, |state| {
+            state.settlements.insert(
+                config.as_of,
+                Settlement {
+                    settled_from,
+                    config: config.clone(),
+                },
+            );
+            state.record(config.as_of, MarketEvent::Settled)?;
+
+            let bidders = state
+                .portfolios
+                .values()
+                .map(|portfolio| portfolio.bidder_id)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let rows = state.accrued_activity(
+                Some(&bidders),
+                latest,
+                config.as_of,
+                config.time_unit,
+                None,
+                None,
+                None,
+            );
+            let entries = SettlementRevision {
+                as_of: config.as_of,
+                revision: 0,
+                revised_at: config.as_of,
+                config: Some(config.clone()),
+                activity: state.post_entries(&config, 0, rows),
+            };
+            state.issue_statements(settled_from, &entries);
+
+            Ok(Ok(SettlementRecord {
+                valid_from: settled_from,
+                valid_until: config.as_of,
+                activity: entries.activity,
+            }))
+        })
+    }
+
+    async fn reverse_settlement(
+        &self,
+        as_of: Self::DateTime,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut state = self.lock();
+
+        let Some(posted) = state.posted_settlement(as_of) else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+        if posted.config.is_none() {
+            return Ok(Err(SettlementConflict::Reversed { as_of }));
+        }
+
+        state.atomically(true, |state| {
+            let revision = state.post_reversal(as_of, posted.revision + 1, revised_at)?;
+            state.issue_statements(posted.settled_from, &revision);
+            Ok(Ok(revision))
+        })
+    }
+
+    async fn correct_settlement(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut state = self.lock();
+
+        let as_of = config.as_of;
+        let Some(posted) = state.posted_settlement(as_of) else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+
+        // Repeating the latest correction returns it as it was made
+        if posted.revision > 0 && posted.config.as_ref() == Some(&config) {
+            return Ok(Ok(SettlementRevision {
+                as_of,
+                revision: posted.revision,
+                revised_at: posted.revised_at,
+                activity: settled_activity(state.settlement_entries(
+                    as_of,
+                    posted.revision,
+                    posted.revision,
+                )),
+                config: Some(config),
+            }));
+        }
+
+        state.atomically(true, |state| {
+            let mut revision = posted.revision;
+            if posted.config.is_some() {
+                revision += 1;
+                let reversal = state.post_reversal(as_of, revision, revised_at)?;
+                state.issue_statements(posted.settled_from, &reversal);
+            }
+            revision += 1;
+            state.insert_revision(as_of, revision, revised_at, Some(config.clone()))?;
+
+            // The activity was accrued in units of the original settlement's time
+            // unit, and is re-expressed in those of the correction
+            let scale = posted.original.time_unit / config.time_unit;
+            let rows = state
+                .settlement_entries(as_of, 0, 0)
+                .into_iter()
+                .map(|((bidder_id, product_id), total)| AccruedRow {
+                    bidder_id,
+                    product_id,
+                    accrued: total.accrued * scale,
+                })
+                .collect();
+            let correction = SettlementRevision {
+                as_of,
+                revision,
+                revised_at,
+                activity: state.post_entries(&config, revision, rows),
+                config: Some(config),
+            };
+            state.issue_statements(posted.settled_from, &correction);
+
+            Ok(Ok(correction))
+        })
+    }
+
+    async fn get_settlement_revisions(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<SettlementRevision<Self>>, Self::Error> {
+        let state = self.lock();
+        let mut revisions = state
+            .revisions
+            .iter()
+            .filter(|revision| revision.as_of == as_of)
+            .collect::<Vec<_>>();
+        revisions.sort_by_key(|revision| revision.revision);
+
+        Ok(revisions
+            .into_iter()
+            .map(|revision| SettlementRevision {
+                as_of,
+                revision: revision.revision,
+                revised_at: revision.revised_at,
+                config: revision.config.clone(),
+                activity: settled_activity(state.settlement_entries(
+                    as_of,
+                    revision.revision,
+                    revision.revision,
+                )),
+            })
+            .collect())
+    }
+
+    async fn get_settlement_statement(
+        &self,
+        statement_id: u64,
+    ) -> Result<Option<SettlementStatement<Self>>, Self::Error> {
+        let state = self.lock();
+        Ok(statement_id
+            .checked_sub(1)
+            .and_then(|index| state.statements.get(index as usize))
+            .map(|statement| issued_statement(statement_id, statement)))
+    }
+
+    async fn get_settlement_statements(
+        &self,
+        as_of: Self::DateTime,
+        bidder_id: Option<Self::BidderId>,
+    ) -> Result<Vec<SettlementStatement<Self>>, Self::Error> {
+        let state = self.lock();
+        Ok(state
+            .statements
+            .iter()
+            .zip(1..)
+            .filter(|(statement, _)| statement.as_of == as_of)
+            .filter(|(statement, _)| {
+                bidder_id.is_none_or(|bidder_id| statement.bidder_id == bidder_id)
+            })
+            .map(|(statement, id)| issued_statement(id, statement))
+            .collect())
+    }
+
+    /// Get the bidder's settlement history
+    ///
+    /// This returns a list of settled activity, one record per settlement in
+    /// which the bidder had activity. The records are ordered by the end of
+    /// their settlement interval in descending order.
+    async fn get_settlement_history(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Activity<Self::ProductId>, Self::DateTime>, Self::Error> {
+        let state = self.lock();
+        let mut results = state
+            .settlements
+            .iter()
+            .rev()
+            .filter(|(_, settlement)| in_range(settlement.settled_from, query.after, query.before))
+            .filter_map(|(as_of, settlement)| {
+                // The bidder had activity in the settlement if it was paid
+                let mut payment = None;
+                let mut positions: Map<ProductId> = Map::default();
+                for entry in state
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.as_of == *as_of && entry.bidder_id == bidder_id)
+                {
+                    match entry.product_id {
+                        Some(product_id) => {
+                            *positions.entry(product_id).or_default() += entry.settled
+                        }
+                        None => *payment.get_or_insert(0.0) += entry.settled,
+                    }
+                }
+                Some(ValueRecord {
+                    valid_from: settlement.settled_from,
+                    valid_until: Some(*as_of),
+                    value: Activity {
+                        positions,
+                        payment: payment?,
+                    },
+                    actor: None,
+                })
+            })
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        // Settlement intervals are contiguous, so we page on the end of the
+        // extra interval to ensure it is included in the next page.
+        let more = if results.len() == limit + 1 {
+            let extra = results.pop().unwrap();
+            Some(DateTimeRangeQuery {
+                before: extra.valid_until,
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    async fn get_bidder_summary(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<BidderSummary<Self>, Self::Error> {
+        let unsettled = self
+            .get_unsettled_activity(bidder_id, as_of, time_unit)
+            .await?;
+
+        let state = self.lock();
+        let mut settled = Activity::default();
+        for entry in state
+            .entries
+            .iter()
+            .filter(|entry| entry.bidder_id == bidder_id && entry.as_of <= as_of)
+        {
+            match entry.product_id {
+                Some(product_id) => {
+                    *settled.positions.entry(product_id).or_default() += entry.settled;
+                }
+                None => settled.payment += entry.settled,
+            }
+        }
+
+        // The outcomes in effect are expressed in terms of the contemporary
+        // product basis of the batch that produced them
+        let mut rates: Map<ProductId> = Map::default();
+        for row in state.portfolio_outcomes.iter().filter(|row| {
+            state.portfolios[&row.id].bidder_id == bidder_id
+                && valid_at(row.valid_from, row.valid_until, as_of)
+        }) {
+            let (Some(rate), _) = rate_and_price(&row.value) else {
+                continue;
+            };
+            if rate == 0.0 {
+                continue;
+            }
+            for basis in state.basis_view().filter(|basis| {
+                basis.portfolio_id == row.id
+                    && valid_at(basis.valid_from, basis.valid_until, row.valid_from)
+            }) {
+                *rates.entry(basis.product_id).or_default() += rate * basis.weight;
+            }
+        }
+
+        let mut positions = settled.positions.clone();
+        for (product_id, amount) in unsettled.value.positions.iter() {
+            *positions.entry(*product_id).or_default() += amount;
+        }
+
+        Ok(BidderSummary {
+            as_of,
+            positions,
+            rates,
+            settled,
+            unsettled: unsettled.value,
+            unsettled_from: unsettled.valid_from,
+        })
+    }
+}
@@ -0,0 +1,76 @@
+#![warn(missing_docs)]
+// Note: this overwrites the link in the README to point to the rust docs of the fts-memory crate.
+//! [fts_core]: https://docs.rs/fts_core/latest/fts_core/index.html
+//! [fts_axum]: https://docs.rs/fts_axum/latest/fts_axum/index.html
+//! [fts_solver]: https://docs.rs/fts_solver/latest/fts_solver/index.html
+//! [fts_sqlite]: https://docs.rs/fts_sqlite/latest/fts_sqlite/index.html
+//! [fts_postgres]: https://docs.rs/fts_postgres/latest/fts_postgres/index.html
+//! [fts_memory]: https://docs.rs/fts_memory/latest/fts_memory/index.html
+#![doc = include_str!("../README.md")]
+
+use std::sync::{Arc, Mutex, MutexGuard, atomic::AtomicBool};
+
+mod error;
+mod filter;
+mod r#impl;
+mod state;
+pub mod types;
+
+pub use error::Error;
+use state::State;
+
+/// In-memory implementation of the flow trading repositories.
+///
+/// This struct holds every table of the temporal data model in memory,
+/// implementing all the repository traits defined in `fts-core`. Clones of a
+/// `Db` share the same data, as clones of a connection pool share the same
+/// database. Every operation holds a single lock for its duration, so
+/// operations are atomic and never observe each other partially applied.
+///
+/// # Example
+///
+/// ```
+/// # use fts_memory::{Db, types::DateTime};
+/// let now = DateTime::from(time::OffsetDateTime::now_utc());
+/// let db = Db::new(now);
+/// ```
+#[derive(Clone)]
+pub struct Db {
+    state: Arc<Mutex<State>>,
+    /// Whether the inputs of each batch are recorded
+    record_batch_inputs: bool,
+    /// Whether a batch auction is executing, shared by all clones
+    batch_running: Arc<AtomicBool>,
+}
+
+impl Db {
+    /// Create an empty repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `as_of` - Initial timestamp of the batch table, as for a new database
+    pub fn new(as_of: types::DateTime) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::new(as_of))),
+            record_batch_inputs: false,
+            batch_running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Record (or not) the demand curves and portfolios of each batch run
+    /// through this handle exactly as they were assembled for the solver, so
+    /// that any batch may be solved again.
+    pub fn with_batch_inputs(mut self, record: bool) -> Self {
+        self.record_batch_inputs = record;
+        self
+    }
+
+    /// Lock the data for the duration of an operation
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while the lock is held never leaves the data partially
+        // modified (see `State::atomically`), so the data remains usable
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,836 @@
+//! The tables of the in-memory repository.
+//!
+//! The tables mirror those of the database backends: each entity has a row
+//! holding its current state, and its history is kept in lifetime tables whose
+//! rows are valid over `[valid_from, valid_until)`. The triggers which maintain
+//! the lifetime tables of the databases are methods here, which every write to
+//! an entity goes through. Application data and outcomes are held as JSON, as
+//! the repositories are generic over their types.
+
+use crate::{
+    Db, Error,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::models::{
+    Activity, Actor, Auction, Basis, BatchAttempt, BatchRun, DemandCurve, MarketEvent,
+    SettlementConfig, Weights,
+};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Whether a row valid over `[valid_from, valid_until)` is valid at `as_of`
+pub(crate) fn valid_at(
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    as_of: DateTime,
+) -> bool {
+    valid_from <= as_of && valid_until.is_none_or(|valid_until| as_of < valid_until)
+}
+
+/// The earlier of two times, where None is unbounded (as `least()` in SQL)
+pub(crate) fn coalesce_min(a: Option<DateTime>, b: Option<DateTime>) -> Option<DateTime> {
+    a.or(b).min(b.or(a))
+}
+
+/// Whether `valid_from` lies within the (exclusive) `before` and (inclusive) `after`
+pub(crate) fn in_range(
+    valid_from: DateTime,
+    after: Option<DateTime>,
+    before: Option<DateTime>,
+) -> bool {
+    after.is_none_or(|after| valid_from >= after) && before.is_none_or(|before| valid_from < before)
+}
+
+#[derive(Clone)]
+pub(crate) struct Product {
+    /// The time at which the product was created
+    pub as_of: DateTime,
+    pub app_data: Value,
+    /// The current parent, if any, and the ratio to it
+    pub parent: Option<(ProductId, f64)>,
+}
+
+/// The lifetime of a product's parent and its ratio to it
+#[derive(Clone)]
+pub(crate) struct ParentRow {
+    pub product_id: ProductId,
+    pub parent_id: ProductId,
+    pub ratio: f64,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+}
+
+/// A path of the transitive closure of the product tree, where
+/// value(src) * ratio = value(dst)
+#[derive(Clone)]
+pub(crate) struct ProductPath {
+    pub src_id: ProductId,
+    pub dst_id: ProductId,
+    pub ratio: f64,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Demand {
+    pub as_of: DateTime,
+    pub bidder_id: BidderId,
+    pub app_data: Value,
+    pub curve_data: DemandCurve,
+    pub purged_at: Option<DateTime>,
+}
+
+/// The lifetime of a demand's curve
+#[derive(Clone)]
+pub(crate) struct CurveRow {
+    pub demand_id: DemandId,
+    pub value: DemandCurve,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub actor: Actor,
+}
+
+#[derive(Clone)]
+pub(crate) struct Portfolio {
+    pub as_of: DateTime,
+    pub bidder_id: BidderId,
+    pub app_data: Value,
+    pub demand: Weights<DemandId>,
+    pub basis: Basis<ProductId>,
+    pub purged_at: Option<DateTime>,
+}
+
+/// The lifetime of a member of a portfolio's demand or product group
+#[derive(Clone)]
+pub(crate) struct GroupRow<Id> {
+    pub portfolio_id: PortfolioId,
+    pub id: Id,
+    pub weight: f64,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub actor: Actor,
+}
+
+/// The lifetime of a batch outcome of a portfolio or product
+#[derive(Clone)]
+pub(crate) struct OutcomeRow<Id> {
+    pub id: Id,
+    pub value: Value,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+}
+
+/// The latest batch, whose outcomes are those in effect
+#[derive(Clone)]
+pub(crate) struct Batch {
+    pub as_of: DateTime,
+    pub portfolios: u32,
+    pub products: u32,
+}
+
+#[derive(Clone)]
+pub(crate) struct Settlement {
+    pub settled_from: DateTime,
+    pub config: SettlementConfig<DateTime>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Revision {
+    pub as_of: DateTime,
+    pub revision: u32,
+    pub revised_at: DateTime,
+    /// The configuration of a correction, or None for a reversal
+    pub config: Option<SettlementConfig<DateTime>>,
+}
+
+/// A statement issued to a bidder of the entries posted by a revision
+#[derive(Clone)]
+pub(crate) struct Statement {
+    pub as_of: DateTime,
+    pub revision: u32,
+    pub bidder_id: BidderId,
+    pub settled_from: DateTime,
+    /// The time of the revision, or None for the settlement as originally made
+    pub revised_at: Option<DateTime>,
+    /// The configuration of the entries, or None for a reversal
+    pub config: Option<SettlementConfig<DateTime>>,
+    pub activity: Activity<ProductId>,
+}
+
+/// An entry of a settlement, which is a bidder's position in a product or,
+/// if `product_id` is None, the bidder's payment
+#[derive(Clone)]
+pub(crate) struct Entry {
+    pub as_of: DateTime,
+    pub revision: u32,
+    pub bidder_id: BidderId,
+    pub product_id: Option<ProductId>,
+    pub accrued: f64,
+    pub settled: f64,
+}
+
+#[derive(Clone)]
+pub(crate) struct OutboxRow {
+    pub sequence: u64,
+    pub as_of: DateTime,
+    /// The serialized `MarketEvent`
+    pub event: Value,
+    pub delivered_at: Option<DateTime>,
+}
+
+/// Every table of the repository
+#[derive(Clone)]
+pub(crate) struct State {
+    pub products: BTreeMap<ProductId, Product>,
+    pub product_tree: Vec<ProductPath>,
+    pub product_parent: Vec<ParentRow>,
+    pub demands: BTreeMap<DemandId, Demand>,
+    pub curve_data: Vec<CurveRow>,
+    pub portfolios: BTreeMap<PortfolioId, Portfolio>,
+    pub portfolio_demand: Vec<GroupRow<DemandId>>,
+    pub portfolio_product: Vec<GroupRow<ProductId>>,
+    pub batch: Batch,
+    pub portfolio_outcomes: Vec<OutcomeRow<PortfolioId>>,
+    pub product_outcomes: Vec<OutcomeRow<ProductId>>,
+    pub batch_runs: BTreeMap<DateTime, BatchRun>,
+    pub batch_attempts: BTreeMap<DateTime, BatchAttempt>,
+    /// The serialized `Auction` of each batch whose inputs were recorded
+    pub batch_inputs: BTreeMap<DateTime, String>,
+    pub settlements: BTreeMap<DateTime, Settlement>,
+    pub revisions: Vec<Revision>,
+    pub entries: Vec<Entry>,
+    /// The statements issued, in order, the id of each being its position + 1
+    pub statements: Vec<Statement>,
+    pub outbox: Vec<OutboxRow>,
+}
+
+impl State {
+    pub fn new(as_of: DateTime) -> Self {
+        Self {
+            products: BTreeMap::new(),
+            product_tree: Vec::new(),
+            product_parent: Vec::new(),
+            demands: BTreeMap::new(),
+            curve_data: Vec::new(),
+            portfolios: BTreeMap::new(),
+            portfolio_demand: Vec::new(),
+            portfolio_product: Vec::new(),
+            batch: Batch {
+                as_of,
+                portfolios: 0,
+                products: 0,
+            },
+            portfolio_outcomes: Vec::new(),
+            product_outcomes: Vec::new(),
+            batch_runs: BTreeMap::new(),
+            batch_attempts: BTreeMap::new(),
+            batch_inputs: BTreeMap::new(),
+            settlements: BTreeMap::new(),
+            revisions: Vec::new(),
+            entries: Vec::new(),
+            statements: Vec::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    /// Apply `f` to the tables as a transaction: if it fails, or `commit` is
+    /// false, the tables are left as they were.
+    pub fn atomically<R>(
+        &mut self,
+        commit: bool,
+        f: impl FnOnce(&mut Self) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let saved = self.clone();
+        let result = f(self);
+        if result.is_err() || !commit {
+            *self = saved;
+        }
+        result
+    }
+
+    /// Record a market event in the outbox
+    pub fn record(&mut self, as_of: DateTime, event: MarketEvent<Db>) -> Result<(), Error> {
+        let sequence = self.outbox.last().map_or(0, |row| row.sequence) + 1;
+        self.outbox.push(OutboxRow {
+            sequence,
+            as_of,
+            event: serde_json::to_value(event)?,
+            delivered_at: None,
+        });
+        Ok(())
+    }
+
+    /// Rewrite the recorded inputs of every batch, applying `redact` to each,
+    /// which returns whether it changed them (e.g. to erase a purged bid)
+    pub fn redact_batch_inputs(
+        &mut self,
+        redact: impl Fn(&mut Auction<Db>) -> bool,
+    ) -> Result<(), Error> {
+        for inputs in self.batch_inputs.values_mut() {
+            let mut auction = serde_json::from_str(inputs)?;
+            if redact(&mut auction) {
+                *inputs = serde_json::to_string(&auction)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert a product, extending the product tree to any parent
+    pub fn insert_product(
+        &mut self,
+        product_id: ProductId,
+        as_of: DateTime,
+        app_data: Value,
+        parent: Option<(ProductId, f64)>,
+    ) -> Result<(), Error> {
+        if self.products.contains_key(&product_id) {
+            return Err(Error::duplicate("product", product_id));
+        }
+
+        if let Some((parent_id, parent_ratio)) = parent {
+            if !self.products.contains_key(&parent_id) {
+                return Err(Error::missing("product", parent_id));
+            }
+
+            // Invalidate current paths to the parent, extending them to
+            // include the new product. Those just invalidated by a sibling
+            // are extended, too.
+            let mut extended = Vec::new();
+            for path in self.product_tree.iter_mut().filter(|path| {
+                path.dst_id == parent_id
+                    && path
+                        .valid_until
+                        .is_none_or(|valid_until| valid_until == as_of)
+            }) {
+                path.valid_until = Some(as_of);
+                extended.push(ProductPath {
+                    src_id: path.src_id,
+                    dst_id: product_id,
+                    ratio: path.ratio * parent_ratio,
+                    valid_from: as_of,
+                    valid_until: None,
+                });
+            }
+            self.product_tree.extend(extended);
+            self.product_parent.push(ParentRow {
+                product_id,
+                parent_id,
+                ratio: parent_ratio,
+                valid_from: as_of,
+                valid_until: None,
+            });
+        }
+
+        // The "leaf" path of the product
+        self.product_tree.push(ProductPath {
+            src_id: product_id,
+            dst_id: product_id,
+            ratio: 1.0,
+            valid_from: as_of,
+            valid_until: None,
+        });
+
+        self.products.insert(
+            product_id,
+            Product {
+                as_of,
+                app_data,
+                parent,
+            },
+        );
+        self.record(as_of, MarketEvent::ProductCreated { product_id })
+    }
+
+    /// The product and its current ancestors, each with its ratio to the product
+    pub fn lineage(&self, product_id: ProductId) -> Vec<(ProductId, f64)> {
+        let mut lineage = vec![(product_id, 1.0)];
+        let mut current = product_id;
+        while let Some((parent_id, ratio)) = self.products[&current].parent {
+            let scale = ratio * lineage.last().unwrap().1;
+            lineage.push((parent_id, scale));
+            current = parent_id;
+        }
+        lineage
+    }
+
+    /// Move a product under `parent_id` with the given ratio, which may be its
+    /// current parent, ending the current paths through the product and
+    /// starting those through its new parent.
+    pub fn move_product(
+        &mut self,
+        product_id: ProductId,
+        parent_id: ProductId,
+        ratio: f64,
+        as_of: DateTime,
+    ) -> Result<(), Error> {
+        let leaves: Vec<(ProductId, f64)> = self
+            .product_tree
+            .iter()
+            .filter(|path| path.src_id == product_id && path.valid_until.is_none())
+            .map(|path| (path.dst_id, path.ratio))
+            .collect();
+
+        // Invalidate the paths from the former ancestors to the product's leaves
+        let old_parent = self.products[&product_id].parent.map(|(id, _)| id);
+        if let Some(old_parent) = old_parent {
+            let ancestors = self.lineage(old_parent);
+            for path in self.product_tree.iter_mut().filter(|path| {
+                path.valid_until.is_none()
+                    && ancestors.iter().any(|(id, _)| *id == path.src_id)
+                    && leaves.iter().any(|(id, _)| *id == path.dst_id)
+            }) {
+                path.valid_until = Some(as_of);
+            }
+        }
+
+        for row in self
+            .product_parent
+            .iter_mut()
+            .filter(|row| row.product_id == product_id && row.valid_until.is_none())
+        {
+            row.valid_until = Some(as_of);
+        }
+        self.product_parent.push(ParentRow {
+            product_id,
+            parent_id,
+            ratio,
+            valid_from: as_of,
+            valid_until: None,
+        });
+        self.products.get_mut(&product_id).unwrap().parent = Some((parent_id, ratio));
+
+        // A former parent left without children is its own basis element again
+        if let Some(old_parent) = old_parent.filter(|id| *id != parent_id) {
+            let childless = !self
+                .products
+                .values()
+                .any(|product| product.parent.is_some_and(|(id, _)| id == old_parent));
+            if childless {
+                for (src_id, ratio) in self.lineage(old_parent) {
+                    self.product_tree.push(ProductPath {
+                        src_id,
+                        dst_id: old_parent,
+                        ratio,
+                        valid_from: as_of,
+                        valid_until: None,
+                    });
+                }
+            }
+        }
+
+        // A new parent which was its own basis element ceases to be one
+        let leaf = self.product_tree.iter().any(|path| {
+            path.src_id == parent_id && path.dst_id == parent_id && path.valid_until.is_none()
+        });
+        if leaf {
+            for path in self
+                .product_tree
+                .iter_mut()
+                .filter(|path| path.dst_id == parent_id && path.valid_until.is_none())
+            {
+                path.valid_until = Some(as_of);
+            }
+        }
+
+        for (src_id, scale) in self.lineage(parent_id) {
+            for (dst_id, weight) in leaves.iter() {
+                self.product_tree.push(ProductPath {
+                    src_id,
+                    dst_id: *dst_id,
+                    ratio: scale * ratio * weight,
+                    valid_from: as_of,
+                    valid_until: None,
+                });
+            }
+        }
+
+        // Rows both started and ended at `as_of` were never in effect
+        self.product_tree
+            .retain(|path| path.valid_until != Some(path.valid_from));
+        self.product_parent
+            .retain(|row| row.valid_until != Some(row.valid_from));
+
+        self.record(as_of, MarketEvent::ProductMoved { product_id })
+    }
+
+    /// Insert a demand, starting the lifetime of its curve
+    pub fn insert_demand(
+        &mut self,
+        demand_id: DemandId,
+        bidder_id: BidderId,
+        app_data: Value,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<(), Error> {
+        if self.demands.contains_key(&demand_id) {
+            return Err(Error::duplicate("demand", demand_id));
+        }
+
+        self.curve_data.push(CurveRow {
+            demand_id,
+            value: curve_data.clone(),
+            valid_from: as_of,
+            valid_until: None,
+            actor,
+        });
+        self.demands.insert(
+            demand_id,
+            Demand {
+                as_of,
+                bidder_id,
+                app_data,
+                curve_data,
+                purged_at: None,
+            },
+        );
+        self.record(
+            as_of,
+            MarketEvent::DemandCreated {
+                demand_id,
+                bidder_id,
+            },
+        )
+    }
+
+    /// Update a demand's curve, ending the lifetime of the previous one
+    pub fn update_demand(
+        &mut self,
+        demand_id: DemandId,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<Option<&Demand>, Error> {
+        let Some(demand) = self.demands.get(&demand_id) else {
+            return Ok(None);
+        };
+        let (previous, bidder_id) = (demand.as_of, demand.bidder_id);
+
+        // The lifetimes are keyed by their start, as in the databases
+        if previous == as_of {
+            return Err(Error::duplicate(
+                "curve",
+                format!("{demand_id} as of {as_of}"),
+            ));
+        }
+        for row in self
+            .curve_data
+            .iter_mut()
+            .filter(|row| row.demand_id == demand_id && row.valid_from == previous)
+        {
+            row.valid_until = Some(as_of);
+        }
+        self.curve_data.push(CurveRow {
+            demand_id,
+            value: curve_data.clone(),
+            valid_from: as_of,
+            valid_until: None,
+            actor,
+        });
+
+        self.record(
+            as_of,
+            MarketEvent::DemandUpdated {
+                demand_id,
+                bidder_id,
+            },
+        )?;
+
+        let demand = self.demands.get_mut(&demand_id).unwrap();
+        demand.as_of = as_of;
+        demand.curve_data = curve_data;
+        Ok(Some(demand))
+    }
+
+    /// Insert a portfolio, starting the lifetimes of its groups
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert_portfolio(
+        &mut self,
+        portfolio_id: PortfolioId,
+        bidder_id: BidderId,
+        app_data: Value,
+        demand: Weights<DemandId>,
+        basis: Basis<ProductId>,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<(), Error> {
+        if self.portfolios.contains_key(&portfolio_id) {
+            return Err(Error::duplicate("portfolio", portfolio_id));
+        }
+
+        self.push_demand_group(portfolio_id, &demand, actor, as_of)?;
+        self.push_product_group(portfolio_id, &basis, actor, as_of)?;
+        self.portfolios.insert(
+            portfolio_id,
+            Portfolio {
+                as_of,
+                bidder_id,
+                app_data,
+                demand,
+                basis,
+                purged_at: None,
+            },
+        );
+        self.record(
+            as_of,
+            MarketEvent::PortfolioCreated {
+                portfolio_id,
+                bidder_id,
+            },
+        )
+    }
+
+    /// Update either or both of a portfolio's groups, ending the lifetimes of
+    /// those they replace
+    pub fn update_portfolio(
+        &mut self,
+        portfolio_id: PortfolioId,
+        demand: Option<Weights<DemandId>>,
+        basis: Option<Basis<ProductId>>,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<Option<&Portfolio>, Error> {
+        let Some(portfolio) = self.portfolios.get(&portfolio_id) else {
+            return Ok(None);
+        };
+        let (previous, bidder_id) = (portfolio.as_of, portfolio.bidder_id);
+
+        if let Some(demand) = demand.as_ref() {
+            end_group(&mut self.portfolio_demand, portfolio_id, previous, as_of);
+            self.push_demand_group(portfolio_id, demand, actor, as_of)?;
+        }
+        if let Some(basis) = basis.as_ref() {
+            end_group(&mut self.portfolio_product, portfolio_id, previous, as_of);
+            self.push_product_group(portfolio_id, basis, actor, as_of)?;
+        }
+
+        self.record(
+            as_of,
+            MarketEvent::PortfolioUpdated {
+                portfolio_id,
+                bidder_id,
+            },
+        )?;
+
+        let portfolio = self.portfolios.get_mut(&portfolio_id).unwrap();
+        portfolio.as_of = as_of;
+        if let Some(demand) = demand {
+            portfolio.demand = demand;
+        }
+        if let Some(basis) = basis {
+            portfolio.basis = basis;
+        }
+        Ok(Some(portfolio))
+    }
+
+    /// The paths of the product tree, derived anew from the products' parents
+    /// rather than maintained as the tree changes
+    pub fn derive_product_tree(&self) -> Vec<ProductPath> {
+        // The tree only changes when a product is created or a parent changes
+        let mut times: Vec<DateTime> = self
+            .products
+            .values()
+            .map(|product| product.as_of)
+            .chain(
+                self.product_parent
+                    .iter()
+                    .flat_map(|row| std::iter::once(row.valid_from).chain(row.valid_until)),
+            )
+            .collect();
+        times.sort();
+        times.dedup();
+
+        let mut paths = Vec::new();
+        let mut open: BTreeMap<(ProductId, ProductId), ProductPath> = BTreeMap::new();
+        for as_of in times {
+            let mut children: HashMap<ProductId, Vec<(ProductId, f64)>> = HashMap::new();
+            for row in self
+                .product_parent
+                .iter()
+                .filter(|row| valid_at(row.valid_from, row.valid_until, as_of))
+            {
+                children
+                    .entry(row.parent_id)
+                    .or_default()
+                    .push((row.product_id, row.ratio));
+            }
+
+            // Each product existing at the time has a path to each of its leaves
+            let mut current = BTreeMap::new();
+            for (&src_id, _) in self
+                .products
+                .iter()
+                .filter(|(_, product)| product.as_of <= as_of)
+            {
+                let mut stack = vec![(src_id, 1.0, 0)];
+                while let Some((id, ratio, depth)) = stack.pop() {
+                    match children.get(&id) {
+                        // The parents of a corrupted tree may form a cycle,
+                        // which is not followed indefinitely
+                        Some(_) if depth > self.products.len() => {}
+                        Some(children) => {
+                            stack.extend(children.iter().map(|&(child_id, child_ratio)| {
+                                (child_id, ratio * child_ratio, depth + 1)
+                            }))
+                        }
+                        None => {
+                            current.insert((src_id, id), ratio);
+                        }
+                    }
+                }
+            }
+
+            // A path whose ratio changed ends, and another begins
+            open.retain(|key, path| {
+                if current.get(key) == Some(&path.ratio) {
+                    true
+                } else {
+                    path.valid_until = Some(as_of);
+                    paths.push(path.clone());
+                    false
+                }
+            });
+            for ((src_id, dst_id), ratio) in current {
+                open.entry((src_id, dst_id)).or_insert(ProductPath {
+                    src_id,
+                    dst_id,
+                    ratio,
+                    valid_from: as_of,
+                    valid_until: None,
+                });
+            }
+        }
+        paths.extend(open.into_values());
+        paths
+    }
+
+    /// Restart the current groups of a portfolio from the portfolio itself as
+    /// of its latest update, replacing whichever rows are current
+    pub fn restart_groups(&mut self, portfolio_id: PortfolioId) {
+        let portfolio = &self.portfolios[&portfolio_id];
+        let as_of = portfolio.as_of;
+        restart_group(
+            &mut self.portfolio_demand,
+            portfolio_id,
+            portfolio.demand.iter().map(|(&id, &weight)| (id, weight)),
+            as_of,
+        );
+        restart_group(
+            &mut self.portfolio_product,
+            portfolio_id,
+            portfolio.basis.iter().map(|(&id, &weight)| (id, weight)),
+            as_of,
+        );
+    }
+
+    fn push_demand_group(
+        &mut self,
+        portfolio_id: PortfolioId,
+        demand: &Weights<DemandId>,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<(), Error> {
+        for (&demand_id, &weight) in demand.iter() {
+            if !self.demands.contains_key(&demand_id) {
+                return Err(Error::missing("demand", demand_id));
+            }
+            push_group(
+                &mut self.portfolio_demand,
+                portfolio_id,
+                demand_id,
+                weight,
+                actor,
+                as_of,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn push_product_group(
+        &mut self,
+        portfolio_id: PortfolioId,
+        basis: &Basis<ProductId>,
+        actor: Actor,
+        as_of: DateTime,
+    ) -> Result<(), Error> {
+        for (&product_id, &weight) in basis.iter() {
+            if !self.products.contains_key(&product_id) {
+                return Err(Error::missing("product", product_id));
+            }
+            push_group(
+                &mut self.portfolio_product,
+                portfolio_id,
+                product_id,
+                weight,
+                actor,
+                as_of,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// End the lifetime of the current group of a portfolio
+fn end_group<Id>(
+    rows: &mut [GroupRow<Id>],
+    portfolio_id: PortfolioId,
+    previous: DateTime,
+    as_of: DateTime,
+) {
+    for row in rows.iter_mut().filter(|row| {
+        row.portfolio_id == portfolio_id && row.valid_from <= previous && row.valid_until.is_none()
+    }) {
+        row.valid_until = Some(as_of);
+    }
+}
+
+/// Replace the current group of a portfolio by `group` as of `as_of`
+fn restart_group<Id>(
+    rows: &mut Vec<GroupRow<Id>>,
+    portfolio_id: PortfolioId,
+    group: impl Iterator<Item = (Id, f64)>,
+    as_of: DateTime,
+) {
+    rows.retain(|row| {
+        row.portfolio_id != portfolio_id || row.valid_until.is_some() || row.valid_from < as_of
+    });
+    end_group(rows, portfolio_id, as_of, as_of);
+    rows.extend(group.map(|(id, weight)| GroupRow {
+        portfolio_id,
+        id,
+        weight,
+        valid_from: as_of,
+        valid_until: None,
+        actor: Actor::System,
+    }));
+}
+
+/// Start the lifetime of a member of a portfolio's group
+fn push_group<Id: PartialEq + std::fmt::Display>(
+    rows: &mut Vec<GroupRow<Id>>,
+    portfolio_id: PortfolioId,
+    id: Id,
+    weight: f64,
+    actor: Actor,
+    as_of: DateTime,
+) -> Result<(), Error> {
+    // The lifetimes are keyed by their start, as in the databases
+    if rows
+        .iter()
+        .any(|row| row.portfolio_id == portfolio_id && row.id == id && row.valid_from == as_of)
+    {
+        return Err(Error::duplicate(
+            "group member",
+            format!("{id} of {portfolio_id} as of {as_of}"),
+        ));
+    }
+    rows.push(GroupRow {
+        portfolio_id,
+        id,
+        weight,
+        valid_from: as_of,
+        valid_until: None,
+        actor,
+    });
+    Ok(())
+}
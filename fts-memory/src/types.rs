@@ -0,0 +1,11 @@
+//! Type definitions for the in-memory implementation.
+//!
+//! The public types include strongly-typed IDs and datetime representations
+//! that ensure type safety across the system. They serialize exactly as those
+//! of the database backends, so that an application may switch between them.
+
+mod datetime;
+pub use datetime::DateTime;
+
+mod ids;
+pub use ids::{BidderId, DemandId, PortfolioId, ProductId};
@@ -0,0 +1,111 @@
+//! DateTime type for temporal data in flow trading.
+//!
+//! This module provides a [`DateTime`] type that represents UTC timestamps with
+//! subsecond precision. It wraps `time::OffsetDateTime` and ensures all
+//! serialization happens in RFC3339 format for consistency across the system.
+
+use std::{borrow::Borrow, fmt::Display};
+use time::format_description::well_known::Rfc3339;
+
+/// A type that represents a datetime with subsecond precision.
+///
+/// This type is used throughout the flow trading system to represent timestamps
+/// for events, validity periods, and historical records. It ensures:
+///
+/// - All times are stored and processed in UTC
+/// - Serialization/deserialization uses RFC3339 format
+///
+/// Unlike the database backends, timestamps are stored exactly as provided,
+/// with no loss of precision.
+///
+/// # Examples
+///
+/// ```
+/// # use fts_memory::types::DateTime;
+/// # use time::OffsetDateTime;
+/// let now = OffsetDateTime::now_utc();
+/// let datetime = DateTime::from(now);
+/// println!("{}", datetime); // Prints in RFC3339 format
+/// ```
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(from = "DateTimeDto", into = "DateTimeDto")]
+pub struct DateTime(time::OffsetDateTime);
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format(&Rfc3339).unwrap())
+    }
+}
+
+impl<T: Borrow<time::OffsetDateTime>> From<T> for DateTime {
+    fn from(value: T) -> Self {
+        Self(value.borrow().to_offset(time::UtcOffset::UTC))
+    }
+}
+
+impl DateTime {
+    /// The number of seconds elapsed from `self` until `until`, which is
+    /// negative if `until` precedes `self`
+    pub(crate) fn seconds_until(self, until: Self) -> f64 {
+        (until.0 - self.0).as_seconds_f64()
+    }
+
+    /// The start of the hour or day containing this time
+    pub(crate) fn truncate(self, day: bool) -> Self {
+        let truncated = if day {
+            self.0.replace_time(time::Time::MIDNIGHT)
+        } else {
+            self.0
+                .replace_time(time::Time::from_hms(self.0.hour(), 0, 0).unwrap())
+        };
+        Self(truncated)
+    }
+
+    /// The time `span` after `self`
+    pub(crate) fn add(self, span: time::Duration) -> Self {
+        Self(self.0 + span)
+    }
+}
+
+impl From<DateTime> for time::OffsetDateTime {
+    fn from(value: DateTime) -> Self {
+        value.0
+    }
+}
+
+// This is a helper type that ensures (de)serialization happens with respect to RFC3339
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DateTimeDto(#[serde(with = "time::serde::rfc3339")] time::OffsetDateTime);
+
+impl From<DateTimeDto> for DateTime {
+    fn from(value: DateTimeDto) -> Self {
+        value.0.into()
+    }
+}
+
+impl From<DateTime> for DateTimeDto {
+    fn from(value: DateTime) -> Self {
+        DateTimeDto(value.into())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for DateTime {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "DateTime".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "format": "date-time",
+        })
+    }
+}
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/app_data_search.rs"]
+mod app_data_search;
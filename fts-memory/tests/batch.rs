@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/batch.rs"]
+mod batch;
@@ -0,0 +1,90 @@
+use fts_core::ports::Application;
+use fts_solver::clarabel::ClarabelSolver;
+use types::{BidderId, DateTime, DemandId, PortfolioId, ProductId};
+
+// The backend under test, as seen by the shared repository tests
+pub use fts_memory::{Db, types};
+
+pub struct TestApp(pub Db);
+
+// Not every test uses each fixture
+#[allow(dead_code)]
+impl TestApp {
+    /// Open an empty market (which is always available)
+    pub async fn open(as_of: DateTime) -> anyhow::Result<Option<Self>> {
+        Ok(Some(Self(Db::new(as_of))))
+    }
+
+    pub fn record_batch_inputs(&mut self, record: bool) {
+        self.0 = self.0.clone().with_batch_inputs(record);
+    }
+}
+
+impl Application for TestApp {
+    type Context = ();
+    type DemandData = ();
+    type PortfolioData = ();
+    type ProductData = ();
+    type Repository = Db;
+    type Solver = ClarabelSolver<DemandId, PortfolioId, ProductId>;
+
+    fn database(&self) -> &Self::Repository {
+        &self.0
+    }
+
+    fn now(&self) -> DateTime {
+        time::OffsetDateTime::now_utc().into()
+    }
+
+    fn solver(&self) -> Self::Solver {
+        ClarabelSolver::default()
+    }
+
+    fn generate_demand_id(&self, _data: &Self::DemandData) -> (DemandId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_portfolio_id(&self, _data: &Self::PortfolioData) -> (PortfolioId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_product_id(&self, _data: &Self::ProductData) -> (ProductId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    async fn can_create_bid(&self, _context: &Self::Context) -> Option<BidderId> {
+        None
+    }
+
+    async fn can_query_bid(&self, _context: &Self::Context) -> Vec<BidderId> {
+        Vec::new()
+    }
+
+    async fn can_read_bid(&self, _context: &Self::Context, _bidder_id: BidderId) -> bool {
+        false
+    }
+
+    async fn can_update_bid(&self, _context: &Self::Context, _bidder_id: BidderId) -> bool {
+        false
+    }
+
+    async fn can_view_products(&self, _context: &Self::Context) -> bool {
+        true
+    }
+
+    async fn can_manage_products(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_batch(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_settlement(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_purge_bid(&self, _context: &Self::Context) -> bool {
+        false
+    }
+}
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/consistency.rs"]
+mod consistency;
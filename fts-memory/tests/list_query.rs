@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/list_query.rs"]
+mod list_query;
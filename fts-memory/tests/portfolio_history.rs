@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/portfolio_history.rs"]
+mod portfolio_history;
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/product_expansion.rs"]
+mod product_expansion;
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/product_search.rs"]
+mod product_search;
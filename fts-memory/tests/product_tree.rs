@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/product_tree.rs"]
+mod product_tree;
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/purge.rs"]
+mod purge;
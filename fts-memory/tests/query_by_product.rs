@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/query_by_product.rs"]
+mod query_by_product;
@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/settlement.rs"]
+mod settlement;
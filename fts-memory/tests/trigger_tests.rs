@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/trigger_tests.rs"]
+mod trigger_tests;
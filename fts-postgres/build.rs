@@ -0,0 +1,4 @@
+fn main() {
+    println!("cargo:rerun-if-changed=queries");
+    println!("cargo:rerun-if-changed=schema");
+}
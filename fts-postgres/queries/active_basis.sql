@@ -0,0 +1,18 @@
+-- The product weights of the portfolios at $1, one row per product, from
+-- which the bases of the active portfolios are assembled. The weights are read
+-- from portfolio_product (through basis_view, which expands them onto the
+-- products they were partitioned into) rather than aggregated into JSON, so
+-- that a portfolio with a large basis is not serialized and parsed anew for
+-- every batch.
+select
+    portfolio_id,
+    product_id,
+    weight
+from
+    basis_view
+where
+    valid_from <= $1
+and
+    ($1 < valid_until or valid_until is null)
+order by
+    portfolio_id, product_id
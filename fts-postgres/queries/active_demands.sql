@@ -0,0 +1,44 @@
+-- A demand is considered active if and only if
+-- * it has non-null curve data, AND
+-- * it is associated to at least 1 portfolio.
+with
+portfolio_by_id as (
+    select
+        demand_id,
+        min(valid_until) as expires
+    from
+        portfolio_demand
+    where
+        valid_from <= $1
+    and
+        ($1 < valid_until or valid_until is null)
+    group by
+        demand_id
+),
+
+curve_data_by_id as (
+    select
+        demand_id,
+        valid_until as expires,
+        value
+    from
+        curve_data
+    where
+        value is not null
+    and
+        valid_from <= $1
+    and
+        ($1 < valid_until or valid_until is null)
+)
+
+-- least() ignores nulls
+select
+    demand_id as id,
+    least(portfolio_by_id.expires, curve_data_by_id.expires) as expires,
+    curve_data_by_id.value
+from
+    portfolio_by_id
+join
+    curve_data_by_id
+using
+    (demand_id)
@@ -0,0 +1,45 @@
+-- A portfolio is considered active if and only if
+-- * it has at least one associated demand, AND
+-- * it has at least one associated product.
+-- Their product weights are read separately, row by row, from active_basis.sql.
+with
+demand_by_id as (
+    select
+        portfolio_id,
+        min(valid_until) as expires,
+        jsonb_object_agg(demand_id, weight) as dgroup
+    from
+        portfolio_demand
+    where
+        valid_from <= $1
+    and
+        ($1 < valid_until or valid_until is null)
+    group by
+        portfolio_id
+),
+
+basis_by_id as (
+    select
+        portfolio_id,
+        min(valid_until) as expires
+    from
+        basis_view
+    where
+        valid_from <= $1
+    and
+        ($1 < valid_until or valid_until is null)
+    group by
+        portfolio_id
+)
+
+-- least() ignores nulls
+select
+    portfolio_id as id,
+    least(demand_by_id.expires, basis_by_id.expires) as expires,
+    dgroup as demand
+from
+    demand_by_id
+join
+    basis_by_id
+using
+    (portfolio_id)
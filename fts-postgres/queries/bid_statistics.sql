@@ -0,0 +1,57 @@
+-- fn(as_of: DateTime) -> BidStatisticsRow
+--
+-- Count the active portfolios whose (expanded) basis includes each product,
+-- along with the active demands associated to them. As in the batch auction,
+-- a portfolio is active if it has at least one demand and one product.
+with
+portfolio_cte as (
+    select distinct
+        basis_view.product_id,
+        basis_view.portfolio_id
+    from
+        basis_view
+    where
+        basis_view.valid_from <= $1
+        and
+        ($1 < basis_view.valid_until or basis_view.valid_until is null)
+        and
+        exists (
+            select
+                1
+            from
+                portfolio_demand
+            where
+                portfolio_demand.portfolio_id = basis_view.portfolio_id
+                and
+                portfolio_demand.valid_from <= $1
+                and
+                ($1 < portfolio_demand.valid_until or portfolio_demand.valid_until is null)
+        )
+)
+
+select
+    portfolio_cte.product_id as product_id,
+    count(distinct portfolio_cte.portfolio_id) as portfolios,
+    count(distinct curve_data.demand_id) as demands
+from
+    portfolio_cte
+left join
+    portfolio_demand
+    on
+        portfolio_demand.portfolio_id = portfolio_cte.portfolio_id
+        and
+        portfolio_demand.valid_from <= $1
+        and
+        ($1 < portfolio_demand.valid_until or portfolio_demand.valid_until is null)
+left join
+    curve_data
+    on
+        curve_data.demand_id = portfolio_demand.demand_id
+        and
+        curve_data.value is not null
+        and
+        curve_data.valid_from <= $1
+        and
+        ($1 < curve_data.valid_until or curve_data.valid_until is null)
+group by
+    portfolio_cte.product_id
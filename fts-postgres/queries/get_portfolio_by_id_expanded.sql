@@ -0,0 +1,74 @@
+-- fn(portfolio_id: PortfolioId, as_of: DateTime) -> PortfolioRow
+with
+app_data_cte as (
+    select
+        id as portfolio_id,
+        bidder_id,
+        app_data as value,
+        as_of
+    from
+        portfolio
+    where
+        id = $1
+        and
+        purged_at is null
+),
+
+demand_cte as (
+    select
+        portfolio_id,
+        max(valid_from) as valid_from,
+        min(valid_until) as valid_until,
+        jsonb_object_agg(demand_id, weight) as value
+    from
+        portfolio_demand
+    where
+        portfolio_id = $1
+        and
+        valid_from <= $2
+        and
+        ($2 < valid_until or valid_until is null)
+    group by
+        portfolio_id
+),
+
+basis_cte as (
+    select
+        portfolio_id,
+        max(valid_from) as valid_from,
+        min(valid_until) as valid_until,
+        jsonb_object_agg(product_id, weight) as value
+    from
+        basis_view
+    where
+        portfolio_id = $1
+        and
+        valid_from <= $2
+        and
+        ($2 < valid_until or valid_until is null)
+    group by
+        portfolio_id
+)
+
+-- greatest() and least() ignore nulls
+select
+    app_data_cte.portfolio_id as id,
+    coalesce(
+        greatest(demand_cte.valid_from, basis_cte.valid_from),
+        app_data_cte.as_of
+    ) as valid_from,
+    least(demand_cte.valid_until, basis_cte.valid_until) as valid_until,
+    app_data_cte.bidder_id,
+    app_data_cte.value as app_data,
+    demand_cte.value as demand,
+    basis_cte.value as basis
+from
+    app_data_cte
+left join
+    demand_cte
+    using
+        (portfolio_id)
+left join
+    basis_cte
+    using
+        (portfolio_id);
@@ -0,0 +1,34 @@
+-- fn(product_id: ProductId, as_of: DateTime) -> ProductRow
+select
+    product.id,
+    product.app_data,
+    coalesce(
+        (
+            select
+                jsonb_build_array(product_parent.parent_id, product_parent.ratio)
+            from
+                product_parent
+            where
+                product_parent.product_id = product.id
+            and
+                product_parent.valid_from <= $2
+            and
+                ($2 < product_parent.valid_until or product_parent.valid_until is null)
+        ),
+        jsonb_build_array(product.id, 1.0)
+    ) as parent,
+    jsonb_object_agg(product_tree.dst_id, product_tree.ratio) as basis
+from
+    product
+join
+    product_tree
+on
+    product.id = product_tree.src_id
+where
+    product.id = $1
+and
+    product_tree.valid_from <= $2
+and
+    ($2 < product_tree.valid_until or product_tree.valid_until is null)
+group by
+    product.id
@@ -0,0 +1,56 @@
+-- fn(after: Option<DateTime>, until: DateTime, as_of: DateTime, time_unit: f64) -> OutcomeStatisticsRow
+--
+-- Aggregate the outcomes of every product: the most recent price as of
+-- `as_of`, and the volume traded over [after, until), measuring time in units
+-- of `time_unit` seconds. Outcomes still in effect are considered to end at
+-- `until`.
+with
+volume_cte as (
+    select
+        product_id,
+        coalesce(sum(
+            coalesce((value ->> 'rate')::double precision, 0.0) * greatest(
+                extract(epoch from
+                    least(coalesce(valid_until, $2), $2)
+                    - greatest(valid_from, coalesce($1, valid_from))
+                )::double precision,
+                0.0
+            ) / $4
+        ), 0.0) as volume
+    from
+        product_outcome
+    where
+        valid_from < $2
+        and
+        ($1::timestamptz is null or valid_until is null or $1 < valid_until)
+    group by
+        product_id
+),
+
+price_cte as (
+    select distinct on (product_id)
+        product_id,
+        (value ->> 'price')::double precision as price,
+        valid_from as cleared
+    from
+        product_outcome
+    where
+        valid_from <= $3
+        and
+        value ->> 'price' is not null
+    order by
+        product_id,
+        valid_from desc
+)
+
+select
+    product_id,
+    price_cte.price,
+    price_cte.cleared,
+    coalesce(volume_cte.volume, 0.0) as volume
+from
+    price_cte
+full outer join
+    volume_cte
+using
+    (product_id)
@@ -0,0 +1,62 @@
+-- fn(product_id: ProductId, after: Option<DateTime>, before: Option<DateTime>, as_of: DateTime, time_unit: f64, field: &str, span: &str, limit: i64) -> ValueRow<PriceSummary>
+--
+-- Aggregate the outcomes of a product into buckets, where `field` is the
+-- precision to which date_trunc truncates a timestamp to the start of its
+-- bucket and `span` is the interval advancing the start of a bucket to its
+-- end. Outcomes still in effect are considered to end at `as_of`.
+with
+outcome_cte as (
+    select
+        date_trunc($6, valid_from, 'UTC') as bucket,
+        valid_from,
+        (value ->> 'price')::double precision as price,
+        coalesce((value ->> 'rate')::double precision, 0.0) as rate,
+        greatest(
+            extract(epoch from coalesce(valid_until, $4) - valid_from)::double precision,
+            0.0
+        ) as duration
+    from
+        product_outcome
+    where
+        product_id = $1
+        and
+        ($2::timestamptz is null or valid_from >= $2)
+        and
+        ($3::timestamptz is null or valid_from < $3)
+),
+
+window_cte as (
+    select
+        bucket,
+        price,
+        rate,
+        duration,
+        first_value(price) over (
+            partition by bucket order by valid_from asc
+        ) as open,
+        first_value(price) over (
+            partition by bucket order by valid_from desc
+        ) as close
+    from
+        outcome_cte
+)
+
+select
+    bucket as valid_from,
+    bucket + $7::interval as valid_until,
+    jsonb_build_object(
+        'open', max(open),
+        'high', max(price),
+        'low', min(price),
+        'close', max(close),
+        'twap', sum(price * duration) / nullif(sum(case when price is null then 0.0 else duration end), 0.0),
+        'volume', coalesce(sum(rate * duration) / $5, 0.0),
+        'batches', count(*)
+    ) as value
+from
+    window_cte
+group by
+    bucket
+order by
+    bucket desc
+limit $8
@@ -0,0 +1,88 @@
+create table product (
+    id uuid primary key,
+    -- the datetime at which this product was created
+    as_of timestamptz not null,
+    -- arbitrary data for a client application to associate
+    app_data jsonb not null,
+    -- products can optionally have parents
+    parent_id uuid references product (id),
+    parent_ratio double precision default 0.0 not null
+);
+--
+-- products can be decomposed over time
+-- this table represents a transitive closure over the hierarchical tree of products
+create table product_tree (
+    -- ancestor product
+    src_id uuid not null references product (id),
+    -- descendent product
+    dst_id uuid not null references product (id),
+    -- value(ancestor) * ratio = value(descendent)
+    ratio double precision not null,
+    -- location(ancestor) - location(descendant) = depth >= 0
+    depth integer not null,
+    -- when was this relation created?
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    -- indices necessary for efficient querying
+    primary key (src_id, dst_id, valid_from),
+    unique (dst_id, src_id, valid_from),
+    unique (valid_from, valid_until, src_id, dst_id)
+);
+--
+-- This trigger fully maintains the product_tree table as we insert new
+-- products
+create function product_tree_trigger() returns trigger as $$
+begin
+    -- Invalidate existing paths to the parent
+    update product_tree
+    set
+        valid_until = new.as_of
+    where
+        dst_id = new.parent_id;
+
+    -- Extend paths ending in the parent id to include the new product
+    insert into product_tree (
+        src_id,
+        dst_id,
+        ratio,
+        depth,
+        valid_from,
+        valid_until
+    )
+    select
+        pt.src_id,
+        new.id,
+        pt.ratio * new.parent_ratio as ratio,
+        pt.depth + 1 as depth,
+        new.as_of,
+        null as valid_until
+    from
+        product_tree as pt
+    where
+        pt.dst_id = new.parent_id;
+
+    -- Insert the "leaf" row for the product
+    insert into product_tree (
+        src_id,
+        dst_id,
+        ratio,
+        depth,
+        valid_from,
+        valid_until
+    )
+    values (
+        new.id,
+        new.id,
+        1.0,
+        0,
+        new.as_of,
+        null
+    );
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger product_tree_trigger
+after insert on product
+for each row execute function product_tree_trigger();
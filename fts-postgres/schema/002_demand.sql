@@ -0,0 +1,73 @@
+create table demand (
+    id uuid primary key,
+    as_of timestamptz not null,
+    bidder_id uuid not null,
+    app_data jsonb not null,
+    curve_data jsonb, -- Option<Json<Demand>>
+    -- the actor (bidder, operator, or system) responsible for the latest change
+    actor text not null default 'bidder',
+    -- demands may be purged, erasing their data but retaining a tombstone
+    purged_at timestamptz
+);
+--
+create index demand_by_bidder on demand (bidder_id, id);
+--
+-- manage updates to demand curve data
+create table curve_data (
+    -- the demand curve to attach this data to
+    demand_id uuid not null references demand (id),
+    -- a jsonb representation of the demand curve
+    value jsonb,
+    -- what is the lifetime of this change?
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    actor text not null default 'bidder',
+    -- create an index (and eliminate race conditions) for querying
+    primary key (demand_id, valid_from),
+    unique (valid_from, valid_until, demand_id)
+);
+--
+-- These triggers maintain the curve_data table:
+--  1. When a new demand is created:
+create function demand_insert_trigger() returns trigger as $$
+begin
+    insert into curve_data (
+        demand_id, value, valid_from, valid_until, actor
+    )
+    values (
+        new.id, new.curve_data, new.as_of, null, new.actor
+    );
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger demand_insert_trigger
+after insert on demand
+for each row execute function demand_insert_trigger();
+--
+-- 2. When an existing demand is updated:
+create function demand_update_trigger() returns trigger as $$
+begin
+    update curve_data
+    set
+        valid_until = new.as_of
+    where
+        demand_id = old.id
+        and
+        valid_from = old.as_of;
+
+    insert into curve_data (
+        demand_id, value, valid_from, valid_until, actor
+    )
+    values (
+        new.id, new.curve_data, new.as_of, null, new.actor
+    );
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger demand_update_trigger
+after update on demand
+for each row execute function demand_update_trigger();
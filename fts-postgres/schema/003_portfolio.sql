@@ -0,0 +1,150 @@
+create table portfolio (
+    id uuid primary key,
+    as_of timestamptz not null,
+    bidder_id uuid not null,
+    app_data jsonb not null,
+    demand jsonb, -- Option<Json<Record<DemandId, number>>>
+    basis jsonb, -- Option<Json<Record<ProductId, number>>>
+    -- the actor (bidder, operator, or system) responsible for the latest change
+    actor text not null default 'bidder',
+    -- portfolios may be purged, erasing their data but retaining a tombstone
+    purged_at timestamptz
+);
+--
+create index portfolio_by_bidder on portfolio (bidder_id, id);
+--
+-- we track the lifetimes of the portfolio's demand group
+create table portfolio_demand (
+    portfolio_id uuid not null references portfolio (id),
+    demand_id uuid not null references demand (id),
+    weight double precision not null,
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    actor text not null default 'bidder',
+    -- query by portfolio or by demand equally efficiently
+    primary key (portfolio_id, demand_id, valid_from),
+    unique (demand_id, portfolio_id, valid_from),
+    -- build an index for efficient batch auction generation
+    unique (valid_until, valid_from, portfolio_id, demand_id)
+);
+--
+-- we track the lifetimes of the portfolio's product group
+create table portfolio_product (
+    portfolio_id uuid not null references portfolio (id),
+    product_id uuid not null references product (id),
+    weight double precision not null,
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    actor text not null default 'bidder',
+    primary key (portfolio_id, product_id, valid_from),
+    unique (valid_until, valid_from, portfolio_id, product_id)
+);
+--
+-- we use triggers to automatically maintain these lifetime tables
+--
+--  when a new portfolio is created, insert new lifetime records in both tables
+create function portfolio_insert_trigger() returns trigger as $$
+begin
+    -- track the demand lifetime
+    insert into portfolio_demand (
+        portfolio_id, demand_id, weight, valid_from, valid_until, actor
+    )
+    select
+        new.id,
+        key::uuid,
+        value::double precision,
+        new.as_of,
+        null,
+        new.actor
+    from
+        jsonb_each_text(new.demand);
+
+    -- track the product lifetime
+    insert into portfolio_product (
+        portfolio_id, product_id, weight, valid_from, valid_until, actor
+    )
+    select
+        new.id,
+        key::uuid,
+        value::double precision,
+        new.as_of,
+        null,
+        new.actor
+    from
+        jsonb_each_text(new.basis);
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger portfolio_insert_trigger
+after insert on portfolio
+for each row execute function portfolio_insert_trigger();
+--
+-- when an existing portfolio has its demand updated:
+create function portfolio_update_demand_trigger() returns trigger as $$
+begin
+    update portfolio_demand
+    set
+        valid_until = new.as_of
+    where
+        portfolio_id = old.id
+        and
+        valid_from <= old.as_of
+        and
+        valid_until is null;
+
+    insert into portfolio_demand (
+        portfolio_id, demand_id, weight, valid_from, valid_until, actor
+    )
+    select
+        new.id,
+        key::uuid,
+        value::double precision,
+        new.as_of,
+        null,
+        new.actor
+    from
+        jsonb_each_text(new.demand);
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger portfolio_update_demand_trigger
+after update of demand on portfolio
+for each row execute function portfolio_update_demand_trigger();
+--
+-- when an existing portfolio has its basis updated:
+create function portfolio_update_basis_trigger() returns trigger as $$
+begin
+    update portfolio_product
+    set
+        valid_until = new.as_of
+    where
+        portfolio_id = old.id
+        and
+        valid_from <= old.as_of
+        and
+        valid_until is null;
+
+    insert into portfolio_product (
+        portfolio_id, product_id, weight, valid_from, valid_until, actor
+    )
+    select
+        new.id,
+        key::uuid,
+        value::double precision,
+        new.as_of,
+        null,
+        new.actor
+    from
+        jsonb_each_text(new.basis);
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger portfolio_update_basis_trigger
+after update of basis on portfolio
+for each row execute function portfolio_update_basis_trigger();
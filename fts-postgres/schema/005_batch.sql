@@ -0,0 +1,87 @@
+-- Every so-often, a batch is compiled from the current submissions.
+-- An auction is then run, asynchronously updating the *_outcomes fields with the outcomes.
+create table batch (
+    id integer primary key,
+    as_of timestamptz not null,
+    portfolio_outcomes jsonb not null, -- Json<Record<PortfolioId, PortfolioOutcome>>
+    product_outcomes jsonb not null -- Json<Record<ProductId, ProductOutcome>>
+);
+--
+-- the output of the batch auction with respect to the portfolios
+create table portfolio_outcome (
+    portfolio_id uuid not null references portfolio (id),
+    value jsonb,
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    primary key (portfolio_id, valid_from),
+    unique (valid_from, valid_until, portfolio_id)
+);
+--
+-- the output of the batch auction with respect to the products
+create table product_outcome (
+    product_id uuid not null references product (id),
+    value jsonb,
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    primary key (product_id, valid_from),
+    unique (valid_from, valid_until, product_id)
+);
+--
+-- as with the other tables, we use triggers to maintain the outputs
+create function batch_update_portfolio_trigger() returns trigger as $$
+begin
+    -- invalidate existing output
+    update portfolio_outcome
+    set
+        valid_until = new.as_of
+    where
+        valid_from = old.as_of;
+
+    -- create new output
+    insert into portfolio_outcome (
+        portfolio_id, value, valid_from, valid_until
+    )
+    select
+        key::uuid,
+        value,
+        new.as_of,
+        null as valid_until
+    from
+        jsonb_each(new.portfolio_outcomes);
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger batch_update_portfolio_trigger
+after update of portfolio_outcomes on batch
+for each row execute function batch_update_portfolio_trigger();
+--
+create function batch_update_product_trigger() returns trigger as $$
+begin
+    -- invalidate existing output
+    update product_outcome
+    set
+        valid_until = new.as_of
+    where
+        valid_from = old.as_of;
+
+    -- create new output
+    insert into product_outcome (
+        product_id, value, valid_from, valid_until
+    )
+    select
+        key::uuid,
+        value,
+        new.as_of,
+        null as valid_until
+    from
+        jsonb_each(new.product_outcomes);
+
+    return null;
+end;
+$$ language plpgsql;
+--
+create trigger batch_update_product_trigger
+after update of product_outcomes on batch
+for each row execute function batch_update_product_trigger();
@@ -0,0 +1,33 @@
+-- A settlement integrates the rates of trade produced by the batch auctions
+-- over time, rounding the accrued positions and payments to a fixed precision.
+-- Each settlement covers the interval [settled_from, as_of).
+create table settlement (
+    as_of timestamptz primary key,
+    settled_from timestamptz not null,
+    config jsonb not null -- Json<SettlementConfig>
+);
+--
+-- the settled position of each bidder in each product
+create table settlement_position (
+    as_of timestamptz not null references settlement (as_of),
+    bidder_id uuid not null,
+    product_id uuid not null references product (id),
+    -- the unrounded quantity accrued over the settlement interval
+    accrued double precision not null,
+    -- the rounded quantity, adjusted for the residuals of previous settlements
+    settled double precision not null,
+    primary key (bidder_id, as_of, product_id),
+    unique (as_of, bidder_id, product_id)
+);
+--
+-- the settled payment of each bidder
+create table settlement_payment (
+    as_of timestamptz not null references settlement (as_of),
+    bidder_id uuid not null,
+    -- the unrounded payment accrued over the settlement interval
+    accrued double precision not null,
+    -- the rounded payment, adjusted for the residuals of previous settlements
+    settled double precision not null,
+    primary key (bidder_id, as_of),
+    unique (as_of, bidder_id)
+);
@@ -0,0 +1,28 @@
+-- A settlement may be revised, either reversed by posting entries offsetting
+-- each of its positions and payments, or corrected by posting the entries of
+-- settling its interval again with another configuration. Revisions append
+-- entries rather than altering those already posted, so the net settlement is
+-- the sum of its entries across every revision, the original being revision 0.
+create table settlement_revision (
+    as_of timestamptz not null references settlement (as_of),
+    revision integer not null check (revision > 0),
+    revised_at timestamptz not null,
+    -- the configuration of a correction, or null for a reversal
+    config jsonb, -- Json<SettlementConfig>
+    primary key (as_of, revision)
+);
+--
+-- The entries of the settlements are keyed by revision
+alter table settlement_position
+    add column revision integer not null default 0,
+    drop constraint settlement_position_pkey,
+    drop constraint settlement_position_as_of_bidder_id_product_id_key,
+    add primary key (bidder_id, as_of, product_id, revision),
+    add unique (as_of, bidder_id, product_id, revision);
+--
+alter table settlement_payment
+    add column revision integer not null default 0,
+    drop constraint settlement_payment_pkey,
+    drop constraint settlement_payment_as_of_bidder_id_key,
+    add primary key (bidder_id, as_of, revision),
+    add unique (as_of, bidder_id, revision);
@@ -0,0 +1,14 @@
+-- Each batch auction records how it was solved, whether or not the solver
+-- produced a solution, so that the outcomes of any batch may be explained
+-- after the fact. A batch retried at the same time replaces its record.
+create table batch_run (
+    as_of timestamptz primary key,
+    -- the solver backend, e.g. clarabel or osqp
+    backend text not null,
+    settings jsonb not null, -- Json<Map<String, String>>
+    -- the termination status reported by the solver
+    status text not null,
+    stats jsonb not null, -- Json<Map<String, f64>>
+    -- the error of a batch which failed to solve, or null
+    error text
+);
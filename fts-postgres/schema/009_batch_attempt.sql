@@ -0,0 +1,9 @@
+-- A failed attempt to run a batch auction leaves the outcomes of the previous
+-- batch in effect, so each is recorded lest an outage of clearing go unnoticed.
+-- A batch retried at the same time replaces its record.
+create table batch_attempt (
+    as_of timestamptz primary key,
+    -- the stage at which the batch failed: database or solver
+    category text not null,
+    message text not null
+);
@@ -0,0 +1,102 @@
+-- The tree of products may be changed over time, by updating the ratio of a
+-- product to its parent or by moving it under another parent. The parent and
+-- ratio columns of the product hold its current parent, while this table holds
+-- the lifetime of each.
+create table product_parent (
+    product_id uuid not null references product (id),
+    parent_id uuid not null references product (id),
+    ratio double precision not null,
+    valid_from timestamptz not null,
+    valid_until timestamptz,
+    primary key (product_id, valid_from)
+);
+--
+create index product_parent_parent_id on product_parent (parent_id, valid_from);
+--
+insert into product_parent (product_id, parent_id, ratio, valid_from, valid_until)
+select
+    id,
+    parent_id,
+    parent_ratio,
+    as_of,
+    null
+from
+    product
+where
+    parent_id is not null;
+--
+-- As paths of the tree now end (and begin) over time, only the current paths
+-- to the parent are extended to a new product, along with those just ended by
+-- the insertion of a sibling.
+create or replace function product_tree_trigger() returns trigger as $$
+begin
+    -- Invalidate current paths to the parent
+    update product_tree
+    set
+        valid_until = new.as_of
+    where
+        dst_id = new.parent_id
+        and
+        valid_until is null;
+
+    -- Extend paths ending in the parent id to include the new product
+    insert into product_tree (
+        src_id,
+        dst_id,
+        ratio,
+        depth,
+        valid_from,
+        valid_until
+    )
+    select
+        pt.src_id,
+        new.id,
+        pt.ratio * new.parent_ratio as ratio,
+        pt.depth + 1 as depth,
+        new.as_of,
+        null as valid_until
+    from
+        product_tree as pt
+    where
+        pt.dst_id = new.parent_id
+        and
+        pt.valid_until = new.as_of;
+
+    if new.parent_id is not null then
+        insert into product_parent (
+            product_id,
+            parent_id,
+            ratio,
+            valid_from,
+            valid_until
+        )
+        values (
+            new.id,
+            new.parent_id,
+            new.parent_ratio,
+            new.as_of,
+            null
+        );
+    end if;
+
+    -- Insert the "leaf" row for the product
+    insert into product_tree (
+        src_id,
+        dst_id,
+        ratio,
+        depth,
+        valid_from,
+        valid_until
+    )
+    values (
+        new.id,
+        new.id,
+        1.0,
+        0,
+        new.as_of,
+        null
+    );
+
+    return null;
+end;
+$$ language plpgsql;
@@ -0,0 +1,9 @@
+-- A batch auction may record its inputs exactly as they were assembled for
+-- the solver (if so configured), so that it may be solved again without
+-- reconstructing its bids from the temporal tables, whose schema may since
+-- have changed. A batch retried at the same time replaces its record.
+create table batch_input (
+    as_of timestamptz primary key,
+    -- json rather than jsonb, which would not preserve the order of the keys
+    inputs json not null -- Json<Auction>
+);
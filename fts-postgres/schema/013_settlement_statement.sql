@@ -0,0 +1,18 @@
+-- A statement is issued to each bidder with entries whenever a settlement is
+-- made or revised, in the same transaction as the entries themselves. The
+-- statement is a finalized record, never altered once issued, whose id is
+-- never reused (hence an identity), so that it may be referenced by other
+-- records, such as invoices.
+create table settlement_statement (
+    id bigint generated always as identity primary key,
+    as_of timestamptz not null references settlement (as_of),
+    revision integer not null,
+    bidder_id uuid not null,
+    settled_from timestamptz not null,
+    -- the time of the revision, or null for the settlement as originally made
+    revised_at timestamptz,
+    -- the configuration of the entries, or null for a reversal
+    config jsonb, -- Json<SettlementConfig>
+    activity jsonb not null, -- Json<Activity>
+    unique (as_of, revision, bidder_id)
+);
@@ -0,0 +1,40 @@
+//! Filtering of records by their application data.
+//!
+//! Products, demands, and portfolios may be searched by JSON paths into their
+//! application data, each of which is compared against a value.
+
+use fts_core::models::{AppDataFilter, Comparison, FilterValue};
+use sqlx::{Postgres, QueryBuilder};
+
+/// Restrict a query of `table` to the records whose application data satisfies
+/// every filter.
+pub(crate) fn push_filters<'args>(
+    query_builder: &mut QueryBuilder<'args, Postgres>,
+    table: &str,
+    filters: &'args [AppDataFilter],
+) {
+    // jsonb_path_query_first yields null for absent fields, which never
+    // compares true, so absent fields never satisfy a filter
+    for filter in filters {
+        query_builder
+            .push(format_args!(
+                " and jsonb_path_query_first({table}.app_data, "
+            ))
+            .push_bind(filter.path.as_str())
+            .push("::jsonpath")
+            .push(match filter.op {
+                Comparison::Eq => ") = to_jsonb(",
+                Comparison::Ne => ") != to_jsonb(",
+                Comparison::Lt => ") < to_jsonb(",
+                Comparison::Le => ") <= to_jsonb(",
+                Comparison::Gt => ") > to_jsonb(",
+                Comparison::Ge => ") >= to_jsonb(",
+            });
+        match &filter.value {
+            FilterValue::Bool(value) => query_builder.push_bind(*value),
+            FilterValue::Number(value) => query_builder.push_bind(*value),
+            FilterValue::String(value) => query_builder.push_bind(value.as_str()),
+        };
+        query_builder.push(")");
+    }
+}
@@ -0,0 +1,29 @@
+//! Repository trait implementations for the PostgreSQL database.
+//!
+//! This module contains the implementations of all repository traits defined in
+//! `fts-core` for the PostgreSQL database backend.
+
+use crate::{
+    Db,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::ports::Repository;
+
+mod batch;
+mod consistency;
+mod demand;
+mod health;
+mod import;
+mod portfolio;
+mod product;
+mod retention;
+mod settlement;
+
+impl Repository for Db {
+    type Error = sqlx::Error;
+    type DateTime = DateTime;
+    type BidderId = BidderId;
+    type ProductId = ProductId;
+    type DemandId = DemandId;
+    type PortfolioId = PortfolioId;
+}
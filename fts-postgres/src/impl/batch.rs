@@ -0,0 +1,926 @@
+use crate::Db;
+use crate::types::{DateTime, DemandId, PortfolioId, ProductId, ValueRow};
+use fts_core::models::{
+    Auction, BatchAttempt, BatchError, BatchFailure, BatchMetadata, BatchPreview, BatchRun,
+    BidderOutcome, BidderTrade, DateTimeRangeQuery, DateTimeRangeResponse, MarketStatistics,
+    PriceInterval, PriceSummary, ProductStatistics, SolverTelemetry, ValueRecord,
+};
+use fts_core::{
+    models::{Basis, DemandCurve, DemandCurveDto, Map, Weights},
+    ports::{BatchRepository, Solver},
+};
+use futures_util::{Stream, TryStreamExt as _};
+use std::collections::HashMap;
+use tokio::try_join;
+use tracing::{Level, event};
+
+/// The key of the advisory lock held while a batch auction executes, so that
+/// batches run serially across every server sharing the database.
+const BATCH_LOCK: i64 = 0x6674_735f_6261_7463;
+
+/// The outcomes of a portfolio within a range, most recent first, where a null
+/// limit returns every outcome.
+const PORTFOLIO_OUTCOMES: &str = r#"
+    select
+        valid_from,
+        valid_until,
+        value
+    from
+        portfolio_outcome
+    where
+        portfolio_id = $1
+    and
+        ($2::timestamptz is null or valid_from >= $2)
+    and
+        ($3::timestamptz is null or valid_from < $3)
+    order by
+        valid_from desc
+    limit $4
+"#;
+
+/// The outcomes of a product within a range, most recent first, where a null
+/// limit returns every outcome.
+const PRODUCT_OUTCOMES: &str = r#"
+    select
+        valid_from,
+        valid_until,
+        value
+    from
+        product_outcome
+    where
+        product_id = $1
+    and
+        ($2::timestamptz is null or valid_from >= $2)
+    and
+        ($3::timestamptz is null or valid_from < $3)
+    order by
+        valid_from desc
+    limit $4
+"#;
+
+/// The net trade of a bidder in each product in each of a range of batches,
+/// most recent first, where the limit is on the number of batches. A batch in
+/// which none of the bidder's portfolios had a basis has a row without a
+/// product.
+const BIDDER_OUTCOMES: &str = r#"
+    with batch_cte as (
+        select distinct
+            portfolio_outcome.valid_from
+        from
+            portfolio_outcome
+        join
+            portfolio
+            on
+                portfolio_outcome.portfolio_id = portfolio.id
+        where
+            portfolio.bidder_id = $1
+        and
+            ($2::timestamptz is null or portfolio_outcome.valid_from >= $2)
+        and
+            ($3::timestamptz is null or portfolio_outcome.valid_from < $3)
+        order by
+            portfolio_outcome.valid_from desc
+        limit $4
+    )
+    select
+        portfolio_outcome.valid_from,
+        max(portfolio_outcome.valid_until) as valid_until,
+        basis_view.product_id,
+        coalesce(sum(
+            (portfolio_outcome.value ->> 'rate')::double precision * basis_view.weight
+        ), 0.0) as rate,
+        coalesce(sum(
+            (portfolio_outcome.value ->> 'rate')::double precision * basis_view.weight
+            * coalesce((product_outcome.value ->> 'price')::double precision, 0.0)
+        ), 0.0) as payment
+    from
+        batch_cte
+    join
+        portfolio_outcome
+        on
+            portfolio_outcome.valid_from = batch_cte.valid_from
+    join
+        portfolio
+        on
+            portfolio_outcome.portfolio_id = portfolio.id
+    left join
+        basis_view
+        on
+            portfolio_outcome.portfolio_id = basis_view.portfolio_id
+            and
+            basis_view.valid_from <= portfolio_outcome.valid_from
+            and
+            (portfolio_outcome.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+    left join
+        product_outcome
+        on
+            product_outcome.product_id = basis_view.product_id
+            and
+            product_outcome.valid_from = portfolio_outcome.valid_from
+    where
+        portfolio.bidder_id = $1
+    group by
+        portfolio_outcome.valid_from,
+        basis_view.product_id
+    order by
+        portfolio_outcome.valid_from desc,
+        basis_view.product_id
+"#;
+
+#[derive(sqlx::FromRow)]
+struct ActiveDemand {
+    id: DemandId,
+    expires: Option<DateTime>,
+    value: sqlx::types::Json<DemandCurveDto>,
+}
+
+impl ActiveDemand {
+    fn curve(self) -> DemandCurve {
+        // SAFETY: we only serialize validated demand curves
+        unsafe { DemandCurve::new_unchecked(self.value.0) }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ActivePortfolio {
+    id: PortfolioId,
+    expires: Option<DateTime>,
+    demand: sqlx::types::Json<Weights<DemandId>>,
+}
+
+/// The weight of a product in the basis of an active portfolio
+#[derive(sqlx::FromRow)]
+struct ActiveBasis {
+    portfolio_id: PortfolioId,
+    product_id: ProductId,
+    weight: f64,
+}
+
+/// The net trade of a bidder in a product in a batch (see `BIDDER_OUTCOMES`)
+#[derive(sqlx::FromRow)]
+struct BidderTradeRow {
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    product_id: Option<ProductId>,
+    rate: f64,
+    payment: f64,
+}
+
+/// The record of how a batch was solved
+#[derive(sqlx::FromRow)]
+struct BatchRunRow {
+    as_of: DateTime,
+    backend: String,
+    settings: sqlx::types::Json<Map<String, String>>,
+    status: String,
+    stats: sqlx::types::Json<Map<String, f64>>,
+    error: Option<String>,
+}
+
+impl From<BatchRunRow> for ValueRecord<DateTime, BatchRun> {
+    fn from(row: BatchRunRow) -> Self {
+        Self {
+            valid_from: row.as_of,
+            valid_until: None,
+            value: BatchRun {
+                solver: SolverTelemetry {
+                    backend: row.backend,
+                    settings: row.settings.0,
+                    status: row.status,
+                    stats: row.stats.0,
+                },
+                error: row.error,
+            },
+            actor: None,
+        }
+    }
+}
+
+/// The outcomes of a product, aggregated over some window
+#[derive(sqlx::FromRow)]
+struct OutcomeStatisticsRow {
+    product_id: ProductId,
+    price: Option<f64>,
+    cleared: Option<DateTime>,
+    volume: f64,
+}
+
+/// The active bids referencing a product
+#[derive(sqlx::FromRow)]
+struct BidStatisticsRow {
+    product_id: ProductId,
+    portfolios: i64,
+    demands: i64,
+}
+
+fn coalesce_min<T: Copy + Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    a.or(b).min(b.or(a))
+}
+
+/// Rewrite the recorded inputs of every batch whose `field` has an entry for
+/// `id`, applying `redact` to each, e.g. to erase a purged bid.
+pub(super) async fn redact_batch_inputs(
+    conn: &mut sqlx::PgConnection,
+    field: &str,
+    id: impl std::fmt::Display,
+    redact: impl Fn(&mut Auction<Db>),
+) -> Result<(), sqlx::Error> {
+    let rows: Vec<(DateTime, sqlx::types::Json<Auction<Db>>)> = sqlx::query_as(
+        "select as_of, inputs from batch_input where inputs -> $1 -> $2 is not null for update",
+    )
+    .bind(field)
+    .bind(id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for (as_of, mut inputs) in rows {
+        redact(&mut inputs.0);
+        // As when recorded, the inputs are bound as text rather than jsonb
+        let inputs = serde_json::to_string(&inputs.0)
+            .map_err(|error| sqlx::Error::Encode(Box::new(error)))?;
+        sqlx::query("update batch_input set inputs = $2::json where as_of = $1")
+            .bind(as_of)
+            .bind(inputs)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The inputs to a batch auction, along with the time the first of them expires
+type BatchInputs = (
+    Map<DemandId, DemandCurve>,
+    Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+    Option<DateTime>,
+);
+
+impl Db {
+    /// Gather the demands and portfolios active at `timestamp`
+    async fn gather_batch(&self, timestamp: DateTime) -> Result<BatchInputs, sqlx::Error> {
+        // The demands and portfolios are read from the same snapshot, so that
+        // every portfolio's demands are among those gathered
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("set transaction isolation level repeatable read, read only")
+            .execute(&mut *tx)
+            .await?;
+
+        let demand_records =
+            sqlx::query_as::<_, ActiveDemand>(include_str!("../../queries/active_demands.sql"))
+                .bind(timestamp)
+                .fetch_all(&mut *tx)
+                .await?;
+
+        let portfolio_records = sqlx::query_as::<_, ActivePortfolio>(include_str!(
+            "../../queries/active_portfolios.sql"
+        ))
+        .bind(timestamp)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let basis_records =
+            sqlx::query_as::<_, ActiveBasis>(include_str!("../../queries/active_basis.sql"))
+                .bind(timestamp)
+                .fetch_all(&mut *tx)
+                .await?;
+
+        tx.commit().await?;
+
+        let mut expires = coalesce_min(
+            demand_records.first().and_then(|x| x.expires),
+            portfolio_records
+                .first()
+                .and_then(|portfolio| portfolio.expires),
+        );
+
+        let demands = demand_records
+            .into_iter()
+            .map(|row| {
+                expires = coalesce_min(expires, row.expires);
+                (row.id, row.curve())
+            })
+            .collect();
+
+        let mut bases = HashMap::<PortfolioId, Vec<(ProductId, f64)>>::new();
+        for row in basis_records {
+            bases
+                .entry(row.portfolio_id)
+                .or_default()
+                .push((row.product_id, row.weight));
+        }
+
+        let portfolios = portfolio_records
+            .into_iter()
+            .map(|row| {
+                expires = coalesce_min(expires, row.expires);
+                let basis = bases.remove(&row.id).unwrap_or_default();
+                (row.id, (row.demand.0, basis.into_iter().collect()))
+            })
+            .collect();
+
+        Ok((demands, portfolios, expires))
+    }
+
+    /// Execute a batch auction at `timestamp`, recording its outcomes and
+    /// how it was solved
+    async fn execute_batch<T>(
+        &self,
+        timestamp: DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<DateTime>, T::State), T::Error>, sqlx::Error>
+    where
+        T: Solver<DemandId, PortfolioId, ProductId>,
+        T::PortfolioOutcome: serde::Serialize,
+        T::ProductOutcome: serde::Serialize,
+    {
+        let (demands, portfolios, expires) = self.gather_batch(timestamp).await?;
+        let inputs = self
+            .record_batch_inputs
+            .then(|| Auction::<Self>::from_parts(demands.clone(), portfolios.clone()));
+
+        // TODO: we may wish to filter the portfolios we include for administrative reasons./
+        // what is the best way to do this? Perhaps we say this is (one of) the responsibilities
+        // of the state, e.g. contains a HashSet of the "suspended" portfolio ids, and our solver is
+        // responsible.... I actually like this a lot.
+
+        let (outcome, telemetry) = solver
+            .solve_with_telemetry(demands, portfolios, state)
+            .await;
+
+        // However the solve ended, it is recorded alongside any outcomes
+        let mut tx = self.pool.begin().await?;
+
+        let (result, error) = match outcome {
+            Ok((portfolio_outcomes, product_outcomes)) => {
+                let next_state = solver.next_state(&portfolio_outcomes);
+                let portfolio_outcomes = sqlx::types::Json(portfolio_outcomes);
+                let product_outcomes = sqlx::types::Json(product_outcomes);
+                sqlx::query(
+                    r#"
+                    update
+                        batch
+                    set
+                        as_of = $1,
+                        portfolio_outcomes = $2,
+                        product_outcomes = $3
+                    "#,
+                )
+                .bind(timestamp)
+                .bind(portfolio_outcomes)
+                .bind(product_outcomes)
+                .execute(&mut *tx)
+                .await?;
+                (Ok((expires, next_state)), None)
+            }
+            Err(error) => {
+                let message = error.to_string();
+                (Err(error), Some(message))
+            }
+        };
+
+        sqlx::query(
+            r#"
+            insert into batch_run (as_of, backend, settings, status, stats, error)
+            values ($1, $2, $3, $4, $5, $6)
+            on conflict (as_of) do update set
+                backend = excluded.backend,
+                settings = excluded.settings,
+                status = excluded.status,
+                stats = excluded.stats,
+                error = excluded.error
+            "#,
+        )
+        .bind(timestamp)
+        .bind(&telemetry.backend)
+        .bind(sqlx::types::Json(&telemetry.settings))
+        .bind(&telemetry.status)
+        .bind(sqlx::types::Json(&telemetry.stats))
+        .bind(error)
+        .execute(&mut *tx)
+        .await?;
+
+        // The inputs are bound as text, as jsonb would reorder their keys
+        if let Some(inputs) = inputs {
+            let inputs = serde_json::to_string(&inputs)
+                .map_err(|error| sqlx::Error::Encode(Box::new(error)))?;
+            sqlx::query(
+                r#"
+                insert into batch_input (as_of, inputs)
+                values ($1, $2::json)
+                on conflict (as_of) do update set
+                    inputs = excluded.inputs
+                "#,
+            )
+            .bind(timestamp)
+            .bind(inputs)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Record a failed attempt to run the batch at `timestamp`
+    async fn record_attempt(
+        &self,
+        timestamp: DateTime,
+        category: BatchFailure,
+        message: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            insert into batch_attempt (as_of, category, message)
+            values ($1, $2, $3)
+            on conflict (as_of) do update set
+                category = excluded.category,
+                message = excluded.message
+            "#,
+        )
+        .bind(timestamp)
+        .bind(category.as_str())
+        .bind(message)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+impl<T: Solver<DemandId, PortfolioId, ProductId>> BatchRepository<T> for Db
+where
+    T: Send,
+    T::Error: Send,
+    T::State: Send,
+    T::PortfolioOutcome: Unpin + Send + 'static + serde::Serialize + serde::de::DeserializeOwned,
+    T::ProductOutcome: Unpin + Send + 'static + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn run_batch_with_state(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<Self::DateTime>, T::State), BatchError<T::Error>>, Self::Error> {
+        // The lock is scoped to a transaction of its own, so that it is
+        // released however this call ends, even if it is cancelled
+        let mut lock = self.pool.begin().await?;
+        let acquired: bool = sqlx::query_scalar("select pg_try_advisory_xact_lock($1)")
+            .bind(BATCH_LOCK)
+            .fetch_one(&mut *lock)
+            .await?;
+        if !acquired {
+            return Ok(Err(BatchError::InProgress));
+        }
+
+        let result = self.execute_batch(timestamp, solver, state).await;
+
+        let failure = match &result {
+            Ok(Ok(_)) => None,
+            Ok(Err(error)) => Some((BatchFailure::Solver, error.to_string())),
+            Err(error) => Some((BatchFailure::Database, error.to_string())),
+        };
+        if let Some((category, message)) = failure {
+            // The database may itself be the cause of the failure, so the
+            // attempt is recorded on a best-effort basis
+            if let Err(error) = self.record_attempt(timestamp, category, &message).await {
+                event!(
+                    Level::ERROR,
+                    err = error.to_string(),
+                    "failed to record batch attempt"
+                );
+            }
+        }
+
+        // Should the release fail, the lock is dropped with the connection
+        if let Err(error) = lock.rollback().await {
+            event!(
+                Level::WARN,
+                err = error.to_string(),
+                "failed to release batch lock"
+            );
+        }
+
+        result.map(|outcome| outcome.map_err(BatchError::Solver))
+    }
+
+    async fn preview_batch(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<
+        Result<BatchPreview<Self, T::PortfolioOutcome, T::ProductOutcome>, T::Error>,
+        Self::Error,
+    > {
+        let (demands, portfolios, expires) = self.gather_batch(timestamp).await?;
+
+        Ok(solver
+            .solve(demands, portfolios, state)
+            .await
+            .map(|(portfolios, products)| BatchPreview {
+                as_of: timestamp,
+                expires,
+                portfolios,
+                products,
+            }))
+    }
+
+    async fn get_latest_batch(&self) -> Result<Option<BatchMetadata<Self>>, Self::Error> {
+        // The batch table consists of a single row, which is overwritten by
+        // each batch auction (and initialized without any outcomes).
+        let row: Option<(DateTime, i64, i64)> = sqlx::query_as(
+            r#"
+                select
+                    as_of,
+                    (select count(*) from jsonb_object_keys(portfolio_outcomes)) as portfolios,
+                    (select count(*) from jsonb_object_keys(product_outcomes)) as products
+                from
+                    batch
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(as_of, portfolios, products)| BatchMetadata {
+            as_of,
+            portfolios: portfolios as u32,
+            products: products as u32,
+        }))
+    }
+
+    async fn get_batch_runs(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchRun, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<BatchRunRow> = sqlx::query_as(
+            r#"
+                select
+                    as_of,
+                    backend,
+                    settings,
+                    status,
+                    stats,
+                    error
+                from
+                    batch_run
+                where
+                    ($1::timestamptz is null or as_of >= $1)
+                and
+                    ($2::timestamptz is null or as_of < $2)
+                order by
+                    as_of desc
+                limit $3
+            "#,
+        )
+        .bind(query.after)
+        .bind(query.before)
+        .bind(limit_p1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.as_of),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_batch_attempts(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchAttempt, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<(DateTime, String, String)> = sqlx::query_as(
+            r#"
+                select
+                    as_of,
+                    category,
+                    message
+                from
+                    batch_attempt
+                where
+                    ($1::timestamptz is null or as_of >= $1)
+                and
+                    ($2::timestamptz is null or as_of < $2)
+                order by
+                    as_of desc
+                limit $3
+            "#,
+        )
+        .bind(query.after)
+        .bind(query.before)
+        .bind(limit_p1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|(as_of, _, _)| *as_of),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        rows.into_iter()
+            .map(|(as_of, category, message)| {
+                Ok(ValueRecord {
+                    valid_from: as_of,
+                    valid_until: None,
+                    value: BatchAttempt {
+                        category: category
+                            .parse()
+                            .map_err(|error: String| sqlx::Error::Decode(error.into()))?,
+                        message,
+                    },
+                    actor: None,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()
+            .map(|results| DateTimeRangeResponse { results, more })
+    }
+
+    async fn get_batch_inputs(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Auction<Self>>, Self::Error> {
+        let inputs: Option<sqlx::types::Json<Auction<Self>>> =
+            sqlx::query_scalar("select inputs from batch_input where as_of = $1")
+                .bind(as_of)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(inputs.map(|inputs| inputs.0))
+    }
+
+    /// Get the portfolio's outcomes
+    ///
+    /// This returns a list of outcomes, each corresponding to a specific point in time.
+    /// The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`.
+    async fn get_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<T::PortfolioOutcome, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<ValueRow<T::PortfolioOutcome>> = sqlx::query_as(PORTFOLIO_OUTCOMES)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(Some(limit_p1))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_bidder_outcomes(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BidderOutcome<Self::ProductId>, Self::DateTime>, Self::Error>
+    {
+        let rows: Vec<BidderTradeRow> = sqlx::query_as(BIDDER_OUTCOMES)
+            .bind(bidder_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind((limit + 1) as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut results: Vec<ValueRecord<DateTime, BidderOutcome<ProductId>>> = Vec::new();
+        for row in rows {
+            let record = match results.last_mut() {
+                Some(record) if record.valid_from == row.valid_from => record,
+                _ => {
+                    results.push(ValueRecord {
+                        valid_from: row.valid_from,
+                        valid_until: row.valid_until,
+                        value: BidderOutcome::default(),
+                        actor: None,
+                    });
+                    results.last_mut().unwrap()
+                }
+            };
+            if let Some(product_id) = row.product_id {
+                record.value.trades.insert(
+                    product_id,
+                    BidderTrade {
+                        rate: row.rate,
+                        payment: row.payment,
+                    },
+                );
+                record.value.payment += row.payment;
+            }
+        }
+
+        let more = if results.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra batch, just below the oldest batch of this page.
+            results.pop();
+            Some(DateTimeRangeQuery {
+                before: results.last().map(|record| record.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
+    /// Get the product's outcomes
+    ///
+    /// This returns a list of outcomes, each corresponding to a specific point in time.
+    /// The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`.
+    async fn get_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<T::ProductOutcome, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<ValueRow<T::ProductOutcome>> = sqlx::query_as(PRODUCT_OUTCOMES)
+            .bind(product_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(Some(limit_p1))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    fn stream_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::PortfolioOutcome>, sqlx::Error>> + Send
+    {
+        sqlx::query_as::<_, ValueRow<T::PortfolioOutcome>>(PORTFOLIO_OUTCOMES)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(None::<i64>)
+            .fetch(&self.pool)
+            .map_ok(Into::into)
+    }
+
+    fn stream_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::ProductOutcome>, sqlx::Error>> + Send
+    {
+        sqlx::query_as::<_, ValueRow<T::ProductOutcome>>(PRODUCT_OUTCOMES)
+            .bind(product_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(None::<i64>)
+            .fetch(&self.pool)
+            .map_ok(Into::into)
+    }
+
+    async fn get_product_summary(
+        &self,
+        product_id: Self::ProductId,
+        interval: PriceInterval,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<PriceSummary, Self::DateTime>, Self::Error> {
+        let (field, span) = match interval {
+            PriceInterval::Hour => ("hour", "1 hour"),
+            PriceInterval::Day => ("day", "1 day"),
+        };
+
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<ValueRow<PriceSummary>> =
+            sqlx::query_as(include_str!("../../queries/product_summary.sql"))
+                .bind(product_id)
+                .bind(query.after)
+                .bind(query.before)
+                .bind(as_of)
+                .bind(time_unit)
+                .bind(field)
+                .bind(span)
+                .bind(limit_p1)
+                .fetch_all(&self.pool)
+                .await?;
+
+        // Batches are bucketed by their start, so we page on the end of the
+        // extra bucket to ensure all of its batches are in the next page.
+        let more = if rows.len() == limit + 1 {
+            let extra = rows.pop().unwrap();
+            Some(DateTimeRangeQuery {
+                before: extra.valid_until,
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_market_statistics(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<MarketStatistics<Self>, Self::Error> {
+        let until = query.before.map_or(as_of, |before| before.min(as_of));
+
+        let outcomes = sqlx::query_as::<_, OutcomeStatisticsRow>(include_str!(
+            "../../queries/outcome_statistics.sql"
+        ))
+        .bind(query.after)
+        .bind(until)
+        .bind(as_of)
+        .bind(time_unit)
+        .fetch_all(&self.pool);
+
+        let bids =
+            sqlx::query_as::<_, BidStatisticsRow>(include_str!("../../queries/bid_statistics.sql"))
+                .bind(as_of)
+                .fetch_all(&self.pool);
+
+        let positions = self.net_positions(as_of, time_unit);
+
+        let (outcomes, bids, positions) = try_join!(outcomes, bids, positions)?;
+
+        let mut products: Map<ProductId, ProductStatistics<DateTime>> = Map::default();
+        for row in outcomes {
+            let entry = products.entry(row.product_id).or_default();
+            entry.price = row.price;
+            entry.cleared = row.cleared;
+            entry.volume = row.volume;
+        }
+        for row in bids {
+            let entry = products.entry(row.product_id).or_default();
+            entry.portfolios = row.portfolios as u32;
+            entry.demands = row.demands as u32;
+        }
+        for ((_, product_id), position) in positions {
+            if position > 0.0 {
+                products.entry(product_id).or_default().open_interest += position;
+            }
+        }
+
+        Ok(MarketStatistics { as_of, products })
+    }
+}
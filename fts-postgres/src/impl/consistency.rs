@@ -0,0 +1,365 @@
+use crate::{
+    Db,
+    types::{DateTime, PortfolioId, ProductId},
+};
+use fts_core::models::ConsistencyRecord;
+use sqlx::PgConnection;
+use std::collections::{BTreeMap, HashMap};
+
+/// The tables of portfolio groups, along with the column of their members and
+/// the column of the portfolio they are derived from
+const GROUPS: [(&str, &str, &str); 2] = [
+    ("portfolio_demand", "demand_id", "demand"),
+    ("portfolio_product", "product_id", "basis"),
+];
+
+/// A path of the product tree
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct PathRow {
+    src_id: ProductId,
+    dst_id: ProductId,
+    ratio: f64,
+    depth: i32,
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+}
+
+/// The lifetime of a product's parent
+#[derive(sqlx::FromRow)]
+struct ParentRow {
+    product_id: ProductId,
+    parent_id: ProductId,
+    ratio: f64,
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+}
+
+impl Db {
+    /// Check the product tree and the current portfolio groups against a fresh derivation, replacing those which diverge if `repair`.
+    pub(crate) async fn consistency(
+        &self,
+        repair: bool,
+    ) -> Result<ConsistencyRecord<ProductId, PortfolioId>, sqlx::Error> {
+        if !repair {
+            let mut conn = self.pool.acquire().await?;
+            let (products, portfolios, _) = self.divergence(&mut conn).await?;
+            return Ok(ConsistencyRecord {
+                products,
+                portfolios,
+                repaired: false,
+            });
+        }
+
+        // The check is repeated within the transaction which repairs it, so
+        // that nothing changes in between
+        let mut tx = self.pool.begin().await?;
+        let (products, portfolios, derived) = self.divergence(&mut tx).await?;
+
+        for &product_id in &products {
+            sqlx::query("delete from product_tree where src_id = $1")
+                .bind(product_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for path in derived
+            .into_iter()
+            .filter(|path| products.contains(&path.src_id))
+        {
+            sqlx::query(
+                r#"
+                insert into
+                    product_tree (src_id, dst_id, ratio, depth, valid_from, valid_until)
+                values
+                    ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(path.src_id)
+            .bind(path.dst_id)
+            .bind(path.ratio)
+            .bind(path.depth)
+            .bind(path.valid_from)
+            .bind(path.valid_until)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // The current group is restarted as of the latest update of the
+        // portfolio, replacing the rows begun since and ending the others
+        for &portfolio_id in &portfolios {
+            for (table, member, column) in GROUPS {
+                sqlx::query(&format!(
+                    r#"
+                    delete from {table}
+                    where portfolio_id = $1
+                    and valid_until is null
+                    and valid_from >= (select as_of from portfolio where id = $1)
+                    "#
+                ))
+                .bind(portfolio_id)
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query(&format!(
+                    r#"
+                    update {table}
+                    set valid_until = (select as_of from portfolio where id = $1)
+                    where portfolio_id = $1
+                    and valid_until is null
+                    "#
+                ))
+                .bind(portfolio_id)
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query(&format!(
+                    r#"
+                    insert into
+                        {table} (portfolio_id, {member}, weight, valid_from, valid_until, actor)
+                    select
+                            portfolio.id,
+                        group_member.key::uuid,
+                        group_member.value::double precision,
+                        portfolio.as_of,
+                        null,
+                        'system'
+                    from
+                        portfolio, jsonb_each_text(portfolio.{column}) as group_member
+                    where
+                        portfolio.id = $1
+                    "#
+                ))
+                .bind(portfolio_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        let repaired = !(products.is_empty() && portfolios.is_empty());
+        Ok(ConsistencyRecord {
+            products,
+            portfolios,
+            repaired,
+        })
+    }
+
+    /// The products whose paths diverge from those derived anew, the
+    /// portfolios whose current groups diverge from the portfolio, and the
+    /// paths derived anew
+    async fn divergence(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<(Vec<ProductId>, Vec<PortfolioId>, Vec<PathRow>), sqlx::Error> {
+        let products: Vec<(ProductId, DateTime)> =
+            sqlx::query_as("select id, as_of from product order by id")
+                .fetch_all(&mut *conn)
+                .await?;
+
+        let parents: Vec<ParentRow> = sqlx::query_as(
+            r#"
+            select
+                product_id,
+                parent_id,
+                ratio,
+                valid_from,
+                valid_until
+            from
+                product_parent
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let paths: Vec<PathRow> = sqlx::query_as(
+            r#"
+            select
+                product_tree.src_id,
+                product_tree.dst_id,
+                product_tree.ratio,
+                product_tree.depth,
+                product_tree.valid_from,
+                product_tree.valid_until
+            from
+                product_tree
+            "#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let derived = derive_paths(&products, &parents);
+        let divergent = products
+            .iter()
+            .map(|(product_id, _)| *product_id)
+            .filter(|&product_id| {
+                !same_paths(
+                    paths_from(&paths, product_id),
+                    paths_from(&derived, product_id),
+                )
+            })
+            .collect();
+
+        // A group diverges if its current rows are not exactly the members of
+        // the portfolio's JSON column
+        let mut portfolios: Vec<PortfolioId> = Vec::new();
+        for (table, member, column) in GROUPS {
+            let ids: Vec<PortfolioId> = sqlx::query_scalar(&format!(
+                r#"
+                select
+                    portfolio.id
+                from
+                    portfolio
+                where
+                    (select count(*) from {table} where portfolio_id = portfolio.id and valid_until is null)
+                    != (select count(*) from jsonb_each_text(portfolio.{column}))
+                or
+                    exists (
+                        select 1 from jsonb_each_text(portfolio.{column}) as group_member
+                        where not exists (
+                            select 1 from {table}
+                            where portfolio_id = portfolio.id
+                            and valid_until is null
+                            and {member} = group_member.key::uuid
+                            and weight = group_member.value::double precision
+                        )
+                    )
+                "#
+            ))
+            .fetch_all(&mut *conn)
+            .await?;
+            portfolios.extend(ids);
+        }
+        portfolios.sort();
+        portfolios.dedup();
+
+        Ok((divergent, portfolios, derived))
+    }
+}
+
+/// The paths of the product tree, derived anew from the creation of each
+/// product and the lifetimes of their parents
+fn derive_paths(products: &[(ProductId, DateTime)], parents: &[ParentRow]) -> Vec<PathRow> {
+    // The tree only changes when a product is created or a parent changes
+    let mut times: Vec<DateTime> = products
+        .iter()
+        .map(|(_, as_of)| *as_of)
+        .chain(
+            parents
+                .iter()
+                .flat_map(|row| std::iter::once(row.valid_from).chain(row.valid_until)),
+        )
+        .collect();
+    times.sort();
+    times.dedup();
+
+    let mut paths = Vec::new();
+    let mut open: BTreeMap<(ProductId, ProductId), PathRow> = BTreeMap::new();
+    for as_of in times {
+        let mut children: HashMap<ProductId, Vec<(ProductId, f64)>> = HashMap::new();
+        for row in parents.iter().filter(|row| {
+            row.valid_from <= as_of
+                && row
+                    .valid_until
+                    .is_none_or(|valid_until| as_of < valid_until)
+        }) {
+            children
+                .entry(row.parent_id)
+                .or_default()
+                .push((row.product_id, row.ratio));
+        }
+
+        // Each product existing at the time has a path to each of its leaves
+        let mut current = BTreeMap::new();
+        for &(src_id, _) in products.iter().filter(|(_, created)| *created <= as_of) {
+            let mut stack = vec![(src_id, 1.0, 0)];
+            while let Some((id, ratio, depth)) = stack.pop() {
+                match children.get(&id) {
+                    // The parents of a corrupted tree may form a cycle, which
+                    // is not followed indefinitely
+                    Some(_) if depth as usize > products.len() => {}
+                    Some(children) => {
+                        stack.extend(children.iter().map(|&(child_id, child_ratio)| {
+                            (child_id, ratio * child_ratio, depth + 1)
+                        }))
+                    }
+                    None => {
+                        current.insert((src_id, id), (ratio, depth));
+                    }
+                }
+            }
+        }
+
+        // A path whose ratio changed ends, and another begins
+        open.retain(|key, path| {
+            if current.get(key) == Some(&(path.ratio, path.depth)) {
+                true
+            } else {
+                path.valid_until = Some(as_of);
+                paths.push(path.clone());
+                false
+            }
+        });
+        for ((src_id, dst_id), (ratio, depth)) in current {
+            open.entry((src_id, dst_id)).or_insert(PathRow {
+                src_id,
+                dst_id,
+                ratio,
+                depth,
+                valid_from: as_of,
+                valid_until: None,
+            });
+        }
+    }
+    paths.extend(open.into_values());
+    paths
+}
+
+/// The paths from a product, ordered by their leaf and then by time
+fn paths_from(paths: &[PathRow], product_id: ProductId) -> Vec<&PathRow> {
+    let mut paths: Vec<&PathRow> = paths
+        .iter()
+        .filter(|path| path.src_id == product_id)
+        .collect();
+    paths.sort_by_key(|path| (path.dst_id, path.valid_from));
+    paths
+}
+
+/// Whether two ordered lists of paths hold the same ratios at every time.
+///
+/// A path may be ended and begun anew with the same ratio (e.g. by an update
+/// which does not change it), so consecutive paths with the same ratio are
+/// merged before they are compared.
+fn same_paths(actual: Vec<&PathRow>, derived: Vec<&PathRow>) -> bool {
+    let merge = |paths: Vec<&PathRow>| {
+        let mut merged: Vec<PathRow> = Vec::new();
+        for path in paths {
+            match merged.last_mut() {
+                Some(last)
+                    if last.dst_id == path.dst_id
+                        && last.depth == path.depth
+                        && last.valid_until == Some(path.valid_from)
+                        && same_ratio(last.ratio, path.ratio) =>
+                {
+                    last.valid_until = path.valid_until;
+                }
+                _ => merged.push(path.clone()),
+            }
+        }
+        merged
+    };
+
+    let (actual, derived) = (merge(actual), merge(derived));
+    actual.len() == derived.len()
+        && actual.iter().zip(&derived).all(|(a, b)| {
+            a.dst_id == b.dst_id
+                && a.depth == b.depth
+                && a.valid_from == b.valid_from
+                && a.valid_until == b.valid_until
+                && same_ratio(a.ratio, b.ratio)
+        })
+}
+
+/// Whether two ratios are equal, up to the rounding of their products
+fn same_ratio(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * a.abs().max(b.abs())
+}
@@ -0,0 +1,497 @@
+use super::batch::redact_batch_inputs;
+use crate::{
+    Db,
+    types::{DateTime, DemandRow, HistoryRow},
+};
+use fts_core::{
+    models::{
+        Actor, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandCurveDto, DemandQuery,
+        DemandQueryResponse, DemandRecord, DemandSearch, DemandSearchResponse, Map, Tombstone,
+        ValueRecord,
+    },
+    ports::DemandRepository,
+};
+use futures_util::{Stream, TryStreamExt as _};
+
+/// The curve changes of a demand within a range, most recent first, where a
+/// null limit returns every change.
+const CURVE_HISTORY: &str = r#"
+    select
+        valid_from,
+        valid_until,
+        value,
+        actor
+    from
+        curve_data
+    where
+        demand_id = $1
+    and
+        ($2::timestamptz is null or valid_from >= $2)
+    and
+        ($3::timestamptz is null or valid_from < $3)
+    and
+        value is not null
+    order by
+        valid_from desc
+    limit $4
+"#;
+
+impl<DemandData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
+    DemandRepository<DemandData> for Db
+{
+    async fn get_demand_bidder_id(
+        &self,
+        demand_id: Self::DemandId,
+    ) -> Result<Option<Self::BidderId>, Self::Error> {
+        sqlx::query_scalar(
+            r#"
+            select
+                bidder_id
+            from
+                demand
+            where
+                id = $1
+            and
+                purged_at is null
+            "#,
+        )
+        .bind(demand_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_demand_bidder_ids(
+        &self,
+        demand_ids: &[Self::DemandId],
+    ) -> Result<Map<Self::DemandId, Self::BidderId>, Self::Error> {
+        let rows: Vec<(Self::DemandId, Self::BidderId)> = sqlx::query_as(
+            r#"
+            select
+                id,
+                bidder_id
+            from
+                demand
+            where
+                id = any($1)
+            and
+                purged_at is null
+            "#,
+        )
+        .bind(demand_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    async fn query_demand(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandQuery<Self::DemandId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<DemandQueryResponse<Self, DemandData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(DemandQueryResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut rows: Vec<DemandRow<DemandData>> = sqlx::query_as(
+            r#"
+            select
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                case when $6 then curve_data end as curve_data,
+                null::jsonb as portfolios
+            from
+                demand
+            where
+                bidder_id = any($1)
+            and
+                purged_at is null
+            and
+                (not $2 or curve_data is not null)
+            and
+                ($3::timestamptz is null or as_of >= $3)
+            and
+                ($4::uuid is null or id > $4)
+            order by
+                id
+            limit $5
+            "#,
+        )
+        .bind(bidder_ids)
+        .bind(query.has_curve)
+        .bind(query.touched_since)
+        .bind(query.after)
+        // +1 to check if there are more results
+        .bind((limit + 1) as i64)
+        .bind(query.include_curve)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(DemandQuery {
+                has_curve: query.has_curve,
+                include_curve: query.include_curve,
+                touched_since: query.touched_since,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(DemandQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn query_demand_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
+    ) -> Result<Vec<DemandRecord<Self, DemandData>>, Self::Error> {
+        if bidder_ids.is_empty() {
+            Ok(Vec::new())
+        } else {
+            let query: Vec<DemandRow<DemandData>> = sqlx::query_as(
+                r#"
+                select
+                    id,
+                    as_of as valid_from,
+                    null::timestamptz as valid_until,
+                    bidder_id,
+                    app_data,
+                    curve_data,
+                    null::jsonb as portfolios
+                from
+                    demand
+                where
+                    bidder_id = any($1)
+                and
+                    curve_data is not null
+                and
+                    exists (
+                        select
+                            1
+                        from
+                            portfolio_demand
+                        join
+                            portfolio_product
+                        using
+                            (portfolio_id)
+                        where
+                            portfolio_demand.demand_id = demand.id
+                        and
+                            portfolio_demand.valid_until is null
+                        and
+                            portfolio_product.product_id = $2
+                        and
+                            portfolio_product.valid_until is null
+                    )
+                "#,
+            )
+            .bind(bidder_ids)
+            .bind(product_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(query.into_iter().map(Into::into).collect())
+        }
+    }
+
+    async fn create_demand(
+        &self,
+        demand_id: Self::DemandId,
+        bidder_id: Self::BidderId,
+        app_data: DemandData,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<DemandRecord<Self, DemandData>, Self::Error> {
+        // Important: If curve_data is None, we insert NULL into the database
+        // Else, this propagates into a [0] value in the JSONB column
+        let curve_data = curve_data.to_option().map(sqlx::types::Json);
+        let demand: DemandRow<DemandData> = sqlx::query_as(
+            r#"
+            insert into
+                demand (id, as_of, bidder_id, app_data, curve_data, actor)
+            values
+                ($1, $2, $3, $4, $5, $6)
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                curve_data,
+                null::jsonb as portfolios
+            "#,
+        )
+        .bind(demand_id)
+        .bind(as_of)
+        .bind(bidder_id)
+        .bind(sqlx::types::Json(app_data))
+        .bind(curve_data)
+        .bind(actor.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(demand.into())
+    }
+
+    async fn update_demand(
+        &self,
+        demand_id: Self::DemandId,
+        curve_data: DemandCurve,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
+        let curve_data = curve_data.to_option().map(sqlx::types::Json);
+        let demand: Option<DemandRow<DemandData>> = sqlx::query_as(
+            r#"
+            update
+                demand
+            set
+                as_of = $2,
+                curve_data = $3,
+                actor = $4
+            where
+                id = $1
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                curve_data,
+                null::jsonb as portfolios
+            "#,
+        )
+        .bind(demand_id)
+        .bind(as_of)
+        .bind(curve_data)
+        .bind(actor.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(demand.map(Into::into))
+    }
+
+    async fn get_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
+        let query: Option<DemandRow<DemandData>> =
+            sqlx::query_as(include_str!("../../queries/get_demand_by_id.sql"))
+                .bind(demand_id)
+                .bind(as_of)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(query.map(Into::into))
+    }
+
+    async fn search_demands(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandSearch<Self::DemandId>,
+        limit: usize,
+    ) -> Result<DemandSearchResponse<Self, DemandData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(DemandSearchResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            r#"select
+                demand.id,
+                demand.as_of as valid_from,
+                null::timestamptz as valid_until,
+                demand.bidder_id,
+                demand.app_data,
+                demand.curve_data,
+                null::jsonb as portfolios
+            from
+                demand
+            where
+                demand.curve_data is not null
+            and
+                demand.bidder_id = any("#,
+        );
+        query_builder.push_bind(bidder_ids).push(")");
+
+        if let Some(after) = query.after {
+            query_builder.push(" and demand.id > ").push_bind(after);
+        }
+
+        crate::filter::push_filters(&mut query_builder, "demand", &query.filters);
+
+        query_builder
+            .push(" order by demand.id limit ")
+            // +1 to check if there are more results
+            .push_bind((limit + 1) as i64);
+
+        let mut rows: Vec<DemandRow<DemandData>> =
+            query_builder.build_query_as().fetch_all(&self.pool).await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(DemandSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(DemandSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<DemandCurve, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<HistoryRow<DemandCurveDto>> = sqlx::query_as(CURVE_HISTORY)
+            .bind(demand_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(limit_p1) // +1 to check if there are more results
+            .fetch_all(&self.pool)
+            .await?;
+
+        // We paginate by adding 1 to the limit, popping the result of, and
+        // using it to adjust the query object
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(curve_record).collect(),
+            more,
+        })
+    }
+
+    fn stream_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, DemandCurve>, sqlx::Error>> + Send {
+        sqlx::query_as::<_, HistoryRow<DemandCurveDto>>(CURVE_HISTORY)
+            .bind(demand_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(None::<i64>)
+            .fetch(&self.pool)
+            .map_ok(curve_record)
+    }
+
+    async fn purge_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::DemandId>>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Erase the data of the demand itself, leaving the row as a tombstone.
+        // Note that the update trigger records a (null) curve, which is erased
+        // along with the rest of the history below.
+        let bidder_id = sqlx::query_scalar(
+            r#"
+            update
+                demand
+            set
+                as_of = $2,
+                app_data = 'null',
+                curve_data = null,
+                actor = 'operator',
+                purged_at = $2
+            where
+                id = $1
+            and
+                purged_at is null
+            returning
+                bidder_id
+            "#,
+        )
+        .bind(demand_id)
+        .bind(as_of)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(bidder_id) = bidder_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            delete from
+                curve_data
+            where
+                demand_id = $1
+            "#,
+        )
+        .bind(demand_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // The curve is erased from the recorded inputs of past batches too,
+        // which are rewritten as stored (json preserves the order of keys)
+        redact_batch_inputs(&mut tx, "demand_curves", demand_id, |auction| {
+            auction.demand_curves.shift_remove(&demand_id);
+        })
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(Tombstone {
+            id: demand_id,
+            bidder_id,
+            purged_at: as_of,
+        }))
+    }
+}
+
+/// The record of a change to a demand's curve
+fn curve_record(
+    HistoryRow {
+        valid_from,
+        valid_until,
+        value,
+        actor,
+    }: HistoryRow<DemandCurveDto>,
+) -> ValueRecord<DateTime, DemandCurve> {
+    ValueRecord {
+        valid_from,
+        valid_until,
+        // SAFETY: this is only being called when deserializing a SQL query, and we ensure curves
+        //         are valid going into the database.
+        value: unsafe { DemandCurve::new_unchecked(value.0) },
+        actor: actor.parse().ok(),
+    }
+}
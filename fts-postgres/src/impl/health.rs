@@ -0,0 +1,86 @@
+use crate::types::{PortfolioId, ProductId};
+use crate::{Db, MIGRATOR};
+use fts_core::{
+    models::{ConsistencyRecord, MigrationStatus, SchemaMigration},
+    ports::HealthRepository,
+};
+
+impl Db {
+    /// The status of the database's schema migrations: those applied to it,
+    /// those pending, and the resulting version of its schema.
+    ///
+    /// This does not apply any migration, so it may inspect a database
+    /// obtained from [`Db::connect`] before it is opened.
+    pub async fn migration_status(&self) -> Result<MigrationStatus, sqlx::Error> {
+        let mut conn = self.pool.acquire().await?;
+
+        // The table of applied migrations is created by the first migration
+        let migrated: bool =
+            sqlx::query_scalar("select to_regclass('_sqlx_migrations') is not null")
+                .fetch_one(&mut *conn)
+                .await?;
+
+        let applied: Vec<(i64, String)> = if migrated {
+            sqlx::query_as("select version, description from _sqlx_migrations order by version")
+                .fetch_all(&mut *conn)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(migration_status(applied))
+    }
+}
+
+impl HealthRepository for Db {
+    async fn check_health(&self) -> Result<MigrationStatus, Self::Error> {
+        self.migration_status().await
+    }
+
+    async fn check_consistency(
+        &self,
+        repair: bool,
+    ) -> Result<ConsistencyRecord<ProductId, PortfolioId>, Self::Error> {
+        self.consistency(repair).await
+    }
+}
+
+/// Combine the migrations applied to the database with those known to this
+/// crate, which include any applied by a newer version of it
+fn migration_status(applied: Vec<(i64, String)>) -> MigrationStatus {
+    let version = applied.last().map(|(version, _)| *version);
+
+    let mut migrations: Vec<SchemaMigration> = applied
+        .into_iter()
+        .map(|(version, description)| SchemaMigration {
+            version,
+            description,
+            applied: true,
+        })
+        .collect();
+    let applied = migrations.len();
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+    {
+        if migrations
+            .iter()
+            .all(|applied| applied.version != migration.version)
+        {
+            migrations.push(SchemaMigration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: false,
+            });
+        }
+    }
+    migrations.sort_by_key(|migration| migration.version);
+
+    MigrationStatus {
+        applied: applied as u32,
+        pending: (migrations.len() - applied) as u32,
+        version,
+        migrations,
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    Db,
+    types::{DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{Actor, ImportDocument, ImportIssue, ImportRecord},
+    ports::ImportRepository,
+};
+use std::collections::HashSet;
+
+impl<DemandData, PortfolioData, ProductData>
+    ImportRepository<DemandData, PortfolioData, ProductData> for Db
+where
+    DemandData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned,
+    PortfolioData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned,
+    ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn import(
+        &self,
+        document: ImportDocument<Self, DemandData, PortfolioData, ProductData>,
+        actor: Actor,
+        as_of: Self::DateTime,
+        dry_run: bool,
+    ) -> Result<Result<ImportRecord, Vec<ImportIssue<Self>>>, Self::Error> {
+        // Validate within the transaction, locking the referenced entities so
+        // that they cannot change before the import completes
+        let mut tx = self.pool.begin().await?;
+
+        let product_ids: Vec<ProductId> = document.product_ids().copied().collect();
+        let products: HashSet<ProductId> =
+            sqlx::query_scalar("select id from product where id = any($1) for share")
+                .bind(product_ids)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let demand_ids: Vec<DemandId> = document.demand_ids().copied().collect();
+        let demands: HashSet<DemandId> =
+            sqlx::query_scalar("select id from demand where id = any($1) for share")
+                .bind(demand_ids)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let portfolio_ids: Vec<PortfolioId> = document.portfolio_ids().copied().collect();
+        let portfolios: HashSet<PortfolioId> =
+            sqlx::query_scalar("select id from portfolio where id = any($1)")
+                .bind(portfolio_ids)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .collect();
+
+        let issues = document.validate(&products, &demands, &portfolios);
+        if !issues.is_empty() {
+            tx.rollback().await?;
+            return Ok(Err(issues));
+        }
+
+        let record = ImportRecord {
+            products: document.products.len() as u64,
+            demands: document.demands.len() as u64,
+            portfolios: document.portfolios.len() as u64,
+            dry_run,
+        };
+
+        // The triggers manage the product tree, which is why parents must
+        // precede their children
+        for product in document.products {
+            let (parent_id, parent_ratio) = product
+                .parent
+                .map_or((None, 0.0), |(parent_id, ratio)| (Some(parent_id), ratio));
+            sqlx::query(
+                r#"
+                insert into
+                    product (id, as_of, app_data, parent_id, parent_ratio)
+                values
+                    ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(product.id)
+            .bind(as_of)
+            .bind(sqlx::types::Json(product.app_data))
+            .bind(parent_id)
+            .bind(parent_ratio)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for demand in document.demands {
+            sqlx::query(
+                r#"
+                insert into
+                    demand (id, as_of, bidder_id, app_data, curve_data, actor)
+                values
+                    ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(demand.id)
+            .bind(as_of)
+            .bind(demand.bidder_id)
+            .bind(sqlx::types::Json(demand.app_data))
+            .bind(demand.curve_data.to_option().map(sqlx::types::Json))
+            .bind(actor.as_str())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for portfolio in document.portfolios {
+            let demand = if portfolio.demand.is_empty() {
+                None
+            } else {
+                Some(sqlx::types::Json(portfolio.demand))
+            };
+            let basis = if portfolio.basis.is_empty() {
+                None
+            } else {
+                Some(sqlx::types::Json(portfolio.basis))
+            };
+            sqlx::query(
+                r#"
+                insert into
+                    portfolio (id, as_of, bidder_id, app_data, demand, basis, actor)
+                values
+                    ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(portfolio.id)
+            .bind(as_of)
+            .bind(portfolio.bidder_id)
+            .bind(sqlx::types::Json(portfolio.app_data))
+            .bind(demand)
+            .bind(basis)
+            .bind(actor.as_str())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+
+        Ok(Ok(record))
+    }
+}
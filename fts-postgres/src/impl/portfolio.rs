@@ -0,0 +1,723 @@
+use super::batch::redact_batch_inputs;
+use crate::{
+    Db,
+    types::{DateTime, DemandId, HistoryRow, PortfolioRow, ProductId},
+};
+use fts_core::{
+    models::{
+        Actor, Basis, DateTimeRangeQuery, DateTimeRangeResponse, Map, PortfolioQuery,
+        PortfolioQueryResponse, PortfolioRecord, PortfolioSearch, PortfolioSearchResponse,
+        Tombstone, ValueRecord, Weights,
+    },
+    ports::PortfolioRepository,
+};
+use futures_util::{Stream, TryStreamExt as _};
+
+/// The demand group changes of a portfolio within a range, most recent first,
+/// where a null limit returns every change.
+const DEMAND_HISTORY: &str = r#"
+    select
+        valid_from,
+        -- every row of a group is written (and closed) by the same change
+        min(valid_until) as valid_until,
+        jsonb_object_agg(demand_id, weight) as value,
+        min(actor) as actor
+    from
+        portfolio_demand
+    where
+        portfolio_id = $1
+    and
+        ($2::timestamptz is null or valid_from >= $2)
+    and
+        ($3::timestamptz is null or valid_from < $3)
+    group by
+        valid_from
+    order by
+        valid_from desc
+    limit $4
+"#;
+
+/// The product group changes of a portfolio within a range, most recent first,
+/// where a null limit returns every change.
+const PRODUCT_HISTORY: &str = r#"
+    select
+        valid_from,
+        -- every row of a group is written (and closed) by the same change
+        min(valid_until) as valid_until,
+        jsonb_object_agg(product_id, weight) as value,
+        min(actor) as actor
+    from
+        portfolio_product
+    where
+        portfolio_id = $1
+    and
+        ($2::timestamptz is null or valid_from >= $2)
+    and
+        ($3::timestamptz is null or valid_from < $3)
+    group by
+        valid_from
+    order by
+        valid_from desc
+    limit $4
+"#;
+
+impl<PortfolioData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
+    PortfolioRepository<PortfolioData> for Db
+{
+    async fn get_portfolio_bidder_id(
+        &self,
+        portfolio_id: Self::PortfolioId,
+    ) -> Result<Option<Self::BidderId>, Self::Error> {
+        sqlx::query_scalar(
+            r#"
+            select
+                bidder_id
+            from
+                portfolio
+            where
+                id = $1
+            and
+                purged_at is null
+            "#,
+        )
+        .bind(portfolio_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_portfolio_bidder_ids(
+        &self,
+        portfolio_ids: &[Self::PortfolioId],
+    ) -> Result<Map<Self::PortfolioId, Self::BidderId>, Self::Error> {
+        let rows: Vec<(Self::PortfolioId, Self::BidderId)> = sqlx::query_as(
+            r#"
+            select
+                id,
+                bidder_id
+            from
+                portfolio
+            where
+                id = any($1)
+            and
+                purged_at is null
+            "#,
+        )
+        .bind(portfolio_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    async fn query_portfolio(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioQuery<Self::PortfolioId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<PortfolioQueryResponse<Self, PortfolioData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(PortfolioQueryResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut rows: Vec<PortfolioRow<PortfolioData>> = sqlx::query_as(
+            r#"
+            select
+                portfolio.id,
+                portfolio.as_of as valid_from,
+                null::timestamptz as valid_until,
+                portfolio.bidder_id,
+                portfolio.app_data,
+                portfolio.demand,
+                portfolio.basis
+            from
+                portfolio
+            where
+                portfolio.bidder_id = any($1)
+            and
+                portfolio.purged_at is null
+            and
+                (not $2 or portfolio.demand is not null or portfolio.basis is not null)
+            and
+                (
+                    not $3
+                    or
+                    exists (
+                        select
+                            1
+                        from
+                            portfolio_demand
+                        join
+                            demand
+                        on
+                            demand.id = portfolio_demand.demand_id
+                        where
+                            portfolio_demand.portfolio_id = portfolio.id
+                        and
+                            portfolio_demand.valid_until is null
+                        and
+                            demand.curve_data is not null
+                    )
+                )
+            and
+                ($4::timestamptz is null or portfolio.as_of >= $4)
+            and
+                ($5::uuid is null or portfolio.id > $5)
+            order by
+                portfolio.id
+            limit $6
+            "#,
+        )
+        .bind(bidder_ids)
+        .bind(query.active_only)
+        .bind(query.has_curve)
+        .bind(query.touched_since)
+        .bind(query.after)
+        // +1 to check if there are more results
+        .bind((limit + 1) as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(PortfolioQuery {
+                active_only: query.active_only,
+                has_curve: query.has_curve,
+                touched_since: query.touched_since,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(PortfolioQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn query_portfolio_with_expanded_products(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        as_of: Self::DateTime,
+    ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        if bidder_ids.is_empty() {
+            Ok(Vec::new())
+        } else {
+            let query: Vec<PortfolioRow<PortfolioData>> =
+                sqlx::query_as(include_str!("../../queries/query_portfolios_expanded.sql"))
+                    .bind(bidder_ids)
+                    .bind(as_of)
+                    .fetch_all(&self.pool)
+                    .await?;
+
+            Ok(query.into_iter().map(Into::into).collect())
+        }
+    }
+
+    async fn query_portfolio_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
+    ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        if bidder_ids.is_empty() {
+            Ok(Vec::new())
+        } else {
+            let query: Vec<PortfolioRow<PortfolioData>> = sqlx::query_as(
+                r#"
+                select
+                    portfolio.id,
+                    portfolio.as_of as valid_from,
+                    null::timestamptz as valid_until,
+                    portfolio.bidder_id,
+                    portfolio.app_data,
+                    portfolio.demand,
+                    portfolio.basis
+                from
+                    portfolio_product
+                join
+                    portfolio
+                on
+                    portfolio.id = portfolio_product.portfolio_id
+                where
+                    portfolio_product.product_id = $2
+                and
+                    portfolio_product.valid_until is null
+                and
+                    portfolio.bidder_id = any($1)
+                "#,
+            )
+            .bind(bidder_ids)
+            .bind(product_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            Ok(query.into_iter().map(Into::into).collect())
+        }
+    }
+
+    async fn create_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        bidder_id: Self::BidderId,
+        app_data: PortfolioData,
+        demand: Weights<Self::DemandId>,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<PortfolioRecord<Self, PortfolioData>, Self::Error> {
+        let demand = if demand.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(demand))
+        };
+        let basis = if basis.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(basis))
+        };
+        let portfolio: PortfolioRow<PortfolioData> = sqlx::query_as(
+            r#"
+            insert into
+                portfolio (id, as_of, bidder_id, app_data, demand, basis, actor)
+            values
+                ($1, $2, $3, $4, $5, $6, $7)
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                demand,
+                basis
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(as_of)
+        .bind(bidder_id)
+        .bind(sqlx::types::Json(app_data))
+        .bind(demand)
+        .bind(basis)
+        .bind(actor.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(portfolio.into())
+    }
+
+    async fn update_portfolio_demand(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        demand: Weights<Self::DemandId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let demand = if demand.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(demand))
+        };
+        let updated: Option<PortfolioRow<PortfolioData>> = sqlx::query_as(
+            r#"
+            update
+                portfolio
+            set
+                as_of = $2,
+                demand = $3,
+                actor = $4
+            where
+                id = $1
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                demand,
+                basis
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(as_of)
+        .bind(demand)
+        .bind(actor.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(updated.map(Into::into))
+    }
+
+    async fn update_portfolio_basis(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let basis = if basis.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(basis))
+        };
+        let updated: Option<PortfolioRow<PortfolioData>> = sqlx::query_as(
+            r#"
+            update
+                portfolio
+            set
+                as_of = $2,
+                basis = $3,
+                actor = $4
+            where
+                id = $1
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                demand,
+                basis
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(as_of)
+        .bind(basis)
+        .bind(actor.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(updated.map(Into::into))
+    }
+
+    async fn update_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        demand: Weights<Self::DemandId>,
+        basis: Basis<Self::ProductId>,
+        actor: Actor,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let demand = if demand.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(demand))
+        };
+        let basis = if basis.is_empty() {
+            None
+        } else {
+            Some(sqlx::types::Json(basis))
+        };
+        let updated: Option<PortfolioRow<PortfolioData>> = sqlx::query_as(
+            r#"
+            update
+                portfolio
+            set
+                as_of = $2,
+                demand = $3,
+                basis = $4,
+                actor = $5
+            where
+                id = $1
+            returning
+                id,
+                as_of as valid_from,
+                null::timestamptz as valid_until,
+                bidder_id,
+                app_data,
+                demand,
+                basis
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(as_of)
+        .bind(demand)
+        .bind(basis)
+        .bind(actor.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(updated.map(Into::into))
+    }
+
+    async fn get_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let query: Option<PortfolioRow<PortfolioData>> =
+            sqlx::query_as(include_str!("../../queries/get_portfolio_by_id.sql"))
+                .bind(portfolio_id)
+                .bind(as_of)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(query.map(Into::into))
+    }
+
+    async fn get_portfolio_with_expanded_products(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let query: Option<PortfolioRow<PortfolioData>> = sqlx::query_as(include_str!(
+            "../../queries/get_portfolio_by_id_expanded.sql"
+        ))
+        .bind(portfolio_id)
+        .bind(as_of)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(query.map(Into::into))
+    }
+
+    async fn search_portfolios(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioSearch<Self::PortfolioId>,
+        limit: usize,
+    ) -> Result<PortfolioSearchResponse<Self, PortfolioData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(PortfolioSearchResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            r#"select
+                portfolio.id,
+                portfolio.as_of as valid_from,
+                null::timestamptz as valid_until,
+                portfolio.bidder_id,
+                portfolio.app_data,
+                portfolio.demand,
+                portfolio.basis
+            from
+                portfolio
+            where
+                (portfolio.demand is not null or portfolio.basis is not null)
+            and
+                portfolio.bidder_id = any("#,
+        );
+        query_builder.push_bind(bidder_ids).push(")");
+
+        if let Some(after) = query.after {
+            query_builder.push(" and portfolio.id > ").push_bind(after);
+        }
+
+        crate::filter::push_filters(&mut query_builder, "portfolio", &query.filters);
+
+        query_builder
+            .push(" order by portfolio.id limit ")
+            // +1 to check if there are more results
+            .push_bind((limit + 1) as i64);
+
+        let mut rows: Vec<PortfolioRow<PortfolioData>> =
+            query_builder.build_query_as().fetch_all(&self.pool).await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(PortfolioSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(PortfolioSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    /// Get the history of this portfolio's demands
+    ///
+    /// This returns a list of records, each containing the state of the portfolio's demand group
+    /// at a specific point in time. The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`. This is important for a `more` pointer to work correctly,
+    /// so the demand is actually a map of `demand_id` to `weight` at that point in time.
+    async fn get_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Weights<Self::DemandId>, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<HistoryRow<Weights<DemandId>>> = sqlx::query_as(DEMAND_HISTORY)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(limit_p1)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    fn stream_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Weights<DemandId>>, sqlx::Error>> + Send
+    {
+        sqlx::query_as::<_, HistoryRow<Weights<DemandId>>>(DEMAND_HISTORY)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(None::<i64>)
+            .fetch(&self.pool)
+            .map_ok(Into::into)
+    }
+
+    /// Get the history of this portfolio's products
+    ///
+    /// This returns a list of records, each containing the state of the portfolio's product group
+    /// at a specific point in time. The records are ordered by `valid_from` in descending order
+    /// and are grouped by `valid_from`. This is important for a `more` pointer to work correctly,
+    /// so the basis is actually a map of `product_id` to `weight` at that point in time.
+    async fn get_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Basis<Self::ProductId>, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<HistoryRow<Basis<ProductId>>> = sqlx::query_as(PRODUCT_HISTORY)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(limit_p1)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    fn stream_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Basis<ProductId>>, sqlx::Error>> + Send
+    {
+        sqlx::query_as::<_, HistoryRow<Basis<ProductId>>>(PRODUCT_HISTORY)
+            .bind(portfolio_id)
+            .bind(query.after)
+            .bind(query.before)
+            .bind(None::<i64>)
+            .fetch(&self.pool)
+            .map_ok(Into::into)
+    }
+
+    async fn purge_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::PortfolioId>>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Erase the data of the portfolio itself, leaving the row as a
+        // tombstone. The update triggers close out the current groups, which
+        // are erased along with the rest of the history below.
+        let bidder_id = sqlx::query_scalar(
+            r#"
+            update
+                portfolio
+            set
+                as_of = $2,
+                app_data = 'null',
+                demand = null,
+                basis = null,
+                actor = 'operator',
+                purged_at = $2
+            where
+                id = $1
+            and
+                purged_at is null
+            returning
+                bidder_id
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(as_of)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(bidder_id) = bidder_id else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            delete from
+                portfolio_demand
+            where
+                portfolio_id = $1
+            "#,
+        )
+        .bind(portfolio_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            delete from
+                portfolio_product
+            where
+                portfolio_id = $1
+            "#,
+        )
+        .bind(portfolio_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // The groups are erased from the recorded inputs of past batches too
+        redact_batch_inputs(&mut tx, "portfolios", portfolio_id, |auction| {
+            auction.portfolios.shift_remove(&portfolio_id);
+        })
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(Tombstone {
+            id: portfolio_id,
+            bidder_id,
+            purged_at: as_of,
+        }))
+    }
+}
@@ -0,0 +1,579 @@
+use crate::Db;
+use crate::types::{DateTime, ProductId, ProductRow};
+use fts_core::{
+    models::{
+        AppDataFilter, ProductQuery, ProductQueryResponse, ProductRecord, ProductSearch,
+        ProductSearchResponse, ProductTreeConflict,
+    },
+    ports::ProductRepository,
+};
+
+/// The current parent of a product, which is None for a root product, or None
+/// if there is no such product. The product is locked, so that concurrent
+/// changes to the tree through it are serialized.
+async fn current_parent(
+    conn: &mut sqlx::PgConnection,
+    product_id: ProductId,
+) -> Result<Option<Option<ProductId>>, sqlx::Error> {
+    sqlx::query_scalar("select parent_id from product where id = $1 for update")
+        .bind(product_id)
+        .fetch_optional(&mut *conn)
+        .await
+}
+
+/// Whether `product_id` is `parent_id` or one of its current ancestors
+async fn is_ancestor(
+    conn: &mut sqlx::PgConnection,
+    product_id: ProductId,
+    parent_id: ProductId,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        with recursive lineage (id) as (
+            select $2::uuid
+            union all
+            select product.parent_id from lineage join product on product.id = lineage.id
+            where product.parent_id is not null
+        )
+        select exists (select 1 from lineage where id = $1)
+        "#,
+    )
+    .bind(product_id)
+    .bind(parent_id)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+/// Move a product under `parent_id` with the given ratio, which may be its
+/// current parent `old_parent_id`, ending the current paths through the
+/// product and starting those through its new parent as of `as_of`.
+async fn move_in_tree(
+    conn: &mut sqlx::PgConnection,
+    product_id: ProductId,
+    old_parent_id: Option<ProductId>,
+    parent_id: ProductId,
+    ratio: f64,
+    as_of: DateTime,
+) -> Result<(), sqlx::Error> {
+    // Invalidate the paths from the former ancestors to the product's leaves
+    sqlx::query(
+        r#"
+        with recursive lineage (id) as (
+            select $3::uuid
+            union all
+            select product.parent_id from lineage join product on product.id = lineage.id
+            where product.parent_id is not null
+        )
+        update product_tree
+        set
+            valid_until = $2
+        where
+            valid_until is null
+        and
+            src_id in (select id from lineage)
+        and
+            dst_id in (
+                select dst_id from product_tree where src_id = $1 and valid_until is null
+            )
+        "#,
+    )
+    .bind(product_id)
+    .bind(as_of)
+    .bind(old_parent_id)
+    .execute(&mut *conn)
+    .await?;
+
+    // A new parent which was its own basis element ceases to be one
+    sqlx::query(
+        r#"
+        update product_tree
+        set
+            valid_until = $2
+        where
+            dst_id = $1
+        and
+            valid_until is null
+        and
+            exists (
+                select 1 from product_tree
+                where src_id = $1 and dst_id = $1 and valid_until is null
+            )
+        "#,
+    )
+    .bind(parent_id)
+    .bind(as_of)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "update product_parent set valid_until = $2 where product_id = $1 and valid_until is null",
+    )
+    .bind(product_id)
+    .bind(as_of)
+    .execute(&mut *conn)
+    .await?;
+
+    // Rows both started and ended at `as_of` were never in effect
+    sqlx::query("delete from product_tree where valid_until = valid_from")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("delete from product_parent where product_id = $1 and valid_until = valid_from")
+        .bind(product_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        r#"
+        insert into
+            product_parent (product_id, parent_id, ratio, valid_from, valid_until)
+        values
+            ($1, $2, $3, $4, null)
+        "#,
+    )
+    .bind(product_id)
+    .bind(parent_id)
+    .bind(ratio)
+    .bind(as_of)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("update product set parent_id = $2, parent_ratio = $3 where id = $1")
+        .bind(product_id)
+        .bind(parent_id)
+        .bind(ratio)
+        .execute(&mut *conn)
+        .await?;
+
+    // A former parent left without children is its own basis element again
+    sqlx::query(
+        r#"
+        with recursive lineage (id, ratio, depth) as (
+            select $1::uuid, 1.0::double precision, 0
+            union all
+            select product.parent_id, lineage.ratio * product.parent_ratio, lineage.depth + 1
+            from lineage join product on product.id = lineage.id
+            where product.parent_id is not null
+        )
+        insert into
+            product_tree (src_id, dst_id, ratio, depth, valid_from, valid_until)
+        select
+            id, $1, ratio, depth, $2, null
+        from
+            lineage
+        where
+            $1 is not null
+        and
+            not exists (select 1 from product where parent_id = $1)
+        "#,
+    )
+    .bind(old_parent_id)
+    .bind(as_of)
+    .execute(&mut *conn)
+    .await?;
+
+    // Extend the paths to the new parent to the product's leaves
+    sqlx::query(
+        r#"
+        with recursive lineage (id, ratio, depth) as (
+            select $2::uuid, 1.0::double precision, 0
+            union all
+            select product.parent_id, lineage.ratio * product.parent_ratio, lineage.depth + 1
+            from lineage join product on product.id = lineage.id
+            where product.parent_id is not null
+        )
+        insert into
+            product_tree (src_id, dst_id, ratio, depth, valid_from, valid_until)
+        select
+            lineage.id,
+            leaf.dst_id,
+            lineage.ratio * $3 * leaf.ratio,
+            lineage.depth + 1 + leaf.depth,
+            $4,
+            null
+        from
+            lineage
+        join
+            product_tree as leaf
+        on
+            leaf.src_id = $1
+        and
+            leaf.valid_until is null
+        "#,
+    )
+    .bind(product_id)
+    .bind(parent_id)
+    .bind(ratio)
+    .bind(as_of)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The products existing as of `as_of` whose application data satisfies every
+/// filter (and which are root products as of the time, if `roots_only`),
+/// ordered by id from just after `after`. One more than `limit` rows are
+/// returned, to check if there are more.
+async fn select_products<ProductData>(
+    pool: &sqlx::PgPool,
+    filters: &[AppDataFilter],
+    roots_only: bool,
+    after: Option<ProductId>,
+    as_of: DateTime,
+    limit: usize,
+) -> Result<Vec<ProductRow<ProductData>>, sqlx::Error>
+where
+    ProductData: Send + Unpin + 'static + serde::de::DeserializeOwned,
+{
+    // The parent is that of the product as of the time, if any
+    let mut query_builder = sqlx::QueryBuilder::new(
+        r#"select
+            product.id,
+            product.app_data,
+            case
+                when product_parent.parent_id is null then jsonb_build_array(product.id, 1.0)
+                else jsonb_build_array(product_parent.parent_id, product_parent.ratio)
+            end as parent,
+            jsonb_object_agg(product_tree.dst_id, product_tree.ratio) as basis
+        from
+            product
+        join
+            product_tree
+        on
+            product.id = product_tree.src_id
+        left join
+            product_parent
+        on
+            product_parent.product_id = product.id
+        and
+            product_parent.valid_from <= "#,
+    );
+    query_builder
+        .push_bind(as_of)
+        .push(" and (")
+        .push_bind(as_of)
+        .push(" < product_parent.valid_until or product_parent.valid_until is null)")
+        .push(" where product_tree.valid_from <= ")
+        .push_bind(as_of)
+        .push(" and (")
+        .push_bind(as_of)
+        .push(" < product_tree.valid_until or product_tree.valid_until is null)");
+
+    if roots_only {
+        query_builder.push(" and product_parent.parent_id is null");
+    }
+
+    if let Some(after) = after {
+        query_builder.push(" and product.id > ").push_bind(after);
+    }
+
+    crate::filter::push_filters(&mut query_builder, "product", filters);
+
+    query_builder
+        .push(" group by product.id, product_parent.parent_id, product_parent.ratio")
+        .push(" order by product.id limit ")
+        // +1 to check if there are more results
+        .push_bind((limit + 1) as i64);
+
+    query_builder.build_query_as().fetch_all(pool).await
+}
+
+impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
+    ProductRepository<ProductData> for Db
+{
+    async fn create_product(
+        &self,
+        product_id: Self::ProductId,
+        app_data: ProductData,
+        as_of: Self::DateTime,
+    ) -> Result<ProductRecord<Self, ProductData>, Self::Error> {
+        // the triggers manage all the details of updating the product tree
+        let new_product: ProductRow<ProductData> = sqlx::query_as(
+            r#"
+            insert into
+                product (id, as_of, app_data)
+            values
+                ($1, $2, $3)
+            returning
+                id,
+                app_data,
+                jsonb_build_array(id, 1.0) as parent,
+                jsonb_build_object(id, 1.0) as basis
+            "#,
+        )
+        .bind(product_id)
+        .bind(as_of)
+        .bind(sqlx::types::Json(app_data))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(new_product.into())
+    }
+
+    async fn create_products<T: Send + IntoIterator<Item = (Self::ProductId, ProductData)>>(
+        &self,
+        products: T,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<ProductRecord<Self, ProductData>>, Self::Error>
+    where
+        T::IntoIter: Send,
+    {
+        let products = products.into_iter();
+        let mut records = Vec::with_capacity(products.size_hint().0);
+
+        // Insert the products one at a time, so that the records are returned
+        // in order, but within one transaction so that they are all or nothing
+        let mut tx = self.pool.begin().await?;
+        for (product_id, app_data) in products {
+            let new_product: ProductRow<ProductData> = sqlx::query_as(
+                r#"
+                insert into
+                    product (id, as_of, app_data)
+                values
+                    ($1, $2, $3)
+                returning
+                    id,
+                    app_data,
+                    jsonb_build_array(id, 1.0) as parent,
+                    jsonb_build_object(id, 1.0) as basis
+                "#,
+            )
+            .bind(product_id)
+            .bind(as_of)
+            .bind(sqlx::types::Json(app_data))
+            .fetch_one(&mut *tx)
+            .await?;
+            records.push(new_product.into());
+        }
+        tx.commit().await?;
+
+        Ok(records)
+    }
+
+    async fn partition_product<T: Send + IntoIterator<Item = (Self::ProductId, ProductData, f64)>>(
+        &self,
+        product_id: Self::ProductId,
+        children: T,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Vec<ProductRecord<Self, ProductData>>>, Self::Error>
+    where
+        T::IntoIter: Send + ExactSizeIterator,
+    {
+        let mut tx = self.pool.begin().await?;
+
+        // Locking the product serializes concurrent partitions of it, so that
+        // the check below remains valid until we commit
+        let npaths: Option<i64> = sqlx::query_scalar(
+            r#"
+            select
+                (
+                    select
+                        count(*)
+                    from
+                        product_tree
+                    where
+                        src_id = product.id
+                    and
+                        valid_from <= $2
+                    and
+                        ($2 < valid_until or valid_until is null)
+                )
+            from
+                product
+            where
+                id = $1
+            for update
+            "#,
+        )
+        .bind(product_id)
+        .bind(as_of)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        match npaths {
+            None | Some(0) => return Ok(None),
+            Some(1) => {}
+            Some(_) => return Ok(Some(Vec::new())),
+        }
+
+        let children = children.into_iter();
+        if children.len() == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "insert into product (id, as_of, app_data, parent_id, parent_ratio) ",
+        );
+        // TODO: we should partition the iterator to play nice with DB limits
+        query_builder
+            .push_values(children, |mut b, (child_id, app_data, child_ratio)| {
+                b.push_bind(child_id)
+                    .push_bind(as_of)
+                    .push_bind(sqlx::types::Json(app_data))
+                    .push_bind(product_id)
+                    .push_bind(child_ratio);
+            })
+            .push(
+                r#" returning
+                id,
+                app_data,
+                jsonb_build_array(parent_id, parent_ratio) as parent,
+                jsonb_build_object(id, 1.0) as basis"#,
+            );
+
+        let result: Vec<ProductRow<ProductData>> =
+            query_builder.build_query_as().fetch_all(&mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(Some(result.into_iter().map(Into::into).collect()))
+    }
+
+    async fn update_product_ratio(
+        &self,
+        product_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(parent_id) = current_parent(&mut tx, product_id).await? else {
+            return Ok(Err(ProductTreeConflict::Missing { product_id }));
+        };
+        let Some(parent_id) = parent_id else {
+            return Ok(Err(ProductTreeConflict::Root { product_id }));
+        };
+
+        move_in_tree(
+            &mut tx,
+            product_id,
+            Some(parent_id),
+            parent_id,
+            ratio,
+            as_of,
+        )
+        .await?;
+        let record: ProductRow<ProductData> =
+            sqlx::query_as(include_str!("../../queries/get_product_by_id.sql"))
+                .bind(product_id)
+                .bind(as_of)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        tx.commit().await?;
+
+        Ok(Ok(record.into()))
+    }
+
+    async fn move_product(
+        &self,
+        product_id: Self::ProductId,
+        parent_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(old_parent_id) = current_parent(&mut tx, product_id).await? else {
+            return Ok(Err(ProductTreeConflict::Missing { product_id }));
+        };
+        if current_parent(&mut tx, parent_id).await?.is_none() {
+            return Ok(Err(ProductTreeConflict::Missing {
+                product_id: parent_id,
+            }));
+        }
+        if is_ancestor(&mut tx, product_id, parent_id).await? {
+            return Ok(Err(ProductTreeConflict::Cycle { parent_id }));
+        }
+
+        move_in_tree(&mut tx, product_id, old_parent_id, parent_id, ratio, as_of).await?;
+        let record: ProductRow<ProductData> =
+            sqlx::query_as(include_str!("../../queries/get_product_by_id.sql"))
+                .bind(product_id)
+                .bind(as_of)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        tx.commit().await?;
+
+        Ok(Ok(record.into()))
+    }
+
+    async fn get_product(
+        &self,
+        product_id: Self::ProductId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<ProductRecord<Self, ProductData>>, Self::Error> {
+        let row: Option<ProductRow<ProductData>> =
+            sqlx::query_as(include_str!("../../queries/get_product_by_id.sql"))
+                .bind(product_id)
+                .bind(as_of)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn query_products(
+        &self,
+        query: ProductQuery<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductQueryResponse<Self, ProductData>, Self::Error> {
+        let mut rows: Vec<ProductRow<ProductData>> = select_products(
+            &self.pool,
+            &[],
+            !query.include_tree,
+            query.after,
+            as_of,
+            limit,
+        )
+        .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(ProductQuery {
+                include_tree: query.include_tree,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(ProductQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn search_products(
+        &self,
+        query: ProductSearch<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductSearchResponse<Self, ProductData>, Self::Error> {
+        let mut rows: Vec<ProductRow<ProductData>> =
+            select_products(&self.pool, &query.filters, false, query.after, as_of, limit).await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(ProductSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(ProductSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{Db, types::DateTime};
+use fts_core::{
+    models::{CompactionRecord, PruneRecord},
+    ports::RetentionRepository,
+};
+
+/// The condition selecting the rows of history superseded by the horizon `$1`
+const SUPERSEDED: &str = "valid_until <= $1";
+
+/// The tables of superseded history, along with whether their rows are needed
+/// to accrue unsettled activity, and the condition selecting the rows to prune
+/// as of the horizon `$1`.
+const HISTORY: [(&str, bool, &str); 6] = [
+    ("curve_data", false, SUPERSEDED),
+    ("portfolio_demand", false, SUPERSEDED),
+    ("portfolio_product", true, SUPERSEDED),
+    ("portfolio_outcome", true, SUPERSEDED),
+    ("product_outcome", true, SUPERSEDED),
+    // The recorded inputs of a batch are only of use to solve it again
+    ("batch_input", false, "as_of < $1"),
+];
+
+/// The tables of group history, along with the column of their members
+const GROUPS: [(&str, &str); 2] = [
+    ("portfolio_demand", "demand_id"),
+    ("portfolio_product", "product_id"),
+];
+
+impl RetentionRepository for Db {
+    async fn prune_history(
+        &self,
+        before: Self::DateTime,
+    ) -> Result<PruneRecord<Self::DateTime>, Self::Error> {
+        let archive = self
+            .archive_schema
+            .as_ref()
+            .map(|schema| format!("\"{}\"", schema.replace('"', "\"\"")));
+
+        let mut tx = self.pool.begin().await?;
+
+        // A concurrent settlement only moves the latest settlement forward,
+        // so the history pruned below remains safe to prune
+        let latest: Option<DateTime> = sqlx::query_scalar("select max(as_of) from settlement")
+            .fetch_one(&mut *tx)
+            .await?;
+        let settled_before = latest.map(|latest| latest.min(before));
+
+        if let Some(archive) = archive.as_ref() {
+            sqlx::query(&format!("create schema if not exists {archive}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut pruned = [0; HISTORY.len()];
+        for ((table, settled, prunable), pruned) in HISTORY.into_iter().zip(pruned.iter_mut()) {
+            let horizon = if settled {
+                settled_before
+            } else {
+                Some(before)
+            };
+            let Some(horizon) = horizon else {
+                continue;
+            };
+
+            let statement = match archive.as_ref() {
+                Some(archive) => {
+                    // The archived tables mirror the columns, but not the
+                    // constraints, of the originals
+                    sqlx::query(&format!(
+                        "create table if not exists {archive}.{table} (like {table})"
+                    ))
+                    .execute(&mut *tx)
+                    .await?;
+                    format!(
+                        r#"
+                        with pruned as (
+                            delete from {table} where {prunable} returning *
+                        )
+                        insert into {archive}.{table} select * from pruned
+                        "#
+                    )
+                }
+                None => format!("delete from {table} where {prunable}"),
+            };
+
+            *pruned = sqlx::query(&statement)
+                .bind(horizon)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+
+        tx.commit().await?;
+
+        let [
+            curves,
+            demand_groups,
+            product_groups,
+            portfolio_outcomes,
+            product_outcomes,
+            batch_inputs,
+        ] = pruned;
+
+        Ok(PruneRecord {
+            before,
+            settled_before,
+            curves,
+            demand_groups,
+            product_groups,
+            portfolio_outcomes,
+            product_outcomes,
+            batch_inputs,
+            archived: archive.is_some(),
+        })
+    }
+
+    async fn compact_history(&self) -> Result<CompactionRecord, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut removed = [0; GROUPS.len()];
+        for ((table, member), removed) in GROUPS.into_iter().zip(removed.iter_mut()) {
+            // Portfolio updates end the open version of a group, so they must
+            // wait for the compaction of its runs, but readers need not
+            sqlx::query(&format!("lock table {table} in share row exclusive mode"))
+                .execute(&mut *tx)
+                .await?;
+
+            // A version is redundant if its every row matches a row of the
+            // version it immediately follows, and the two are the same size.
+            // Each run of redundant versions is removed, and the version it
+            // follows (the head) extended to the end of the run.
+            *removed = sqlx::query_scalar::<_, i64>(&format!(
+                r#"
+                with version as (
+                    select portfolio_id, valid_from, count(*) as size
+                    from {table}
+                    group by portfolio_id, valid_from
+                ),
+                redundant as (
+                    select
+                        b.portfolio_id,
+                        a.valid_from as previous,
+                        b.valid_from,
+                        b.valid_until
+                    from version as va
+                    join {table} as a
+                        on a.portfolio_id = va.portfolio_id and a.valid_from = va.valid_from
+                    join {table} as b
+                        on b.portfolio_id = a.portfolio_id
+                        and b.{member} = a.{member}
+                        and b.valid_from = a.valid_until
+                        and b.weight = a.weight
+                        and b.actor = a.actor
+                    join version as vb
+                        on vb.portfolio_id = b.portfolio_id and vb.valid_from = b.valid_from
+                    where vb.size = va.size
+                    group by b.portfolio_id, a.valid_from, b.valid_from, b.valid_until, va.size
+                    having count(*) = va.size
+                ),
+                run as (
+                    select
+                        r.*,
+                        sum(case when exists (
+                            select 1 from redundant as p
+                            where p.portfolio_id = r.portfolio_id and p.valid_from = r.previous
+                        ) then 0 else 1 end) over (partition by r.portfolio_id order by r.valid_from) as run
+                    from redundant as r
+                ),
+                plan as (
+                    select
+                        portfolio_id,
+                        min(previous) as head,
+                        case
+                            when count(valid_until) < count(*) then null
+                            else max(valid_until)
+                        end as valid_until
+                    from run
+                    group by portfolio_id, run
+                ),
+                extended as (
+                    update {table} as t set valid_until = plan.valid_until
+                    from plan
+                    where plan.portfolio_id = t.portfolio_id and t.valid_from = plan.head
+                ),
+                removed as (
+                    delete from {table} as t
+                    using plan
+                    where plan.portfolio_id = t.portfolio_id
+                    and t.valid_from > plan.head
+                    and (plan.valid_until is null or t.valid_from < plan.valid_until)
+                    returning 1
+                )
+                select count(*) from removed
+                "#
+            ))
+            .fetch_one(&mut *tx)
+            .await? as u64;
+        }
+
+        tx.commit().await?;
+
+        let [demand_groups, product_groups] = removed;
+        Ok(CompactionRecord {
+            demand_groups,
+            product_groups,
+        })
+    }
+}
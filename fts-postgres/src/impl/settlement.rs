@@ -0,0 +1,1124 @@
+use crate::Db;
+use crate::types::{BidderId, DateTime, ProductId, ValueRow};
+use fts_core::{
+    models::{
+        Activity, BidderSummary, DateTimeRangeQuery, DateTimeRangeResponse, Map, SettlementConfig,
+        SettlementConflict, SettlementRecord, SettlementRevision, SettlementStatement,
+        UnsettledActivityQuery, UnsettledActivityResponse, ValueRecord,
+    },
+    ports::SettlementRepository,
+};
+use std::collections::HashMap;
+
+/// The number of bidders whose activity is settled at a time
+const SETTLEMENT_PAGE_SIZE: i64 = 1000;
+
+/// The columns of the statements, to be filtered
+const STATEMENTS: &str = r#"
+    select
+        id,
+        bidder_id,
+        as_of,
+        settled_from,
+        revision,
+        revised_at,
+        config,
+        activity
+    from
+        settlement_statement
+"#;
+
+/// The activity accrued by a bidder over some interval. If `product_id` is
+/// None, the row corresponds to the bidder's payment.
+#[derive(sqlx::FromRow)]
+struct AccruedRow {
+    bidder_id: BidderId,
+    product_id: Option<ProductId>,
+    accrued: f64,
+}
+
+/// The cumulative activity of a bidder across all previous settlements.
+#[derive(sqlx::FromRow)]
+struct TotalRow {
+    bidder_id: BidderId,
+    product_id: Option<ProductId>,
+    accrued: f64,
+    settled: f64,
+}
+
+/// A settlement previously made.
+#[derive(sqlx::FromRow)]
+struct SettlementRow {
+    settled_from: DateTime,
+    config: sqlx::types::Json<SettlementConfig<DateTime>>,
+}
+
+/// A revision of a settlement.
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    revision: i32,
+    revised_at: DateTime,
+    config: Option<sqlx::types::Json<SettlementConfig<DateTime>>>,
+}
+
+/// A settlement as it stands after its latest revision, if any.
+struct PostedSettlement {
+    settled_from: DateTime,
+    /// The configuration with which the settlement was originally made
+    original: SettlementConfig<DateTime>,
+    /// The latest revision, or 0 if never revised
+    revision: i32,
+    revised_at: DateTime,
+    /// The configuration in effect, or None if the settlement is reversed
+    config: Option<SettlementConfig<DateTime>>,
+}
+
+/// A statement issued to a bidder.
+#[derive(sqlx::FromRow)]
+struct StatementRow {
+    id: i64,
+    bidder_id: BidderId,
+    as_of: DateTime,
+    settled_from: DateTime,
+    revision: i32,
+    revised_at: Option<DateTime>,
+    config: Option<sqlx::types::Json<SettlementConfig<DateTime>>>,
+    activity: sqlx::types::Json<Activity<ProductId>>,
+}
+
+impl From<StatementRow> for SettlementStatement<Db> {
+    fn from(row: StatementRow) -> Self {
+        Self {
+            id: row.id as u64,
+            bidder_id: row.bidder_id,
+            valid_from: row.settled_from,
+            valid_until: row.as_of,
+            revision: row.revision as u32,
+            revised_at: row.revised_at,
+            config: row.config.map(|config| config.0),
+            activity: row.activity.0,
+        }
+    }
+}
+
+/// A bidder's quantity of a product. If `product_id` is None, the row
+/// corresponds to the bidder's payment.
+#[derive(sqlx::FromRow)]
+struct AmountRow {
+    product_id: Option<ProductId>,
+    amount: f64,
+}
+
+/// Collect settled entries into the activity of each bidder
+fn settled_activity(rows: Vec<TotalRow>) -> Map<BidderId, Activity<ProductId>> {
+    let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+    for row in rows {
+        let entry = activity.entry(row.bidder_id).or_default();
+        match row.product_id {
+            Some(product_id) => {
+                entry.positions.insert(product_id, row.settled);
+            }
+            None => entry.payment = row.settled,
+        }
+    }
+    activity
+}
+
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+impl Db {
+    /// The net position of every bidder in every product, combining the
+    /// settled positions with the activity accrued since the most recent
+    /// settlement up to `as_of`.
+    pub(crate) async fn net_positions(
+        &self,
+        as_of: DateTime,
+        time_unit: f64,
+    ) -> Result<HashMap<(BidderId, ProductId), f64>, sqlx::Error> {
+        let latest: Option<DateTime> = sqlx::query_scalar("select max(as_of) from settlement")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let settled: Vec<AccruedRow> = sqlx::query_as(
+            r#"
+                select
+                    bidder_id,
+                    product_id,
+                    sum(settled) as accrued
+                from
+                    settlement_position
+                where
+                    as_of <= $1
+                group by
+                    bidder_id,
+                    product_id
+            "#,
+        )
+        .bind(as_of)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let unsettled: Vec<AccruedRow> =
+            sqlx::query_as(include_str!("../../queries/accrued_activity.sql"))
+                .bind(None::<&[BidderId]>)
+                .bind(latest)
+                .bind(as_of)
+                .bind(time_unit)
+                .bind(None::<&[ProductId]>)
+                .bind(None::<ProductId>)
+                .bind(None::<i64>)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut positions = HashMap::new();
+        for row in settled.into_iter().chain(unsettled) {
+            // Rows without a product are payments
+            if let Some(product_id) = row.product_id {
+                *positions.entry((row.bidder_id, product_id)).or_default() += row.accrued;
+            }
+        }
+        Ok(positions)
+    }
+
+    /// The activity of a bidder accrued since the most recent settlement up
+    /// to `as_of`, restricted to at most `limit` (if any) positions in
+    /// `product_ids` (if any) following `after`, along with the start of the
+    /// unsettled interval.
+    async fn unsettled_activity(
+        &self,
+        bidder_id: BidderId,
+        as_of: DateTime,
+        time_unit: f64,
+        product_ids: &[ProductId],
+        after: Option<ProductId>,
+        limit: Option<i64>,
+    ) -> Result<(DateTime, Vec<AccruedRow>), sqlx::Error> {
+        let latest: Option<DateTime> = sqlx::query_scalar("select max(as_of) from settlement")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let rows: Vec<AccruedRow> =
+            sqlx::query_as(include_str!("../../queries/accrued_activity.sql"))
+                .bind(&[bidder_id][..])
+                .bind(latest)
+                .bind(as_of)
+                .bind(time_unit)
+                .bind((!product_ids.is_empty()).then_some(product_ids))
+                .bind(after)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let valid_from = match latest {
+            Some(latest) => latest,
+            None => first_batch(&self.pool).await?.unwrap_or(as_of),
+        };
+
+        Ok((valid_from, rows))
+    }
+}
+
+impl SettlementRepository for Db {
+    async fn get_unsettled_activity(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<ValueRecord<Self::DateTime, Activity<Self::ProductId>>, Self::Error> {
+        let (valid_from, rows) = self
+            .unsettled_activity(bidder_id, as_of, time_unit, &[], None, None)
+            .await?;
+
+        let mut activity = Activity::default();
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => {
+                    activity.positions.insert(product_id, row.accrued);
+                }
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        Ok(ValueRecord {
+            valid_from,
+            valid_until: Some(as_of),
+            value: activity,
+            actor: None,
+        })
+    }
+
+    async fn get_unsettled_positions(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        query: UnsettledActivityQuery<Self::ProductId>,
+        limit: usize,
+    ) -> Result<UnsettledActivityResponse<Self>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let (valid_from, rows) = self
+            .unsettled_activity(
+                bidder_id,
+                as_of,
+                time_unit,
+                &query.products,
+                query.after,
+                Some(limit_p1),
+            )
+            .await?;
+
+        // The positions precede the payment, in order of product
+        let mut activity = Activity::default();
+        let mut positions = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => positions.push((product_id, row.accrued)),
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        let more = if positions.len() == limit + 1 {
+            positions.pop();
+            Some(UnsettledActivityQuery {
+                products: query.products,
+                after: positions.last().map(|(product_id, _)| *product_id),
+            })
+        } else {
+            None
+        };
+        activity.positions.extend(positions);
+
+        Ok(UnsettledActivityResponse {
+            activity: ValueRecord {
+                valid_from,
+                valid_until: Some(as_of),
+                value: activity,
+                actor: None,
+            },
+            more,
+        })
+    }
+
+    async fn settle_activity(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+    ) -> Result<Result<SettlementRecord<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Settlements must be serialized, since each one continues from the
+        // previous. This lock conflicts with itself, but not with readers.
+        lock_settlements(&mut tx).await?;
+
+        // A settlement which has already been made is returned as it stands,
+        // provided it is retried with the configuration now in effect
+        if let Some(posted) = posted_settlement(&mut tx, config.as_of).await? {
+            return match posted.config {
+                None => Ok(Err(SettlementConflict::Reversed {
+                    as_of: config.as_of,
+                })),
+                Some(existing) if existing != config => {
+                    Ok(Err(SettlementConflict::Mismatched { existing }))
+                }
+                Some(_) => {
+                    let entries =
+                        settlement_entries(&mut tx, config.as_of, 0, posted.revision).await?;
+                    Ok(Ok(SettlementRecord {
+                        valid_from: posted.settled_from,
+                        valid_until: config.as_of,
+                        activity: settled_activity(entries),
+                    }))
+                }
+            };
+        }
+
+        let latest: Option<DateTime> = sqlx::query_scalar("select max(as_of) from settlement")
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if let Some(latest) = latest.filter(|latest| *latest > config.as_of) {
+            return Ok(Err(SettlementConflict::Superseded { latest }));
+        }
+
+        let settled_from = match latest {
+            Some(latest) => latest,
+            None => first_batch(&mut *tx).await?.unwrap_or(config.as_of),
+        };
+
+        sqlx::query(
+            r#"
+                insert into
+                    settlement (as_of, settled_from, config)
+                values
+                    ($1, $2, $3)
+            "#,
+        )
+        .bind(config.as_of)
+        .bind(settled_from)
+        .bind(sqlx::types::Json(&config))
+        .execute(&mut *tx)
+        .await?;
+
+        // Accrue and settle the activity a page of bidders at a time, rather
+        // than aggregating the activity of the entire market at once
+        let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+        let mut after = None::<BidderId>;
+        loop {
+            let bidders: Vec<BidderId> = sqlx::query_scalar(
+                r#"
+                    select distinct
+                        bidder_id
+                    from
+                        portfolio
+                    where
+                        ($1::uuid is null or bidder_id > $1)
+                    order by
+                        bidder_id
+                    limit $2
+                "#,
+            )
+            .bind(after)
+            .bind(SETTLEMENT_PAGE_SIZE)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let Some(last) = bidders.last() else {
+                break;
+            };
+            after = Some(*last);
+
+            let rows: Vec<AccruedRow> =
+                sqlx::query_as(include_str!("../../queries/accrued_activity.sql"))
+                    .bind(&bidders)
+                    .bind(latest)
+                    .bind(config.as_of)
+                    .bind(config.time_unit)
+                    .bind(None::<&[ProductId]>)
+                    .bind(None::<ProductId>)
+                    .bind(None::<i64>)
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+            let entries = SettlementRevision {
+                as_of: config.as_of,
+                revision: 0,
+                revised_at: config.as_of,
+                config: Some(config.clone()),
+                activity: post_entries(&mut tx, &config, 0, &bidders, rows).await?,
+            };
+            issue_statements(&mut tx, settled_from, &entries).await?;
+            activity.extend(entries.activity);
+        }
+
+        tx.commit().await?;
+
+        Ok(Ok(SettlementRecord {
+            valid_from: settled_from,
+            valid_until: config.as_of,
+            activity,
+        }))
+    }
+
+    async fn reverse_settlement(
+        &self,
+        as_of: Self::DateTime,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_settlements(&mut tx).await?;
+
+        let Some(posted) = posted_settlement(&mut tx, as_of).await? else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+        if posted.config.is_none() {
+            return Ok(Err(SettlementConflict::Reversed { as_of }));
+        }
+
+        let revision = post_reversal(&mut tx, as_of, posted.revision + 1, revised_at).await?;
+        issue_statements(&mut tx, posted.settled_from, &revision).await?;
+
+        tx.commit().await?;
+
+        Ok(Ok(revision))
+    }
+
+    async fn correct_settlement(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_settlements(&mut tx).await?;
+
+        let as_of = config.as_of;
+        let Some(posted) = posted_settlement(&mut tx, as_of).await? else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+
+        // Repeating the latest correction returns it as it was made
+        if posted.revision > 0 && posted.config.as_ref() == Some(&config) {
+            let entries =
+                settlement_entries(&mut tx, as_of, posted.revision, posted.revision).await?;
+            return Ok(Ok(SettlementRevision {
+                as_of,
+                revision: posted.revision as u32,
+                revised_at: posted.revised_at,
+                config: Some(config),
+                activity: settled_activity(entries),
+            }));
+        }
+
+        let mut revision = posted.revision;
+        if posted.config.is_some() {
+            revision += 1;
+            let reversal = post_reversal(&mut tx, as_of, revision, revised_at).await?;
+            issue_statements(&mut tx, posted.settled_from, &reversal).await?;
+        }
+        revision += 1;
+
+        sqlx::query(
+            r#"
+                insert into
+                    settlement_revision (as_of, revision, revised_at, config)
+                values
+                    ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(as_of)
+        .bind(revision)
+        .bind(revised_at)
+        .bind(sqlx::types::Json(&config))
+        .execute(&mut *tx)
+        .await?;
+
+        // The activity was accrued in units of the original settlement's time
+        // unit, and is re-expressed in those of the correction
+        let scale = posted.original.time_unit / config.time_unit;
+        let original = settlement_entries(&mut tx, as_of, 0, 0).await?;
+        let mut bidders: Vec<BidderId> = original.iter().map(|row| row.bidder_id).collect();
+        bidders.dedup();
+        let rows = original
+            .into_iter()
+            .map(|row| AccruedRow {
+                bidder_id: row.bidder_id,
+                product_id: row.product_id,
+                accrued: row.accrued * scale,
+            })
+            .collect();
+
+        let correction = SettlementRevision {
+            as_of,
+            revision: revision as u32,
+            revised_at,
+            activity: post_entries(&mut tx, &config, revision, &bidders, rows).await?,
+            config: Some(config),
+        };
+        issue_statements(&mut tx, posted.settled_from, &correction).await?;
+
+        tx.commit().await?;
+
+        Ok(Ok(correction))
+    }
+
+    async fn get_settlement_revisions(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<SettlementRevision<Self>>, Self::Error> {
+        let mut conn = self.pool.acquire().await?;
+
+        let revisions: Vec<RevisionRow> = sqlx::query_as(
+            r#"
+                select
+                    revision,
+                    revised_at,
+                    config
+                from
+                    settlement_revision
+                where
+                    as_of = $1
+                order by
+                    revision
+            "#,
+        )
+        .bind(as_of)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut results = Vec::with_capacity(revisions.len());
+        for row in revisions {
+            let entries = settlement_entries(&mut conn, as_of, row.revision, row.revision).await?;
+            results.push(SettlementRevision {
+                as_of,
+                revision: row.revision as u32,
+                revised_at: row.revised_at,
+                config: row.config.map(|config| config.0),
+                activity: settled_activity(entries),
+            });
+        }
+        Ok(results)
+    }
+
+    /// Get the bidder's settlement history
+    ///
+    /// This returns a list of settled activity, one record per settlement in
+    /// which the bidder had activity. The records are ordered by the end of
+    /// their settlement interval in descending order.
+    async fn get_settlement_statement(
+        &self,
+        statement_id: u64,
+    ) -> Result<Option<SettlementStatement<Self>>, Self::Error> {
+        let row: Option<StatementRow> = sqlx::query_as(&format!("{STATEMENTS} where id = $1"))
+            .bind(statement_id as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(Into::into))
+    }
+
+    async fn get_settlement_statements(
+        &self,
+        as_of: Self::DateTime,
+        bidder_id: Option<Self::BidderId>,
+    ) -> Result<Vec<SettlementStatement<Self>>, Self::Error> {
+        let rows: Vec<StatementRow> = sqlx::query_as(&format!(
+            "{STATEMENTS} where as_of = $1 and ($2::uuid is null or bidder_id = $2) order by id"
+        ))
+        .bind(as_of)
+        .bind(bidder_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_settlement_history(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Activity<Self::ProductId>, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows: Vec<ValueRow<Activity<ProductId>>> = sqlx::query_as(
+            r#"
+                with
+                position_cte as (
+                    select
+                        as_of,
+                        jsonb_object_agg(product_id, settled) as positions
+                    from (
+                        select
+                            as_of,
+                            product_id,
+                            sum(settled) as settled
+                        from
+                            settlement_position
+                        where
+                            bidder_id = $1
+                        group by
+                            as_of,
+                            product_id
+                    ) as position_totals
+                    group by
+                        as_of
+                ),
+                payment_cte as (
+                    select
+                        as_of,
+                        sum(settled) as settled
+                    from
+                        settlement_payment
+                    where
+                        bidder_id = $1
+                    group by
+                        as_of
+                )
+                select
+                    settlement.settled_from as valid_from,
+                    settlement.as_of as valid_until,
+                    jsonb_build_object(
+                        'positions', coalesce(position_cte.positions, '{}'),
+                        'payment', payment_cte.settled
+                    ) as value
+                from
+                    settlement
+                join
+                    payment_cte
+                    on
+                        settlement.as_of = payment_cte.as_of
+                left join
+                    position_cte
+                    on
+                        settlement.as_of = position_cte.as_of
+                where
+                    ($2::timestamptz is null or settlement.settled_from >= $2)
+                and
+                    ($3::timestamptz is null or settlement.settled_from < $3)
+                order by
+                    settlement.as_of desc
+                limit $4
+            "#,
+        )
+        .bind(bidder_id)
+        .bind(query.after)
+        .bind(query.before)
+        .bind(limit_p1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Settlement intervals are contiguous, so we page on the end of the
+        // extra interval to ensure it is included in the next page.
+        let more = if rows.len() == limit + 1 {
+            let extra = rows.pop().unwrap();
+            Some(DateTimeRangeQuery {
+                before: extra.valid_until,
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_bidder_summary(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<BidderSummary<Self>, Self::Error> {
+        let unsettled = self
+            .get_unsettled_activity(bidder_id, as_of, time_unit)
+            .await?;
+
+        let settled_rows: Vec<AmountRow> = sqlx::query_as(
+            r#"
+                select
+                    product_id,
+                    sum(settled) as amount
+                from
+                    settlement_position
+                where
+                    bidder_id = $1
+                and
+                    as_of <= $2
+                group by
+                    product_id
+                union all
+                select
+                    null as product_id,
+                    coalesce(sum(settled), 0.0) as amount
+                from
+                    settlement_payment
+                where
+                    bidder_id = $1
+                and
+                    as_of <= $2
+            "#,
+        )
+        .bind(bidder_id)
+        .bind(as_of)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut settled = Activity::default();
+        for row in settled_rows {
+            match row.product_id {
+                Some(product_id) => {
+                    settled.positions.insert(product_id, row.amount);
+                }
+                None => settled.payment = row.amount,
+            }
+        }
+
+        // The outcomes in effect are expressed in terms of the contemporary
+        // product basis of the batch that produced them
+        let rates = sqlx::query_as::<_, AmountRow>(
+            r#"
+                select
+                    basis_view.product_id,
+                    sum((portfolio_outcome.value ->> 'rate')::double precision * basis_view.weight) as amount
+                from
+                    portfolio_outcome
+                join
+                    portfolio
+                    on
+                        portfolio_outcome.portfolio_id = portfolio.id
+                join
+                    basis_view
+                    on
+                        portfolio_outcome.portfolio_id = basis_view.portfolio_id
+                        and
+                        basis_view.valid_from <= portfolio_outcome.valid_from
+                        and
+                        (portfolio_outcome.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+                where
+                    portfolio.bidder_id = $1
+                and
+                    portfolio_outcome.valid_from <= $2
+                and
+                    ($2 < portfolio_outcome.valid_until or portfolio_outcome.valid_until is null)
+                and
+                    (portfolio_outcome.value ->> 'rate')::double precision != 0
+                group by
+                    basis_view.product_id
+            "#,
+        )
+        .bind(bidder_id)
+        .bind(as_of)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .filter_map(|row| row.product_id.map(|product_id| (product_id, row.amount)))
+        .collect();
+
+        let mut positions = settled.positions.clone();
+        for (product_id, amount) in unsettled.value.positions.iter() {
+            *positions.entry(*product_id).or_default() += amount;
+        }
+
+        Ok(BidderSummary {
+            as_of,
+            positions,
+            rates,
+            settled,
+            unsettled: unsettled.value,
+            unsettled_from: unsettled.valid_from,
+        })
+    }
+}
+
+/// The timestamp of the earliest batch outcome, if any
+async fn first_batch<'c, E: sqlx::PgExecutor<'c>>(
+    executor: E,
+) -> Result<Option<DateTime>, sqlx::Error> {
+    sqlx::query_scalar("select min(valid_from) from portfolio_outcome")
+        .fetch_one(executor)
+        .await
+}
+
+/// Serialize the settlements, each of which continues from the previous. The
+/// lock conflicts with itself, but not with readers.
+async fn lock_settlements(conn: &mut sqlx::PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("lock table settlement in share row exclusive mode")
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// The state of the settlement as of `as_of`, if it was made, net of any
+/// revisions since.
+async fn posted_settlement(
+    conn: &mut sqlx::PgConnection,
+    as_of: DateTime,
+) -> Result<Option<PostedSettlement>, sqlx::Error> {
+    let Some(settlement): Option<SettlementRow> =
+        sqlx::query_as("select settled_from, config from settlement where as_of = $1")
+            .bind(as_of)
+            .fetch_optional(&mut *conn)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    let latest: Option<RevisionRow> = sqlx::query_as(
+        r#"
+            select
+                revision,
+                revised_at,
+                config
+            from
+                settlement_revision
+            where
+                as_of = $1
+            order by
+                revision desc
+            limit 1
+        "#,
+    )
+    .bind(as_of)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let original = settlement.config.0;
+    Ok(Some(match latest {
+        Some(latest) => PostedSettlement {
+            settled_from: settlement.settled_from,
+            original,
+            revision: latest.revision,
+            revised_at: latest.revised_at,
+            config: latest.config.map(|config| config.0),
+        },
+        None => PostedSettlement {
+            settled_from: settlement.settled_from,
+            revision: 0,
+            revised_at: as_of,
+            config: Some(original.clone()),
+            original,
+        },
+    }))
+}
+
+/// The entries of the settlement as of `as_of` posted by the revisions
+/// `from..=until`, summed per bidder and product.
+async fn settlement_entries(
+    conn: &mut sqlx::PgConnection,
+    as_of: DateTime,
+    from: i32,
+    until: i32,
+) -> Result<Vec<TotalRow>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+            select
+                bidder_id,
+                product_id,
+                sum(accrued) as accrued,
+                sum(settled) as settled
+            from
+                settlement_position
+            where
+                as_of = $1
+            and
+                revision between $2 and $3
+            group by
+                bidder_id,
+                product_id
+            union all
+            select
+                bidder_id,
+                null as product_id,
+                sum(accrued) as accrued,
+                sum(settled) as settled
+            from
+                settlement_payment
+            where
+                as_of = $1
+            and
+                revision between $2 and $3
+            group by
+                bidder_id
+            order by
+                bidder_id
+        "#,
+    )
+    .bind(as_of)
+    .bind(from)
+    .bind(until)
+    .fetch_all(conn)
+    .await
+}
+
+/// Settle the accrued activity of the given bidders as of the configuration's
+/// `as_of`, posting the entries as `revision`.
+async fn post_entries(
+    conn: &mut sqlx::PgConnection,
+    config: &SettlementConfig<DateTime>,
+    revision: i32,
+    bidders: &[BidderId],
+    rows: Vec<AccruedRow>,
+) -> Result<Map<BidderId, Activity<ProductId>>, sqlx::Error> {
+    // Rounding each settlement independently would let the residuals drift,
+    // so we instead round the running totals and settle the difference
+    // against what has already been settled.
+    let totals = sqlx::query_as::<_, TotalRow>(
+        r#"
+            select
+                bidder_id,
+                product_id,
+                sum(accrued) as accrued,
+                sum(settled) as settled
+            from
+                settlement_position
+            where
+                bidder_id = any($1)
+            group by
+                bidder_id,
+                product_id
+            union all
+            select
+                bidder_id,
+                null as product_id,
+                sum(accrued) as accrued,
+                sum(settled) as settled
+            from
+                settlement_payment
+            where
+                bidder_id = any($1)
+            group by
+                bidder_id
+        "#,
+    )
+    .bind(bidders)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| ((row.bidder_id, row.product_id), (row.accrued, row.settled)))
+    .collect::<HashMap<_, _>>();
+
+    let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+    for row in rows {
+        let (accrued, settled) = totals
+            .get(&(row.bidder_id, row.product_id))
+            .copied()
+            .unwrap_or_default();
+
+        let entry = activity.entry(row.bidder_id).or_default();
+        match row.product_id {
+            Some(product_id) => {
+                let decimals = config.position_decimals;
+                let amount = round(round(accrued + row.accrued, decimals) - settled, decimals);
+                sqlx::query(
+                    r#"
+                        insert into
+                            settlement_position (as_of, revision, bidder_id, product_id, accrued, settled)
+                        values
+                            ($1, $2, $3, $4, $5, $6)
+                    "#,
+                )
+                .bind(config.as_of)
+                .bind(revision)
+                .bind(row.bidder_id)
+                .bind(product_id)
+                .bind(row.accrued)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?;
+                entry.positions.insert(product_id, amount);
+            }
+            None => {
+                let decimals = config.payment_decimals;
+                let amount = round(round(accrued + row.accrued, decimals) - settled, decimals);
+                sqlx::query(
+                    r#"
+                        insert into
+                            settlement_payment (as_of, revision, bidder_id, accrued, settled)
+                        values
+                            ($1, $2, $3, $4, $5)
+                    "#,
+                )
+                .bind(config.as_of)
+                .bind(revision)
+                .bind(row.bidder_id)
+                .bind(row.accrued)
+                .bind(amount)
+                .execute(&mut *conn)
+                .await?;
+                entry.payment = amount;
+            }
+        }
+    }
+    Ok(activity)
+}
+
+/// Issue a statement to each bidder with entries in `entries`.
+async fn issue_statements(
+    conn: &mut sqlx::PgConnection,
+    settled_from: DateTime,
+    entries: &SettlementRevision<Db>,
+) -> Result<(), sqlx::Error> {
+    let revised_at = (entries.revision > 0).then_some(entries.revised_at);
+    for (bidder_id, activity) in entries.activity.iter() {
+        sqlx::query(
+            r#"
+                insert into
+                    settlement_statement (as_of, revision, bidder_id, settled_from, revised_at, config, activity)
+                values
+                    ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(entries.as_of)
+        .bind(entries.revision as i32)
+        .bind(bidder_id)
+        .bind(settled_from)
+        .bind(revised_at)
+        .bind(entries.config.as_ref().map(sqlx::types::Json))
+        .bind(sqlx::types::Json(activity))
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Reverse the settlement as of `as_of`, posting entries offsetting every
+/// entry posted so far as `revision`.
+async fn post_reversal(
+    conn: &mut sqlx::PgConnection,
+    as_of: DateTime,
+    revision: i32,
+    revised_at: DateTime,
+) -> Result<SettlementRevision<Db>, sqlx::Error> {
+    sqlx::query(
+        r#"
+            insert into
+                settlement_revision (as_of, revision, revised_at, config)
+            values
+                ($1, $2, $3, null)
+        "#,
+    )
+    .bind(as_of)
+    .bind(revision)
+    .bind(revised_at)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+            insert into
+                settlement_position (as_of, revision, bidder_id, product_id, accrued, settled)
+            select
+                as_of,
+                $2,
+                bidder_id,
+                product_id,
+                -sum(accrued),
+                -sum(settled)
+            from
+                settlement_position
+            where
+                as_of = $1
+            group by
+                as_of,
+                bidder_id,
+                product_id
+        "#,
+    )
+    .bind(as_of)
+    .bind(revision)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+            insert into
+                settlement_payment (as_of, revision, bidder_id, accrued, settled)
+            select
+                as_of,
+                $2,
+                bidder_id,
+                -sum(accrued),
+                -sum(settled)
+            from
+                settlement_payment
+            where
+                as_of = $1
+            group by
+                as_of,
+                bidder_id
+        "#,
+    )
+    .bind(as_of)
+    .bind(revision)
+    .execute(&mut *conn)
+    .await?;
+
+    let entries = settlement_entries(conn, as_of, revision, revision).await?;
+    Ok(SettlementRevision {
+        as_of,
+        revision: revision as u32,
+        revised_at,
+        config: None,
+        activity: settled_activity(entries),
+    })
+}
@@ -0,0 +1,126 @@
+#![warn(missing_docs)]
+// Note: this overwrites the link in the README to point to the rust docs of the fts-postgres crate.
+//! [fts_core]: https://docs.rs/fts_core/latest/fts_core/index.html
+//! [fts_axum]: https://docs.rs/fts_axum/latest/fts_axum/index.html
+//! [fts_solver]: https://docs.rs/fts_solver/latest/fts_solver/index.html
+//! [fts_sqlite]: https://docs.rs/fts_sqlite/latest/fts_sqlite/index.html
+//! [fts_postgres]: https://docs.rs/fts_postgres/latest/fts_postgres/index.html
+#![doc = include_str!("../README.md")]
+
+use sqlx::postgres;
+
+pub mod config;
+mod filter;
+mod r#impl;
+pub mod types;
+
+use config::PostgresConfig;
+
+/// The schema migrations, which are applied when the database is opened
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./schema");
+
+/// PostgreSQL database implementation for flow trading repositories.
+///
+/// This struct wraps a connection pool to a PostgreSQL database, implementing
+/// all the repository traits defined in `fts-core`. Unlike SQLite, PostgreSQL
+/// supports concurrent writers, so a single pool serves both reads and writes.
+/// Operations spanning several statements run within a transaction.
+///
+/// # Example
+///
+/// ```no_run
+/// # use fts_postgres::{Db, config::PostgresConfig, types::DateTime};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let config = PostgresConfig {
+///     database_url: "postgres://localhost/flow_trading".to_owned(),
+///     max_connections: 10,
+///     archive_schema: None,
+///     record_batch_inputs: false,
+/// };
+/// let now = DateTime::from(time::OffsetDateTime::now_utc());
+/// let db = Db::open(&config, now).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Db {
+    /// Connection pool for all database operations
+    pub pool: sqlx::Pool<sqlx::Postgres>,
+    /// The schema into which pruned history is archived, if any
+    pub archive_schema: Option<String>,
+    /// Whether the inputs of each batch are recorded
+    pub record_batch_inputs: bool,
+}
+
+impl Db {
+    /// Open a connection to the specified PostgreSQL database.
+    ///
+    /// The database must already exist. Any pending migrations are applied,
+    /// and the batch table is initialized.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration specifying the database URL and pool size
+    /// * `as_of` - Initial timestamp for the batch table if creating a new database
+    ///
+    /// # Errors
+    ///
+    /// Returns `sqlx::Error` if:
+    /// - Database connection fails
+    /// - Migrations fail to apply
+    /// - Initial batch row creation fails
+    pub async fn open(
+        config: &PostgresConfig,
+        as_of: types::DateTime,
+    ) -> Result<Self, sqlx::Error> {
+        let db = Self::connect(config).await?;
+        let pool = &db.pool;
+
+        // Run any pending migrations before returning. The migrator holds an
+        // advisory lock, so several servers may safely open the same database.
+        MIGRATOR.run(pool).await?;
+
+        // Also, ensure there is one row in the batch table.
+        // This is important because of the trigger-managed temporal tables.
+        // The `batch` table consists of a single row with JSON columns. On update,
+        // a trigger will "explode" the JSON values into the appropriate outcome-tracking
+        // tables, similarly to how we manage portfolios and demand curves.
+        sqlx::query(
+            r#"
+            insert into
+                batch (id, as_of, portfolio_outcomes, product_outcomes)
+            values
+                (0, $1, '{}', '{}')
+            on conflict
+                do nothing
+            "#,
+        )
+        .bind(as_of)
+        .execute(pool)
+        .await?;
+
+        Ok(db)
+    }
+
+    /// Connect to the specified PostgreSQL database without migrating it.
+    ///
+    /// Unlike [`Db::open`], no migration is applied and the batch table is not
+    /// initialized, so the handle is only fit to inspect the database, e.g.
+    /// its [migration status](Db::migration_status) ahead of a deployment.
+    ///
+    /// # Errors
+    ///
+    /// Returns `sqlx::Error` if the database connection fails
+    pub async fn connect(config: &PostgresConfig) -> Result<Self, sqlx::Error> {
+        let pool = postgres::PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .connect(&config.database_url)
+            .await?;
+
+        Ok(Self {
+            pool,
+            archive_schema: config.archive_schema.clone(),
+            record_batch_inputs: config.record_batch_inputs,
+        })
+    }
+}
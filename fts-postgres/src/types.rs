@@ -0,0 +1,157 @@
+//! Type definitions for the PostgreSQL implementation.
+//!
+//! This module contains both public types used throughout the crate and internal
+//! types used for database row mapping. The public types include strongly-typed
+//! IDs and datetime representations that ensure type safety across the system.
+
+use fts_core::{
+    models::{
+        Basis, DemandCurve, DemandCurveDto, DemandRecord, PortfolioRecord, ProductRecord, Sum,
+        ValueRecord, Weights,
+    },
+    ports::Repository,
+};
+
+mod datetime;
+pub use datetime::DateTime;
+
+mod ids;
+pub use ids::{BidderId, DemandId, PortfolioId, ProductId};
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct DemandRow<AppData> {
+    pub id: DemandId,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub bidder_id: BidderId,
+    pub app_data: sqlx::types::Json<AppData>,
+    pub curve_data: Option<sqlx::types::Json<DemandCurveDto>>,
+    pub portfolios: Option<sqlx::types::Json<Sum<PortfolioId>>>,
+}
+
+impl<T, AppData> From<DemandRow<AppData>> for DemandRecord<T, AppData>
+where
+    T: Repository<
+            DateTime = DateTime,
+            BidderId = BidderId,
+            DemandId = DemandId,
+            PortfolioId = PortfolioId,
+            ProductId = ProductId,
+        >,
+{
+    fn from(row: DemandRow<AppData>) -> Self {
+        DemandRecord {
+            id: row.id,
+            valid_from: row.valid_from,
+            valid_until: row.valid_until,
+            bidder_id: row.bidder_id,
+            app_data: row.app_data.0,
+            curve_data: row
+                .curve_data
+                // SAFETY: we are deserialized from the database, and we ensure we only save valid demand curves
+                .map(|x| unsafe { DemandCurve::new_unchecked(x.0) })
+                .unwrap_or_default(),
+            portfolios: row.portfolios.map(|x| x.0).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct PortfolioRow<AppData> {
+    pub id: PortfolioId,
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub bidder_id: BidderId,
+    pub app_data: sqlx::types::Json<AppData>,
+    pub demand: Option<sqlx::types::Json<Weights<DemandId>>>,
+    pub basis: Option<sqlx::types::Json<Basis<ProductId>>>,
+}
+
+impl<T, AppData> From<PortfolioRow<AppData>> for PortfolioRecord<T, AppData>
+where
+    T: Repository<
+            DateTime = DateTime,
+            BidderId = BidderId,
+            DemandId = DemandId,
+            PortfolioId = PortfolioId,
+            ProductId = ProductId,
+        >,
+{
+    fn from(row: PortfolioRow<AppData>) -> Self {
+        PortfolioRecord {
+            id: row.id,
+            valid_from: row.valid_from,
+            valid_until: row.valid_until,
+            bidder_id: row.bidder_id,
+            app_data: row.app_data.0,
+            demand: row.demand.map(|x| x.0).unwrap_or_default(),
+            basis: row.basis.map(|x| x.0).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct ProductRow<AppData> {
+    pub id: ProductId,
+    pub app_data: sqlx::types::Json<AppData>,
+    pub parent: sqlx::types::Json<(ProductId, f64)>,
+    pub basis: sqlx::types::Json<Basis<ProductId>>,
+}
+
+impl<T, AppData> From<ProductRow<AppData>> for ProductRecord<T, AppData>
+where
+    T: Repository<
+            DateTime = DateTime,
+            BidderId = BidderId,
+            DemandId = DemandId,
+            PortfolioId = PortfolioId,
+            ProductId = ProductId,
+        >,
+{
+    fn from(row: ProductRow<AppData>) -> Self {
+        ProductRecord {
+            id: row.id,
+            app_data: row.app_data.0,
+            parent: row.parent.0,
+            basis: row.basis.0,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct ValueRow<Value> {
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub value: sqlx::types::Json<Value>,
+}
+
+impl<T> From<ValueRow<T>> for ValueRecord<DateTime, T> {
+    fn from(row: ValueRow<T>) -> Self {
+        ValueRecord {
+            valid_from: row.valid_from,
+            valid_until: row.valid_until,
+            value: row.value.0,
+            actor: None,
+        }
+    }
+}
+
+/// A history row, additionally attributed to the actor responsible for the change
+#[derive(sqlx::FromRow)]
+pub(crate) struct HistoryRow<Value> {
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub value: sqlx::types::Json<Value>,
+    pub actor: String,
+}
+
+impl<T> From<HistoryRow<T>> for ValueRecord<DateTime, T> {
+    fn from(row: HistoryRow<T>) -> Self {
+        ValueRecord {
+            valid_from: row.valid_from,
+            valid_until: row.valid_until,
+            value: row.value.0,
+            actor: row.actor.parse().ok(),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! DateTime type for temporal data in flow trading.
+//!
+//! This module provides a [`DateTime`] type that represents UTC timestamps with
+//! microsecond precision. It wraps `time::OffsetDateTime` and ensures all
+//! serialization happens in RFC3339 format for consistency across the system.
+
+use std::{borrow::Borrow, fmt::Display};
+use time::format_description::well_known::Rfc3339;
+
+/// A type that represents a datetime with subsecond precision.
+///
+/// This type is used throughout the flow trading system to represent timestamps
+/// for events, validity periods, and historical records. It ensures:
+///
+/// - All times are stored and processed in UTC
+/// - Serialization/deserialization uses RFC3339 format
+/// - PostgreSQL storage uses the `timestamptz` type
+///
+/// PostgreSQL stores timestamps with microsecond precision, so any finer
+/// precision is truncated on conversion. This way, a timestamp compares equal
+/// to itself after a round trip through the database.
+///
+/// # Examples
+///
+/// ```
+/// # use fts_postgres::types::DateTime;
+/// # use time::OffsetDateTime;
+/// let now = OffsetDateTime::now_utc();
+/// let datetime = DateTime::from(now);
+/// println!("{}", datetime); // Prints in RFC3339 format
+/// ```
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+)]
+#[serde(from = "DateTimeDto", into = "DateTimeDto")]
+#[sqlx(transparent)]
+pub struct DateTime(time::OffsetDateTime);
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format(&Rfc3339).unwrap())
+    }
+}
+
+impl<T: Borrow<time::OffsetDateTime>> From<T> for DateTime {
+    fn from(value: T) -> Self {
+        let utc = value.borrow().to_offset(time::UtcOffset::UTC);
+        let micros = utc.microsecond();
+        Self(utc.replace_microsecond(micros).unwrap())
+    }
+}
+
+impl From<DateTime> for time::OffsetDateTime {
+    fn from(value: DateTime) -> Self {
+        value.0
+    }
+}
+
+// This is a helper type that ensures (de)serialization happens with respect to RFC3339
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DateTimeDto(#[serde(with = "time::serde::rfc3339")] time::OffsetDateTime);
+
+impl From<DateTimeDto> for DateTime {
+    fn from(value: DateTimeDto) -> Self {
+        value.0.into()
+    }
+}
+
+impl From<DateTime> for DateTimeDto {
+    fn from(value: DateTime) -> Self {
+        DateTimeDto(value.into())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for DateTime {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "DateTime".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "format": "date-time",
+        })
+    }
+}
@@ -0,0 +1,74 @@
+//! Strongly-typed identifier types for flow trading entities.
+//!
+//! This module provides newtype wrappers around UUIDs for different entity types
+//! in the system. Using distinct types for each kind of ID prevents mixing up
+//! identifiers at compile time and improves code clarity.
+//!
+//! All ID types implement:
+//! - Serialization/deserialization as transparent UUIDs
+//! - PostgreSQL storage as native `uuid` values (and arrays thereof)
+//! - Display formatting
+//! - Conversion to/from standard UUIDs
+
+macro_rules! new_id {
+    ($struct:ident) => {
+        new_id!($struct, "A newtype wrapper around a uuid");
+    };
+    ($struct:ident, $doc:literal) => {
+        #[doc = $doc]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            serde::Serialize,
+            serde::Deserialize,
+            sqlx::Type,
+        )]
+        #[serde(transparent)]
+        #[sqlx(transparent)]
+        pub struct $struct(pub uuid::Uuid);
+
+        impl From<$struct> for uuid::Uuid {
+            fn from(value: $struct) -> Self {
+                value.0
+            }
+        }
+
+        impl From<uuid::Uuid> for $struct {
+            fn from(value: uuid::Uuid) -> Self {
+                Self(value)
+            }
+        }
+
+        impl std::fmt::Display for $struct {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl std::str::FromStr for $struct {
+            type Err = <uuid::Uuid as std::str::FromStr>::Err;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(Self(s.parse()?))
+            }
+        }
+    };
+}
+
+new_id!(
+    BidderId,
+    "Unique identifier for a bidder in the flow trading system"
+);
+new_id!(DemandId, "Unique identifier for a demand curve submission");
+new_id!(
+    PortfolioId,
+    "Unique identifier for a portfolio that groups demands and products"
+);
+new_id!(ProductId, "Unique identifier for a tradeable product");
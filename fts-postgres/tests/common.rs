@@ -0,0 +1,157 @@
+use fts_core::ports::Application;
+use fts_postgres::config::PostgresConfig;
+use fts_solver::clarabel::ClarabelSolver;
+use sqlx::{ConnectOptions as _, Connection as _, Executor as _, postgres::PgConnectOptions};
+use types::{BidderId, DateTime, DemandId, PortfolioId, ProductId};
+
+// The backend under test, as seen by the shared repository tests
+pub use fts_postgres::{Db, types};
+
+/// A database created for a single test on the server at `POSTGRES_URL`,
+/// which is dropped along with this handle.
+pub struct TestDb {
+    pub db: Db,
+    url: String,
+    name: String,
+}
+
+impl TestDb {
+    /// Create and open a fresh database, or None if no server is configured
+    pub async fn create(as_of: DateTime) -> anyhow::Result<Option<Self>> {
+        Self::create_with_archive(as_of, None).await
+    }
+
+    /// As `create`, archiving pruned history into the given schema
+    pub async fn create_with_archive(
+        as_of: DateTime,
+        archive_schema: Option<&str>,
+    ) -> anyhow::Result<Option<Self>> {
+        let Ok(url) = std::env::var("POSTGRES_URL") else {
+            eprintln!("POSTGRES_URL is not set, skipping");
+            return Ok(None);
+        };
+
+        let name = format!("fts_test_{}", uuid::Uuid::new_v4().simple());
+        let mut admin = sqlx::PgConnection::connect(&url).await?;
+        admin
+            .execute(format!("create database {name}").as_str())
+            .await?;
+        admin.close().await?;
+
+        let options: PgConnectOptions = url.parse()?;
+        let config = PostgresConfig {
+            database_url: options.database(&name).to_url_lossy().to_string(),
+            max_connections: 5,
+            archive_schema: archive_schema.map(ToOwned::to_owned),
+            record_batch_inputs: false,
+        };
+        let db = Db::open(&config, as_of).await?;
+
+        Ok(Some(Self { db, url, name }))
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let url = self.url.clone();
+        let statement = format!("drop database if exists {} with (force)", self.name);
+        // The test's runtime may be shutting down, so we drop the database
+        // from a runtime of our own
+        let _ = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    if let Ok(mut admin) = sqlx::PgConnection::connect(&url).await {
+                        let _ = admin.execute(statement.as_str()).await;
+                    }
+                })
+        })
+        .join();
+    }
+}
+
+pub struct TestApp(pub TestDb);
+
+// Not every test uses each fixture
+#[allow(dead_code)]
+impl TestApp {
+    /// Open a fresh database, or None if no server is configured
+    pub async fn open(as_of: DateTime) -> anyhow::Result<Option<Self>> {
+        Ok(TestDb::create(as_of).await?.map(Self))
+    }
+
+    pub fn record_batch_inputs(&mut self, record: bool) {
+        self.0.db.record_batch_inputs = record;
+    }
+}
+
+impl Application for TestApp {
+    type Context = ();
+    type DemandData = ();
+    type PortfolioData = ();
+    type ProductData = ();
+    type Repository = Db;
+    type Solver = ClarabelSolver<DemandId, PortfolioId, ProductId>;
+
+    fn database(&self) -> &Self::Repository {
+        &self.0.db
+    }
+
+    fn now(&self) -> DateTime {
+        time::OffsetDateTime::now_utc().into()
+    }
+
+    fn solver(&self) -> Self::Solver {
+        ClarabelSolver::default()
+    }
+
+    fn generate_demand_id(&self, _data: &Self::DemandData) -> (DemandId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_portfolio_id(&self, _data: &Self::PortfolioData) -> (PortfolioId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    fn generate_product_id(&self, _data: &Self::ProductData) -> (ProductId, DateTime) {
+        (uuid::Uuid::new_v4().into(), self.now())
+    }
+
+    async fn can_create_bid(&self, _context: &Self::Context) -> Option<BidderId> {
+        None
+    }
+
+    async fn can_query_bid(&self, _context: &Self::Context) -> Vec<BidderId> {
+        Vec::new()
+    }
+
+    async fn can_read_bid(&self, _context: &Self::Context, _bidder_id: BidderId) -> bool {
+        false
+    }
+
+    async fn can_update_bid(&self, _context: &Self::Context, _bidder_id: BidderId) -> bool {
+        false
+    }
+
+    async fn can_view_products(&self, _context: &Self::Context) -> bool {
+        true
+    }
+
+    async fn can_manage_products(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_batch(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_run_settlement(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_purge_bid(&self, _context: &Self::Context) -> bool {
+        false
+    }
+}
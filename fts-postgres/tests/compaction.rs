@@ -0,0 +1,4 @@
+mod common;
+
+#[path = "../../repository-tests/compaction.rs"]
+mod compaction;
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{TestApp, TestDb};
+use fts_core::ports::{Application as _, HealthRepository as _};
+
+#[tokio::test]
+async fn test_migrations_are_applied_on_open() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(database) = TestDb::create(now.into()).await? else {
+        return Ok(());
+    };
+
+    let app = TestApp(database);
+
+    let status = app.database().check_health().await?;
+    assert!(status.applied > 0);
+    assert_eq!(status.pending, 0);
+    assert_eq!(status.migrations.len(), status.applied as usize);
+    assert!(status.migrations.iter().all(|migration| migration.applied));
+    assert_eq!(
+        status.version,
+        status.migrations.last().map(|migration| migration.version)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+mod common;
+
+#[path = "../../repository-tests/retention.rs"]
+mod retention;
+
+use common::{TestApp, TestDb};
+use fts_core::ports::{Application as _, RetentionRepository as _};
+use fts_postgres::types::DemandId;
+use retention::open_market;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_prune_history_into_archive() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(database) = TestDb::create_with_archive(now.into(), Some("archive")).await? else {
+        return Ok(());
+    };
+    let (app, _, demand_id) = open_market(TestApp(database), now).await?;
+    let db = app.database();
+
+    let record = db
+        .prune_history((now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.curves, 1);
+    assert!(record.archived);
+
+    // Pruning again archives nothing more
+    let record = db
+        .prune_history((now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.curves, 0);
+
+    let archived: Vec<(DemandId,)> = sqlx::query_as("select demand_id from archive.curve_data")
+        .fetch_all(&db.pool)
+        .await?;
+    assert_eq!(archived, vec![(demand_id,)]);
+
+    Ok(())
+}
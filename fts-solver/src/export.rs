@@ -4,6 +4,59 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::io::Write;
 
+/// The standard file formats the quadratic program can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// The (free) MPS format, see [`export_mps`]
+    Mps,
+    /// The CPLEX LP format, see [`export_lp`]
+    Lp,
+}
+
+impl ExportFormat {
+    /// Every supported format
+    pub const ALL: [Self; 2] = [Self::Mps, Self::Lp];
+
+    /// The name of the format, which is also its conventional file extension
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mps => "mps",
+            Self::Lp => "lp",
+        }
+    }
+
+    /// The format with the given name or file extension (ignoring case), if any
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// Convert a set of flow trading submissions to a quadratic program and
+    /// export this program in this format.
+    pub fn export<
+        DemandId: Display + Eq + Hash + Clone,
+        PortfolioId: Display + Eq + Hash + Clone,
+        ProductId: Display + Eq + Hash + Clone + Ord,
+    >(
+        self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        buffer: &mut impl Write,
+    ) -> Result<(), std::io::Error> {
+        match self {
+            Self::Mps => export_mps(demand_curves, portfolios, buffer),
+            Self::Lp => export_lp(demand_curves, portfolios, buffer),
+        }
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
 /// Convert a set of flow trading submissions to a quadratic program and export
 /// this program to `.mps` format.
 pub fn export_mps<
@@ -10,6 +10,18 @@ pub mod clarabel;
 #[cfg(feature = "osqp")]
 pub mod osqp;
 
+/// The error of a solver which failed to solve an auction
+#[derive(Debug, thiserror::Error)]
+pub enum SolveError {
+    /// The solver panicked
+    #[error("the solver panicked: {0}")]
+    Panicked(#[from] tokio::task::JoinError),
+
+    /// The solver terminated without a solution, with the given status
+    #[error("the solver terminated without a solution: {0}")]
+    Unsolved(String),
+}
+
 // A helper method that prepares an auction by canonicalizing and sorting elements
 // in a manner that facilitates CSC matrix construction
 pub(crate) fn prepare<
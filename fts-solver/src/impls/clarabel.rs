@@ -1,7 +1,7 @@
-use crate::{PortfolioOutcome, ProductOutcome, disaggregate};
+use crate::{PortfolioOutcome, ProductOutcome, SolveError, disaggregate};
 use clarabel::{algebra::*, solver::*};
 use fts_core::{
-    models::{Basis, DemandCurve, Map, Weights},
+    models::{Basis, DemandCurve, Map, SolverTelemetry, Weights},
     ports::Solver,
 };
 use std::{hash::Hash, marker::PhantomData};
@@ -41,20 +41,19 @@ impl<
         settings: DefaultSettings<f64>,
         demand_curves: Map<DemandId, DemandCurve>,
         portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
-    ) -> Result<
-        (
-            Map<PortfolioId, PortfolioOutcome>,
-            Map<ProductId, ProductOutcome>,
-        ),
-        SolverStatus,
-    > {
+        telemetry: &mut SolverTelemetry,
+    ) -> Option<(
+        Map<PortfolioId, PortfolioOutcome>,
+        Map<ProductId, ProductOutcome>,
+    )> {
         // This prepare method canonicalizes the input in a manner appropriate for naive CSC construction
         let (demand_curves, portfolios, mut portfolio_outcomes, mut product_outcomes) =
             super::prepare(demand_curves, portfolios);
 
         // If there are no portfolios or products, there is nothing to do.
         if portfolio_outcomes.len() == 0 || product_outcomes.len() == 0 {
-            return Ok((portfolio_outcomes, product_outcomes));
+            telemetry.status = "Trivial".to_string();
+            return Some((portfolio_outcomes, product_outcomes));
         }
 
         // The trade and bid constraints are all (something) = 0, we need to
@@ -184,13 +183,28 @@ impl<
         let mut solver = DefaultSolver::new(&p_matrix, &q, &a_matrix, &b, &s, settings)
             .expect("valid solver config");
         solver.solve();
-        match solver.solution.status {
+
+        let solution = &solver.solution;
+        telemetry.status = format!("{:?}", solution.status);
+        telemetry.stats = [
+            ("iterations", solution.iterations as f64),
+            ("solve_time", solution.solve_time),
+            ("obj_val", solution.obj_val),
+            ("obj_val_dual", solution.obj_val_dual),
+            ("r_prim", solution.r_prim),
+            ("r_dual", solution.r_dual),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+
+        match solution.status {
             SolverStatus::Solved => {}
             SolverStatus::AlmostSolved => {
-                tracing::warn!(status = ?solver.solution.status, "convergence issues");
+                tracing::warn!(status = ?solution.status, "convergence issues");
             }
-            status => {
-                return Err(status);
+            _ => {
+                return None;
             }
         };
 
@@ -217,17 +231,46 @@ impl<
         // of the trades as a tie-break. We should think about the best way to regularize
         // the solve accordingly.
 
-        Ok((portfolio_outcomes, product_outcomes))
+        Some((portfolio_outcomes, product_outcomes))
     }
 }
 
+/// The notable settings of the solver, by name
+fn describe(settings: &DefaultSettings<f64>) -> Map<String, String> {
+    macro_rules! describe {
+        ($($name:ident),* $(,)?) => {
+            [$((stringify!($name), settings.$name.to_string())),*]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect()
+        };
+    }
+
+    describe!(
+        max_iter,
+        time_limit,
+        tol_gap_abs,
+        tol_gap_rel,
+        tol_feas,
+        tol_infeas_abs,
+        tol_infeas_rel,
+        tol_ktratio,
+        equilibrate_enable,
+        direct_solve_method,
+        static_regularization_enable,
+        dynamic_regularization_enable,
+        iterative_refinement_enable,
+        presolve_enable,
+    )
+}
+
 impl<
     DemandId: Clone + Eq + Hash + Ord + Send + Sync + 'static,
     PortfolioId: Clone + Eq + Hash + Ord + Send + Sync + 'static,
     ProductId: Clone + Eq + Hash + Ord + Send + Sync + 'static,
 > Solver<DemandId, PortfolioId, ProductId> for ClarabelSolver<DemandId, PortfolioId, ProductId>
 {
-    type Error = tokio::task::JoinError;
+    type Error = SolveError;
     type PortfolioOutcome = PortfolioOutcome;
     type ProductOutcome = ProductOutcome;
 
@@ -237,7 +280,7 @@ impl<
         &self,
         demand_curves: Map<DemandId, DemandCurve>,
         portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
-        _state: Self::State,
+        state: Self::State,
     ) -> Result<
         (
             Map<PortfolioId, Self::PortfolioOutcome>,
@@ -245,12 +288,52 @@ impl<
         ),
         Self::Error,
     > {
+        self.solve_with_telemetry(demand_curves, portfolios, state)
+            .await
+            .0
+    }
+
+    async fn solve_with_telemetry(
+        &self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        _state: Self::State,
+    ) -> (
+        Result<
+            (
+                Map<PortfolioId, Self::PortfolioOutcome>,
+                Map<ProductId, Self::ProductOutcome>,
+            ),
+            Self::Error,
+        >,
+        SolverTelemetry,
+    ) {
         let settings = self.0.clone();
-        let solution =
-            tokio::spawn(async move { Self::solve(settings, demand_curves, portfolios) }).await?;
+        let configured = SolverTelemetry {
+            backend: "clarabel".to_string(),
+            settings: describe(&settings),
+            ..Default::default()
+        };
 
-        // TODO: The JoinError happens when we panic inside.
-        // We can change this later, for now we just assume the solver worked.
-        Ok(solution.expect("failed to solve"))
+        let mut telemetry = configured.clone();
+        let task = tokio::spawn(async move {
+            let solution = Self::solve(settings, demand_curves, portfolios, &mut telemetry);
+            (solution, telemetry)
+        });
+
+        match task.await {
+            Ok((Some(solution), telemetry)) => (Ok(solution), telemetry),
+            Ok((None, telemetry)) => (
+                Err(SolveError::Unsolved(telemetry.status.clone())),
+                telemetry,
+            ),
+            Err(error) => (
+                Err(error.into()),
+                SolverTelemetry {
+                    status: "Panicked".to_string(),
+                    ..configured
+                },
+            ),
+        }
     }
 }
@@ -1,6 +1,6 @@
-use crate::{PortfolioOutcome, ProductOutcome, disaggregate};
+use crate::{PortfolioOutcome, ProductOutcome, SolveError, disaggregate};
 use fts_core::{
-    models::{Basis, DemandCurve, Map, Weights},
+    models::{Basis, DemandCurve, Map, SolverTelemetry, Weights},
     ports::Solver,
 };
 use osqp::{CscMatrix, Problem, Settings, Solution, Status};
@@ -14,13 +14,17 @@ use std::{hash::Hash, marker::PhantomData};
 /// though sometimes with lower precision.
 pub struct OsqpSolver<DemandId, PortfolioId, ProductId>(
     Settings,
+    Map<String, String>,
     PhantomData<(DemandId, PortfolioId, ProductId)>,
 );
 
 impl<A, B, C> OsqpSolver<A, B, C> {
     /// create a new solver with the given settings
+    ///
+    /// OSQP does not expose the values of its settings, so those of a solver
+    /// created this way are not reported in its telemetry.
     pub fn new(settings: Settings) -> Self {
-        Self(settings, PhantomData::default())
+        Self(settings, Map::default(), PhantomData::default())
     }
 }
 
@@ -28,6 +32,10 @@ impl<A, B, C> Default for OsqpSolver<A, B, C> {
     fn default() -> Self {
         Self(
             Settings::default().verbose(false).polishing(true),
+            [("verbose", "false"), ("polishing", "true")]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
             PhantomData::default(),
         )
     }
@@ -43,20 +51,20 @@ impl<
         settings: Settings,
         demand_curves: Map<DemandId, DemandCurve>,
         portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
-    ) -> Result<
-        (
-            Map<PortfolioId, PortfolioOutcome>,
-            Map<ProductId, ProductOutcome>,
-        ),
-        OsqpStatus,
-    > {
+        state: Option<Map<PortfolioId>>,
+        telemetry: &mut SolverTelemetry,
+    ) -> Option<(
+        Map<PortfolioId, PortfolioOutcome>,
+        Map<ProductId, ProductOutcome>,
+    )> {
         // This prepare method canonicalizes the input in a manner appropriate for naive CSC construction
         let (demand_curves, portfolios, mut portfolio_outcomes, mut product_outcomes) =
             super::prepare(demand_curves, portfolios);
 
         // If there are no portfolios or products, there is nothing to do.
         if portfolio_outcomes.len() == 0 || product_outcomes.len() == 0 {
-            return Ok((portfolio_outcomes, product_outcomes));
+            telemetry.status = "Trivial".to_string();
+            return Some((portfolio_outcomes, product_outcomes));
         }
 
         // The trade and bid constraints are all (something) = 0, we need to
@@ -82,8 +90,13 @@ impl<
         let mut a_rowval = Vec::new();
         let mut a_colptr = Vec::new();
 
+        // The initial iterate: the previous rates of the portfolios (if any),
+        // and segment rates which add up to the rate each demand implies
+        let mut x0 = Vec::new();
+        let mut demand_rates = vec![0.0; ndemands];
+
         // We begin by setting up the portfolio variables.
-        for (demand, basis) in portfolios.values() {
+        for (portfolio_id, (demand, basis)) in portfolios.iter() {
             // We can skip any portfolio variable that does not have associated products or demands
             if basis.len() == 0 || demand.len() == 0 {
                 continue;
@@ -93,6 +106,13 @@ impl<
             p.push(0.0);
             q.push(0.0);
 
+            let rate = state
+                .as_ref()
+                .and_then(|rates| rates.get(portfolio_id))
+                .copied()
+                .unwrap_or_default();
+            x0.push(rate);
+
             // start a new column in the constraint matrix
             a_colptr.push(a_nzval.len());
 
@@ -110,6 +130,7 @@ impl<
                 let idx = demand_curves.get_index_of(demand_id).unwrap();
                 a_nzval.push(weight);
                 a_rowval.push(nproducts + idx);
+                demand_rates[idx] += weight * rate;
             }
         }
 
@@ -120,6 +141,9 @@ impl<
             let points = demand_curve.points();
 
             if let Some(segments) = disaggregate(points.into_iter(), min, max) {
+                // The segments take up the demand's rate in order, each as
+                // much as its box allows
+                let mut remaining = demand_rates[offset];
                 for segment in segments {
                     // TODO: propagate the error upwards
                     let segment = segment.unwrap();
@@ -141,6 +165,10 @@ impl<
                     a_rowval.push(lb.len());
                     lb.push(segment.q0);
                     ub.push(segment.q1);
+
+                    let rate = remaining.max(segment.q0).min(segment.q1);
+                    x0.push(rate);
+                    remaining -= rate;
                 }
             }
         }
@@ -173,8 +201,31 @@ impl<
         // Now we can solve!
         let mut solver = Problem::new(&p_matrix, &q, &a_matrix, &lb, &ub, &settings)
             .expect("unable to setup problem");
-        solver.warm_start_x(&vec![0.0; n]);
-        let (status, solution) = remap(solver.solve());
+        solver.warm_start_x(&x0);
+        let status = solver.solve();
+
+        let mut stats = vec![
+            ("iterations", status.iter() as f64),
+            ("setup_time", status.setup_time().as_secs_f64()),
+            ("solve_time", status.solve_time().as_secs_f64()),
+            ("polish_time", status.polish_time().as_secs_f64()),
+            ("run_time", status.run_time().as_secs_f64()),
+            ("rho_updates", status.rho_updates() as f64),
+            ("rho_estimate", status.rho_estimate()),
+        ];
+        let (status, solution) = remap(status);
+        if let Some(solution) = solution.as_ref() {
+            stats.extend([
+                ("obj_val", solution.obj_val()),
+                ("pri_res", solution.pri_res()),
+                ("dua_res", solution.dua_res()),
+            ]);
+        }
+        telemetry.status = format!("{status:?}");
+        telemetry.stats = stats
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
 
         if status.ok() {
             // Does not panic, because ok() is only true when we return the solution
@@ -204,9 +255,9 @@ impl<
             // of the trades as a tie-break. We should think about the best way to regularize
             // the solve accordingly.
 
-            Ok((portfolio_outcomes, product_outcomes))
+            Some((portfolio_outcomes, product_outcomes))
         } else {
-            Err(status)
+            None
         }
     }
 }
@@ -217,17 +268,20 @@ impl<
     ProductId: Clone + Eq + Hash + Ord + Send + Sync + 'static,
 > Solver<DemandId, PortfolioId, ProductId> for OsqpSolver<DemandId, PortfolioId, ProductId>
 {
-    type Error = tokio::task::JoinError;
+    type Error = SolveError;
     type PortfolioOutcome = PortfolioOutcome;
     type ProductOutcome = ProductOutcome;
 
+    /// The rates of the portfolios in a previous solution (e.g. that of the
+    /// preceding auction), from which to warm-start the solve. Portfolios
+    /// absent from it start from a rate of zero.
     type State = Option<Map<PortfolioId>>;
 
     async fn solve(
         &self,
         demand_curves: Map<DemandId, DemandCurve>,
         portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
-        _state: Self::State,
+        state: Self::State,
     ) -> Result<
         (
             Map<PortfolioId, Self::PortfolioOutcome>,
@@ -235,13 +289,65 @@ impl<
         ),
         Self::Error,
     > {
+        self.solve_with_telemetry(demand_curves, portfolios, state)
+            .await
+            .0
+    }
+
+    async fn solve_with_telemetry(
+        &self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        state: Self::State,
+    ) -> (
+        Result<
+            (
+                Map<PortfolioId, Self::PortfolioOutcome>,
+                Map<ProductId, Self::ProductOutcome>,
+            ),
+            Self::Error,
+        >,
+        SolverTelemetry,
+    ) {
         let settings = self.0.clone();
-        let solution =
-            tokio::spawn(async move { Self::solve(settings, demand_curves, portfolios) }).await?;
+        let configured = SolverTelemetry {
+            backend: "osqp".to_string(),
+            settings: self.1.clone(),
+            ..Default::default()
+        };
 
-        // TODO: The JoinError happens when we panic inside.
-        // We can change this later, for now we just assume the solver worked.
-        Ok(solution.expect("failed to solve"))
+        let mut telemetry = configured.clone();
+        let task = tokio::spawn(async move {
+            let solution = Self::solve(settings, demand_curves, portfolios, state, &mut telemetry);
+            (solution, telemetry)
+        });
+
+        match task.await {
+            Ok((Some(solution), telemetry)) => (Ok(solution), telemetry),
+            Ok((None, telemetry)) => (
+                Err(SolveError::Unsolved(telemetry.status.clone())),
+                telemetry,
+            ),
+            Err(error) => (
+                Err(error.into()),
+                SolverTelemetry {
+                    status: "Panicked".to_string(),
+                    ..configured
+                },
+            ),
+        }
+    }
+
+    fn next_state(
+        &self,
+        portfolio_outcomes: &Map<PortfolioId, Self::PortfolioOutcome>,
+    ) -> Self::State {
+        Some(
+            portfolio_outcomes
+                .iter()
+                .map(|(portfolio_id, outcome)| (portfolio_id.clone(), outcome.rate))
+                .collect(),
+        )
     }
 }
 
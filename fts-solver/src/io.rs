@@ -1,12 +1,25 @@
-use crate::export::{export_lp, export_mps};
+use crate::export::{ExportFormat, export_lp, export_mps};
 use fts_core::{
-    models::{Basis, DemandCurve, Map, Weights},
+    models::{Basis, DemandCurve, Map, SolverTelemetry, Weights},
     ports::Solver,
 };
 use serde::{Deserialize, Serialize};
 use std::io::Write;
 use std::{fmt, hash::Hash};
 
+#[cfg(feature = "generate")]
+mod generate;
+#[cfg(feature = "generate")]
+pub use generate::*;
+
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "csv")]
+pub use csv::*;
+
+mod validate;
+pub use validate::*;
+
 // First order of business: create some newtype wrappers for the various primitives.
 
 macro_rules! string_wrapper {
@@ -29,7 +42,7 @@ string_wrapper!(PortfolioId);
 string_wrapper!(ProductId);
 
 /// a representation of a portfolio
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Portfolio {
     /// the demand curves
     demand: Weights<DemandId>,
@@ -37,8 +50,20 @@ pub struct Portfolio {
     basis: Basis<ProductId>,
 }
 
+impl Portfolio {
+    /// the weights of the demand curves
+    pub fn demand(&self) -> &Weights<DemandId> {
+        &self.demand
+    }
+
+    /// the weights of the products
+    pub fn basis(&self) -> &Basis<ProductId> {
+        &self.basis
+    }
+}
+
 /// a representation of an auction
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Auction {
     /// the demand curves
     pub demand_curves: Map<DemandId, DemandCurve>,
@@ -78,6 +103,47 @@ impl Auction {
         }
     }
 
+    /// solve the auction, reporting how it was solved (or why it could not be)
+    pub async fn solve_with_telemetry<T: Solver<DemandId, PortfolioId, ProductId>>(
+        self,
+        solver: T,
+    ) -> (
+        Result<Outcome<T::PortfolioOutcome, T::ProductOutcome>, T::Error>,
+        SolverTelemetry,
+    ) {
+        let portfolios = self
+            .portfolios
+            .into_iter()
+            .map(|(portfolio_id, Portfolio { demand, basis })| (portfolio_id, (demand, basis)))
+            .collect::<Map<_, _>>();
+
+        let (solution, telemetry) = solver
+            .solve_with_telemetry(self.demand_curves, portfolios, Default::default())
+            .await;
+
+        (
+            solution.map(|(portfolio_outcomes, product_outcomes)| Outcome {
+                portfolios: portfolio_outcomes,
+                products: product_outcomes,
+            }),
+            telemetry,
+        )
+    }
+
+    /// export the auction to the given format
+    pub fn export(
+        self,
+        format: ExportFormat,
+        buffer: &mut impl Write,
+    ) -> Result<(), std::io::Error> {
+        let portfolios = self
+            .portfolios
+            .into_iter()
+            .map(|(portfolio_id, Portfolio { demand, basis })| (portfolio_id, (demand, basis)))
+            .collect();
+        format.export(self.demand_curves, portfolios, buffer)
+    }
+
     /// export the auction to LP format
     pub fn export_lp(self, buffer: &mut impl Write) -> Result<(), std::io::Error> {
         let portfolios = self
@@ -0,0 +1,190 @@
+use super::{AuctionDto, DemandId, Portfolio, PortfolioId, ProductId};
+use fts_core::models::{DemandCurveDto, Map, Point, PwlCurveDto};
+use serde::Deserialize;
+use std::io::Read;
+
+/// an error from reading an auction from CSV
+#[derive(Debug, thiserror::Error)]
+pub enum CsvError {
+    /// the CSV itself could not be read or parsed
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// a row lacks a value the kind of row requires
+    #[error("line {line}: missing {field}")]
+    Missing {
+        /// the line of the row
+        line: u64,
+        /// the column without a value
+        field: &'static str,
+    },
+    /// a portfolio weights the same demand curve or product more than once
+    #[error("line {line}: portfolio {portfolio_id} already weights {id}")]
+    Duplicate {
+        /// the line of the repeated row
+        line: u64,
+        /// the portfolio
+        portfolio_id: String,
+        /// the demand curve or product
+        id: String,
+    },
+}
+
+/// the kind of a row, either given by its `kind` column (in a single file) or
+/// implied by the file it is in (in a set of tables)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    /// a point of a demand curve
+    Point,
+    /// the weight of a demand curve in a portfolio
+    Demand,
+    /// the weight of a product in a portfolio
+    Product,
+}
+
+/// the union of the columns of every kind of row; each kind only requires
+/// some of them, and the tables only have those
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Row {
+    kind: Option<Kind>,
+    portfolio_id: Option<String>,
+    demand_id: Option<String>,
+    product_id: Option<String>,
+    rate: Option<f64>,
+    price: Option<f64>,
+    weight: Option<f64>,
+}
+
+// Rows may arrive in any order, so the auction is assembled as they are read.
+#[derive(Default)]
+struct Builder {
+    points: Map<DemandId, Vec<Point>>,
+    portfolios: Map<PortfolioId, Portfolio>,
+}
+
+impl Builder {
+    fn read<R: Read>(&mut self, reader: R, kind: Option<Kind>) -> Result<(), CsvError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+
+        for record in reader.records() {
+            let record = record?;
+            let line = record.position().map_or(0, |position| position.line());
+            let row = record.deserialize::<Row>(Some(&headers))?;
+            self.push(line, kind, row)?;
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, line: u64, kind: Option<Kind>, row: Row) -> Result<(), CsvError> {
+        match required(kind.or(row.kind), line, "kind")? {
+            Kind::Point => {
+                let demand_id = DemandId(required(row.demand_id, line, "demand_id")?);
+                let point = Point {
+                    rate: required(row.rate, line, "rate")?,
+                    price: required(row.price, line, "price")?,
+                };
+                self.points.entry(demand_id).or_default().push(point);
+            }
+            Kind::Demand => {
+                let portfolio_id = required(row.portfolio_id, line, "portfolio_id")?;
+                let demand_id = DemandId(required(row.demand_id, line, "demand_id")?);
+                let portfolio = self.portfolio(portfolio_id.clone());
+                if portfolio.demand.contains_key(&demand_id) {
+                    return Err(CsvError::Duplicate {
+                        line,
+                        portfolio_id,
+                        id: demand_id.to_string(),
+                    });
+                }
+                portfolio
+                    .demand
+                    .insert(demand_id, row.weight.unwrap_or(1.0));
+            }
+            Kind::Product => {
+                let portfolio_id = required(row.portfolio_id, line, "portfolio_id")?;
+                let product_id = ProductId(required(row.product_id, line, "product_id")?);
+                let portfolio = self.portfolio(portfolio_id.clone());
+                if portfolio.basis.contains_key(&product_id) {
+                    return Err(CsvError::Duplicate {
+                        line,
+                        portfolio_id,
+                        id: product_id.to_string(),
+                    });
+                }
+                portfolio
+                    .basis
+                    .insert(product_id, row.weight.unwrap_or(1.0));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn portfolio(&mut self, portfolio_id: String) -> &mut Portfolio {
+        self.portfolios
+            .entry(PortfolioId(portfolio_id))
+            .or_insert_with(|| Portfolio {
+                demand: Default::default(),
+                basis: Default::default(),
+            })
+    }
+
+    fn build(self) -> AuctionDto {
+        AuctionDto {
+            demand_curves: self
+                .points
+                .into_iter()
+                .map(|(demand_id, points)| (demand_id, DemandCurveDto::Pwl(PwlCurveDto(points))))
+                .collect(),
+            portfolios: self.portfolios,
+        }
+    }
+}
+
+fn required<T>(value: Option<T>, line: u64, field: &'static str) -> Result<T, CsvError> {
+    value.ok_or(CsvError::Missing { line, field })
+}
+
+impl AuctionDto {
+    /// read an auction from a single CSV file
+    ///
+    /// The file has the columns `kind`, `portfolio_id`, `demand_id`,
+    /// `product_id`, `rate`, `price` and `weight`, of which each kind of row
+    /// only requires some:
+    ///
+    /// - `point` rows give a point (`rate`, `price`) of the piecewise linear
+    ///   demand curve `demand_id`, in order of increasing rate;
+    /// - `demand` rows give the `weight` of `demand_id` in `portfolio_id`;
+    /// - `product` rows give the `weight` of `product_id` in `portfolio_id`.
+    ///
+    /// An omitted weight is 1. The curves are not validated; see
+    /// [`AuctionDto::validate`].
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self, CsvError> {
+        let mut builder = Builder::default();
+        builder.read(reader, None)?;
+        Ok(builder.build())
+    }
+
+    /// read an auction from a set of CSV tables, one for each kind of row of
+    /// [`AuctionDto::from_csv`] and with only the columns it requires
+    ///
+    /// - `demands` has the columns `demand_id`, `rate` and `price`;
+    /// - `portfolios` has the columns `portfolio_id`, `demand_id` and `weight`;
+    /// - `weights` has the columns `portfolio_id`, `product_id` and `weight`.
+    pub fn from_csv_tables<D: Read, P: Read, W: Read>(
+        demands: D,
+        portfolios: P,
+        weights: W,
+    ) -> Result<Self, CsvError> {
+        let mut builder = Builder::default();
+        builder.read(demands, Some(Kind::Point))?;
+        builder.read(portfolios, Some(Kind::Demand))?;
+        builder.read(weights, Some(Kind::Product))?;
+        Ok(builder.build())
+    }
+}
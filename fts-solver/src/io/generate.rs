@@ -0,0 +1,82 @@
+use super::{Auction, DemandId, Portfolio, PortfolioId, ProductId};
+use fts_core::models::{Basis, DemandCurve, Map, Point, PwlCurve, Weights};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// the shape of a randomly generated auction
+#[derive(Debug, Clone)]
+pub struct AuctionGenerator {
+    /// the number of bidders, each with a single demand curve and portfolio
+    pub bidders: usize,
+    /// the number of products
+    pub products: usize,
+    /// the probability that a portfolio trades any given product
+    pub density: f64,
+}
+
+impl AuctionGenerator {
+    /// generate an auction, identical for identical seeds
+    ///
+    /// Each bidder values its portfolio at a price of its own, buying below
+    /// it and selling above it, so that bidders with overlapping portfolios
+    /// trade with one another. Every portfolio trades at least one product
+    /// (if there are any), however small the density.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the density is not within `[0, 1]`.
+    pub fn generate(&self, seed: u64) -> Auction {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let products = (0..self.products)
+            .map(|j| ProductId(format!("product-{j}")))
+            .collect::<Vec<_>>();
+
+        let mut demand_curves = Map::default();
+        let mut portfolios = Map::default();
+
+        for i in 0..self.bidders {
+            let value = rng.random_range(5.0..15.0);
+            let spread = rng.random_range(0.5..2.5);
+            let rate = rng.random_range(1.0..10.0);
+
+            // The points are in order of rate with decreasing prices, so the
+            // curve is always valid
+            let curve = PwlCurve::new(vec![
+                Point {
+                    rate: -rate,
+                    price: value + spread,
+                },
+                Point {
+                    rate,
+                    price: value - spread,
+                },
+            ])
+            .expect("generated curves are valid");
+
+            let mut basis = products
+                .iter()
+                .filter(|_| rng.random_bool(self.density))
+                .map(|product_id| (product_id.clone(), 1.0))
+                .collect::<Basis<_>>();
+            if basis.is_empty() && !products.is_empty() {
+                let product_id = products[rng.random_range(0..products.len())].clone();
+                basis.insert(product_id, 1.0);
+            }
+
+            let demand_id = DemandId(format!("bidder-{i}"));
+            let demand = std::iter::once((demand_id.clone(), 1.0)).collect::<Weights<_>>();
+
+            demand_curves.insert(demand_id, DemandCurve::Pwl(curve));
+            portfolios.insert(
+                PortfolioId(format!("bidder-{i}")),
+                Portfolio { demand, basis },
+            );
+        }
+
+        Auction {
+            demand_curves,
+            portfolios,
+        }
+    }
+}
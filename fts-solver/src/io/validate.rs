@@ -0,0 +1,143 @@
+use super::{Auction, DemandId, Portfolio, PortfolioId};
+use fts_core::models::{
+    CurveDiagnostic, DemandCurve, DemandCurveDto, DemandCurveError, Map, Severity,
+};
+use serde::{Deserialize, Serialize};
+
+/// a representation of an auction whose demand curves have not been validated
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuctionDto {
+    /// the demand curves
+    pub demand_curves: Map<DemandId, DemandCurveDto>,
+    /// the portfolios
+    pub portfolios: Map<PortfolioId, Portfolio>,
+}
+
+/// an error from converting an [`AuctionDto`] into an [`Auction`]
+#[derive(Debug, thiserror::Error)]
+#[error("demand curve {demand_id} is invalid: {source}")]
+pub struct InvalidDemandCurve {
+    /// the demand curve concerned
+    pub demand_id: DemandId,
+    /// why it is invalid
+    pub source: DemandCurveError,
+}
+
+impl TryFrom<AuctionDto> for Auction {
+    type Error = InvalidDemandCurve;
+
+    /// validate each demand curve, failing on the first which is invalid
+    fn try_from(value: AuctionDto) -> Result<Self, Self::Error> {
+        let demand_curves = value
+            .demand_curves
+            .into_iter()
+            .map(|(demand_id, curve)| match DemandCurve::try_from(curve) {
+                Ok(curve) => Ok((demand_id, curve)),
+                Err(source) => Err(InvalidDemandCurve { demand_id, source }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            demand_curves,
+            portfolios: value.portfolios,
+        })
+    }
+}
+
+/// a finding from validating an auction, attributed to the demand curve or
+/// portfolio it concerns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionDiagnostic {
+    /// the demand curve concerned, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demand_curve: Option<DemandId>,
+    /// the portfolio concerned, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub portfolio: Option<PortfolioId>,
+    /// the finding itself
+    #[serde(flatten)]
+    pub diagnostic: CurveDiagnostic,
+}
+
+/// the outcome of validating an auction without solving it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionValidation {
+    /// whether the auction can be solved, i.e. there are no errors
+    pub valid: bool,
+    /// every finding, demand curves first
+    pub diagnostics: Vec<AuctionDiagnostic>,
+}
+
+impl FromIterator<AuctionDiagnostic> for AuctionValidation {
+    fn from_iter<I: IntoIterator<Item = AuctionDiagnostic>>(iter: I) -> Self {
+        let diagnostics: Vec<AuctionDiagnostic> = iter.into_iter().collect();
+        Self {
+            valid: diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.diagnostic.severity != Severity::Error),
+            diagnostics,
+        }
+    }
+}
+
+impl AuctionDto {
+    /// diagnose every problem with the auction, rather than only the first
+    ///
+    /// Each demand curve is diagnosed as on submission, and each portfolio is
+    /// checked for weights which are not finite and for references to demand
+    /// curves the auction does not define.
+    pub fn validate(&self) -> AuctionValidation {
+        let curves = self.demand_curves.iter().flat_map(|(demand_id, curve)| {
+            curve
+                .diagnose()
+                .into_iter()
+                .map(|diagnostic| AuctionDiagnostic {
+                    demand_curve: Some(demand_id.clone()),
+                    portfolio: None,
+                    diagnostic,
+                })
+        });
+
+        let portfolios =
+            self.portfolios
+                .iter()
+                .flat_map(|(portfolio_id, Portfolio { demand, basis })| {
+                    let mut diagnostics = Vec::new();
+                    for (demand_id, weight) in demand.iter() {
+                        if !self.demand_curves.contains_key(demand_id) {
+                            diagnostics.push(CurveDiagnostic::error(
+                                "dangling_demand",
+                                format!("Demand curve {demand_id} is not defined"),
+                            ));
+                        }
+                        if !weight.is_finite() {
+                            diagnostics.push(CurveDiagnostic::error(
+                                "nonfinite_weight",
+                                format!("Weight of demand curve {demand_id} is not finite"),
+                            ));
+                        }
+                    }
+                    for (product_id, weight) in basis.iter() {
+                        if !weight.is_finite() {
+                            diagnostics.push(CurveDiagnostic::error(
+                                "nonfinite_weight",
+                                format!("Weight of product {product_id} is not finite"),
+                            ));
+                        }
+                    }
+                    if basis.is_empty() {
+                        diagnostics.push(CurveDiagnostic::warning(
+                            "empty_basis",
+                            "Portfolio trades no products",
+                        ));
+                    }
+                    diagnostics.into_iter().map(|diagnostic| AuctionDiagnostic {
+                        demand_curve: None,
+                        portfolio: Some(portfolio_id.clone()),
+                        diagnostic,
+                    })
+                });
+
+        curves.chain(portfolios).collect()
+    }
+}
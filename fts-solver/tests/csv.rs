@@ -0,0 +1,78 @@
+use fts_solver::io::{Auction, AuctionDto, CsvError};
+
+const JSON: &str = r#"{
+    "demand_curves": {
+        "a": [{ "rate": -2.0, "price": 6.0 }, { "rate": 2.0, "price": 4.0 }],
+        "b": [{ "rate": -1.0, "price": 5.5 }, { "rate": 3.0, "price": 4.5 }]
+    },
+    "portfolios": {
+        "p": { "demand": { "a": 1.0 }, "basis": { "x": 1.0, "y": -0.5 } },
+        "q": { "demand": { "b": 2.0 }, "basis": { "x": 1.0 } }
+    }
+}"#;
+
+fn json(auction: &AuctionDto) -> serde_json::Value {
+    serde_json::to_value(auction).unwrap()
+}
+
+#[test]
+fn single_file_matches_json() {
+    let csv = "\
+kind,portfolio_id,demand_id,product_id,rate,price,weight
+point,,a,,-2,6,
+point,,a,,2,4,
+demand,p,a,,,,
+product,p,,x,,,
+product,p,,y,,,-0.5
+point,,b,,-1,5.5,
+point,,b,,3,4.5,
+demand,q,b,,,,2
+product,q,,x,,,1
+";
+    let auction = AuctionDto::from_csv(csv.as_bytes()).unwrap();
+    let expected = serde_json::from_str::<AuctionDto>(JSON).unwrap();
+    assert_eq!(json(&auction), json(&expected));
+    assert!(auction.validate().valid);
+    assert!(Auction::try_from(auction).is_ok());
+}
+
+#[test]
+fn tables_match_json() {
+    let demands = "demand_id,rate,price\na,-2,6\na,2,4\nb,-1,5.5\nb,3,4.5\n";
+    let portfolios = "portfolio_id,demand_id,weight\np,a,\nq,b,2\n";
+    let weights = "portfolio_id, product_id, weight\np, x, 1\np, y, -0.5\nq, x, 1\n";
+    let auction = AuctionDto::from_csv_tables(
+        demands.as_bytes(),
+        portfolios.as_bytes(),
+        weights.as_bytes(),
+    )
+    .unwrap();
+    let expected = serde_json::from_str::<AuctionDto>(JSON).unwrap();
+    assert_eq!(json(&auction), json(&expected));
+}
+
+#[test]
+fn missing_values_report_their_line() {
+    let csv = "kind,demand_id,rate,price\npoint,a,1,2\npoint,a,,3\n";
+    match AuctionDto::from_csv(csv.as_bytes()) {
+        Err(CsvError::Missing { line, field }) => assert_eq!((line, field), (3, "rate")),
+        other => panic!("expected a missing rate, got {other:?}"),
+    }
+}
+
+#[test]
+fn duplicate_weights_are_rejected() {
+    let csv = "kind,portfolio_id,product_id,weight\nproduct,p,x,1\nproduct,p,x,2\n";
+    assert!(matches!(
+        AuctionDto::from_csv(csv.as_bytes()),
+        Err(CsvError::Duplicate { line: 3, .. })
+    ));
+}
+
+#[test]
+fn invalid_curves_fail_conversion() {
+    let csv = "kind,portfolio_id,demand_id,rate,price\npoint,,a,1,5\npoint,,a,2,6\n";
+    let auction = AuctionDto::from_csv(csv.as_bytes()).unwrap();
+    let error = Auction::try_from(auction).unwrap_err();
+    assert_eq!(error.demand_id.to_string(), "a");
+}
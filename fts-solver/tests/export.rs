@@ -0,0 +1,39 @@
+use fts_solver::{export::ExportFormat, io::AuctionGenerator};
+
+#[test]
+fn formats_are_found_by_extension() {
+    for format in ExportFormat::ALL {
+        assert_eq!(
+            ExportFormat::from_extension(format.extension()),
+            Some(format)
+        );
+        let upper = format.extension().to_uppercase();
+        assert_eq!(ExportFormat::from_extension(&upper), Some(format));
+    }
+    assert_eq!(ExportFormat::from_extension("json"), None);
+}
+
+#[test]
+fn export_dispatches_by_format() {
+    let auction = AuctionGenerator {
+        bidders: 4,
+        products: 2,
+        density: 0.5,
+    }
+    .generate(0);
+
+    let mut mps = Vec::new();
+    auction.clone().export_mps(&mut mps).unwrap();
+    let mut exported = Vec::new();
+    auction
+        .clone()
+        .export(ExportFormat::Mps, &mut exported)
+        .unwrap();
+    assert_eq!(exported, mps);
+
+    let mut lp = Vec::new();
+    auction.clone().export_lp(&mut lp).unwrap();
+    let mut exported = Vec::new();
+    auction.export(ExportFormat::Lp, &mut exported).unwrap();
+    assert_eq!(exported, lp);
+}
@@ -0,0 +1,58 @@
+use fts_solver::io::{AuctionDto, AuctionGenerator};
+
+#[test]
+fn invalid_auctions_report_every_problem() {
+    let auction: AuctionDto = serde_json::from_str(
+        r#"{
+            "demand_curves": {
+                "a": [{ "rate": 1.0, "price": 5.0 }, { "rate": 2.0, "price": 6.0 }],
+                "b": { "price": 3.0 }
+            },
+            "portfolios": {
+                "p": { "demand": { "a": 1.0, "c": 2.0 }, "basis": {} },
+                "q": { "demand": "b", "basis": "x" }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let validation = auction.validate();
+    assert!(!validation.valid);
+
+    let codes = validation
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let subject = diagnostic
+                .demand_curve
+                .as_ref()
+                .map(ToString::to_string)
+                .or_else(|| diagnostic.portfolio.as_ref().map(ToString::to_string));
+            (subject.unwrap(), diagnostic.diagnostic.code.as_str())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![
+            ("a".to_owned(), "zero_trade"),
+            ("a".to_owned(), "non_monotone"),
+            ("p".to_owned(), "dangling_demand"),
+            ("p".to_owned(), "empty_basis"),
+        ]
+    );
+}
+
+#[test]
+fn generated_auctions_are_valid() {
+    let generator = AuctionGenerator {
+        bidders: 10,
+        products: 3,
+        density: 0.5,
+    };
+
+    let json = serde_json::to_string(&generator.generate(3)).unwrap();
+    let auction: AuctionDto = serde_json::from_str(&json).unwrap();
+    let validation = auction.validate();
+    assert!(validation.valid);
+    assert!(validation.diagnostics.is_empty());
+}
@@ -0,0 +1,205 @@
+//! Regression benchmark for paginating the outcome history of a portfolio and
+//! a product once the outcome tables hold millions of rows.
+//!
+//! Run with `cargo bench -p fts-sqlite --bench outcome_history`. Each page,
+//! whether the newest, one from the middle of the history, or the oldest, must
+//! be fetched in under 10ms; the benchmark fails otherwise.
+
+use fts_core::{
+    models::{Actor, DateTimeRangeQuery, Point, PwlCurve},
+    ports::{BatchRepository, DemandRepository, PortfolioRepository, ProductRepository},
+};
+use fts_solver::clarabel::ClarabelSolver;
+use fts_sqlite::{
+    Db,
+    config::SqliteConfig,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use std::time::{Duration, Instant};
+
+type Solver = ClarabelSolver<DemandId, PortfolioId, ProductId>;
+
+/// The number of batches in the history of every portfolio and product
+const BATCHES: i64 = 100_000;
+/// The number of portfolios (and products), whose outcomes are interleaved
+const PORTFOLIOS: usize = 10;
+/// The number of outcomes per page
+const PAGE_SIZE: usize = 100;
+/// The number of times each page is fetched
+const SAMPLES: usize = 50;
+/// The slowest acceptable median time to fetch a page
+const BUDGET: Duration = Duration::from_millis(10);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-bench-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let result = run(&config).await;
+
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+
+    result
+}
+
+async fn run(config: &SqliteConfig) -> anyhow::Result<()> {
+    let start = time::Date::from_calendar_date(2025, time::Month::January, 1)?
+        .midnight()
+        .assume_utc();
+    let db = Db::open(config, start.into()).await?;
+
+    let (portfolios, products) = populate(&db, start.into()).await?;
+
+    let mut failed = false;
+    for (name, portfolio_id, product_id) in [
+        ("first", portfolios[0], products[0]),
+        ("last", portfolios[PORTFOLIOS - 1], products[PORTFOLIOS - 1]),
+    ] {
+        for (page, before) in [
+            ("newest", None),
+            ("middle", Some(BATCHES / 2)),
+            ("oldest", Some(PAGE_SIZE as i64 + 1)),
+        ] {
+            let query = || DateTimeRangeQuery {
+                before: before
+                    .map(|minutes| DateTime::from(start + time::Duration::minutes(minutes))),
+                after: None,
+            };
+
+            let portfolio = median(async || {
+                let page = <Db as BatchRepository<Solver>>::get_portfolio_outcomes(
+                    &db,
+                    portfolio_id,
+                    query(),
+                    PAGE_SIZE,
+                )
+                .await?;
+                assert_eq!(page.results.len(), PAGE_SIZE);
+                Ok(())
+            })
+            .await?;
+
+            let product = median(async || {
+                let page = <Db as BatchRepository<Solver>>::get_product_outcomes(
+                    &db,
+                    product_id,
+                    query(),
+                    PAGE_SIZE,
+                )
+                .await?;
+                assert_eq!(page.results.len(), PAGE_SIZE);
+                Ok(())
+            })
+            .await?;
+
+            println!(
+                "{name} portfolio, {page} page: {portfolio:?}; {name} product, {page} page: {product:?}"
+            );
+            failed |= portfolio > BUDGET || product > BUDGET;
+        }
+    }
+
+    anyhow::ensure!(!failed, "a page took longer than {BUDGET:?} to fetch");
+    Ok(())
+}
+
+/// Create the portfolios and products, each with `BATCHES` outcomes a minute
+/// apart, writing the outcomes directly rather than running every batch.
+async fn populate(db: &Db, as_of: DateTime) -> anyhow::Result<(Vec<PortfolioId>, Vec<ProductId>)> {
+    let mut portfolios = Vec::new();
+    let mut products = Vec::new();
+    for _ in 0..PORTFOLIOS {
+        let product_id = ProductId(uuid::Uuid::new_v4());
+        <Db as ProductRepository<()>>::create_product(db, product_id, (), as_of).await?;
+
+        let bidder_id = BidderId(uuid::Uuid::new_v4());
+        let demand_id = DemandId(uuid::Uuid::new_v4());
+        <Db as DemandRepository<()>>::create_demand(
+            db,
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: -1.0,
+                    price: 1.0,
+                },
+                Point {
+                    rate: 1.0,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            as_of,
+        )
+        .await?;
+
+        let portfolio_id = PortfolioId(uuid::Uuid::new_v4());
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            portfolio_id,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            as_of,
+        )
+        .await?;
+
+        portfolios.push(portfolio_id);
+        products.push(product_id);
+    }
+
+    for table in ["portfolio", "product"] {
+        let ids = match table {
+            "portfolio" => serde_json::to_string(&portfolios)?,
+            _ => serde_json::to_string(&products)?,
+        };
+        sqlx::query(&format!(
+            r#"
+            with recursive batches(n) as (
+                select 1 union all select n + 1 from batches where n < $1
+            )
+            insert into {table}_outcome ({table}_id, value, valid_from, valid_until)
+            select
+                ids.value,
+                jsonb_object('price', 0.5, 'rate', 0.0),
+                strftime('%Y-%m-%d %H:%M:%f', '2025-01-01', '+' || n || ' minutes'),
+                iif(
+                    n < $1,
+                    strftime('%Y-%m-%d %H:%M:%f', '2025-01-01', '+' || (n + 1) || ' minutes'),
+                    null
+                )
+            from
+                batches, json_each($2) as ids
+            "#
+        ))
+        .bind(BATCHES)
+        .bind(ids)
+        .execute(&db.writer)
+        .await?;
+    }
+    sqlx::query("analyze").execute(&db.writer).await?;
+
+    Ok((portfolios, products))
+}
+
+/// The median time taken by `f` over `SAMPLES` runs
+async fn median(f: impl AsyncFn() -> anyhow::Result<()>) -> anyhow::Result<Duration> {
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        f().await?;
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+    Ok(samples[SAMPLES / 2])
+}
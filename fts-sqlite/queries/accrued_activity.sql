@@ -0,0 +1,81 @@
+-- fn(bidder_ids: Option<Json<&[BidderId]>>, from: Option<DateTime>, until: DateTime, time_unit: f64, market_id: &str, product_ids: Option<Json<&[ProductId]>>, after: Option<ProductId>, limit: i64) -> AccruedRow
+--
+-- Integrate the portfolio outcomes of the market over [from, until), measuring
+-- time in units of `time_unit` seconds, for the given bidders (or every
+-- bidder, if null). Positions are reported in the contemporary product basis
+-- of each batch, restricted to the given products (if any) with ids following
+-- `after`, and at most `limit` (-1 for no limit) of them are reported in order
+-- of bidder and product. Rows without a product id are the bidders' payments,
+-- which are never restricted.
+with
+outcome_cte as (
+    select
+        portfolio.bidder_id,
+        portfolio_outcome.portfolio_id,
+        portfolio_outcome.valid_from,
+        portfolio_outcome.value ->> '$.rate' as rate,
+        coalesce(portfolio_outcome.value ->> '$.price', 0.0) as price,
+        (
+            julianday(min(coalesce(portfolio_outcome.valid_until, $3), $3))
+            - julianday(max(portfolio_outcome.valid_from, coalesce($2, portfolio_outcome.valid_from)))
+        ) * 86400.0 / $4 as duration
+    from
+        portfolio_outcome
+    join
+        portfolio
+        on
+            portfolio_outcome.portfolio_id = portfolio.id
+    where
+        portfolio.market_id = $5
+        and
+        ($1 is null or portfolio.bidder_id in (select atom from json_each($1)))
+        and
+        portfolio_outcome.valid_from < $3
+        and
+        ($2 is null or portfolio_outcome.valid_until is null or $2 < portfolio_outcome.valid_until)
+        and
+        portfolio_outcome.value ->> '$.rate' != 0
+)
+
+select
+    bidder_id as "bidder_id!: BidderId",
+    product_id as "product_id?: ProductId",
+    accrued as "accrued!: f64"
+from (
+    select
+        outcome_cte.bidder_id,
+        basis_view.product_id,
+        sum(outcome_cte.rate * basis_view.weight * outcome_cte.duration) as accrued
+    from
+        outcome_cte
+    join
+        basis_view
+        on
+            outcome_cte.portfolio_id = basis_view.portfolio_id
+            and
+            basis_view.valid_from <= outcome_cte.valid_from
+            and
+            (outcome_cte.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+    where
+        ($6 is null or basis_view.product_id in (select atom from json_each($6)))
+        and
+        ($7 is null or basis_view.product_id > $7)
+    group by
+        outcome_cte.bidder_id,
+        basis_view.product_id
+    order by
+        outcome_cte.bidder_id,
+        basis_view.product_id
+    limit $8
+)
+
+union all
+
+select
+    bidder_id,
+    null as product_id,
+    sum(price * rate * duration) as accrued
+from
+    outcome_cte
+group by
+    bidder_id
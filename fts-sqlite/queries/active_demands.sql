@@ -1,6 +1,7 @@
 -- A demand is considered active if and only if
 -- * it has non-null curve data, AND
 -- * it is associated to at least 1 portfolio.
+-- Only the demands of the market $2 are considered.
 with
 portfolio_by_id as (
     select
@@ -25,6 +26,8 @@ curve_data_by_id as (
         value is not null
     and
         valid_from <= $1
+    and
+        demand_id in (select id from demand where market_id = $2)
     and
         ($1 < valid_until or valid_until is null)
 )
@@ -1,6 +1,8 @@
 -- A portfolio is considered active if and only if
 -- * it has at least one associated demand, AND
 -- * it has at least one associated product.
+-- Only the portfolios of the market $2 are considered. Their product weights
+-- are read separately, row by row, from active_basis.sql.
 with
 demand_by_id as (
     select
@@ -13,6 +15,8 @@ demand_by_id as (
         valid_from <= $1
     and
         ($1 < valid_until or valid_until is null)
+    and
+        portfolio_id in (select id from portfolio where market_id = $2)
     group by
         portfolio_id
 ),
@@ -20,8 +24,7 @@ demand_by_id as (
 basis_by_id as (
     select
         portfolio_id,
-        valid_until as expires,
-        jsonb_group_object(product_id, weight) as pgroup
+        valid_until as expires
     from
         basis_view
     where
@@ -38,8 +41,7 @@ select
         coalesce(demand_by_id.expires, basis_by_id.expires),
         coalesce(basis_by_id.expires, demand_by_id.expires)
     ) as "expires?: DateTime",
-    json(dgroup) as "demand!: sqlx::types::Json<Weights<DemandId>>",
-    json(pgroup) as "basis!: sqlx::types::Json<Basis<ProductId>>"
+    json(dgroup) as "demand!: sqlx::types::Json<Weights<DemandId>>"
 from
     demand_by_id
 join
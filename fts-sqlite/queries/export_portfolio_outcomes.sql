@@ -0,0 +1,29 @@
+-- fn(from: Option<DateTime>, until: DateTime, superseded: bool, market_id: &str) -> PortfolioOutcomeRow
+--
+-- The portfolio outcomes of the market's batches in [from, until), or if `superseded`,
+-- only those outcomes which were also superseded by `until`.
+select
+    portfolio_outcome.portfolio_id as "portfolio_id!: PortfolioId",
+    portfolio.bidder_id as "bidder_id!: BidderId",
+    portfolio_outcome.valid_from as "valid_from!: DateTime",
+    portfolio_outcome.valid_until as "valid_until?: DateTime",
+    portfolio_outcome.value ->> '$.price' as "price?: f64",
+    portfolio_outcome.value ->> '$.rate' as "rate?: f64",
+    json(portfolio_outcome.value) as "value?: String"
+from
+    portfolio_outcome
+join
+    portfolio
+    on
+        portfolio_outcome.portfolio_id = portfolio.id
+where
+    ($1 is null or $1 <= portfolio_outcome.valid_from)
+    and
+    portfolio_outcome.valid_from < $2
+    and
+    (not $3 or portfolio_outcome.valid_until <= $2)
+    and
+    portfolio.market_id = $4
+order by
+    portfolio_outcome.valid_from,
+    portfolio_outcome.portfolio_id
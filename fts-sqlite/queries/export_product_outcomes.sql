@@ -0,0 +1,24 @@
+-- fn(from: Option<DateTime>, until: DateTime, superseded: bool, market_id: &str) -> ProductOutcomeRow
+--
+-- The product outcomes of the market's batches in [from, until), or if `superseded`,
+-- only those outcomes which were also superseded by `until`.
+select
+    product_id as "product_id!: ProductId",
+    valid_from as "valid_from!: DateTime",
+    valid_until as "valid_until?: DateTime",
+    value ->> '$.price' as "price?: f64",
+    value ->> '$.rate' as "rate?: f64",
+    json(value) as "value?: String"
+from
+    product_outcome
+where
+    ($1 is null or $1 <= valid_from)
+    and
+    valid_from < $2
+    and
+    (not $3 or valid_until <= $2)
+    and
+    product_id in (select id from product where market_id = $4)
+order by
+    valid_from,
+    product_id
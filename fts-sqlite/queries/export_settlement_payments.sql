@@ -0,0 +1,31 @@
+-- fn(from: Option<DateTime>, until: DateTime, market_id: &str) -> SettlementPaymentRow
+--
+-- The market's settled payments of the settlements made in [from, until),
+-- net of any revisions of them
+select
+    settlement.as_of as "as_of!: DateTime",
+    settlement.settled_from as "settled_from!: DateTime",
+    settlement_payment.bidder_id as "bidder_id!: BidderId",
+    sum(settlement_payment.accrued) as "accrued!: f64",
+    sum(settlement_payment.settled) as "settled!: f64"
+from
+    settlement
+join
+    settlement_payment
+    on
+        settlement.market_id = settlement_payment.market_id
+        and
+        settlement.as_of = settlement_payment.as_of
+where
+    settlement.market_id = $3
+    and
+    ($1 is null or $1 <= settlement.as_of)
+    and
+    settlement.as_of < $2
+group by
+    settlement.as_of,
+    settlement.settled_from,
+    settlement_payment.bidder_id
+order by
+    settlement.as_of,
+    settlement_payment.bidder_id
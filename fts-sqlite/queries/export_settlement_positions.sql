@@ -0,0 +1,34 @@
+-- fn(from: Option<DateTime>, until: DateTime, market_id: &str) -> SettlementPositionRow
+--
+-- The market's settled positions of the settlements made in [from, until),
+-- net of any revisions of them
+select
+    settlement.as_of as "as_of!: DateTime",
+    settlement.settled_from as "settled_from!: DateTime",
+    settlement_position.bidder_id as "bidder_id!: BidderId",
+    settlement_position.product_id as "product_id!: ProductId",
+    sum(settlement_position.accrued) as "accrued!: f64",
+    sum(settlement_position.settled) as "settled!: f64"
+from
+    settlement
+join
+    settlement_position
+    on
+        settlement.market_id = settlement_position.market_id
+        and
+        settlement.as_of = settlement_position.as_of
+where
+    settlement.market_id = $3
+    and
+    ($1 is null or $1 <= settlement.as_of)
+    and
+    settlement.as_of < $2
+group by
+    settlement.as_of,
+    settlement.settled_from,
+    settlement_position.bidder_id,
+    settlement_position.product_id
+order by
+    settlement.as_of,
+    settlement_position.bidder_id,
+    settlement_position.product_id
@@ -0,0 +1,44 @@
+-- fn(from: Option<DateTime>, until: DateTime, superseded: bool, market_id: &str) -> TradeRow
+--
+-- The rates of trade of each bidder in each product, as determined by the
+-- portfolio outcomes of the market's batches in [from, until) (or if
+-- `superseded`, only those outcomes which were also superseded by `until`). As
+-- when accruing activity, trades are expressed in the contemporary product
+-- basis of each batch.
+select
+    portfolio.bidder_id as "bidder_id!: BidderId",
+    portfolio_outcome.portfolio_id as "portfolio_id!: PortfolioId",
+    basis_view.product_id as "product_id!: ProductId",
+    portfolio_outcome.valid_from as "valid_from!: DateTime",
+    portfolio_outcome.valid_until as "valid_until?: DateTime",
+    portfolio_outcome.value ->> '$.rate' * basis_view.weight as "rate!: f64",
+    portfolio_outcome.value ->> '$.price' as "price?: f64"
+from
+    portfolio_outcome
+join
+    portfolio
+    on
+        portfolio_outcome.portfolio_id = portfolio.id
+join
+    basis_view
+    on
+        portfolio_outcome.portfolio_id = basis_view.portfolio_id
+        and
+        basis_view.valid_from <= portfolio_outcome.valid_from
+        and
+        (portfolio_outcome.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+where
+    ($1 is null or $1 <= portfolio_outcome.valid_from)
+    and
+    portfolio_outcome.valid_from < $2
+    and
+    (not $3 or portfolio_outcome.valid_until <= $2)
+    and
+    portfolio_outcome.value ->> '$.rate' != 0
+    and
+    portfolio.market_id = $4
+order by
+    portfolio_outcome.valid_from,
+    portfolio.bidder_id,
+    portfolio_outcome.portfolio_id,
+    basis_view.product_id
@@ -1,4 +1,4 @@
--- fn(demand_id: DemandId, as_of: DateTime) -> DemandRow
+-- fn(demand_id: DemandId, as_of: DateTime, market_id: &str) -> DemandRow
 with
 app_data_cte as (
     select
@@ -10,6 +10,10 @@ app_data_cte as (
         demand
     where
         id = $1
+        and
+        market_id = $3
+        and
+        purged_at is null
 ),
 
 curve_data_cte as (
@@ -1,15 +1,22 @@
--- fn(product_id: ProductId, as_of: DateTime) -> ProductRow
+-- fn(product_id: ProductId, as_of: DateTime, market_id: &str) -> ProductRow
 select
     product.id as "id!: ProductId",
     json(product.app_data) as "app_data!: sqlx::types::Json<ProductData>",
-    case
-        when
-            product.parent_id is null
-        then
-            json_array(product.id, 1.0)
-        else
-            json_array(product.parent_id, product.parent_ratio)
-        end as "parent!: sqlx::types::Json<(ProductId, f64)>",
+    coalesce(
+        (
+            select
+                json_array(product_parent.parent_id, product_parent.ratio)
+            from
+                product_parent
+            where
+                product_parent.product_id = product.id
+            and
+                product_parent.valid_from <= $2
+            and
+                ($2 < product_parent.valid_until or product_parent.valid_until is null)
+        ),
+        json_array(product.id, 1.0)
+    ) as "parent!: sqlx::types::Json<(ProductId, f64)>",
     json_group_object(product_tree.dst_id, product_tree.ratio) as "basis!: sqlx::types::Json<Basis<ProductId>>"
 from
     product
@@ -19,6 +26,8 @@ on
     product.id = product_tree.src_id
 where
     product.id = $1
+and
+    product.market_id = $3
 and
     product_tree.valid_from <= $2
 and
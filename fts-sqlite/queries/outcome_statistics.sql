@@ -0,0 +1,58 @@
+-- fn(after: Option<DateTime>, until: DateTime, as_of: DateTime, time_unit: f64, market_id: &str) -> OutcomeStatisticsRow
+--
+-- Aggregate the outcomes of every product of the market: the most recent
+-- price as of `as_of`, and the volume traded over [after, until), measuring
+-- time in units of `time_unit` seconds. Outcomes still in effect are
+-- considered to end at `until`.
+with
+volume_cte as (
+    select
+        product_id,
+        total(
+            coalesce(value ->> '$.rate', 0.0) * max(
+                julianday(min(coalesce(valid_until, $2), $2))
+                - julianday(max(valid_from, coalesce($1, valid_from))),
+                0.0
+            ) * 86400.0 / $4
+        ) as volume
+    from
+        product_outcome
+    where
+        valid_from < $2
+        and
+        ($1 is null or valid_until is null or $1 < valid_until)
+        and
+        product_id in (select id from product where market_id = $5)
+    group by
+        product_id
+),
+
+-- sqlite takes the bare columns from the row attaining the maximum
+price_cte as (
+    select
+        product_id,
+        value ->> '$.price' as price,
+        max(valid_from) as cleared
+    from
+        product_outcome
+    where
+        valid_from <= $3
+        and
+        value ->> '$.price' is not null
+        and
+        product_id in (select id from product where market_id = $5)
+    group by
+        product_id
+)
+
+select
+    product_id as "product_id!: ProductId",
+    price_cte.price as "price?: f64",
+    price_cte.cleared as "cleared?: DateTime",
+    coalesce(volume_cte.volume, 0.0) as "volume!: f64"
+from
+    price_cte
+full outer join
+    volume_cte
+using
+    (product_id)
@@ -0,0 +1,64 @@
+-- fn(product_id: ProductId, after: Option<DateTime>, before: Option<DateTime>, as_of: DateTime, time_unit: f64, format: &str, span: &str, limit: i64, market_id: &str) -> ValueRow<PriceSummary>
+--
+-- Aggregate the outcomes of a product into buckets, where `format` truncates
+-- a timestamp to the start of its bucket and `span` is the datetime modifier
+-- advancing the start of a bucket to its end. Outcomes still in effect are
+-- considered to end at `as_of`.
+with
+outcome_cte as (
+    select
+        strftime($6, valid_from) as bucket,
+        valid_from,
+        value ->> '$.price' as price,
+        coalesce(value ->> '$.rate', 0.0) as rate,
+        max(
+            julianday(coalesce(valid_until, $4)) - julianday(valid_from),
+            0.0
+        ) * 86400.0 as duration
+    from
+        product_outcome
+    where
+        product_id = $1
+        and
+        ($2 is null or valid_from >= $2)
+        and
+        ($3 is null or valid_from < $3)
+        and
+        exists (select 1 from product where id = $1 and market_id = $9)
+),
+
+window_cte as (
+    select
+        bucket,
+        price,
+        rate,
+        duration,
+        first_value(price) over (
+            partition by bucket order by valid_from asc
+        ) as open,
+        first_value(price) over (
+            partition by bucket order by valid_from desc
+        ) as close
+    from
+        outcome_cte
+)
+
+select
+    bucket as "valid_from!: DateTime",
+    datetime(bucket, $7) as "valid_until?: DateTime",
+    json_object(
+        'open', max(open),
+        'high', max(price),
+        'low', min(price),
+        'close', max(close),
+        'twap', sum(price * duration) / nullif(sum(iif(price is null, 0.0, duration)), 0.0),
+        'volume', coalesce(sum(rate * duration) / $5, 0.0),
+        'batches', count(*)
+    ) as "value!: sqlx::types::Json<PriceSummary>"
+from
+    window_cte
+group by
+    bucket
+order by
+    bucket desc
+limit $8
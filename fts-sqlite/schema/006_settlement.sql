@@ -0,0 +1,36 @@
+-- A settlement integrates the rates of trade produced by the batch auctions
+-- over time, rounding the accrued positions and payments to a fixed precision.
+-- Each settlement covers the interval [settled_from, as_of).
+create table settlement (
+    as_of text primary key,
+    settled_from text not null,
+    config blob not null -- Json<SettlementConfig>
+) strict, without rowid;
+--
+-- the settled position of each bidder in each product
+create table settlement_position (
+    as_of text not null,
+    bidder_id text not null,
+    product_id text not null,
+    -- the unrounded quantity accrued over the settlement interval
+    accrued real not null,
+    -- the rounded quantity, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (bidder_id, as_of, product_id),
+    unique (as_of, bidder_id, product_id),
+    foreign key (as_of) references settlement (as_of),
+    foreign key (product_id) references product (id)
+) strict, without rowid;
+--
+-- the settled payment of each bidder
+create table settlement_payment (
+    as_of text not null,
+    bidder_id text not null,
+    -- the unrounded payment accrued over the settlement interval
+    accrued real not null,
+    -- the rounded payment, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (bidder_id, as_of),
+    unique (as_of, bidder_id),
+    foreign key (as_of) references settlement (as_of)
+) strict, without rowid;
@@ -0,0 +1,7 @@
+-- Demands and portfolios may be purged (e.g. to honour a request for erasure).
+-- Purging erases the application data and the history of the record, but the
+-- row itself is retained as a tombstone, so that the references of other
+-- records (such as portfolio groups and batch outcomes) remain valid.
+alter table demand add column purged_at text;
+--
+alter table portfolio add column purged_at text;
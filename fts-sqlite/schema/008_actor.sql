@@ -0,0 +1,152 @@
+-- Every change to a demand or portfolio is attributed to an actor (the bidder,
+-- an operator, or the system). The actor of the latest change is stored
+-- alongside the record, and the triggers copy it into the history tables.
+alter table demand add column actor text not null default 'bidder';
+--
+alter table curve_data add column actor text not null default 'bidder';
+--
+alter table portfolio add column actor text not null default 'bidder';
+--
+alter table portfolio_demand add column actor text not null default 'bidder';
+--
+alter table portfolio_product add column actor text not null default 'bidder';
+--
+-- The triggers are otherwise identical to their original definitions
+drop trigger demand_insert_trigger;
+--
+create trigger demand_insert_trigger
+after insert on demand
+begin
+insert into curve_data (
+    demand_id,
+    value,
+    valid_from,
+    valid_until,
+    actor
+)
+values (
+    new.id,
+    new.curve_data,
+    new.as_of,
+    null,
+    new.actor
+);
+end;
+--
+drop trigger demand_update_trigger;
+--
+create trigger demand_update_trigger
+after update on demand
+begin
+update curve_data
+set
+    valid_until = new.as_of
+where
+    demand_id = old.id
+    and
+    valid_from = old.as_of;
+insert into curve_data (
+    demand_id, value, valid_from, valid_until, actor
+)
+values (
+    new.id, new.curve_data, new.as_of, null, new.actor
+);
+end;
+--
+drop trigger portfolio_insert_trigger;
+--
+create trigger portfolio_insert_trigger
+after insert on portfolio
+begin
+-- track the demand lifetime
+insert into portfolio_demand (
+    portfolio_id, demand_id, weight, valid_from, valid_until, actor
+)
+select
+    new.id, -- noqa: RF01
+    key,
+    value,
+    new.as_of,
+    null,
+    new.actor
+from
+    json_each(new.demand);
+    -- track the product lifetime
+insert into
+portfolio_product (portfolio_id, product_id, weight, valid_from, valid_until, actor)
+select
+    new.id, -- noqa: RF01
+    key,
+    value,
+    new.as_of,
+    null,
+    new.actor
+from
+    json_each(new.basis);
+end;
+--
+drop trigger portfolio_update_demand_trigger;
+--
+create trigger portfolio_update_demand_trigger
+after update of demand on portfolio
+begin
+update portfolio_demand
+set
+    valid_until = new.as_of
+where
+    portfolio_id = old.id
+    and
+    valid_from <= old.as_of
+    and
+    valid_until is null;
+insert into portfolio_demand (
+    portfolio_id,
+    demand_id,
+    weight,
+    valid_from,
+    valid_until,
+    actor
+)
+select
+    new.id, -- noqa: RF01
+    key,
+    value,
+    new.as_of,
+    null,
+    new.actor
+from
+    json_each(new.demand);
+end;
+--
+drop trigger portfolio_update_basis_trigger;
+--
+create trigger portfolio_update_basis_trigger
+after update of basis on portfolio
+begin
+update portfolio_product
+set
+    valid_until = new.as_of
+where
+    portfolio_id = old.id
+    and
+    valid_from <= old.as_of
+    and
+    valid_until is null;
+insert into portfolio_product (
+    portfolio_id,
+    product_id,
+    weight,
+    valid_from,
+    valid_until,
+    actor
+)
+select
+    new.id, -- noqa: RF01
+    key,
+    value,
+    new.as_of,
+    null,
+    new.actor
+from
+    json_each(new.basis);
+end;
@@ -0,0 +1,164 @@
+-- A single database may host several isolated markets, each identified by a
+-- market id. Products, demands, and portfolios belong to the market in which
+-- they were created, while the batch and the settlements are kept per market.
+-- Every existing record belongs to the default market, ''.
+alter table product add column market_id text not null default '';
+--
+create index product_market_id on product (market_id, id);
+--
+alter table demand add column market_id text not null default '';
+--
+create index demand_market_id on demand (market_id, bidder_id);
+--
+alter table portfolio add column market_id text not null default '';
+--
+create index portfolio_market_id on portfolio (market_id, bidder_id);
+--
+-- each market has a batch row of its own
+alter table batch add column market_id text not null default '';
+--
+create unique index batch_market_id on batch (market_id);
+--
+-- Markets may run their batches at the same time, so the outcomes a batch
+-- supersedes are only those of its market's portfolios and products
+drop trigger batch_update_portfolio_trigger;
+--
+create trigger batch_update_portfolio_trigger
+after update of portfolio_outcomes on batch
+begin
+-- invalidate existing output
+update portfolio_outcome
+set
+    valid_until = new.as_of
+where
+    valid_from = old.as_of
+    and
+    portfolio_id in (
+        select id from portfolio where market_id = new.market_id
+    );
+-- create new output
+insert into portfolio_outcome (
+    portfolio_id, value, valid_from, valid_until
+)
+select
+    "key",
+    jsonb(value) as value,
+    new.as_of, -- noqa: RF01
+    null as valid_until
+from
+    json_each(new.portfolio_outcomes);
+end;
+--
+drop trigger batch_update_product_trigger;
+--
+create trigger batch_update_product_trigger
+after update of product_outcomes on batch
+begin
+-- invalidate existing output
+update
+product_outcome
+set
+    valid_until = new.as_of
+where
+    valid_from = old.as_of
+    and
+    product_id in (
+        select id from product where market_id = new.market_id
+    );
+-- create new output
+insert into
+product_outcome (product_id, value, valid_from, valid_until)
+select
+    "key",
+    jsonb(value) as value,
+    new.as_of, -- noqa: RF01
+    null as valid_until
+from
+    json_each(new.product_outcomes);
+end;
+--
+-- Markets may also settle at the same time, so the settlements are keyed by
+-- market. SQLite cannot alter a primary key, so the tables are rebuilt: the
+-- originals are renamed out of the way (which carries their foreign keys
+-- along), copied into the default market, and dropped.
+alter table settlement_payment rename to settlement_payment_old;
+--
+alter table settlement_position rename to settlement_position_old;
+--
+alter table settlement rename to settlement_old;
+--
+create table settlement (
+    market_id text not null,
+    as_of text not null,
+    settled_from text not null,
+    config blob not null, -- Json<SettlementConfig>
+    primary key (market_id, as_of)
+) strict, without rowid;
+--
+create table settlement_position (
+    market_id text not null,
+    as_of text not null,
+    bidder_id text not null,
+    product_id text not null,
+    -- the unrounded quantity accrued over the settlement interval
+    accrued real not null,
+    -- the rounded quantity, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (market_id, bidder_id, as_of, product_id),
+    unique (market_id, as_of, bidder_id, product_id),
+    foreign key (market_id, as_of) references settlement (market_id, as_of),
+    foreign key (product_id) references product (id)
+) strict, without rowid;
+--
+create table settlement_payment (
+    market_id text not null,
+    as_of text not null,
+    bidder_id text not null,
+    -- the unrounded payment accrued over the settlement interval
+    accrued real not null,
+    -- the rounded payment, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (market_id, bidder_id, as_of),
+    unique (market_id, as_of, bidder_id),
+    foreign key (market_id, as_of) references settlement (market_id, as_of)
+) strict, without rowid;
+--
+insert into settlement (market_id, as_of, settled_from, config)
+select
+    '',
+    as_of,
+    settled_from,
+    config
+from
+    settlement_old;
+--
+insert into settlement_position (
+    market_id, as_of, bidder_id, product_id, accrued, settled
+)
+select
+    '',
+    as_of,
+    bidder_id,
+    product_id,
+    accrued,
+    settled
+from
+    settlement_position_old;
+--
+insert into settlement_payment (
+    market_id, as_of, bidder_id, accrued, settled
+)
+select
+    '',
+    as_of,
+    bidder_id,
+    accrued,
+    settled
+from
+    settlement_payment_old;
+--
+drop table settlement_payment_old;
+--
+drop table settlement_position_old;
+--
+drop table settlement_old;
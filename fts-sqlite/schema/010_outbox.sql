@@ -0,0 +1,108 @@
+-- Every change to the market records an event in the outbox, within the same
+-- transaction as the change, so that a relay may publish each event (at least
+-- once) and then mark it delivered. The triggers only identify what changed,
+-- as the current state of the entity is available from the other tables.
+create table outbox (
+    sequence integer primary key autoincrement,
+    market_id text not null,
+    as_of text not null,
+    event blob not null, -- Json<MarketEvent>
+    delivered_at text
+) strict;
+--
+create index outbox_pending on outbox (market_id, sequence)
+where delivered_at is null;
+--
+create trigger outbox_product_insert_trigger
+after insert on product
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object('type', 'product_created', 'product_id', new.id)
+);
+end;
+--
+create trigger outbox_demand_insert_trigger
+after insert on demand
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object(
+        'type', 'demand_created',
+        'demand_id', new.id,
+        'bidder_id', new.bidder_id
+    )
+);
+end;
+--
+create trigger outbox_demand_update_trigger
+after update on demand
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object(
+        'type', iif(new.purged_at is null, 'demand_updated', 'demand_purged'),
+        'demand_id', new.id,
+        'bidder_id', new.bidder_id
+    )
+);
+end;
+--
+create trigger outbox_portfolio_insert_trigger
+after insert on portfolio
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object(
+        'type', 'portfolio_created',
+        'portfolio_id', new.id,
+        'bidder_id', new.bidder_id
+    )
+);
+end;
+--
+create trigger outbox_portfolio_update_trigger
+after update on portfolio
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object(
+        'type',
+        iif(new.purged_at is null, 'portfolio_updated', 'portfolio_purged'),
+        'portfolio_id', new.id,
+        'bidder_id', new.bidder_id
+    )
+);
+end;
+--
+create trigger outbox_batch_update_trigger
+after update of as_of on batch
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object('type', 'batch_executed')
+);
+end;
+--
+create trigger outbox_settlement_insert_trigger
+after insert on settlement
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object('type', 'settled')
+);
+end;
@@ -0,0 +1,91 @@
+-- A settlement may be revised, either reversed by posting entries offsetting
+-- each of its positions and payments, or corrected by posting the entries of
+-- settling its interval again with another configuration. Revisions append
+-- entries rather than altering those already posted, so the net settlement is
+-- the sum of its entries across every revision, the original being revision 0.
+create table settlement_revision (
+    market_id text not null,
+    as_of text not null,
+    revision integer not null check (revision > 0),
+    revised_at text not null,
+    -- the configuration of a correction, or null for a reversal
+    config blob, -- Json<SettlementConfig>
+    primary key (market_id, as_of, revision),
+    foreign key (market_id, as_of) references settlement (market_id, as_of)
+) strict, without rowid;
+--
+-- The entries of the settlements are keyed by revision, for which the tables
+-- are rebuilt, as SQLite cannot alter a primary key.
+alter table settlement_payment rename to settlement_payment_old;
+--
+alter table settlement_position rename to settlement_position_old;
+--
+create table settlement_position (
+    market_id text not null,
+    as_of text not null,
+    revision integer not null default 0,
+    bidder_id text not null,
+    product_id text not null,
+    -- the unrounded quantity accrued over the settlement interval
+    accrued real not null,
+    -- the rounded quantity, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (market_id, bidder_id, as_of, product_id, revision),
+    unique (market_id, as_of, bidder_id, product_id, revision),
+    foreign key (market_id, as_of) references settlement (market_id, as_of),
+    foreign key (product_id) references product (id)
+) strict, without rowid;
+--
+create table settlement_payment (
+    market_id text not null,
+    as_of text not null,
+    revision integer not null default 0,
+    bidder_id text not null,
+    -- the unrounded payment accrued over the settlement interval
+    accrued real not null,
+    -- the rounded payment, adjusted for the residuals of previous settlements
+    settled real not null,
+    primary key (market_id, bidder_id, as_of, revision),
+    unique (market_id, as_of, bidder_id, revision),
+    foreign key (market_id, as_of) references settlement (market_id, as_of)
+) strict, without rowid;
+--
+insert into settlement_position (
+    market_id, as_of, bidder_id, product_id, accrued, settled
+)
+select
+    market_id,
+    as_of,
+    bidder_id,
+    product_id,
+    accrued,
+    settled
+from
+    settlement_position_old;
+--
+insert into settlement_payment (
+    market_id, as_of, bidder_id, accrued, settled
+)
+select
+    market_id,
+    as_of,
+    bidder_id,
+    accrued,
+    settled
+from
+    settlement_payment_old;
+--
+drop table settlement_payment_old;
+--
+drop table settlement_position_old;
+--
+create trigger outbox_settlement_revision_insert_trigger
+after insert on settlement_revision
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    new.as_of,
+    jsonb_object('type', 'settlement_revised')
+);
+end;
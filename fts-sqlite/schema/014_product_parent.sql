@@ -0,0 +1,113 @@
+-- The tree of products may be changed over time, by updating the ratio of a
+-- product to its parent or by moving it under another parent. The parent and
+-- ratio columns of the product hold its current parent, while this table holds
+-- the lifetime of each.
+create table product_parent (
+    product_id text not null,
+    parent_id text not null,
+    ratio real not null,
+    valid_from text not null,
+    valid_until text,
+    primary key (product_id, valid_from),
+    foreign key (product_id) references product (id),
+    foreign key (parent_id) references product (id)
+) strict, without rowid;
+--
+create index product_parent_parent_id on product_parent (parent_id, valid_from);
+--
+insert into product_parent (product_id, parent_id, ratio, valid_from, valid_until)
+select
+    id,
+    parent_id,
+    parent_ratio,
+    as_of,
+    null
+from
+    product
+where
+    parent_id is not null;
+--
+-- As paths of the tree now end (and begin) over time, only the current paths
+-- to the parent are extended to a new product, along with those just ended by
+-- the insertion of a sibling.
+drop trigger product_tree_trigger;
+--
+create trigger product_tree_trigger
+after insert on product
+begin
+-- noqa: disable=RF01
+update product_tree
+set
+    valid_until = new.as_of
+where
+    dst_id = new.parent_id
+    and
+    valid_until is null;
+
+insert into product_tree (
+    src_id,
+    dst_id,
+    ratio,
+    depth,
+    valid_from,
+    valid_until
+)
+select
+    pt.src_id,
+    new.id,
+    pt.ratio * new.parent_ratio as ratio,
+    pt.depth + 1 as depth,
+    new.as_of,
+    null as valid_until
+from
+    product_tree as pt
+where
+    pt.dst_id = new.parent_id
+    and
+    pt.valid_until = new.as_of;
+
+insert into product_parent (
+    product_id,
+    parent_id,
+    ratio,
+    valid_from,
+    valid_until
+)
+select
+    new.id,
+    new.parent_id,
+    new.parent_ratio,
+    new.as_of,
+    null
+where
+    new.parent_id is not null;
+-- noqa: enable=RF01
+
+insert into product_tree (
+    src_id,
+    dst_id,
+    ratio,
+    depth,
+    valid_from,
+    valid_until
+)
+values (
+    new.id,
+    new.id,
+    1.0,
+    0,
+    new.as_of,
+    null
+);
+end;
+--
+create trigger outbox_product_update_trigger
+after update of parent_id, parent_ratio on product
+begin
+insert into outbox (market_id, as_of, event)
+values (
+    new.market_id,
+    (select max(valid_from) from product_parent where product_id = new.id),
+    jsonb_object('type', 'product_moved', 'product_id', new.id)
+);
+end;
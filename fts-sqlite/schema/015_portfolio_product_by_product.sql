@@ -0,0 +1,7 @@
+-- The product groups of portfolios are held relationally in portfolio_product,
+-- which, unlike portfolio_demand, could only be searched by portfolio. Queries
+-- scoped to a product, such as the portfolios holding it or the activity
+-- accrued in it, would otherwise scan the groups of every portfolio.
+create unique index portfolio_product_by_product on portfolio_product (
+    product_id, portfolio_id, valid_from
+);
@@ -0,0 +1,10 @@
+-- A batch auction may record its inputs exactly as they were assembled for
+-- the solver (if so configured), so that it may be solved again without
+-- reconstructing its bids from the temporal tables, whose schema may since
+-- have changed. A batch retried at the same time replaces its record.
+create table batch_input (
+    market_id text not null,
+    as_of text not null,
+    inputs text not null, -- Json<Auction>
+    primary key (market_id, as_of)
+) strict, without rowid;
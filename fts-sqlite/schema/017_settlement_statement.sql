@@ -0,0 +1,20 @@
+-- A statement is issued to each bidder with entries whenever a settlement is
+-- made or revised, in the same transaction as the entries themselves. The
+-- statement is a finalized record, never altered once issued, whose id is
+-- never reused (hence autoincrement), so that it may be referenced by other
+-- records, such as invoices.
+create table settlement_statement (
+    id integer primary key autoincrement,
+    market_id text not null,
+    as_of text not null,
+    revision integer not null,
+    bidder_id text not null,
+    settled_from text not null,
+    -- the time of the revision, or null for the settlement as originally made
+    revised_at text,
+    -- the configuration of the entries, or null for a reversal
+    config blob, -- Json<SettlementConfig>
+    activity blob not null, -- Json<Activity>
+    unique (market_id, as_of, revision, bidder_id),
+    foreign key (market_id, as_of) references settlement (market_id, as_of)
+) strict;
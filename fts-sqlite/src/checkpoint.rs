@@ -0,0 +1,101 @@
+//! Explicit control of write-ahead log checkpoints.
+//!
+//! SQLite checkpoints the write-ahead log (WAL) back into the database file
+//! automatically, once it exceeds `wal_autocheckpoint` pages. Replication
+//! tools in the style of Litestream or LiteFS instead want to decide when a
+//! checkpoint happens, so that they can first capture the frames of the WAL.
+//! Such deployments may disable automatic checkpoints (by setting
+//! `wal_autocheckpoint` to 0), checkpoint explicitly with [`Db::checkpoint`],
+//! and register a hook with [`Db::with_checkpoint_hook`] to coordinate their
+//! snapshots with each checkpoint.
+
+use crate::{Db, instrument::Timed as _};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How aggressively a checkpoint copies the WAL into the database file.
+///
+/// See <https://www.sqlite.org/pragma.html#pragma_wal_checkpoint>.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointMode {
+    /// Checkpoint as many frames as possible without waiting on readers or writers
+    #[default]
+    Passive,
+    /// Wait for writers, then checkpoint every frame
+    Full,
+    /// As `Full`, then wait for readers so that the WAL restarts from the beginning
+    Restart,
+    /// As `Restart`, then truncate the WAL file to zero bytes
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Passive => "passive",
+            Self::Full => "full",
+            Self::Restart => "restart",
+            Self::Truncate => "truncate",
+        }
+    }
+}
+
+/// The result of a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointRecord {
+    /// The mode of the checkpoint
+    pub mode: CheckpointMode,
+
+    /// Whether the checkpoint was prevented from completing by another connection
+    pub busy: bool,
+
+    /// The number of frames in the WAL, or None if the database is not in WAL mode
+    pub wal_frames: Option<u64>,
+
+    /// The number of frames checkpointed into the database file, or None if
+    /// the database is not in WAL mode
+    pub checkpointed_frames: Option<u64>,
+}
+
+/// A function invoked after each explicit checkpoint.
+pub type CheckpointHook = Arc<dyn Fn(&CheckpointRecord) + Send + Sync>;
+
+impl Db {
+    /// Register a function to be invoked after each checkpoint made through
+    /// [`Db::checkpoint`], replacing any previously registered.
+    pub fn with_checkpoint_hook(
+        mut self,
+        hook: impl Fn(&CheckpointRecord) + Send + Sync + 'static,
+    ) -> Self {
+        self.checkpoint_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Checkpoint the WAL into the database file, then invoke the checkpoint hook.
+    ///
+    /// The checkpoint is made on the writer's connection, so it never races a
+    /// write of this process, though a `Full` (or stronger) checkpoint may wait
+    /// up to the busy timeout for the reader pool.
+    pub async fn checkpoint(&self, mode: CheckpointMode) -> Result<CheckpointRecord, sqlx::Error> {
+        let (busy, wal_frames, checkpointed_frames): (i64, i64, i64) =
+            sqlx::query_as(&format!("pragma wal_checkpoint({})", mode.as_str()))
+                .fetch_one(&mut *self.acquire_writer().await?)
+                .timed("checkpoint", self.slow_query_threshold)
+                .await?;
+
+        // SQLite reports -1 frames when the database is not in WAL mode
+        let record = CheckpointRecord {
+            mode,
+            busy: busy != 0,
+            wal_frames: u64::try_from(wal_frames).ok(),
+            checkpointed_frames: u64::try_from(checkpointed_frames).ok(),
+        };
+
+        if let Some(hook) = self.checkpoint_hook.as_ref() {
+            hook(&record);
+        }
+
+        Ok(record)
+    }
+}
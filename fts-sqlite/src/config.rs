@@ -3,8 +3,9 @@
 //! This module provides configuration options for establishing and managing
 //! SQLite database connections.
 
+use crate::maintenance::MaintenanceConfig;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 /// Configuration for SQLite database connections.
 ///
@@ -14,7 +15,7 @@ use std::path::PathBuf;
 ///
 /// ```
 /// use fts_sqlite::config::SqliteConfig;
-/// use std::path::PathBuf;
+/// use std::{path::PathBuf, time::Duration};
 ///
 /// // In-memory database (default)
 /// let config = SqliteConfig::default();
@@ -22,7 +23,7 @@ use std::path::PathBuf;
 /// // File-based database
 /// let config = SqliteConfig {
 ///     database_path: Some(PathBuf::from("flow_trading.db")),
-///     create_if_missing: true,
+///     ..Default::default()
 /// };
 /// ```
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -33,17 +34,139 @@ pub struct SqliteConfig {
     /// Whether to create the database if it doesn't exist
     #[serde(default = "default_true")]
     pub create_if_missing: bool,
+
+    /// Whether to apply pending schema migrations when the database is opened.
+    /// If false, opening a database with pending migrations fails instead,
+    /// leaving them to be applied in a separate step with
+    /// [`Db::migrate`](crate::Db::migrate)
+    #[serde(default = "default_true")]
+    pub auto_migrate: bool,
+
+    /// Read-only copies of the database file, maintained by external
+    /// replication (e.g. LiteFS), across which reads are balanced along with
+    /// the database itself. A replica lags by however long its replication
+    /// takes, so a read made on it may not yet observe the latest writes.
+    /// Writes, snapshots, and units of work remain on the database itself
+    #[serde(default)]
+    pub replica_paths: Vec<PathBuf>,
+
+    /// Database file into which pruned history is archived. If None, pruned history is discarded
+    #[serde(default)]
+    pub archive_path: Option<PathBuf>,
+
+    /// Directory to which pruned batch outcomes and trades are exported as Parquet files
+    #[cfg(feature = "parquet")]
+    #[serde(default)]
+    pub export_path: Option<PathBuf>,
+
+    /// Size in bytes to which the WAL file is truncated after a checkpoint (-1 for no limit)
+    #[serde(default = "default_journal_size_limit")]
+    pub journal_size_limit: i64,
+
+    /// Number of WAL pages after which a commit checkpoints automatically
+    /// (0 disables automatic checkpoints, e.g. when a replication tool manages them)
+    #[serde(default = "default_wal_autocheckpoint")]
+    pub wal_autocheckpoint: u32,
+
+    /// The market to which the opened database is scoped. Other markets of
+    /// the same database may be reached with [`Db::market`](crate::Db::market)
+    #[serde(default)]
+    pub market_id: String,
+
+    /// Passphrase with which the database file (and any archive) is encrypted
+    /// by SQLCipher. If None, the database is stored in plaintext. Ignored for
+    /// in-memory databases
+    #[cfg(feature = "sqlcipher")]
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+
+    /// Duration beyond which a query is logged as slow
+    #[serde(default = "default_slow_query_threshold", with = "humantime_serde")]
+    pub slow_query_threshold: Duration,
+
+    /// How long a connection waits for a lock held by another connection
+    /// before failing with `SQLITE_BUSY`
+    #[serde(default = "default_busy_timeout", with = "humantime_serde")]
+    pub busy_timeout: Duration,
+
+    /// Number of times a write transaction is begun anew if the database
+    /// remains busy for the whole `busy_timeout` (0 never retries)
+    #[serde(default = "default_write_retries")]
+    pub write_retries: u32,
+
+    /// Delay before the first retry of a busy write transaction, which
+    /// doubles (with jitter) at each further retry
+    #[serde(default = "default_write_retry_backoff", with = "humantime_serde")]
+    pub write_retry_backoff: Duration,
+
+    /// Whether to record the demand curves and portfolios of each batch
+    /// exactly as they were assembled for the solver, so that any batch may
+    /// be solved again
+    #[serde(default)]
+    pub record_batch_inputs: bool,
+
+    /// JSON paths into the application data of products, demands, and
+    /// portfolios (e.g. `$.kind`) on which to index them, so that searches
+    /// filtering on these paths need not scan every record
+    #[serde(default)]
+    pub app_data_indexes: Vec<String>,
+
+    /// Periodic maintenance of the query planner's statistics and of the
+    /// pages left free by pruning
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_journal_size_limit() -> i64 {
+    27103364
+}
+
+fn default_wal_autocheckpoint() -> u32 {
+    1000
+}
+
+fn default_slow_query_threshold() -> Duration {
+    Duration::from_millis(250)
+}
+
+fn default_busy_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_write_retries() -> u32 {
+    3
+}
+
+fn default_write_retry_backoff() -> Duration {
+    Duration::from_millis(50)
+}
+
 impl Default for SqliteConfig {
     fn default() -> Self {
         Self {
             database_path: None,
             create_if_missing: true,
+            auto_migrate: true,
+            replica_paths: Vec::new(),
+            archive_path: None,
+            #[cfg(feature = "parquet")]
+            export_path: None,
+            journal_size_limit: default_journal_size_limit(),
+            wal_autocheckpoint: default_wal_autocheckpoint(),
+            market_id: String::new(),
+            #[cfg(feature = "sqlcipher")]
+            encryption_key: None,
+            slow_query_threshold: default_slow_query_threshold(),
+            busy_timeout: default_busy_timeout(),
+            write_retries: default_write_retries(),
+            write_retry_backoff: default_write_retry_backoff(),
+            record_batch_inputs: false,
+            app_data_indexes: Vec::new(),
+            maintenance: MaintenanceConfig::default(),
         }
     }
 }
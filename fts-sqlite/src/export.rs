@@ -0,0 +1,532 @@
+//! Export of batch outcomes, trades, and settlements to Parquet files.
+//!
+//! Each dataset is written to its own directory, partitioned by the (UTC) date
+//! of its records in the Hive style, e.g.
+//! `<directory>/trade/date=2025-01-01/<file>.parquet`. Outcomes and trades are
+//! dated by the batch which produced them, and settlements by the time up to
+//! which they settle. Exporting the same interval again overwrites the files
+//! written previously, so an export may safely be repeated. Only the records
+//! of the database's market are exported, and the names of the files of any
+//! market but the default are prefixed by its id.
+//!
+//! This module is only available with the `parquet` feature.
+
+use crate::{
+    Db,
+    instrument::Timed as _,
+    types::{BidderId, DateTime, PortfolioId, ProductId},
+};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::ArrowError;
+use futures_util::{Stream, TryStreamExt as _};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use time::{format_description::BorrowedFormatItem, macros::format_description};
+
+/// The format of the timestamps in the names of exported files
+const FILE_TIME: &[BorrowedFormatItem<'_>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+/// The number of records encoded at a time, and so held in memory, by an export
+const ROW_GROUP_SIZE: usize = 65536;
+
+/// Errors which may occur while exporting.
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    /// The records could not be read from the database
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+
+    /// The records could not be encoded
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    /// The files could not be written
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<ArrowError> for ExportError {
+    fn from(error: ArrowError) -> Self {
+        Self::Parquet(error.into())
+    }
+}
+
+/// A summary of the records written by an export.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportRecord {
+    /// The number of exported product outcomes
+    pub product_outcomes: u64,
+
+    /// The number of exported portfolio outcomes
+    pub portfolio_outcomes: u64,
+
+    /// The number of exported trades, i.e. rates of trade of a bidder in a product
+    pub trades: u64,
+
+    /// The number of exported settled positions
+    pub settlement_positions: u64,
+
+    /// The number of exported settled payments
+    pub settlement_payments: u64,
+
+    /// The files written by the export
+    pub files: Vec<PathBuf>,
+}
+
+impl Db {
+    /// Export the outcomes and trades of the batches executed in [`from`, `until`),
+    /// along with the settlements made in that interval, to Parquet files
+    /// within `directory`.
+    ///
+    /// If `from` is None, everything before `until` is exported.
+    pub async fn export_parquet(
+        &self,
+        directory: impl AsRef<Path>,
+        from: Option<DateTime>,
+        until: DateTime,
+    ) -> Result<ExportRecord, ExportError> {
+        let mut conn = self.acquire_reader().await?;
+        let name = match from {
+            Some(from) => file_name(&self.market_id, &file_time(from), until),
+            None => file_name(&self.market_id, "start", until),
+        };
+
+        let mut record = export_outcomes(
+            &mut conn,
+            directory.as_ref(),
+            &name,
+            &self.market_id,
+            from,
+            until,
+            false,
+            self.slow_query_threshold,
+        )
+        .await?;
+
+        let positions = sqlx::query_file_as!(
+            SettlementPositionRow,
+            "queries/export_settlement_positions.sql",
+            from,
+            until,
+            self.market_id
+        )
+        .fetch(&mut *conn);
+        let (count, files) = write_dataset(directory.as_ref(), &name, positions)
+            .timed(
+                "export_parquet.settlement_positions",
+                self.slow_query_threshold,
+            )
+            .await?;
+        record.settlement_positions = count;
+        record.files.extend(files);
+
+        let payments = sqlx::query_file_as!(
+            SettlementPaymentRow,
+            "queries/export_settlement_payments.sql",
+            from,
+            until,
+            self.market_id
+        )
+        .fetch(&mut *conn);
+        let (count, files) = write_dataset(directory.as_ref(), &name, payments)
+            .timed(
+                "export_parquet.settlement_payments",
+                self.slow_query_threshold,
+            )
+            .await?;
+        record.settlement_payments = count;
+        record.files.extend(files);
+
+        Ok(record)
+    }
+}
+
+/// Export the outcomes and trades of the market's batches executed in [`from`, `until`),
+/// or if `superseded`, only those which were also superseded by `until`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn export_outcomes(
+    conn: &mut sqlx::SqliteConnection,
+    directory: &Path,
+    name: &str,
+    market_id: &str,
+    from: Option<DateTime>,
+    until: DateTime,
+    superseded: bool,
+    threshold: Duration,
+) -> Result<ExportRecord, ExportError> {
+    let mut record = ExportRecord::default();
+
+    let product_outcomes = sqlx::query_file_as!(
+        ProductOutcomeRow,
+        "queries/export_product_outcomes.sql",
+        from,
+        until,
+        superseded,
+        market_id
+    )
+    .fetch(&mut *conn);
+    let (count, files) = write_dataset(directory, name, product_outcomes)
+        .timed("export_outcomes.product_outcomes", threshold)
+        .await?;
+    record.product_outcomes = count;
+    record.files.extend(files);
+
+    let portfolio_outcomes = sqlx::query_file_as!(
+        PortfolioOutcomeRow,
+        "queries/export_portfolio_outcomes.sql",
+        from,
+        until,
+        superseded,
+        market_id
+    )
+    .fetch(&mut *conn);
+    let (count, files) = write_dataset(directory, name, portfolio_outcomes)
+        .timed("export_outcomes.portfolio_outcomes", threshold)
+        .await?;
+    record.portfolio_outcomes = count;
+    record.files.extend(files);
+
+    let trades = sqlx::query_file_as!(
+        TradeRow,
+        "queries/export_trades.sql",
+        from,
+        until,
+        superseded,
+        market_id
+    )
+    .fetch(&mut *conn);
+    let (count, files) = write_dataset(directory, name, trades)
+        .timed("export_outcomes.trades", threshold)
+        .await?;
+    record.trades = count;
+    record.files.extend(files);
+
+    Ok(record)
+}
+
+/// The name of an export's files, from its market, a label, and the time up to
+/// which it exports
+pub(crate) fn file_name(market_id: &str, label: &str, until: DateTime) -> String {
+    if market_id.is_empty() {
+        format!("{label}-{}", file_time(until))
+    } else {
+        format!("{market_id}-{label}-{}", file_time(until))
+    }
+}
+
+/// The format of a time in the name of an export's files
+pub(crate) fn file_time(datetime: DateTime) -> String {
+    let datetime: time::OffsetDateTime = datetime.into();
+    datetime.format(FILE_TIME).expect("the format is valid")
+}
+
+/// A kind of record which is exported as a dataset of its own.
+trait Dataset: Sized + Send + 'static {
+    /// The name of the dataset's directory
+    const NAME: &'static str;
+
+    /// The time by which the record is partitioned
+    fn time(&self) -> DateTime;
+
+    /// Convert the records to columns
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)>;
+}
+
+/// Write the records, which are ordered by time, into a file of each date's partition,
+/// returning the number of records written and the files written.
+///
+/// The records are encoded as they are read, a row group at a time, so that
+/// no more than a row group of them are held in memory.
+async fn write_dataset<T: Dataset>(
+    directory: &Path,
+    name: &str,
+    rows: impl Stream<Item = Result<T, sqlx::Error>>,
+) -> Result<(u64, Vec<PathBuf>), ExportError> {
+    let mut writer = DatasetWriter::new(directory.join(T::NAME), format!("{name}.parquet"));
+    let mut rows = std::pin::pin!(rows);
+    let mut count = 0;
+    let mut group = Vec::new();
+
+    while let Some(row) = rows.try_next().await? {
+        if group.len() == ROW_GROUP_SIZE
+            || group
+                .first()
+                .is_some_and(|first: &T| date(first.time()) != date(row.time()))
+        {
+            let rows = std::mem::take(&mut group);
+            writer = off_runtime(move || writer.write(rows)).await?;
+        }
+        group.push(row);
+        count += 1;
+    }
+
+    if !group.is_empty() {
+        writer = off_runtime(move || writer.write(group)).await?;
+    }
+    let files = off_runtime(move || writer.finish()).await?;
+
+    Ok((count, files))
+}
+
+/// Encoding and writing the files blocks, so we move it off the runtime
+async fn off_runtime<R: Send + 'static>(
+    f: impl FnOnce() -> Result<R, ExportError> + Send + 'static,
+) -> Result<R, ExportError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Writes a dataset's records into the file of the partition of their date.
+struct DatasetWriter {
+    directory: PathBuf,
+    name: String,
+    properties: WriterProperties,
+    partition: Option<(time::Date, ArrowWriter<File>)>,
+    files: Vec<PathBuf>,
+}
+
+impl DatasetWriter {
+    fn new(directory: PathBuf, name: String) -> Self {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+
+        Self {
+            directory,
+            name,
+            properties,
+            partition: None,
+            files: Vec::new(),
+        }
+    }
+
+    /// Write records of a single date, starting the file of their partition
+    /// if it is not already open.
+    fn write<T: Dataset>(mut self, rows: Vec<T>) -> Result<Self, ExportError> {
+        let date = date(rows[0].time());
+        let batch = RecordBatch::try_from_iter(T::columns(&rows))?;
+
+        if self
+            .partition
+            .as_ref()
+            .is_none_or(|(current, _)| *current != date)
+        {
+            self.close()?;
+
+            let partition = self.directory.join(format!("date={date}"));
+            std::fs::create_dir_all(&partition)?;
+            let path = partition.join(&self.name);
+
+            let writer = ArrowWriter::try_new(
+                File::create(&path)?,
+                batch.schema(),
+                Some(self.properties.clone()),
+            )?;
+            self.partition = Some((date, writer));
+            self.files.push(path);
+        }
+
+        let (_, writer) = self.partition.as_mut().expect("the partition is open");
+        writer.write(&batch)?;
+        Ok(self)
+    }
+
+    /// Close the file being written, if any
+    fn close(&mut self) -> Result<(), ExportError> {
+        if let Some((_, writer)) = self.partition.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+
+    /// Close the last file, returning every file written
+    fn finish(mut self) -> Result<Vec<PathBuf>, ExportError> {
+        self.close()?;
+        Ok(self.files)
+    }
+}
+
+fn date(datetime: DateTime) -> time::Date {
+    let datetime: time::OffsetDateTime = datetime.into();
+    datetime.date()
+}
+
+fn ids<T>(rows: &[T], id: impl Fn(&T) -> uuid::Uuid) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(
+        rows.iter().map(|row| id(row).to_string()),
+    ))
+}
+
+fn times<T>(rows: &[T], time: impl Fn(&T) -> Option<DateTime>) -> ArrayRef {
+    let micros = |datetime: DateTime| {
+        let datetime: time::OffsetDateTime = datetime.into();
+        (datetime.unix_timestamp_nanos() / 1000) as i64
+    };
+    Arc::new(
+        TimestampMicrosecondArray::from_iter(rows.iter().map(|row| time(row).map(micros)))
+            .with_timezone("UTC"),
+    )
+}
+
+fn floats<T>(rows: &[T], value: impl Fn(&T) -> Option<f64>) -> ArrayRef {
+    Arc::new(Float64Array::from_iter(rows.iter().map(value)))
+}
+
+fn strings<T>(rows: &[T], value: impl Fn(&T) -> Option<&str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter(rows.iter().map(value)))
+}
+
+/// The outcome of a batch for a product.
+struct ProductOutcomeRow {
+    product_id: ProductId,
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    price: Option<f64>,
+    rate: Option<f64>,
+    value: Option<String>,
+}
+
+impl Dataset for ProductOutcomeRow {
+    const NAME: &'static str = "product_outcome";
+
+    fn time(&self) -> DateTime {
+        self.valid_from
+    }
+
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("product_id", ids(rows, |row| row.product_id.0)),
+            ("valid_from", times(rows, |row| Some(row.valid_from))),
+            ("valid_until", times(rows, |row| row.valid_until)),
+            ("price", floats(rows, |row| row.price)),
+            ("rate", floats(rows, |row| row.rate)),
+            ("value", strings(rows, |row| row.value.as_deref())),
+        ]
+    }
+}
+
+/// The outcome of a batch for a portfolio.
+struct PortfolioOutcomeRow {
+    portfolio_id: PortfolioId,
+    bidder_id: BidderId,
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    price: Option<f64>,
+    rate: Option<f64>,
+    value: Option<String>,
+}
+
+impl Dataset for PortfolioOutcomeRow {
+    const NAME: &'static str = "portfolio_outcome";
+
+    fn time(&self) -> DateTime {
+        self.valid_from
+    }
+
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("portfolio_id", ids(rows, |row| row.portfolio_id.0)),
+            ("bidder_id", ids(rows, |row| row.bidder_id.0)),
+            ("valid_from", times(rows, |row| Some(row.valid_from))),
+            ("valid_until", times(rows, |row| row.valid_until)),
+            ("price", floats(rows, |row| row.price)),
+            ("rate", floats(rows, |row| row.rate)),
+            ("value", strings(rows, |row| row.value.as_deref())),
+        ]
+    }
+}
+
+/// The rate of trade of a bidder in a product, by way of a portfolio.
+struct TradeRow {
+    bidder_id: BidderId,
+    portfolio_id: PortfolioId,
+    product_id: ProductId,
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    rate: f64,
+    price: Option<f64>,
+}
+
+impl Dataset for TradeRow {
+    const NAME: &'static str = "trade";
+
+    fn time(&self) -> DateTime {
+        self.valid_from
+    }
+
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("bidder_id", ids(rows, |row| row.bidder_id.0)),
+            ("portfolio_id", ids(rows, |row| row.portfolio_id.0)),
+            ("product_id", ids(rows, |row| row.product_id.0)),
+            ("valid_from", times(rows, |row| Some(row.valid_from))),
+            ("valid_until", times(rows, |row| row.valid_until)),
+            ("rate", floats(rows, |row| Some(row.rate))),
+            ("price", floats(rows, |row| row.price)),
+        ]
+    }
+}
+
+/// The settled position of a bidder in a product.
+struct SettlementPositionRow {
+    as_of: DateTime,
+    settled_from: DateTime,
+    bidder_id: BidderId,
+    product_id: ProductId,
+    accrued: f64,
+    settled: f64,
+}
+
+impl Dataset for SettlementPositionRow {
+    const NAME: &'static str = "settlement_position";
+
+    fn time(&self) -> DateTime {
+        self.as_of
+    }
+
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("as_of", times(rows, |row| Some(row.as_of))),
+            ("settled_from", times(rows, |row| Some(row.settled_from))),
+            ("bidder_id", ids(rows, |row| row.bidder_id.0)),
+            ("product_id", ids(rows, |row| row.product_id.0)),
+            ("accrued", floats(rows, |row| Some(row.accrued))),
+            ("settled", floats(rows, |row| Some(row.settled))),
+        ]
+    }
+}
+
+/// The settled payment of a bidder.
+struct SettlementPaymentRow {
+    as_of: DateTime,
+    settled_from: DateTime,
+    bidder_id: BidderId,
+    accrued: f64,
+    settled: f64,
+}
+
+impl Dataset for SettlementPaymentRow {
+    const NAME: &'static str = "settlement_payment";
+
+    fn time(&self) -> DateTime {
+        self.as_of
+    }
+
+    fn columns(rows: &[Self]) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("as_of", times(rows, |row| Some(row.as_of))),
+            ("settled_from", times(rows, |row| Some(row.settled_from))),
+            ("bidder_id", ids(rows, |row| row.bidder_id.0)),
+            ("accrued", floats(rows, |row| Some(row.accrued))),
+            ("settled", floats(rows, |row| Some(row.settled))),
+        ]
+    }
+}
@@ -0,0 +1,112 @@
+//! Filtering of records by their application data.
+//!
+//! Products, demands, and portfolios may be searched by JSON paths into their
+//! application data. SQLite only uses an index on an expression when the query
+//! repeats that expression verbatim, so the paths are written into statements
+//! as literals rather than bound, and an index on `json_extract` is maintained
+//! for each of the configured `app_data_indexes`.
+
+use crate::instrument::Timed as _;
+use fts_core::models::{AppDataFilter, Comparison, FilterValue};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+use std::time::Duration;
+
+/// The tables whose application data may be indexed
+const TABLES: [&str; 3] = ["product", "demand", "portfolio"];
+
+/// Quote a JSON path as a SQL string literal
+fn path_literal(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "''"))
+}
+
+/// The name of the index on `path` into the application data of `table`,
+/// which is unique to the path without needing to sanitize it
+fn index_name(table: &str, path: &str) -> String {
+    let hex: String = path.bytes().map(|byte| format!("{byte:02x}")).collect();
+    format!("{table}_app_data_{hex}")
+}
+
+/// Restrict a query of `table` to the records whose application data satisfies
+/// every filter.
+pub(crate) fn push_filters<'args>(
+    query_builder: &mut QueryBuilder<'args, Sqlite>,
+    table: &str,
+    filters: &'args [AppDataFilter],
+) {
+    // json_extract yields null for absent fields, which never compares
+    // true, so absent fields never satisfy a filter
+    for filter in filters {
+        query_builder
+            .push(format_args!(
+                " and json_extract({table}.app_data, {}",
+                path_literal(&filter.path)
+            ))
+            .push(match filter.op {
+                Comparison::Eq => ") = ",
+                Comparison::Ne => ") != ",
+                Comparison::Lt => ") < ",
+                Comparison::Le => ") <= ",
+                Comparison::Gt => ") > ",
+                Comparison::Ge => ") >= ",
+            });
+        match &filter.value {
+            FilterValue::Bool(value) => query_builder.push_bind(*value),
+            FilterValue::Number(value) => query_builder.push_bind(*value),
+            FilterValue::String(value) => query_builder.push_bind(value.as_str()),
+        };
+    }
+}
+
+/// Index the application data of every table on each of `paths`, dropping the
+/// indexes on any path no longer configured.
+pub(crate) async fn index_app_data(
+    conn: &mut SqliteConnection,
+    paths: &[String],
+    threshold: Duration,
+) -> Result<(), sqlx::Error> {
+    if let Some(path) = paths.iter().find(|path| !path.starts_with('$')) {
+        return Err(sqlx::Error::Configuration(
+            format!("{path} is not a JSON path").into(),
+        ));
+    }
+
+    let wanted: Vec<(&str, String, &str)> = TABLES
+        .into_iter()
+        .flat_map(|table| {
+            paths
+                .iter()
+                .map(move |path| (table, index_name(table, path), path.as_str()))
+        })
+        .collect();
+
+    let existing: Vec<String> = sqlx::query_scalar(
+        "select name from sqlite_schema where type = 'index' and name glob '*_app_data_*'",
+    )
+    .fetch_all(&mut *conn)
+    .timed("index_app_data.existing", threshold)
+    .await?;
+
+    for name in existing.iter() {
+        if !wanted.iter().any(|(_, wanted, _)| wanted == name) {
+            sqlx::raw_sql(&format!("drop index {name}"))
+                .execute(&mut *conn)
+                .timed("index_app_data.drop", threshold)
+                .await?;
+        }
+    }
+
+    // Markets are searched separately, so each index leads with the market
+    for (table, name, path) in wanted.iter() {
+        if !existing.contains(name) {
+            sqlx::raw_sql(&format!(
+                "create index {name} on {table} (market_id, json_extract(app_data, {}))",
+                path_literal(path)
+            ))
+            .execute(&mut *conn)
+            .timed("index_app_data.create", threshold)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
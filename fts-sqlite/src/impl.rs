@@ -7,12 +7,25 @@ use crate::{
     Db,
     types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
 };
-use fts_core::ports::Repository;
+use fts_core::{
+    models::{DateTimeRangeQuery, DateTimeRangeResponse, ValueRecord},
+    ports::Repository,
+};
+use futures_util::{Stream, TryStreamExt as _, stream};
 
 mod batch;
+mod consistency;
 mod demand;
+mod health;
+mod import;
+mod outbox;
 mod portfolio;
 mod product;
+mod retention;
+mod settlement;
+
+/// The number of records read from the database at a time when streaming
+const STREAM_PAGE_SIZE: usize = 1000;
 
 impl Repository for Db {
     type Error = sqlx::Error;
@@ -22,3 +35,33 @@ impl Repository for Db {
     type DemandId = DemandId;
     type PortfolioId = PortfolioId;
 }
+
+/// Stream the records of a paginated query, one page at a time.
+///
+/// Rather than hold a connection to the database for as long as the consumer
+/// takes to drain the stream, each page is read in full and released before
+/// the next is requested.
+fn stream_pages<V, F, Fut>(
+    query: DateTimeRangeQuery<DateTime>,
+    fetch: F,
+) -> impl Stream<Item = Result<ValueRecord<DateTime, V>, sqlx::Error>> + Send
+where
+    V: Send,
+    F: Fn(DateTimeRangeQuery<DateTime>) -> Fut + Send,
+    Fut: Future<Output = Result<DateTimeRangeResponse<V, DateTime>, sqlx::Error>> + Send,
+{
+    stream::try_unfold(Some(query), move |next| {
+        let page = next.map(&fetch);
+        async move {
+            let Some(page) = page else {
+                return Ok::<_, sqlx::Error>(None);
+            };
+            let page = page.await?;
+            Ok(Some((
+                stream::iter(page.results.into_iter().map(Ok)),
+                page.more,
+            )))
+        }
+    })
+    .try_flatten()
+}
@@ -1,11 +1,46 @@
-use crate::Db;
+use super::{STREAM_PAGE_SIZE, stream_pages};
 use crate::types::{DateTime, DemandId, PortfolioId, ProductId, ValueRow};
-use fts_core::models::{DateTimeRangeQuery, DateTimeRangeResponse};
+use crate::{Db, instrument::Timed as _};
+use fts_core::models::{
+    Auction, BatchAttempt, BatchError, BatchFailure, BatchMetadata, BatchPreview, BatchRun,
+    BidderOutcome, BidderTrade, DateTimeRangeQuery, DateTimeRangeResponse, MarketStatistics,
+    PriceInterval, PriceSummary, ProductStatistics, SolverTelemetry, ValueRecord,
+};
 use fts_core::{
-    models::{Basis, DemandCurve, DemandCurveDto, Weights},
+    models::{Basis, DemandCurve, DemandCurveDto, Map, Weights},
     ports::{BatchRepository, Solver},
 };
+use futures_util::Stream;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 use tokio::try_join;
+use tracing::{Level, event};
+
+/// Marks a batch auction as executing for a market until dropped
+struct BatchGuard<'a> {
+    running: &'a Mutex<HashSet<String>>,
+    market_id: &'a str,
+}
+
+impl<'a> BatchGuard<'a> {
+    /// Mark a batch as executing for the market, unless one already is
+    fn acquire(running: &'a Mutex<HashSet<String>>, market_id: &'a str) -> Option<Self> {
+        let inserted = running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(market_id.to_owned());
+        inserted.then_some(Self { running, market_id })
+    }
+}
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(self.market_id);
+    }
+}
 
 struct ActiveDemand {
     id: DemandId,
@@ -24,36 +59,136 @@ struct ActivePortfolio {
     id: PortfolioId,
     expires: Option<DateTime>,
     demand: sqlx::types::Json<Weights<DemandId>>,
-    basis: sqlx::types::Json<Basis<ProductId>>,
+}
+
+/// The weight of a product in the basis of an active portfolio
+struct ActiveBasis {
+    portfolio_id: PortfolioId,
+    product_id: ProductId,
+    weight: f64,
+}
+
+/// The net trade of a bidder in a product in a batch, or a batch in which
+/// none of the bidder's portfolios had a basis (without a product)
+#[derive(sqlx::FromRow)]
+struct BidderTradeRow {
+    valid_from: DateTime,
+    valid_until: Option<DateTime>,
+    product_id: Option<ProductId>,
+    rate: f64,
+    payment: f64,
+}
+
+/// The record of how a batch was solved
+struct BatchRunRow {
+    as_of: DateTime,
+    backend: String,
+    settings: sqlx::types::Json<Map<String, String>>,
+    status: String,
+    stats: sqlx::types::Json<Map<String, f64>>,
+    error: Option<String>,
+}
+
+impl From<BatchRunRow> for ValueRecord<DateTime, BatchRun> {
+    fn from(row: BatchRunRow) -> Self {
+        Self {
+            valid_from: row.as_of,
+            valid_until: None,
+            value: BatchRun {
+                solver: SolverTelemetry {
+                    backend: row.backend,
+                    settings: row.settings.0,
+                    status: row.status,
+                    stats: row.stats.0,
+                },
+                error: row.error,
+            },
+            actor: None,
+        }
+    }
+}
+
+/// A failed attempt to run a batch
+struct BatchAttemptRow {
+    as_of: DateTime,
+    category: String,
+    message: String,
+}
+
+/// The outcomes of a product, aggregated over some window
+struct OutcomeStatisticsRow {
+    product_id: ProductId,
+    price: Option<f64>,
+    cleared: Option<DateTime>,
+    volume: f64,
+}
+
+/// The active bids referencing a product
+struct BidStatisticsRow {
+    product_id: ProductId,
+    portfolios: i64,
+    demands: i64,
 }
 
 fn coalesce_min<T: Copy + Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
     a.or(b).min(b.or(a))
 }
 
-impl<T: Solver<DemandId, PortfolioId, ProductId>> BatchRepository<T> for Db
-where
-    T: Send,
-    T::Error: Send,
-    T::State: Send,
-    T::PortfolioOutcome: Unpin + Send + serde::Serialize + serde::de::DeserializeOwned,
-    T::ProductOutcome: Unpin + Send + serde::Serialize + serde::de::DeserializeOwned,
-{
-    async fn run_batch(
-        &self,
-        timestamp: Self::DateTime,
-        solver: T,
-        state: T::State,
-    ) -> Result<Result<Option<Self::DateTime>, T::Error>, Self::Error> {
-        let demand_records =
-            sqlx::query_file_as!(ActiveDemand, "queries/active_demands.sql", timestamp)
-                .fetch_all(&self.reader);
+/// The inputs to a batch auction, along with the time the first of them expires
+type BatchInputs = (
+    Map<DemandId, DemandCurve>,
+    Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+    Option<DateTime>,
+);
+
+impl Db {
+    /// Gather the demands and portfolios active at `timestamp`
+    async fn gather_batch(&self, timestamp: DateTime) -> Result<BatchInputs, sqlx::Error> {
+        // The demands and portfolios are read from the same snapshot, so that
+        // every portfolio's demands are among those gathered
+        let (demand_records, portfolio_records, basis_records) = self
+            .snapshot(|db| {
+                Box::pin(async move {
+                    let demand_records = async {
+                        sqlx::query_file_as!(
+                            ActiveDemand,
+                            "queries/active_demands.sql",
+                            timestamp,
+                            db.market_id
+                        )
+                        .fetch_all(&mut *db.acquire_reader().await?)
+                        .timed("gather_batch.demands", db.slow_query_threshold)
+                        .await
+                    };
 
-        let portfolio_records =
-            sqlx::query_file_as!(ActivePortfolio, "queries/active_portfolios.sql", timestamp)
-                .fetch_all(&self.reader);
+                    let portfolio_records = async {
+                        sqlx::query_file_as!(
+                            ActivePortfolio,
+                            "queries/active_portfolios.sql",
+                            timestamp,
+                            db.market_id
+                        )
+                        .fetch_all(&mut *db.acquire_reader().await?)
+                        .timed("gather_batch.portfolios", db.slow_query_threshold)
+                        .await
+                    };
 
-        let (demand_records, portfolio_records) = try_join!(demand_records, portfolio_records)?;
+                    let basis_records = async {
+                        sqlx::query_file_as!(
+                            ActiveBasis,
+                            "queries/active_basis.sql",
+                            timestamp,
+                            db.market_id
+                        )
+                        .fetch_all(&mut *db.acquire_reader().await?)
+                        .timed("gather_batch.basis", db.slow_query_threshold)
+                        .await
+                    };
+
+                    try_join!(demand_records, portfolio_records, basis_records)
+                })
+            })
+            .await?;
 
         let mut expires = coalesce_min(
             demand_records.get(0).map(|x| x.expires).flatten(),
@@ -71,23 +206,148 @@ where
             })
             .collect();
 
+        let mut bases = HashMap::<PortfolioId, Vec<(ProductId, f64)>>::new();
+        for row in basis_records {
+            bases
+                .entry(row.portfolio_id)
+                .or_default()
+                .push((row.product_id, row.weight));
+        }
+
         let portfolios = portfolio_records
             .into_iter()
             .map(|row| {
                 expires = coalesce_min(expires, row.expires);
-                (row.id, (row.demand.0, row.basis.0))
+                let basis = bases.remove(&row.id).unwrap_or_default();
+                (row.id, (row.demand.0, basis.into_iter().collect()))
             })
             .collect();
 
+        Ok((demands, portfolios, expires))
+    }
+
+    /// Assemble the demands and portfolios active at `as_of` into a
+    /// self-contained auction, exactly as a batch auction run at that time
+    /// would gather them, e.g. to solve offline with `ftauction`.
+    pub async fn gather_auction(&self, as_of: DateTime) -> Result<Auction<Self>, sqlx::Error> {
+        let (demands, portfolios, _) = self.gather_batch(as_of).await?;
+        Ok(Auction::from_parts(demands, portfolios))
+    }
+
+    /// Retrieve the outcomes recorded by the batch auction run at `as_of`,
+    /// keyed by portfolio and by product, e.g. to compare them with those of
+    /// solving the batch again.
+    ///
+    /// The outcomes are empty if no batch was run at that time, if the solver
+    /// failed to solve it, or if its outcomes have since been pruned.
+    pub async fn get_batch_outcomes<PortfolioOutcome, ProductOutcome>(
+        &self,
+        as_of: DateTime,
+    ) -> Result<
+        (
+            Map<PortfolioId, PortfolioOutcome>,
+            Map<ProductId, ProductOutcome>,
+        ),
+        sqlx::Error,
+    >
+    where
+        PortfolioOutcome: serde::de::DeserializeOwned + Send + Unpin + 'static,
+        ProductOutcome: serde::de::DeserializeOwned + Send + Unpin + 'static,
+    {
+        let mut reader = self.acquire_reader().await?;
+
+        let portfolios: Vec<(PortfolioId, sqlx::types::Json<PortfolioOutcome>)> = sqlx::query_as(
+            r#"
+                select
+                    portfolio_outcome.portfolio_id,
+                    json(portfolio_outcome.value)
+                from
+                    portfolio_outcome
+                join
+                    portfolio
+                    on
+                        portfolio_outcome.portfolio_id = portfolio.id
+                where
+                    portfolio_outcome.valid_from = $1
+                and
+                    portfolio.market_id = $2
+                "#,
+        )
+        .bind(as_of)
+        .bind(self.market_id.as_str())
+        .fetch_all(&mut *reader)
+        .timed("get_batch_outcomes.portfolios", self.slow_query_threshold)
+        .await?;
+
+        let products: Vec<(ProductId, sqlx::types::Json<ProductOutcome>)> = sqlx::query_as(
+            r#"
+            select
+                product_outcome.product_id,
+                json(product_outcome.value)
+            from
+                product_outcome
+            join
+                product
+                on
+                    product_outcome.product_id = product.id
+            where
+                product_outcome.valid_from = $1
+            and
+                product.market_id = $2
+            "#,
+        )
+        .bind(as_of)
+        .bind(self.market_id.as_str())
+        .fetch_all(&mut *reader)
+        .timed("get_batch_outcomes.products", self.slow_query_threshold)
+        .await?;
+
+        Ok((
+            portfolios
+                .into_iter()
+                .map(|(portfolio_id, outcome)| (portfolio_id, outcome.0))
+                .collect(),
+            products
+                .into_iter()
+                .map(|(product_id, outcome)| (product_id, outcome.0))
+                .collect(),
+        ))
+    }
+
+    /// Execute a batch auction at `timestamp`, recording its outcomes and
+    /// how it was solved
+    async fn execute_batch<T>(
+        &self,
+        timestamp: DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<DateTime>, T::State), T::Error>, sqlx::Error>
+    where
+        T: Solver<DemandId, PortfolioId, ProductId>,
+        T::PortfolioOutcome: serde::Serialize,
+        T::ProductOutcome: serde::Serialize,
+    {
+        let (demands, portfolios, expires) = self.gather_batch(timestamp).await?;
+        let inputs = self
+            .record_batch_inputs
+            .then(|| Auction::<Self>::from_parts(demands.clone(), portfolios.clone()));
+
         // TODO: we may wish to filter the portfolios we include for administrative reasons./
         // what is the best way to do this? Perhaps we say this is (one of) the responsibilities
         // of the state, e.g. contains a HashSet of the "suspended" portfolio ids, and our solver is
         // responsible.... I actually like this a lot.
 
-        let outcome = solver.solve(demands, portfolios, state).await;
+        let (outcome, telemetry) = solver
+            .solve_with_telemetry(demands, portfolios, state)
+            .await;
 
-        match outcome {
+        // However the solve ended, it is recorded alongside any outcomes
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let (result, error) = match outcome {
             Ok((portfolio_outcomes, product_outcomes)) => {
+                let next_state = solver.next_state(&portfolio_outcomes);
                 let portfolio_outcomes = sqlx::types::Json(portfolio_outcomes);
                 let product_outcomes = sqlx::types::Json(product_outcomes);
                 sqlx::query!(
@@ -98,17 +358,328 @@ where
                         as_of = $1,
                         portfolio_outcomes = jsonb($2),
                         product_outcomes = jsonb($3)
+                    where
+                        market_id = $4
                     "#,
                     timestamp,
                     portfolio_outcomes,
-                    product_outcomes
+                    product_outcomes,
+                    self.market_id,
                 )
-                .execute(&self.writer)
+                .execute(&mut *tx)
+                .timed("run_batch", self.slow_query_threshold)
                 .await?;
-                Ok(Ok(expires))
+                (Ok((expires, next_state)), None)
+            }
+            Err(error) => {
+                let message = error.to_string();
+                (Err(error), Some(message))
+            }
+        };
+
+        let settings = sqlx::types::Json(&telemetry.settings);
+        let stats = sqlx::types::Json(&telemetry.stats);
+        sqlx::query!(
+            r#"
+            insert into batch_run (
+                market_id, as_of, backend, settings, status, stats, error
+            )
+            values ($1, $2, $3, jsonb($4), $5, jsonb($6), $7)
+            on conflict (market_id, as_of) do update set
+                backend = excluded.backend,
+                settings = excluded.settings,
+                status = excluded.status,
+                stats = excluded.stats,
+                error = excluded.error
+            "#,
+            self.market_id,
+            timestamp,
+            telemetry.backend,
+            settings,
+            telemetry.status,
+            stats,
+            error,
+        )
+        .execute(&mut *tx)
+        .timed("run_batch.telemetry", self.slow_query_threshold)
+        .await?;
+
+        if let Some(inputs) = inputs {
+            sqlx::query(
+                r#"
+                insert into batch_input (market_id, as_of, inputs)
+                values ($1, $2, $3)
+                on conflict (market_id, as_of) do update set
+                    inputs = excluded.inputs
+                "#,
+            )
+            .bind(self.market_id.as_str())
+            .bind(timestamp)
+            .bind(sqlx::types::Json(inputs))
+            .execute(&mut *tx)
+            .timed("run_batch.inputs", self.slow_query_threshold)
+            .await?;
+        }
+
+        tx.commit()
+            .timed("run_batch.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Record a failed attempt to run the batch at `timestamp`
+    async fn record_attempt(
+        &self,
+        timestamp: DateTime,
+        category: BatchFailure,
+        message: &str,
+    ) -> Result<(), sqlx::Error> {
+        let category = category.as_str();
+        sqlx::query!(
+            r#"
+            insert into batch_attempt (market_id, as_of, category, message)
+            values ($1, $2, $3, $4)
+            on conflict (market_id, as_of) do update set
+                category = excluded.category,
+                message = excluded.message
+            "#,
+            self.market_id,
+            timestamp,
+            category,
+            message,
+        )
+        .execute(&mut *self.acquire_writer().await?)
+        .timed("run_batch.attempt", self.slow_query_threshold)
+        .await?;
+        Ok(())
+    }
+}
+
+impl<T: Solver<DemandId, PortfolioId, ProductId>> BatchRepository<T> for Db
+where
+    T: Send,
+    T::Error: Send,
+    T::State: Send,
+    T::PortfolioOutcome: Unpin + Send + serde::Serialize + serde::de::DeserializeOwned,
+    T::ProductOutcome: Unpin + Send + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn run_batch_with_state(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<Result<(Option<Self::DateTime>, T::State), BatchError<T::Error>>, Self::Error> {
+        // Every handle to the database shares the single writer, so batches
+        // are serialized across the handles of this process
+        let Some(_guard) = BatchGuard::acquire(&self.running_batches, &self.market_id) else {
+            return Ok(Err(BatchError::InProgress));
+        };
+
+        let result = self.execute_batch(timestamp, solver, state).await;
+
+        let failure = match &result {
+            Ok(Ok(_)) => None,
+            Ok(Err(error)) => Some((BatchFailure::Solver, error.to_string())),
+            Err(error) => Some((BatchFailure::Database, error.to_string())),
+        };
+        if let Some((category, message)) = failure {
+            // The database may itself be the cause of the failure, so the
+            // attempt is recorded on a best-effort basis
+            if let Err(error) = self.record_attempt(timestamp, category, &message).await {
+                event!(
+                    Level::ERROR,
+                    err = error.to_string(),
+                    "failed to record batch attempt"
+                );
             }
-            Err(error) => Ok(Err(error)),
         }
+
+        result.map(|outcome| outcome.map_err(BatchError::Solver))
+    }
+
+    async fn preview_batch(
+        &self,
+        timestamp: Self::DateTime,
+        solver: T,
+        state: T::State,
+    ) -> Result<
+        Result<BatchPreview<Self, T::PortfolioOutcome, T::ProductOutcome>, T::Error>,
+        Self::Error,
+    > {
+        let (demands, portfolios, expires) = self.gather_batch(timestamp).await?;
+
+        Ok(solver
+            .solve(demands, portfolios, state)
+            .await
+            .map(|(portfolios, products)| BatchPreview {
+                as_of: timestamp,
+                expires,
+                portfolios,
+                products,
+            }))
+    }
+
+    async fn get_latest_batch(&self) -> Result<Option<BatchMetadata<Self>>, Self::Error> {
+        // The batch table consists of a single row per market, which is
+        // overwritten by each batch auction (and initialized without any outcomes).
+        let row = sqlx::query!(
+            r#"
+                select
+                    as_of as "as_of!: crate::types::DateTime",
+                    (select count(*) from json_each(portfolio_outcomes)) as "portfolios!: i64",
+                    (select count(*) from json_each(product_outcomes)) as "products!: i64"
+                from
+                    batch
+                where
+                    market_id = $1
+            "#,
+            self.market_id
+        )
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_latest_batch", self.slow_query_threshold)
+        .await?;
+
+        Ok(row.map(|row| BatchMetadata {
+            as_of: row.as_of,
+            portfolios: row.portfolios as u32,
+            products: row.products as u32,
+        }))
+    }
+
+    async fn get_batch_runs(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchRun, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows = sqlx::query_as!(
+            BatchRunRow,
+            r#"
+                select
+                    as_of as "as_of!: crate::types::DateTime",
+                    backend,
+                    json(settings) as "settings!: sqlx::types::Json<Map<String, String>>",
+                    status,
+                    json(stats) as "stats!: sqlx::types::Json<Map<String, f64>>",
+                    error
+                from
+                    batch_run
+                where
+                    market_id = $1
+                and
+                    as_of >= coalesce($2, '')
+                and
+                    as_of < coalesce($3, x'')
+                order by
+                    as_of desc
+                limit $4
+            "#,
+            self.market_id,
+            query.after,
+            query.before,
+            limit_p1,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_batch_runs", self.slow_query_threshold)
+        .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.as_of),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_batch_attempts(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BatchAttempt, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows = sqlx::query_as!(
+            BatchAttemptRow,
+            r#"
+                select
+                    as_of as "as_of!: crate::types::DateTime",
+                    category,
+                    message
+                from
+                    batch_attempt
+                where
+                    market_id = $1
+                and
+                    as_of >= coalesce($2, '')
+                and
+                    as_of < coalesce($3, x'')
+                order by
+                    as_of desc
+                limit $4
+            "#,
+            self.market_id,
+            query.after,
+            query.before,
+            limit_p1,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_batch_attempts", self.slow_query_threshold)
+        .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.as_of),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ValueRecord {
+                    valid_from: row.as_of,
+                    valid_until: None,
+                    value: BatchAttempt {
+                        category: row
+                            .category
+                            .parse()
+                            .map_err(|error: String| sqlx::Error::Decode(error.into()))?,
+                        message: row.message,
+                    },
+                    actor: None,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()
+            .map(|results| DateTimeRangeResponse { results, more })
+    }
+
+    async fn get_batch_inputs(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Auction<Self>>, Self::Error> {
+        let inputs: Option<sqlx::types::Json<Auction<Self>>> = sqlx::query_scalar(
+            "select inputs from batch_input where market_id = $1 and as_of = $2",
+        )
+        .bind(self.market_id.as_str())
+        .bind(as_of)
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_batch_inputs", self.slow_query_threshold)
+        .await?;
+        Ok(inputs.map(|inputs| inputs.0))
     }
 
     /// Get the portfolio's outcomes
@@ -134,10 +705,16 @@ where
                     portfolio_outcome
                 where
                     portfolio_id = $1
+                -- An absent bound is replaced by one beyond every datetime
+                -- (which is text, sorting after '' and before any blob), so
+                -- that the page is a range of the primary key rather than a
+                -- scan of every newer outcome
+                and
+                    valid_from >= coalesce($2, '')
                 and
-                    ($2 is null or valid_from >= $2)
+                    valid_from < coalesce($3, x'')
                 and
-                    ($3 is null or valid_until is null or valid_until < $3)
+                    exists (select 1 from portfolio where id = $1 and market_id = $5)
                 group by
                     valid_from
                 order by
@@ -148,14 +725,18 @@ where
             query.after,
             query.before,
             limit_p1,
+            self.market_id,
         )
-        .fetch_all(&self.reader)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio_outcomes", self.slow_query_threshold)
         .await?;
 
         let more = if rows.len() == limit + 1 {
-            let extra = rows.pop().unwrap();
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
             Some(DateTimeRangeQuery {
-                before: Some(extra.valid_from),
+                before: rows.last().map(|row| row.valid_from),
                 after: query.after,
             })
         } else {
@@ -168,6 +749,133 @@ where
         })
     }
 
+    async fn get_bidder_outcomes(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<BidderOutcome<Self::ProductId>, Self::DateTime>, Self::Error>
+    {
+        // The page is bounded by the batches, rather than by the rows of each
+        // batch's trades
+        let rows: Vec<BidderTradeRow> = sqlx::query_as(
+            r#"
+            with batch_cte as (
+                select distinct
+                    portfolio_outcome.valid_from
+                from
+                    portfolio_outcome
+                join
+                    portfolio
+                    on
+                        portfolio_outcome.portfolio_id = portfolio.id
+                where
+                    portfolio.bidder_id = $1
+                and
+                    portfolio.market_id = $2
+                and
+                    portfolio_outcome.valid_from >= coalesce($3, '')
+                and
+                    portfolio_outcome.valid_from < coalesce($4, x'')
+                order by
+                    portfolio_outcome.valid_from desc
+                limit $5
+            )
+            select
+                portfolio_outcome.valid_from,
+                max(portfolio_outcome.valid_until) as valid_until,
+                basis_view.product_id,
+                total(portfolio_outcome.value ->> '$.rate' * basis_view.weight) as rate,
+                total(
+                    portfolio_outcome.value ->> '$.rate' * basis_view.weight
+                    * coalesce(product_outcome.value ->> '$.price', 0.0)
+                ) as payment
+            from
+                batch_cte
+            join
+                portfolio_outcome
+                on
+                    portfolio_outcome.valid_from = batch_cte.valid_from
+            join
+                portfolio
+                on
+                    portfolio_outcome.portfolio_id = portfolio.id
+            left join
+                basis_view
+                on
+                    portfolio_outcome.portfolio_id = basis_view.portfolio_id
+                    and
+                    basis_view.valid_from <= portfolio_outcome.valid_from
+                    and
+                    (portfolio_outcome.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+            left join
+                product_outcome
+                on
+                    product_outcome.product_id = basis_view.product_id
+                    and
+                    product_outcome.valid_from = portfolio_outcome.valid_from
+            where
+                portfolio.bidder_id = $1
+            and
+                portfolio.market_id = $2
+            group by
+                portfolio_outcome.valid_from,
+                basis_view.product_id
+            order by
+                portfolio_outcome.valid_from desc,
+                basis_view.product_id
+            "#,
+        )
+        .bind(bidder_id)
+        .bind(self.market_id.as_str())
+        .bind(query.after)
+        .bind(query.before)
+        .bind((limit + 1) as i64)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_bidder_outcomes", self.slow_query_threshold)
+        .await?;
+
+        let mut results: Vec<ValueRecord<DateTime, BidderOutcome<ProductId>>> = Vec::new();
+        for row in rows {
+            let record = match results.last_mut() {
+                Some(record) if record.valid_from == row.valid_from => record,
+                _ => {
+                    results.push(ValueRecord {
+                        valid_from: row.valid_from,
+                        valid_until: row.valid_until,
+                        value: BidderOutcome::default(),
+                        actor: None,
+                    });
+                    results.last_mut().unwrap()
+                }
+            };
+            if let Some(product_id) = row.product_id {
+                record.value.trades.insert(
+                    product_id,
+                    BidderTrade {
+                        rate: row.rate,
+                        payment: row.payment,
+                    },
+                );
+                record.value.payment += row.payment;
+            }
+        }
+
+        let more = if results.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra batch, just below the oldest batch of this page.
+            results.pop();
+            Some(DateTimeRangeQuery {
+                before: results.last().map(|record| record.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse { results, more })
+    }
+
     /// Get the product's outcomes
     ///
     /// This returns a list of outcomes, each corresponding to a specific point in time.
@@ -191,10 +899,16 @@ where
                     product_outcome
                 where
                     product_id = $1
+                -- An absent bound is replaced by one beyond every datetime
+                -- (which is text, sorting after '' and before any blob), so
+                -- that the page is a range of the primary key rather than a
+                -- scan of every newer outcome
                 and
-                    ($2 is null or valid_from >= $2)
+                    valid_from >= coalesce($2, '')
                 and
-                    ($3 is null or valid_until is null or valid_until < $3)
+                    valid_from < coalesce($3, x'')
+                and
+                    exists (select 1 from product where id = $1 and market_id = $5)
                 group by
                     valid_from
                 order by
@@ -205,14 +919,100 @@ where
             query.after,
             query.before,
             limit_p1,
+            self.market_id,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_product_outcomes", self.slow_query_threshold)
+        .await?;
+
+        let more = if rows.len() == limit + 1 {
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
+            Some(DateTimeRangeQuery {
+                before: rows.last().map(|row| row.valid_from),
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    fn stream_portfolio_outcomes(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::PortfolioOutcome>, sqlx::Error>> + Send
+    {
+        stream_pages(query, move |query| {
+            <Self as BatchRepository<T>>::get_portfolio_outcomes(
+                self,
+                portfolio_id,
+                query,
+                STREAM_PAGE_SIZE,
+            )
+        })
+    }
+
+    fn stream_product_outcomes(
+        &self,
+        product_id: Self::ProductId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, T::ProductOutcome>, sqlx::Error>> + Send
+    {
+        stream_pages(query, move |query| {
+            <Self as BatchRepository<T>>::get_product_outcomes(
+                self,
+                product_id,
+                query,
+                STREAM_PAGE_SIZE,
+            )
+        })
+    }
+
+    async fn get_product_summary(
+        &self,
+        product_id: Self::ProductId,
+        interval: PriceInterval,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<PriceSummary, Self::DateTime>, Self::Error> {
+        let (format, span) = match interval {
+            PriceInterval::Hour => ("%Y-%m-%d %H:00:00", "+1 hour"),
+            PriceInterval::Day => ("%Y-%m-%d 00:00:00", "+1 day"),
+        };
+
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows = sqlx::query_file_as!(
+            ValueRow::<PriceSummary>,
+            "queries/product_summary.sql",
+            product_id,
+            query.after,
+            query.before,
+            as_of,
+            time_unit,
+            format,
+            span,
+            limit_p1,
+            self.market_id,
         )
-        .fetch_all(&self.reader)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_product_summary", self.slow_query_threshold)
         .await?;
 
+        // Batches are bucketed by their start, so we page on the end of the
+        // extra bucket to ensure all of its batches are in the next page.
         let more = if rows.len() == limit + 1 {
             let extra = rows.pop().unwrap();
             Some(DateTimeRangeQuery {
-                before: Some(extra.valid_from),
+                before: extra.valid_until,
                 after: query.after,
             })
         } else {
@@ -224,4 +1024,70 @@ where
             more,
         })
     }
+
+    async fn get_market_statistics(
+        &self,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<MarketStatistics<Self>, Self::Error> {
+        let until = query.before.map_or(as_of, |before| before.min(as_of));
+
+        let (outcomes, bids, positions) = self
+            .snapshot(|db| {
+                Box::pin(async move {
+                    let outcomes = async {
+                        sqlx::query_file_as!(
+                            OutcomeStatisticsRow,
+                            "queries/outcome_statistics.sql",
+                            query.after,
+                            until,
+                            as_of,
+                            time_unit,
+                            db.market_id,
+                        )
+                        .fetch_all(&mut *db.acquire_reader().await?)
+                        .timed("get_market_statistics.outcomes", db.slow_query_threshold)
+                        .await
+                    };
+
+                    let bids = async {
+                        sqlx::query_file_as!(
+                            BidStatisticsRow,
+                            "queries/bid_statistics.sql",
+                            as_of,
+                            db.market_id
+                        )
+                        .fetch_all(&mut *db.acquire_reader().await?)
+                        .timed("get_market_statistics.bids", db.slow_query_threshold)
+                        .await
+                    };
+
+                    let positions = db.net_positions(as_of, time_unit);
+
+                    try_join!(outcomes, bids, positions)
+                })
+            })
+            .await?;
+
+        let mut products: Map<ProductId, ProductStatistics<DateTime>> = Map::default();
+        for row in outcomes {
+            let entry = products.entry(row.product_id).or_default();
+            entry.price = row.price;
+            entry.cleared = row.cleared;
+            entry.volume = row.volume;
+        }
+        for row in bids {
+            let entry = products.entry(row.product_id).or_default();
+            entry.portfolios = row.portfolios as u32;
+            entry.demands = row.demands as u32;
+        }
+        for ((_, product_id), position) in positions {
+            if position > 0.0 {
+                products.entry(product_id).or_default().open_interest += position;
+            }
+        }
+
+        Ok(MarketStatistics { as_of, products })
+    }
 }
@@ -1,16 +1,20 @@
+use super::{STREAM_PAGE_SIZE, stream_pages};
 use crate::{
     Db,
-    types::{BidderId, DateTime, DemandId, DemandRow, PortfolioId, ValueRow},
+    instrument::Timed as _,
+    types::{BidderId, DateTime, DemandId, DemandRow, HistoryRow, PortfolioId},
 };
 use fts_core::{
     models::{
-        DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandCurveDto, DemandRecord, Sum,
+        Actor, DateTimeRangeQuery, DateTimeRangeResponse, DemandCurve, DemandCurveDto, DemandQuery,
+        DemandQueryResponse, DemandRecord, DemandSearch, DemandSearchResponse, Map, Sum, Tombstone,
         ValueRecord,
     },
     ports::DemandRepository,
 };
+use futures_util::Stream;
 
-impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
+impl<DemandData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
     DemandRepository<DemandData> for Db
 {
     async fn get_demand_bidder_id(
@@ -25,18 +29,140 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
                 demand
             where
                 id = $1
+            and
+                market_id = $2
+            and
+                purged_at is null
             "#,
-            demand_id
+            demand_id,
+            self.market_id,
         )
-        .fetch_optional(&self.reader)
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_demand_bidder_id", self.slow_query_threshold)
         .await
     }
 
+    async fn get_demand_bidder_ids(
+        &self,
+        demand_ids: &[Self::DemandId],
+    ) -> Result<Map<Self::DemandId, Self::BidderId>, Self::Error> {
+        if demand_ids.is_empty() {
+            return Ok(Map::default());
+        }
+
+        let demand_ids = sqlx::types::Json(demand_ids);
+        let rows = sqlx::query!(
+            r#"
+            select
+                id as "id!: DemandId",
+                bidder_id as "bidder_id!: BidderId"
+            from
+                demand
+            where
+                id in (select value from json_each($1))
+            and
+                market_id = $2
+            and
+                purged_at is null
+            "#,
+            demand_ids,
+            self.market_id,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_demand_bidder_ids", self.slow_query_threshold)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.bidder_id))
+            .collect())
+    }
+
     async fn query_demand(
         &self,
         bidder_ids: &[Self::BidderId],
+        query: DemandQuery<Self::DemandId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<DemandQueryResponse<Self, DemandData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(DemandQueryResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let bidder_ids = sqlx::types::Json(bidder_ids);
+        // +1 to check if there are more results
+        let fetch = (limit + 1) as i64;
+        let mut rows = sqlx::query_as!(
+            DemandRow,
+            r#"
+            select
+                demand.id as "id!: DemandId",
+                as_of as "valid_from!: DateTime",
+                null as "valid_until?: DateTime",
+                bidder_id as "bidder_id!: BidderId",
+                json(app_data) as "app_data!: sqlx::types::Json<DemandData>",
+                case when $7 then json(curve_data) end as "curve_data?: sqlx::types::Json<DemandCurveDto>",
+                null as "portfolios?: sqlx::types::Json<Sum<PortfolioId>>"
+            from
+                demand
+            join
+                json_each($1) as bidder_ids
+            on
+                demand.bidder_id = bidder_ids.atom
+            where
+                market_id = $2
+            and
+                purged_at is null
+            and
+                (not $3 or curve_data is not null)
+            and
+                ($4 is null or as_of >= $4)
+            and
+                ($5 is null or demand.id > $5)
+            order by
+                demand.id
+            limit $6
+            "#,
+            bidder_ids,
+            self.market_id,
+            query.has_curve,
+            query.touched_since,
+            query.after,
+            fetch,
+            query.include_curve,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("query_demand", self.slow_query_threshold)
+        .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(DemandQuery {
+                has_curve: query.has_curve,
+                include_curve: query.include_curve,
+                touched_since: query.touched_since,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(DemandQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn query_demand_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
     ) -> Result<Vec<DemandRecord<Self, DemandData>>, Self::Error> {
-        if bidder_ids.len() == 0 {
+        if bidder_ids.is_empty() {
             Ok(Vec::new())
         } else {
             let bidder_ids = sqlx::types::Json(bidder_ids);
@@ -58,26 +184,127 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
                 on
                     demand.bidder_id = bidder_ids.atom
                 where
+                    market_id = $3
+                and
                     curve_data is not null
+                and
+                    exists (
+                        select
+                            1
+                        from
+                            portfolio_demand
+                        join
+                            portfolio_product
+                        using
+                            (portfolio_id)
+                        where
+                            portfolio_demand.demand_id = demand.id
+                        and
+                            portfolio_demand.valid_until is null
+                        and
+                            portfolio_product.product_id = $2
+                        and
+                            portfolio_product.valid_until is null
+                    )
                 "#,
-                bidder_ids
+                bidder_ids,
+                product_id,
+                self.market_id,
             )
-            .fetch_all(&self.reader)
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed("query_demand_by_product", self.slow_query_threshold)
             .await?;
 
             Ok(query.into_iter().map(Into::into).collect())
         }
     }
 
+    async fn search_demands(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: DemandSearch<Self::DemandId>,
+        limit: usize,
+    ) -> Result<DemandSearchResponse<Self, DemandData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(DemandSearchResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            r#"select
+                demand.id,
+                demand.as_of as "valid_from",
+                null as "valid_until",
+                demand.bidder_id,
+                json(demand.app_data) as "app_data",
+                json(demand.curve_data) as "curve_data",
+                null as "portfolios"
+            from
+                demand
+            join
+                json_each("#,
+        );
+        query_builder
+            .push_bind(sqlx::types::Json(bidder_ids))
+            .push(
+                r#") as bidder_ids
+            on
+                demand.bidder_id = bidder_ids.atom
+            where
+                demand.curve_data is not null
+            and
+                demand.market_id = "#,
+            )
+            .push_bind(self.market_id.as_str());
+
+        if let Some(after) = query.after {
+            query_builder.push(" and demand.id > ").push_bind(after);
+        }
+
+        crate::filter::push_filters(&mut query_builder, "demand", &query.filters);
+
+        query_builder
+            .push(" order by demand.id limit ")
+            // +1 to check if there are more results
+            .push_bind((limit + 1) as i64);
+
+        let mut rows: Vec<DemandRow<DemandData>> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed("search_demands", self.slow_query_threshold)
+            .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(DemandSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(DemandSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
     async fn create_demand(
         &self,
         demand_id: Self::DemandId,
         bidder_id: Self::BidderId,
         app_data: DemandData,
         curve_data: DemandCurve,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<DemandRecord<Self, DemandData>, Self::Error> {
         let app_data = sqlx::types::Json(app_data);
+        let actor = actor.as_str();
         // Important: If curve_data is None, we insert NULL into the database
         // Else, this propagates into a [0] value in the JSONB column
         let curve_data = curve_data.to_option().map(|x| sqlx::types::Json(x));
@@ -85,9 +312,9 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
             DemandRow::<DemandData>,
             r#"
             insert into
-                demand (id, as_of, bidder_id, app_data, curve_data)
+                demand (id, as_of, bidder_id, app_data, curve_data, actor, market_id)
             values
-                ($1, $2, $3, jsonb($4), jsonb($5))
+                ($1, $2, $3, jsonb($4), jsonb($5), $6, $7)
             returning
                 id as "id!: DemandId",
                 as_of as "valid_from!: DateTime",
@@ -102,8 +329,11 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
             bidder_id,
             app_data,
             curve_data,
+            actor,
+            self.market_id,
         )
-        .fetch_one(&self.writer)
+        .fetch_one(&mut *self.acquire_writer().await?)
+        .timed("create_demand", self.slow_query_threshold)
         .await?;
         Ok(demand.into())
     }
@@ -112,9 +342,11 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
         &self,
         demand_id: Self::DemandId,
         curve_data: DemandCurve,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
         let curve_data = curve_data.to_option().map(|x| sqlx::types::Json(x));
+        let actor = actor.as_str();
         let demand = sqlx::query_as!(
             DemandRow::<DemandData>,
             r#"
@@ -122,9 +354,12 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
                 demand
             set
                 as_of = $2,
-                curve_data = jsonb($3)
+                curve_data = jsonb($3),
+                actor = $4
             where
                 id = $1
+            and
+                market_id = $5
             returning
                 id as "id!: DemandId",
                 as_of as "valid_from!: DateTime",
@@ -137,8 +372,11 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
             demand_id,
             as_of,
             curve_data,
+            actor,
+            self.market_id,
         )
-        .fetch_optional(&self.writer)
+        .fetch_optional(&mut *self.acquire_writer().await?)
+        .timed("update_demand", self.slow_query_threshold)
         .await?
         .map(Into::into);
         Ok(demand)
@@ -149,10 +387,16 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
         demand_id: Self::DemandId,
         as_of: Self::DateTime,
     ) -> Result<Option<DemandRecord<Self, DemandData>>, Self::Error> {
-        let query =
-            sqlx::query_file_as!(DemandRow, "queries/get_demand_by_id.sql", demand_id, as_of)
-                .fetch_optional(&self.reader)
-                .await?;
+        let query = sqlx::query_file_as!(
+            DemandRow,
+            "queries/get_demand_by_id.sql",
+            demand_id,
+            as_of,
+            self.market_id
+        )
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_demand", self.slow_query_threshold)
+        .await?;
 
         Ok(query.map(Into::into))
     }
@@ -165,12 +409,13 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
     ) -> Result<DateTimeRangeResponse<DemandCurve, Self::DateTime>, Self::Error> {
         let limit_p1 = (limit + 1) as i64;
         let mut rows = sqlx::query_as!(
-            ValueRow::<DemandCurveDto>,
+            HistoryRow::<DemandCurveDto>,
             r#"
                 select
                     valid_from as "valid_from!: DateTime",
                     valid_until as "valid_until?: DateTime",
-                    json(coalesce(value, "null")) as "value!: sqlx::types::Json<DemandCurveDto>"
+                    json(coalesce(value, "null")) as "value!: sqlx::types::Json<DemandCurveDto>",
+                    actor
                 from
                     curve_data
                 where
@@ -178,9 +423,11 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
                 and
                     ($2 is null or valid_from >= $2)
                 and
-                    ($3 is null or valid_until is null or valid_until < $3)
+                    ($3 is null or valid_from < $3)
                 and
                     value is not null
+                and
+                    exists (select 1 from demand where id = $1 and market_id = $5)
                 order by
                     valid_from desc
                 limit $4
@@ -189,16 +436,20 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
             query.after,
             query.before,
             limit_p1, // +1 to check if there are more results
+            self.market_id,
         )
-        .fetch_all(&self.reader)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_demand_curve_history", self.slow_query_threshold)
         .await?;
 
         // We paginate by adding 1 to the limit, popping the result of, and
         // using it to adjust the query object
         let more = if rows.len() == limit + 1 {
-            let extra = rows.pop().unwrap();
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
             Some(DateTimeRangeQuery {
-                before: Some(extra.valid_from),
+                before: rows.last().map(|row| row.valid_from),
                 after: query.after,
             })
         } else {
@@ -209,20 +460,122 @@ impl<DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
             results: rows
                 .into_iter()
                 .map(
-                    |ValueRow {
+                    |HistoryRow {
                          valid_from,
                          valid_until,
                          value,
+                         actor,
                      }| ValueRecord {
                         valid_from,
                         valid_until,
                         value: unsafe { DemandCurve::new_unchecked(value.0) },
                         // SAFETY: this is only being called when deserializing a SQL query, and we ensure curves
                         //         are valid going into the database.
+                        actor: actor.parse().ok(),
                     },
                 )
                 .collect(),
             more,
         })
     }
+
+    fn stream_demand_curve_history(
+        &self,
+        demand_id: Self::DemandId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, DemandCurve>, sqlx::Error>> + Send {
+        stream_pages(query, move |query| {
+            <Self as DemandRepository<DemandData>>::get_demand_curve_history(
+                self,
+                demand_id,
+                query,
+                STREAM_PAGE_SIZE,
+            )
+        })
+    }
+
+    async fn purge_demand(
+        &self,
+        demand_id: Self::DemandId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::DemandId>>, Self::Error> {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        // Erase the data of the demand itself, leaving the row as a tombstone.
+        // Note that the update trigger records a (null) curve, which is erased
+        // along with the rest of the history below.
+        let bidder_id = sqlx::query_scalar!(
+            r#"
+            update
+                demand
+            set
+                as_of = $2,
+                app_data = jsonb('null'),
+                curve_data = null,
+                actor = 'operator',
+                purged_at = $2
+            where
+                id = $1
+            and
+                market_id = $3
+            and
+                purged_at is null
+            returning
+                bidder_id as "bidder_id!: BidderId"
+            "#,
+            demand_id,
+            as_of,
+            self.market_id,
+        )
+        .fetch_optional(&mut *tx)
+        .timed("purge_demand.tombstone", self.slow_query_threshold)
+        .await?;
+
+        let Some(bidder_id) = bidder_id else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            delete from
+                curve_data
+            where
+                demand_id = $1
+            "#,
+            demand_id
+        )
+        .execute(&mut *tx)
+        .timed("purge_demand.curve_data", self.slow_query_threshold)
+        .await?;
+
+        // The curve is erased from the recorded inputs of past batches too
+        sqlx::query(
+            r#"
+            update
+                batch_input
+            set
+                inputs = json_remove(inputs, '$.demand_curves."' || $1 || '"')
+            where
+                market_id = $2
+            and
+                json_type(inputs, '$.demand_curves."' || $1 || '"') is not null
+            "#,
+        )
+        .bind(demand_id)
+        .bind(self.market_id.as_str())
+        .execute(&mut *tx)
+        .timed("purge_demand.batch_input", self.slow_query_threshold)
+        .await?;
+
+        tx.commit()
+            .timed("purge_demand.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Some(Tombstone {
+            id: demand_id,
+            bidder_id,
+            purged_at: as_of,
+        }))
+    }
 }
@@ -0,0 +1,183 @@
+use crate::types::{PortfolioId, ProductId};
+use crate::{Db, MIGRATOR, instrument::Timed as _};
+use fts_core::{
+    models::{ConsistencyRecord, MigrationStatus, SchemaMigration},
+    ports::HealthRepository,
+};
+use std::path::Path;
+
+impl Db {
+    /// The status of the database's schema migrations: those applied to it,
+    /// those pending, and the resulting version of its schema.
+    ///
+    /// This does not apply any migration, so it may inspect a database
+    /// obtained from [`Db::connect`] before it is opened.
+    pub async fn migration_status(&self) -> Result<MigrationStatus, sqlx::Error> {
+        let mut conn = self.acquire_reader().await?;
+
+        // The table of applied migrations is created by the first migration
+        let migrated: bool = sqlx::query_scalar(
+            "select count(*) > 0 from sqlite_schema where type = 'table' and name = '_sqlx_migrations'",
+        )
+        .fetch_one(&mut *conn)
+        .timed("migration_status.migrated", self.slow_query_threshold)
+        .await?;
+
+        let applied: Vec<(i64, String)> = if migrated {
+            sqlx::query_as("select version, description from _sqlx_migrations order by version")
+                .fetch_all(&mut *conn)
+                .timed("migration_status.applied", self.slow_query_threshold)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(migration_status(applied))
+    }
+
+    /// Compare the migrations applied to the database with those known to
+    /// this crate, returning the problems found (none if they agree): those
+    /// which failed part-way, those applied by a newer version of this crate,
+    /// and those whose scripts have changed since they were applied. Any of
+    /// these prevents the database from being migrated.
+    pub async fn verify_migrations(&self) -> Result<Vec<String>, sqlx::Error> {
+        let status = self.migration_status().await?;
+        if status.applied == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.acquire_reader().await?;
+        let applied: Vec<(i64, String, bool, Vec<u8>)> = sqlx::query_as(
+            "select version, description, success, checksum from _sqlx_migrations order by version",
+        )
+        .fetch_all(&mut *conn)
+        .timed("verify_migrations", self.slow_query_threshold)
+        .await?;
+
+        let mut problems = Vec::new();
+        for (version, description, success, checksum) in applied {
+            let known = MIGRATOR.iter().find(|migration| {
+                migration.version == version && !migration.migration_type.is_down_migration()
+            });
+            if !success {
+                problems.push(format!(
+                    "migration {version} ({description}) failed part-way"
+                ));
+            } else if let Some(migration) = known {
+                if *migration.checksum != *checksum {
+                    problems.push(format!(
+                        "migration {version} ({description}) has changed since it was applied"
+                    ));
+                }
+            } else {
+                problems.push(format!(
+                    "migration {version} ({description}) was applied by a newer version"
+                ));
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Apply every pending schema migration, returning the resulting status.
+    ///
+    /// This is the step [`Db::open`] takes itself unless `auto_migrate` is
+    /// disabled, so it is typically applied to a database obtained from
+    /// [`Db::connect`].
+    pub async fn migrate(&self) -> Result<MigrationStatus, sqlx::Error> {
+        MIGRATOR
+            .run(&self.writer)
+            .timed("migrate", self.slow_query_threshold)
+            .await?;
+        self.migration_status().await
+    }
+
+    /// Copy the database into a new file at `path`, e.g. to back it up before
+    /// migrating it. The copy is a consistent snapshot of the database, which
+    /// may be taken while it is in use, but fails if the file already exists.
+    pub async fn backup(&self, path: &Path) -> Result<(), sqlx::Error> {
+        sqlx::query("vacuum into $1")
+            .bind(path.to_string_lossy())
+            .execute(&self.writer)
+            .timed("backup", self.slow_query_threshold)
+            .await?;
+        Ok(())
+    }
+
+    /// Check the integrity of the whole database file, returning the problems
+    /// found (none if the database is intact). Replicas are not checked.
+    pub async fn integrity_check(&self) -> Result<Vec<String>, sqlx::Error> {
+        let messages: Vec<String> = sqlx::query_scalar("pragma integrity_check")
+            .fetch_all(&self.reader)
+            .timed("integrity_check", self.slow_query_threshold)
+            .await?;
+        Ok(messages
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect())
+    }
+
+    /// The size in bytes of the pages of the database holding data, i.e.
+    /// excluding the free pages which deleted rows leave until a vacuum.
+    pub async fn used_bytes(&self) -> Result<u64, sqlx::Error> {
+        let used: i64 = sqlx::query_scalar(
+            "select (page_count - freelist_count) * page_size from pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+        )
+        .fetch_one(&self.writer)
+        .timed("used_bytes", self.slow_query_threshold)
+        .await?;
+        Ok(used as u64)
+    }
+}
+
+impl HealthRepository for Db {
+    async fn check_health(&self) -> Result<MigrationStatus, Self::Error> {
+        self.migration_status().await
+    }
+
+    async fn check_consistency(
+        &self,
+        repair: bool,
+    ) -> Result<ConsistencyRecord<ProductId, PortfolioId>, Self::Error> {
+        self.consistency(repair).await
+    }
+}
+
+/// Combine the migrations applied to the database with those known to this
+/// crate, which include any applied by a newer version of it
+fn migration_status(applied: Vec<(i64, String)>) -> MigrationStatus {
+    let version = applied.last().map(|(version, _)| *version);
+
+    let mut migrations: Vec<SchemaMigration> = applied
+        .into_iter()
+        .map(|(version, description)| SchemaMigration {
+            version,
+            description,
+            applied: true,
+        })
+        .collect();
+    let applied = migrations.len();
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+    {
+        if migrations
+            .iter()
+            .all(|applied| applied.version != migration.version)
+        {
+            migrations.push(SchemaMigration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: false,
+            });
+        }
+    }
+    migrations.sort_by_key(|migration| migration.version);
+
+    MigrationStatus {
+        applied: applied as u32,
+        pending: (migrations.len() - applied) as u32,
+        version,
+        migrations,
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    Db,
+    instrument::Timed as _,
+    types::{DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{Actor, ImportDocument, ImportIssue, ImportRecord},
+    ports::ImportRepository,
+};
+use std::collections::HashSet;
+
+impl<DemandData, PortfolioData, ProductData>
+    ImportRepository<DemandData, PortfolioData, ProductData> for Db
+where
+    DemandData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned,
+    PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned,
+    ProductData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned,
+{
+    async fn import(
+        &self,
+        document: ImportDocument<Self, DemandData, PortfolioData, ProductData>,
+        actor: Actor,
+        as_of: Self::DateTime,
+        dry_run: bool,
+    ) -> Result<Result<ImportRecord, Vec<ImportIssue<Self>>>, Self::Error> {
+        let actor = actor.as_str();
+
+        // Validate within the transaction, so that the referenced entities
+        // cannot change before the import completes. Only the entities of this
+        // market may be referenced.
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let product_ids = sqlx::types::Json(document.product_ids().collect::<Vec<_>>());
+        let products: HashSet<ProductId> = sqlx::query_scalar!(
+            r#"select id as "id!: ProductId" from product where id in (select value from json_each($1)) and market_id = $2"#,
+            product_ids,
+            self.market_id,
+        )
+        .fetch_all(&mut *tx)
+        .timed("import.products", self.slow_query_threshold)
+        .await?
+        .into_iter()
+        .collect();
+
+        let demand_ids = sqlx::types::Json(document.demand_ids().collect::<Vec<_>>());
+        let demands: HashSet<DemandId> = sqlx::query_scalar!(
+            r#"select id as "id!: DemandId" from demand where id in (select value from json_each($1)) and market_id = $2"#,
+            demand_ids,
+            self.market_id,
+        )
+        .fetch_all(&mut *tx)
+        .timed("import.demands", self.slow_query_threshold)
+        .await?
+        .into_iter()
+        .collect();
+
+        let portfolio_ids = sqlx::types::Json(document.portfolio_ids().collect::<Vec<_>>());
+        let portfolios: HashSet<PortfolioId> = sqlx::query_scalar!(
+            r#"select id as "id!: PortfolioId" from portfolio where id in (select value from json_each($1)) and market_id = $2"#,
+            portfolio_ids,
+            self.market_id,
+        )
+        .fetch_all(&mut *tx)
+        .timed("import.portfolios", self.slow_query_threshold)
+        .await?
+        .into_iter()
+        .collect();
+
+        let issues = document.validate(&products, &demands, &portfolios);
+        if !issues.is_empty() {
+            tx.rollback().await?;
+            return Ok(Err(issues));
+        }
+
+        let record = ImportRecord {
+            products: document.products.len() as u64,
+            demands: document.demands.len() as u64,
+            portfolios: document.portfolios.len() as u64,
+            dry_run,
+        };
+
+        // The triggers manage the product tree, which is why parents must
+        // precede their children
+        for product in document.products {
+            let app_data = sqlx::types::Json(product.app_data);
+            let (parent_id, parent_ratio) = product
+                .parent
+                .map_or((None, 0.0), |(parent_id, ratio)| (Some(parent_id), ratio));
+            sqlx::query!(
+                r#"
+                insert into
+                    product (id, market_id, as_of, app_data, parent_id, parent_ratio)
+                values
+                    ($1, $2, $3, jsonb($4), $5, $6)
+                "#,
+                product.id,
+                self.market_id,
+                as_of,
+                app_data,
+                parent_id,
+                parent_ratio,
+            )
+            .execute(&mut *tx)
+            .timed("import.insert_product", self.slow_query_threshold)
+            .await?;
+        }
+
+        for demand in document.demands {
+            let app_data = sqlx::types::Json(demand.app_data);
+            let curve_data = demand.curve_data.to_option().map(sqlx::types::Json);
+            sqlx::query!(
+                r#"
+                insert into
+                    demand (id, market_id, as_of, bidder_id, app_data, curve_data, actor)
+                values
+                    ($1, $2, $3, $4, jsonb($5), jsonb($6), $7)
+                "#,
+                demand.id,
+                self.market_id,
+                as_of,
+                demand.bidder_id,
+                app_data,
+                curve_data,
+                actor,
+            )
+            .execute(&mut *tx)
+            .timed("import.insert_demand", self.slow_query_threshold)
+            .await?;
+        }
+
+        for portfolio in document.portfolios {
+            let app_data = sqlx::types::Json(portfolio.app_data);
+            let demand = if portfolio.demand.is_empty() {
+                None
+            } else {
+                Some(sqlx::types::Json(portfolio.demand))
+            };
+            let basis = if portfolio.basis.is_empty() {
+                None
+            } else {
+                Some(sqlx::types::Json(portfolio.basis))
+            };
+            sqlx::query!(
+                r#"
+                insert into
+                    portfolio (id, market_id, as_of, bidder_id, app_data, demand, basis, actor)
+                values
+                    ($1, $2, $3, $4, jsonb($5), jsonb($6), jsonb($7), $8)
+                "#,
+                portfolio.id,
+                self.market_id,
+                as_of,
+                portfolio.bidder_id,
+                app_data,
+                demand,
+                basis,
+                actor,
+            )
+            .execute(&mut *tx)
+            .timed("import.insert_portfolio", self.slow_query_threshold)
+            .await?;
+        }
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit()
+                .timed("import.commit", self.slow_query_threshold)
+                .await?;
+        }
+
+        Ok(Ok(record))
+    }
+}
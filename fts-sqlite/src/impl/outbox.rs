@@ -0,0 +1,79 @@
+use crate::{Db, instrument::Timed as _, types::DateTime};
+use fts_core::{
+    models::{MarketEvent, OutboxEvent},
+    ports::OutboxRepository,
+};
+
+struct OutboxRow {
+    sequence: i64,
+    as_of: DateTime,
+    event: sqlx::types::Json<MarketEvent<Db>>,
+}
+
+impl OutboxRepository for Db {
+    async fn pending_events(&self, limit: usize) -> Result<Vec<OutboxEvent<Self>>, Self::Error> {
+        let limit = limit as i64;
+        let rows = sqlx::query_as!(
+            OutboxRow,
+            r#"
+            select
+                sequence as "sequence!: i64",
+                as_of as "as_of!: DateTime",
+                json(event) as "event!: sqlx::types::Json<MarketEvent<Db>>"
+            from
+                outbox
+            where
+                market_id = $1
+            and
+                delivered_at is null
+            order by
+                sequence
+            limit $2
+            "#,
+            self.market_id,
+            limit,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("pending_events", self.slow_query_threshold)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| OutboxEvent {
+                sequence: row.sequence as u64,
+                as_of: row.as_of,
+                event: row.event.0,
+            })
+            .collect())
+    }
+
+    async fn mark_delivered(
+        &self,
+        sequence: u64,
+        as_of: Self::DateTime,
+    ) -> Result<u64, Self::Error> {
+        let sequence = sequence as i64;
+        let result = sqlx::query!(
+            r#"
+            update
+                outbox
+            set
+                delivered_at = $3
+            where
+                market_id = $1
+            and
+                sequence <= $2
+            and
+                delivered_at is null
+            "#,
+            self.market_id,
+            sequence,
+            as_of,
+        )
+        .execute(&mut *self.acquire_writer().await?)
+        .timed("mark_delivered", self.slow_query_threshold)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -1,13 +1,20 @@
+use super::{STREAM_PAGE_SIZE, stream_pages};
 use crate::{
     Db,
-    types::{BidderId, DateTime, DemandId, PortfolioId, PortfolioRow, ProductId, ValueRow},
+    instrument::Timed as _,
+    types::{BidderId, DateTime, DemandId, HistoryRow, PortfolioId, PortfolioRow, ProductId},
 };
 use fts_core::{
-    models::{Basis, DateTimeRangeQuery, DateTimeRangeResponse, PortfolioRecord, Weights},
+    models::{
+        Actor, Basis, DateTimeRangeQuery, DateTimeRangeResponse, Map, PortfolioQuery,
+        PortfolioQueryResponse, PortfolioRecord, PortfolioSearch, PortfolioSearchResponse,
+        Tombstone, ValueRecord, Weights,
+    },
     ports::PortfolioRepository,
 };
+use futures_util::Stream;
 
-impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwned>
+impl<PortfolioData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
     PortfolioRepository<PortfolioData> for Db
 {
     async fn get_portfolio_bidder_id(
@@ -22,18 +29,188 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                 portfolio
             where
                 id = $1
+            and
+                market_id = $2
+            and
+                purged_at is null
             "#,
-            portfolio_id
+            portfolio_id,
+            self.market_id,
         )
-        .fetch_optional(&self.reader)
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio_bidder_id", self.slow_query_threshold)
         .await
     }
 
+    async fn get_portfolio_bidder_ids(
+        &self,
+        portfolio_ids: &[Self::PortfolioId],
+    ) -> Result<Map<Self::PortfolioId, Self::BidderId>, Self::Error> {
+        if portfolio_ids.is_empty() {
+            return Ok(Map::default());
+        }
+
+        let portfolio_ids = sqlx::types::Json(portfolio_ids);
+        let rows = sqlx::query!(
+            r#"
+            select
+                id as "id!: PortfolioId",
+                bidder_id as "bidder_id!: BidderId"
+            from
+                portfolio
+            where
+                id in (select value from json_each($1))
+            and
+                market_id = $2
+            and
+                purged_at is null
+            "#,
+            portfolio_ids,
+            self.market_id,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio_bidder_ids", self.slow_query_threshold)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.bidder_id))
+            .collect())
+    }
+
     async fn query_portfolio(
         &self,
         bidder_ids: &[Self::BidderId],
+        query: PortfolioQuery<Self::PortfolioId, Self::DateTime>,
+        limit: usize,
+    ) -> Result<PortfolioQueryResponse<Self, PortfolioData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(PortfolioQueryResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let bidder_ids = sqlx::types::Json(bidder_ids);
+        // +1 to check if there are more results
+        let fetch = (limit + 1) as i64;
+        let mut rows = sqlx::query_as!(
+            PortfolioRow,
+            r#"
+            select
+                portfolio.id as "id!: PortfolioId",
+                as_of as "valid_from!: DateTime",
+                null as "valid_until?: DateTime",
+                bidder_id as "bidder_id!: BidderId",
+                json(app_data) as "app_data!: sqlx::types::Json<PortfolioData>",
+                json(demand) as "demand?: sqlx::types::Json<Weights<DemandId>>",
+                json(basis) as "basis?: sqlx::types::Json<Basis<ProductId>>"
+            from
+                portfolio
+            join
+                json_each($1) as bidder_ids
+            on
+                portfolio.bidder_id = bidder_ids.atom
+            where
+                portfolio.market_id = $2
+            and
+                portfolio.purged_at is null
+            and
+                (not $3 or portfolio.demand is not null or portfolio.basis is not null)
+            and
+                (
+                    not $4
+                    or
+                    exists (
+                        select
+                            1
+                        from
+                            portfolio_demand
+                        join
+                            demand
+                        on
+                            demand.id = portfolio_demand.demand_id
+                        where
+                            portfolio_demand.portfolio_id = portfolio.id
+                        and
+                            portfolio_demand.valid_until is null
+                        and
+                            demand.curve_data is not null
+                    )
+                )
+            and
+                ($5 is null or portfolio.as_of >= $5)
+            and
+                ($6 is null or portfolio.id > $6)
+            order by
+                portfolio.id
+            limit $7
+            "#,
+            bidder_ids,
+            self.market_id,
+            query.active_only,
+            query.has_curve,
+            query.touched_since,
+            query.after,
+            fetch,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("query_portfolio", self.slow_query_threshold)
+        .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(PortfolioQuery {
+                active_only: query.active_only,
+                has_curve: query.has_curve,
+                touched_since: query.touched_since,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(PortfolioQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn query_portfolio_with_expanded_products(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        as_of: Self::DateTime,
+    ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        if bidder_ids.is_empty() {
+            Ok(Vec::new())
+        } else {
+            let bidder_ids = sqlx::types::Json(bidder_ids);
+            let query = sqlx::query_file_as!(
+                PortfolioRow,
+                "queries/query_portfolios_expanded.sql",
+                bidder_ids,
+                as_of,
+                self.market_id
+            )
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed(
+                "query_portfolio_with_expanded_products",
+                self.slow_query_threshold,
+            )
+            .await?;
+
+            Ok(query.into_iter().map(Into::into).collect())
+        }
+    }
+
+    async fn query_portfolio_by_product(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        product_id: Self::ProductId,
     ) -> Result<Vec<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
-        if bidder_ids.len() == 0 {
+        if bidder_ids.is_empty() {
             Ok(Vec::new())
         } else {
             let bidder_ids = sqlx::types::Json(bidder_ids);
@@ -49,25 +226,107 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                     json(demand) as "demand?: sqlx::types::Json<Weights<DemandId>>",
                     json(basis) as "basis?: sqlx::types::Json<Basis<ProductId>>"
                 from
-                    portfolio
+                    portfolio_product
                 join
-                    json_each($1) as bidder_ids
+                    portfolio
                 on
-                    portfolio.bidder_id = bidder_ids.atom
+                    portfolio.id = portfolio_product.portfolio_id
                 where
-                    portfolio.demand is not null
-                or
-                    portfolio.basis is not null
+                    portfolio_product.product_id = $2
+                and
+                    portfolio_product.valid_until is null
+                and
+                    portfolio.bidder_id in (select atom from json_each($1))
+                and
+                    portfolio.market_id = $3
                 "#,
-                bidder_ids
+                bidder_ids,
+                product_id,
+                self.market_id,
             )
-            .fetch_all(&self.reader)
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed("query_portfolio_by_product", self.slow_query_threshold)
             .await?;
 
             Ok(query.into_iter().map(Into::into).collect())
         }
     }
 
+    async fn search_portfolios(
+        &self,
+        bidder_ids: &[Self::BidderId],
+        query: PortfolioSearch<Self::PortfolioId>,
+        limit: usize,
+    ) -> Result<PortfolioSearchResponse<Self, PortfolioData>, Self::Error> {
+        if bidder_ids.is_empty() {
+            return Ok(PortfolioSearchResponse {
+                results: Vec::new(),
+                more: None,
+            });
+        }
+
+        let mut query_builder = sqlx::QueryBuilder::new(
+            r#"select
+                portfolio.id,
+                portfolio.as_of as "valid_from",
+                null as "valid_until",
+                portfolio.bidder_id,
+                json(portfolio.app_data) as "app_data",
+                json(portfolio.demand) as "demand",
+                json(portfolio.basis) as "basis"
+            from
+                portfolio
+            join
+                json_each("#,
+        );
+        query_builder
+            .push_bind(sqlx::types::Json(bidder_ids))
+            .push(
+                r#") as bidder_ids
+            on
+                portfolio.bidder_id = bidder_ids.atom
+            where
+                (portfolio.demand is not null or portfolio.basis is not null)
+            and
+                portfolio.market_id = "#,
+            )
+            .push_bind(self.market_id.as_str());
+
+        if let Some(after) = query.after {
+            query_builder.push(" and portfolio.id > ").push_bind(after);
+        }
+
+        crate::filter::push_filters(&mut query_builder, "portfolio", &query.filters);
+
+        query_builder
+            .push(" order by portfolio.id limit ")
+            // +1 to check if there are more results
+            .push_bind((limit + 1) as i64);
+
+        let mut rows: Vec<PortfolioRow<PortfolioData>> = query_builder
+            .build_query_as()
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed("search_portfolios", self.slow_query_threshold)
+            .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(PortfolioSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(PortfolioSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
     async fn create_portfolio(
         &self,
         portfolio_id: Self::PortfolioId,
@@ -75,8 +334,10 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
         app_data: PortfolioData,
         demand: Weights<Self::DemandId>,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<PortfolioRecord<Self, PortfolioData>, Self::Error> {
+        let actor = actor.as_str();
         let app_data = sqlx::types::Json(app_data);
         let demand = if demand.is_empty() {
             None
@@ -92,9 +353,9 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             PortfolioRow,
             r#"
             insert into
-                portfolio (id, as_of, bidder_id, app_data, demand, basis)
+                portfolio (id, as_of, bidder_id, app_data, demand, basis, actor, market_id)
             values
-                ($1, $2, $3, jsonb($4), jsonb($5), jsonb($6))
+                ($1, $2, $3, jsonb($4), jsonb($5), jsonb($6), $7, $8)
             returning
                 id as "id!: PortfolioId",
                 as_of as "valid_from!: DateTime",
@@ -109,9 +370,12 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             bidder_id,
             app_data,
             demand,
-            basis
+            basis,
+            actor,
+            self.market_id,
         )
-        .fetch_one(&self.writer)
+        .fetch_one(&mut *self.acquire_writer().await?)
+        .timed("create_portfolio", self.slow_query_threshold)
         .await?;
         Ok(portfolio.into())
     }
@@ -120,8 +384,10 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
         &self,
         portfolio_id: Self::PortfolioId,
         demand: Weights<Self::DemandId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let actor = actor.as_str();
         let demand = if demand.is_empty() {
             None
         } else {
@@ -134,9 +400,12 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                 portfolio
             set
                 as_of = $2,
-                demand = jsonb($3)
+                demand = jsonb($3),
+                actor = $4
             where
                 id = $1
+            and
+                market_id = $5
             returning
                 id as "id!: PortfolioId",
                 as_of as "valid_from!: DateTime",
@@ -149,8 +418,11 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             portfolio_id,
             as_of,
             demand,
+            actor,
+            self.market_id,
         )
-        .fetch_optional(&self.writer)
+        .fetch_optional(&mut *self.acquire_writer().await?)
+        .timed("update_portfolio_demand", self.slow_query_threshold)
         .await?;
 
         Ok(updated.map(Into::into))
@@ -160,8 +432,10 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
         &self,
         portfolio_id: Self::PortfolioId,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let actor = actor.as_str();
         let basis = if basis.is_empty() {
             None
         } else {
@@ -174,9 +448,12 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                 portfolio
             set
                 as_of = $2,
-                basis = jsonb($3)
+                basis = jsonb($3),
+                actor = $4
             where
                 id = $1
+            and
+                market_id = $5
             returning
                 id as "id!: PortfolioId",
                 as_of as "valid_from!: DateTime",
@@ -189,8 +466,11 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             portfolio_id,
             as_of,
             basis,
+            actor,
+            self.market_id,
         )
-        .fetch_optional(&self.writer)
+        .fetch_optional(&mut *self.acquire_writer().await?)
+        .timed("update_portfolio_basis", self.slow_query_threshold)
         .await?;
 
         Ok(updated.map(Into::into))
@@ -201,8 +481,10 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
         portfolio_id: Self::PortfolioId,
         demand: Weights<Self::DemandId>,
         basis: Basis<Self::ProductId>,
+        actor: Actor,
         as_of: Self::DateTime,
     ) -> Result<Option<PortfolioRecord<Self, PortfolioData>>, Self::Error> {
+        let actor = actor.as_str();
         let demand = if demand.is_empty() {
             None
         } else {
@@ -221,9 +503,12 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             set
                 as_of = $2,
                 demand = jsonb($3),
-                basis = jsonb($4)
+                basis = jsonb($4),
+                actor = $5
             where
                 id = $1
+            and
+                market_id = $6
             returning
                 id as "id!: PortfolioId",
                 as_of as "valid_from!: DateTime",
@@ -237,8 +522,11 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             as_of,
             demand,
             basis,
+            actor,
+            self.market_id,
         )
-        .fetch_optional(&self.writer)
+        .fetch_optional(&mut *self.acquire_writer().await?)
+        .timed("update_portfolio", self.slow_query_threshold)
         .await?;
 
         Ok(updated.map(Into::into))
@@ -253,9 +541,11 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             PortfolioRow,
             "queries/get_portfolio_by_id.sql",
             portfolio_id,
-            as_of
+            as_of,
+            self.market_id
         )
-        .fetch_optional(&self.reader)
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio", self.slow_query_threshold)
         .await?;
 
         Ok(query.map(Into::into))
@@ -270,9 +560,14 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             PortfolioRow,
             "queries/get_portfolio_by_id_expanded.sql",
             portfolio_id,
-            as_of
+            as_of,
+            self.market_id
+        )
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed(
+            "get_portfolio_with_expanded_products",
+            self.slow_query_threshold,
         )
-        .fetch_optional(&self.reader)
         .await?;
 
         Ok(query.map(Into::into))
@@ -292,12 +587,14 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
     ) -> Result<DateTimeRangeResponse<Weights<Self::DemandId>, Self::DateTime>, Self::Error> {
         let limit_p1 = (limit + 1) as i64;
         let mut rows = sqlx::query_as!(
-            ValueRow::<Weights<DemandId>>,
+            HistoryRow::<Weights<DemandId>>,
             r#"
                 select
                     valid_from as "valid_from!: crate::types::DateTime",
                     valid_until as "valid_until?: crate::types::DateTime",
-                    json_group_object(demand_id, weight) as "value!: sqlx::types::Json<Weights<DemandId>>"
+                    json_group_object(demand_id, weight) as "value!: sqlx::types::Json<Weights<DemandId>>",
+                    -- every row of a group is written by the same change
+                    min(actor) as "actor!: String"
                 from
                     portfolio_demand
                 where
@@ -305,7 +602,9 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                 and
                     ($2 is null or valid_from >= $2)
                 and
-                    ($3 is null or valid_until is null or valid_until < $3)
+                    ($3 is null or valid_from < $3)
+                and
+                    exists (select 1 from portfolio where id = $1 and market_id = $5)
                 group by
                     valid_from
                 order by
@@ -316,14 +615,18 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             query.after,
             query.before,
             limit_p1,
+            self.market_id,
         )
-        .fetch_all(&self.reader)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio_demand_history", self.slow_query_threshold)
         .await?;
 
         let more = if rows.len() == limit + 1 {
-            let extra = rows.pop().unwrap();
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
             Some(DateTimeRangeQuery {
-                before: Some(extra.valid_from),
+                before: rows.last().map(|row| row.valid_from),
                 after: query.after,
             })
         } else {
@@ -336,6 +639,22 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
         })
     }
 
+    fn stream_portfolio_demand_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Weights<DemandId>>, sqlx::Error>> + Send
+    {
+        stream_pages(query, move |query| {
+            <Self as PortfolioRepository<PortfolioData>>::get_portfolio_demand_history(
+                self,
+                portfolio_id,
+                query,
+                STREAM_PAGE_SIZE,
+            )
+        })
+    }
+
     /// Get the history of this portfolio's products
     ///
     /// This returns a list of records, each containing the state of the portfolio's product group
@@ -350,12 +669,14 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
     ) -> Result<DateTimeRangeResponse<Basis<Self::ProductId>, Self::DateTime>, Self::Error> {
         let limit_p1 = (limit + 1) as i64;
         let mut rows = sqlx::query_as!(
-            ValueRow::<Basis<ProductId>>,
+            HistoryRow::<Basis<ProductId>>,
             r#"
                 select
                     valid_from as "valid_from!: crate::types::DateTime",
                     valid_until as "valid_until?: crate::types::DateTime",
-                    json_group_object(product_id, weight) as "value!: sqlx::types::Json<Basis<ProductId>>"
+                    json_group_object(product_id, weight) as "value!: sqlx::types::Json<Basis<ProductId>>",
+                    -- every row of a group is written by the same change
+                    min(actor) as "actor!: String"
                 from
                     portfolio_product
                 where
@@ -363,7 +684,9 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
                 and
                     ($2 is null or valid_from >= $2)
                 and
-                    ($3 is null or valid_until is null or valid_until < $3)
+                    ($3 is null or valid_from < $3)
+                and
+                    exists (select 1 from portfolio where id = $1 and market_id = $5)
                 group by
                     valid_from
                 order by
@@ -374,14 +697,18 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             query.after,
             query.before,
             limit_p1,
+            self.market_id,
         )
-        .fetch_all(&self.reader)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_portfolio_product_history", self.slow_query_threshold)
         .await?;
 
         let more = if rows.len() == limit + 1 {
-            let extra = rows.pop().unwrap();
+            // The upper bound is exclusive, so the next page begins with the
+            // extra row, just below the oldest row of this page.
+            rows.pop();
             Some(DateTimeRangeQuery {
-                before: Some(extra.valid_from),
+                before: rows.last().map(|row| row.valid_from),
                 after: query.after,
             })
         } else {
@@ -393,4 +720,125 @@ impl<PortfolioData: Send + Unpin + serde::Serialize + serde::de::DeserializeOwne
             more,
         })
     }
+
+    fn stream_portfolio_product_history(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+    ) -> impl Stream<Item = Result<ValueRecord<DateTime, Basis<ProductId>>, sqlx::Error>> + Send
+    {
+        stream_pages(query, move |query| {
+            <Self as PortfolioRepository<PortfolioData>>::get_portfolio_product_history(
+                self,
+                portfolio_id,
+                query,
+                STREAM_PAGE_SIZE,
+            )
+        })
+    }
+
+    async fn purge_portfolio(
+        &self,
+        portfolio_id: Self::PortfolioId,
+        as_of: Self::DateTime,
+    ) -> Result<Option<Tombstone<Self, Self::PortfolioId>>, Self::Error> {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        // Erase the data of the portfolio itself, leaving the row as a
+        // tombstone. The update triggers close out the current groups, which
+        // are erased along with the rest of the history below.
+        let bidder_id = sqlx::query_scalar!(
+            r#"
+            update
+                portfolio
+            set
+                as_of = $2,
+                app_data = jsonb('null'),
+                demand = null,
+                basis = null,
+                actor = 'operator',
+                purged_at = $2
+            where
+                id = $1
+            and
+                market_id = $3
+            and
+                purged_at is null
+            returning
+                bidder_id as "bidder_id!: BidderId"
+            "#,
+            portfolio_id,
+            as_of,
+            self.market_id,
+        )
+        .fetch_optional(&mut *tx)
+        .timed("purge_portfolio.tombstone", self.slow_query_threshold)
+        .await?;
+
+        let Some(bidder_id) = bidder_id else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            delete from
+                portfolio_demand
+            where
+                portfolio_id = $1
+            "#,
+            portfolio_id
+        )
+        .execute(&mut *tx)
+        .timed(
+            "purge_portfolio.portfolio_demand",
+            self.slow_query_threshold,
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"
+            delete from
+                portfolio_product
+            where
+                portfolio_id = $1
+            "#,
+            portfolio_id
+        )
+        .execute(&mut *tx)
+        .timed(
+            "purge_portfolio.portfolio_product",
+            self.slow_query_threshold,
+        )
+        .await?;
+
+        // The groups are erased from the recorded inputs of past batches too
+        sqlx::query(
+            r#"
+            update
+                batch_input
+            set
+                inputs = json_remove(inputs, '$.portfolios."' || $1 || '"')
+            where
+                market_id = $2
+            and
+                json_type(inputs, '$.portfolios."' || $1 || '"') is not null
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(self.market_id.as_str())
+        .execute(&mut *tx)
+        .timed("purge_portfolio.batch_input", self.slow_query_threshold)
+        .await?;
+
+        tx.commit()
+            .timed("purge_portfolio.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Some(Tombstone {
+            id: portfolio_id,
+            bidder_id,
+            purged_at: as_of,
+        }))
+    }
 }
@@ -1,10 +1,313 @@
-use crate::Db;
-use crate::types::{ProductId, ProductRow};
+use crate::types::{DateTime, ProductId, ProductRow};
+use crate::{Db, instrument::Timed as _};
 use fts_core::{
-    models::{Basis, ProductRecord},
+    models::{
+        AppDataFilter, Basis, ProductQuery, ProductQueryResponse, ProductRecord, ProductSearch,
+        ProductSearchResponse, ProductTreeConflict,
+    },
     ports::ProductRepository,
 };
 
+impl Db {
+    /// The current parent of a product of the market, which is None for a
+    /// root product, or None if there is no such product
+    async fn current_parent(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        product_id: ProductId,
+    ) -> Result<Option<Option<ProductId>>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"select parent_id as "parent_id?: ProductId" from product where id = $1 and market_id = $2"#,
+            product_id,
+            self.market_id,
+        )
+        .fetch_optional(&mut *conn)
+        .timed("current_parent", self.slow_query_threshold)
+        .await
+    }
+
+    /// Whether `product_id` is `parent_id` or one of its current ancestors
+    async fn is_ancestor(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        product_id: ProductId,
+        parent_id: ProductId,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            with recursive lineage (id) as (
+                select $2
+                union all
+                select product.parent_id from lineage join product on product.id = lineage.id
+                where product.parent_id is not null
+            )
+            select exists (select 1 from lineage where id = $1) as "exists!: bool"
+            "#,
+            product_id,
+            parent_id,
+        )
+        .fetch_one(&mut *conn)
+        .timed("is_ancestor", self.slow_query_threshold)
+        .await
+    }
+
+    /// Move a product under `parent_id` with the given ratio, which may be its
+    /// current parent `old_parent_id`, ending the current paths through the
+    /// product and starting those through its new parent as of `as_of`.
+    async fn move_in_tree(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        product_id: ProductId,
+        old_parent_id: Option<ProductId>,
+        parent_id: ProductId,
+        ratio: f64,
+        as_of: DateTime,
+    ) -> Result<(), sqlx::Error> {
+        // Invalidate the paths from the former ancestors to the product's leaves
+        sqlx::query!(
+            r#"
+            with recursive lineage (id) as (
+                select $3
+                union all
+                select product.parent_id from lineage join product on product.id = lineage.id
+                where product.parent_id is not null
+            )
+            update product_tree
+            set
+                valid_until = $2
+            where
+                valid_until is null
+            and
+                src_id in (select id from lineage)
+            and
+                dst_id in (
+                    select dst_id from product_tree where src_id = $1 and valid_until is null
+                )
+            "#,
+            product_id,
+            as_of,
+            old_parent_id,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.ancestors", self.slow_query_threshold)
+        .await?;
+
+        // A new parent which was its own basis element ceases to be one
+        sqlx::query!(
+            r#"
+            update product_tree
+            set
+                valid_until = $2
+            where
+                dst_id = $1
+            and
+                valid_until is null
+            and
+                exists (
+                    select 1 from product_tree
+                    where src_id = $1 and dst_id = $1 and valid_until is null
+                )
+            "#,
+            parent_id,
+            as_of,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.parent", self.slow_query_threshold)
+        .await?;
+
+        sqlx::query!(
+            "update product_parent set valid_until = $2 where product_id = $1 and valid_until is null",
+            product_id,
+            as_of,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.close", self.slow_query_threshold)
+        .await?;
+
+        // Rows both started and ended at `as_of` were never in effect
+        sqlx::query!("delete from product_tree where valid_until = valid_from")
+            .execute(&mut *conn)
+            .timed("move_product.empty_paths", self.slow_query_threshold)
+            .await?;
+        sqlx::query!(
+            "delete from product_parent where product_id = $1 and valid_until = valid_from",
+            product_id
+        )
+        .execute(&mut *conn)
+        .timed("move_product.empty_parents", self.slow_query_threshold)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            insert into
+                product_parent (product_id, parent_id, ratio, valid_from, valid_until)
+            values
+                ($1, $2, $3, $4, null)
+            "#,
+            product_id,
+            parent_id,
+            ratio,
+            as_of,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.open", self.slow_query_threshold)
+        .await?;
+
+        // the outbox trigger records the move
+        sqlx::query!(
+            "update product set parent_id = $2, parent_ratio = $3 where id = $1",
+            product_id,
+            parent_id,
+            ratio,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.product", self.slow_query_threshold)
+        .await?;
+
+        // A former parent left without children is its own basis element again
+        sqlx::query!(
+            r#"
+            with recursive lineage (id, ratio, depth) as (
+                select $1, 1.0, 0
+                union all
+                select product.parent_id, lineage.ratio * product.parent_ratio, lineage.depth + 1
+                from lineage join product on product.id = lineage.id
+                where product.parent_id is not null
+            )
+            insert into
+                product_tree (src_id, dst_id, ratio, depth, valid_from, valid_until)
+            select
+                id, $1, ratio, depth, $2, null
+            from
+                lineage
+            where
+                $1 is not null
+            and
+                not exists (select 1 from product where parent_id = $1)
+            "#,
+            old_parent_id,
+            as_of,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.childless", self.slow_query_threshold)
+        .await?;
+
+        // Extend the paths to the new parent to the product's leaves
+        sqlx::query!(
+            r#"
+            with recursive lineage (id, ratio, depth) as (
+                select $2, 1.0, 0
+                union all
+                select product.parent_id, lineage.ratio * product.parent_ratio, lineage.depth + 1
+                from lineage join product on product.id = lineage.id
+                where product.parent_id is not null
+            )
+            insert into
+                product_tree (src_id, dst_id, ratio, depth, valid_from, valid_until)
+            select
+                lineage.id,
+                leaf.dst_id,
+                lineage.ratio * $3 * leaf.ratio,
+                lineage.depth + 1 + leaf.depth,
+                $4,
+                null
+            from
+                lineage
+            join
+                product_tree as leaf
+            on
+                leaf.src_id = $1
+            and
+                leaf.valid_until is null
+            "#,
+            product_id,
+            parent_id,
+            ratio,
+            as_of,
+        )
+        .execute(&mut *conn)
+        .timed("move_product.descendants", self.slow_query_threshold)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The products of the market existing as of `as_of` whose application
+    /// data satisfies every filter (and which are root products as of the
+    /// time, if `roots_only`), ordered by id from just after `after`. One more
+    /// than `limit` rows are returned, to check if there are more.
+    async fn select_products<ProductData>(
+        &self,
+        filters: &[AppDataFilter],
+        roots_only: bool,
+        after: Option<ProductId>,
+        as_of: DateTime,
+        limit: usize,
+        statement: &str,
+    ) -> Result<Vec<ProductRow<ProductData>>, sqlx::Error>
+    where
+        ProductData: Send + Unpin + 'static + serde::de::DeserializeOwned,
+    {
+        // The parent is that of the product as of the time, if any
+        let mut query_builder = sqlx::QueryBuilder::new(
+            r#"select
+                product.id,
+                json(product.app_data) as "app_data",
+                case
+                    when product_parent.parent_id is null then json_array(product.id, 1.0)
+                    else json_array(product_parent.parent_id, product_parent.ratio)
+                end as "parent",
+                json_group_object(product_tree.dst_id, product_tree.ratio) as "basis"
+            from
+                product
+            join
+                product_tree
+            on
+                product.id = product_tree.src_id
+            left join
+                product_parent
+            on
+                product_parent.product_id = product.id
+            and
+                product_parent.valid_from <= "#,
+        );
+        query_builder
+            .push_bind(as_of)
+            .push(" and (")
+            .push_bind(as_of)
+            .push(" < product_parent.valid_until or product_parent.valid_until is null)")
+            .push(" where product.market_id = ")
+            .push_bind(self.market_id.as_str())
+            .push(" and product_tree.valid_from <= ")
+            .push_bind(as_of)
+            .push(" and (")
+            .push_bind(as_of)
+            .push(" < product_tree.valid_until or product_tree.valid_until is null)");
+
+        if roots_only {
+            query_builder.push(" and product_parent.parent_id is null");
+        }
+
+        if let Some(after) = after {
+            query_builder.push(" and product.id > ").push_bind(after);
+        }
+
+        crate::filter::push_filters(&mut query_builder, "product", filters);
+
+        query_builder
+            .push(" group by product.id, product_parent.parent_id, product_parent.ratio")
+            .push(" order by product.id limit ")
+            // +1 to check if there are more results
+            .push_bind((limit + 1) as i64);
+
+        query_builder
+            .build_query_as()
+            .fetch_all(&mut *self.acquire_reader().await?)
+            .timed(statement, self.slow_query_threshold)
+            .await
+    }
+}
+
 impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::DeserializeOwned>
     ProductRepository<ProductData> for Db
 {
@@ -23,9 +326,9 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
             ProductRow,
             r#"
             insert into
-                product (id, as_of, app_data)
+                product (id, market_id, as_of, app_data)
             values
-                ($1, $2, jsonb($3))
+                ($1, $2, $3, jsonb($4))
             returning
                 id as "id!: ProductId",
                 json(app_data) as "app_data!: sqlx::types::Json<ProductData>",
@@ -33,15 +336,64 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
                 json_object(id, 1.0) as "basis!: sqlx::types::Json<Basis<ProductId>>"
             "#,
             product_id,
+            self.market_id,
             as_of,
             app_data,
         )
-        .fetch_one(&self.writer)
+        .fetch_one(&mut *self.acquire_writer().await?)
+        .timed("create_product", self.slow_query_threshold)
         .await?;
 
         Ok(new_product.into())
     }
 
+    async fn create_products<T: Send + IntoIterator<Item = (Self::ProductId, ProductData)>>(
+        &self,
+        products: T,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<ProductRecord<Self, ProductData>>, Self::Error>
+    where
+        T::IntoIter: Send,
+    {
+        let products = products.into_iter();
+        let mut records = Vec::with_capacity(products.size_hint().0);
+
+        // Insert the products one at a time, so that the records are returned
+        // in order, but within one transaction so that they are all or nothing
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+        for (product_id, app_data) in products {
+            let app_data = sqlx::types::Json(app_data);
+            let new_product = sqlx::query_as!(
+                ProductRow,
+                r#"
+                insert into
+                    product (id, market_id, as_of, app_data)
+                values
+                    ($1, $2, $3, jsonb($4))
+                returning
+                    id as "id!: ProductId",
+                    json(app_data) as "app_data!: sqlx::types::Json<ProductData>",
+                    json_array(id, 1.0) as "parent!: sqlx::types::Json<(ProductId, f64)>",
+                    json_object(id, 1.0) as "basis!: sqlx::types::Json<Basis<ProductId>>"
+                "#,
+                product_id,
+                self.market_id,
+                as_of,
+                app_data,
+            )
+            .fetch_one(&mut *tx)
+            .timed("create_products", self.slow_query_threshold)
+            .await?;
+            records.push(new_product.into());
+        }
+        tx.commit()
+            .timed("create_products.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(records)
+    }
+
     async fn partition_product<T: Send + IntoIterator<Item = (Self::ProductId, ProductData, f64)>>(
         &self,
         product_id: Self::ProductId,
@@ -52,11 +404,13 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
         T::IntoIter: Send + ExactSizeIterator,
     {
         let npaths = sqlx::query_scalar!(
-            "select count(*) from product_tree where src_id = $1 and valid_from <= $2 and ($2 < valid_until or valid_until is null)",
+            "select count(*) from product_tree join product on product.id = product_tree.src_id where src_id = $1 and market_id = $3 and valid_from <= $2 and ($2 < valid_until or valid_until is null)",
             product_id,
-            as_of
+            as_of,
+            self.market_id,
         )
-        .fetch_one(&self.reader)
+        .fetch_one(&mut *self.acquire_reader().await?)
+        .timed("partition_product.paths", self.slow_query_threshold)
         .await?;
 
         // TODO: not being in a transaction, there is a possibility a product gets partitioned after this check but before our partitioning
@@ -73,12 +427,13 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
         }
 
         let mut query_builder = sqlx::QueryBuilder::new(
-            "insert into product (id, as_of, app_data, parent_id, parent_ratio) ",
+            "insert into product (id, market_id, as_of, app_data, parent_id, parent_ratio) ",
         );
         // TODO: we should partition the iterator to play nice with DB limits
         query_builder
             .push_values(children, |mut b, (child_id, app_data, child_ratio)| {
                 b.push_bind(child_id)
+                    .push_bind(self.market_id.as_str())
                     .push_bind(as_of)
                     .push("jsonb(")
                     .push_bind_unseparated(sqlx::types::Json(app_data))
@@ -96,12 +451,100 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
 
         let result: Vec<ProductRow<ProductData>> = query_builder
             .build_query_as()
-            .fetch_all(&self.writer)
+            .fetch_all(&mut *self.acquire_writer().await?)
+            .timed("partition_product.children", self.slow_query_threshold)
             .await?;
 
         Ok(Some(result.into_iter().map(Into::into).collect()))
     }
 
+    async fn update_product_ratio(
+        &self,
+        product_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let Some(parent_id) = self.current_parent(&mut tx, product_id).await? else {
+            return Ok(Err(ProductTreeConflict::Missing { product_id }));
+        };
+        let Some(parent_id) = parent_id else {
+            return Ok(Err(ProductTreeConflict::Root { product_id }));
+        };
+
+        self.move_in_tree(
+            &mut tx,
+            product_id,
+            Some(parent_id),
+            parent_id,
+            ratio,
+            as_of,
+        )
+        .await?;
+        let record = sqlx::query_file_as!(
+            ProductRow,
+            "queries/get_product_by_id.sql",
+            product_id,
+            as_of,
+            self.market_id,
+        )
+        .fetch_one(&mut *tx)
+        .timed("update_product_ratio.record", self.slow_query_threshold)
+        .await?;
+
+        tx.commit()
+            .timed("update_product_ratio.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Ok(record.into()))
+    }
+
+    async fn move_product(
+        &self,
+        product_id: Self::ProductId,
+        parent_id: Self::ProductId,
+        ratio: f64,
+        as_of: Self::DateTime,
+    ) -> Result<Result<ProductRecord<Self, ProductData>, ProductTreeConflict<ProductId>>, Self::Error>
+    {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let Some(old_parent_id) = self.current_parent(&mut tx, product_id).await? else {
+            return Ok(Err(ProductTreeConflict::Missing { product_id }));
+        };
+        if self.current_parent(&mut tx, parent_id).await?.is_none() {
+            return Ok(Err(ProductTreeConflict::Missing {
+                product_id: parent_id,
+            }));
+        }
+        if self.is_ancestor(&mut tx, product_id, parent_id).await? {
+            return Ok(Err(ProductTreeConflict::Cycle { parent_id }));
+        }
+
+        self.move_in_tree(&mut tx, product_id, old_parent_id, parent_id, ratio, as_of)
+            .await?;
+        let record = sqlx::query_file_as!(
+            ProductRow,
+            "queries/get_product_by_id.sql",
+            product_id,
+            as_of,
+            self.market_id,
+        )
+        .fetch_one(&mut *tx)
+        .timed("move_product.record", self.slow_query_threshold)
+        .await?;
+
+        tx.commit()
+            .timed("move_product.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Ok(record.into()))
+    }
+
     async fn get_product(
         &self,
         product_id: Self::ProductId,
@@ -112,9 +555,81 @@ impl<ProductData: Send + Unpin + 'static + serde::Serialize + serde::de::Deseria
             "queries/get_product_by_id.sql",
             product_id,
             as_of,
+            self.market_id,
         )
-        .fetch_optional(&self.reader)
+        .fetch_optional(&mut *self.acquire_reader().await?)
+        .timed("get_product", self.slow_query_threshold)
         .await?
         .map(Into::into))
     }
+
+    async fn query_products(
+        &self,
+        query: ProductQuery<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductQueryResponse<Self, ProductData>, Self::Error> {
+        let mut rows: Vec<ProductRow<ProductData>> = self
+            .select_products(
+                &[],
+                !query.include_tree,
+                query.after,
+                as_of,
+                limit,
+                "query_products",
+            )
+            .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(ProductQuery {
+                include_tree: query.include_tree,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(ProductQueryResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn search_products(
+        &self,
+        query: ProductSearch<Self::ProductId>,
+        as_of: Self::DateTime,
+        limit: usize,
+    ) -> Result<ProductSearchResponse<Self, ProductData>, Self::Error> {
+        let mut rows: Vec<ProductRow<ProductData>> = self
+            .select_products(
+                &query.filters,
+                false,
+                query.after,
+                as_of,
+                limit,
+                "search_products",
+            )
+            .await?;
+
+        // The lower bound is exclusive, so the next page begins just after
+        // the last row of this page
+        let more = if rows.len() == limit + 1 {
+            rows.pop();
+            Some(ProductSearch {
+                filters: query.filters,
+                after: rows.last().map(|row| row.id),
+            })
+        } else {
+            None
+        };
+
+        Ok(ProductSearchResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
 }
@@ -0,0 +1,337 @@
+use crate::{Db, instrument::Timed as _, types::DateTime};
+use fts_core::{
+    models::{CompactionRecord, PruneRecord},
+    ports::RetentionRepository,
+};
+use sqlx::{Connection as _, SqliteConnection};
+use std::{path::Path, time::Duration};
+
+/// The condition selecting the rows of history superseded by the horizon `$1`
+const SUPERSEDED: &str = "valid_until <= $1";
+
+/// The tables of superseded history, along with whether their rows are needed
+/// to accrue unsettled activity, the condition selecting the rows to prune as
+/// of the horizon `$1`, and the condition selecting the rows of the market `$2`.
+const HISTORY: [(&str, bool, &str, &str); 6] = [
+    (
+        "curve_data",
+        false,
+        SUPERSEDED,
+        "demand_id in (select id from main.demand where market_id = $2)",
+    ),
+    (
+        "portfolio_demand",
+        false,
+        SUPERSEDED,
+        "portfolio_id in (select id from main.portfolio where market_id = $2)",
+    ),
+    (
+        "portfolio_product",
+        true,
+        SUPERSEDED,
+        "portfolio_id in (select id from main.portfolio where market_id = $2)",
+    ),
+    (
+        "portfolio_outcome",
+        true,
+        SUPERSEDED,
+        "portfolio_id in (select id from main.portfolio where market_id = $2)",
+    ),
+    (
+        "product_outcome",
+        true,
+        SUPERSEDED,
+        "product_id in (select id from main.product where market_id = $2)",
+    ),
+    // The recorded inputs of a batch are only of use to solve it again
+    ("batch_input", false, "as_of < $1", "market_id = $2"),
+];
+
+/// The tables of group history, along with the column of their members
+const GROUPS: [(&str, &str); 2] = [
+    ("portfolio_demand", "demand_id"),
+    ("portfolio_product", "product_id"),
+];
+
+/// The default VFS of the platform, which stores databases as files
+#[cfg(windows)]
+const FILE_VFS: &str = "win32";
+#[cfg(not(windows))]
+const FILE_VFS: &str = "unix";
+
+impl RetentionRepository for Db {
+    async fn prune_history(
+        &self,
+        before: Self::DateTime,
+    ) -> Result<PruneRecord<Self::DateTime>, Self::Error> {
+        let mut conn = self.acquire_writer().await?;
+
+        // A database cannot be attached within a transaction, so the archive
+        // is attached to the (sole) writer connection for the duration
+        #[cfg(feature = "parquet")]
+        let export = self.export_path.as_deref();
+        #[cfg(not(feature = "parquet"))]
+        let export = None;
+
+        let Some(archive_path) = self.archive_path.as_ref() else {
+            return prune(
+                &mut conn,
+                &self.market_id,
+                before,
+                false,
+                export,
+                self.slow_query_threshold,
+            )
+            .await;
+        };
+
+        // The archive is named by URI, as a plain filename would otherwise be
+        // opened with the VFS of the main database, which may be `memdb`
+        let archive_uri = format!(
+            "file:{}?vfs={FILE_VFS}",
+            archive_path
+                .to_string_lossy()
+                .replace('%', "%25")
+                .replace('?', "%3f")
+                .replace('#', "%23")
+        );
+        sqlx::query("attach database $1 as archive")
+            .bind(archive_uri)
+            .execute(&mut *conn)
+            .timed("prune_history.attach", self.slow_query_threshold)
+            .await?;
+        let record = prune(
+            &mut conn,
+            &self.market_id,
+            before,
+            true,
+            export,
+            self.slow_query_threshold,
+        )
+        .await;
+        sqlx::query("detach database archive")
+            .execute(&mut *conn)
+            .timed("prune_history.detach", self.slow_query_threshold)
+            .await?;
+        record
+    }
+
+    async fn compact_history(&self) -> Result<CompactionRecord, Self::Error> {
+        let threshold = self.slow_query_threshold;
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let mut removed = [0; GROUPS.len()];
+        for ((table, member), removed) in GROUPS.into_iter().zip(removed.iter_mut()) {
+            // A version is redundant if its every row matches a row of the
+            // version it immediately follows, and the two are the same size.
+            // Each run of redundant versions is then planned as the start of
+            // the version it follows (the head) and the end of the run.
+            sqlx::query(&format!(
+                r#"
+                create temp table compaction as
+                with version as (
+                    select portfolio_id, valid_from, count(*) as size
+                    from main.{table}
+                    where portfolio_id in (select id from main.portfolio where market_id = $1)
+                    group by portfolio_id, valid_from
+                ),
+                redundant as (
+                    select
+                        b.portfolio_id,
+                        a.valid_from as previous,
+                        b.valid_from,
+                        b.valid_until
+                    from version as va
+                    join main.{table} as a
+                        on a.portfolio_id = va.portfolio_id and a.valid_from = va.valid_from
+                    join main.{table} as b
+                        on b.portfolio_id = a.portfolio_id
+                        and b.{member} = a.{member}
+                        and b.valid_from = a.valid_until
+                        and b.weight = a.weight
+                        and b.actor = a.actor
+                    join version as vb
+                        on vb.portfolio_id = b.portfolio_id and vb.valid_from = b.valid_from
+                    where vb.size = va.size
+                    group by b.portfolio_id, a.valid_from, b.valid_from, b.valid_until, va.size
+                    having count(*) = va.size
+                ),
+                run as (
+                    select
+                        r.*,
+                        sum(not exists (
+                            select 1 from redundant as p
+                            where p.portfolio_id = r.portfolio_id and p.valid_from = r.previous
+                        )) over (partition by r.portfolio_id order by r.valid_from) as run
+                    from redundant as r
+                )
+                select
+                    portfolio_id,
+                    min(previous) as head,
+                    iif(count(valid_until) < count(*), null, max(valid_until)) as valid_until
+                from run
+                group by portfolio_id, run
+                "#
+            ))
+            .bind(&self.market_id)
+            .execute(&mut *tx)
+            .timed(&format!("compact_history.plan_{table}"), threshold)
+            .await?;
+
+            *removed = sqlx::query(&format!(
+                r#"
+                delete from main.{table}
+                where exists (
+                    select 1 from temp.compaction as c
+                    where c.portfolio_id = {table}.portfolio_id
+                    and {table}.valid_from > c.head
+                    and (c.valid_until is null or {table}.valid_from < c.valid_until)
+                )
+                "#
+            ))
+            .execute(&mut *tx)
+            .timed(&format!("compact_history.delete_{table}"), threshold)
+            .await?
+            .rows_affected();
+
+            sqlx::query(&format!(
+                r#"
+                update main.{table} set valid_until = c.valid_until
+                from temp.compaction as c
+                where c.portfolio_id = {table}.portfolio_id and {table}.valid_from = c.head
+                "#
+            ))
+            .execute(&mut *tx)
+            .timed(&format!("compact_history.extend_{table}"), threshold)
+            .await?;
+
+            sqlx::query("drop table temp.compaction")
+                .execute(&mut *tx)
+                .timed("compact_history.drop", threshold)
+                .await?;
+        }
+
+        tx.commit()
+            .timed("compact_history.commit", threshold)
+            .await?;
+
+        let [demand_groups, product_groups] = removed;
+        Ok(CompactionRecord {
+            demand_groups,
+            product_groups,
+        })
+    }
+}
+
+/// Prune the history of the market which was superseded before `before`
+async fn prune(
+    conn: &mut SqliteConnection,
+    market_id: &str,
+    before: DateTime,
+    archive: bool,
+    export: Option<&Path>,
+    threshold: Duration,
+) -> Result<PruneRecord<DateTime>, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    let latest = sqlx::query_scalar!(
+        r#"select max(as_of) as "as_of?: DateTime" from settlement where market_id = $1"#,
+        market_id
+    )
+    .fetch_one(&mut *tx)
+    .timed("prune_history.settlement", threshold)
+    .await?;
+    let settled_before = latest.map(|latest| latest.min(before));
+
+    // The pruned outcomes are exported before any of the history needed to
+    // express their trades is pruned
+    #[cfg(feature = "parquet")]
+    if let (Some(directory), Some(horizon)) = (export, settled_before) {
+        let name = crate::export::file_name(market_id, "pruned", horizon);
+        crate::export::export_outcomes(
+            &mut tx, directory, &name, market_id, None, horizon, true, threshold,
+        )
+        .await
+        .map_err(|error| match error {
+            crate::export::ExportError::Database(error) => error,
+            error => sqlx::Error::Io(std::io::Error::other(error)),
+        })?;
+    }
+    #[cfg(not(feature = "parquet"))]
+    let _ = export;
+
+    let mut pruned = [0; HISTORY.len()];
+    for ((table, settled, prunable, market), pruned) in HISTORY.into_iter().zip(pruned.iter_mut()) {
+        let horizon = if settled {
+            settled_before
+        } else {
+            Some(before)
+        };
+        let Some(horizon) = horizon else {
+            continue;
+        };
+
+        if archive {
+            // The archived tables mirror the columns, but not the constraints,
+            // of the originals
+            sqlx::query(&format!(
+                "create table if not exists archive.{table} as select * from main.{table} where false"
+            ))
+            .execute(&mut *tx)
+            .timed(&format!("prune_history.create_{table}"), threshold)
+            .await?;
+            sqlx::query(&format!(
+                "insert into archive.{table} select * from main.{table} where {prunable} and {market}"
+            ))
+            .bind(horizon)
+            .bind(market_id)
+            .execute(&mut *tx)
+            .timed(&format!("prune_history.archive_{table}"), threshold)
+            .await?;
+        }
+
+        *pruned = sqlx::query(&format!(
+            "delete from main.{table} where {prunable} and {market}"
+        ))
+        .bind(horizon)
+        .bind(market_id)
+        .execute(&mut *tx)
+        .timed(&format!("prune_history.delete_{table}"), threshold)
+        .await?
+        .rows_affected();
+    }
+
+    // Delivered events are of no further use, so are neither archived nor counted
+    sqlx::query!(
+        "delete from main.outbox where market_id = $1 and delivered_at <= $2",
+        market_id,
+        before,
+    )
+    .execute(&mut *tx)
+    .timed("prune_history.outbox", threshold)
+    .await?;
+
+    tx.commit().timed("prune_history.commit", threshold).await?;
+
+    let [
+        curves,
+        demand_groups,
+        product_groups,
+        portfolio_outcomes,
+        product_outcomes,
+        batch_inputs,
+    ] = pruned;
+
+    Ok(PruneRecord {
+        before,
+        settled_before,
+        curves,
+        demand_groups,
+        product_groups,
+        portfolio_outcomes,
+        product_outcomes,
+        batch_inputs,
+        archived: archive,
+    })
+}
@@ -0,0 +1,1306 @@
+use crate::types::{BidderId, DateTime, ProductId, ValueRow};
+use crate::{Db, instrument::Timed as _};
+use fts_core::{
+    models::{
+        Activity, BidderSummary, DateTimeRangeQuery, DateTimeRangeResponse, Map, SettlementConfig,
+        SettlementConflict, SettlementRecord, SettlementRevision, SettlementStatement,
+        UnsettledActivityQuery, UnsettledActivityResponse, ValueRecord,
+    },
+    ports::SettlementRepository,
+};
+use sqlx::types::Json;
+use std::collections::HashMap;
+
+/// The number of bidders whose activity is settled at a time
+const SETTLEMENT_PAGE_SIZE: i64 = 1000;
+
+/// The columns of the statements, to be filtered
+const STATEMENTS: &str = r#"
+    select
+        id,
+        bidder_id,
+        as_of,
+        settled_from,
+        revision,
+        revised_at,
+        json(config) as config,
+        json(activity) as activity
+    from
+        settlement_statement
+"#;
+
+/// The activity accrued by a bidder over some interval. If `product_id` is
+/// None, the row corresponds to the bidder's payment.
+struct AccruedRow {
+    bidder_id: BidderId,
+    product_id: Option<ProductId>,
+    accrued: f64,
+}
+
+/// The cumulative activity of a bidder across all previous settlements.
+struct TotalRow {
+    bidder_id: BidderId,
+    product_id: Option<ProductId>,
+    accrued: f64,
+    settled: f64,
+}
+
+/// A settlement previously made.
+struct SettlementRow {
+    settled_from: DateTime,
+    config: Json<SettlementConfig<DateTime>>,
+}
+
+/// A revision of a settlement.
+struct RevisionRow {
+    revision: i64,
+    revised_at: DateTime,
+    config: Option<Json<SettlementConfig<DateTime>>>,
+}
+
+/// A settlement as it stands after its latest revision, if any.
+struct PostedSettlement {
+    settled_from: DateTime,
+    /// The configuration with which the settlement was originally made
+    original: SettlementConfig<DateTime>,
+    /// The latest revision, or 0 if never revised
+    revision: i64,
+    revised_at: DateTime,
+    /// The configuration in effect, or None if the settlement is reversed
+    config: Option<SettlementConfig<DateTime>>,
+}
+
+/// A statement issued to a bidder.
+#[derive(sqlx::FromRow)]
+struct StatementRow {
+    id: i64,
+    bidder_id: BidderId,
+    as_of: DateTime,
+    settled_from: DateTime,
+    revision: i64,
+    revised_at: Option<DateTime>,
+    config: Option<Json<SettlementConfig<DateTime>>>,
+    activity: Json<Activity<ProductId>>,
+}
+
+impl From<StatementRow> for SettlementStatement<Db> {
+    fn from(row: StatementRow) -> Self {
+        Self {
+            id: row.id as u64,
+            bidder_id: row.bidder_id,
+            valid_from: row.settled_from,
+            valid_until: row.as_of,
+            revision: row.revision as u32,
+            revised_at: row.revised_at,
+            config: row.config.map(|config| config.0),
+            activity: row.activity.0,
+        }
+    }
+}
+
+/// A bidder's quantity of a product. If `product_id` is None, the row
+/// corresponds to the bidder's payment.
+struct AmountRow {
+    product_id: Option<ProductId>,
+    amount: f64,
+}
+
+/// Collect settled entries into the activity of each bidder
+fn settled_activity(rows: Vec<TotalRow>) -> Map<BidderId, Activity<ProductId>> {
+    let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+    for row in rows {
+        let entry = activity.entry(row.bidder_id).or_default();
+        match row.product_id {
+            Some(product_id) => {
+                entry.positions.insert(product_id, row.settled);
+            }
+            None => entry.payment = row.settled,
+        }
+    }
+    activity
+}
+
+fn round(value: f64, decimals: u32) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+impl Db {
+    /// The net position of every bidder in every product, combining the
+    /// settled positions with the activity accrued since the most recent
+    /// settlement up to `as_of`.
+    pub(crate) async fn net_positions(
+        &self,
+        as_of: DateTime,
+        time_unit: f64,
+    ) -> Result<HashMap<(BidderId, ProductId), f64>, sqlx::Error> {
+        // The settled positions and the activity since are read from the same
+        // snapshot, so that no settlement is posted in between
+        let (settled, unsettled) = self
+            .snapshot(|db| {
+                Box::pin(async move {
+                    let latest = latest_settlement(&mut *db.acquire_reader().await?, &db.market_id)
+                        .timed("latest_settlement", db.slow_query_threshold)
+                        .await?;
+
+                    let settled = sqlx::query_as!(
+                        AccruedRow,
+                        r#"
+                select
+                    bidder_id as "bidder_id!: BidderId",
+                    product_id as "product_id?: ProductId",
+                    total(settled) as "accrued!: f64"
+                from
+                    settlement_position
+                where
+                    market_id = $1
+                and
+                    as_of <= $2
+                group by
+                    bidder_id,
+                    product_id
+            "#,
+                        db.market_id,
+                        as_of,
+                    )
+                    .fetch_all(&mut *db.acquire_reader().await?)
+                    .timed("net_positions.settled", db.slow_query_threshold)
+                    .await?;
+
+                    let unsettled = sqlx::query_file_as!(
+                        AccruedRow,
+                        "queries/accrued_activity.sql",
+                        None::<Json<&[BidderId]>>,
+                        latest,
+                        as_of,
+                        time_unit,
+                        db.market_id,
+                        None::<Json<&[ProductId]>>,
+                        None::<ProductId>,
+                        -1,
+                    )
+                    .fetch_all(&mut *db.acquire_reader().await?)
+                    .timed("net_positions.unsettled", db.slow_query_threshold)
+                    .await?;
+
+                    Ok::<_, sqlx::Error>((settled, unsettled))
+                })
+            })
+            .await?;
+
+        let mut positions = HashMap::new();
+        for row in settled.into_iter().chain(unsettled) {
+            // Rows without a product are payments
+            if let Some(product_id) = row.product_id {
+                *positions.entry((row.bidder_id, product_id)).or_default() += row.accrued;
+            }
+        }
+        Ok(positions)
+    }
+
+    /// The activity of a bidder accrued since the most recent settlement up
+    /// to `as_of`, restricted to at most `limit` (-1 for no limit) positions
+    /// in `product_ids` (if any) following `after`, along with the start of
+    /// the unsettled interval.
+    async fn unsettled_activity(
+        &self,
+        bidder_id: BidderId,
+        as_of: DateTime,
+        time_unit: f64,
+        product_ids: &[ProductId],
+        after: Option<ProductId>,
+        limit: i64,
+    ) -> Result<(DateTime, Vec<AccruedRow>), sqlx::Error> {
+        let product_ids = product_ids.to_vec();
+        self.snapshot(|db| {
+            Box::pin(async move {
+                let latest = latest_settlement(&mut *db.acquire_reader().await?, &db.market_id)
+                    .timed("latest_settlement", db.slow_query_threshold)
+                    .await?;
+
+                let bidder_ids = [bidder_id];
+                let bidder_ids = Some(Json(&bidder_ids[..]));
+                let product_ids = (!product_ids.is_empty()).then_some(Json(&product_ids[..]));
+                let rows = sqlx::query_file_as!(
+                    AccruedRow,
+                    "queries/accrued_activity.sql",
+                    bidder_ids,
+                    latest,
+                    as_of,
+                    time_unit,
+                    db.market_id,
+                    product_ids,
+                    after,
+                    limit,
+                )
+                .fetch_all(&mut *db.acquire_reader().await?)
+                .timed("get_unsettled_activity", db.slow_query_threshold)
+                .await?;
+
+                let valid_from = match latest {
+                    Some(latest) => latest,
+                    None => first_batch(&mut *db.acquire_reader().await?, &db.market_id)
+                        .timed("first_batch", db.slow_query_threshold)
+                        .await?
+                        .unwrap_or(as_of),
+                };
+
+                Ok((valid_from, rows))
+            })
+        })
+        .await
+    }
+
+    /// The state of the settlement as of `as_of`, if it was made, net of any
+    /// revisions since.
+    async fn posted_settlement(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        as_of: DateTime,
+    ) -> Result<Option<PostedSettlement>, sqlx::Error> {
+        let Some(settlement) = sqlx::query_as!(
+            SettlementRow,
+            r#"
+                select
+                    settled_from as "settled_from!: DateTime",
+                    json(config) as "config!: Json<SettlementConfig<DateTime>>"
+                from
+                    settlement
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+            "#,
+            self.market_id,
+            as_of,
+        )
+        .fetch_optional(&mut *conn)
+        .timed("posted_settlement", self.slow_query_threshold)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let latest = sqlx::query_as!(
+            RevisionRow,
+            r#"
+                select
+                    revision as "revision!: i64",
+                    revised_at as "revised_at!: DateTime",
+                    json(config) as "config?: Json<SettlementConfig<DateTime>>"
+                from
+                    settlement_revision
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                order by
+                    revision desc
+                limit 1
+            "#,
+            self.market_id,
+            as_of,
+        )
+        .fetch_optional(&mut *conn)
+        .timed("posted_settlement.revision", self.slow_query_threshold)
+        .await?;
+
+        let original = settlement.config.0;
+        Ok(Some(match latest {
+            Some(latest) => PostedSettlement {
+                settled_from: settlement.settled_from,
+                original,
+                revision: latest.revision,
+                revised_at: latest.revised_at,
+                config: latest.config.map(|config| config.0),
+            },
+            None => PostedSettlement {
+                settled_from: settlement.settled_from,
+                revision: 0,
+                revised_at: as_of,
+                config: Some(original.clone()),
+                original,
+            },
+        }))
+    }
+
+    /// The entries of the settlement as of `as_of` posted by the revisions
+    /// `from..=until`, summed per bidder and product.
+    async fn settlement_entries(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        as_of: DateTime,
+        from: i64,
+        until: i64,
+    ) -> Result<Vec<TotalRow>, sqlx::Error> {
+        sqlx::query_as!(
+            TotalRow,
+            r#"
+                select
+                    bidder_id as "bidder_id!: BidderId",
+                    product_id as "product_id?: ProductId",
+                    sum(accrued) as "accrued!: f64",
+                    sum(settled) as "settled!: f64"
+                from
+                    settlement_position
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                and
+                    revision between $3 and $4
+                group by
+                    bidder_id,
+                    product_id
+                union all
+                select
+                    bidder_id,
+                    null as product_id,
+                    sum(accrued) as accrued,
+                    sum(settled) as settled
+                from
+                    settlement_payment
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                and
+                    revision between $3 and $4
+                group by
+                    bidder_id
+                order by
+                    1
+            "#,
+            self.market_id,
+            as_of,
+            from,
+            until,
+        )
+        .fetch_all(&mut *conn)
+        .timed("settlement_entries", self.slow_query_threshold)
+        .await
+    }
+
+    /// Settle the accrued activity of the given bidders as of the
+    /// configuration's `as_of`, posting the entries as `revision`.
+    async fn post_entries(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        config: &SettlementConfig<DateTime>,
+        revision: i64,
+        bidders: &[BidderId],
+        rows: Vec<AccruedRow>,
+    ) -> Result<Map<BidderId, Activity<ProductId>>, sqlx::Error> {
+        // Rounding each settlement independently would let the residuals
+        // drift, so we instead round the running totals and settle the
+        // difference against what has already been settled.
+        let bidder_ids = Json(bidders);
+        let totals = sqlx::query_as!(
+            TotalRow,
+            r#"
+                select
+                    bidder_id as "bidder_id!: BidderId",
+                    product_id as "product_id?: ProductId",
+                    sum(accrued) as "accrued!: f64",
+                    sum(settled) as "settled!: f64"
+                from
+                    settlement_position
+                where
+                    market_id = $1
+                and
+                    bidder_id in (select atom from json_each($2))
+                group by
+                    bidder_id,
+                    product_id
+                union all
+                select
+                    bidder_id,
+                    null as product_id,
+                    sum(accrued) as accrued,
+                    sum(settled) as settled
+                from
+                    settlement_payment
+                where
+                    market_id = $1
+                and
+                    bidder_id in (select atom from json_each($2))
+                group by
+                    bidder_id
+            "#,
+            self.market_id,
+            bidder_ids,
+        )
+        .fetch_all(&mut *conn)
+        .timed("settle_activity.totals", self.slow_query_threshold)
+        .await?
+        .into_iter()
+        .map(|row| ((row.bidder_id, row.product_id), (row.accrued, row.settled)))
+        .collect::<HashMap<_, _>>();
+
+        let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+        for row in rows {
+            let (accrued, settled) = totals
+                .get(&(row.bidder_id, row.product_id))
+                .copied()
+                .unwrap_or_default();
+
+            let entry = activity.entry(row.bidder_id).or_default();
+            match row.product_id {
+                Some(product_id) => {
+                    let decimals = config.position_decimals;
+                    let amount = round(round(accrued + row.accrued, decimals) - settled, decimals);
+                    sqlx::query!(
+                        r#"
+                            insert into
+                                settlement_position (market_id, as_of, revision, bidder_id, product_id, accrued, settled)
+                            values
+                                ($1, $2, $3, $4, $5, $6, $7)
+                        "#,
+                        self.market_id,
+                        config.as_of,
+                        revision,
+                        row.bidder_id,
+                        product_id,
+                        row.accrued,
+                        amount,
+                    )
+                    .execute(&mut *conn)
+                    .timed("settle_activity.position", self.slow_query_threshold)
+                    .await?;
+                    entry.positions.insert(product_id, amount);
+                }
+                None => {
+                    let decimals = config.payment_decimals;
+                    let amount = round(round(accrued + row.accrued, decimals) - settled, decimals);
+                    sqlx::query!(
+                        r#"
+                            insert into
+                                settlement_payment (market_id, as_of, revision, bidder_id, accrued, settled)
+                            values
+                                ($1, $2, $3, $4, $5, $6)
+                        "#,
+                        self.market_id,
+                        config.as_of,
+                        revision,
+                        row.bidder_id,
+                        row.accrued,
+                        amount,
+                    )
+                    .execute(&mut *conn)
+                    .timed("settle_activity.payment", self.slow_query_threshold)
+                    .await?;
+                    entry.payment = amount;
+                }
+            }
+        }
+        Ok(activity)
+    }
+
+    /// Reverse the settlement as of `as_of`, posting entries offsetting every
+    /// entry posted so far as `revision`.
+    async fn post_reversal(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        as_of: DateTime,
+        revision: i64,
+        revised_at: DateTime,
+    ) -> Result<SettlementRevision<Self>, sqlx::Error> {
+        sqlx::query!(
+            r#"
+                insert into
+                    settlement_revision (market_id, as_of, revision, revised_at, config)
+                values
+                    ($1, $2, $3, $4, null)
+            "#,
+            self.market_id,
+            as_of,
+            revision,
+            revised_at,
+        )
+        .execute(&mut *conn)
+        .timed("reverse_settlement.revision", self.slow_query_threshold)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                insert into
+                    settlement_position (market_id, as_of, revision, bidder_id, product_id, accrued, settled)
+                select
+                    market_id,
+                    as_of,
+                    $3,
+                    bidder_id,
+                    product_id,
+                    -sum(accrued),
+                    -sum(settled)
+                from
+                    settlement_position
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                group by
+                    bidder_id,
+                    product_id
+            "#,
+            self.market_id,
+            as_of,
+            revision,
+        )
+        .execute(&mut *conn)
+        .timed("reverse_settlement.positions", self.slow_query_threshold)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                insert into
+                    settlement_payment (market_id, as_of, revision, bidder_id, accrued, settled)
+                select
+                    market_id,
+                    as_of,
+                    $3,
+                    bidder_id,
+                    -sum(accrued),
+                    -sum(settled)
+                from
+                    settlement_payment
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                group by
+                    bidder_id
+            "#,
+            self.market_id,
+            as_of,
+            revision,
+        )
+        .execute(&mut *conn)
+        .timed("reverse_settlement.payments", self.slow_query_threshold)
+        .await?;
+
+        let entries = self
+            .settlement_entries(conn, as_of, revision, revision)
+            .await?;
+        Ok(SettlementRevision {
+            as_of,
+            revision: revision as u32,
+            revised_at,
+            config: None,
+            activity: settled_activity(entries),
+        })
+    }
+
+    /// Issue a statement to each bidder of the entries posted by a revision
+    /// of the settlement (or by the settlement itself, as revision 0).
+    async fn issue_statements(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        settled_from: DateTime,
+        entries: &SettlementRevision<Self>,
+    ) -> Result<(), sqlx::Error> {
+        let revised_at = (entries.revision > 0).then_some(entries.revised_at);
+        for (bidder_id, activity) in entries.activity.iter() {
+            sqlx::query(
+                r#"
+                insert into
+                    settlement_statement (market_id, as_of, revision, bidder_id, settled_from, revised_at, config, activity)
+                values
+                    ($1, $2, $3, $4, $5, $6, jsonb($7), jsonb($8))
+                "#,
+            )
+            .bind(self.market_id.as_str())
+            .bind(entries.as_of)
+            .bind(entries.revision as i64)
+            .bind(bidder_id)
+            .bind(settled_from)
+            .bind(revised_at)
+            .bind(entries.config.as_ref().map(Json))
+            .bind(Json(activity))
+            .execute(&mut *conn)
+            .timed("issue_statements", self.slow_query_threshold)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl SettlementRepository for Db {
+    async fn get_unsettled_activity(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<ValueRecord<Self::DateTime, Activity<Self::ProductId>>, Self::Error> {
+        let (valid_from, rows) = self
+            .unsettled_activity(bidder_id, as_of, time_unit, &[], None, -1)
+            .await?;
+
+        let mut activity = Activity::default();
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => {
+                    activity.positions.insert(product_id, row.accrued);
+                }
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        Ok(ValueRecord {
+            valid_from,
+            valid_until: Some(as_of),
+            value: activity,
+            actor: None,
+        })
+    }
+
+    async fn get_unsettled_positions(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+        query: UnsettledActivityQuery<Self::ProductId>,
+        limit: usize,
+    ) -> Result<UnsettledActivityResponse<Self>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let (valid_from, rows) = self
+            .unsettled_activity(
+                bidder_id,
+                as_of,
+                time_unit,
+                &query.products,
+                query.after,
+                limit_p1,
+            )
+            .await?;
+
+        // The positions precede the payment, in order of product
+        let mut activity = Activity::default();
+        let mut positions = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.product_id {
+                Some(product_id) => positions.push((product_id, row.accrued)),
+                None => activity.payment = row.accrued,
+            }
+        }
+
+        let more = if positions.len() == limit + 1 {
+            positions.pop();
+            Some(UnsettledActivityQuery {
+                products: query.products,
+                after: positions.last().map(|(product_id, _)| *product_id),
+            })
+        } else {
+            None
+        };
+        activity.positions.extend(positions);
+
+        Ok(UnsettledActivityResponse {
+            activity: ValueRecord {
+                valid_from,
+                valid_until: Some(as_of),
+                value: activity,
+                actor: None,
+            },
+            more,
+        })
+    }
+
+    async fn settle_activity(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+    ) -> Result<Result<SettlementRecord<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        // A settlement which has already been made is returned as it stands,
+        // provided it is retried with the configuration now in effect
+        if let Some(posted) = self.posted_settlement(&mut tx, config.as_of).await? {
+            return match posted.config {
+                None => Ok(Err(SettlementConflict::Reversed {
+                    as_of: config.as_of,
+                })),
+                Some(existing) if existing != config => {
+                    Ok(Err(SettlementConflict::Mismatched { existing }))
+                }
+                Some(_) => {
+                    let entries = self
+                        .settlement_entries(&mut tx, config.as_of, 0, posted.revision)
+                        .await?;
+                    Ok(Ok(SettlementRecord {
+                        valid_from: posted.settled_from,
+                        valid_until: config.as_of,
+                        activity: settled_activity(entries),
+                    }))
+                }
+            };
+        }
+
+        let latest = latest_settlement(&mut *tx, &self.market_id)
+            .timed("latest_settlement", self.slow_query_threshold)
+            .await?;
+
+        if let Some(latest) = latest.filter(|latest| *latest > config.as_of) {
+            return Ok(Err(SettlementConflict::Superseded { latest }));
+        }
+
+        let settled_from = match latest {
+            Some(latest) => latest,
+            None => first_batch(&mut *tx, &self.market_id)
+                .timed("first_batch", self.slow_query_threshold)
+                .await?
+                .unwrap_or(config.as_of),
+        };
+
+        let config_json = sqlx::types::Json(&config);
+        sqlx::query!(
+            r#"
+                insert into
+                    settlement (market_id, as_of, settled_from, config)
+                values
+                    ($1, $2, $3, jsonb($4))
+            "#,
+            self.market_id,
+            config.as_of,
+            settled_from,
+            config_json,
+        )
+        .execute(&mut *tx)
+        .timed("settle_activity.settlement", self.slow_query_threshold)
+        .await?;
+
+        // Accrue and settle the activity a page of bidders at a time, rather
+        // than aggregating the activity of the entire market at once
+        let mut activity: Map<BidderId, Activity<ProductId>> = Map::default();
+        let mut after = None::<BidderId>;
+        loop {
+            let bidders = sqlx::query_scalar!(
+                r#"
+                    select distinct
+                        bidder_id as "bidder_id!: BidderId"
+                    from
+                        portfolio
+                    where
+                        market_id = $1
+                    and
+                        ($2 is null or bidder_id > $2)
+                    order by
+                        bidder_id
+                    limit $3
+                "#,
+                self.market_id,
+                after,
+                SETTLEMENT_PAGE_SIZE,
+            )
+            .fetch_all(&mut *tx)
+            .timed("settle_activity.bidders", self.slow_query_threshold)
+            .await?;
+
+            let Some(last) = bidders.last() else {
+                break;
+            };
+            after = Some(*last);
+            let bidder_ids = Some(Json(&bidders[..]));
+
+            let rows = sqlx::query_file_as!(
+                AccruedRow,
+                "queries/accrued_activity.sql",
+                bidder_ids,
+                latest,
+                config.as_of,
+                config.time_unit,
+                self.market_id,
+                None::<Json<&[ProductId]>>,
+                None::<ProductId>,
+                -1,
+            )
+            .fetch_all(&mut *tx)
+            .timed("settle_activity.accrued", self.slow_query_threshold)
+            .await?;
+
+            let entries = SettlementRevision {
+                as_of: config.as_of,
+                revision: 0,
+                revised_at: config.as_of,
+                config: Some(config.clone()),
+                activity: self
+                    .post_entries(&mut tx, &config, 0, &bidders, rows)
+                    .await?,
+            };
+            self.issue_statements(&mut tx, settled_from, &entries)
+                .await?;
+            activity.extend(entries.activity);
+        }
+
+        tx.commit()
+            .timed("settle_activity.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Ok(SettlementRecord {
+            valid_from: settled_from,
+            valid_until: config.as_of,
+            activity,
+        }))
+    }
+
+    async fn reverse_settlement(
+        &self,
+        as_of: Self::DateTime,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let Some(posted) = self.posted_settlement(&mut tx, as_of).await? else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+        if posted.config.is_none() {
+            return Ok(Err(SettlementConflict::Reversed { as_of }));
+        }
+
+        let revision = self
+            .post_reversal(&mut tx, as_of, posted.revision + 1, revised_at)
+            .await?;
+        self.issue_statements(&mut tx, posted.settled_from, &revision)
+            .await?;
+
+        tx.commit()
+            .timed("reverse_settlement.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Ok(revision))
+    }
+
+    async fn correct_settlement(
+        &self,
+        config: SettlementConfig<Self::DateTime>,
+        revised_at: Self::DateTime,
+    ) -> Result<Result<SettlementRevision<Self>, SettlementConflict<DateTime>>, Self::Error> {
+        let mut unit_of_work = self.lock_unit_of_work().await?;
+        let mut tx = self.begin_write(&mut unit_of_work).await?;
+
+        let as_of = config.as_of;
+        let Some(posted) = self.posted_settlement(&mut tx, as_of).await? else {
+            return Ok(Err(SettlementConflict::Missing { as_of }));
+        };
+
+        // Repeating the latest correction returns it as it was made
+        if posted.revision > 0 && posted.config.as_ref() == Some(&config) {
+            let entries = self
+                .settlement_entries(&mut tx, as_of, posted.revision, posted.revision)
+                .await?;
+            return Ok(Ok(SettlementRevision {
+                as_of,
+                revision: posted.revision as u32,
+                revised_at: posted.revised_at,
+                config: Some(config),
+                activity: settled_activity(entries),
+            }));
+        }
+
+        let mut revision = posted.revision;
+        if posted.config.is_some() {
+            revision += 1;
+            let reversal = self
+                .post_reversal(&mut tx, as_of, revision, revised_at)
+                .await?;
+            self.issue_statements(&mut tx, posted.settled_from, &reversal)
+                .await?;
+        }
+        revision += 1;
+
+        let config_json = Json(&config);
+        sqlx::query!(
+            r#"
+                insert into
+                    settlement_revision (market_id, as_of, revision, revised_at, config)
+                values
+                    ($1, $2, $3, $4, jsonb($5))
+            "#,
+            self.market_id,
+            as_of,
+            revision,
+            revised_at,
+            config_json,
+        )
+        .execute(&mut *tx)
+        .timed("correct_settlement.revision", self.slow_query_threshold)
+        .await?;
+
+        // The activity was accrued in units of the original settlement's time
+        // unit, and is re-expressed in those of the correction
+        let scale = posted.original.time_unit / config.time_unit;
+        let original = self.settlement_entries(&mut tx, as_of, 0, 0).await?;
+        let mut bidders: Vec<BidderId> = original.iter().map(|row| row.bidder_id).collect();
+        bidders.dedup();
+        let rows = original
+            .into_iter()
+            .map(|row| AccruedRow {
+                bidder_id: row.bidder_id,
+                product_id: row.product_id,
+                accrued: row.accrued * scale,
+            })
+            .collect();
+
+        let correction = SettlementRevision {
+            as_of,
+            revision: revision as u32,
+            revised_at,
+            activity: self
+                .post_entries(&mut tx, &config, revision, &bidders, rows)
+                .await?,
+            config: Some(config),
+        };
+        self.issue_statements(&mut tx, posted.settled_from, &correction)
+            .await?;
+
+        tx.commit()
+            .timed("correct_settlement.commit", self.slow_query_threshold)
+            .await?;
+
+        Ok(Ok(correction))
+    }
+
+    async fn get_settlement_revisions(
+        &self,
+        as_of: Self::DateTime,
+    ) -> Result<Vec<SettlementRevision<Self>>, Self::Error> {
+        let mut conn = self.acquire_reader().await?;
+
+        let revisions = sqlx::query_as!(
+            RevisionRow,
+            r#"
+                select
+                    revision as "revision!: i64",
+                    revised_at as "revised_at!: DateTime",
+                    json(config) as "config?: Json<SettlementConfig<DateTime>>"
+                from
+                    settlement_revision
+                where
+                    market_id = $1
+                and
+                    as_of = $2
+                order by
+                    revision
+            "#,
+            self.market_id,
+            as_of,
+        )
+        .fetch_all(&mut *conn)
+        .timed("get_settlement_revisions", self.slow_query_threshold)
+        .await?;
+
+        let mut results = Vec::with_capacity(revisions.len());
+        for row in revisions {
+            let entries = self
+                .settlement_entries(&mut conn, as_of, row.revision, row.revision)
+                .await?;
+            results.push(SettlementRevision {
+                as_of,
+                revision: row.revision as u32,
+                revised_at: row.revised_at,
+                config: row.config.map(|config| config.0),
+                activity: settled_activity(entries),
+            });
+        }
+        Ok(results)
+    }
+
+    async fn get_settlement_statement(
+        &self,
+        statement_id: u64,
+    ) -> Result<Option<SettlementStatement<Self>>, Self::Error> {
+        let row: Option<StatementRow> =
+            sqlx::query_as(&format!("{STATEMENTS} where market_id = $1 and id = $2"))
+                .bind(self.market_id.as_str())
+                .bind(statement_id as i64)
+                .fetch_optional(&mut *self.acquire_reader().await?)
+                .timed("get_settlement_statement", self.slow_query_threshold)
+                .await?;
+        Ok(row.map(Into::into))
+    }
+
+    async fn get_settlement_statements(
+        &self,
+        as_of: Self::DateTime,
+        bidder_id: Option<Self::BidderId>,
+    ) -> Result<Vec<SettlementStatement<Self>>, Self::Error> {
+        let rows: Vec<StatementRow> = sqlx::query_as(&format!(
+            "{STATEMENTS} where market_id = $1 and as_of = $2 and ($3 is null or bidder_id = $3) order by id"
+        ))
+        .bind(self.market_id.as_str())
+        .bind(as_of)
+        .bind(bidder_id)
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_settlement_statements", self.slow_query_threshold)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// Get the bidder's settlement history
+    ///
+    /// This returns a list of settled activity, one record per settlement in
+    /// which the bidder had activity. The records are ordered by the end of
+    /// their settlement interval in descending order.
+    async fn get_settlement_history(
+        &self,
+        bidder_id: Self::BidderId,
+        query: DateTimeRangeQuery<Self::DateTime>,
+        limit: usize,
+    ) -> Result<DateTimeRangeResponse<Activity<Self::ProductId>, Self::DateTime>, Self::Error> {
+        let limit_p1 = (limit + 1) as i64;
+        let mut rows = sqlx::query_as!(
+            ValueRow::<Activity<ProductId>>,
+            r#"
+                with
+                position_cte as (
+                    select
+                        as_of,
+                        jsonb_group_object(product_id, settled) as positions
+                    from (
+                        select
+                            as_of,
+                            product_id,
+                            sum(settled) as settled
+                        from
+                            settlement_position
+                        where
+                            market_id = $5
+                        and
+                            bidder_id = $1
+                        group by
+                            as_of,
+                            product_id
+                    )
+                    group by
+                        as_of
+                ),
+                payment_cte as (
+                    select
+                        as_of,
+                        sum(settled) as settled
+                    from
+                        settlement_payment
+                    where
+                        market_id = $5
+                    and
+                        bidder_id = $1
+                    group by
+                        as_of
+                )
+                select
+                    settlement.settled_from as "valid_from!: crate::types::DateTime",
+                    settlement.as_of as "valid_until?: crate::types::DateTime",
+                    json_object(
+                        'positions', json(coalesce(position_cte.positions, jsonb_object())),
+                        'payment', payment_cte.settled
+                    ) as "value!: sqlx::types::Json<Activity<ProductId>>"
+                from
+                    settlement
+                join
+                    payment_cte
+                    on
+                        settlement.as_of = payment_cte.as_of
+                left join
+                    position_cte
+                    on
+                        settlement.as_of = position_cte.as_of
+                where
+                    settlement.market_id = $5
+                and
+                    ($2 is null or settlement.settled_from >= $2)
+                and
+                    ($3 is null or settlement.settled_from < $3)
+                order by
+                    settlement.as_of desc
+                limit $4
+            "#,
+            bidder_id,
+            query.after,
+            query.before,
+            limit_p1,
+            self.market_id,
+        )
+        .fetch_all(&mut *self.acquire_reader().await?)
+        .timed("get_settlement_history", self.slow_query_threshold)
+        .await?;
+
+        // Settlement intervals are contiguous, so we page on the end of the
+        // extra interval to ensure it is included in the next page.
+        let more = if rows.len() == limit + 1 {
+            let extra = rows.pop().unwrap();
+            Some(DateTimeRangeQuery {
+                before: extra.valid_until,
+                after: query.after,
+            })
+        } else {
+            None
+        };
+
+        Ok(DateTimeRangeResponse {
+            results: rows.into_iter().map(Into::into).collect(),
+            more,
+        })
+    }
+
+    async fn get_bidder_summary(
+        &self,
+        bidder_id: Self::BidderId,
+        as_of: Self::DateTime,
+        time_unit: f64,
+    ) -> Result<BidderSummary<Self>, Self::Error> {
+        // The activity, settlements and rates are read from the same snapshot,
+        // so that no settlement or batch is made in between
+        let (unsettled, settled_rows, rates) = self
+            .snapshot(|db| {
+                Box::pin(async move {
+                    let unsettled = db
+                        .get_unsettled_activity(bidder_id, as_of, time_unit)
+                        .await?;
+
+                    let settled_rows = sqlx::query_as!(
+                        AmountRow,
+                        r#"
+                select
+                    product_id as "product_id?: ProductId",
+                    total(settled) as "amount!: f64"
+                from
+                    settlement_position
+                where
+                    market_id = $3
+                and
+                    bidder_id = $1
+                and
+                    as_of <= $2
+                group by
+                    product_id
+                union all
+                select
+                    null as product_id,
+                    total(settled) as amount
+                from
+                    settlement_payment
+                where
+                    market_id = $3
+                and
+                    bidder_id = $1
+                and
+                    as_of <= $2
+            "#,
+                        bidder_id,
+                        as_of,
+                        db.market_id,
+                    )
+                    .fetch_all(&mut *db.acquire_reader().await?)
+                    .timed("get_bidder_summary.settled", db.slow_query_threshold)
+                    .await?;
+
+                    // The outcomes in effect are expressed in terms of the contemporary
+                    // product basis of the batch that produced them
+                    let rates: Map<ProductId> = sqlx::query_as!(
+                        AmountRow,
+                        r#"
+                select
+                    basis_view.product_id as "product_id?: ProductId",
+                    total(portfolio_outcome.value ->> '$.rate' * basis_view.weight) as "amount!: f64"
+                from
+                    portfolio_outcome
+                join
+                    portfolio
+                    on
+                        portfolio_outcome.portfolio_id = portfolio.id
+                join
+                    basis_view
+                    on
+                        portfolio_outcome.portfolio_id = basis_view.portfolio_id
+                        and
+                        basis_view.valid_from <= portfolio_outcome.valid_from
+                        and
+                        (portfolio_outcome.valid_from < basis_view.valid_until or basis_view.valid_until is null)
+                where
+                    portfolio.market_id = $3
+                and
+                    portfolio.bidder_id = $1
+                and
+                    portfolio_outcome.valid_from <= $2
+                and
+                    ($2 < portfolio_outcome.valid_until or portfolio_outcome.valid_until is null)
+                and
+                    portfolio_outcome.value ->> '$.rate' != 0
+                group by
+                    basis_view.product_id
+            "#,
+                        bidder_id,
+                        as_of,
+                        db.market_id,
+                    )
+                    .fetch_all(&mut *db.acquire_reader().await?)
+                    .timed("get_bidder_summary.unsettled", db.slow_query_threshold)
+                    .await?
+                    .into_iter()
+                    .filter_map(|row| row.product_id.map(|product_id| (product_id, row.amount)))
+                    .collect();
+
+                    Ok::<_, sqlx::Error>((unsettled, settled_rows, rates))
+                })
+            })
+            .await?;
+
+        let mut settled = Activity::default();
+        for row in settled_rows {
+            match row.product_id {
+                Some(product_id) => {
+                    settled.positions.insert(product_id, row.amount);
+                }
+                None => settled.payment = row.amount,
+            }
+        }
+
+        let mut positions = settled.positions.clone();
+        for (product_id, amount) in unsettled.value.positions.iter() {
+            *positions.entry(*product_id).or_default() += amount;
+        }
+
+        Ok(BidderSummary {
+            as_of,
+            positions,
+            rates,
+            settled,
+            unsettled: unsettled.value,
+            unsettled_from: unsettled.valid_from,
+        })
+    }
+}
+
+/// The time up to which the market was most recently settled, if ever
+async fn latest_settlement<'c, E: sqlx::SqliteExecutor<'c>>(
+    executor: E,
+    market_id: &str,
+) -> Result<Option<DateTime>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"select max(as_of) as "as_of?: DateTime" from settlement where market_id = $1"#,
+        market_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// The timestamp of the market's earliest batch outcome, if any
+async fn first_batch<'c, E: sqlx::SqliteExecutor<'c>>(
+    executor: E,
+    market_id: &str,
+) -> Result<Option<DateTime>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            select
+                min(valid_from) as "valid_from?: DateTime"
+            from
+                portfolio_outcome
+            where
+                portfolio_id in (select id from portfolio where market_id = $1)
+        "#,
+        market_id
+    )
+    .fetch_one(executor)
+    .await
+}
@@ -0,0 +1,40 @@
+//! Tracing of the queries made against the database.
+//!
+//! Every query runs within a `query` span that records the name of its
+//! statement and, once it completes, its duration in milliseconds. A query
+//! that takes longer than the configured `slow_query_threshold` also emits a
+//! warning, so that a spike in batch latency can be traced to its cause.
+
+use std::time::{Duration, Instant};
+use tracing::{Instrument as _, Level, event, field, span};
+
+/// Instruments a query (or any other database operation) with a span and a
+/// slow-query warning.
+pub(crate) trait Timed: IntoFuture + Sized {
+    /// Run the query within a span named for `statement`, warning if it takes
+    /// longer than `threshold`.
+    async fn timed(self, statement: &str, threshold: Duration) -> Self::Output {
+        let span = span!(Level::DEBUG, "query", statement, duration_ms = field::Empty);
+
+        let start = Instant::now();
+        let output = self.into_future().instrument(span.clone()).await;
+        let elapsed = start.elapsed();
+
+        let duration_ms = elapsed.as_secs_f64() * 1000.0;
+        span.record("duration_ms", duration_ms);
+        if elapsed > threshold {
+            event!(
+                parent: &span,
+                Level::WARN,
+                statement,
+                duration_ms,
+                threshold_ms = threshold.as_secs_f64() * 1000.0,
+                "slow query"
+            );
+        }
+
+        output
+    }
+}
+
+impl<F: IntoFuture> Timed for F {}
@@ -7,14 +7,40 @@
 #![doc = include_str!("../README.md")]
 
 use sqlx::sqlite;
-use std::{str::FromStr, time::Duration};
-use tokio::try_join;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
+pub mod checkpoint;
 pub mod config;
+#[cfg(feature = "parquet")]
+pub mod export;
+mod filter;
 mod r#impl;
+mod instrument;
+pub mod maintenance;
+pub mod retry;
+mod transaction;
 pub mod types;
 
+use checkpoint::CheckpointHook;
 use config::SqliteConfig;
+use instrument::Timed as _;
+use maintenance::MaintenanceConfig;
+use retry::RetryCounters;
+use transaction::UnitOfWork;
+
+/// The schema migrations, which are applied when the database is opened
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./schema");
+
+/// Distinguishes the in-memory databases opened by this process
+static IN_MEMORY_DB_SEQ: AtomicUsize = AtomicUsize::new(0);
 
 /// SQLite database implementation for flow trading repositories.
 ///
@@ -25,8 +51,10 @@ use config::SqliteConfig;
 ///
 /// # Connection Management
 ///
-/// - `reader`: A connection pool for read operations, allowing concurrent reads
+/// - `reader`: A read-only connection pool for read operations, allowing concurrent reads
 /// - `writer`: A single-connection pool for write operations, ensuring serialized writes
+/// - `replicas`: Read-only connection pools to copies of the database maintained by
+///   external replication, across which reads are balanced along with `reader`
 ///
 /// # Example
 ///
@@ -41,17 +69,47 @@ use config::SqliteConfig;
 /// ```
 #[derive(Clone)]
 pub struct Db {
-    /// Connection pool for read operations
+    /// Connection pool for read operations (connections are read-only)
     pub reader: sqlx::Pool<sqlx::Sqlite>,
     /// Connection pool for write operations (limited to 1 connection)
     pub writer: sqlx::Pool<sqlx::Sqlite>,
+    /// Connection pools for read operations on replicas of the database, if any
+    pub replicas: Vec<sqlx::Pool<sqlx::Sqlite>>,
+    /// The count of reads made, by which each is assigned a pool in turn
+    next_reader: Arc<AtomicUsize>,
+    /// The database into which pruned history is archived, if any
+    pub archive_path: Option<PathBuf>,
+    /// The directory to which pruned outcomes are exported, if any
+    #[cfg(feature = "parquet")]
+    pub export_path: Option<PathBuf>,
+    /// The function invoked after each explicit checkpoint, if any
+    pub checkpoint_hook: Option<CheckpointHook>,
+    /// The market to which every repository operation is scoped
+    pub market_id: String,
+    /// Duration beyond which a query is logged as slow
+    pub slow_query_threshold: Duration,
+    /// Whether the inputs of each batch are recorded
+    pub record_batch_inputs: bool,
+    /// The configuration of periodic maintenance
+    pub maintenance: MaintenanceConfig,
+    /// Number of times a busy write transaction is retried
+    pub write_retries: u32,
+    /// Delay before the first retry of a busy write transaction
+    pub write_retry_backoff: Duration,
+    /// The counts of retried write transactions, shared by every handle
+    retry_counters: Arc<RetryCounters>,
+    /// The markets for which a batch auction is executing, shared by every handle
+    running_batches: Arc<Mutex<HashSet<String>>>,
+    /// The transaction of the unit of work to which this handle belongs, if any
+    unit_of_work: Option<UnitOfWork>,
 }
 
 impl Db {
     /// Open a connection to the specified SQLite database.
     ///
     /// Creates a new database if one doesn't exist (when `create_if_missing` is true),
-    /// applies all pending migrations, and ensures the batch table is initialized.
+    /// applies all pending migrations (when `auto_migrate` is true), and ensures the
+    /// batch table is initialized for the configured market.
     ///
     /// # Arguments
     ///
@@ -61,7 +119,10 @@ impl Db {
     /// # Database Configuration
     ///
     /// The database is configured with the following settings for optimal performance:
-    /// - WAL mode for better concurrency
+    /// - WAL mode for better concurrency (in-memory databases use a rollback journal)
+    /// - Incremental auto-vacuum for new databases, so that maintenance can
+    ///   return the pages freed by pruning to the filesystem
+    /// - Read-only connections in the reader pool
     /// - Foreign keys enabled for referential integrity
     /// - Optimized cache and memory settings for flow trading workloads
     ///
@@ -69,57 +130,206 @@ impl Db {
     ///
     /// Returns `sqlx::Error` if:
     /// - Database connection fails
-    /// - Migrations fail to apply
+    /// - Migrations fail to apply, or are pending while `auto_migrate` is false
+    /// - An entry of `app_data_indexes` is not a JSON path
     /// - Initial batch row creation fails
     pub async fn open(config: &SqliteConfig, as_of: types::DateTime) -> Result<Self, sqlx::Error> {
-        let db_path = config
-            .database_path
-            .as_ref()
-            .map(|p| p.to_string_lossy().into_owned());
+        let db = Self::connect(config).await?;
+
+        // Run any pending migrations before returning, unless they are left
+        // to the operator, in which case none may remain
+        if config.auto_migrate {
+            MIGRATOR.run(&db.writer).await?;
+        } else {
+            let pending = db.migration_status().await?.pending;
+            if pending > 0 {
+                return Err(sqlx::Error::Configuration(
+                    format!(
+                        "{pending} schema migrations are pending, and auto_migrate is disabled"
+                    )
+                    .into(),
+                ));
+            }
+        }
+        filter::index_app_data(
+            &mut *db.writer.acquire().await?,
+            &config.app_data_indexes,
+            config.slow_query_threshold,
+        )
+        .await?;
+
+        ensure_batch(&db.writer, &config.market_id, as_of)
+            .timed("ensure_batch", config.slow_query_threshold)
+            .await?;
+
+        Ok(db)
+    }
+
+    /// Connect to the specified SQLite database without migrating it.
+    ///
+    /// Unlike [`Db::open`], no migration is applied and the batch table is not
+    /// initialized, so the handle is only fit to inspect the database, e.g.
+    /// its [migration status](Db::migration_status) ahead of a deployment. A
+    /// database which does not exist is still created (when
+    /// `create_if_missing` is true), with every migration pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns `sqlx::Error` if the database connection fails
+    pub async fn connect(config: &SqliteConfig) -> Result<Self, sqlx::Error> {
+        let options = match config.database_path.as_ref() {
+            Some(path) => sqlite::SqliteConnectOptions::new().filename(path),
+            // A named database of the `memdb` VFS is shared by every connection
+            // in the process that opens it, but unlike a shared-cache `:memory:`
+            // database, its connections lock it the same way as a file, so the
+            // reader may be opened read-only without locking out the writer.
+            None => sqlite::SqliteConnectOptions::new()
+                .filename(format!(
+                    "/fts-sqlite-{}",
+                    IN_MEMORY_DB_SEQ.fetch_add(1, Ordering::Relaxed)
+                ))
+                .vfs("memdb"),
+        };
 
         // Use the same hardcoded pragmas as the original open() method
-        let options =
-            sqlite::SqliteConnectOptions::from_str(db_path.as_deref().unwrap_or(":memory:"))?
-                .busy_timeout(Duration::from_secs(5))
-                .foreign_keys(true)
-                .journal_mode(sqlite::SqliteJournalMode::Wal)
-                .synchronous(sqlite::SqliteSynchronous::Normal)
+        let options = options
+            .busy_timeout(config.busy_timeout)
+            .foreign_keys(true)
+            .journal_mode(sqlite::SqliteJournalMode::Wal)
+            .synchronous(sqlite::SqliteSynchronous::Normal)
+            .pragma("cache_size", "1000000000")
+            .pragma("journal_size_limit", config.journal_size_limit.to_string())
+            .pragma("wal_autocheckpoint", config.wal_autocheckpoint.to_string())
+            .pragma("mmap_size", "134217728")
+            .pragma("temp_store", "memory")
+            .create_if_missing(config.create_if_missing);
+
+        // SQLCipher must be keyed before anything else is read from the file,
+        // which sqlx ensures by always issuing the `key` pragma first
+        #[cfg(feature = "sqlcipher")]
+        let options = match (&config.database_path, &config.encryption_key) {
+            (Some(_), Some(key)) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+            _ => options,
+        };
+
+        // The writer's connection is never closed, as an in-memory database
+        // only lives as long as some connection to it remains open.
+        let writer = sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            // Only the writer may set the auto-vacuum mode, which takes effect
+            // when the database is created
+            .connect_with(
+                options
+                    .clone()
+                    .auto_vacuum(sqlite::SqliteAutoVacuum::Incremental),
+            )
+            .await?;
+
+        // The reader is opened only once the writer has created the database,
+        // and any attempt to write through it fails with `SQLITE_READONLY`.
+        let reader = sqlite::SqlitePoolOptions::new()
+            .connect_with(options.read_only(true))
+            .await?;
+
+        // Each replica is a copy of the database maintained by external
+        // replication, which is only ever read, so it must already exist and
+        // is left in whichever journal mode its replication keeps it.
+        let mut replicas = Vec::with_capacity(config.replica_paths.len());
+        for path in &config.replica_paths {
+            let options = sqlite::SqliteConnectOptions::new()
+                .filename(path)
+                .busy_timeout(config.busy_timeout)
                 .pragma("cache_size", "1000000000")
-                .pragma("journal_size_limit", "27103364")
                 .pragma("mmap_size", "134217728")
                 .pragma("temp_store", "memory")
-                .create_if_missing(config.create_if_missing);
+                .read_only(true);
+            #[cfg(feature = "sqlcipher")]
+            let options = match &config.encryption_key {
+                Some(key) => options.pragma("key", format!("'{}'", key.replace('\'', "''"))),
+                None => options,
+            };
+            replicas.push(
+                sqlite::SqlitePoolOptions::new()
+                    .connect_with(options)
+                    .await?,
+            );
+        }
 
-        // TODO: setting read_only(true) on the reader seems to also lock the writer, at least when using :memory:. Need to investigate.
-        let reader = sqlite::SqlitePoolOptions::new().connect_with(options.clone());
-        let writer = sqlite::SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect_with(options);
-
-        let (reader, writer) = try_join!(reader, writer)?;
-
-        // Run any pending migrations before returning
-        sqlx::migrate!("./schema").run(&writer).await?;
-
-        // Also, ensure there is one row in the batch table.
-        // This is important because of the trigger-managed temporal tables.
-        // The `batch` table consists of a single row with JSON columns. On update,
-        // a trigger will "explode" the JSON values into the appropriate outcome-tracking
-        // tables, similarly to how we manage portfolios and demand curves.
-        sqlx::query!(
-            r#"
-            insert into
-                batch (id, as_of, portfolio_outcomes, product_outcomes)
-            values
-                (0, $1, jsonb('{}'), jsonb('{}'))
-            on conflict
-                do nothing
-            "#,
-            as_of
-        )
-        .execute(&writer)
-        .await?;
+        Ok(Self {
+            reader,
+            writer,
+            replicas,
+            next_reader: Default::default(),
+            archive_path: config.archive_path.clone(),
+            #[cfg(feature = "parquet")]
+            export_path: config.export_path.clone(),
+            checkpoint_hook: None,
+            market_id: config.market_id.clone(),
+            slow_query_threshold: config.slow_query_threshold,
+            record_batch_inputs: config.record_batch_inputs,
+            maintenance: config.maintenance.clone(),
+            write_retries: config.write_retries,
+            write_retry_backoff: config.write_retry_backoff,
+            retry_counters: Default::default(),
+            running_batches: Default::default(),
+            unit_of_work: None,
+        })
+    }
 
-        Ok(Self { reader, writer })
+    /// Scope a handle to another market of the same database.
+    ///
+    /// The handle shares the connection pools of this one, so any number of
+    /// markets may be hosted by a single process. Each market is isolated from
+    /// the others: its products, demands, portfolios, batch outcomes, and
+    /// settlements are neither visible to nor affected by any other market.
+    ///
+    /// # Arguments
+    ///
+    /// * `market_id` - The market to which the handle is scoped
+    /// * `as_of` - Initial timestamp for the market's batch if the market is new
+    pub async fn market(
+        &self,
+        market_id: impl Into<String>,
+        as_of: types::DateTime,
+    ) -> Result<Self, sqlx::Error> {
+        let market_id = market_id.into();
+        ensure_batch(&mut *self.acquire_writer().await?, &market_id, as_of)
+            .timed("ensure_batch", self.slow_query_threshold)
+            .await?;
+        Ok(Self {
+            market_id,
+            ..self.clone()
+        })
     }
 }
+
+/// Ensure the market has a row in the batch table.
+///
+/// This is important because of the trigger-managed temporal tables. The
+/// `batch` table consists of a single row per market with JSON columns. On
+/// update, a trigger will "explode" the JSON values into the appropriate
+/// outcome-tracking tables, similarly to how we manage portfolios and demand curves.
+async fn ensure_batch<'c, E: sqlx::SqliteExecutor<'c>>(
+    writer: E,
+    market_id: &str,
+    as_of: types::DateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into
+            batch (market_id, as_of, portfolio_outcomes, product_outcomes)
+        values
+            ($1, $2, jsonb('{}'), jsonb('{}'))
+        on conflict
+            do nothing
+        "#,
+        market_id,
+        as_of
+    )
+    .execute(writer)
+    .await?;
+    Ok(())
+}
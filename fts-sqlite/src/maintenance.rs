@@ -0,0 +1,177 @@
+//! Periodic maintenance of the database's query plans and free pages.
+//!
+//! The history tables churn continuously: outcomes are superseded by every
+//! batch and pruned by retention. Over weeks, the statistics from which SQLite
+//! plans its queries drift from the data, and the pages freed by pruning are
+//! left unused within the file. A maintenance pass refreshes the statistics
+//! (`PRAGMA optimize` and `ANALYZE`) and returns free pages to the filesystem
+//! (`PRAGMA incremental_vacuum`). As each pass holds the sole writer
+//! connection for its duration, [`Db::run_maintenance`] waits for a quiet
+//! period, in which nothing was written, before making one.
+
+use crate::{Db, instrument::Timed as _};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{Instrument as _, Level, event, span};
+
+/// Configuration for the periodic maintenance of the database.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MaintenanceConfig {
+    /// How often to maintain the database (if omitted, it is never maintained)
+    #[serde(default, with = "humantime_serde::option")]
+    pub every: Option<Duration>,
+
+    /// How long nothing must have been written before maintenance proceeds
+    #[serde(default = "default_quiet", with = "humantime_serde")]
+    pub quiet: Duration,
+
+    /// Whether to analyze every table, rather than only those `PRAGMA optimize`
+    /// deems in need of it
+    #[serde(default = "default_true")]
+    pub analyze: bool,
+
+    /// The most pages to free per pass (0 frees every free page)
+    #[serde(default)]
+    pub vacuum_pages: u32,
+}
+
+fn default_quiet() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            every: None,
+            quiet: default_quiet(),
+            analyze: true,
+            vacuum_pages: 0,
+        }
+    }
+}
+
+/// The result of a maintenance pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceRecord {
+    /// Whether every table was analyzed
+    pub analyzed: bool,
+
+    /// The number of free pages returned to the filesystem
+    pub freed_pages: u64,
+
+    /// The number of free pages remaining within the database file
+    pub free_pages: u64,
+
+    /// How long the pass held the writer connection
+    pub elapsed: Duration,
+}
+
+impl Db {
+    /// Maintain the database now, whether or not it is quiet.
+    ///
+    /// The pass is made on the writer's connection, so it never races a write
+    /// of this process, but writes wait for it to complete. Free pages can
+    /// only be returned to the filesystem if the database uses incremental
+    /// auto-vacuum, as databases created by [`Db::open`] do; an older
+    /// database must be vacuumed in full once (`VACUUM`) to adopt it.
+    pub async fn maintain(&self) -> Result<MaintenanceRecord, sqlx::Error> {
+        let config = &self.maintenance;
+        let threshold = self.slow_query_threshold;
+        let mut conn = self.acquire_writer().await?;
+        let start = Instant::now();
+
+        sqlx::query("pragma optimize")
+            .execute(&mut *conn)
+            .timed("maintain.optimize", threshold)
+            .await?;
+
+        if config.analyze {
+            sqlx::query("analyze")
+                .execute(&mut *conn)
+                .timed("maintain.analyze", threshold)
+                .await?;
+        }
+
+        let before: i64 = sqlx::query_scalar("pragma freelist_count")
+            .fetch_one(&mut *conn)
+            .timed("maintain.freelist", threshold)
+            .await?;
+        sqlx::query(&format!(
+            "pragma incremental_vacuum({})",
+            config.vacuum_pages
+        ))
+        .execute(&mut *conn)
+        .timed("maintain.vacuum", threshold)
+        .await?;
+        let after: i64 = sqlx::query_scalar("pragma freelist_count")
+            .fetch_one(&mut *conn)
+            .timed("maintain.freelist", threshold)
+            .await?;
+
+        Ok(MaintenanceRecord {
+            analyzed: config.analyze,
+            freed_pages: before.saturating_sub(after).max(0) as u64,
+            free_pages: after.max(0) as u64,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Maintain the database every `every` of its maintenance configuration,
+    /// each time once nothing has been written for `quiet`.
+    ///
+    /// Every write of this process is made through the writer connection, so
+    /// the database is deemed quiet when that connection has made no changes
+    /// over the period. If no interval is configured, this never maintains
+    /// the database and never returns.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(sqlx::Error)` if the database could not be maintained
+    pub async fn run_maintenance(&self) -> Result<(), sqlx::Error> {
+        let Some(every) = self.maintenance.every else {
+            return std::future::pending().await;
+        };
+
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick completes immediately, so is skipped rather than
+        // maintaining the database as soon as it is opened
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let mut changes = self.total_changes().await?;
+            loop {
+                tokio::time::sleep(self.maintenance.quiet).await;
+                let latest = self.total_changes().await?;
+                if latest == changes {
+                    break;
+                }
+                changes = latest;
+            }
+
+            let span = span!(Level::INFO, "maintaining");
+            let record = self.maintain().instrument(span).await?;
+            event!(
+                Level::INFO,
+                analyzed = record.analyzed,
+                freed_pages = record.freed_pages,
+                free_pages = record.free_pages,
+                elapsed = ?record.elapsed,
+            );
+        }
+    }
+
+    /// The number of rows changed through the writer connection since it was opened
+    async fn total_changes(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("select total_changes()")
+            .fetch_one(&mut *self.acquire_writer().await?)
+            .timed("maintain.changes", self.slow_query_threshold)
+            .await
+    }
+}
@@ -0,0 +1,112 @@
+//! Retrying write transactions which find the database busy.
+//!
+//! Within a process, writes are serialized by the single writer connection,
+//! but another process (or a replication tool checkpointing the WAL) may
+//! still hold the database's write lock. A write transaction begins by taking
+//! the lock (`BEGIN IMMEDIATE`), waiting up to the `busy_timeout` for it, so
+//! that it cannot fail midway upon upgrading a read lock. Should the lock
+//! still be held once the timeout elapses, the transaction is begun anew
+//! after a backoff, doubling at each retry and jittered so that contending
+//! writers do not retry in lockstep. Each retry is logged as a warning and
+//! counted in the [`WriteRetryStats`] of the database.
+
+use crate::{Db, instrument::Timed as _};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use std::{
+    hash::{BuildHasher as _, RandomState},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tracing::{Level, event};
+
+/// The counts of write transactions retried since the database was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteRetryStats {
+    /// The number of retries made, across every write transaction
+    pub retries: u64,
+
+    /// The number of write transactions which found the database busy on
+    /// every attempt, and so failed
+    pub exhausted: u64,
+}
+
+/// The counters of retries, shared by every handle of the database
+#[derive(Debug, Default)]
+pub(crate) struct RetryCounters {
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl Db {
+    /// The counts of write transactions retried since the database was
+    /// opened, across every market of the database.
+    pub fn write_retry_stats(&self) -> WriteRetryStats {
+        WriteRetryStats {
+            retries: self.retry_counters.retries.load(Ordering::Relaxed),
+            exhausted: self.retry_counters.exhausted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Begin a transaction on the writer which holds the write lock from the
+    /// outset, retrying (up to `write_retries` times) if the database is busy.
+    pub(crate) async fn begin_immediate(
+        &self,
+    ) -> Result<Transaction<'static, Sqlite>, sqlx::Error> {
+        let mut retry = 0;
+        loop {
+            match self
+                .writer
+                .begin_with("begin immediate")
+                .timed("begin_immediate", self.slow_query_threshold)
+                .await
+            {
+                Err(error) if is_busy(&error) && retry < self.write_retries => {
+                    retry += 1;
+                    self.retry_counters.retries.fetch_add(1, Ordering::Relaxed);
+
+                    let delay = backoff(self.write_retry_backoff, retry);
+                    event!(
+                        Level::WARN,
+                        retry,
+                        delay_ms = delay.as_secs_f64() * 1000.0,
+                        %error,
+                        "retrying busy write transaction"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(error) if is_busy(&error) => {
+                    self.retry_counters
+                        .exhausted
+                        .fetch_add(1, Ordering::Relaxed);
+                    event!(
+                        Level::ERROR,
+                        retries = retry,
+                        %error,
+                        "write transaction still busy after retrying"
+                    );
+                    return Err(error);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether the error is `SQLITE_BUSY` or `SQLITE_LOCKED` (or any of their
+/// extended codes)
+fn is_busy(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|error| error.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+/// The delay before the given retry (counting from 1): between half and all
+/// of the base delay doubled for each retry before it
+fn backoff(base: Duration, retry: u32) -> Duration {
+    let ceiling = base.saturating_mul(1 << (retry - 1).min(16));
+    let jitter = RandomState::new().hash_one(retry) as f64 / u64::MAX as f64;
+    ceiling.mul_f64(0.5 + 0.5 * jitter)
+}
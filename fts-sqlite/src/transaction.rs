@@ -0,0 +1,264 @@
+//! Units of work spanning several repository operations.
+//!
+//! Each repository operation is atomic on its own, but some requests need
+//! several operations to succeed or fail together, e.g. creating a demand
+//! along with the portfolio referencing it. [`Db::transaction`] runs such a
+//! unit of work within a single transaction, through a handle whose every
+//! operation reads and writes through that transaction.
+//!
+//! Likewise, reads are spread across the pool of reader connections, so
+//! several reads made in turn may each observe a different state of the
+//! database if it is written in between. [`Db::snapshot`] runs a sequence of
+//! reads within a single read transaction, so that they all observe the
+//! database as of the same point in time.
+
+use crate::{Db, instrument::Timed as _};
+use futures_util::future::BoxFuture;
+use sqlx::{Connection as _, Sqlite, SqliteConnection, Transaction, pool::PoolConnection};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, atomic::Ordering},
+};
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// The transaction shared by the handles of a unit of work, which is taken
+/// once the unit of work completes
+pub(crate) type UnitOfWork = Arc<Mutex<Option<Transaction<'static, Sqlite>>>>;
+
+/// A connection on which a repository operation is performed: either one of
+/// the handle's pools, or the transaction of its unit of work
+pub(crate) enum Conn<'a> {
+    Pool(PoolConnection<Sqlite>),
+    UnitOfWork(MappedMutexGuard<'a, Transaction<'static, Sqlite>>),
+}
+
+impl Deref for Conn<'_> {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Pool(conn) => conn,
+            Self::UnitOfWork(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for Conn<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Pool(conn) => conn,
+            Self::UnitOfWork(tx) => tx,
+        }
+    }
+}
+
+impl Db {
+    /// Perform several repository operations atomically.
+    ///
+    /// `f` receives a handle scoped to the same market as this one, but whose
+    /// operations are all performed within a single transaction. The
+    /// transaction is committed if `f` succeeds, and rolled back if it fails.
+    /// Operations which are themselves transactional (such as purges and
+    /// settlements) are nested within it as savepoints. If this handle
+    /// already belongs to a unit of work, `f` is simply run as part of it.
+    ///
+    /// The transaction takes the write lock as it begins, retrying if the
+    /// database is busy, so its operations never find the database busy. The
+    /// unit of work holds the sole writer connection for its duration, so
+    /// it should be kept short, and other handles of the database must not be
+    /// awaited within `f`. Nor can history be pruned into an archive within
+    /// it, as a database cannot be attached within a transaction.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use fts_sqlite::{Db, types::{BidderId, DemandId, PortfolioId, ProductId}};
+    /// # use fts_core::{models::{Actor, DemandCurve}, ports::{DemandRepository, PortfolioRepository}};
+    /// # async fn example(db: Db, bidder_id: BidderId, demand_id: DemandId, portfolio_id: PortfolioId, product_id: ProductId, curve: DemandCurve, as_of: fts_sqlite::types::DateTime) -> Result<(), sqlx::Error> {
+    /// db.transaction(|db| {
+    ///     Box::pin(async move {
+    ///         <Db as DemandRepository<()>>::create_demand(
+    ///             db, demand_id, bidder_id, (), curve, Actor::Bidder, as_of,
+    ///         )
+    ///         .await?;
+    ///         <Db as PortfolioRepository<()>>::create_portfolio(
+    ///             db,
+    ///             portfolio_id,
+    ///             bidder_id,
+    ///             (),
+    ///             std::iter::once((demand_id, 1.0)).collect(),
+    ///             std::iter::once((product_id, 1.0)).collect(),
+    ///             Actor::Bidder,
+    ///             as_of,
+    ///         )
+    ///         .await
+    ///     })
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn transaction<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: for<'a> FnOnce(&'a Db) -> BoxFuture<'a, Result<R, E>> + Send,
+        R: Send,
+        E: From<sqlx::Error> + Send,
+    {
+        if self.unit_of_work.is_some() {
+            return f(self).await;
+        }
+
+        let tx = self.begin_immediate().await?;
+        self.within(tx, f).await
+    }
+
+    /// Perform several read-only repository operations against a consistent
+    /// snapshot of the database.
+    ///
+    /// `f` receives a handle scoped to the same market as this one, but whose
+    /// operations all read through a single read transaction on one of the
+    /// reader connections. Every read therefore observes the database as of
+    /// the moment the snapshot was taken, regardless of any writes committed
+    /// meanwhile. Writes through the handle fail, as the reader connections
+    /// are read-only. If this handle already belongs to a unit of work, `f`
+    /// is simply run as part of it, which is itself consistent.
+    ///
+    /// The snapshot holds a reader connection for its duration and prevents
+    /// the write-ahead log from being checkpointed past it, so it should be
+    /// kept short. An in-memory database has no write-ahead log, so its
+    /// writes instead wait for any snapshot to end. The snapshot is always
+    /// taken on the database itself, rather than any of its replicas, so that
+    /// it observes every write committed before it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use fts_sqlite::{Db, types::{DateTime, DemandId, PortfolioId, ProductId}};
+    /// # use fts_core::{models::DateTimeRangeQuery, ports::{BatchRepository, PortfolioRepository}};
+    /// # type Solver = fts_solver::clarabel::ClarabelSolver<DemandId, PortfolioId, ProductId>;
+    /// # async fn example(db: Db, portfolio_id: PortfolioId, as_of: DateTime) -> Result<(), sqlx::Error> {
+    /// let (portfolio, outcomes) = db
+    ///     .snapshot(|db| {
+    ///         Box::pin(async move {
+    ///             let portfolio = <Db as PortfolioRepository<()>>::get_portfolio_with_expanded_products(
+    ///                 db, portfolio_id, as_of,
+    ///             )
+    ///             .await?;
+    ///             let outcomes = <Db as BatchRepository<Solver>>::get_portfolio_outcomes(
+    ///                 db, portfolio_id, DateTimeRangeQuery { before: None, after: None }, 10,
+    ///             )
+    ///             .await?;
+    ///             Ok::<_, sqlx::Error>((portfolio, outcomes))
+    ///         })
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn snapshot<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: for<'a> FnOnce(&'a Db) -> BoxFuture<'a, Result<R, E>> + Send,
+        R: Send,
+        E: From<sqlx::Error> + Send,
+    {
+        if self.unit_of_work.is_some() {
+            return f(self).await;
+        }
+
+        let mut tx = self.reader.begin().await?;
+        // A read transaction only takes its snapshot upon its first read
+        sqlx::query("select count(*) from sqlite_schema")
+            .execute(&mut *tx)
+            .timed("snapshot", self.slow_query_threshold)
+            .await?;
+        self.within(tx, f).await
+    }
+
+    /// Run `f` with a handle whose operations are all performed within `tx`,
+    /// committing it if `f` succeeds and rolling it back if it fails
+    async fn within<F, R, E>(&self, tx: Transaction<'static, Sqlite>, f: F) -> Result<R, E>
+    where
+        F: for<'a> FnOnce(&'a Db) -> BoxFuture<'a, Result<R, E>> + Send,
+        R: Send,
+        E: From<sqlx::Error> + Send,
+    {
+        let unit_of_work: UnitOfWork = Arc::new(Mutex::new(Some(tx)));
+        let db = Self {
+            unit_of_work: Some(unit_of_work.clone()),
+            ..self.clone()
+        };
+
+        let result = f(&db).await;
+
+        // Any handle leaked from `f` is left without a transaction
+        let tx = unit_of_work
+            .lock()
+            .await
+            .take()
+            .ok_or(sqlx::Error::PoolClosed)?;
+        match result {
+            Ok(value) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Err(error) => {
+                tx.rollback().await?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Acquire a connection for reading, which is the transaction of the
+    /// unit of work (if any), so that its own writes are visible. Otherwise,
+    /// each read is made in turn on the reader or one of the replicas.
+    pub(crate) async fn acquire_reader(&self) -> Result<Conn<'_>, sqlx::Error> {
+        match self.unit_of_work.as_ref() {
+            Some(unit_of_work) => lock(unit_of_work).await,
+            None => {
+                let turn = self.next_reader.fetch_add(1, Ordering::Relaxed);
+                let pool = match turn % (self.replicas.len() + 1) {
+                    0 => &self.reader,
+                    replica => &self.replicas[replica - 1],
+                };
+                Ok(Conn::Pool(pool.acquire().await?))
+            }
+        }
+    }
+
+    /// Acquire a connection for writing, which is the transaction of the
+    /// unit of work (if any).
+    pub(crate) async fn acquire_writer(&self) -> Result<Conn<'_>, sqlx::Error> {
+        match self.unit_of_work.as_ref() {
+            Some(unit_of_work) => lock(unit_of_work).await,
+            None => Ok(Conn::Pool(self.writer.acquire().await?)),
+        }
+    }
+
+    /// Lock the transaction of the unit of work, if any, within which a write
+    /// transaction is to be begun by [`Db::begin_write`].
+    pub(crate) async fn lock_unit_of_work(&self) -> Result<Option<Conn<'_>>, sqlx::Error> {
+        match self.unit_of_work.as_ref() {
+            Some(unit_of_work) => lock(unit_of_work).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Begin a write transaction, which is a savepoint of the unit of work (if
+    /// any), or else a transaction on the writer which takes the write lock as
+    /// it begins, retrying if the database is busy.
+    pub(crate) async fn begin_write<'c>(
+        &self,
+        unit_of_work: &'c mut Option<Conn<'_>>,
+    ) -> Result<Transaction<'c, Sqlite>, sqlx::Error> {
+        match unit_of_work {
+            Some(conn) => conn.begin().await,
+            None => self.begin_immediate().await,
+        }
+    }
+}
+
+async fn lock(unit_of_work: &UnitOfWork) -> Result<Conn<'_>, sqlx::Error> {
+    MutexGuard::try_map(unit_of_work.lock().await, Option::as_mut)
+        .map(Conn::UnitOfWork)
+        .map_err(|_| sqlx::Error::PoolClosed)
+}
@@ -18,6 +18,7 @@ pub use datetime::DateTime;
 mod ids;
 pub use ids::{BidderId, DemandId, PortfolioId, ProductId};
 
+#[derive(sqlx::FromRow)]
 pub(crate) struct DemandRow<AppData> {
     pub id: DemandId,
     pub valid_from: DateTime,
@@ -55,6 +56,7 @@ where
     }
 }
 
+#[derive(sqlx::FromRow)]
 pub(crate) struct PortfolioRow<AppData> {
     pub id: PortfolioId,
     pub valid_from: DateTime,
@@ -128,6 +130,26 @@ impl<T> Into<ValueRecord<DateTime, T>> for ValueRow<T> {
             valid_from: self.valid_from,
             valid_until: self.valid_until,
             value: self.value.0,
+            actor: None,
+        }
+    }
+}
+
+/// A history row, additionally attributed to the actor responsible for the change
+pub(crate) struct HistoryRow<Value> {
+    pub valid_from: DateTime,
+    pub valid_until: Option<DateTime>,
+    pub value: sqlx::types::Json<Value>,
+    pub actor: String,
+}
+
+impl<T> From<HistoryRow<T>> for ValueRecord<DateTime, T> {
+    fn from(row: HistoryRow<T>) -> Self {
+        ValueRecord {
+            valid_from: row.valid_from,
+            valid_until: row.valid_until,
+            value: row.value.0,
+            actor: row.actor.parse().ok(),
         }
     }
 }
@@ -0,0 +1,75 @@
+mod common;
+
+#[path = "../../repository-tests/app_data_search.rs"]
+mod app_data_search;
+
+use fts_sqlite::{Db, config::SqliteConfig};
+use sqlx::Row as _;
+
+#[tokio::test]
+async fn test_app_data_indexes() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let now = time::OffsetDateTime::now_utc();
+
+    async fn indexes(db: &Db) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "select name from sqlite_schema where type = 'index' and name glob '*_app_data_*' order by name",
+        )
+        .fetch_all(&db.reader)
+        .await
+    }
+
+    let result = async {
+        // Every table is indexed on each configured path
+        let config = SqliteConfig {
+            database_path: Some(path.clone()),
+            app_data_indexes: vec!["$.desk".to_string(), "$.kind".to_string()],
+            ..Default::default()
+        };
+        let db = Db::open(&config, now.into()).await?;
+        assert_eq!(indexes(&db).await?.len(), 6);
+
+        // and a search filtering on an indexed path uses its index
+        let plan = sqlx::query(
+            "explain query plan select id from demand where market_id = '' and json_extract(demand.app_data, '$.desk') = 'east'",
+        )
+        .fetch_all(&db.reader)
+        .await?;
+        assert!(
+            plan.iter()
+                .any(|step| step.get::<String, _>("detail").contains("demand_app_data_"))
+        );
+        db.reader.close().await;
+        db.writer.close().await;
+
+        // Indexes on paths no longer configured are dropped when reopened
+        let config = SqliteConfig {
+            app_data_indexes: vec!["$.kind".to_string()],
+            ..config
+        };
+        let db = Db::open(&config, now.into()).await?;
+        let remaining = indexes(&db).await?;
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.iter().all(|name| name.ends_with("242e6b696e64")));
+        db.reader.close().await;
+        db.writer.close().await;
+
+        // and a path must be a JSON path
+        let config = SqliteConfig {
+            app_data_indexes: vec!["kind".to_string()],
+            ..config
+        };
+        assert!(Db::open(&config, now.into()).await.is_err());
+
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+
+    result
+}
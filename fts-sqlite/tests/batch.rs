@@ -0,0 +1,291 @@
+mod common;
+
+#[path = "../../repository-tests/batch.rs"]
+mod batch;
+
+use batch::{Solver, open_market};
+use common::TestApp;
+use fts_core::{
+    models::{
+        Actor, Basis, BatchFailure, DateTimeRangeQuery, DemandCurve, Map, Point, PwlCurve, Weights,
+    },
+    ports::{
+        Application, BatchRepository, DemandRepository as _, PortfolioRepository,
+        ProductRepository as _, Solver as _,
+    },
+};
+use fts_solver::{PortfolioOutcome, ProductOutcome};
+use fts_sqlite::{
+    Db,
+    config::SqliteConfig,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use futures_util::TryStreamExt as _;
+use std::time::Duration;
+
+/// The error of a solver which never finds a solution
+#[derive(Debug)]
+struct Infeasible;
+
+impl std::fmt::Display for Infeasible {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the auction is infeasible")
+    }
+}
+
+impl std::error::Error for Infeasible {}
+
+/// A solver which fails every batch
+struct FailingSolver;
+
+impl fts_core::ports::Solver<DemandId, PortfolioId, ProductId> for FailingSolver {
+    type Error = Infeasible;
+    type PortfolioOutcome = fts_solver::PortfolioOutcome;
+    type ProductOutcome = fts_solver::ProductOutcome;
+    type State = ();
+
+    async fn solve(
+        &self,
+        _demand_curves: Map<DemandId, DemandCurve>,
+        _portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        _state: (),
+    ) -> Result<
+        (
+            Map<PortfolioId, Self::PortfolioOutcome>,
+            Map<ProductId, Self::ProductOutcome>,
+        ),
+        Infeasible,
+    > {
+        Err(Infeasible)
+    }
+}
+
+#[tokio::test]
+async fn test_stream_outcomes() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    let mut portfolios = Vec::new();
+    for rate in [10.0, 0.0] {
+        let bidder_id = BidderId(uuid::Uuid::new_v4());
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: rate - 10.0,
+                    price: 10.0,
+                },
+                Point { rate, price: 0.0 },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        let portfolio_id = app.generate_portfolio_id(&()).0;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            portfolio_id,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        portfolios.push(portfolio_id);
+    }
+
+    // Enough batches that the stream spans more than one page
+    let batches = 1001;
+    for secs in 1..=batches {
+        db.run_batch(
+            DateTime::from(now + Duration::from_secs(secs)),
+            app.solver(),
+            (),
+        )
+        .await??;
+    }
+
+    let all = DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let outcomes: Vec<_> =
+        <Db as BatchRepository<Solver>>::stream_product_outcomes(db, product_id, all)
+            .try_collect()
+            .await?;
+    assert_eq!(outcomes.len(), batches as usize);
+    assert_eq!(
+        outcomes[0].valid_from,
+        DateTime::from(now + Duration::from_secs(batches))
+    );
+    assert!(
+        outcomes
+            .windows(2)
+            .all(|pair| pair[0].valid_from > pair[1].valid_from)
+    );
+
+    // The range is respected, just as when paginating
+    let query = DateTimeRangeQuery {
+        before: Some((now + Duration::from_secs(11)).into()),
+        after: Some((now + Duration::from_secs(1)).into()),
+    };
+    let outcomes: Vec<_> =
+        <Db as BatchRepository<Solver>>::stream_portfolio_outcomes(db, portfolios[0], query)
+            .try_collect()
+            .await?;
+    assert_eq!(outcomes.len(), 10);
+    assert_eq!(
+        outcomes[9].valid_from,
+        (now + Duration::from_secs(1)).into()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_runs() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    db.run_batch(first, app.solver(), ()).await??;
+
+    // A failed batch leaves the outcomes alone, but is recorded all the same
+    let error = db.run_batch(second, FailingSolver, ()).await?.unwrap_err();
+    assert_eq!(error.to_string(), "the auction is infeasible");
+    let latest = <Db as BatchRepository<Solver>>::get_latest_batch(db)
+        .await?
+        .unwrap();
+    assert_eq!(latest.as_of, first);
+
+    let all = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let runs = <Db as BatchRepository<Solver>>::get_batch_runs(db, all(), 1).await?;
+    assert_eq!(runs.results.len(), 1);
+    assert_eq!(runs.results[0].valid_from, second);
+    assert_eq!(
+        runs.results[0].value.error.as_deref(),
+        Some("the auction is infeasible")
+    );
+
+    let runs = <Db as BatchRepository<Solver>>::get_batch_runs(db, runs.more.unwrap(), 1).await?;
+    assert!(runs.more.is_none());
+    let run = &runs.results[0];
+    assert_eq!(run.valid_from, first);
+    assert_eq!(run.value.error, None);
+    assert_eq!(run.value.solver.backend, "clarabel");
+    assert!(run.value.solver.settings.contains_key("max_iter"));
+    assert!(!run.value.solver.status.is_empty());
+
+    // Retrying a batch at the same time replaces its record
+    db.run_batch(second, app.solver(), ()).await??;
+    let runs = <Db as BatchRepository<Solver>>::get_batch_runs(db, all(), 10).await?;
+    assert_eq!(runs.results.len(), 2);
+    assert_eq!(runs.results[0].value.error, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_attempts() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    let third = DateTime::from(now + Duration::from_secs(3));
+
+    // A successful batch is not an attempt worth recording
+    db.run_batch(first, app.solver(), ()).await??;
+    db.run_batch(second, FailingSolver, ()).await?.unwrap_err();
+
+    let all = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let attempts = <Db as BatchRepository<Solver>>::get_batch_attempts(db, all(), 10).await?;
+    assert_eq!(attempts.results.len(), 1);
+    assert_eq!(attempts.results[0].valid_from, second);
+    assert_eq!(attempts.results[0].value.category, BatchFailure::Solver);
+    assert_eq!(
+        attempts.results[0].value.message,
+        "the auction is infeasible"
+    );
+
+    // Should the inputs not be gathered, the failure is recorded all the same
+    db.reader.close().await;
+    assert!(db.run_batch(third, app.solver(), ()).await.is_err());
+    let categories: Vec<(String, String)> =
+        sqlx::query_as("select as_of, category from batch_attempt order by as_of")
+            .fetch_all(&db.writer)
+            .await?;
+    assert_eq!(categories.len(), 2);
+    assert_eq!(categories[1].1, "database");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recorded_batch_inputs() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let config = SqliteConfig {
+        record_batch_inputs: true,
+        ..Default::default()
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let db = app.database();
+    let product_id = open_market(&app, now).await?;
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    db.run_batch(first, app.solver(), ()).await??;
+    db.run_batch(second, FailingSolver, ()).await?.unwrap_err();
+
+    // The recorded inputs are those gathered afresh at the time of the batch
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, first)
+        .await?
+        .unwrap();
+    let gathered = db.gather_auction(first).await?;
+    assert_eq!(
+        serde_json::to_value(&gathered)?,
+        serde_json::to_value(&inputs)?
+    );
+
+    // As are the outcomes recorded for the batch as a whole, of which a
+    // failed batch has none
+    let (demand_curves, portfolios) = inputs.into_parts();
+    let (_, products) = app.solver().solve(demand_curves, portfolios, ()).await?;
+    let (portfolio_outcomes, product_outcomes) = db
+        .get_batch_outcomes::<PortfolioOutcome, ProductOutcome>(first)
+        .await?;
+    assert_eq!(portfolio_outcomes.len(), 2);
+    assert_eq!(
+        product_outcomes[&product_id].price,
+        products[&product_id].price
+    );
+    let (portfolio_outcomes, product_outcomes) = db
+        .get_batch_outcomes::<PortfolioOutcome, ProductOutcome>(second)
+        .await?;
+    assert!(portfolio_outcomes.is_empty() && product_outcomes.is_empty());
+
+    // The inputs of a failed batch are kept, so that it may be reproduced
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, second).await?;
+    assert_eq!(inputs.map(|inputs| inputs.portfolios.len()), Some(2));
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository as _};
+use fts_sqlite::{
+    Db,
+    checkpoint::{CheckpointMode, CheckpointRecord},
+    config::SqliteConfig,
+};
+use std::{
+    ffi::OsString,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+fn with_suffix(path: &Path, suffix: &str) -> OsString {
+    let mut file = path.to_owned().into_os_string();
+    file.push(suffix);
+    file
+}
+
+#[tokio::test]
+async fn test_checkpoint() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        wal_autocheckpoint: 0,
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+
+    let checkpoints: Arc<Mutex<Vec<CheckpointRecord>>> = Default::default();
+    let hooked = checkpoints.clone();
+    let db = Db::open(&config, now.into())
+        .await?
+        .with_checkpoint_hook(move |record| hooked.lock().unwrap().push(record.clone()));
+    let app = TestApp(db);
+
+    for _ in 0..10 {
+        let product_id = app.generate_product_id(&()).0;
+        app.database()
+            .create_product(product_id, (), now.into())
+            .await?;
+    }
+
+    // Without automatic checkpoints, every write remains in the WAL
+    let wal = with_suffix(&path, "-wal");
+    assert!(std::fs::metadata(&wal)?.len() > 0);
+
+    let passive = app.database().checkpoint(CheckpointMode::Passive).await;
+    let truncate = app.database().checkpoint(CheckpointMode::Truncate).await;
+    let wal_len = std::fs::metadata(&wal)?.len();
+
+    let db = &app.0;
+    db.reader.close().await;
+    db.writer.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&path, suffix));
+    }
+
+    let passive = passive?;
+    assert!(!passive.busy);
+    assert!(passive.wal_frames.is_some_and(|frames| frames > 0));
+    assert_eq!(passive.checkpointed_frames, passive.wal_frames);
+
+    let truncate = truncate?;
+    assert!(!truncate.busy);
+    assert_eq!(wal_len, 0);
+
+    // The hook observes every checkpoint, in order
+    assert_eq!(*checkpoints.lock().unwrap(), vec![passive, truncate]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint_without_wal() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    // In-memory databases use a rollback journal, so have no WAL to checkpoint
+    let record = db.checkpoint(CheckpointMode::Full).await?;
+    assert!(!record.busy);
+    assert_eq!(record.wal_frames, None);
+    assert_eq!(record.checkpointed_frames, None);
+
+    Ok(())
+}
@@ -1,12 +1,26 @@
 use fts_core::ports::Application;
 use fts_solver::clarabel::ClarabelSolver;
-use fts_sqlite::{
-    Db,
-    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
-};
+use fts_sqlite::config::SqliteConfig;
+use types::{BidderId, DateTime, DemandId, PortfolioId, ProductId};
+
+// The backend under test, as seen by the shared repository tests
+pub use fts_sqlite::{Db, types};
 
 pub struct TestApp(pub Db);
 
+// Not every test uses each fixture
+#[allow(dead_code)]
+impl TestApp {
+    /// Open a fresh in-memory database (which is always available)
+    pub async fn open(as_of: DateTime) -> anyhow::Result<Option<Self>> {
+        Ok(Some(Self(Db::open(&SqliteConfig::default(), as_of).await?)))
+    }
+
+    pub fn record_batch_inputs(&mut self, record: bool) {
+        self.0.record_batch_inputs = record;
+    }
+}
+
 impl Application for TestApp {
     type Context = ();
     type DemandData = ();
@@ -66,4 +80,12 @@ impl Application for TestApp {
     async fn can_run_batch(&self, _context: &Self::Context) -> bool {
         false
     }
+
+    async fn can_run_settlement(&self, _context: &Self::Context) -> bool {
+        false
+    }
+
+    async fn can_purge_bid(&self, _context: &Self::Context) -> bool {
+        false
+    }
 }
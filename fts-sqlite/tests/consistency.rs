@@ -0,0 +1,72 @@
+mod common;
+
+#[path = "../../repository-tests/consistency.rs"]
+mod consistency;
+
+use common::TestApp;
+use consistency::{Reshaped, expanded_basis, reshape};
+use fts_core::{
+    models::Basis,
+    ports::{Application, HealthRepository as _, ProductRepository},
+};
+use fts_sqlite::{Db, types::DateTime};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_consistency_repair() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+    let Reshaped {
+        portfolio_id,
+        food,
+        vegetable,
+        apple,
+        banana,
+    } = reshape(&app, now).await?;
+
+    // A misfired trigger leaves paths and groups divergent
+    let expected = expanded_basis(db, portfolio_id, at(11)).await?;
+    sqlx::query("update product_tree set ratio = ratio * 2 where src_id = $1 and dst_id = $2")
+        .bind(food)
+        .bind(apple)
+        .execute(&db.writer)
+        .await?;
+    sqlx::query("delete from product_tree where src_id = $1 and dst_id = $1")
+        .bind(banana)
+        .execute(&db.writer)
+        .await?;
+    sqlx::query("delete from portfolio_product where portfolio_id = $1 and product_id = $2")
+        .bind(portfolio_id)
+        .bind(vegetable)
+        .execute(&db.writer)
+        .await?;
+    assert_ne!(expanded_basis(db, portfolio_id, at(11)).await?, expected);
+
+    let mut products = vec![food, banana];
+    products.sort();
+    let record = db.check_consistency(false).await?;
+    assert_eq!(record.products, products);
+    assert_eq!(record.portfolios, vec![portfolio_id]);
+    assert!(!record.repaired);
+
+    // Repairing them restores the expansion
+    let record = db.check_consistency(true).await?;
+    assert_eq!(record.products, products);
+    assert!(record.repaired);
+    assert!(db.check_consistency(false).await?.is_consistent());
+    assert_eq!(expanded_basis(db, portfolio_id, at(11)).await?, expected);
+    assert_eq!(
+        <Db as ProductRepository<()>>::get_product(db, banana, at(11))
+            .await?
+            .unwrap()
+            .basis,
+        Basis::from_iter([(banana, 1.0)])
+    );
+
+    Ok(())
+}
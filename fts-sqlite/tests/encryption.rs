@@ -0,0 +1,70 @@
+#![cfg(feature = "sqlcipher")]
+
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository};
+use fts_sqlite::{Db, config::SqliteConfig};
+use std::{ffi::OsString, path::Path};
+
+fn with_suffix(path: &Path, suffix: &str) -> OsString {
+    let mut file = path.to_owned().into_os_string();
+    file.push(suffix);
+    file
+}
+
+#[tokio::test]
+async fn test_encryption() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        encryption_key: Some("correct horse's battery staple".to_owned()),
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+
+    let db = Db::open(&config, now.into()).await?;
+    let app = TestApp(db);
+    let product_id = app.generate_product_id(&()).0;
+    app.database()
+        .create_product(product_id, (), now.into())
+        .await?;
+    app.0.reader.close().await;
+    app.0.writer.close().await;
+
+    let header = std::fs::read(&path)?;
+
+    let unkeyed = Db::open(
+        &SqliteConfig {
+            encryption_key: None,
+            ..config.clone()
+        },
+        now.into(),
+    )
+    .await;
+    let miskeyed = Db::open(
+        &SqliteConfig {
+            encryption_key: Some("incorrect".to_owned()),
+            ..config.clone()
+        },
+        now.into(),
+    )
+    .await;
+
+    let db = Db::open(&config, now.into()).await?;
+    let product = <Db as ProductRepository<()>>::get_product(&db, product_id, now.into()).await;
+    db.reader.close().await;
+    db.writer.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&path, suffix));
+    }
+
+    // The file is not recognizable as a SQLite database, and can be opened
+    // neither without its key nor with another, but with its key, the data remains
+    assert!(!header.starts_with(b"SQLite format 3\0"));
+    assert!(unkeyed.is_err());
+    assert!(miskeyed.is_err());
+    assert!(product?.is_some());
+
+    Ok(())
+}
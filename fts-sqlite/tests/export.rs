@@ -0,0 +1,165 @@
+#![cfg(feature = "parquet")]
+
+mod common;
+
+use common::TestApp;
+use fts_core::{
+    models::{Actor, Point, PwlCurve, SettlementConfig},
+    ports::{
+        Application, BatchRepository, DemandRepository as _, PortfolioRepository,
+        ProductRepository as _, RetentionRepository as _, SettlementRepository as _,
+    },
+};
+use fts_sqlite::{Db, config::SqliteConfig, types::BidderId};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::{path::Path, time::Duration};
+
+/// Open a market of a buyer and a seller, with a batch executed at `now + 1s`
+/// and superseded by another at `now + 2s`, and settled at `now + 3s`.
+async fn open_market(config: &SqliteConfig, now: time::OffsetDateTime) -> anyhow::Result<TestApp> {
+    let app = TestApp(Db::open(config, now.into()).await?);
+    let db = app.database();
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    for rate in [10.0, 0.0] {
+        let bidder_id = BidderId(uuid::Uuid::new_v4());
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: rate - 10.0,
+                    price: 10.0,
+                },
+                Point { rate, price: 0.0 },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    for secs in [1, 2] {
+        db.run_batch((now + Duration::from_secs(secs)).into(), app.solver(), ())
+            .await??;
+    }
+    db.settle_activity(SettlementConfig {
+        as_of: (now + Duration::from_secs(3)).into(),
+        time_unit: 3600.0,
+        position_decimals: 0,
+        payment_decimals: 2,
+    })
+    .await?
+    .expect("activity should be settled");
+
+    Ok(app)
+}
+
+fn count_rows(path: &Path) -> anyhow::Result<usize> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path)?)?.build()?;
+    let mut rows = 0;
+    for batch in reader {
+        rows += batch?.num_rows();
+    }
+    Ok(rows)
+}
+
+#[tokio::test]
+async fn test_export_parquet() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let directory = std::env::temp_dir().join(format!("fts-export-{}", uuid::Uuid::new_v4()));
+
+    let app = open_market(&SqliteConfig::default(), now).await?;
+    let db = app.database();
+
+    let record = db
+        .export_parquet(&directory, None, (now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.product_outcomes, 2);
+    assert_eq!(record.portfolio_outcomes, 4);
+    assert_eq!(record.trades, 4);
+    assert_eq!(record.settlement_positions, 2);
+    assert_eq!(record.settlement_payments, 2);
+
+    // Every file lies within the partition of its dataset and date
+    for file in &record.files {
+        let partition = file.parent().unwrap();
+        assert!(
+            partition
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("date=")
+        );
+        assert!(partition.starts_with(&directory));
+    }
+    let rows: usize = record
+        .files
+        .iter()
+        .filter(|file| file.starts_with(directory.join("trade")))
+        .map(|file| count_rows(file))
+        .sum::<anyhow::Result<_>>()?;
+    assert_eq!(rows, 4);
+
+    // Exporting only the second batch omits the first, as well as the settlement
+    let record = db
+        .export_parquet(
+            &directory,
+            Some((now + Duration::from_secs(2)).into()),
+            (now + Duration::from_secs(3)).into(),
+        )
+        .await?;
+    assert_eq!(record.product_outcomes, 1);
+    assert_eq!(record.settlement_positions, 0);
+
+    std::fs::remove_dir_all(&directory)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_pruned_outcomes() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let directory = std::env::temp_dir().join(format!("fts-export-{}", uuid::Uuid::new_v4()));
+
+    let config = SqliteConfig {
+        export_path: Some(directory.clone()),
+        ..Default::default()
+    };
+    let app = open_market(&config, now).await?;
+    let db = app.database();
+
+    // Only the outcomes of the first batch are superseded (and settled)
+    let record = db
+        .prune_history((now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.portfolio_outcomes, 2);
+
+    let mut rows = 0;
+    for entry in std::fs::read_dir(directory.join("portfolio_outcome"))? {
+        for file in std::fs::read_dir(entry?.path())? {
+            rows += count_rows(&file?.path())?;
+        }
+    }
+    assert_eq!(rows, 2);
+
+    std::fs::remove_dir_all(&directory)?;
+
+    Ok(())
+}
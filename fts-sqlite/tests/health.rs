@@ -0,0 +1,126 @@
+use fts_core::ports::HealthRepository as _;
+use fts_sqlite::{Db, config::SqliteConfig};
+
+#[tokio::test]
+async fn test_migrations_are_applied_on_open() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    let status = db.check_health().await?;
+    assert!(status.applied > 0);
+    assert_eq!(status.pending, 0);
+    assert_eq!(status.migrations.len(), status.applied as usize);
+    assert!(status.migrations.iter().all(|migration| migration.applied));
+    assert_eq!(
+        status.version,
+        status.migrations.last().map(|migration| migration.version)
+    );
+    assert!(db.integrity_check().await?.is_empty());
+    assert!(db.verify_migrations().await?.is_empty());
+    assert!(db.used_bytes().await? > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migration_status_before_open() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+
+    // A database which was never opened has every migration pending
+    let connected = Db::connect(&config).await?;
+    let before = connected.migration_status().await;
+
+    // and none once it is opened
+    let db = Db::open(&config, now.into()).await?;
+    let after = connected.migration_status().await;
+
+    for db in [connected, db] {
+        db.reader.close().await;
+        db.writer.close().await;
+    }
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+
+    let (before, after) = (before?, after?);
+    assert_eq!(before.applied, 0);
+    assert!(before.pending > 0);
+    assert_eq!(before.version, None);
+    assert!(before.migrations.iter().all(|migration| !migration.applied));
+
+    assert_eq!(after.applied, before.pending);
+    assert_eq!(after.pending, 0);
+    assert_eq!(
+        after.version,
+        before.migrations.last().map(|migration| migration.version)
+    );
+    assert_eq!(
+        after
+            .migrations
+            .iter()
+            .map(|migration| &migration.description)
+            .collect::<Vec<_>>(),
+        before
+            .migrations
+            .iter()
+            .map(|migration| &migration.description)
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrations_left_to_the_operator() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("fts-sqlite-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir(&dir)?;
+    let config = SqliteConfig {
+        database_path: Some(dir.join("market.db")),
+        auto_migrate: false,
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+
+    // Opening the database refuses to migrate it
+    let refused = Db::open(&config, now.into()).await.err();
+
+    // so it is backed up and migrated explicitly, after which it opens
+    let connected = Db::connect(&config).await?;
+    let backup = dir.join("backup.db");
+    connected.backup(&backup).await?;
+    let migrated = connected.migrate().await?;
+    let opened = Db::open(&config, now.into()).await.map(|_| ());
+
+    // The backup is as the database was before it was migrated
+    let copy = Db::connect(&SqliteConfig {
+        database_path: Some(backup.clone()),
+        create_if_missing: false,
+        ..Default::default()
+    })
+    .await?;
+    let backed_up = copy.migration_status().await?;
+    let again = connected.backup(&backup).await;
+
+    for db in [connected, copy] {
+        db.reader.close().await;
+        db.writer.close().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(refused.is_some_and(|error| error.to_string().contains("pending")));
+    assert_eq!(migrated.pending, 0);
+    assert!(opened.is_ok());
+    assert_eq!(backed_up.applied, 0);
+    assert_eq!(backed_up.pending, migrated.applied);
+    // An existing backup is never overwritten
+    assert!(again.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use fts_sqlite::{Db, config::SqliteConfig, maintenance::MaintenanceConfig};
+use std::time::Duration;
+
+/// Fill the database with pages which are then freed, as pruning would
+async fn churn(db: &Db) -> anyhow::Result<()> {
+    sqlx::query("create table filler (data blob)")
+        .execute(&db.writer)
+        .await?;
+    sqlx::query(
+        "with recursive n(i) as (select 1 union all select i + 1 from n where i < 100) insert into filler select randomblob(4096) from n",
+    )
+    .execute(&db.writer)
+    .await?;
+    sqlx::query("drop table filler").execute(&db.writer).await?;
+    Ok(())
+}
+
+async fn free_pages(db: &Db) -> anyhow::Result<i64> {
+    Ok(sqlx::query_scalar("pragma freelist_count")
+        .fetch_one(&db.writer)
+        .await?)
+}
+
+#[tokio::test]
+async fn test_maintain() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    // New databases are vacuumed incrementally
+    let auto_vacuum: i64 = sqlx::query_scalar("pragma auto_vacuum")
+        .fetch_one(&db.writer)
+        .await?;
+    assert_eq!(auto_vacuum, 2);
+
+    churn(&db).await?;
+    let freed = free_pages(&db).await?;
+    assert!(freed >= 100);
+
+    let record = db.maintain().await?;
+    // (the statistics may themselves occupy some of the free pages)
+    assert!(record.analyzed);
+    assert!(record.freed_pages >= 100 && record.freed_pages <= freed as u64);
+    assert_eq!(record.free_pages, 0);
+    assert_eq!(free_pages(&db).await?, 0);
+
+    // The statistics of the planner were gathered
+    let statistics: i64 = sqlx::query_scalar("select count(*) from sqlite_stat1")
+        .fetch_one(&db.reader)
+        .await?;
+    assert!(statistics > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_maintenance() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let config = SqliteConfig {
+        maintenance: MaintenanceConfig {
+            every: Some(Duration::from_millis(50)),
+            quiet: Duration::from_millis(50),
+            analyze: false,
+            vacuum_pages: 10,
+        },
+        ..Default::default()
+    };
+    let db = Db::open(&config, now.into()).await?;
+    churn(&db).await?;
+
+    // Each pass frees at most the configured number of pages
+    let freed = free_pages(&db).await?;
+    let maintenance = db.clone();
+    let task = tokio::spawn(async move { maintenance.run_maintenance().await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let remaining = free_pages(&db).await?;
+    assert!(remaining < freed);
+    assert!(remaining > 0);
+
+    // Maintenance waits while the database is being written
+    let writing = tokio::spawn({
+        let db = db.clone();
+        async move {
+            for _ in 0..100 {
+                sqlx::query("update batch set as_of = as_of")
+                    .execute(&db.writer)
+                    .await?;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Ok::<_, sqlx::Error>(())
+        }
+    });
+    // (allowing any pass already under way to complete)
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let busy = free_pages(&db).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(free_pages(&db).await?, busy);
+
+    writing.await??;
+    task.abort();
+    Ok(())
+}
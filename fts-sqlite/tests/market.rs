@@ -0,0 +1,178 @@
+mod common;
+
+use common::TestApp;
+use fts_core::{
+    models::{Actor, DateTimeRangeQuery, Point, ProductSearch, PwlCurve, SettlementConfig},
+    ports::{
+        Application, BatchRepository, DemandRepository, PortfolioRepository, ProductRepository,
+        SettlementRepository,
+    },
+};
+use fts_sqlite::{
+    Db,
+    config::SqliteConfig,
+    types::{BidderId, DateTime, PortfolioId, ProductId},
+};
+use std::time::Duration;
+
+type Solver = <TestApp as Application>::Solver;
+
+/// Create a product traded by a buyer and a seller with mirrored demand
+/// curves, so that the market clears at a rate of 5.
+async fn create_market(
+    app: &TestApp,
+    now: DateTime,
+) -> anyhow::Result<(ProductId, Vec<PortfolioId>)> {
+    let db = app.database();
+
+    let product_id = app.generate_product_id(&()).0;
+    <Db as ProductRepository<()>>::create_product(db, product_id, (), now).await?;
+
+    let mut portfolios = Vec::new();
+    for (min_rate, max_rate) in [(0.0, 10.0), (-10.0, 0.0)] {
+        let bidder_id = BidderId(uuid::Uuid::new_v4());
+        let demand_id = app.generate_demand_id(&()).0;
+        <Db as DemandRepository<()>>::create_demand(
+            db,
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: min_rate,
+                    price: 10.0,
+                },
+                Point {
+                    rate: max_rate,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now,
+        )
+        .await?;
+
+        let portfolio_id = app.generate_portfolio_id(&()).0;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            portfolio_id,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now,
+        )
+        .await?;
+        portfolios.push(portfolio_id);
+    }
+
+    Ok((product_id, portfolios))
+}
+
+#[tokio::test]
+async fn test_market_isolation() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let t0 = DateTime::from(now);
+    let t1 = DateTime::from(now + Duration::from_secs(60));
+    let t2 = DateTime::from(now + Duration::from_secs(120));
+
+    let east = Db::open(&SqliteConfig::default(), t0).await?;
+    let west = east.market("west", t0).await?;
+    let east = TestApp(east);
+    let west = TestApp(west);
+
+    let (east_product, east_portfolios) = create_market(&east, t0).await?;
+    let (west_product, west_portfolios) = create_market(&west, t0).await?;
+
+    // Neither market sees the other's products or bids
+    assert!(
+        <Db as ProductRepository<()>>::get_product(west.database(), east_product, t0)
+            .await?
+            .is_none()
+    );
+    assert!(
+        <Db as PortfolioRepository<()>>::get_portfolio(west.database(), east_portfolios[0], t0)
+            .await?
+            .is_none()
+    );
+    let search = <Db as ProductRepository<()>>::search_products(
+        west.database(),
+        ProductSearch {
+            filters: Vec::new(),
+            after: None,
+        },
+        t0,
+        10,
+    )
+    .await?;
+    assert_eq!(search.results.len(), 1);
+    assert_eq!(search.results[0].id, west_product);
+
+    // Markets may run their batches at the same time
+    for app in [&east, &west] {
+        app.database().run_batch(t1, app.solver(), ()).await??;
+    }
+    for app in [&east, &west] {
+        let batch = <Db as BatchRepository<Solver>>::get_latest_batch(app.database())
+            .await?
+            .expect("the batch should exist");
+        assert_eq!(batch.as_of, t1);
+        assert_eq!(batch.portfolios, 2);
+        assert_eq!(batch.products, 1);
+    }
+
+    // A batch only supersedes the outcomes of its own market
+    east.database().run_batch(t2, east.solver(), ()).await??;
+    let all = DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let outcomes = <Db as BatchRepository<Solver>>::get_product_outcomes(
+        west.database(),
+        west_product,
+        all,
+        10,
+    )
+    .await?;
+    assert_eq!(outcomes.results.len(), 1);
+    assert_eq!(outcomes.results[0].valid_until, None);
+
+    let all = DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let outcomes = <Db as BatchRepository<Solver>>::get_product_outcomes(
+        west.database(),
+        east_product,
+        all,
+        10,
+    )
+    .await?;
+    assert!(outcomes.results.is_empty());
+
+    // Markets may also settle at the same time, each settling only its own activity
+    let as_of = DateTime::from(now + Duration::from_secs(180));
+    for (app, portfolios) in [(&east, &east_portfolios), (&west, &west_portfolios)] {
+        let record = app
+            .database()
+            .settle_activity(SettlementConfig {
+                as_of,
+                time_unit: 60.0,
+                position_decimals: 3,
+                payment_decimals: 2,
+            })
+            .await?
+            .expect("the settlement should be made");
+        assert_eq!(record.activity.len(), portfolios.len());
+
+        let buyer =
+            <Db as PortfolioRepository<()>>::get_portfolio_bidder_id(app.database(), portfolios[0])
+                .await?
+                .expect("the portfolio should exist");
+        assert!(record.activity.contains_key(&buyer));
+    }
+
+    Ok(())
+}
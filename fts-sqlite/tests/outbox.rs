@@ -0,0 +1,41 @@
+mod common;
+
+#[path = "../../repository-tests/outbox.rs"]
+mod outbox;
+
+use common::TestApp;
+use fts_core::ports::{Application, OutboxRepository as _, ProductRepository};
+use fts_sqlite::{Db, config::SqliteConfig};
+use outbox::pending;
+
+#[tokio::test]
+async fn test_outbox_scope() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+    let other = db.market("other", now.into()).await?;
+
+    let product_id = app.generate_product_id(&()).0;
+    <Db as ProductRepository<()>>::create_product(db, product_id, (), now.into()).await?;
+
+    // Each market has an outbox of its own
+    assert_eq!(pending(db).await?, ["product_created"]);
+    assert!(other.pending_events(100).await?.is_empty());
+
+    // Nothing is recorded for changes which are rolled back
+    let abandoned = app.generate_product_id(&()).0;
+    let result: anyhow::Result<()> = db
+        .transaction(|db| {
+            Box::pin(async move {
+                <Db as ProductRepository<()>>::create_product(db, abandoned, (), now.into())
+                    .await?;
+                anyhow::bail!("the product is abandoned")
+            })
+        })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(pending(db).await?, ["product_created"]);
+
+    Ok(())
+}
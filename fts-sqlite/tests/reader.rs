@@ -0,0 +1,96 @@
+use fts_sqlite::{Db, config::SqliteConfig};
+
+#[tokio::test]
+async fn test_writes_through_the_reader_fail() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    let result = sqlx::query("update batch set as_of = as_of")
+        .execute(&db.reader)
+        .await;
+    let error = result.expect_err("the reader should be read-only");
+    assert!(
+        error
+            .as_database_error()
+            .is_some_and(|error| error.message().contains("readonly")),
+        "unexpected error: {error}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reads_do_not_lock_out_the_writer() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..16 {
+        let reader = db.reader.clone();
+        tasks.spawn(async move {
+            sqlx::query("select count(*) from batch")
+                .fetch_one(&reader)
+                .await
+                .map(|_| ())
+        });
+        let writer = db.writer.clone();
+        tasks.spawn(async move {
+            sqlx::query("update batch set as_of = as_of")
+                .execute(&writer)
+                .await
+                .map(|_| ())
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_memory_databases_are_distinct() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let a = Db::open(&SqliteConfig::default(), now.into()).await?;
+    let b = Db::open(&SqliteConfig::default(), now.into()).await?;
+
+    sqlx::query("delete from batch").execute(&a.writer).await?;
+
+    let (count,): (i64,) = sqlx::query_as("select count(*) from batch")
+        .fetch_one(&b.reader)
+        .await?;
+    assert_eq!(count, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_file_databases_have_a_read_only_reader() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+    let db = Db::open(&config, now.into()).await?;
+
+    let written = sqlx::query("update batch set as_of = as_of")
+        .execute(&db.writer)
+        .await;
+    let unwritten = sqlx::query("update batch set as_of = as_of")
+        .execute(&db.reader)
+        .await;
+
+    db.reader.close().await;
+    db.writer.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+
+    written?;
+    assert!(unwritten.is_err());
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository};
+use fts_sqlite::{Db, checkpoint::CheckpointMode, config::SqliteConfig};
+use std::{ffi::OsString, path::Path};
+
+fn with_suffix(path: &Path, suffix: &str) -> OsString {
+    let mut file = path.to_owned().into_os_string();
+    file.push(suffix);
+    file
+}
+
+#[tokio::test]
+async fn test_reads_are_balanced_across_replicas() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let replica = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let now = time::OffsetDateTime::now_utc();
+
+    // The replica is a copy of the database as of the first product
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let replicated = app.generate_product_id(&()).0;
+    app.database()
+        .create_product(replicated, (), now.into())
+        .await?;
+    app.database().checkpoint(CheckpointMode::Truncate).await?;
+    std::fs::copy(&path, &replica)?;
+    app.0.reader.close().await;
+    app.0.writer.close().await;
+
+    let config = SqliteConfig {
+        replica_paths: vec![replica.clone()],
+        ..config
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let unreplicated = app.generate_product_id(&()).0;
+    app.database()
+        .create_product(unreplicated, (), now.into())
+        .await?;
+
+    // Reads alternate between the database and its replica, which has not
+    // observed the later product...
+    let mut found = Vec::new();
+    for product_id in [replicated, replicated, unreplicated, unreplicated] {
+        let product =
+            <Db as ProductRepository<()>>::get_product(app.database(), product_id, now.into())
+                .await?;
+        found.push(product.is_some());
+    }
+
+    // ...but a snapshot always observes it
+    let snapshot = app
+        .database()
+        .snapshot(|db| {
+            Box::pin(<Db as ProductRepository<()>>::get_product(
+                db,
+                unreplicated,
+                now.into(),
+            ))
+        })
+        .await;
+
+    // Writes never reach the replica
+    let unwritten = sqlx::query("update batch set as_of = as_of")
+        .execute(&app.0.replicas[0])
+        .await;
+
+    let db = &app.0;
+    db.reader.close().await;
+    db.writer.close().await;
+    db.replicas[0].close().await;
+    for file in [&path, &replica] {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(with_suffix(file, suffix));
+        }
+    }
+
+    assert_eq!(found, vec![true, true, true, false]);
+    assert!(snapshot?.is_some());
+    assert!(unwritten.is_err());
+
+    Ok(())
+}
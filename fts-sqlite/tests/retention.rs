@@ -0,0 +1,47 @@
+mod common;
+
+#[path = "../../repository-tests/retention.rs"]
+mod retention;
+
+use common::TestApp;
+use fts_core::ports::{Application as _, RetentionRepository as _};
+use fts_sqlite::{Db, config::SqliteConfig};
+use retention::open_market;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_prune_history_into_archive() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let path = std::env::temp_dir().join(format!("fts-archive-{}.db", uuid::Uuid::new_v4()));
+
+    let config = SqliteConfig {
+        archive_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let (app, _, demand_id) = open_market(app, now).await?;
+    let db = app.database();
+
+    let record = db
+        .prune_history((now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.curves, 1);
+    assert!(record.archived);
+
+    // Pruning again archives nothing more
+    let record = db
+        .prune_history((now + Duration::from_secs(4)).into())
+        .await?;
+    assert_eq!(record.curves, 0);
+
+    let archive = sqlx::SqlitePool::connect(&format!("sqlite://{}", path.display())).await?;
+    let archived: Vec<(String,)> = sqlx::query_as("select demand_id from curve_data")
+        .fetch_all(&archive)
+        .await?;
+    archive.close().await;
+    std::fs::remove_file(&path)?;
+
+    assert_eq!(archived, vec![(demand_id.0.to_string(),)]);
+
+    Ok(())
+}
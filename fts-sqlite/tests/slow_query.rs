@@ -0,0 +1,74 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository as _};
+use fts_sqlite::{Db, config::SqliteConfig};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{Event, Level, Subscriber, field::Field};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    prelude::*,
+};
+
+/// Collects the statements of the slow-query warnings
+#[derive(Clone, Default)]
+struct SlowQueries(Arc<Mutex<Vec<String>>>);
+
+impl<S: Subscriber> Layer<S> for SlowQueries {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if *event.metadata().level() == Level::WARN {
+            event.record(&mut |field: &Field, value: &dyn std::fmt::Debug| {
+                if field.name() == "statement" {
+                    self.0.lock().unwrap().push(format!("{value:?}"));
+                }
+            });
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_slow_query_warning() -> anyhow::Result<()> {
+    let slow_queries = SlowQueries::default();
+    let _guard = tracing_subscriber::registry()
+        .with(slow_queries.clone())
+        .set_default();
+
+    let now = time::OffsetDateTime::now_utc();
+
+    // Every query is slower than a zero threshold
+    let config = SqliteConfig {
+        slow_query_threshold: Duration::ZERO,
+        ..Default::default()
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let product_id = app.generate_product_id(&()).0;
+    app.database()
+        .create_product(product_id, (), now.into())
+        .await?;
+    assert_eq!(
+        *slow_queries.0.lock().unwrap(),
+        vec![
+            "\"index_app_data.existing\"",
+            "\"ensure_batch\"",
+            "\"create_product\""
+        ]
+    );
+
+    // ...but none are slower than an hour
+    slow_queries.0.lock().unwrap().clear();
+    let config = SqliteConfig {
+        slow_query_threshold: Duration::from_secs(3600),
+        ..Default::default()
+    };
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let product_id = app.generate_product_id(&()).0;
+    app.database()
+        .create_product(product_id, (), now.into())
+        .await?;
+    assert!(slow_queries.0.lock().unwrap().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository};
+use fts_sqlite::{Db, config::SqliteConfig};
+
+#[tokio::test]
+async fn test_snapshot() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+    let app = TestApp(Db::open(&config, now.into()).await?);
+    let db = app.database().clone();
+
+    let existing = app.generate_product_id(&()).0;
+    let created = app.generate_product_id(&()).0;
+    <Db as ProductRepository<()>>::create_product(&db, existing, (), now.into()).await?;
+
+    // Writes committed while the snapshot is open are not visible within it
+    let writer = db.clone();
+    let result = db
+        .snapshot(|db| {
+            Box::pin(async move {
+                let before = <Db as ProductRepository<()>>::get_product(db, existing, now.into())
+                    .await?
+                    .is_some();
+                <Db as ProductRepository<()>>::create_product(&writer, created, (), now.into())
+                    .await?;
+                let after = <Db as ProductRepository<()>>::get_product(db, created, now.into())
+                    .await?
+                    .is_some();
+
+                // Nor may anything be written through it
+                let written =
+                    <Db as ProductRepository<()>>::create_product(db, created, (), now.into())
+                        .await;
+
+                Ok::<_, sqlx::Error>((before, after, written.is_err()))
+            })
+        })
+        .await;
+
+    // Once the snapshot ends, the writes are visible
+    let visible = <Db as ProductRepository<()>>::get_product(&db, created, now.into()).await;
+
+    db.reader.close().await;
+    db.writer.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+
+    assert_eq!(result?, (true, false, true));
+    assert!(visible?.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snapshot_within_transaction() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+    let product_id = app.generate_product_id(&()).0;
+
+    // A snapshot taken within a unit of work sees the writes made before it
+    let found = db
+        .transaction(|db| {
+            Box::pin(async move {
+                <Db as ProductRepository<()>>::create_product(db, product_id, (), now.into())
+                    .await?;
+                db.snapshot(|db| {
+                    Box::pin(async move {
+                        <Db as ProductRepository<()>>::get_product(db, product_id, now.into()).await
+                    })
+                })
+                .await
+            })
+        })
+        .await?;
+    assert!(found.is_some());
+
+    Ok(())
+}
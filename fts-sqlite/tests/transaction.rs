@@ -0,0 +1,102 @@
+mod common;
+
+use common::TestApp;
+use fts_core::{
+    models::{Actor, Basis, DemandCurve, Weights},
+    ports::{Application, DemandRepository, PortfolioRepository, ProductRepository},
+};
+use fts_sqlite::{Db, config::SqliteConfig, types::BidderId};
+
+#[tokio::test]
+async fn test_transaction() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let purged_at = now + std::time::Duration::from_secs(1);
+
+    let app = TestApp(Db::open(&SqliteConfig::default(), now.into()).await?);
+    let db = app.database();
+    let curve = serde_json::from_value::<DemandCurve>(serde_json::json!({ "price": 1.0 }))?;
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let product_id = app.generate_product_id(&()).0;
+    let demand_id = app.generate_demand_id(&()).0;
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+
+    // The operations of a unit of work see the writes of those before them
+    // (here, the portfolio references the product and the demand)
+    let curve_ = curve.clone();
+    db.transaction(|db| {
+        Box::pin(async move {
+            <Db as ProductRepository<()>>::create_product(db, product_id, (), now.into()).await?;
+            <Db as DemandRepository<()>>::create_demand(
+                db,
+                demand_id,
+                bidder_id,
+                (),
+                curve_,
+                Actor::Bidder,
+                now.into(),
+            )
+            .await?;
+            <Db as PortfolioRepository<()>>::create_portfolio(
+                db,
+                portfolio_id,
+                bidder_id,
+                (),
+                Weights::from_iter([(demand_id, 1.0)]),
+                Basis::from_iter([(product_id, 1.0)]),
+                Actor::Bidder,
+                now.into(),
+            )
+            .await?;
+            Ok::<_, anyhow::Error>(())
+        })
+    })
+    .await?;
+
+    let portfolio = <Db as PortfolioRepository<()>>::get_portfolio(db, portfolio_id, now.into())
+        .await?
+        .expect("the portfolio should be committed");
+    assert_eq!(portfolio.demand.get(&demand_id), Some(&1.0));
+
+    // A failing unit of work is rolled back entirely, including any
+    // operations (such as purges) which are transactional of their own
+    let other_demand_id = app.generate_demand_id(&()).0;
+    let result: anyhow::Result<()> = db
+        .transaction(|db| {
+            Box::pin(async move {
+                <Db as DemandRepository<()>>::create_demand(
+                    db,
+                    other_demand_id,
+                    bidder_id,
+                    (),
+                    curve,
+                    Actor::Bidder,
+                    now.into(),
+                )
+                .await?;
+                <Db as PortfolioRepository<()>>::purge_portfolio(
+                    db,
+                    portfolio_id,
+                    purged_at.into(),
+                )
+                .await?
+                .expect("the portfolio should be purged");
+                anyhow::bail!("the bid is rejected")
+            })
+        })
+        .await;
+    assert!(result.is_err());
+
+    assert!(
+        <Db as DemandRepository<()>>::get_demand(db, other_demand_id, now.into())
+            .await?
+            .is_none()
+    );
+    assert!(
+        <Db as PortfolioRepository<()>>::get_portfolio(db, portfolio_id, now.into())
+            .await?
+            .is_some()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::TestApp;
+use fts_core::ports::{Application, ProductRepository as _};
+use fts_sqlite::{Db, config::SqliteConfig, retry::WriteRetryStats};
+use sqlx::{ConnectOptions as _, sqlite::SqliteConnectOptions};
+use std::{ffi::OsString, path::Path, time::Duration};
+
+fn with_suffix(path: &Path, suffix: &str) -> OsString {
+    let mut file = path.to_owned().into_os_string();
+    file.push(suffix);
+    file
+}
+
+#[tokio::test]
+async fn test_write_retry() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join(format!("fts-sqlite-{}.db", uuid::Uuid::new_v4()));
+    let config = SqliteConfig {
+        database_path: Some(path.clone()),
+        busy_timeout: Duration::from_millis(10),
+        write_retries: 20,
+        write_retry_backoff: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let now = time::OffsetDateTime::now_utc();
+    let mut app = TestApp(Db::open(&config, now.into()).await?);
+
+    // Another process holds the write lock for a while
+    let mut other = SqliteConnectOptions::new()
+        .filename(&path)
+        .connect()
+        .await?;
+    sqlx::query("begin immediate").execute(&mut other).await?;
+    let release = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        sqlx::query("commit").execute(&mut other).await?;
+        Ok::<_, sqlx::Error>(other)
+    });
+
+    // The write is retried until the lock is released
+    let products = vec![(app.generate_product_id(&()).0, ())];
+    let created = app.database().create_products(products, now.into()).await?;
+    assert_eq!(created.len(), 1);
+    let stats = app.database().write_retry_stats();
+    assert!(stats.retries > 0);
+    assert_eq!(stats.exhausted, 0);
+
+    // ...but not indefinitely
+    let mut other = release.await??;
+    sqlx::query("begin immediate").execute(&mut other).await?;
+    app.0.write_retries = 2;
+    let products = vec![(app.generate_product_id(&()).0, ())];
+    let result = app.database().create_products(products, now.into()).await;
+    assert!(result.is_err());
+    assert_eq!(
+        app.database().write_retry_stats(),
+        WriteRetryStats {
+            retries: stats.retries + 2,
+            exhausted: 1,
+        }
+    );
+
+    sqlx::query("rollback").execute(&mut other).await?;
+    let db = &app.0;
+    db.reader.close().await;
+    db.writer.close().await;
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(with_suffix(&path, suffix));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DemandId, PortfolioId},
+};
+use fts_core::{
+    models::{
+        Actor, AppDataFilter, Comparison, DemandSearch, FilterValue, Point, PortfolioSearch,
+        PwlCurve,
+    },
+    ports::{Application as _, DemandRepository, PortfolioRepository},
+};
+use serde_json::json;
+
+fn filter(path: &str, op: Comparison, value: FilterValue) -> AppDataFilter {
+    AppDataFilter {
+        path: path.to_string(),
+        op,
+        value,
+    }
+}
+
+#[tokio::test]
+async fn test_demand_and_portfolio_search() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let alice = BidderId(uuid::Uuid::new_v4());
+    let bob = BidderId(uuid::Uuid::new_v4());
+
+    // Alice bids from two desks, Bob from one
+    let mut east = Vec::new();
+    for (bidder_id, desk, priority) in [
+        (alice, "east", 1.0),
+        (alice, "east", 2.0),
+        (alice, "east", 3.0),
+        (alice, "west", 1.0),
+        (bob, "east", 1.0),
+    ] {
+        let demand_id = DemandId(uuid::Uuid::new_v4());
+        <Db as DemandRepository<serde_json::Value>>::create_demand(
+            db,
+            demand_id,
+            bidder_id,
+            json!({ "desk": desk, "priority": priority, "trader's": desk }),
+            PwlCurve::new(vec![
+                Point {
+                    rate: 0.0,
+                    price: 10.0,
+                },
+                Point {
+                    rate: 10.0,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        let portfolio_id = PortfolioId(uuid::Uuid::new_v4());
+        <Db as PortfolioRepository<serde_json::Value>>::create_portfolio(
+            db,
+            portfolio_id,
+            bidder_id,
+            json!({ "desk": desk }),
+            std::iter::once((demand_id, 1.0)).collect(),
+            Default::default(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        if bidder_id == alice && desk == "east" {
+            east.push((demand_id, portfolio_id));
+        }
+    }
+
+    // Page through Alice's urgent demands on the east desk, one at a time
+    let query = DemandSearch {
+        filters: vec![
+            filter(
+                "$.desk",
+                Comparison::Eq,
+                FilterValue::String("east".to_string()),
+            ),
+            filter("$.priority", Comparison::Ge, FilterValue::Number(2.0)),
+        ],
+        after: None,
+    };
+    let mut found = Vec::new();
+    let mut next = Some(query);
+    while let Some(query) = next.take() {
+        let page =
+            <Db as DemandRepository<serde_json::Value>>::search_demands(db, &[alice], query, 1)
+                .await?;
+        assert!(page.results.len() <= 1);
+        found.extend(page.results.into_iter().map(|demand| demand.id));
+        next = page.more;
+    }
+    let mut expected: Vec<_> = east[1..].iter().map(|(demand_id, _)| *demand_id).collect();
+    expected.sort();
+    assert_eq!(found, expected);
+
+    // Paths are quoted, so may contain quotes of their own
+    let page = <Db as DemandRepository<serde_json::Value>>::search_demands(
+        db,
+        &[alice],
+        DemandSearch {
+            filters: vec![filter(
+                "$.\"trader's\"",
+                Comparison::Eq,
+                FilterValue::String("east".to_string()),
+            )],
+            after: None,
+        },
+        100,
+    )
+    .await?;
+    assert_eq!(page.results.len(), 3);
+
+    // Only the portfolios of the given bidders are searched
+    let page = <Db as PortfolioRepository<serde_json::Value>>::search_portfolios(
+        db,
+        &[alice, bob],
+        PortfolioSearch {
+            filters: vec![filter(
+                "$.desk",
+                Comparison::Eq,
+                FilterValue::String("east".to_string()),
+            )],
+            after: None,
+        },
+        100,
+    )
+    .await?;
+    assert!(page.more.is_none());
+    assert_eq!(page.results.len(), 4);
+
+    let page = <Db as PortfolioRepository<serde_json::Value>>::search_portfolios(
+        db,
+        &[alice],
+        PortfolioSearch {
+            filters: vec![filter(
+                "$.desk",
+                Comparison::Eq,
+                FilterValue::String("east".to_string()),
+            )],
+            after: None,
+        },
+        100,
+    )
+    .await?;
+    let mut expected: Vec<_> = east.iter().map(|(_, portfolio_id)| *portfolio_id).collect();
+    expected.sort();
+    let found: Vec<_> = page.results.iter().map(|portfolio| portfolio.id).collect();
+    assert_eq!(found, expected);
+
+    // Without any bidders, nothing is searched
+    let page = <Db as PortfolioRepository<serde_json::Value>>::search_portfolios(
+        db,
+        &[],
+        PortfolioSearch {
+            filters: Vec::new(),
+            after: None,
+        },
+        100,
+    )
+    .await?;
+    assert!(page.results.is_empty());
+
+    Ok(())
+}
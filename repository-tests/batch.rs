@@ -0,0 +1,588 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{
+        Actor, Basis, BatchError, DateTimeRangeQuery, DemandCurve, Map, Point, PriceInterval,
+        PwlCurve, Weights,
+    },
+    ports::{
+        Application, BatchRepository, DemandRepository, PortfolioRepository,
+        ProductRepository as _, Solver as _,
+    },
+};
+use futures_util::TryStreamExt as _;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+pub type Solver = <TestApp as Application>::Solver;
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+/// A solver which announces that it has started, then waits to be released
+/// before solving as usual
+#[derive(Default)]
+struct GatedSolver {
+    started: Arc<Notify>,
+    release: Arc<Notify>,
+}
+
+impl fts_core::ports::Solver<DemandId, PortfolioId, ProductId> for GatedSolver {
+    type Error = fts_solver::SolveError;
+    type PortfolioOutcome = fts_solver::PortfolioOutcome;
+    type ProductOutcome = fts_solver::ProductOutcome;
+    type State = ();
+
+    async fn solve(
+        &self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        state: (),
+    ) -> Result<
+        (
+            Map<PortfolioId, Self::PortfolioOutcome>,
+            Map<ProductId, Self::ProductOutcome>,
+        ),
+        fts_solver::SolveError,
+    > {
+        self.started.notify_one();
+        self.release.notified().await;
+        Solver::default()
+            .solve(demand_curves, portfolios, state)
+            .await
+    }
+}
+
+/// A solver which warm-starts from the rates of the previous batch, recording
+/// the rates with which it was started before solving as usual
+#[derive(Clone, Default)]
+struct WarmSolver {
+    started_from: Arc<Mutex<Vec<Option<Map<PortfolioId>>>>>,
+}
+
+impl fts_core::ports::Solver<DemandId, PortfolioId, ProductId> for WarmSolver {
+    type Error = fts_solver::SolveError;
+    type PortfolioOutcome = fts_solver::PortfolioOutcome;
+    type ProductOutcome = fts_solver::ProductOutcome;
+    type State = Option<Map<PortfolioId>>;
+
+    async fn solve(
+        &self,
+        demand_curves: Map<DemandId, DemandCurve>,
+        portfolios: Map<PortfolioId, (Weights<DemandId>, Basis<ProductId>)>,
+        state: Self::State,
+    ) -> Result<
+        (
+            Map<PortfolioId, Self::PortfolioOutcome>,
+            Map<ProductId, Self::ProductOutcome>,
+        ),
+        fts_solver::SolveError,
+    > {
+        self.started_from.lock().unwrap().push(state);
+        Solver::default().solve(demand_curves, portfolios, ()).await
+    }
+
+    fn next_state(
+        &self,
+        portfolio_outcomes: &Map<PortfolioId, Self::PortfolioOutcome>,
+    ) -> Self::State {
+        Some(
+            portfolio_outcomes
+                .iter()
+                .map(|(portfolio_id, outcome)| (*portfolio_id, outcome.rate))
+                .collect(),
+        )
+    }
+}
+
+/// Open a market of a buyer and a seller of a single product, whose demand
+/// curves are mirrored so that it clears at a rate of 5 and a price of 5
+pub async fn open_market(app: &TestApp, now: time::OffsetDateTime) -> anyhow::Result<ProductId> {
+    let db = app.database();
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+    for rates in [(0.0, 10.0), (-10.0, 0.0)] {
+        let bidder_id = BidderId(uuid::Uuid::new_v4());
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: rates.0,
+                    price: 10.0,
+                },
+                Point {
+                    rate: rates.1,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    Ok(product_id)
+}
+
+#[tokio::test]
+async fn test_batch_outcomes() -> anyhow::Result<()> {
+    // Start on the hour, so that both batches fall into the same bucket
+    let now = time::OffsetDateTime::now_utc().replace_time(time::Time::MIDNIGHT);
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    // The buyer and seller have mirrored demand curves, so the market clears
+    // at a rate of 5 and a price of 5.
+    let mut portfolios = Vec::new();
+    for (bidder_id, rates) in [(buyer, (0.0, 10.0)), (seller, (-10.0, 0.0))] {
+        let demand_id = app.generate_demand_id(&()).0;
+        let points = vec![
+            Point {
+                rate: rates.0,
+                price: 10.0,
+            },
+            Point {
+                rate: rates.1,
+                price: 0.0,
+            },
+        ];
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(points)?.into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        let portfolio_id = app.generate_portfolio_id(&()).0;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            portfolio_id,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        portfolios.push(portfolio_id);
+    }
+
+    let first = DateTime::from(now + Duration::from_secs(60));
+    let second = DateTime::from(now + Duration::from_secs(1860));
+    let as_of = DateTime::from(now + Duration::from_secs(3660));
+
+    // Previewing a batch does not record its outcomes
+    let preview = db.preview_batch(first, app.solver(), ()).await??;
+    assert_eq!(preview.portfolios.len(), 2);
+    assert!(approx_eq(preview.products[&product_id].price, 5.0));
+    let latest = <Db as BatchRepository<Solver>>::get_latest_batch(db).await?;
+    assert_eq!(latest.map(|batch| batch.portfolios), Some(0));
+
+    db.run_batch(first, app.solver(), ()).await??;
+    db.run_batch(second, app.solver(), ()).await??;
+
+    let latest = <Db as BatchRepository<Solver>>::get_latest_batch(db)
+        .await?
+        .unwrap();
+    assert_eq!(latest.as_of, second);
+    assert_eq!(latest.portfolios, 2);
+    assert_eq!(latest.products, 1);
+
+    let all = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+
+    let outcomes =
+        <Db as BatchRepository<Solver>>::get_product_outcomes(db, product_id, all(), 1).await?;
+    assert_eq!(outcomes.results.len(), 1);
+    assert_eq!(outcomes.results[0].valid_from, second);
+    assert_eq!(outcomes.results[0].valid_until, None);
+    assert!(approx_eq(outcomes.results[0].value.price, 5.0));
+
+    let outcomes = <Db as BatchRepository<Solver>>::get_product_outcomes(
+        db,
+        product_id,
+        outcomes.more.unwrap(),
+        1,
+    )
+    .await?;
+    assert_eq!(outcomes.results[0].valid_from, first);
+    assert_eq!(outcomes.results[0].valid_until, Some(second));
+    assert!(outcomes.more.is_none());
+
+    let outcomes =
+        <Db as BatchRepository<Solver>>::get_portfolio_outcomes(db, portfolios[0], all(), 10)
+            .await?;
+    assert_eq!(outcomes.results.len(), 2);
+    assert!(approx_eq(outcomes.results[0].value.rate, 5.0));
+
+    // Streaming yields every outcome at once, in the same order
+    let outcomes: Vec<_> =
+        <Db as BatchRepository<Solver>>::stream_product_outcomes(db, product_id, all())
+            .try_collect()
+            .await?;
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].valid_from, second);
+    assert_eq!(outcomes[1].valid_until, Some(second));
+
+    let outcomes: Vec<_> = <Db as BatchRepository<Solver>>::stream_portfolio_outcomes(
+        db,
+        portfolios[1],
+        DateTimeRangeQuery {
+            before: Some(second),
+            after: None,
+        },
+    )
+    .try_collect()
+    .await?;
+    assert_eq!(outcomes.len(), 1);
+    assert!(approx_eq(outcomes[0].value.rate, -5.0));
+
+    // Each outcome is in effect for half an hour, trading at a rate of 5 per hour
+    let summary = <Db as BatchRepository<Solver>>::get_product_summary(
+        db,
+        product_id,
+        PriceInterval::Hour,
+        all(),
+        as_of,
+        3600.0,
+        10,
+    )
+    .await?;
+    assert_eq!(summary.results.len(), 1);
+    assert_eq!(summary.results[0].valid_from, now.into());
+    assert_eq!(
+        summary.results[0].valid_until,
+        Some((now + Duration::from_secs(3600)).into())
+    );
+    assert_eq!(summary.results[0].value.batches, 2);
+    assert!(approx_eq(summary.results[0].value.open.unwrap(), 5.0));
+    assert!(approx_eq(summary.results[0].value.volume, 5.0));
+
+    let statistics =
+        <Db as BatchRepository<Solver>>::get_market_statistics(db, all(), as_of, 3600.0).await?;
+    let product = &statistics.products[&product_id];
+    assert_eq!(product.cleared, Some(second));
+    assert!(approx_eq(product.price.unwrap(), 5.0));
+    assert!(approx_eq(product.volume, 5.0));
+    assert!(approx_eq(product.open_interest, 5.0));
+    assert_eq!(product.portfolios, 2);
+    assert_eq!(product.demands, 2);
+
+    // Each batch records how it was solved, but not a preview
+    let runs = <Db as BatchRepository<Solver>>::get_batch_runs(db, all(), 1).await?;
+    assert_eq!(runs.results.len(), 1);
+    assert_eq!(runs.results[0].valid_from, second);
+    assert_eq!(runs.results[0].value.solver.backend, "clarabel");
+    assert_eq!(runs.results[0].value.error, None);
+    let runs = <Db as BatchRepository<Solver>>::get_batch_runs(db, runs.more.unwrap(), 1).await?;
+    assert_eq!(runs.results[0].valid_from, first);
+    assert!(runs.more.is_none());
+
+    // and none of them failed
+    let attempts = <Db as BatchRepository<Solver>>::get_batch_attempts(db, all(), 10).await?;
+    assert!(attempts.results.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_inputs() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(mut app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    app.record_batch_inputs(true);
+    let db = app.database();
+
+    let product_id = open_market(&app, now).await?;
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    db.run_batch(first, app.solver(), ()).await??;
+
+    // Solving the recorded inputs again reproduces the outcomes exactly
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, first)
+        .await?
+        .unwrap();
+    assert_eq!(inputs.demand_curves.len(), 2);
+    assert_eq!(inputs.portfolios.len(), 2);
+    let (demand_curves, portfolios) = inputs.into_parts();
+    let (_, products) = app.solver().solve(demand_curves, portfolios, ()).await?;
+    let outcomes = <Db as BatchRepository<Solver>>::get_product_outcomes(
+        db,
+        product_id,
+        DateTimeRangeQuery {
+            before: None,
+            after: None,
+        },
+        1,
+    )
+    .await?;
+    assert_eq!(outcomes.results[0].value.price, products[&product_id].price);
+
+    // Nothing is recorded when no batch was run, or when recording is off
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, second).await?;
+    assert!(inputs.is_none());
+    app.record_batch_inputs(false);
+    app.database().run_batch(second, app.solver(), ()).await??;
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(app.database(), second).await?;
+    assert!(inputs.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_purged_batch_inputs() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(mut app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    app.record_batch_inputs(true);
+    let db = app.database();
+
+    open_market(&app, now).await?;
+    let as_of = DateTime::from(now + Duration::from_secs(1));
+    let purged_at = DateTime::from(now + Duration::from_secs(2));
+    db.run_batch(as_of, app.solver(), ()).await??;
+
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, as_of)
+        .await?
+        .unwrap();
+    let demand_id = *inputs.demand_curves.keys().next().unwrap();
+    let portfolio_id = *inputs.portfolios.keys().next().unwrap();
+
+    // Purging a bid erases it from the recorded inputs of past batches too
+    <Db as DemandRepository<()>>::purge_demand(db, demand_id, purged_at)
+        .await?
+        .expect("demand should exist");
+    <Db as PortfolioRepository<()>>::purge_portfolio(db, portfolio_id, purged_at)
+        .await?
+        .expect("portfolio should exist");
+
+    let inputs = <Db as BatchRepository<Solver>>::get_batch_inputs(db, as_of)
+        .await?
+        .unwrap();
+    assert_eq!(inputs.demand_curves.len(), 1);
+    assert!(!inputs.demand_curves.contains_key(&demand_id));
+    assert_eq!(inputs.portfolios.len(), 1);
+    assert!(!inputs.portfolios.contains_key(&portfolio_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bidder_outcomes() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    // The buyer splits their demand across two portfolios, which together
+    // mirror the seller's, so the market clears at a rate of 5 and a price of 5
+    for (bidder_id, rates) in [
+        (buyer, (0.0, 5.0)),
+        (buyer, (0.0, 5.0)),
+        (seller, (-10.0, 0.0)),
+    ] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: rates.0,
+                    price: 10.0,
+                },
+                Point {
+                    rate: rates.1,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    db.run_batch(first, app.solver(), ()).await??;
+    db.run_batch(second, app.solver(), ()).await??;
+
+    let all = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let outcomes =
+        <Db as BatchRepository<Solver>>::get_bidder_outcomes(db, buyer, all(), 1).await?;
+    assert_eq!(outcomes.results.len(), 1);
+    assert_eq!(outcomes.results[0].valid_from, second);
+    assert_eq!(outcomes.results[0].valid_until, None);
+    let outcome = &outcomes.results[0].value;
+    assert_eq!(outcome.trades.len(), 1);
+    assert!(approx_eq(outcome.trades[&product_id].rate, 5.0));
+    assert!(approx_eq(outcome.trades[&product_id].payment, 25.0));
+    assert!(approx_eq(outcome.payment, 25.0));
+
+    let outcomes =
+        <Db as BatchRepository<Solver>>::get_bidder_outcomes(db, buyer, outcomes.more.unwrap(), 1)
+            .await?;
+    assert_eq!(outcomes.results[0].valid_from, first);
+    assert_eq!(outcomes.results[0].valid_until, Some(second));
+    assert!(outcomes.more.is_none());
+
+    // The seller is owed for their trade
+    let outcomes =
+        <Db as BatchRepository<Solver>>::get_bidder_outcomes(db, seller, all(), 10).await?;
+    assert_eq!(outcomes.results.len(), 2);
+    assert!(approx_eq(
+        outcomes.results[1].value.trades[&product_id].rate,
+        -5.0
+    ));
+    assert!(approx_eq(outcomes.results[1].value.payment, -25.0));
+
+    // A bidder without any portfolio has no outcomes
+    let outcomes = <Db as BatchRepository<Solver>>::get_bidder_outcomes(
+        db,
+        BidderId(uuid::Uuid::new_v4()),
+        all(),
+        10,
+    )
+    .await?;
+    assert!(outcomes.results.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_in_progress() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+
+    // A batch attempted while another is solving returns without running
+    let gated = GatedSolver::default();
+    let (started, release) = (gated.started.clone(), gated.release.clone());
+    let (running, contending) = tokio::join!(db.run_batch(first, gated, ()), async {
+        started.notified().await;
+        let contending = db.run_batch(second, app.solver(), ()).await;
+        release.notify_one();
+        contending
+    });
+    running??;
+    assert!(matches!(contending?, Err(BatchError::InProgress)));
+
+    // ... and leaves no trace, not even as a failed attempt
+    let all = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let latest = <Db as BatchRepository<Solver>>::get_latest_batch(db)
+        .await?
+        .unwrap();
+    assert_eq!(latest.as_of, first);
+    let attempts = <Db as BatchRepository<Solver>>::get_batch_attempts(db, all(), 10).await?;
+    assert!(attempts.results.is_empty());
+
+    // Once the first batch completes, batches run again
+    db.run_batch(second, app.solver(), ()).await??;
+    let latest = <Db as BatchRepository<Solver>>::get_latest_batch(db)
+        .await?
+        .unwrap();
+    assert_eq!(latest.as_of, second);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_state() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+    open_market(&app, now).await?;
+
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(2));
+    let solver = WarmSolver::default();
+
+    // The first batch starts afresh, and leaves the rates it cleared at
+    let (_, state) = db
+        .run_batch_with_state(first, solver.clone(), None)
+        .await??;
+    let rates = state.clone().unwrap();
+    assert_eq!(rates.len(), 2);
+    assert!(rates.values().all(|rate| approx_eq(rate.abs(), 5.0)));
+
+    // ... from which the second batch is started
+    let (_, next_state) = db
+        .run_batch_with_state(second, solver.clone(), state)
+        .await??;
+    assert_eq!(next_state, Some(rates.clone()));
+    assert_eq!(*solver.started_from.lock().unwrap(), [None, Some(rates)]);
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{Actor, Basis, CompactionRecord, DateTimeRangeQuery, DemandCurve, Weights},
+    ports::{
+        Application, DemandRepository as _, PortfolioRepository, ProductRepository as _,
+        RetentionRepository as _,
+    },
+};
+use std::time::Duration;
+
+type Groups = (Weights<DemandId>, Basis<ProductId>);
+
+async fn groups(db: &Db, portfolio_id: PortfolioId, as_of: DateTime) -> anyhow::Result<Groups> {
+    let record = <Db as PortfolioRepository<()>>::get_portfolio(db, portfolio_id, as_of)
+        .await?
+        .unwrap();
+    Ok((record.demand, record.basis))
+}
+
+async fn versions(
+    db: &Db,
+    portfolio_id: PortfolioId,
+) -> anyhow::Result<(Vec<DateTime>, Vec<DateTime>)> {
+    let query = || DateTimeRangeQuery {
+        before: None,
+        after: None,
+    };
+    let demand = <Db as PortfolioRepository<()>>::get_portfolio_demand_history(
+        db,
+        portfolio_id,
+        query(),
+        100,
+    )
+    .await?;
+    let product = <Db as PortfolioRepository<()>>::get_portfolio_product_history(
+        db,
+        portfolio_id,
+        query(),
+        100,
+    )
+    .await?;
+    Ok((
+        demand
+            .results
+            .iter()
+            .map(|record| record.valid_from)
+            .collect(),
+        product
+            .results
+            .iter()
+            .map(|record| record.valid_from)
+            .collect(),
+    ))
+}
+
+#[tokio::test]
+async fn test_compact_history() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let apple = app.generate_product_id(&()).0;
+    let banana = app.generate_product_id(&()).0;
+    db.create_product(apple, (), at(0)).await?;
+    db.create_product(banana, (), at(0)).await?;
+    let demand_id = app.generate_demand_id(&()).0;
+    db.create_demand(
+        demand_id,
+        bidder_id,
+        (),
+        DemandCurve::None,
+        Actor::Bidder,
+        at(0),
+    )
+    .await?;
+
+    let demand = Weights::from_iter([(demand_id, 1.0)]);
+    let narrow = Basis::from_iter([(apple, 1.0)]);
+    let wide = Basis::from_iter([(apple, 1.0), (banana, 2.0)]);
+
+    // An algorithmic bidder repeatedly resubmits the same portfolio, which is
+    // only occasionally changed, and then adjusted by an operator
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+    db.create_portfolio(
+        portfolio_id,
+        bidder_id,
+        (),
+        demand.clone(),
+        narrow.clone(),
+        Actor::Bidder,
+        at(1),
+    )
+    .await?;
+    let updates = [
+        (narrow.clone(), Actor::Bidder, at(2)),
+        (narrow.clone(), Actor::Bidder, at(3)),
+        (wide.clone(), Actor::Bidder, at(4)),
+        (wide.clone(), Actor::Bidder, at(5)),
+        (wide.clone(), Actor::Operator, at(6)),
+    ];
+    for (basis, actor, as_of) in updates {
+        <Db as PortfolioRepository<()>>::update_portfolio(
+            db,
+            portfolio_id,
+            demand.clone(),
+            basis,
+            actor,
+            as_of,
+        )
+        .await?;
+    }
+
+    let mut before = Vec::new();
+    for secs in 1..=6 {
+        before.push(groups(db, portfolio_id, at(secs)).await?);
+    }
+    assert_eq!(versions(db, portfolio_id).await?.0.len(), 6);
+
+    // The redundant versions are merged into the versions they follow
+    let record = db.compact_history().await?;
+    assert_eq!(
+        record,
+        CompactionRecord {
+            demand_groups: 4,
+            product_groups: 4,
+        }
+    );
+    assert_eq!(
+        versions(db, portfolio_id).await?,
+        (vec![at(6), at(1)], vec![at(6), at(4), at(1)])
+    );
+
+    // The groups as of any time are unaffected
+    for secs in 1..=6 {
+        assert_eq!(
+            groups(db, portfolio_id, at(secs)).await?,
+            before[secs as usize - 1]
+        );
+    }
+
+    // Nothing further is redundant, and the compacted groups remain updatable
+    assert_eq!(
+        db.compact_history().await?,
+        CompactionRecord {
+            demand_groups: 0,
+            product_groups: 0,
+        }
+    );
+    <Db as PortfolioRepository<()>>::update_portfolio(
+        db,
+        portfolio_id,
+        Weights::default(),
+        narrow.clone(),
+        Actor::Bidder,
+        at(7),
+    )
+    .await?;
+    assert_eq!(groups(db, portfolio_id, at(6)).await?, before[5]);
+    assert_eq!(
+        groups(db, portfolio_id, at(7)).await?,
+        (Weights::default(), narrow)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{Actor, Basis},
+    ports::{Application, HealthRepository as _, PortfolioRepository, ProductRepository},
+};
+use std::time::Duration;
+
+/// The products of a tree, and a portfolio on it, which have been reshaped
+/// several times
+// Read by the backends which corrupt their records to test repairs
+#[allow(dead_code)]
+pub struct Reshaped {
+    pub portfolio_id: PortfolioId,
+    pub food: ProductId,
+    pub vegetable: ProductId,
+    pub apple: ProductId,
+    pub banana: ProductId,
+}
+
+pub async fn reshape(app: &TestApp, now: time::OffsetDateTime) -> anyhow::Result<Reshaped> {
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let food = app.generate_product_id(&()).0;
+    let fruit = app.generate_product_id(&()).0;
+    let vegetable = app.generate_product_id(&()).0;
+    let apple = app.generate_product_id(&()).0;
+    let banana = app.generate_product_id(&()).0;
+
+    <Db as ProductRepository<()>>::create_product(db, food, (), at(0)).await?;
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+    <Db as PortfolioRepository<()>>::create_portfolio(
+        db,
+        portfolio_id,
+        bidder_id,
+        (),
+        Default::default(),
+        std::iter::once((food, 1.0)).collect(),
+        Actor::Bidder,
+        at(1),
+    )
+    .await?;
+    db.partition_product(food, vec![(fruit, (), 2.0), (vegetable, (), 3.0)], at(2))
+        .await?;
+    db.partition_product(fruit, vec![(apple, (), 5.0), (banana, (), 7.0)], at(4))
+        .await?;
+    <Db as ProductRepository<()>>::update_product_ratio(db, fruit, 4.0, at(6))
+        .await?
+        .unwrap();
+    <Db as ProductRepository<()>>::update_product_ratio(db, fruit, 4.0, at(7))
+        .await?
+        .unwrap();
+    <Db as ProductRepository<()>>::move_product(db, banana, vegetable, 1.0, at(8))
+        .await?
+        .unwrap();
+    <Db as PortfolioRepository<()>>::update_portfolio_basis(
+        db,
+        portfolio_id,
+        Basis::from_iter([(fruit, 1.0), (vegetable, 2.0)]),
+        Actor::Bidder,
+        at(9),
+    )
+    .await?
+    .unwrap();
+    <Db as ProductRepository<()>>::move_product(db, apple, vegetable, 2.0, at(10))
+        .await?
+        .unwrap();
+
+    Ok(Reshaped {
+        portfolio_id,
+        food,
+        vegetable,
+        apple,
+        banana,
+    })
+}
+
+#[allow(dead_code)]
+pub async fn expanded_basis(
+    db: &Db,
+    portfolio_id: PortfolioId,
+    as_of: DateTime,
+) -> anyhow::Result<Basis<ProductId>> {
+    Ok(
+        <Db as PortfolioRepository<()>>::get_portfolio_with_expanded_products(
+            db,
+            portfolio_id,
+            as_of,
+        )
+        .await?
+        .unwrap()
+        .basis,
+    )
+}
+
+#[tokio::test]
+async fn test_consistency_check() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+    reshape(&app, now).await?;
+
+    // The records maintained as the tree and portfolio changed agree with
+    // those derived anew
+    let record = db.check_consistency(false).await?;
+    assert!(record.is_consistent());
+    assert!(!record.repaired);
+
+    // Repairing consistent records leaves them be
+    let record = db.check_consistency(true).await?;
+    assert!(record.is_consistent());
+    assert!(!record.repaired);
+
+    Ok(())
+}
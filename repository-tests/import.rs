@@ -0,0 +1,227 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DemandId, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{
+        Actor, DemandCurve, ImportDemand, ImportDocument, ImportIssue, ImportPortfolio,
+        ImportProduct, Point, PwlCurve,
+    },
+    ports::{
+        Application, DemandRepository, ImportRepository as _, PortfolioRepository,
+        ProductRepository,
+    },
+};
+
+/// The ids of a product partitioned into two halves, and of a bidder trading
+/// the first half through a single portfolio.
+struct Market {
+    root: ProductId,
+    half: ProductId,
+    other: ProductId,
+    bidder_id: BidderId,
+    demand_id: DemandId,
+    portfolio_id: PortfolioId,
+}
+
+impl Market {
+    fn new(app: &TestApp) -> Self {
+        Self {
+            root: app.generate_product_id(&()).0,
+            half: app.generate_product_id(&()).0,
+            other: app.generate_product_id(&()).0,
+            bidder_id: BidderId(uuid::Uuid::new_v4()),
+            demand_id: app.generate_demand_id(&()).0,
+            portfolio_id: app.generate_portfolio_id(&()).0,
+        }
+    }
+
+    fn document(&self) -> anyhow::Result<ImportDocument<Db, (), (), ()>> {
+        let curve: DemandCurve = PwlCurve::new(vec![
+            Point {
+                rate: -10.0,
+                price: 10.0,
+            },
+            Point {
+                rate: 0.0,
+                price: 0.0,
+            },
+        ])?
+        .into();
+
+        Ok(ImportDocument {
+            products: vec![
+                ImportProduct {
+                    id: self.root,
+                    app_data: (),
+                    parent: None,
+                },
+                ImportProduct {
+                    id: self.half,
+                    app_data: (),
+                    parent: Some((self.root, 0.5)),
+                },
+                ImportProduct {
+                    id: self.other,
+                    app_data: (),
+                    parent: Some((self.root, 0.5)),
+                },
+            ],
+            demands: vec![ImportDemand {
+                id: self.demand_id,
+                bidder_id: self.bidder_id,
+                app_data: (),
+                curve_data: curve,
+            }],
+            portfolios: vec![ImportPortfolio {
+                id: self.portfolio_id,
+                bidder_id: self.bidder_id,
+                app_data: (),
+                demand: std::iter::once((self.demand_id, 1.0)).collect(),
+                basis: std::iter::once((self.half, 1.0)).collect(),
+            }],
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_import() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let market = Market::new(&app);
+
+    // A dry run reports the import without performing it
+    let record = match db
+        .import(market.document()?, Actor::Operator, now.into(), true)
+        .await?
+    {
+        Ok(record) => record,
+        Err(issues) => anyhow::bail!("unexpected issues: {}", issues.len()),
+    };
+    assert_eq!(record.products, 3);
+    assert_eq!(record.demands, 1);
+    assert_eq!(record.portfolios, 1);
+    assert!(record.dry_run);
+    assert!(
+        <Db as ProductRepository<()>>::get_product(db, market.root, now.into())
+            .await?
+            .is_none()
+    );
+
+    let record = match db
+        .import(market.document()?, Actor::Operator, now.into(), false)
+        .await?
+    {
+        Ok(record) => record,
+        Err(issues) => anyhow::bail!("unexpected issues: {}", issues.len()),
+    };
+    assert!(!record.dry_run);
+
+    let product = <Db as ProductRepository<()>>::get_product(db, market.half, now.into())
+        .await?
+        .expect("product should be imported");
+    assert_eq!(product.parent, (market.root, 0.5));
+
+    let demand = <Db as DemandRepository<()>>::get_demand(db, market.demand_id, now.into())
+        .await?
+        .expect("demand should be imported");
+    assert!(demand.portfolios.contains_key(&market.portfolio_id));
+
+    let portfolio =
+        <Db as PortfolioRepository<()>>::get_portfolio(db, market.portfolio_id, now.into())
+            .await?
+            .expect("portfolio should be imported");
+    assert_eq!(portfolio.basis.get(&market.half), Some(&1.0));
+
+    // Importing the same document again only duplicates what exists
+    let issues = match db
+        .import(market.document()?, Actor::Operator, now.into(), false)
+        .await?
+    {
+        Ok(_) => anyhow::bail!("duplicate document should not be imported"),
+        Err(issues) => issues,
+    };
+    assert_eq!(issues.len(), 5);
+    assert!(issues.iter().all(|issue| matches!(
+        issue,
+        ImportIssue::DuplicateProduct { .. }
+            | ImportIssue::DuplicateDemand { .. }
+            | ImportIssue::DuplicatePortfolio { .. }
+    )));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_unknown_references() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let market = Market::new(&app);
+    let mut document = market.document()?;
+
+    // Orphan the halves, and reference a missing demand and product
+    document.products.remove(0);
+    let missing_demand = DemandId(uuid::Uuid::new_v4());
+    let missing_product = ProductId(uuid::Uuid::new_v4());
+    document.portfolios[0].demand.insert(missing_demand, 1.0);
+    document.portfolios[0].basis.insert(missing_product, 1.0);
+
+    let issues = match db
+        .import(document, Actor::Operator, now.into(), false)
+        .await?
+    {
+        Ok(_) => anyhow::bail!("invalid document should not be imported"),
+        Err(issues) => issues,
+    };
+    assert_eq!(issues.len(), 4);
+    assert!(issues.iter().any(|issue| matches!(
+        issue,
+        ImportIssue::UnknownParent { parent_id, .. } if *parent_id == market.root
+    )));
+    assert!(issues.iter().any(|issue| matches!(
+        issue,
+        ImportIssue::UnknownDemand { portfolio_id: p, demand_id } if *p == market.portfolio_id && *demand_id == missing_demand
+    )));
+    assert!(issues.iter().any(|issue| matches!(
+        issue,
+        ImportIssue::UnknownProduct { product_id, .. } if *product_id == missing_product
+    )));
+
+    // Nothing, not even the valid demand, was imported
+    assert!(
+        <Db as DemandRepository<()>>::get_demand(db, market.demand_id, now.into())
+            .await?
+            .is_none()
+    );
+
+    // Parents which already exist may be referenced
+    let root = app.generate_product_id(&()).0;
+    <Db as ProductRepository<()>>::create_product(db, root, (), now.into()).await?;
+    let document: ImportDocument<Db, (), (), ()> = ImportDocument {
+        products: vec![ImportProduct {
+            id: app.generate_product_id(&()).0,
+            app_data: (),
+            parent: Some((root, 1.0)),
+        }],
+        demands: Vec::new(),
+        portfolios: Vec::new(),
+    };
+    let record = match db
+        .import(document, Actor::Operator, now.into(), false)
+        .await?
+    {
+        Ok(record) => record,
+        Err(issues) => anyhow::bail!("unexpected issues: {}", issues.len()),
+    };
+    assert_eq!(record.products, 1);
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, DemandId, PortfolioId},
+};
+use fts_core::{
+    models::{Actor, Basis, DemandCurve, DemandQuery, PortfolioQuery, Weights},
+    ports::{Application, DemandRepository, PortfolioRepository, ProductRepository as _},
+};
+use std::time::Duration;
+
+async fn demand_ids(
+    db: &Db,
+    bidder_id: BidderId,
+    mut query: DemandQuery<DemandId, DateTime>,
+    limit: usize,
+) -> anyhow::Result<Vec<Vec<DemandId>>> {
+    let mut pages = Vec::new();
+    loop {
+        let page =
+            <Db as DemandRepository<()>>::query_demand(db, &[bidder_id], query, limit).await?;
+        pages.push(page.results.iter().map(|record| record.id).collect());
+        match page.more {
+            Some(more) => query = more,
+            None => return Ok(pages),
+        }
+    }
+}
+
+async fn portfolio_ids(
+    db: &Db,
+    bidder_id: BidderId,
+    mut query: PortfolioQuery<PortfolioId, DateTime>,
+    limit: usize,
+) -> anyhow::Result<Vec<Vec<PortfolioId>>> {
+    let mut pages = Vec::new();
+    loop {
+        let page = <Db as PortfolioRepository<()>>::query_portfolio(db, &[bidder_id], query, limit)
+            .await?;
+        pages.push(page.results.iter().map(|record| record.id).collect());
+        match page.more {
+            Some(more) => query = more,
+            None => return Ok(pages),
+        }
+    }
+}
+
+fn sorted<T: Ord>(mut ids: Vec<T>) -> Vec<T> {
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn test_list_query() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), at(0)).await?;
+
+    let curve =
+        |price: f64| serde_json::from_value::<DemandCurve>(serde_json::json!({ "price": price }));
+
+    // Two demands with a curve, the first of which is changed later, and one
+    // without a curve
+    let mut demands = Vec::new();
+    for (secs, curve_data) in [(1, curve(1.0)?), (2, curve(1.0)?), (3, DemandCurve::None)] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            curve_data,
+            Actor::Bidder,
+            at(secs),
+        )
+        .await?;
+        demands.push(demand_id);
+    }
+    <Db as DemandRepository<()>>::update_demand(db, demands[0], curve(2.0)?, Actor::Bidder, at(5))
+        .await?;
+
+    // A portfolio of a demand with a curve, one of the demand without, and
+    // one with empty groups
+    let mut portfolios = Vec::new();
+    for (secs, demand) in [
+        (1, Weights::from_iter([(demands[0], 1.0)])),
+        (2, Weights::from_iter([(demands[2], 1.0)])),
+        (3, Weights::default()),
+    ] {
+        let portfolio_id = app.generate_portfolio_id(&()).0;
+        let basis = if demand.is_empty() {
+            Basis::default()
+        } else {
+            Basis::from_iter([(product_id, 1.0)])
+        };
+        db.create_portfolio(
+            portfolio_id,
+            bidder_id,
+            (),
+            demand,
+            basis,
+            Actor::Bidder,
+            at(secs),
+        )
+        .await?;
+        portfolios.push(portfolio_id);
+    }
+
+    // By default, only the demands with a curve are listed, a page at a time
+    let with_curve = sorted(vec![demands[0], demands[1]]);
+    assert_eq!(
+        demand_ids(db, bidder_id, DemandQuery::default(), 1).await?,
+        vec![vec![with_curve[0]], vec![with_curve[1]]]
+    );
+
+    // The curves may be left out of the listing
+    let page = <Db as DemandRepository<()>>::query_demand(
+        db,
+        &[bidder_id],
+        DemandQuery {
+            include_curve: false,
+            ..Default::default()
+        },
+        10,
+    )
+    .await?;
+    assert_eq!(page.results.len(), 2);
+    assert!(
+        page.results
+            .iter()
+            .all(|record| matches!(record.curve_data, DemandCurve::None))
+    );
+
+    // The demands without a curve may also be listed
+    let everything = demand_ids(
+        db,
+        bidder_id,
+        DemandQuery {
+            has_curve: false,
+            ..Default::default()
+        },
+        2,
+    )
+    .await?;
+    assert_eq!(everything.len(), 2);
+    assert_eq!(everything.concat(), sorted(demands.clone()));
+
+    // Or only the demands changed since a time
+    assert_eq!(
+        demand_ids(
+            db,
+            bidder_id,
+            DemandQuery {
+                has_curve: false,
+                touched_since: Some(at(3)),
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .concat(),
+        sorted(vec![demands[0], demands[2]])
+    );
+
+    // By default, only the portfolios with non-empty groups are listed
+    assert_eq!(
+        portfolio_ids(db, bidder_id, PortfolioQuery::default(), 1)
+            .await?
+            .concat(),
+        sorted(vec![portfolios[0], portfolios[1]])
+    );
+    assert_eq!(
+        portfolio_ids(
+            db,
+            bidder_id,
+            PortfolioQuery {
+                active_only: false,
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .concat(),
+        sorted(portfolios.clone())
+    );
+
+    // The portfolios may be restricted to those with a demand with a curve,
+    // or to those changed since a time
+    assert_eq!(
+        portfolio_ids(
+            db,
+            bidder_id,
+            PortfolioQuery {
+                has_curve: true,
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .concat(),
+        vec![portfolios[0]]
+    );
+    assert_eq!(
+        portfolio_ids(
+            db,
+            bidder_id,
+            PortfolioQuery {
+                active_only: false,
+                touched_since: Some(at(2)),
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .concat(),
+        sorted(vec![portfolios[1], portfolios[2]])
+    );
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime},
+};
+use fts_core::{
+    models::{Actor, Basis, DemandCurve, MarketEvent, Weights},
+    ports::{
+        Application, BatchRepository as _, DemandRepository, OutboxRepository, PortfolioRepository,
+        ProductRepository,
+    },
+};
+use std::time::Duration;
+
+/// The types of the pending events, in order
+pub async fn pending(db: &Db) -> anyhow::Result<Vec<String>> {
+    let events = db.pending_events(100).await?;
+    Ok(events
+        .iter()
+        .map(|event| {
+            let json = serde_json::to_value(&event.event).unwrap();
+            json["type"].as_str().unwrap().to_owned()
+        })
+        .collect())
+}
+
+#[tokio::test]
+async fn test_outbox() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let later = now + std::time::Duration::from_secs(1);
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+    let curve =
+        |price: f64| serde_json::from_value::<DemandCurve>(serde_json::json!({ "price": price }));
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let product_id = app.generate_product_id(&()).0;
+    let demand_id = app.generate_demand_id(&()).0;
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+
+    <Db as ProductRepository<()>>::create_product(db, product_id, (), now.into()).await?;
+    <Db as DemandRepository<()>>::create_demand(
+        db,
+        demand_id,
+        bidder_id,
+        (),
+        curve(1.0)?,
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+    <Db as PortfolioRepository<()>>::create_portfolio(
+        db,
+        portfolio_id,
+        bidder_id,
+        (),
+        Weights::from_iter([(demand_id, 1.0)]),
+        Basis::from_iter([(product_id, 1.0)]),
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+    <Db as DemandRepository<()>>::update_demand(
+        db,
+        demand_id,
+        curve(2.0)?,
+        Actor::Bidder,
+        later.into(),
+    )
+    .await?;
+    db.run_batch(later.into(), app.solver(), ()).await??;
+
+    // Every change is recorded, in order, and the events identify what changed
+    let events = db.pending_events(100).await?;
+    assert!(matches!(
+        events[1].event,
+        MarketEvent::DemandCreated { demand_id: id, bidder_id: bidder } if id == demand_id && bidder == bidder_id
+    ));
+    assert!(events.windows(2).all(|w| w[0].sequence < w[1].sequence));
+    assert_eq!(
+        pending(db).await?,
+        [
+            "product_created",
+            "demand_created",
+            "portfolio_created",
+            "demand_updated",
+            "batch_executed"
+        ]
+    );
+
+    // Delivered events are no longer pending
+    let delivered = db.mark_delivered(events[2].sequence, later.into()).await?;
+    assert_eq!(delivered, 3);
+    assert_eq!(pending(db).await?, ["demand_updated", "batch_executed"]);
+    assert_eq!(db.pending_events(1).await?.len(), 1);
+
+    // Nothing is recorded for changes which fail
+    let result = <Db as DemandRepository<()>>::create_demand(
+        db,
+        demand_id,
+        bidder_id,
+        (),
+        curve(3.0)?,
+        Actor::Bidder,
+        later.into(),
+    )
+    .await;
+    assert!(result.is_err());
+    assert_eq!(pending(db).await?, ["demand_updated", "batch_executed"]);
+
+    <Db as PortfolioRepository<()>>::purge_portfolio(db, portfolio_id, later.into()).await?;
+    assert_eq!(
+        pending(db).await?,
+        ["demand_updated", "batch_executed", "portfolio_purged"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_product_tree_events() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let food = app.generate_product_id(&()).0;
+    let fruit = app.generate_product_id(&()).0;
+    let vegetable = app.generate_product_id(&()).0;
+    let apple = app.generate_product_id(&()).0;
+    let banana = app.generate_product_id(&()).0;
+
+    <Db as ProductRepository<()>>::create_product(db, food, (), at(0)).await?;
+    db.partition_product(food, vec![(fruit, (), 2.0), (vegetable, (), 3.0)], at(2))
+        .await?;
+    db.partition_product(fruit, vec![(apple, (), 5.0), (banana, (), 7.0)], at(4))
+        .await?;
+    <Db as ProductRepository<()>>::update_product_ratio(db, fruit, 4.0, at(6))
+        .await?
+        .unwrap();
+    <Db as ProductRepository<()>>::move_product(db, banana, vegetable, 1.0, at(8))
+        .await?
+        .unwrap();
+    <Db as ProductRepository<()>>::move_product(db, apple, vegetable, 2.0, at(10))
+        .await?
+        .unwrap();
+
+    // Each change to the tree is recorded in the outbox
+    let moved = db
+        .pending_events(100)
+        .await?
+        .into_iter()
+        .filter(|event| matches!(event.event, MarketEvent::ProductMoved { .. }))
+        .count();
+    assert_eq!(moved, 3);
+
+    Ok(())
+}
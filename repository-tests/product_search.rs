@@ -0,0 +1,128 @@
+use crate::common::{Db, TestApp};
+use fts_core::{
+    models::{AppDataFilter, Comparison, FilterValue, ProductSearch},
+    ports::{Application, ProductRepository},
+};
+use serde_json::json;
+
+fn filter(path: &str, op: Comparison, value: FilterValue) -> AppDataFilter {
+    AppDataFilter {
+        path: path.to_string(),
+        op,
+        value,
+    }
+}
+
+#[tokio::test]
+async fn test_product_search() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    // Hourly energy products over a day, and a single capacity product
+    let mut energy = Vec::new();
+    for hour in 0..24 {
+        let product_id = app.generate_product_id(&()).0;
+        db.create_product(
+            product_id,
+            json!({
+                "kind": "energy",
+                "start": format!("2025-01-01T{hour:02}:00:00Z"),
+                "end": format!("2025-01-01T{:02}:00:00Z", hour + 1),
+            }),
+            now.into(),
+        )
+        .await?;
+        energy.push(product_id);
+    }
+    energy.sort();
+    let capacity = app.generate_product_id(&()).0;
+    db.create_product(capacity, json!({ "kind": "capacity" }), now.into())
+        .await?;
+
+    // The delivery window overlaps [06:30, 09:00)
+    let query = ProductSearch {
+        filters: vec![
+            filter(
+                "$.kind",
+                Comparison::Eq,
+                FilterValue::String("energy".to_string()),
+            ),
+            filter(
+                "$.start",
+                Comparison::Lt,
+                FilterValue::String("2025-01-01T09:00:00Z".to_string()),
+            ),
+            filter(
+                "$.end",
+                Comparison::Gt,
+                FilterValue::String("2025-01-01T06:30:00Z".to_string()),
+            ),
+        ],
+        after: None,
+    };
+
+    // Page through the results two at a time
+    let mut found = Vec::new();
+    let mut next = Some(query);
+    while let Some(query) = next.take() {
+        let page =
+            <Db as ProductRepository<serde_json::Value>>::search_products(db, query, now.into(), 2)
+                .await?;
+        assert!(page.results.len() <= 2);
+        found.extend(page.results.into_iter().map(|product| product.app_data));
+        next = page.more;
+    }
+    let mut starts: Vec<_> = found
+        .iter()
+        .map(|app_data| app_data["start"].as_str().unwrap())
+        .collect();
+    starts.sort();
+    assert_eq!(
+        starts,
+        [
+            "2025-01-01T06:00:00Z",
+            "2025-01-01T07:00:00Z",
+            "2025-01-01T08:00:00Z"
+        ]
+    );
+
+    // Absent fields never satisfy a filter, even a negated one
+    let page = <Db as ProductRepository<serde_json::Value>>::search_products(
+        db,
+        ProductSearch {
+            filters: vec![filter(
+                "$.start",
+                Comparison::Ne,
+                FilterValue::String(String::new()),
+            )],
+            after: None,
+        },
+        now.into(),
+        100,
+    )
+    .await?;
+    assert!(page.more.is_none());
+    let mut ids: Vec<_> = page.results.iter().map(|product| product.id).collect();
+    ids.sort();
+    assert_eq!(ids, energy);
+
+    // Without filters, every product is returned
+    let page = <Db as ProductRepository<serde_json::Value>>::search_products(
+        db,
+        ProductSearch {
+            filters: Vec::new(),
+            after: None,
+        },
+        now.into(),
+        100,
+    )
+    .await?;
+    assert_eq!(page.results.len(), 25);
+    assert!(page.results.iter().any(|product| product.id == capacity));
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, PortfolioId, ProductId},
+};
+use fts_core::{
+    models::{Actor, Basis, ProductQuery, ProductTreeConflict},
+    ports::{Application, PortfolioRepository, ProductRepository},
+};
+use std::time::Duration;
+
+async fn expanded_basis(
+    db: &Db,
+    portfolio_id: PortfolioId,
+    as_of: DateTime,
+) -> anyhow::Result<Basis<ProductId>> {
+    Ok(
+        <Db as PortfolioRepository<()>>::get_portfolio_with_expanded_products(
+            db,
+            portfolio_id,
+            as_of,
+        )
+        .await?
+        .unwrap()
+        .basis,
+    )
+}
+
+#[tokio::test]
+async fn test_product_tree_changes() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let food = app.generate_product_id(&()).0;
+    let fruit = app.generate_product_id(&()).0;
+    let vegetable = app.generate_product_id(&()).0;
+    let apple = app.generate_product_id(&()).0;
+    let banana = app.generate_product_id(&()).0;
+    let missing = app.generate_product_id(&()).0;
+
+    <Db as ProductRepository<()>>::create_product(db, food, (), at(0)).await?;
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+    <Db as PortfolioRepository<()>>::create_portfolio(
+        db,
+        portfolio_id,
+        bidder_id,
+        (),
+        Default::default(),
+        std::iter::once((food, 1.0)).collect(),
+        Actor::Bidder,
+        at(1),
+    )
+    .await?;
+    db.partition_product(food, vec![(fruit, (), 2.0), (vegetable, (), 3.0)], at(2))
+        .await?;
+    db.partition_product(fruit, vec![(apple, (), 5.0), (banana, (), 7.0)], at(4))
+        .await?;
+
+    // Correcting the ratio of fruit rescales its descendants
+    let record = <Db as ProductRepository<()>>::update_product_ratio(db, fruit, 4.0, at(6))
+        .await?
+        .unwrap();
+    assert_eq!(record.parent, (food, 4.0));
+    assert_eq!(
+        record.basis,
+        Basis::from_iter([(apple, 5.0), (banana, 7.0)])
+    );
+
+    // Moving banana under vegetable partitions vegetable
+    let record = <Db as ProductRepository<()>>::move_product(db, banana, vegetable, 1.0, at(8))
+        .await?
+        .unwrap();
+    assert_eq!(record.parent, (vegetable, 1.0));
+
+    // Moving apple away leaves fruit as its own basis element again
+    <Db as ProductRepository<()>>::move_product(db, apple, vegetable, 2.0, at(10))
+        .await?
+        .unwrap();
+
+    // The expansion of the portfolio follows the tree as of each time
+    assert_eq!(
+        expanded_basis(db, portfolio_id, at(5)).await?,
+        Basis::from_iter([(apple, 10.0), (banana, 14.0), (vegetable, 3.0)])
+    );
+    assert_eq!(
+        expanded_basis(db, portfolio_id, at(7)).await?,
+        Basis::from_iter([(apple, 20.0), (banana, 28.0), (vegetable, 3.0)])
+    );
+    assert_eq!(
+        expanded_basis(db, portfolio_id, at(9)).await?,
+        Basis::from_iter([(apple, 20.0), (banana, 3.0)])
+    );
+    assert_eq!(
+        expanded_basis(db, portfolio_id, at(11)).await?,
+        Basis::from_iter([(fruit, 4.0), (banana, 3.0), (apple, 6.0)])
+    );
+
+    // So do the products themselves
+    let product = |id, secs| <Db as ProductRepository<()>>::get_product(db, id, at(secs));
+    assert_eq!(product(banana, 7).await?.unwrap().parent, (fruit, 7.0));
+    assert_eq!(product(banana, 9).await?.unwrap().parent, (vegetable, 1.0));
+    assert_eq!(product(fruit, 5).await?.unwrap().parent, (food, 2.0));
+    assert_eq!(
+        product(fruit, 9).await?.unwrap().basis,
+        Basis::from_iter([(apple, 5.0)])
+    );
+    assert_eq!(
+        product(fruit, 11).await?.unwrap().basis,
+        Basis::from_iter([(fruit, 1.0)])
+    );
+    assert_eq!(
+        product(vegetable, 11).await?.unwrap().basis,
+        Basis::from_iter([(banana, 1.0), (apple, 2.0)])
+    );
+
+    // A product left without children may be partitioned anew
+    let pear = app.generate_product_id(&()).0;
+    let children = db
+        .partition_product(fruit, vec![(pear, (), 1.0)], at(12))
+        .await?
+        .unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(
+        expanded_basis(db, portfolio_id, at(12)).await?,
+        Basis::from_iter([(pear, 4.0), (banana, 3.0), (apple, 6.0)])
+    );
+
+    // Changes which are not possible are refused
+    assert_eq!(
+        <Db as ProductRepository<()>>::update_product_ratio(db, food, 2.0, at(13))
+            .await?
+            .err(),
+        Some(ProductTreeConflict::Root { product_id: food })
+    );
+    assert_eq!(
+        <Db as ProductRepository<()>>::move_product(db, food, apple, 1.0, at(13))
+            .await?
+            .err(),
+        Some(ProductTreeConflict::Cycle { parent_id: apple })
+    );
+    assert_eq!(
+        <Db as ProductRepository<()>>::move_product(db, apple, missing, 1.0, at(13))
+            .await?
+            .err(),
+        Some(ProductTreeConflict::Missing {
+            product_id: missing
+        })
+    );
+
+    Ok(())
+}
+
+async fn listed(
+    db: &Db,
+    include_tree: bool,
+    as_of: DateTime,
+    limit: usize,
+) -> anyhow::Result<Vec<ProductId>> {
+    let mut ids = Vec::new();
+    let mut query = Some(ProductQuery {
+        include_tree,
+        after: None,
+    });
+    while let Some(next) = query {
+        let page = <Db as ProductRepository<()>>::query_products(db, next, as_of, limit).await?;
+        assert!(page.results.len() <= limit);
+        ids.extend(page.results.into_iter().map(|product| product.id));
+        query = page.more;
+    }
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_product_listing() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let at = |secs: u64| DateTime::from(now + Duration::from_secs(secs));
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let db = app.database();
+
+    let mut food = app.generate_product_id(&()).0;
+    let mut fuel = app.generate_product_id(&()).0;
+    if fuel < food {
+        std::mem::swap(&mut food, &mut fuel);
+    }
+    let fruit = app.generate_product_id(&()).0;
+    let vegetable = app.generate_product_id(&()).0;
+
+    <Db as ProductRepository<()>>::create_product(db, food, (), at(0)).await?;
+    <Db as ProductRepository<()>>::create_product(db, fuel, (), at(2)).await?;
+    db.partition_product(food, vec![(fruit, (), 2.0), (vegetable, (), 3.0)], at(4))
+        .await?;
+
+    // Products are listed only once they exist
+    assert_eq!(listed(db, false, at(1), 1).await?, vec![food]);
+    assert_eq!(listed(db, false, at(3), 1).await?, vec![food, fuel]);
+
+    // Partitioning does not change the roots, but grows the tree
+    assert_eq!(listed(db, false, at(5), 1).await?, vec![food, fuel]);
+    let mut tree = vec![food, fuel, fruit, vegetable];
+    tree.sort();
+    assert_eq!(listed(db, true, at(5), 3).await?, tree);
+    assert_eq!(listed(db, true, at(3), 3).await?, vec![food, fuel]);
+
+    // Each record reflects the tree as of the time
+    let page =
+        <Db as ProductRepository<()>>::query_products(db, ProductQuery::default(), at(5), 10)
+            .await?;
+    assert!(page.more.is_none());
+    assert_eq!(
+        page.results[0].basis,
+        Basis::from_iter([(fruit, 2.0), (vegetable, 3.0)])
+    );
+
+    Ok(())
+}
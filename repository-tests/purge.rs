@@ -0,0 +1,177 @@
+use crate::common::{Db, TestApp, types::BidderId};
+use fts_core::{
+    models::{Actor, Basis, DateTimeRangeQuery, DemandCurve, DemandQuery, PortfolioQuery, Weights},
+    ports::{Application, DemandRepository, PortfolioRepository, ProductRepository as _},
+};
+
+#[tokio::test]
+async fn test_purge() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let later = now + std::time::Duration::from_secs(1);
+    let purged_at = now + std::time::Duration::from_secs(2);
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    let curve =
+        |price: f64| serde_json::from_value::<DemandCurve>(serde_json::json!({ "price": price }));
+
+    let demand_id = app.generate_demand_id(&()).0;
+    db.create_demand(
+        demand_id,
+        bidder_id,
+        (),
+        curve(1.0)?,
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+    <Db as DemandRepository<()>>::update_demand(
+        db,
+        demand_id,
+        curve(2.0)?,
+        Actor::Bidder,
+        later.into(),
+    )
+    .await?;
+
+    let portfolio_id = app.generate_portfolio_id(&()).0;
+    db.create_portfolio(
+        portfolio_id,
+        bidder_id,
+        (),
+        Weights::from_iter([(demand_id, 1.0)]),
+        Basis::from_iter([(product_id, 1.0)]),
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+
+    // The owners of several bids may be looked up at once, omitting unknown ids
+    let unknown = app.generate_demand_id(&()).0;
+    let owners =
+        <Db as DemandRepository<()>>::get_demand_bidder_ids(db, &[demand_id, unknown]).await?;
+    assert_eq!(owners.len(), 1);
+    assert_eq!(owners.get(&demand_id), Some(&bidder_id));
+    let owners =
+        <Db as PortfolioRepository<()>>::get_portfolio_bidder_ids(db, &[portfolio_id]).await?;
+    assert_eq!(owners.get(&portfolio_id), Some(&bidder_id));
+
+    // Purging the demand leaves only its tombstone
+    let tombstone = <Db as DemandRepository<()>>::purge_demand(db, demand_id, purged_at.into())
+        .await?
+        .expect("demand should exist");
+    assert_eq!(tombstone.id, demand_id);
+    assert_eq!(tombstone.bidder_id, bidder_id);
+    assert_eq!(tombstone.purged_at, purged_at.into());
+
+    assert!(
+        <Db as DemandRepository<()>>::get_demand_bidder_id(db, demand_id)
+            .await?
+            .is_none()
+    );
+    assert!(
+        <Db as DemandRepository<()>>::get_demand_bidder_ids(db, &[demand_id])
+            .await?
+            .is_empty()
+    );
+    // Even as of a time before the purge, the demand no longer exists
+    assert!(
+        <Db as DemandRepository<()>>::get_demand(db, demand_id, later.into())
+            .await?
+            .is_none()
+    );
+    let history = <Db as DemandRepository<()>>::get_demand_curve_history(
+        db,
+        demand_id,
+        DateTimeRangeQuery {
+            before: None,
+            after: None,
+        },
+        10,
+    )
+    .await?;
+    assert!(history.results.is_empty());
+    // Purged demands are not listed, even among the inactive ones
+    assert!(
+        <Db as DemandRepository<()>>::query_demand(
+            db,
+            &[bidder_id],
+            DemandQuery {
+                has_curve: false,
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .results
+        .is_empty()
+    );
+
+    // A purged demand cannot be purged again
+    assert!(
+        <Db as DemandRepository<()>>::purge_demand(db, demand_id, purged_at.into())
+            .await?
+            .is_none()
+    );
+
+    // The portfolio remains, still referencing the tombstone
+    let portfolio =
+        <Db as PortfolioRepository<()>>::get_portfolio(db, portfolio_id, purged_at.into())
+            .await?
+            .expect("portfolio should exist");
+    assert!(portfolio.demand.contains_key(&demand_id));
+
+    // Purging the portfolio erases its group histories
+    let tombstone =
+        <Db as PortfolioRepository<()>>::purge_portfolio(db, portfolio_id, purged_at.into())
+            .await?
+            .expect("portfolio should exist");
+    assert_eq!(tombstone.id, portfolio_id);
+    assert_eq!(tombstone.bidder_id, bidder_id);
+    assert!(
+        <Db as PortfolioRepository<()>>::get_portfolio_bidder_ids(db, &[portfolio_id])
+            .await?
+            .is_empty()
+    );
+
+    assert!(
+        <Db as PortfolioRepository<()>>::get_portfolio(db, portfolio_id, now.into())
+            .await?
+            .is_none()
+    );
+    let history = <Db as PortfolioRepository<()>>::get_portfolio_product_history(
+        db,
+        portfolio_id,
+        DateTimeRangeQuery {
+            before: None,
+            after: None,
+        },
+        10,
+    )
+    .await?;
+    assert!(history.results.is_empty());
+    assert!(
+        <Db as PortfolioRepository<()>>::query_portfolio(
+            db,
+            &[bidder_id],
+            PortfolioQuery {
+                active_only: false,
+                ..Default::default()
+            },
+            10,
+        )
+        .await?
+        .results
+        .is_empty()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::common::{Db, TestApp, types::BidderId};
+use fts_core::{
+    models::{Actor, Basis, DemandCurve, Weights},
+    ports::{Application, DemandRepository, PortfolioRepository, ProductRepository as _},
+};
+
+#[tokio::test]
+async fn test_query_by_product() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let bidder_id = BidderId(uuid::Uuid::new_v4());
+    let product1 = app.generate_product_id(&()).0;
+    let product2 = app.generate_product_id(&()).0;
+    db.create_product(product1, (), now.into()).await?;
+    db.create_product(product2, (), now.into()).await?;
+
+    // One demand and portfolio per product
+    let curve = || serde_json::from_value::<DemandCurve>(serde_json::json!({ "price": 1 }));
+    let mut portfolios = Vec::new();
+    let mut demands = Vec::new();
+    for product_id in [product1, product2] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            curve()?,
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        let portfolio_id = app.generate_portfolio_id(&()).0;
+        db.create_portfolio(
+            portfolio_id,
+            bidder_id,
+            (),
+            Weights::from_iter([(demand_id, 1.0)]),
+            Basis::from_iter([(product_id, 1.0)]),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        demands.push(demand_id);
+        portfolios.push(portfolio_id);
+    }
+
+    let found =
+        <Db as PortfolioRepository<()>>::query_portfolio_by_product(db, &[bidder_id], product1)
+            .await?;
+    assert_eq!(
+        found.iter().map(|record| record.id).collect::<Vec<_>>(),
+        vec![portfolios[0]]
+    );
+
+    let found =
+        <Db as DemandRepository<()>>::query_demand_by_product(db, &[bidder_id], product2).await?;
+    assert_eq!(
+        found.iter().map(|record| record.id).collect::<Vec<_>>(),
+        vec![demands[1]]
+    );
+
+    // Other bidders' records are excluded
+    let other = BidderId(uuid::Uuid::new_v4());
+    assert!(
+        <Db as PortfolioRepository<()>>::query_portfolio_by_product(db, &[other], product1)
+            .await?
+            .is_empty()
+    );
+
+    // Once the basis no longer references the product, neither are returned
+    let later = now + std::time::Duration::from_secs(1);
+    <Db as PortfolioRepository<()>>::update_portfolio_basis(
+        db,
+        portfolios[0],
+        Basis::from_iter([(product2, 1.0)]),
+        Actor::Bidder,
+        later.into(),
+    )
+    .await?;
+
+    assert!(
+        <Db as PortfolioRepository<()>>::query_portfolio_by_product(db, &[bidder_id], product1)
+            .await?
+            .is_empty()
+    );
+    assert!(
+        <Db as DemandRepository<()>>::query_demand_by_product(db, &[bidder_id], product1)
+            .await?
+            .is_empty()
+    );
+    assert_eq!(
+        <Db as DemandRepository<()>>::query_demand_by_product(db, &[bidder_id], product2)
+            .await?
+            .len(),
+        2
+    );
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime, DemandId},
+};
+use fts_core::{
+    models::{Actor, DateTimeRangeQuery, DemandCurve, Point, PwlCurve, SettlementConfig},
+    ports::{
+        Application, BatchRepository, DemandRepository, PortfolioRepository,
+        ProductRepository as _, RetentionRepository as _, SettlementRepository as _,
+    },
+};
+use std::time::Duration;
+
+type Solver = <TestApp as Application>::Solver;
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+/// Open a market of a buyer and a seller, whose outcomes are superseded once
+/// by a second batch, and whose buyer revises their curve in between.
+pub async fn open_market(
+    app: TestApp,
+    now: time::OffsetDateTime,
+) -> anyhow::Result<(TestApp, BidderId, DemandId)> {
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    let curve = |rate: f64| -> anyhow::Result<DemandCurve> {
+        Ok(PwlCurve::new(vec![
+            Point {
+                rate: rate - 10.0,
+                price: 10.0,
+            },
+            Point { rate, price: 0.0 },
+        ])?
+        .into())
+    };
+
+    let buy_demand = app.generate_demand_id(&()).0;
+    db.create_demand(
+        buy_demand,
+        buyer,
+        (),
+        curve(10.0)?,
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+    let sell_demand = app.generate_demand_id(&()).0;
+    db.create_demand(
+        sell_demand,
+        seller,
+        (),
+        curve(0.0)?,
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+
+    for (bidder_id, demand_id) in [(buyer, buy_demand), (seller, sell_demand)] {
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    db.run_batch((now + Duration::from_secs(1)).into(), app.solver(), ())
+        .await??;
+    <Db as DemandRepository<()>>::update_demand(
+        db,
+        buy_demand,
+        curve(8.0)?,
+        Actor::Bidder,
+        (now + Duration::from_secs(2)).into(),
+    )
+    .await?;
+    db.run_batch((now + Duration::from_secs(3)).into(), app.solver(), ())
+        .await??;
+
+    Ok((app, buyer, buy_demand))
+}
+
+#[tokio::test]
+async fn test_prune_history() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let horizon = now + Duration::from_secs(4);
+    let later = now + Duration::from_secs(3600);
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    let (app, buyer, demand_id) = open_market(app, now).await?;
+    let db = app.database();
+
+    let unsettled = db
+        .get_unsettled_activity(buyer, later.into(), 3600.0)
+        .await?;
+
+    // Without a settlement, only the superseded curve is pruned
+    let record = db.prune_history(horizon.into()).await?;
+    assert_eq!(record.before, horizon.into());
+    assert_eq!(record.settled_before, None);
+    assert_eq!(record.curves, 1);
+    assert_eq!(record.demand_groups, 0);
+    assert_eq!(record.product_groups, 0);
+    assert_eq!(record.portfolio_outcomes, 0);
+    assert_eq!(record.product_outcomes, 0);
+    assert_eq!(record.batch_inputs, 0);
+    assert!(!record.archived);
+
+    let history = <Db as DemandRepository<()>>::get_demand_curve_history(
+        db,
+        demand_id,
+        DateTimeRangeQuery {
+            before: None,
+            after: None,
+        },
+        10,
+    )
+    .await?;
+    assert_eq!(history.results.len(), 1);
+
+    let pruned = db
+        .get_unsettled_activity(buyer, later.into(), 3600.0)
+        .await?;
+    assert_eq!(pruned.valid_from, unsettled.valid_from);
+    assert!(approx_eq(pruned.value.payment, unsettled.value.payment));
+
+    // Once settled, the outcomes superseded before the horizon are pruned too,
+    // without disturbing the activity accrued since
+    let settled_at = now + Duration::from_secs(5);
+    db.settle_activity(SettlementConfig {
+        as_of: settled_at.into(),
+        time_unit: 3600.0,
+        position_decimals: 0,
+        payment_decimals: 2,
+    })
+    .await?
+    .expect("activity should be settled");
+
+    let unsettled = db
+        .get_unsettled_activity(buyer, later.into(), 3600.0)
+        .await?;
+
+    let record = db.prune_history(horizon.into()).await?;
+    assert_eq!(record.settled_before, Some(horizon.into()));
+    assert_eq!(record.curves, 0);
+    assert_eq!(record.portfolio_outcomes, 2);
+    assert_eq!(record.product_outcomes, 1);
+
+    let pruned = db
+        .get_unsettled_activity(buyer, later.into(), 3600.0)
+        .await?;
+    assert_eq!(pruned.valid_from, settled_at.into());
+    assert!(approx_eq(pruned.value.payment, unsettled.value.payment));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prune_batch_inputs() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    let first = DateTime::from(now + Duration::from_secs(1));
+    let second = DateTime::from(now + Duration::from_secs(3));
+
+    let Some(mut app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+    app.record_batch_inputs(true);
+    let (app, _, _) = open_market(app, now).await?;
+    let db = app.database();
+
+    // Only the inputs of the batches run before the horizon are pruned
+    let record = db
+        .prune_history((now + Duration::from_secs(2)).into())
+        .await?;
+    assert_eq!(record.batch_inputs, 1);
+    assert!(
+        <Db as BatchRepository<Solver>>::get_batch_inputs(db, first)
+            .await?
+            .is_none()
+    );
+    assert!(
+        <Db as BatchRepository<Solver>>::get_batch_inputs(db, second)
+            .await?
+            .is_some()
+    );
+
+    let record = db.prune_history(second).await?;
+    assert_eq!(record.batch_inputs, 0);
+
+    Ok(())
+}
@@ -0,0 +1,629 @@
+use crate::common::{
+    Db, TestApp,
+    types::{BidderId, DateTime},
+};
+use fts_core::{
+    models::{
+        Actor, DateTimeRangeQuery, Point, PwlCurve, SettlementConfig, SettlementConflict,
+        UnsettledActivityQuery,
+    },
+    ports::{
+        Application, BatchRepository, DemandRepository, PortfolioRepository,
+        ProductRepository as _, SettlementRepository,
+    },
+};
+use std::time::Duration;
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+#[tokio::test]
+async fn test_settlement() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    // The buyer and seller have mirrored demand curves, so the market clears
+    // at a rate of 5 and a price of 5.
+    let buy_demand = app.generate_demand_id(&()).0;
+    db.create_demand(
+        buy_demand,
+        buyer,
+        (),
+        PwlCurve::new(vec![
+            Point {
+                rate: 0.0,
+                price: 10.0,
+            },
+            Point {
+                rate: 10.0,
+                price: 0.0,
+            },
+        ])?
+        .into(),
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+
+    let sell_demand = app.generate_demand_id(&()).0;
+    db.create_demand(
+        sell_demand,
+        seller,
+        (),
+        PwlCurve::new(vec![
+            Point {
+                rate: -10.0,
+                price: 10.0,
+            },
+            Point {
+                rate: 0.0,
+                price: 0.0,
+            },
+        ])?
+        .into(),
+        Actor::Bidder,
+        now.into(),
+    )
+    .await?;
+
+    for (bidder_id, demand_id) in [(buyer, buy_demand), (seller, sell_demand)] {
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    let batch_time = now + Duration::from_secs(1);
+    db.run_batch(batch_time.into(), app.solver(), ()).await??;
+
+    // Rates are expressed per hour, so after 0.34 hours each bidder has
+    // accrued 1.7 units of trade. The solver is only accurate to within some
+    // tolerance, so we avoid intervals that would round from exactly one half.
+    let config = |as_of: DateTime| SettlementConfig {
+        as_of,
+        time_unit: 3600.0,
+        position_decimals: 0,
+        payment_decimals: 2,
+    };
+    let first = DateTime::from(batch_time + Duration::from_secs(1224));
+    let second = DateTime::from(batch_time + Duration::from_secs(2448));
+
+    let unsettled = db.get_unsettled_activity(buyer, first, 3600.0).await?;
+    assert_eq!(unsettled.valid_from, batch_time.into());
+    assert!(approx_eq(unsettled.value.positions[&product_id], 1.7));
+    assert!(approx_eq(unsettled.value.payment, 8.5));
+
+    let settlement = db.settle_activity(config(first)).await?.unwrap();
+    assert_eq!(settlement.valid_from, batch_time.into());
+    assert_eq!(settlement.valid_until, first);
+    assert_eq!(settlement.activity[&buyer].positions[&product_id], 2.0);
+    assert_eq!(settlement.activity[&seller].positions[&product_id], -2.0);
+    assert!(approx_eq(settlement.activity[&buyer].payment, 8.5));
+    assert!(approx_eq(settlement.activity[&seller].payment, -8.5));
+
+    // Retrying the settlement returns it as it was made, rather than settling
+    // the activity twice
+    let retried = db.settle_activity(config(first)).await?.unwrap();
+    assert_eq!(retried.valid_from, settlement.valid_from);
+    assert_eq!(retried.activity, settlement.activity);
+
+    // unless the retry differs in its configuration
+    let Err(conflict) = db
+        .settle_activity(SettlementConfig {
+            position_decimals: 3,
+            ..config(first)
+        })
+        .await?
+    else {
+        panic!("the settlement should conflict");
+    };
+    assert_eq!(
+        conflict,
+        SettlementConflict::Mismatched {
+            existing: config(first)
+        }
+    );
+
+    // The unsettled activity resets after the settlement
+    let unsettled = db.get_unsettled_activity(seller, second, 3600.0).await?;
+    assert_eq!(unsettled.valid_from, first);
+    assert!(approx_eq(unsettled.value.positions[&product_id], -1.7));
+
+    // The rounding residual of the first settlement is carried into the second
+    let settlement = db.settle_activity(config(second)).await?.unwrap();
+    assert_eq!(settlement.valid_from, first);
+    assert_eq!(settlement.activity[&buyer].positions[&product_id], 1.0);
+    assert_eq!(settlement.activity[&seller].positions[&product_id], -1.0);
+
+    // Activity cannot be settled up to a time preceding the latest settlement
+    let between = DateTime::from(batch_time + Duration::from_secs(1836));
+    let Err(conflict) = db.settle_activity(config(between)).await? else {
+        panic!("the settlement should conflict");
+    };
+    assert_eq!(conflict, SettlementConflict::Superseded { latest: second });
+
+    // but earlier settlements may still be retried
+    let retried = db.settle_activity(config(first)).await?.unwrap();
+    assert_eq!(retried.valid_until, first);
+    assert_eq!(retried.activity[&buyer].positions[&product_id], 2.0);
+
+    let history = db
+        .get_settlement_history(
+            buyer,
+            DateTimeRangeQuery {
+                before: None,
+                after: None,
+            },
+            1,
+        )
+        .await?;
+    assert_eq!(history.results.len(), 1);
+    assert_eq!(history.results[0].valid_until, Some(second));
+    assert_eq!(history.results[0].value.positions[&product_id], 1.0);
+
+    let history = db
+        .get_settlement_history(buyer, history.more.unwrap(), 1)
+        .await?;
+    assert_eq!(history.results.len(), 1);
+    assert_eq!(history.results[0].valid_until, Some(first));
+    assert_eq!(history.results[0].value.positions[&product_id], 2.0);
+    assert!(history.more.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unsettled_positions() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    // Both bidders trade a strip of three products at once
+    let mut products = Vec::new();
+    for _ in 0..3 {
+        let product_id = app.generate_product_id(&()).0;
+        db.create_product(product_id, (), now.into()).await?;
+        products.push(product_id);
+    }
+    products.sort();
+
+    for (bidder_id, min_rate) in [(buyer, 0.0), (seller, -10.0)] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(vec![
+                Point {
+                    rate: min_rate,
+                    price: 10.0,
+                },
+                Point {
+                    rate: min_rate + 10.0,
+                    price: 0.0,
+                },
+            ])?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            products
+                .iter()
+                .map(|product_id| (*product_id, 1.0))
+                .collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    let batch_time = now + Duration::from_secs(1);
+    db.run_batch(batch_time.into(), app.solver(), ()).await??;
+    let as_of = DateTime::from(batch_time + Duration::from_secs(1224));
+
+    let unsettled = db.get_unsettled_activity(buyer, as_of, 3600.0).await?;
+    assert_eq!(unsettled.value.positions.len(), 3);
+
+    // Page through the buyer's positions, two at a time
+    let mut positions = Vec::new();
+    let mut next = Some(UnsettledActivityQuery::default());
+    while let Some(query) = next.take() {
+        let page = db
+            .get_unsettled_positions(buyer, as_of, 3600.0, query, 2)
+            .await?;
+        assert!(page.activity.value.positions.len() <= 2);
+        assert_eq!(page.activity.valid_from, unsettled.valid_from);
+        assert!(approx_eq(
+            page.activity.value.payment,
+            unsettled.value.payment
+        ));
+        positions.extend(page.activity.value.positions);
+        next = page.more;
+    }
+    assert_eq!(
+        positions
+            .iter()
+            .map(|(product_id, _)| *product_id)
+            .collect::<Vec<_>>(),
+        products
+    );
+    for (product_id, position) in positions {
+        assert!(approx_eq(position, unsettled.value.positions[&product_id]));
+        assert!(approx_eq(position, 1.7));
+    }
+
+    // The positions may be restricted to some products, but not the payment
+    let page = db
+        .get_unsettled_positions(
+            seller,
+            as_of,
+            3600.0,
+            UnsettledActivityQuery {
+                products: vec![products[1]],
+                after: None,
+            },
+            10,
+        )
+        .await?;
+    assert!(page.more.is_none());
+    assert_eq!(page.activity.value.positions.len(), 1);
+    assert!(approx_eq(page.activity.value.positions[&products[1]], -1.7));
+    assert!(approx_eq(
+        page.activity.value.payment,
+        -unsettled.value.payment
+    ));
+
+    // Settling pages through the bidders, settling each exactly once
+    let settlement = db
+        .settle_activity(SettlementConfig {
+            as_of,
+            time_unit: 3600.0,
+            position_decimals: 0,
+            payment_decimals: 2,
+        })
+        .await?
+        .unwrap();
+    assert_eq!(settlement.activity.len(), 2);
+    for product_id in products.iter() {
+        assert_eq!(settlement.activity[&buyer].positions[product_id], 2.0);
+        assert_eq!(settlement.activity[&seller].positions[product_id], -2.0);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_revision() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    for (bidder_id, points) in [
+        (buyer, [(0.0, 10.0), (10.0, 0.0)]),
+        (seller, [(-10.0, 10.0), (0.0, 0.0)]),
+    ] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(
+                points
+                    .into_iter()
+                    .map(|(rate, price)| Point { rate, price })
+                    .collect(),
+            )?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    let batch_time = now + Duration::from_secs(1);
+    db.run_batch(batch_time.into(), app.solver(), ()).await??;
+
+    // After 0.34 hours each bidder has accrued 1.7 units of trade, which the
+    // settlement mistakenly rounds to whole units
+    let config = |position_decimals: u32| SettlementConfig {
+        as_of: DateTime::from(batch_time + Duration::from_secs(1224)),
+        time_unit: 3600.0,
+        position_decimals,
+        payment_decimals: 2,
+    };
+    let first = config(0).as_of;
+    let revised_at = DateTime::from(batch_time + Duration::from_secs(3600));
+
+    let settlement = db.settle_activity(config(0)).await?.unwrap();
+    assert_eq!(settlement.activity[&buyer].positions[&product_id], 2.0);
+
+    // Correcting the settlement reverses it, then settles it again
+    let correction = db.correct_settlement(config(1), revised_at).await?.unwrap();
+    assert_eq!(correction.revision, 2);
+    assert_eq!(correction.config, Some(config(1)));
+    assert_eq!(correction.activity[&buyer].positions[&product_id], 1.7);
+    assert_eq!(correction.activity[&seller].positions[&product_id], -1.7);
+    assert!(approx_eq(correction.activity[&buyer].payment, 8.5));
+
+    // Repeating the correction returns it as it was made
+    let repeated = db.correct_settlement(config(1), revised_at).await?.unwrap();
+    assert_eq!(repeated.revision, correction.revision);
+    assert_eq!(repeated.activity, correction.activity);
+
+    // Retrying the settlement must now use the corrected configuration
+    let Err(conflict) = db.settle_activity(config(0)).await? else {
+        panic!("the settlement should conflict");
+    };
+    assert_eq!(
+        conflict,
+        SettlementConflict::Mismatched {
+            existing: config(1)
+        }
+    );
+    let retried = db.settle_activity(config(1)).await?.unwrap();
+    assert!(approx_eq(
+        retried.activity[&buyer].positions[&product_id],
+        1.7
+    ));
+
+    // The original settlement is linked to the reversal and the correction
+    let revisions = db.get_settlement_revisions(first).await?;
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].revision, 1);
+    assert_eq!(revisions[0].config, None);
+    assert_eq!(revisions[0].activity[&buyer].positions[&product_id], -2.0);
+    assert_eq!(revisions[1].revision, correction.revision);
+    assert_eq!(revisions[1].config, correction.config);
+    assert_eq!(revisions[1].activity, correction.activity);
+
+    let history = db
+        .get_settlement_history(
+            buyer,
+            DateTimeRangeQuery {
+                before: None,
+                after: None,
+            },
+            10,
+        )
+        .await?;
+    assert_eq!(history.results.len(), 1);
+    assert!(approx_eq(
+        history.results[0].value.positions[&product_id],
+        1.7
+    ));
+    assert!(approx_eq(history.results[0].value.payment, 8.5));
+
+    // Reversing the settlement offsets every entry posted so far
+    let reversal = db.reverse_settlement(first, revised_at).await?.unwrap();
+    assert_eq!(reversal.revision, 3);
+    assert_eq!(reversal.activity[&buyer].positions[&product_id], -1.7);
+
+    let Err(conflict) = db.reverse_settlement(first, revised_at).await? else {
+        panic!("the reversal should conflict");
+    };
+    assert_eq!(conflict, SettlementConflict::Reversed { as_of: first });
+
+    let Err(conflict) = db.settle_activity(config(1)).await? else {
+        panic!("the settlement should conflict");
+    };
+    assert_eq!(conflict, SettlementConflict::Reversed { as_of: first });
+
+    let summary = db.get_bidder_summary(buyer, first, 3600.0).await?;
+    assert!(approx_eq(summary.settled.positions[&product_id], 0.0));
+    assert!(approx_eq(summary.settled.payment, 0.0));
+
+    // A reversed settlement may be corrected without being reversed again
+    let correction = db.correct_settlement(config(0), revised_at).await?.unwrap();
+    assert_eq!(correction.revision, 4);
+    assert_eq!(correction.activity[&buyer].positions[&product_id], 2.0);
+
+    // Only settlements which were made may be revised
+    let missing = DateTime::from(batch_time);
+    let Err(conflict) = db.reverse_settlement(missing, revised_at).await? else {
+        panic!("the reversal should conflict");
+    };
+    assert_eq!(conflict, SettlementConflict::Missing { as_of: missing });
+
+    let Err(conflict) = db
+        .correct_settlement(
+            SettlementConfig {
+                as_of: missing,
+                ..config(0)
+            },
+            revised_at,
+        )
+        .await?
+    else {
+        panic!("the correction should conflict");
+    };
+    assert_eq!(conflict, SettlementConflict::Missing { as_of: missing });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_statements() -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+
+    let Some(app) = TestApp::open(now.into()).await? else {
+        return Ok(());
+    };
+
+    let db = app.database();
+
+    let buyer = BidderId(uuid::Uuid::new_v4());
+    let seller = BidderId(uuid::Uuid::new_v4());
+
+    let product_id = app.generate_product_id(&()).0;
+    db.create_product(product_id, (), now.into()).await?;
+
+    for (bidder_id, points) in [
+        (buyer, [(0.0, 10.0), (10.0, 0.0)]),
+        (seller, [(-10.0, 10.0), (0.0, 0.0)]),
+    ] {
+        let demand_id = app.generate_demand_id(&()).0;
+        db.create_demand(
+            demand_id,
+            bidder_id,
+            (),
+            PwlCurve::new(
+                points
+                    .into_iter()
+                    .map(|(rate, price)| Point { rate, price })
+                    .collect(),
+            )?
+            .into(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+
+        <Db as PortfolioRepository<()>>::create_portfolio(
+            db,
+            app.generate_portfolio_id(&()).0,
+            bidder_id,
+            (),
+            std::iter::once((demand_id, 1.0)).collect(),
+            std::iter::once((product_id, 1.0)).collect(),
+            Actor::Bidder,
+            now.into(),
+        )
+        .await?;
+    }
+
+    let batch_time = now + Duration::from_secs(1);
+    db.run_batch(batch_time.into(), app.solver(), ()).await??;
+
+    let config = |position_decimals: u32| SettlementConfig {
+        as_of: DateTime::from(batch_time + Duration::from_secs(1224)),
+        time_unit: 3600.0,
+        position_decimals,
+        payment_decimals: 2,
+    };
+    let as_of = config(0).as_of;
+    let revised_at = DateTime::from(batch_time + Duration::from_secs(3600));
+
+    // Retrying a settlement or a correction issues no further statements
+    db.settle_activity(config(0)).await?.unwrap();
+    db.settle_activity(config(0)).await?.unwrap();
+    let correction = db.correct_settlement(config(1), revised_at).await?.unwrap();
+    db.correct_settlement(config(1), revised_at).await?.unwrap();
+
+    let statements = db.get_settlement_statements(as_of, None).await?;
+    assert_eq!(statements.len(), 6);
+    assert!(statements.windows(2).all(|pair| pair[0].id < pair[1].id));
+    assert_eq!(
+        statements
+            .iter()
+            .map(|statement| statement.revision)
+            .collect::<Vec<_>>(),
+        [0, 0, 1, 1, 2, 2]
+    );
+
+    // The original statement was issued as of the settlement
+    let original = &statements[0];
+    assert_eq!(original.valid_until, as_of);
+    assert_eq!(original.revised_at, None);
+    assert_eq!(original.config, Some(config(0)));
+
+    // A statement is issued to each bidder for the reversal and correction
+    let buyer_statements = db.get_settlement_statements(as_of, Some(buyer)).await?;
+    assert_eq!(buyer_statements.len(), 3);
+    assert!(
+        buyer_statements
+            .iter()
+            .all(|statement| statement.bidder_id == buyer)
+    );
+    assert_eq!(buyer_statements[0].activity.positions[&product_id], 2.0);
+    assert_eq!(buyer_statements[1].config, None);
+    assert_eq!(buyer_statements[1].revised_at, Some(revised_at));
+    assert_eq!(buyer_statements[1].activity.positions[&product_id], -2.0);
+    assert_eq!(buyer_statements[2].config, correction.config);
+    assert_eq!(buyer_statements[2].activity, correction.activity[&buyer]);
+
+    // Reversing the settlement issues statements of its own
+    db.reverse_settlement(as_of, revised_at).await?.unwrap();
+    let reversed = db.get_settlement_statements(as_of, Some(seller)).await?;
+    assert_eq!(reversed.len(), 4);
+    assert_eq!(reversed[3].revision, 3);
+    assert_eq!(reversed[3].activity.positions[&product_id], 1.7);
+
+    // Each statement may be retrieved by its id, which is never reused
+    let statement = db
+        .get_settlement_statement(buyer_statements[2].id)
+        .await?
+        .unwrap();
+    assert_eq!(statement.bidder_id, buyer);
+    assert_eq!(statement.revision, 2);
+    assert_eq!(statement.activity, buyer_statements[2].activity);
+    let issued = db.get_settlement_statements(as_of, None).await?;
+    assert_eq!(issued.len(), 8);
+    let last = issued.last().unwrap().id;
+    assert!(db.get_settlement_statement(last + 1).await?.is_none());
+
+    // Statements of other settlements are not listed
+    let missing = DateTime::from(batch_time);
+    assert!(
+        db.get_settlement_statements(missing, None)
+            .await?
+            .is_empty()
+    );
+
+    Ok(())
+}